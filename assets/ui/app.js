@@ -0,0 +1,229 @@
+// File manager for sftp-manager. Talks to the JSON API of the same server;
+// responses carry their payload in `sftp` and errors in `message`. Once the
+// server has API keys, every call carries the key entered in the header,
+// kept for the browser tab only.
+"use strict";
+
+let cwd = "/";
+
+const $ = (id) => document.getElementById(id);
+
+function headers() {
+  const key = sessionStorage.getItem("apiKey");
+  return key ? { Authorization: `Bearer ${key}` } : {};
+}
+
+async function api(method, url, body) {
+  const response = await fetch(url, { method, body, headers: headers() });
+  const json = await response.json().catch(() => ({}));
+  if (!response.ok) {
+    throw new Error(json.message || `${response.status} ${response.statusText}`);
+  }
+  return json.sftp;
+}
+
+function join(dir, name) {
+  return dir === "/" ? `/${name}` : `${dir}/${name}`;
+}
+
+function query(params) {
+  return new URLSearchParams(params).toString();
+}
+
+function showMessage(text, isError) {
+  const message = $("message");
+  message.textContent = text;
+  message.className = isError ? "error" : "";
+  message.hidden = !text;
+}
+
+function formatSize(bytes) {
+  const units = ["B", "KiB", "MiB", "GiB", "TiB"];
+  let size = bytes;
+  let unit = 0;
+  while (size >= 1024 && unit < units.length - 1) {
+    size /= 1024;
+    unit += 1;
+  }
+  return unit === 0 ? `${size} B` : `${size.toFixed(1)} ${units[unit]}`;
+}
+
+function element(tag, text, attributes = {}) {
+  const node = document.createElement(tag);
+  if (text !== undefined) node.textContent = text;
+  Object.assign(node, attributes);
+  return node;
+}
+
+function renderBreadcrumbs() {
+  const nav = $("breadcrumbs");
+  nav.replaceChildren();
+  const parts = cwd.split("/").filter(Boolean);
+  const crumbs = [["/", "root"]];
+  parts.forEach((part, i) => crumbs.push(["/" + parts.slice(0, i + 1).join("/"), part]));
+  crumbs.forEach(([path, label], i) => {
+    if (i > 0) nav.append(" / ");
+    nav.append(element("a", label, { href: "#", onclick: (e) => { e.preventDefault(); browse(path); } }));
+  });
+}
+
+async function browse(path) {
+  try {
+    const listing = await api("GET", `/files?${query({ path })}`);
+    cwd = listing.path;
+    renderBreadcrumbs();
+    renderEntries(listing.entries);
+    showMessage("");
+  } catch (e) {
+    showMessage(`Cannot open ${path}: ${e.message}`, true);
+  }
+}
+
+function renderEntries(entries) {
+  const body = $("entries");
+  body.replaceChildren();
+  if (cwd !== "/") {
+    const row = element("tr");
+    const cell = element("td");
+    cell.append(element("a", "..", { href: "#", onclick: (e) => { e.preventDefault(); browse(cwd.replace(/\/[^/]*$/, "") || "/"); } }));
+    row.append(cell, element("td"), element("td"), element("td"));
+    body.append(row);
+  }
+  if (entries.length === 0) {
+    const row = element("tr", undefined, { className: "empty" });
+    row.append(element("td", "This directory is empty", { colSpan: 4 }));
+    body.append(row);
+    return;
+  }
+  for (const entry of entries) {
+    const path = join(cwd, entry.name);
+    const row = element("tr");
+
+    const name = element("td");
+    if (entry.is_dir) {
+      name.append(element("a", `${entry.name}/`, { href: "#", onclick: (e) => { e.preventDefault(); browse(path); } }));
+    } else {
+      name.append(element("a", entry.name, { href: "#", onclick: (e) => { e.preventDefault(); download(path, entry.name); } }));
+    }
+
+    const actions = element("td", undefined, { className: "actions" });
+    actions.append(element("button", "Delete", { type: "button", className: "danger", onclick: () => remove(path) }));
+
+    row.append(
+      name,
+      element("td", entry.is_dir ? "" : formatSize(entry.bytes), { className: "size" }),
+      element("td", new Date(entry.modified).toLocaleString()),
+      actions,
+    );
+    body.append(row);
+  }
+}
+
+// Links cannot carry the key, so files are fetched and then saved
+async function download(path, name) {
+  try {
+    const response = await fetch(`/files/download?${query({ path })}`, { headers: headers() });
+    if (!response.ok) {
+      const json = await response.json().catch(() => ({}));
+      throw new Error(json.message || `${response.status} ${response.statusText}`);
+    }
+    const url = URL.createObjectURL(await response.blob());
+    element("a", undefined, { href: url, download: name }).click();
+    URL.revokeObjectURL(url);
+  } catch (e) {
+    showMessage(`Cannot download ${path}: ${e.message}`, true);
+  }
+}
+
+async function remove(path) {
+  if (!confirm(`Delete ${path}?`)) return;
+  try {
+    const result = await api("DELETE", `/files?${query({ path })}`);
+    showMessage(result.trash_id ? `Moved ${path} to the trash` : `Deleted ${path}`);
+  } catch (e) {
+    showMessage(`Cannot delete ${path}: ${e.message}`, true);
+  }
+  await browse(cwd);
+}
+
+async function upload(files) {
+  for (const file of files) {
+    const path = join(cwd, file.name);
+    showMessage(`Uploading ${file.name}…`);
+    try {
+      await api("PUT", `/files?${query({ path })}`, file);
+    } catch (e) {
+      if (!e.message.includes("already exists") || !confirm(`${path} exists. Replace it?`)) {
+        showMessage(`Cannot upload ${file.name}: ${e.message}`, true);
+        continue;
+      }
+      try {
+        await api("PUT", `/files?${query({ path, overwrite: true })}`, file);
+      } catch (e) {
+        showMessage(`Cannot upload ${file.name}: ${e.message}`, true);
+        continue;
+      }
+    }
+    showMessage(`Uploaded ${file.name}`);
+  }
+  await browse(cwd);
+}
+
+async function refreshStatus() {
+  try {
+    const status = await api("GET", "/sftp/status");
+    const badge = $("status");
+    badge.textContent = status.enabled ? "SFTP enabled" : "SFTP disabled";
+    badge.className = `badge ${status.enabled ? "on" : "off"}`;
+    $("toggle").textContent = status.enabled ? "Disable" : "Enable";
+    $("show-credentials").hidden = !status.enabled;
+    if (!status.enabled) $("credentials").hidden = true;
+    $("cred-expires").textContent = status.expires_at || "never";
+  } catch (e) {
+    showMessage(`Cannot read the server status: ${e.message}`, true);
+  }
+}
+
+async function toggle() {
+  try {
+    await api("POST", "/sftp/toggle");
+  } catch (e) {
+    showMessage(`Cannot switch the server: ${e.message}`, true);
+  }
+  await refreshStatus();
+}
+
+async function showCredentials() {
+  const section = $("credentials");
+  if (!section.hidden) {
+    section.hidden = true;
+    return;
+  }
+  try {
+    const credentials = await api("GET", "/sftp/credentials");
+    $("cred-host").textContent = `${credentials.bind_addrs}:${credentials.port}`;
+    $("cred-username").textContent = credentials.username;
+    $("cred-password").textContent = credentials.password;
+    section.hidden = false;
+  } catch (e) {
+    showMessage(`Cannot read the credentials: ${e.message}`, true);
+  }
+}
+
+function saveKey() {
+  const key = $("api-key").value.trim();
+  if (key) sessionStorage.setItem("apiKey", key);
+  else sessionStorage.removeItem("apiKey");
+  refreshStatus();
+  browse(cwd);
+}
+
+$("api-key").value = sessionStorage.getItem("apiKey") || "";
+$("api-key").onchange = saveKey;
+$("toggle").onclick = toggle;
+$("show-credentials").onclick = showCredentials;
+$("refresh").onclick = () => { refreshStatus(); browse(cwd); };
+$("upload").onchange = (e) => { upload([...e.target.files]); e.target.value = ""; };
+
+refreshStatus();
+browse("/");
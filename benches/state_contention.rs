@@ -0,0 +1,135 @@
+// Logins read the SFTP credentials while rotations and status changes
+// write them. Compares the former tokio RwLock with the lock-free
+// SharedCredentials, many sessions checking logins against one writer.
+// The credentials sit in a state snapshot, as in the server state.
+//
+//     cargo bench --bench state_contention
+
+use arc_swap::ArcSwap;
+use sftp_manager::sftp::{
+    CredentialsSource, SftpCredentials, SharedCredentials,
+};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+const SESSIONS: usize = 64;
+const LOGINS: usize = 20_000;
+// Pause between writes, about what a busy API sees
+const WRITE_EVERY: Duration = Duration::from_micros(50);
+
+// The part of the server state logins read
+struct Snapshot {
+    enabled: bool,
+    credentials: Option<Arc<SftpCredentials>>,
+}
+
+struct State(ArcSwap<Snapshot>);
+
+impl CredentialsSource for State {
+    fn load(&self) -> Option<Arc<SftpCredentials>> {
+        self.0.load().credentials.clone()
+    }
+}
+
+fn credentials(n: usize) -> SftpCredentials {
+    SftpCredentials::new(format!("sftp_{}", n), "secret".to_string())
+}
+
+async fn locked() -> Duration {
+    let shared = Arc::new(RwLock::new(Some(credentials(0))));
+    let writer = {
+        let shared = shared.clone();
+        tokio::spawn(async move {
+            for n in 1.. {
+                *shared.write().await = Some(credentials(n));
+                tokio::time::sleep(WRITE_EVERY).await;
+            }
+        })
+    };
+    let elapsed = sessions(move || {
+        let shared = shared.clone();
+        async move {
+            shared
+                .read()
+                .await
+                .as_ref()
+                .is_some_and(|c| c.matches("sftp_0", "secret"))
+        }
+    })
+    .await;
+    writer.abort();
+    elapsed
+}
+
+async fn lock_free() -> Duration {
+    let state = Arc::new(State(ArcSwap::from_pointee(Snapshot {
+        enabled: true,
+        credentials: Some(Arc::new(credentials(0))),
+    })));
+    let shared: SharedCredentials = state.clone();
+    let writer = tokio::spawn(async move {
+        for n in 1.. {
+            let next = Arc::new(credentials(n));
+            state.0.rcu(|current| Snapshot {
+                enabled: current.enabled,
+                credentials: Some(next.clone()),
+            });
+            tokio::time::sleep(WRITE_EVERY).await;
+        }
+    });
+    let elapsed = sessions(move || {
+        let shared = shared.clone();
+        async move {
+            shared
+                .load()
+                .as_deref()
+                .is_some_and(|c| c.matches("sftp_0", "secret"))
+        }
+    })
+    .await;
+    writer.abort();
+    elapsed
+}
+
+// Time for every session to check its logins
+async fn sessions<F, Fut>(login: F) -> Duration
+where
+    F: Fn() -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = bool> + Send,
+{
+    let started = Instant::now();
+    let tasks: Vec<_> = (0..SESSIONS)
+        .map(|_| {
+            let login = login.clone();
+            tokio::spawn(async move {
+                for _ in 0..LOGINS {
+                    std::hint::black_box(login().await);
+                }
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap();
+    }
+    started.elapsed()
+}
+
+fn main() {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let logins = (SESSIONS * LOGINS) as f64;
+    for (name, elapsed) in [
+        ("tokio RwLock", runtime.block_on(locked())),
+        ("ArcSwap", runtime.block_on(lock_free())),
+    ] {
+        println!(
+            "{:<14} {:>10.1?} {:>8.1} ns/login",
+            name,
+            elapsed,
+            elapsed.as_nanos() as f64 / logins
+        );
+    }
+}
@@ -0,0 +1,345 @@
+//! Typed async client for the sftp-manager REST management API.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), sftp_manager_client::Error> {
+//! let client = sftp_manager_client::Client::new("http://localhost:3000")?;
+//! let enabled = client.enable(Some(7)).await?;
+//! let credentials = client.credentials().await?;
+//! println!("{}@{}:{}", credentials.username, credentials.host, credentials.port);
+//! # let _ = enabled;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod models;
+
+// For building clients passed to `Client::with_http`
+pub use reqwest;
+
+use bytes::Bytes;
+use models::*;
+use reqwest::{Method, RequestBuilder, Url};
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Why a call failed
+#[derive(Debug)]
+pub enum Error {
+    /// The base URL cannot be used to build request URLs
+    InvalidUrl(String),
+    /// The server could not be reached, or the response not read
+    Http(reqwest::Error),
+    /// The server answered with an error status and message
+    Api { status: u16, message: String },
+    /// The response body is not the expected JSON
+    Decode(serde_json::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidUrl(url) => write!(f, "invalid base URL '{}'", url),
+            Error::Http(e) => write!(f, "request failed: {}", e),
+            Error::Api { status, message } => {
+                write!(f, "{} (HTTP {})", message, status)
+            }
+            Error::Decode(e) => write!(f, "unexpected response: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Http(e) => Some(e),
+            Error::Decode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Http(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Client for one server. Cloning is cheap and shares the connection pool.
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: Url,
+}
+
+impl Client {
+    /// Client for the API at `base_url`, e.g. `http://localhost:3000`
+    pub fn new(base_url: &str) -> Result<Self> {
+        Self::with_http(base_url, reqwest::Client::new())
+    }
+
+    /// Client sending requests through `http`, e.g. one with timeouts or
+    /// TLS settings of its own
+    pub fn with_http(base_url: &str, http: reqwest::Client) -> Result<Self> {
+        let base_url = Url::parse(base_url)
+            .ok()
+            .filter(|url| !url.cannot_be_a_base())
+            .ok_or_else(|| Error::InvalidUrl(base_url.to_string()))?;
+        Ok(Self { http, base_url })
+    }
+
+    pub async fn health(&self) -> Result<Health> {
+        // The only endpoint whose payload is not wrapped
+        let response = self.request(Method::GET, &["health"]).send().await?;
+        let body = response.bytes().await?;
+        serde_json::from_slice(&body).map_err(Error::Decode)
+    }
+
+    /// Enable the server when disabled, disable it when enabled
+    pub async fn toggle(&self) -> Result<ToggleResponse> {
+        self.call(self.request(Method::POST, &["sftp", "toggle"])).await
+    }
+
+    /// Enable the server with new credentials lasting `expiration_days`
+    /// (0 for never), or the server's default when None
+    pub async fn enable(
+        &self,
+        expiration_days: Option<u64>,
+    ) -> Result<ToggleResponse> {
+        let request = self
+            .request(Method::POST, &["sftp", "enable"])
+            .json(&json!({ "expiration_days": expiration_days }));
+        self.call(request).await
+    }
+
+    pub async fn disable(&self) -> Result<ToggleResponse> {
+        self.call(self.request(Method::POST, &["sftp", "disable"])).await
+    }
+
+    pub async fn status(&self) -> Result<Status> {
+        self.call(self.request(Method::GET, &["sftp", "status"])).await
+    }
+
+    pub async fn credentials(&self) -> Result<Credentials> {
+        self.call(self.request(Method::GET, &["sftp", "credentials"])).await
+    }
+
+    /// Replace the credentials of the running server
+    pub async fn rotate_credentials(&self) -> Result<ToggleResponse> {
+        let request =
+            self.request(Method::POST, &["sftp", "credentials", "rotate"]);
+        self.call(request).await
+    }
+
+    pub async fn sessions(&self) -> Result<Vec<Session>> {
+        self.call(self.request(Method::GET, &["sftp", "sessions"])).await
+    }
+
+    /// Where to resume uploading `path` after a dropped connection
+    pub async fn resume_point(&self, path: &str) -> Result<ResumePoint> {
+        let request = self
+            .request(Method::GET, &["sftp", "resume"])
+            .query(&[("path", path)]);
+        self.call(request).await
+    }
+
+    /// Disconnect a session
+    pub async fn kick_session(&self, id: u64) -> Result<()> {
+        let id = id.to_string();
+        let request = self.request(Method::DELETE, &["sftp", "sessions", &id]);
+        self.call::<Value>(request).await.map(drop)
+    }
+
+    pub async fn list_users(&self) -> Result<Vec<User>> {
+        self.call(self.request(Method::GET, &["admin", "users"])).await
+    }
+
+    pub async fn create_user(&self, username: &str) -> Result<User> {
+        let request = self
+            .request(Method::POST, &["admin", "users"])
+            .json(&json!({ "username": username }));
+        self.call(request).await
+    }
+
+    /// Replace the cron windows during which a user may be connected,
+    /// e.g. "* 8-17 * * mon-fri"; an empty list allows any time
+    pub async fn set_access_windows(
+        &self,
+        username: &str,
+        access_windows: &[String],
+    ) -> Result<User> {
+        let request = self
+            .request(
+                Method::PUT,
+                &["admin", "users", username, "access-windows"],
+            )
+            .json(&json!({ "access_windows": access_windows }));
+        self.call(request).await
+    }
+
+    /// Set the password a user logs in with over SFTP and FTPS; None
+    /// removes it, leaving only their authorized keys
+    pub async fn set_password(
+        &self,
+        username: &str,
+        password: Option<&str>,
+    ) -> Result<User> {
+        let request = self
+            .request(Method::PUT, &["admin", "users", username, "password"])
+            .json(&json!({ "password": password }));
+        self.call(request).await
+    }
+
+    pub async fn delete_user(&self, username: &str) -> Result<()> {
+        let request =
+            self.request(Method::DELETE, &["admin", "users", username]);
+        self.call::<Value>(request).await.map(drop)
+    }
+
+    /// Entries of a directory below the root, e.g. "/incoming"
+    pub async fn list_files(&self, path: &str) -> Result<DirectoryListing> {
+        let request =
+            self.request(Method::GET, &["files"]).query(&[("path", path)]);
+        self.call(request).await
+    }
+
+    /// Store `body` at `path`; an existing file is only replaced when
+    /// `overwrite` is set
+    pub async fn upload_file(
+        &self,
+        path: &str,
+        body: impl Into<reqwest::Body>,
+        overwrite: bool,
+    ) -> Result<FileUpload> {
+        let request = self
+            .request(Method::PUT, &["files"])
+            .query(&[("path", path)])
+            .query(&[("overwrite", overwrite)])
+            .body(body);
+        self.call(request).await
+    }
+
+    /// Contents of a file
+    pub async fn download_file(&self, path: &str) -> Result<Bytes> {
+        let request = self
+            .request(Method::GET, &["files", "download"])
+            .query(&[("path", path)]);
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(api_error(response).await);
+        }
+        Ok(response.bytes().await?)
+    }
+
+    pub async fn delete_file(&self, path: &str) -> Result<FileDelete> {
+        let request =
+            self.request(Method::DELETE, &["files"]).query(&[("path", path)]);
+        self.call(request).await
+    }
+
+    pub async fn file_tags(&self, path: &str) -> Result<FileTags> {
+        let request = self
+            .request(Method::GET, &["files", "tags"])
+            .query(&[("path", path)]);
+        self.call(request).await
+    }
+
+    /// Replace the tags of a file; an empty map removes them
+    pub async fn set_file_tags(
+        &self,
+        path: &str,
+        tags: &BTreeMap<String, String>,
+    ) -> Result<FileTags> {
+        let request = self
+            .request(Method::PUT, &["files", "tags"])
+            .query(&[("path", path)])
+            .json(tags);
+        self.call(request).await
+    }
+
+    /// Files carrying every one of `tags`
+    pub async fn find_tagged_files(
+        &self,
+        tags: &BTreeMap<String, String>,
+    ) -> Result<Vec<FileTags>> {
+        let request = self
+            .request(Method::POST, &["files", "tags", "search"])
+            .json(&json!({ "tags": tags }));
+        self.call(request).await
+    }
+
+    fn request(&self, method: Method, segments: &[&str]) -> RequestBuilder {
+        let mut url = self.base_url.clone();
+        if let Ok(mut path) = url.path_segments_mut() {
+            path.pop_if_empty().extend(segments);
+        }
+        self.http.request(method, url)
+    }
+
+    // Send a request and unwrap the payload of its response
+    async fn call<T: DeserializeOwned>(
+        &self,
+        request: RequestBuilder,
+    ) -> Result<T> {
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(api_error(response).await);
+        }
+        let body = response.bytes().await?;
+        let mut body: Value =
+            serde_json::from_slice(&body).map_err(Error::Decode)?;
+        serde_json::from_value(body["sftp"].take()).map_err(Error::Decode)
+    }
+}
+
+async fn api_error(response: reqwest::Response) -> Error {
+    let status = response.status();
+    let message = response
+        .json::<Value>()
+        .await
+        .ok()
+        .and_then(|body| body["message"].as_str().map(String::from))
+        .unwrap_or_else(|| {
+            status.canonical_reason().unwrap_or("request failed").to_string()
+        });
+    Error::Api { status: status.as_u16(), message }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paths_are_encoded_below_the_base_url() {
+        let client = Client::new("http://localhost:3000/manager/").unwrap();
+        let request = client
+            .request(Method::DELETE, &["admin", "users", "a b/c"])
+            .build()
+            .unwrap();
+        assert_eq!(
+            request.url().as_str(),
+            "http://localhost:3000/manager/admin/users/a%20b%2Fc"
+        );
+        assert!(Client::new("mailto:admin@example.com").is_err());
+    }
+
+    #[test]
+    fn test_status_decodes_server_output() {
+        let body = r#"{"sftp":{"enabled":true,
+            "expires_at":"2026-10-24T06:06:10+00:00","expiring_soon":false,
+            "listener":{"reachable":true,"address":"127.0.0.1:2222",
+            "server_banner":"SSH-2.0-russh","latency_ms":1},
+            "disk":{"available_bytes":512,"total_bytes":4096,
+            "reserve_bytes":0,"low":true}}}"#;
+        let mut body: Value = serde_json::from_str(body).unwrap();
+        let status: Status =
+            serde_json::from_value(body["sftp"].take()).unwrap();
+        assert!(status.enabled);
+        assert_eq!(status.schedule_open, None);
+        assert_eq!(status.listener.unwrap().address, "127.0.0.1:2222");
+        assert!(status.disk.unwrap().low);
+    }
+}
@@ -0,0 +1,227 @@
+// Payloads of the management API, mirroring the server's response models.
+// Optional fields the server leaves out decode as None.
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Health {
+    pub status: String,
+    pub timestamp: String,
+    pub version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uptime: Option<u64>,
+    pub sftp: SftpHealth,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SftpHealth {
+    pub enabled: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub listener: Option<ListenerCheck>,
+}
+
+// Username and password accepted by the SFTP server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Login {
+    pub username: String,
+    pub password: String,
+}
+
+// Result of toggling, enabling, disabling or rotating
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToggleResponse {
+    pub status: String,
+    pub enabled: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credentials: Option<Login>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Status {
+    pub enabled: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+    pub expiring_soon: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule_open: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub listener: Option<ListenerCheck>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub drain: Option<DrainStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure: Option<FailureStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disk: Option<DiskStatus>,
+    #[serde(default)]
+    pub running: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub listen_addr: Option<String>,
+    #[serde(default)]
+    pub active_sessions: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ListenerCheck {
+    pub reachable: bool,
+    pub address: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_banner: Option<String>,
+    pub latency_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiskStatus {
+    pub available_bytes: u64,
+    pub total_bytes: u64,
+    pub reserve_bytes: u64,
+    pub low: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DrainStatus {
+    pub started_at: String,
+    pub deadline: String,
+    pub completed: bool,
+    pub active_sessions: usize,
+    pub open_files: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FailureStatus {
+    pub reason: String,
+    // What failed, e.g. "bind" or "certificate"; empty from older servers
+    #[serde(default)]
+    pub kind: String,
+    pub attempts: u32,
+    pub failed_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_at: Option<String>,
+    pub gave_up: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+    // Where clients connect: the external address, or the listen address
+    pub host: String,
+    pub bind_addrs: String,
+    pub port: u16,
+    pub root_dir: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port_mapping: Option<PortMapping>,
+}
+
+// Port mapping on the server's router, with the router's external address
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PortMapping {
+    pub protocol: String,
+    pub external_ip: String,
+    pub external_port: u16,
+    pub internal_port: u16,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    pub id: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peer_addr: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    pub connected_at: DateTime<Utc>,
+    pub open_files: usize,
+    #[serde(default)]
+    pub memory_bytes: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_method: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub negotiated: Option<Negotiated>,
+}
+
+// Algorithms a session agreed on in the key exchange
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Negotiated {
+    pub kex: String,
+    pub host_key: String,
+    pub cipher: String,
+    // Unset for AEAD ciphers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mac: Option<String>,
+}
+
+// Where a client resumes uploading a file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResumePoint {
+    pub path: String,
+    // Bytes from the start of the file known to be written
+    pub offset: u64,
+    pub size: u64,
+    // False when the offset is just the size of the file
+    pub tracked: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct User {
+    pub id: i64,
+    pub username: String,
+    pub created_at: DateTime<Utc>,
+    // Cron windows in UTC during which the user may be connected; empty
+    // allows any time
+    #[serde(default)]
+    pub access_windows: Vec<String>,
+    // Tenant the user belongs to; None for users of the default root
+    #[serde(default)]
+    pub tenant: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DirectoryListing {
+    pub path: String,
+    // Directories first, then files, each sorted by name
+    pub entries: Vec<DirectoryEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DirectoryEntry {
+    pub name: String,
+    pub is_dir: bool,
+    pub bytes: u64,
+    pub modified: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileUpload {
+    pub path: String,
+    pub bytes: u64,
+    // Digest name to hex value
+    pub checksums: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileDelete {
+    pub path: String,
+    // Set when the file was moved to the trash instead of deleted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trash_id: Option<String>,
+}
+
+// Key/value tags attached to a file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileTags {
+    pub path: String,
+    pub tags: BTreeMap<String, String>,
+}
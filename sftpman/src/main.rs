@@ -0,0 +1,205 @@
+mod output;
+mod profile;
+
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use output::{OutputFormat, View};
+use profile::{Profile, ProfileFile};
+use serde::Serialize;
+use serde_json::{Value, json};
+use sftp_manager_client::reqwest::header::{
+    AUTHORIZATION, HeaderMap, HeaderValue,
+};
+use sftp_manager_client::{Client, reqwest};
+use std::time::Duration;
+
+// Command-line client for the sftp-manager management API. The server is
+// taken from --url, else from a profile in the configuration file.
+#[derive(Debug, Parser)]
+#[command(version, about = "Administers an sftp-manager server")]
+struct Cli {
+    /// Profile to use from the configuration file
+    #[arg(long, short, global = true, env = "SFTPMAN_PROFILE")]
+    profile: Option<String>,
+
+    /// Management API URL, overriding the profile
+    #[arg(long, global = true, env = "SFTPMAN_URL")]
+    url: Option<String>,
+
+    /// API key, overriding the profile's
+    #[arg(
+        long,
+        global = true,
+        env = "SFTPMAN_API_KEY",
+        hide_env_values = true
+    )]
+    api_key: Option<String>,
+
+    /// Output format; the profile's, else table
+    #[arg(long, short, global = true, value_enum)]
+    output: Option<OutputFormat>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Show whether the server is enabled, and its listener and drain state
+    Status,
+    /// Enable the server with new credentials
+    Enable {
+        /// Days until the credentials expire, 0 for never; the server's
+        /// expiration_days when omitted
+        #[arg(long)]
+        days: Option<u64>,
+    },
+    /// Disable the server
+    Disable,
+    /// Show the current credentials
+    Credentials,
+    /// Replace the credentials of the running server
+    Rotate,
+    /// Manage user accounts
+    #[command(subcommand)]
+    Users(UsersCommand),
+    /// List or disconnect SFTP sessions
+    #[command(subcommand)]
+    Sessions(SessionsCommand),
+    /// List the profiles in the configuration file
+    Profiles,
+}
+
+#[derive(Debug, Subcommand)]
+enum UsersCommand {
+    /// List user accounts
+    List,
+    /// Create a user account
+    Add { username: String },
+    /// Delete a user account
+    Remove { username: String },
+}
+
+#[derive(Debug, Subcommand)]
+enum SessionsCommand {
+    /// List connected sessions
+    List,
+    /// Disconnect a session
+    Kick { id: u64 },
+}
+
+const USER_COLUMNS: &[&str] = &["id", "username", "created_at"];
+const SESSION_COLUMNS: &[&str] =
+    &["id", "username", "peer_addr", "country", "connected_at", "open_files"];
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    if let Err(e) = run(cli).await {
+        eprintln!("error: {:#}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run(cli: Cli) -> anyhow::Result<()> {
+    let config_path = profile::config_path();
+    let profiles = match &config_path {
+        Some(path) => ProfileFile::load(path)?,
+        None => ProfileFile::default(),
+    };
+
+    if let Command::Profiles = cli.command {
+        let rows: Vec<Value> = profiles
+            .profiles
+            .iter()
+            .map(|(name, profile)| {
+                let default = profiles.default_profile.as_ref() == Some(name);
+                json!({"name": name, "url": profile.url, "default": default})
+            })
+            .collect();
+        let format = cli.output.unwrap_or(OutputFormat::Table);
+        let view = View::List(&["name", "url", "default"]);
+        println!("{}", output::render(format, &Value::Array(rows), view));
+        return Ok(());
+    }
+
+    let mut profile = profiles.select(cli.profile.as_deref())?;
+    if let Some(url) = cli.url {
+        profile.url = url;
+    }
+    if let Some(key) = cli.api_key {
+        profile.api_key = Some(key);
+    }
+    let format = cli.output.or(profile.output).unwrap_or(OutputFormat::Table);
+    let http = http_client(&profile)?;
+    let client = Client::with_http(&profile.url, http)?;
+
+    let (value, view) = match cli.command {
+        Command::Status => (to_value(client.status().await?), View::Record),
+        Command::Enable { days } => {
+            (to_value(client.enable(days).await?), View::Record)
+        }
+        Command::Disable => (to_value(client.disable().await?), View::Record),
+        Command::Credentials => {
+            (to_value(client.credentials().await?), View::Record)
+        }
+        Command::Rotate => {
+            (to_value(client.rotate_credentials().await?), View::Record)
+        }
+        Command::Users(UsersCommand::List) => {
+            (to_value(client.list_users().await?), View::List(USER_COLUMNS))
+        }
+        Command::Users(UsersCommand::Add { username }) => {
+            (to_value(client.create_user(&username).await?), View::Record)
+        }
+        Command::Users(UsersCommand::Remove { username }) => {
+            client.delete_user(&username).await?;
+            return done(format, &format!("Removed user {}", username));
+        }
+        Command::Sessions(SessionsCommand::List) => {
+            (to_value(client.sessions().await?), View::List(SESSION_COLUMNS))
+        }
+        Command::Sessions(SessionsCommand::Kick { id }) => {
+            client.kick_session(id).await?;
+            return done(format, &format!("Disconnected session {}", id));
+        }
+        Command::Profiles => unreachable!("handled above"),
+    };
+
+    println!("{}", output::render(format, &value, view));
+    Ok(())
+}
+
+// HTTP client sending the profile's API key and client certificate
+fn http_client(profile: &Profile) -> anyhow::Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder()
+        .timeout(Duration::from_secs(profile.timeout_secs));
+    if let Some(key) = &profile.api_key {
+        let mut value = HeaderValue::from_str(&format!("Bearer {}", key))
+            .context("invalid API key")?;
+        value.set_sensitive(true);
+        builder = builder
+            .default_headers(HeaderMap::from_iter([(AUTHORIZATION, value)]));
+    }
+    if let Some(path) = &profile.client_cert {
+        let pem = std::fs::read(path)
+            .with_context(|| format!("cannot read {}", path.display()))?;
+        let identity = reqwest::Identity::from_pem(&pem)
+            .with_context(|| format!("invalid {}", path.display()))?;
+        builder = builder.identity(identity);
+    }
+    builder.build().context("cannot build HTTP client")
+}
+
+fn to_value(payload: impl Serialize) -> Value {
+    serde_json::to_value(payload).unwrap_or(Value::Null)
+}
+
+// Report an action without a payload
+fn done(format: OutputFormat, message: &str) -> anyhow::Result<()> {
+    match format {
+        OutputFormat::Table => println!("{}", message),
+        OutputFormat::Json => println!("{}", json!({ "message": message })),
+    }
+    Ok(())
+}
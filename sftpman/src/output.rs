@@ -0,0 +1,136 @@
+use clap::ValueEnum;
+use serde::Deserialize;
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    Table,
+    Json,
+}
+
+// How a response is laid out as a table
+pub enum View<'a> {
+    // One object, as field/value rows; nested objects use dotted names
+    Record,
+    // A list of objects, one row each, with these fields as columns
+    List(&'a [&'a str]),
+}
+
+pub fn render(format: OutputFormat, value: &Value, view: View) -> String {
+    match format {
+        OutputFormat::Json => {
+            serde_json::to_string_pretty(value).unwrap_or_default()
+        }
+        OutputFormat::Table => match view {
+            View::Record => {
+                let mut rows = Vec::new();
+                flatten("", value, &mut rows);
+                table(&["FIELD", "VALUE"], &rows)
+            }
+            View::List(columns) => {
+                let items = value.as_array().map(Vec::as_slice).unwrap_or(&[]);
+                if items.is_empty() {
+                    return "(none)".to_string();
+                }
+                let rows: Vec<Vec<String>> = items
+                    .iter()
+                    .map(|item| {
+                        columns.iter().map(|c| cell(&item[*c])).collect()
+                    })
+                    .collect();
+                let header: Vec<String> =
+                    columns.iter().map(|c| c.to_uppercase()).collect();
+                let header: Vec<&str> =
+                    header.iter().map(String::as_str).collect();
+                table(&header, &rows)
+            }
+        },
+    }
+}
+
+fn flatten(prefix: &str, value: &Value, rows: &mut Vec<Vec<String>>) {
+    match value {
+        Value::Object(fields) => {
+            for (name, value) in fields {
+                let name = if prefix.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{}.{}", prefix, name)
+                };
+                flatten(&name, value, rows);
+            }
+        }
+        _ if prefix.is_empty() => rows.push(vec![String::new(), cell(value)]),
+        _ => rows.push(vec![prefix.to_string(), cell(value)]),
+    }
+}
+
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => "-".to_string(),
+        Value::String(s) => s.clone(),
+        Value::Array(items) => {
+            items.iter().map(cell).collect::<Vec<_>>().join(", ")
+        }
+        other => other.to_string(),
+    }
+}
+
+// Left-aligned columns separated by two spaces
+fn table(header: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = header.iter().map(|h| h.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let line = |cells: Vec<&str>| {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        padded.join("  ").trim_end().to_string()
+    };
+    let mut lines = vec![line(header.to_vec())];
+    lines.extend(
+        rows.iter().map(|row| line(row.iter().map(String::as_str).collect())),
+    );
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_tables_align_columns() {
+        let sessions = json!([
+            {"id": 1, "username": "alice", "open_files": 2},
+            {"id": 12, "username": null, "open_files": 0},
+        ]);
+        let output = render(
+            OutputFormat::Table,
+            &sessions,
+            View::List(&["id", "username", "open_files"]),
+        );
+        assert_eq!(
+            output,
+            "ID  USERNAME  OPEN_FILES\n\
+             1   alice     2\n\
+             12  -         0"
+        );
+
+        let status = json!({"enabled": true, "drain": {"completed": false}});
+        let output = render(OutputFormat::Table, &status, View::Record);
+        assert_eq!(
+            output,
+            "FIELD            VALUE\n\
+             drain.completed  false\n\
+             enabled          true"
+        );
+    }
+}
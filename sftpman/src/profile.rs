@@ -0,0 +1,136 @@
+use crate::output::OutputFormat;
+use anyhow::{Context, bail};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_URL: &str = "http://localhost:3000";
+
+// Servers known by name, read from the configuration file:
+//
+//   default_profile = "prod"
+//
+//   [profiles.prod]
+//   url = "https://sftp-admin.example.com"
+//   output = "json"
+//   api_key = "..."
+//   client_cert = "/etc/sftpman/admin.pem"
+#[derive(Debug, Default, Deserialize)]
+pub struct ProfileFile {
+    #[serde(default)]
+    pub default_profile: Option<String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Profile {
+    // Base URL of the management API
+    pub url: String,
+    #[serde(default)]
+    pub output: Option<OutputFormat>,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    // Admin, tenant or instance key sent as a bearer token
+    #[serde(default)]
+    pub api_key: Option<String>,
+    // PEM file with the client certificate followed by its private key
+    #[serde(default)]
+    pub client_cert: Option<PathBuf>,
+}
+
+fn default_timeout_secs() -> u64 {
+    30
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            url: DEFAULT_URL.to_string(),
+            output: None,
+            timeout_secs: default_timeout_secs(),
+            api_key: None,
+            client_cert: None,
+        }
+    }
+}
+
+// $SFTPMAN_CONFIG, else sftpman/config.toml in the XDG config directory
+pub fn config_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("SFTPMAN_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("sftpman").join("config.toml"))
+}
+
+impl ProfileFile {
+    // A missing file is the same as an empty one
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => Self::parse(&contents)
+                .with_context(|| format!("invalid {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Ok(Self::default())
+            }
+            Err(e) => Err(e).context(format!("cannot read {}", path.display())),
+        }
+    }
+
+    fn parse(contents: &str) -> anyhow::Result<Self> {
+        Ok(toml::from_str(contents)?)
+    }
+
+    // The named profile, else the default one, else localhost
+    pub fn select(&self, name: Option<&str>) -> anyhow::Result<Profile> {
+        match name.or(self.default_profile.as_deref()) {
+            Some(name) => match self.profiles.get(name) {
+                Some(profile) => Ok(profile.clone()),
+                None => bail!("no profile named '{}'", name),
+            },
+            None => Ok(Profile::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiles_fall_back_to_the_default() {
+        let file = ProfileFile::parse(
+            r#"
+            default_profile = "prod"
+
+            [profiles.prod]
+            url = "https://prod.example.com"
+
+            [profiles.staging]
+            url = "http://staging:3000"
+            output = "json"
+            api_key = "staging-0123456789"
+            client_cert = "/etc/sftpman/staging.pem"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(file.select(None).unwrap().url, "https://prod.example.com");
+        let staging = file.select(Some("staging")).unwrap();
+        assert_eq!(staging.output, Some(OutputFormat::Json));
+        assert_eq!(staging.timeout_secs, 30);
+        assert_eq!(staging.api_key.as_deref(), Some("staging-0123456789"));
+        assert_eq!(
+            staging.client_cert.as_deref(),
+            Some(Path::new("/etc/sftpman/staging.pem"))
+        );
+        assert_eq!(file.select(None).unwrap().api_key, None);
+        assert!(file.select(Some("dev")).is_err());
+
+        let empty = ProfileFile::default();
+        assert_eq!(empty.select(None).unwrap().url, DEFAULT_URL);
+    }
+}
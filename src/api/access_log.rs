@@ -0,0 +1,71 @@
+use crate::api::tls::ApiClient;
+use axum::body::Body;
+use axum::extract::ConnectInfo;
+use axum::http::{Request, Response};
+use std::time::Duration;
+use tower_http::classify::{ServerErrorsAsFailures, SharedClassifier};
+use tower_http::trace::TraceLayer;
+use tracing::{Span, field, info, info_span};
+
+type AccessLogLayer = TraceLayer<
+    SharedClassifier<ServerErrorsAsFailures>,
+    fn(&Request<Body>) -> Span,
+    (),
+    fn(&Response<Body>, Duration, &Span),
+>;
+
+// One "access" line per API request with the method, path, caller,
+// status and latency. The country is filled in by the GeoIP middleware,
+// the tenant or instance by the scoped key middleware.
+pub fn access_log_layer() -> AccessLogLayer {
+    TraceLayer::new_for_http()
+        .make_span_with(request_span as fn(&Request<Body>) -> Span)
+        .on_request(())
+        .on_response(log_response as fn(&Response<Body>, Duration, &Span))
+}
+
+fn request_span(request: &Request<Body>) -> Span {
+    let client = request
+        .extensions()
+        .get::<ConnectInfo<ApiClient>>()
+        .map(|ConnectInfo(client)| client);
+
+    let span = info_span!(
+        "access",
+        method = %request.method(),
+        path = %request.uri().path(),
+        client = field::Empty,
+        certificate = field::Empty,
+        country = field::Empty,
+        tenant = field::Empty,
+        instance = field::Empty,
+        forwarded_for = field::Empty,
+        user_agent = field::Empty,
+    );
+    if let Some(client) = client {
+        span.record("client", client.addr.ip().to_string());
+        if let Some(certificate) = &client.certificate {
+            span.record("certificate", certificate.as_str());
+        }
+    }
+    // Behind a proxy the peer is the proxy itself
+    if let Some(forwarded) = header(request, "x-forwarded-for") {
+        span.record("forwarded_for", forwarded);
+    }
+    if let Some(user_agent) = header(request, "user-agent") {
+        span.record("user_agent", user_agent);
+    }
+    span
+}
+
+fn header<'a>(request: &'a Request<Body>, name: &str) -> Option<&'a str> {
+    request.headers().get(name).and_then(|v| v.to_str().ok())
+}
+
+fn log_response(response: &Response<Body>, latency: Duration, _span: &Span) {
+    info!(
+        status = response.status().as_u16(),
+        latency_ms = latency.as_secs_f64() * 1000.0,
+        "request completed"
+    );
+}
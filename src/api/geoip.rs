@@ -0,0 +1,40 @@
+use crate::api::tls::ApiClient;
+use crate::responses::sftp::SftpApiResponse;
+use crate::sftp::PeerFilter;
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::StatusCode;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use std::sync::Arc;
+use tracing::{Span, warn};
+
+// Middleware turning away API clients from countries that are not let in.
+// Uses the connecting address; X-Forwarded-For can be set by anyone.
+// Health checks are open to all.
+pub async fn restrict_countries(
+    State(filter): State<Arc<dyn PeerFilter>>,
+    ConnectInfo(client): ConnectInfo<ApiClient>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let country = filter.country(client.addr.ip());
+    if let Some(country) = &country {
+        Span::current().record("country", country.as_str());
+    }
+    if request.uri().path().starts_with("/health")
+        || filter.allows(country.as_deref())
+    {
+        return next.run(request).await;
+    }
+
+    warn!(
+        "Rejected API request from {} (country {})",
+        client.addr,
+        country.as_deref().unwrap_or("unknown")
+    );
+    SftpApiResponse::<()>::error(
+        StatusCode::FORBIDDEN,
+        "Requests from this location are not allowed",
+    )
+    .into_response()
+}
@@ -0,0 +1,237 @@
+use crate::api::handlers::sftp::credentials_accessor;
+use crate::api::scoped_keys::KeyHolder;
+use crate::api::tls::ApiClient;
+use crate::models::accounts::{
+    AddKeyRequest, CreateShareRequest, CreateUserRequest, EraseUserRequest,
+    SetAccessWindowsRequest, SetPasswordRequest,
+};
+use crate::models::sftp::StateSnapshot;
+use crate::responses::sftp::SftpApiResponse;
+use crate::services::supervisor::DEFAULT_INSTANCE;
+use crate::state::AppState;
+use axum::{
+    Extension, Json,
+    extract::{ConnectInfo, Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+};
+use serde::Deserialize;
+use tracing::{error, info};
+
+#[derive(Debug, Deserialize)]
+pub struct LogQuery {
+    #[serde(default = "default_log_limit")]
+    pub limit: u32,
+}
+
+fn default_log_limit() -> u32 {
+    100
+}
+
+#[derive(Debug, Deserialize)]
+pub struct JournalQuery {
+    // Return entries after this sequence number
+    #[serde(default)]
+    pub since: u64,
+    #[serde(default = "default_log_limit")]
+    pub limit: u32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    // Include the SFTP credentials, recorded as a retrieval of them
+    #[serde(default)]
+    pub credentials: bool,
+}
+
+pub async fn export_state(
+    State(state): State<AppState>,
+    ConnectInfo(client): ConnectInfo<ApiClient>,
+    headers: HeaderMap,
+    key: Option<Extension<KeyHolder>>,
+    Query(query): Query<ExportQuery>,
+) -> impl IntoResponse {
+    info!("Export state request");
+    let accessor = query
+        .credentials
+        .then(|| credentials_accessor(&client, &headers, key.as_deref()));
+    match state.sftp_service.export_state(DEFAULT_INSTANCE, accessor).await {
+        Ok(snapshot) => Json(snapshot).into_response(),
+        Err(e) => {
+            error!("Failed to export state: {}", e);
+            SftpApiResponse::<()>::error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to export state: {}", e),
+            )
+            .into_response()
+        }
+    }
+}
+
+pub async fn import_state(
+    State(state): State<AppState>,
+    Json(snapshot): Json<StateSnapshot>,
+) -> impl IntoResponse {
+    info!("Import state request");
+    state.sftp_service.import_state(snapshot).await
+}
+
+pub async fn get_audit_log(
+    State(state): State<AppState>,
+    Query(query): Query<LogQuery>,
+) -> impl IntoResponse {
+    info!("Get audit log request");
+    state.audit.list_audit(query.limit).await
+}
+
+pub async fn get_transfer_log(
+    State(state): State<AppState>,
+    Query(query): Query<LogQuery>,
+) -> impl IntoResponse {
+    info!("Get transfer log request");
+    state.audit.list_transfers(query.limit).await
+}
+
+pub async fn list_users(State(state): State<AppState>) -> impl IntoResponse {
+    info!("List users request");
+    state.accounts.list_users().await
+}
+
+pub async fn create_user(
+    State(state): State<AppState>,
+    Json(request): Json<CreateUserRequest>,
+) -> impl IntoResponse {
+    info!("Create user request");
+    state.accounts.create_user(request).await
+}
+
+pub async fn delete_user(
+    State(state): State<AppState>,
+    Path(username): Path<String>,
+) -> impl IntoResponse {
+    info!("Delete user request");
+    state.accounts.delete_user(&username).await
+}
+
+pub async fn export_user_data(
+    State(state): State<AppState>,
+    Path(username): Path<String>,
+) -> impl IntoResponse {
+    info!("Export user data request");
+    state.privacy.export(&username).await
+}
+
+pub async fn erase_user(
+    State(state): State<AppState>,
+    Path(username): Path<String>,
+    Json(request): Json<EraseUserRequest>,
+) -> impl IntoResponse {
+    info!("Erase user request");
+    state.privacy.erase(&username, request).await
+}
+
+pub async fn set_access_windows(
+    State(state): State<AppState>,
+    Path(username): Path<String>,
+    Json(request): Json<SetAccessWindowsRequest>,
+) -> impl IntoResponse {
+    info!("Set access windows request");
+    state.accounts.set_access_windows(&username, request).await
+}
+
+pub async fn set_password(
+    State(state): State<AppState>,
+    Path(username): Path<String>,
+    Json(request): Json<SetPasswordRequest>,
+) -> impl IntoResponse {
+    info!("Set password request");
+    state.accounts.set_password(&username, request).await
+}
+
+pub async fn list_keys(
+    State(state): State<AppState>,
+    Path(username): Path<String>,
+) -> impl IntoResponse {
+    info!("List keys request");
+    state.accounts.list_keys(&username).await
+}
+
+pub async fn add_key(
+    State(state): State<AppState>,
+    Path(username): Path<String>,
+    Json(request): Json<AddKeyRequest>,
+) -> impl IntoResponse {
+    info!("Add key request");
+    state.accounts.add_key(&username, request).await
+}
+
+pub async fn remove_key(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> impl IntoResponse {
+    info!("Remove key request");
+    state.accounts.remove_key(id).await
+}
+
+pub async fn list_shares(State(state): State<AppState>) -> impl IntoResponse {
+    info!("List shares request");
+    state.accounts.list_shares().await
+}
+
+pub async fn create_share(
+    State(state): State<AppState>,
+    Json(request): Json<CreateShareRequest>,
+) -> impl IntoResponse {
+    info!("Create share request");
+    state.accounts.create_share(request).await
+}
+
+pub async fn get_share(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+) -> impl IntoResponse {
+    info!("Get share request");
+    state.accounts.get_share(&token).await
+}
+
+pub async fn delete_share(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+) -> impl IntoResponse {
+    info!("Delete share request");
+    state.accounts.delete_share(&token).await
+}
+
+pub async fn get_journal(
+    State(state): State<AppState>,
+    Query(query): Query<JournalQuery>,
+) -> impl IntoResponse {
+    info!("Get event journal request");
+    state.journal.read(query.since, query.limit as usize).await
+}
+
+pub async fn get_mirror_queue(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    info!("Get mirror queue request");
+    state.mirror.get_queue().await
+}
+
+pub async fn retry_mirror_jobs(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    info!("Retry failed mirror jobs request");
+    state.mirror.retry_failed().await
+}
+
+pub async fn get_backup_status(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    info!("Get backup status request");
+    state.backup.get_status().await
+}
+
+pub async fn start_backup(State(state): State<AppState>) -> impl IntoResponse {
+    info!("Start backup request");
+    state.backup.run_now().await
+}
@@ -0,0 +1,8 @@
+use crate::state::AppState;
+use axum::{extract::State, response::IntoResponse};
+use tracing::info;
+
+pub async fn list_replicas(State(state): State<AppState>) -> impl IntoResponse {
+    info!("List replicas request");
+    state.cluster.list_replicas().await
+}
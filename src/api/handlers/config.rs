@@ -0,0 +1,79 @@
+use crate::config::settings::Settings;
+use crate::config::validation::{
+    ConfigIssue, ValidationContext, has_errors, validate,
+};
+use crate::services::config_reload::ReloadReport;
+use crate::state::AppState;
+use axum::{
+    Json,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use tracing::info;
+
+#[derive(Debug, Serialize)]
+pub struct ConfigValidationResponse {
+    pub valid: bool,
+    pub issues: Vec<ConfigIssue>,
+}
+
+impl IntoResponse for ConfigValidationResponse {
+    fn into_response(self) -> Response {
+        let status = if self.valid {
+            StatusCode::OK
+        } else {
+            StatusCode::UNPROCESSABLE_ENTITY
+        };
+        (status, Json(self)).into_response()
+    }
+}
+
+impl IntoResponse for ReloadReport {
+    fn into_response(self) -> Response {
+        let status = if self.reloaded {
+            StatusCode::OK
+        } else {
+            StatusCode::UNPROCESSABLE_ENTITY
+        };
+        (status, Json(self)).into_response()
+    }
+}
+
+// Validate the posted configuration, or the running one when no body is sent
+pub async fn validate_config(
+    State(state): State<AppState>,
+    body: Option<Json<Settings>>,
+) -> impl IntoResponse {
+    info!("Validate configuration request");
+
+    // A posted configuration names paths and ports chosen by the caller, so
+    // it only gets the checks that neither touch the host nor change it
+    let (settings, static_only) = match body {
+        Some(Json(settings)) => (settings, true),
+        None => (state.settings.borrow().clone(), false),
+    };
+
+    // Ports held by this process are not free but are not a problem either
+    let context = {
+        let current = state.settings.borrow();
+        ValidationContext {
+            bound_ports: vec![current.server.port, current.sftp.port],
+            static_only,
+        }
+    };
+
+    let issues =
+        tokio::task::spawn_blocking(move || validate(&settings, &context))
+            .await
+            .unwrap_or_default();
+
+    ConfigValidationResponse { valid: !has_errors(&issues), issues }
+}
+
+// Re-read the configuration files and apply what can change at runtime
+pub async fn reload_config(State(state): State<AppState>) -> impl IntoResponse {
+    info!("Reload configuration request");
+    state.config_reloader.reload().await
+}
@@ -0,0 +1,127 @@
+use crate::models::files::{
+    ExtractRequest, FilePathQuery, FileSearchQuery, FileTagSearchRequest,
+    FileUploadQuery, RestoreRequest,
+};
+use crate::state::AppState;
+use axum::{
+    Json,
+    body::Body,
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+};
+use std::collections::BTreeMap;
+use tracing::info;
+
+pub async fn get_file_checksum(
+    State(state): State<AppState>,
+    Query(query): Query<FilePathQuery>,
+) -> impl IntoResponse {
+    info!("Get checksum request for {}", query.path);
+    state.audit.get_checksum(&query.path).await
+}
+
+pub async fn list_directory(
+    State(state): State<AppState>,
+    Query(query): Query<FilePathQuery>,
+) -> impl IntoResponse {
+    state.files.list(&query.path).await
+}
+
+pub async fn download_file(
+    State(state): State<AppState>,
+    Query(query): Query<FilePathQuery>,
+) -> impl IntoResponse {
+    info!("Download request for {}", query.path);
+    state.files.download(&query.path).await
+}
+
+pub async fn upload_file(
+    State(state): State<AppState>,
+    Query(query): Query<FileUploadQuery>,
+    headers: HeaderMap,
+    body: Body,
+) -> Response {
+    info!("Upload request for {}", query.path);
+    match state.uploads.admit(&headers, body) {
+        Ok(body) => state
+            .files
+            .upload(&query.path, query.overwrite, body)
+            .await
+            .into_response(),
+        Err(aborted) => aborted.into_response(),
+    }
+}
+
+pub async fn delete_file(
+    State(state): State<AppState>,
+    Query(query): Query<FilePathQuery>,
+) -> impl IntoResponse {
+    info!("Delete request for {}", query.path);
+    state.files.delete(&query.path).await
+}
+
+pub async fn list_trash(State(state): State<AppState>) -> impl IntoResponse {
+    state.files.list_trash().await
+}
+
+pub async fn restore_file(
+    State(state): State<AppState>,
+    Json(request): Json<RestoreRequest>,
+) -> impl IntoResponse {
+    info!("Restore request: {:?}", request);
+    state.files.restore(request).await
+}
+
+pub async fn search_files(
+    State(state): State<AppState>,
+    Query(query): Query<FileSearchQuery>,
+) -> impl IntoResponse {
+    info!("File search for {:?}", query.q);
+    state.file_index.search(query).await
+}
+
+pub async fn get_file_tags(
+    State(state): State<AppState>,
+    Query(query): Query<FilePathQuery>,
+) -> impl IntoResponse {
+    state.file_tags.get(&query.path).await
+}
+
+pub async fn set_file_tags(
+    State(state): State<AppState>,
+    Query(query): Query<FilePathQuery>,
+    Json(tags): Json<BTreeMap<String, String>>,
+) -> impl IntoResponse {
+    info!("Set tags request for {}", query.path);
+    state.file_tags.set(&query.path, tags).await
+}
+
+pub async fn search_file_tags(
+    State(state): State<AppState>,
+    Json(request): Json<FileTagSearchRequest>,
+) -> impl IntoResponse {
+    info!("File tag search for {:?}", request.tags);
+    state.file_tags.search(request).await
+}
+
+pub async fn get_integrity_report(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    state.integrity.get_report().await
+}
+
+pub async fn extract_archive(
+    State(state): State<AppState>,
+    Json(request): Json<ExtractRequest>,
+) -> impl IntoResponse {
+    info!("Extract request: {:?}", request);
+    state.extract.start(request).await
+}
+
+pub async fn get_extract_job(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    state.extract.get_job(&id)
+}
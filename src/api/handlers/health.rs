@@ -1,3 +1,5 @@
+use crate::models::files::BackupStatus;
+use crate::models::sftp::ListenerCheck;
 use crate::state::AppState;
 use axum::{
     Json,
@@ -6,15 +8,26 @@ use axum::{
     response::{IntoResponse, Response},
 };
 use chrono::Utc;
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize)]
 pub struct HealthResponse {
     pub status: String,
     pub timestamp: String,
     pub version: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub uptime: Option<u64>,
+    pub sftp: SftpHealth,
+    // Only while backups are enabled; failures do not degrade health
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backup: Option<BackupStatus>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SftpHealth {
+    pub enabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub listener: Option<ListenerCheck>,
 }
 
 impl IntoResponse for HealthResponse {
@@ -24,12 +37,46 @@ impl IntoResponse for HealthResponse {
 }
 
 pub async fn health_check(State(state): State<AppState>) -> impl IntoResponse {
+    health(&state).await
+}
+
+// 503 while the SFTP listener should be up but cannot be reached, so
+// orchestrators hold traffic back
+pub async fn readiness_check(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    let health = health(&state).await;
+    let status = if health.status == "healthy" {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(health))
+}
+
+async fn health(state: &AppState) -> HealthResponse {
     let uptime_diff = (Utc::now() - state.uptime).num_seconds() as u64;
 
+    // Only probe the listener when it is supposed to be running
+    let enabled = state.sftp_service.state.is_enabled().await;
+    let listener = if state.sftp_service.state.should_run().await {
+        Some(state.sftp_service.probe().await.check().await)
+    } else {
+        None
+    };
+    let healthy = listener.as_ref().is_none_or(|l| l.reachable);
+    let backup = if state.settings.borrow().backup.enabled {
+        Some(state.backup.status().await)
+    } else {
+        None
+    };
+
     HealthResponse {
-        status: "healthy".to_string(),
+        status: if healthy { "healthy" } else { "degraded" }.to_string(),
         timestamp: Utc::now().to_rfc3339(),
         version: "0.1.0".into(),
         uptime: Some(uptime_diff),
+        sftp: SftpHealth { enabled, listener },
+        backup,
     }
 }
@@ -0,0 +1,160 @@
+use crate::api::handlers::sftp::credentials_accessor;
+use crate::api::scoped_keys::{ApiScope, KeyHolder, out_of_scope};
+use crate::api::tls::ApiClient;
+use crate::models::files::{FilePathQuery, FileUploadQuery};
+use crate::services::supervisor::unknown_instance;
+use crate::state::AppState;
+use axum::{
+    Extension,
+    body::Body,
+    extract::{ConnectInfo, Path, Query, State},
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+};
+use tracing::info;
+
+type Scope = Option<Extension<ApiScope>>;
+
+// Refuses requests whose API key is scoped to another instance or a tenant
+fn check_scope(scope: &Scope, name: &str) -> Option<Response> {
+    match scope {
+        Some(Extension(scope))
+            if !ApiScope::allows_instance(Some(scope), name) =>
+        {
+            Some(out_of_scope::<()>(scope).into_response())
+        }
+        _ => None,
+    }
+}
+
+pub async fn list_instances(
+    State(state): State<AppState>,
+    scope: Scope,
+) -> impl IntoResponse {
+    info!("List SFTP instances request");
+    let mut response = state.supervisor.list().await;
+    if let Some(instances) = &mut response.sftp {
+        let scope = scope.as_ref().map(|Extension(scope)| scope);
+        instances.retain(|i| ApiScope::allows_instance(scope, &i.name));
+    }
+    response
+}
+
+pub async fn get_instance_status(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    scope: Scope,
+) -> Response {
+    if let Some(response) = check_scope(&scope, &name) {
+        return response;
+    }
+    info!("Get SFTP instance {} status request", name);
+    state.supervisor.status(&name).await.into_response()
+}
+
+pub async fn toggle_instance(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    scope: Scope,
+    ConnectInfo(client): ConnectInfo<ApiClient>,
+    headers: HeaderMap,
+    key: Option<Extension<KeyHolder>>,
+) -> Response {
+    if let Some(response) = check_scope(&scope, &name) {
+        return response;
+    }
+    info!("🔁 Toggle SFTP instance {} request", name);
+    let accessor = credentials_accessor(&client, &headers, key.as_deref());
+    state.supervisor.toggle(&name, accessor).await.into_response()
+}
+
+pub async fn get_instance_credentials(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    scope: Scope,
+    ConnectInfo(client): ConnectInfo<ApiClient>,
+    headers: HeaderMap,
+    key: Option<Extension<KeyHolder>>,
+) -> Response {
+    if let Some(response) = check_scope(&scope, &name) {
+        return response;
+    }
+    info!("Get SFTP instance {} credentials request", name);
+    let accessor = credentials_accessor(&client, &headers, key.as_deref());
+    match state.supervisor.credentials(&name, accessor).await {
+        Ok(response) => response.into_response(),
+        Err(response) => response.into_response(),
+    }
+}
+
+pub async fn list_instance_directory(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    scope: Scope,
+    Query(query): Query<FilePathQuery>,
+) -> Response {
+    if let Some(response) = check_scope(&scope, &name) {
+        return response;
+    }
+    match state.supervisor.files(&name) {
+        Some(files) => files.list(&query.path).await.into_response(),
+        None => unknown_instance::<()>(&name).into_response(),
+    }
+}
+
+pub async fn download_instance_file(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    scope: Scope,
+    Query(query): Query<FilePathQuery>,
+) -> Response {
+    if let Some(response) = check_scope(&scope, &name) {
+        return response;
+    }
+    info!("Download request for {} of instance {}", query.path, name);
+    match state.supervisor.files(&name) {
+        Some(files) => files.download(&query.path).await,
+        None => unknown_instance::<()>(&name).into_response(),
+    }
+}
+
+pub async fn upload_instance_file(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    scope: Scope,
+    Query(query): Query<FileUploadQuery>,
+    headers: HeaderMap,
+    body: Body,
+) -> Response {
+    if let Some(response) = check_scope(&scope, &name) {
+        return response;
+    }
+    info!("Upload request for {} of instance {}", query.path, name);
+    let body = match state.uploads.admit(&headers, body) {
+        Ok(body) => body,
+        Err(aborted) => return aborted.into_response(),
+    };
+    match state.supervisor.files(&name) {
+        Some(files) => files
+            .upload(&query.path, query.overwrite, body)
+            .await
+            .into_response(),
+        None => unknown_instance::<()>(&name).into_response(),
+    }
+}
+
+pub async fn delete_instance_file(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    scope: Scope,
+    Query(query): Query<FilePathQuery>,
+) -> Response {
+    if let Some(response) = check_scope(&scope, &name) {
+        return response;
+    }
+    info!("Delete request for {} of instance {}", query.path, name);
+    match state.supervisor.files(&name) {
+        Some(files) => files.delete(&query.path).await.into_response(),
+        None => unknown_instance::<()>(&name).into_response(),
+    }
+}
@@ -0,0 +1,73 @@
+use crate::services::metrics::{Gauge, certificate_expiry, render};
+use crate::state::AppState;
+use axum::{extract::State, http::header, response::IntoResponse};
+use chrono::Utc;
+use std::path::Path;
+use std::time::SystemTime;
+use tracing::{debug, warn};
+
+// Gauges for alerting before credentials or certificates expire or the
+// disk fills up, in the Prometheus text format
+pub async fn get_metrics(State(state): State<AppState>) -> impl IntoResponse {
+    debug!("Get metrics request");
+    let mut credentials = Gauge::new(
+        "sftp_manager_credentials_expiry_seconds",
+        "Seconds until the SFTP credentials of an instance expire",
+    );
+    let mut disk = Gauge::new(
+        "sftp_manager_disk_free_bytes",
+        "Bytes available on the filesystem of an instance's root directory",
+    );
+    for (name, service) in state.supervisor.instances() {
+        let current = service.state.current();
+        if current.enabled
+            && let Some(expiration) = current.expiration
+        {
+            let seconds = match expiration.duration_since(SystemTime::now()) {
+                Ok(remaining) => remaining.as_secs_f64(),
+                Err(e) => -e.duration().as_secs_f64(),
+            };
+            credentials.set(&[("instance", name)], seconds.floor());
+        }
+        let root = Path::new(&service.root_dir);
+        match service.context.disk_space.status(root).await {
+            Ok(status) => {
+                disk.set(&[("instance", name)], status.available_bytes as f64)
+            }
+            Err(e) => warn!("Cannot read free space of {}: {}", name, e),
+        }
+    }
+
+    let mut certificates = Gauge::new(
+        "sftp_manager_tls_certificate_expiry_seconds",
+        "Seconds until the TLS certificate of a listener expires",
+    );
+    let files = {
+        let settings = state.settings.borrow();
+        let tls = &settings.server.tls;
+        let ftps = &settings.ftps;
+        [
+            ("api", tls.enabled, tls.certificate_file.clone()),
+            ("ftps", ftps.enabled, ftps.certificate_file.clone()),
+        ]
+    };
+    for (listener, enabled, file) in files {
+        if !enabled || file.is_empty() {
+            continue;
+        }
+        match certificate_expiry(&file) {
+            Ok(expiry) => {
+                let seconds = (expiry - Utc::now()).num_seconds();
+                certificates.set(&[("listener", listener)], seconds as f64);
+            }
+            Err(e) => {
+                warn!("Cannot read the {} certificate: {:#}", listener, e)
+            }
+        }
+    }
+
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        render(&[credentials, certificates, disk]),
+    )
+}
@@ -1,2 +1,11 @@
+pub mod admin;
+pub mod cluster;
+pub mod config;
+pub mod files;
 pub mod health;
+pub mod instances;
+pub mod metrics;
 pub(crate) mod sftp;
+pub mod tenants;
+pub mod tus;
+pub mod ui;
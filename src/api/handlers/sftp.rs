@@ -1,10 +1,50 @@
+use crate::api::handlers::admin::LogQuery;
+use crate::api::scoped_keys::KeyHolder;
+use crate::api::tls::ApiClient;
+use crate::models::files::FilePathQuery;
+use crate::models::sftp::{
+    CredentialsAccessor, DrainRequest, EnableRequest, PathRulesBody,
+    ScheduleRequest,
+};
+use crate::services::supervisor::DEFAULT_INSTANCE;
 use crate::state::AppState;
-use axum::{extract::State, response::IntoResponse};
+use crate::stats::BucketSize;
+use axum::{
+    Extension, Json,
+    extract::{ConnectInfo, Path, Query, State},
+    http::HeaderMap,
+    response::IntoResponse,
+};
+use serde::Deserialize;
 use tracing::info;
 
-pub async fn toggle_sftp(State(state): State<AppState>) -> impl IntoResponse {
+pub async fn toggle_sftp(
+    State(state): State<AppState>,
+    ConnectInfo(client): ConnectInfo<ApiClient>,
+    headers: HeaderMap,
+    key: Option<Extension<KeyHolder>>,
+) -> impl IntoResponse {
     info!("🔁 Toggle SFTP request");
-    state.sftp_service.toggle().await
+    let accessor = credentials_accessor(&client, &headers, key.as_deref());
+    state.sftp_service.toggle(DEFAULT_INSTANCE, accessor).await
+}
+
+pub async fn enable_sftp(
+    State(state): State<AppState>,
+    ConnectInfo(client): ConnectInfo<ApiClient>,
+    headers: HeaderMap,
+    key: Option<Extension<KeyHolder>>,
+    request: Option<Json<EnableRequest>>,
+) -> impl IntoResponse {
+    info!("Enable SFTP request");
+    let request = request.map(|Json(r)| r).unwrap_or_default();
+    let accessor = credentials_accessor(&client, &headers, key.as_deref());
+    state.sftp_service.enable(request, DEFAULT_INSTANCE, accessor).await
+}
+
+pub async fn disable_sftp(State(state): State<AppState>) -> impl IntoResponse {
+    info!("Disable SFTP request");
+    state.sftp_service.disable().await
 }
 
 pub async fn get_sftp_status(
@@ -16,7 +56,166 @@ pub async fn get_sftp_status(
 
 pub async fn get_sftp_credentials(
     State(state): State<AppState>,
+    ConnectInfo(client): ConnectInfo<ApiClient>,
+    headers: HeaderMap,
+    key: Option<Extension<KeyHolder>>,
 ) -> impl IntoResponse {
     info!("Get SFTP credentials request");
-    state.sftp_service.get_credentials().await
+    let accessor = credentials_accessor(&client, &headers, key.as_deref());
+    state.sftp_service.get_credentials(DEFAULT_INSTANCE, accessor).await
+}
+
+pub async fn get_sftp_credentials_access_log(
+    State(state): State<AppState>,
+    Query(query): Query<LogQuery>,
+) -> impl IntoResponse {
+    info!("Get SFTP credentials access log request");
+    state.audit.list_credentials_access(query.limit).await
+}
+
+// Who is asking for credentials over REST
+pub(crate) fn credentials_accessor(
+    client: &ApiClient,
+    headers: &HeaderMap,
+    key: Option<&KeyHolder>,
+) -> CredentialsAccessor {
+    CredentialsAccessor {
+        api: "rest",
+        client: Some(client.addr.ip().to_string()),
+        forwarded_for: headers
+            .get("x-forwarded-for")
+            .and_then(|v| v.to_str().ok())
+            .map(String::from),
+        certificate: client.certificate.clone(),
+        api_key: key.map(KeyHolder::label),
+    }
+}
+
+pub async fn rotate_sftp_credentials(
+    State(state): State<AppState>,
+    ConnectInfo(client): ConnectInfo<ApiClient>,
+    headers: HeaderMap,
+    key: Option<Extension<KeyHolder>>,
+) -> impl IntoResponse {
+    info!("🔁 Rotate SFTP credentials request");
+    let accessor = credentials_accessor(&client, &headers, key.as_deref());
+    state.sftp_service.rotate_credentials(DEFAULT_INSTANCE, accessor).await
+}
+
+pub async fn get_sftp_credential_history(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    info!("Get SFTP credential history request");
+    state.sftp_service.get_credential_history().await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatsQuery {
+    pub bucket: Option<BucketSize>,
+}
+
+pub async fn get_sftp_stats(
+    State(state): State<AppState>,
+    Query(query): Query<StatsQuery>,
+) -> impl IntoResponse {
+    info!("Get SFTP stats request");
+    state.sftp_service.get_stats(query.bucket).await
+}
+
+pub async fn get_sftp_sessions(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    info!("Get SFTP sessions request");
+    state.sftp_service.get_sessions()
+}
+
+pub async fn get_sftp_resume_point(
+    State(state): State<AppState>,
+    Query(query): Query<FilePathQuery>,
+) -> impl IntoResponse {
+    info!("Get SFTP resume point of {} request", query.path);
+    state.sftp_service.get_resume_point(&query.path).await
+}
+
+pub async fn kick_sftp_session(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> impl IntoResponse {
+    info!("Kick SFTP session {} request", id);
+    state.sftp_service.kick_session(id).await
+}
+
+pub async fn list_sftp_recordings(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    info!("List SFTP recordings request");
+    state.sftp_service.list_recordings().await
+}
+
+pub async fn get_sftp_recording(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> impl IntoResponse {
+    info!("Get SFTP recording {} request", name);
+    state.sftp_service.get_recording(&name).await
+}
+
+pub async fn get_sftp_usage(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    info!("Get SFTP disk usage request");
+    state.disk_usage.get_usage().await
+}
+
+pub async fn get_sftp_schedule(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    info!("Get SFTP schedule request");
+    state.sftp_service.get_schedule().await
+}
+
+pub async fn set_sftp_schedule(
+    State(state): State<AppState>,
+    Json(request): Json<ScheduleRequest>,
+) -> impl IntoResponse {
+    info!("Set SFTP schedule request");
+    state.sftp_service.set_schedule(request).await
+}
+
+pub async fn get_sftp_path_rules(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    info!("Get SFTP path rules request");
+    state.sftp_service.get_path_rules().await
+}
+
+pub async fn set_sftp_path_rules(
+    State(state): State<AppState>,
+    Json(request): Json<PathRulesBody>,
+) -> impl IntoResponse {
+    info!("Set SFTP path rules request");
+    state.sftp_service.set_path_rules(request).await
+}
+
+pub async fn start_sftp_drain(
+    State(state): State<AppState>,
+    request: Option<Json<DrainRequest>>,
+) -> impl IntoResponse {
+    info!("Start SFTP drain request");
+    let request = request.map(|Json(r)| r).unwrap_or_default();
+    state.sftp_service.start_drain(request).await
+}
+
+pub async fn get_sftp_drain(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    info!("Get SFTP drain request");
+    state.sftp_service.get_drain().await
+}
+
+pub async fn cancel_sftp_drain(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    info!("Cancel SFTP drain request");
+    state.sftp_service.cancel_drain().await
 }
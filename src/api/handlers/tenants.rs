@@ -0,0 +1,174 @@
+use crate::api::scoped_keys::{ApiScope, out_of_scope};
+use crate::models::accounts::CreateUserRequest;
+use crate::models::files::{FilePathQuery, FileUploadQuery};
+use crate::services::tenants::unknown_tenant;
+use crate::state::AppState;
+use axum::{
+    Extension, Json,
+    body::Body,
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+};
+use tracing::info;
+
+type Scope = Option<Extension<ApiScope>>;
+
+// Refuses requests whose API key is scoped to another tenant or instance
+fn check_scope(scope: &Scope, tenant: &str) -> Option<Response> {
+    match scope {
+        Some(Extension(scope))
+            if !ApiScope::allows_tenant(Some(scope), tenant) =>
+        {
+            Some(out_of_scope::<()>(scope).into_response())
+        }
+        _ => None,
+    }
+}
+
+pub async fn list_tenants(
+    State(state): State<AppState>,
+    scope: Scope,
+) -> impl IntoResponse {
+    info!("List tenants request");
+    let mut response = state.tenants.list().await;
+    if let Some(tenants) = &mut response.sftp {
+        let scope = scope.as_ref().map(|Extension(scope)| scope);
+        tenants.retain(|tenant| ApiScope::allows_tenant(scope, &tenant.name));
+    }
+    response
+}
+
+pub async fn get_tenant(
+    State(state): State<AppState>,
+    Path(tenant): Path<String>,
+    scope: Scope,
+) -> Response {
+    if let Some(response) = check_scope(&scope, &tenant) {
+        return response;
+    }
+    info!("Get tenant {} request", tenant);
+    state.tenants.status(&tenant).await.into_response()
+}
+
+pub async fn list_tenant_users(
+    State(state): State<AppState>,
+    Path(tenant): Path<String>,
+    scope: Scope,
+) -> Response {
+    if let Some(response) = check_scope(&scope, &tenant) {
+        return response;
+    }
+    if !state.tenants.exists(&tenant) {
+        return unknown_tenant::<()>(&tenant).into_response();
+    }
+    info!("List users of tenant {} request", tenant);
+    state.accounts.list_tenant_users(&tenant).await.into_response()
+}
+
+pub async fn create_tenant_user(
+    State(state): State<AppState>,
+    Path(tenant): Path<String>,
+    scope: Scope,
+    Json(request): Json<CreateUserRequest>,
+) -> Response {
+    if let Some(response) = check_scope(&scope, &tenant) {
+        return response;
+    }
+    if !state.tenants.exists(&tenant) {
+        return unknown_tenant::<()>(&tenant).into_response();
+    }
+    info!("Create user of tenant {} request", tenant);
+    state
+        .accounts
+        .create_tenant_user(request, Some(&tenant))
+        .await
+        .into_response()
+}
+
+pub async fn delete_tenant_user(
+    State(state): State<AppState>,
+    Path((tenant, username)): Path<(String, String)>,
+    scope: Scope,
+) -> Response {
+    if let Some(response) = check_scope(&scope, &tenant) {
+        return response;
+    }
+    if !state.tenants.exists(&tenant) {
+        return unknown_tenant::<()>(&tenant).into_response();
+    }
+    info!("Delete user of tenant {} request", tenant);
+    state.accounts.delete_tenant_user(&tenant, &username).await.into_response()
+}
+
+pub async fn list_tenant_directory(
+    State(state): State<AppState>,
+    Path(tenant): Path<String>,
+    scope: Scope,
+    Query(query): Query<FilePathQuery>,
+) -> Response {
+    if let Some(response) = check_scope(&scope, &tenant) {
+        return response;
+    }
+    match state.tenants.files(&tenant) {
+        Some(files) => files.list(&query.path).await.into_response(),
+        None => unknown_tenant::<()>(&tenant).into_response(),
+    }
+}
+
+pub async fn download_tenant_file(
+    State(state): State<AppState>,
+    Path(tenant): Path<String>,
+    scope: Scope,
+    Query(query): Query<FilePathQuery>,
+) -> Response {
+    if let Some(response) = check_scope(&scope, &tenant) {
+        return response;
+    }
+    info!("Download request for {} of tenant {}", query.path, tenant);
+    match state.tenants.files(&tenant) {
+        Some(files) => files.download(&query.path).await,
+        None => unknown_tenant::<()>(&tenant).into_response(),
+    }
+}
+
+pub async fn upload_tenant_file(
+    State(state): State<AppState>,
+    Path(tenant): Path<String>,
+    scope: Scope,
+    Query(query): Query<FileUploadQuery>,
+    headers: HeaderMap,
+    body: Body,
+) -> Response {
+    if let Some(response) = check_scope(&scope, &tenant) {
+        return response;
+    }
+    info!("Upload request for {} of tenant {}", query.path, tenant);
+    let body = match state.uploads.admit(&headers, body) {
+        Ok(body) => body,
+        Err(aborted) => return aborted.into_response(),
+    };
+    match state.tenants.files(&tenant) {
+        Some(files) => files
+            .upload(&query.path, query.overwrite, body)
+            .await
+            .into_response(),
+        None => unknown_tenant::<()>(&tenant).into_response(),
+    }
+}
+
+pub async fn delete_tenant_file(
+    State(state): State<AppState>,
+    Path(tenant): Path<String>,
+    scope: Scope,
+    Query(query): Query<FilePathQuery>,
+) -> Response {
+    if let Some(response) = check_scope(&scope, &tenant) {
+        return response;
+    }
+    info!("Delete request for {} of tenant {}", query.path, tenant);
+    match state.tenants.files(&tenant) {
+        Some(files) => files.delete(&query.path).await.into_response(),
+        None => unknown_tenant::<()>(&tenant).into_response(),
+    }
+}
@@ -0,0 +1,45 @@
+use crate::state::AppState;
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::HeaderMap,
+    response::IntoResponse,
+};
+use tracing::info;
+
+pub async fn tus_options(State(state): State<AppState>) -> impl IntoResponse {
+    state.tus.options()
+}
+
+pub async fn tus_create(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    info!("TUS upload creation request");
+    state.tus.create(&headers).await
+}
+
+pub async fn tus_head(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    state.tus.head(&id).await
+}
+
+pub async fn tus_patch(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    body: Body,
+) -> impl IntoResponse {
+    state.tus.patch(&id, &headers, body).await
+}
+
+pub async fn tus_delete(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    info!("TUS upload termination request for {}", id);
+    state.tus.delete(&id, &headers).await
+}
@@ -0,0 +1,42 @@
+use crate::state::AppState;
+use axum::{
+    extract::State,
+    http::{StatusCode, header},
+    response::{Html, IntoResponse, Response},
+};
+
+// Assets of the file manager, compiled into the binary
+const INDEX_HTML: &str = include_str!("../../../assets/ui/index.html");
+const APP_JS: &str = include_str!("../../../assets/ui/app.js");
+const STYLE_CSS: &str = include_str!("../../../assets/ui/style.css");
+
+pub async fn index(State(state): State<AppState>) -> Response {
+    if !enabled(&state) {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    Html(INDEX_HTML).into_response()
+}
+
+pub async fn script(State(state): State<AppState>) -> Response {
+    asset(&state, "text/javascript; charset=utf-8", APP_JS)
+}
+
+pub async fn stylesheet(State(state): State<AppState>) -> Response {
+    asset(&state, "text/css; charset=utf-8", STYLE_CSS)
+}
+
+fn asset(
+    state: &AppState,
+    content_type: &'static str,
+    body: &'static str,
+) -> Response {
+    if !enabled(state) {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    ([(header::CONTENT_TYPE, content_type)], body).into_response()
+}
+
+// Read per request so the UI can be switched off by a reload
+fn enabled(state: &AppState) -> bool {
+    state.settings.borrow().ui.enabled
+}
@@ -1,2 +1,6 @@
+pub mod access_log;
+pub mod geoip;
 pub mod handlers;
 pub mod routes;
+pub mod scoped_keys;
+pub mod tls;
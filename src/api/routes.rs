@@ -1,17 +1,220 @@
-use crate::api::handlers::{self, health::health_check};
+use crate::api::handlers::{
+    self,
+    health::{health_check, readiness_check},
+};
 use crate::state::AppState;
 use axum::{
     Router,
-    routing::{get, post},
+    routing::{delete, get, head, options, post, put},
 };
 
 pub fn configure_health_routes() -> Router<AppState> {
-    Router::new().route("/health", get(health_check))
+    Router::new()
+        .route("/health", get(health_check))
+        .route("/health/ready", get(readiness_check))
+        .route("/metrics", get(handlers::metrics::get_metrics))
+}
+
+pub fn configure_config_routes() -> Router<AppState> {
+    Router::new()
+        .route("/config/validate", post(handlers::config::validate_config))
+        .route("/config/reload", post(handlers::config::reload_config))
+}
+
+pub fn configure_admin_routes() -> Router<AppState> {
+    Router::new()
+        .route("/admin/export", get(handlers::admin::export_state))
+        .route("/admin/import", post(handlers::admin::import_state))
+        .route("/admin/audit", get(handlers::admin::get_audit_log))
+        .route("/admin/transfers", get(handlers::admin::get_transfer_log))
+        .route("/admin/journal", get(handlers::admin::get_journal))
+        .route("/admin/mirror", get(handlers::admin::get_mirror_queue))
+        .route("/admin/mirror/retry", post(handlers::admin::retry_mirror_jobs))
+        .route(
+            "/admin/backup",
+            get(handlers::admin::get_backup_status)
+                .post(handlers::admin::start_backup),
+        )
+        .route(
+            "/admin/users",
+            get(handlers::admin::list_users).post(handlers::admin::create_user),
+        )
+        .route("/admin/users/{username}", delete(handlers::admin::delete_user))
+        .route(
+            "/admin/users/{username}/export",
+            get(handlers::admin::export_user_data),
+        )
+        .route(
+            "/admin/users/{username}/erase",
+            post(handlers::admin::erase_user),
+        )
+        .route(
+            "/admin/users/{username}/access-windows",
+            put(handlers::admin::set_access_windows),
+        )
+        .route(
+            "/admin/users/{username}/password",
+            put(handlers::admin::set_password),
+        )
+        .route(
+            "/admin/users/{username}/keys",
+            get(handlers::admin::list_keys).post(handlers::admin::add_key),
+        )
+        .route("/admin/keys/{id}", delete(handlers::admin::remove_key))
+        .route(
+            "/admin/shares",
+            get(handlers::admin::list_shares)
+                .post(handlers::admin::create_share),
+        )
+        .route(
+            "/admin/shares/{token}",
+            get(handlers::admin::get_share)
+                .delete(handlers::admin::delete_share),
+        )
+}
+
+pub fn configure_cluster_routes() -> Router<AppState> {
+    Router::new()
+        .route("/cluster/replicas", get(handlers::cluster::list_replicas))
+}
+
+pub fn configure_instance_routes() -> Router<AppState> {
+    Router::new()
+        .route("/instances", get(handlers::instances::list_instances))
+        .route(
+            "/instances/{name}",
+            get(handlers::instances::get_instance_status),
+        )
+        .route(
+            "/instances/{name}/toggle",
+            post(handlers::instances::toggle_instance),
+        )
+        .route(
+            "/instances/{name}/credentials",
+            get(handlers::instances::get_instance_credentials),
+        )
+        .route(
+            "/instances/{name}/files",
+            get(handlers::instances::list_instance_directory)
+                .put(handlers::instances::upload_instance_file)
+                .delete(handlers::instances::delete_instance_file),
+        )
+        .route(
+            "/instances/{name}/files/download",
+            get(handlers::instances::download_instance_file),
+        )
+}
+
+pub fn configure_tenant_routes() -> Router<AppState> {
+    Router::new()
+        .route("/tenants", get(handlers::tenants::list_tenants))
+        .route("/tenants/{tenant}", get(handlers::tenants::get_tenant))
+        .route(
+            "/tenants/{tenant}/users",
+            get(handlers::tenants::list_tenant_users)
+                .post(handlers::tenants::create_tenant_user),
+        )
+        .route(
+            "/tenants/{tenant}/users/{username}",
+            delete(handlers::tenants::delete_tenant_user),
+        )
+        .route(
+            "/tenants/{tenant}/files",
+            get(handlers::tenants::list_tenant_directory)
+                .put(handlers::tenants::upload_tenant_file)
+                .delete(handlers::tenants::delete_tenant_file),
+        )
+        .route(
+            "/tenants/{tenant}/files/download",
+            get(handlers::tenants::download_tenant_file),
+        )
+}
+
+pub fn configure_files_routes() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/files",
+            get(handlers::files::list_directory)
+                .put(handlers::files::upload_file)
+                .delete(handlers::files::delete_file),
+        )
+        .route("/files/download", get(handlers::files::download_file))
+        .route(
+            "/files/tus",
+            options(handlers::tus::tus_options).post(handlers::tus::tus_create),
+        )
+        .route(
+            "/files/tus/{id}",
+            head(handlers::tus::tus_head)
+                .patch(handlers::tus::tus_patch)
+                .delete(handlers::tus::tus_delete),
+        )
+        .route("/files/checksum", get(handlers::files::get_file_checksum))
+        .route("/files/trash", get(handlers::files::list_trash))
+        .route("/files/integrity", get(handlers::files::get_integrity_report))
+        .route("/files/restore", post(handlers::files::restore_file))
+        .route("/files/search", get(handlers::files::search_files))
+        .route(
+            "/files/tags",
+            get(handlers::files::get_file_tags)
+                .put(handlers::files::set_file_tags),
+        )
+        .route("/files/tags/search", post(handlers::files::search_file_tags))
+        .route("/files/extract", post(handlers::files::extract_archive))
+        .route("/files/extract/{id}", get(handlers::files::get_extract_job))
+}
+
+pub fn configure_ui_routes() -> Router<AppState> {
+    Router::new()
+        .route("/ui", get(handlers::ui::index))
+        .route("/ui/", get(handlers::ui::index))
+        .route("/ui/app.js", get(handlers::ui::script))
+        .route("/ui/style.css", get(handlers::ui::stylesheet))
 }
 
 pub fn configure_sftp_routes() -> Router<AppState> {
     Router::new()
         .route("/sftp/toggle", post(handlers::sftp::toggle_sftp))
+        .route("/sftp/enable", post(handlers::sftp::enable_sftp))
+        .route("/sftp/disable", post(handlers::sftp::disable_sftp))
         .route("/sftp/status", get(handlers::sftp::get_sftp_status))
         .route("/sftp/credentials", get(handlers::sftp::get_sftp_credentials))
+        .route(
+            "/sftp/credentials/rotate",
+            post(handlers::sftp::rotate_sftp_credentials),
+        )
+        .route(
+            "/sftp/credentials/access-log",
+            get(handlers::sftp::get_sftp_credentials_access_log),
+        )
+        .route(
+            "/sftp/credentials/history",
+            get(handlers::sftp::get_sftp_credential_history),
+        )
+        .route("/sftp/stats", get(handlers::sftp::get_sftp_stats))
+        .route("/sftp/sessions", get(handlers::sftp::get_sftp_sessions))
+        .route("/sftp/sessions/{id}", delete(handlers::sftp::kick_sftp_session))
+        .route("/sftp/resume", get(handlers::sftp::get_sftp_resume_point))
+        .route("/sftp/recordings", get(handlers::sftp::list_sftp_recordings))
+        .route(
+            "/sftp/recordings/{name}",
+            get(handlers::sftp::get_sftp_recording),
+        )
+        .route("/sftp/usage", get(handlers::sftp::get_sftp_usage))
+        .route(
+            "/sftp/schedule",
+            get(handlers::sftp::get_sftp_schedule)
+                .post(handlers::sftp::set_sftp_schedule),
+        )
+        .route(
+            "/sftp/path-rules",
+            get(handlers::sftp::get_sftp_path_rules)
+                .put(handlers::sftp::set_sftp_path_rules),
+        )
+        .route(
+            "/sftp/drain",
+            get(handlers::sftp::get_sftp_drain)
+                .post(handlers::sftp::start_sftp_drain)
+                .delete(handlers::sftp::cancel_sftp_drain),
+        )
 }
@@ -0,0 +1,281 @@
+use crate::api::tls::ApiClient;
+use crate::config::settings::{ApiRole, Settings};
+use crate::responses::sftp::SftpApiResponse;
+use crate::s3::sigv4::constant_time_eq;
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+use std::fmt;
+use tokio::sync::watch;
+use tracing::{Span, warn};
+
+// What a request made with a scoped API key may manage: one tenant, its
+// users and files, or one SFTP instance, its credentials and files. The
+// handlers of /tenants and /instances check it against the name in their
+// path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApiScope {
+    Tenant(String),
+    Instance(String),
+}
+
+impl ApiScope {
+    // Whether a request with `scope`, None for admins' unscoped ones, may
+    // use the tenant `name`
+    pub fn allows_tenant(scope: Option<&ApiScope>, name: &str) -> bool {
+        match scope {
+            None => true,
+            Some(ApiScope::Tenant(tenant)) => tenant == name,
+            Some(ApiScope::Instance(_)) => false,
+        }
+    }
+
+    pub fn allows_instance(scope: Option<&ApiScope>, name: &str) -> bool {
+        match scope {
+            None => true,
+            Some(ApiScope::Instance(instance)) => instance == name,
+            Some(ApiScope::Tenant(_)) => false,
+        }
+    }
+
+    // The routes the key may reach at all; the handlers narrow them down
+    // to its tenant or instance
+    fn covers(&self, path: &str) -> bool {
+        let prefix = match self {
+            ApiScope::Tenant(_) => "/tenants",
+            ApiScope::Instance(_) => "/instances",
+        };
+        path.strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+}
+
+impl fmt::Display for ApiScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiScope::Tenant(name) => write!(f, "tenant '{}'", name),
+            ApiScope::Instance(name) => write!(f, "instance '{}'", name),
+        }
+    }
+}
+
+// Response to a scoped request for another tenant or instance
+pub fn out_of_scope<T: Serialize>(scope: &ApiScope) -> SftpApiResponse<T> {
+    SftpApiResponse::error(
+        StatusCode::FORBIDDEN,
+        format!("The API key only grants access to {}", scope),
+    )
+}
+
+// Middleware for API keys: a request with a tenant's or an instance's
+// bearer key may only use /tenants/... or /instances/..., and carries its
+// scope for the handlers there. Once any API keys are configured, other
+// requests need an admin key, or a client certificate whose role is
+// checked next; health checks and the web UI's static files, which the
+// browser loads without the key, are open to all.
+pub async fn scope_api_keys(
+    State(settings): State<watch::Receiver<Settings>>,
+    ConnectInfo(client): ConnectInfo<ApiClient>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if is_open(request.uri().path()) {
+        return next.run(request).await;
+    }
+    let (locked, holder) = {
+        let settings = settings.borrow();
+        let locked = settings.has_scoped_api_keys()
+            || !settings.server.admin_api_keys.is_empty();
+        let holder = bearer_token(request.headers())
+            .map(|key| holder_of_key(&settings, key));
+        (locked, holder)
+    };
+    if !locked {
+        return next.run(request).await;
+    }
+
+    let scope = match holder {
+        None if client.certificate.is_some() => {
+            return next.run(request).await;
+        }
+        None => {
+            return reject(
+                &client,
+                StatusCode::UNAUTHORIZED,
+                "An API key or client certificate is required",
+            );
+        }
+        Some(None) => {
+            return reject(
+                &client,
+                StatusCode::UNAUTHORIZED,
+                "Unknown API key",
+            );
+        }
+        Some(Some(KeyHolder::Admin)) => {
+            request.extensions_mut().insert(ApiRole::Admin);
+            request.extensions_mut().insert(KeyHolder::Admin);
+            return next.run(request).await;
+        }
+        Some(Some(KeyHolder::Scoped(scope))) => scope,
+    };
+    if !scope.covers(request.uri().path()) {
+        return reject(
+            &client,
+            StatusCode::FORBIDDEN,
+            &format!("The API key only grants access to {}", scope),
+        );
+    }
+    match &scope {
+        ApiScope::Tenant(name) => Span::current().record("tenant", name),
+        ApiScope::Instance(name) => Span::current().record("instance", name),
+    };
+    request.extensions_mut().insert(KeyHolder::Scoped(scope.clone()));
+    request.extensions_mut().insert(scope);
+    next.run(request).await
+}
+
+// Paths served without a key; the UI then sends its key with every call
+fn is_open(path: &str) -> bool {
+    path.starts_with("/health") || path == "/ui" || path.starts_with("/ui/")
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+}
+
+// Who an API key belongs to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyHolder {
+    Admin,
+    Scoped(ApiScope),
+}
+
+impl KeyHolder {
+    // "admin", "tenant:<name>" or "instance:<name>", as the audit log
+    // records it
+    pub fn label(&self) -> String {
+        match self {
+            KeyHolder::Admin => "admin".to_string(),
+            KeyHolder::Scoped(ApiScope::Tenant(name)) => {
+                format!("tenant:{}", name)
+            }
+            KeyHolder::Scoped(ApiScope::Instance(name)) => {
+                format!("instance:{}", name)
+            }
+        }
+    }
+}
+
+// Admin, tenant or instance holding `key`, comparing every key in
+// constant time
+pub fn holder_of_key(settings: &Settings, key: &str) -> Option<KeyHolder> {
+    let admins =
+        settings.server.admin_api_keys.iter().map(|k| (k, KeyHolder::Admin));
+    let tenants = settings.tenants.iter().flat_map(|tenant| {
+        let scope = ApiScope::Tenant(tenant.name.clone());
+        tenant
+            .api_keys
+            .iter()
+            .map(move |k| (k, KeyHolder::Scoped(scope.clone())))
+    });
+    let instances = settings.instances.iter().flat_map(|instance| {
+        let scope = ApiScope::Instance(instance.name.clone());
+        instance
+            .api_keys
+            .iter()
+            .map(move |k| (k, KeyHolder::Scoped(scope.clone())))
+    });
+    let mut found = None;
+    for (candidate, holder) in admins.chain(tenants).chain(instances) {
+        if constant_time_eq(candidate.expose(), key) {
+            found = Some(holder);
+        }
+    }
+    found
+}
+
+fn reject(client: &ApiClient, status: StatusCode, message: &str) -> Response {
+    warn!("Rejected API request from {}: {}", client.addr, message);
+    SftpApiResponse::<()>::error(status, message).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::settings::{InstanceSettings, TenantSettings};
+
+    #[test]
+    fn test_keys_are_confined_to_their_tenant_or_instance() {
+        let mut settings = Settings::default();
+        settings.tenants.push(TenantSettings {
+            name: "acme".to_string(),
+            root_dir: "/srv/tenants/acme".to_string(),
+            quota_bytes: 0,
+            api_keys: vec!["acme-0123456789ab".to_string().into()],
+            instance: None,
+        });
+        settings.instances.push(InstanceSettings {
+            name: "partner".to_string(),
+            port: 2223,
+            bind_addrs: "0.0.0.0".to_string(),
+            external_host: None,
+            external_port: None,
+            root_dir: "/srv/partner".to_string(),
+            api_keys: vec!["partner-0123456789".to_string().into()],
+        });
+        let acme = ApiScope::Tenant("acme".to_string());
+        let partner = ApiScope::Instance("partner".to_string());
+        settings.server.admin_api_keys =
+            vec!["admin-0123456789ab".to_string().into()];
+        assert_eq!(
+            holder_of_key(&settings, "acme-0123456789ab"),
+            Some(KeyHolder::Scoped(acme.clone()))
+        );
+        assert_eq!(
+            holder_of_key(&settings, "partner-0123456789"),
+            Some(KeyHolder::Scoped(partner.clone()))
+        );
+        assert_eq!(
+            holder_of_key(&settings, "admin-0123456789ab"),
+            Some(KeyHolder::Admin)
+        );
+        assert_eq!(holder_of_key(&settings, "acme-0123456789ac"), None);
+
+        assert!(acme.covers("/tenants"));
+        assert!(acme.covers("/tenants/acme/files"));
+        assert!(!acme.covers("/tenantsx"));
+        assert!(!acme.covers("/admin/users"));
+        assert!(!acme.covers("/instances/partner"));
+        assert!(partner.covers("/instances/partner/files"));
+        assert!(!partner.covers("/sftp/credentials"));
+
+        assert!(ApiScope::allows_tenant(Some(&acme), "acme"));
+        assert!(!ApiScope::allows_tenant(Some(&acme), "acmecorp"));
+        assert!(!ApiScope::allows_tenant(Some(&partner), "acme"));
+        assert!(ApiScope::allows_tenant(None, "acme"));
+        assert!(ApiScope::allows_instance(Some(&partner), "partner"));
+        assert!(!ApiScope::allows_instance(Some(&partner), "default"));
+
+        assert_eq!(KeyHolder::Admin.label(), "admin");
+        assert_eq!(KeyHolder::Scoped(acme).label(), "tenant:acme");
+        assert_eq!(KeyHolder::Scoped(partner).label(), "instance:partner");
+    }
+
+    #[test]
+    fn test_only_health_checks_and_ui_files_skip_the_key() {
+        assert!(is_open("/health/ready"));
+        assert!(is_open("/ui"));
+        assert!(is_open("/ui/app.js"));
+        assert!(!is_open("/uix"));
+        assert!(!is_open("/files"));
+        assert!(!is_open("/sftp/credentials"));
+    }
+}
@@ -0,0 +1,302 @@
+use crate::api::scoped_keys::ApiScope;
+use crate::config::settings::{ApiRole, ServerTlsSettings, Settings};
+use crate::error::Error;
+use crate::ftps::load_certificate;
+use crate::responses::sftp::SftpApiResponse;
+use axum::extract::connect_info::Connected;
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::serve::{IncomingStream, Listener};
+use rustls_pki_types::CertificateDer;
+use rustls_pki_types::pem::PemObject;
+use sha2::{Digest, Sha256};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch};
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{RootCertStore, ServerConfig, crypto};
+use tokio_rustls::server::TlsStream;
+use tracing::{debug, warn};
+
+// Clients that have not finished the handshake by then are dropped
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Build the acceptor for the management API. Client certificates issued
+// by client_ca_file are verified when offered; whether one is required is
+// decided per request, so health checks work without one.
+pub fn api_tls_acceptor(
+    settings: &ServerTlsSettings,
+) -> crate::error::Result<TlsAcceptor> {
+    let (certificates, key) = load_certificate(
+        &settings.certificate_file,
+        &settings.private_key_file,
+    )?;
+    let provider = Arc::new(crypto::ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+
+    let config = if settings.client_ca_file.is_empty() {
+        builder.with_no_client_auth().with_single_cert(certificates, key)?
+    } else {
+        let mut roots = RootCertStore::empty();
+        let invalid = |e: &dyn std::fmt::Display| Error::Certificate {
+            file: settings.client_ca_file.clone(),
+            message: e.to_string(),
+        };
+        for ca in CertificateDer::pem_file_iter(&settings.client_ca_file)
+            .map_err(|e| invalid(&e))?
+        {
+            roots.add(ca.map_err(|e| invalid(&e))?)?;
+        }
+        let verifier =
+            WebPkiClientVerifier::builder_with_provider(roots.into(), provider)
+                .allow_unauthenticated()
+                .build()
+                .map_err(|e| invalid(&e))?;
+        builder
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(certificates, key)?
+    };
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+// Accepts TLS connections for `axum::serve`. Handshakes run in their own
+// tasks so a slow client does not hold up the others.
+pub struct TlsListener {
+    connections: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+    local_addr: SocketAddr,
+}
+
+impl TlsListener {
+    pub fn new(
+        listener: TcpListener,
+        acceptor: TlsAcceptor,
+    ) -> io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let (sender, connections) = mpsc::channel(64);
+        tokio::spawn(async move {
+            loop {
+                let (stream, peer) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        // Usually out of file descriptors; let some close
+                        warn!("Failed to accept API connection: {}", e);
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                        continue;
+                    }
+                };
+                let acceptor = acceptor.clone();
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    let handshake = acceptor.accept(stream);
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake)
+                        .await
+                    {
+                        Ok(Ok(stream)) => {
+                            let _ = sender.send((stream, peer)).await;
+                        }
+                        Ok(Err(e)) => {
+                            debug!("TLS handshake with {} failed: {}", peer, e)
+                        }
+                        Err(_) => {
+                            debug!("TLS handshake with {} timed out", peer)
+                        }
+                    }
+                });
+            }
+        });
+        Ok(Self { connections, local_addr })
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.connections.recv().await {
+            Some(connection) => connection,
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+// Peer of a management API connection
+#[derive(Debug, Clone)]
+pub struct ApiClient {
+    pub addr: SocketAddr,
+    // SHA-256 fingerprint of the verified client certificate, if any
+    pub certificate: Option<String>,
+}
+
+impl Connected<IncomingStream<'_, TcpListener>> for ApiClient {
+    fn connect_info(stream: IncomingStream<'_, TcpListener>) -> Self {
+        Self { addr: *stream.remote_addr(), certificate: None }
+    }
+}
+
+impl ApiClient {
+    // Client of a TLS connection, with its certificate if it sent one
+    pub fn tls(stream: &TlsStream<TcpStream>, addr: SocketAddr) -> Self {
+        let (_, connection) = stream.get_ref();
+        let certificate = connection
+            .peer_certificates()
+            .and_then(|chain| chain.first())
+            .map(|certificate| hex::encode(Sha256::digest(certificate)));
+        Self { addr, certificate }
+    }
+}
+
+impl Connected<IncomingStream<'_, TlsListener>> for ApiClient {
+    fn connect_info(stream: IncomingStream<'_, TlsListener>) -> Self {
+        Self::tls(stream.io(), *stream.remote_addr())
+    }
+}
+
+// Role of a client certificate under the current settings
+pub fn certificate_role(
+    settings: &ServerTlsSettings,
+    fingerprint: &str,
+) -> Option<ApiRole> {
+    settings
+        .client_roles
+        .iter()
+        .find(|mapping| {
+            mapping.normalized_fingerprint().as_deref() == Some(fingerprint)
+        })
+        .map(|mapping| mapping.role)
+        .or(settings.default_role)
+}
+
+// Middleware for client certificate authentication: requires a certificate
+// with a role allowing the request. Health checks and requests made with
+// an admin key or scoped by a tenant's or an instance's API key are let
+// through.
+pub async fn require_client_role(
+    State(settings): State<watch::Receiver<Settings>>,
+    ConnectInfo(client): ConnectInfo<ApiClient>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if request.uri().path().starts_with("/health")
+        || request.extensions().get::<ApiScope>().is_some()
+        || request.extensions().get::<ApiRole>().is_some()
+    {
+        return next.run(request).await;
+    }
+
+    let Some(fingerprint) = &client.certificate else {
+        return reject(
+            &client,
+            StatusCode::UNAUTHORIZED,
+            "A client certificate is required",
+        );
+    };
+    let role = certificate_role(&settings.borrow().server.tls, fingerprint);
+    let allowed = match role {
+        Some(ApiRole::Admin) => true,
+        Some(ApiRole::ReadOnly) => {
+            read_only_allows(request.method(), request.uri().path())
+        }
+        None => false,
+    };
+    match role {
+        Some(role) if allowed => {
+            request.extensions_mut().insert(role);
+            next.run(request).await
+        }
+        Some(_) => reject(
+            &client,
+            StatusCode::FORBIDDEN,
+            "The client certificate's role does not allow this",
+        ),
+        None => reject(
+            &client,
+            StatusCode::FORBIDDEN,
+            "The client certificate has no role",
+        ),
+    }
+}
+
+// Read-only clients may look but not change anything, nor fetch
+// credentials, state exports, file contents, recordings or share links
+fn read_only_allows(method: &Method, path: &str) -> bool {
+    let secret = path.ends_with("/credentials")
+        || path.ends_with("/export")
+        || path.ends_with("/files/download")
+        || path.starts_with("/admin/shares")
+        || path.starts_with("/sftp/recordings/");
+    matches!(*method, Method::GET | Method::HEAD) && !secret
+}
+
+fn reject(client: &ApiClient, status: StatusCode, message: &str) -> Response {
+    warn!(
+        "Rejected API request from {} (certificate {}): {}",
+        client.addr,
+        client.certificate.as_deref().unwrap_or("none"),
+        message
+    );
+    SftpApiResponse::<()>::error(status, message).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::settings::ClientRoleSettings;
+
+    #[test]
+    fn test_certificates_map_to_roles() {
+        let fingerprint = "ab".repeat(32);
+        let mut settings = ServerTlsSettings {
+            client_roles: vec![ClientRoleSettings {
+                fingerprint: vec!["AB"; 32].join(":"),
+                role: ApiRole::Admin,
+            }],
+            ..Default::default()
+        };
+        assert_eq!(
+            certificate_role(&settings, &fingerprint),
+            Some(ApiRole::Admin)
+        );
+        assert_eq!(certificate_role(&settings, &"cd".repeat(32)), None);
+
+        settings.default_role = Some(ApiRole::ReadOnly);
+        assert_eq!(
+            certificate_role(&settings, &"cd".repeat(32)),
+            Some(ApiRole::ReadOnly)
+        );
+    }
+
+    #[test]
+    fn test_read_only_clients_get_no_secrets() {
+        for path in ["/sftp/status", "/files", "/admin/audit", "/instances"] {
+            assert!(read_only_allows(&Method::GET, path), "{}", path);
+        }
+        assert!(!read_only_allows(&Method::POST, "/sftp/toggle"));
+        for path in [
+            "/sftp/credentials",
+            "/instances/partner/credentials",
+            "/admin/export",
+            "/admin/users/alice/export",
+            "/files/download",
+            "/tenants/acme/files/download",
+            "/instances/partner/files/download",
+            "/admin/shares",
+            "/admin/shares/abc",
+            "/sftp/recordings/session-1.cast",
+        ] {
+            assert!(!read_only_allows(&Method::GET, path), "{}", path);
+            assert!(!read_only_allows(&Method::HEAD, path), "{}", path);
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use crate::config::settings::{ConfigFormat, SettingsSource};
+use crate::utils::logger::LogFormat;
+use clap::{Parser, Subcommand};
+
+// Command-line interface. Flags override values from the configuration file.
+#[derive(Debug, Parser)]
+#[command(version, about = "Manages a temporary SFTP server over HTTP")]
+pub struct Cli {
+    /// Configuration file to load instead of config/default
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<String>,
+
+    /// Format of the configuration files, when the extension does not tell
+    #[arg(long, value_enum)]
+    pub config_format: Option<ConfigFormat>,
+
+    /// Environment whose file is layered over the configuration, e.g.
+    /// production for config/production.toml
+    #[arg(long = "env", env = "APP_ENV", value_name = "NAME")]
+    pub environment: Option<String>,
+
+    /// HTTP API port (server.port)
+    #[arg(long)]
+    pub port: Option<u16>,
+
+    /// SFTP listener port (sftp.port)
+    #[arg(long)]
+    pub sftp_port: Option<u16>,
+
+    /// Directory served over SFTP (sftp.root_dir)
+    #[arg(long, value_name = "DIR")]
+    pub root_dir: Option<String>,
+
+    /// Log output format
+    #[arg(long, value_enum, default_value_t = LogFormat::Compact)]
+    pub log_format: LogFormat,
+
+    /// Check the configuration, print any problems and exit
+    #[arg(long)]
+    pub validate_config: bool,
+
+    /// Query /health/ready of the server configured here and exit with 0
+    /// when it is ready, 1 otherwise; for container health checks
+    #[arg(long)]
+    pub healthcheck: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+// Administrative operations that run instead of the server
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Re-encrypt persisted secrets with the key in SFTP_MANAGER_NEW_SECRET_KEY
+    Rekey,
+}
+
+impl Cli {
+    // Where settings are loaded from, including the overriding flags
+    pub fn settings_source(&self) -> SettingsSource {
+        let mut overrides = Vec::new();
+        if let Some(port) = self.port {
+            overrides.push(("server.port".to_string(), port.to_string()));
+        }
+        if let Some(port) = self.sftp_port {
+            overrides.push(("sftp.port".to_string(), port.to_string()));
+        }
+        if let Some(root_dir) = &self.root_dir {
+            overrides.push(("sftp.root_dir".to_string(), root_dir.clone()));
+        }
+
+        SettingsSource {
+            config_path: self.config.clone(),
+            format: self.config_format,
+            environment: self.environment.clone().filter(|e| !e.is_empty()),
+            overrides,
+        }
+    }
+}
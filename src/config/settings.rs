@@ -1,11 +1,101 @@
-use config::{Config, ConfigError, File};
+use crate::sftp::checksum::ChecksumAlgorithm;
+use crate::sftp::filenames::{
+    Dotfiles, FilenamePolicy, Normalization, Sanitization,
+};
+use crate::sftp::hooks::ConnectionEvent;
+use crate::sftp::{
+    AuthFailureLimits, ClientVersionRules, CompletionRules, DiskLimits,
+    KeepAlive, LandingRules, ListingCache, PathRule, RecordingPolicy,
+    SecretString, TarpitLimits, TransferLimits,
+};
+use clap::ValueEnum;
+use config::{Config, ConfigError, File, FileFormat};
 use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use zeroize::Zeroizing;
 
 // Main application settings
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Settings {
     pub server: ServerSettings,
     pub sftp: SftpSettings,
+    #[serde(default)]
+    pub webhooks: WebhookSettings,
+    #[serde(default)]
+    pub schedule: ScheduleSettings,
+    #[serde(default)]
+    pub database: DatabaseSettings,
+    #[serde(default)]
+    pub redis: RedisSettings,
+    #[serde(default)]
+    pub journal: JournalSettings,
+    #[serde(default)]
+    pub logging: LoggingSettings,
+    #[serde(default)]
+    pub secrets: SecretsSettings,
+    #[serde(default)]
+    pub vault: VaultSettings,
+    #[serde(default)]
+    pub scan: ScanSettings,
+    #[serde(default)]
+    pub retention: RetentionSettings,
+    #[serde(default)]
+    pub watcher: WatcherSettings,
+    #[serde(default)]
+    pub listing_cache: ListingCacheSettings,
+    #[serde(default)]
+    pub pipeline: PipelineSettings,
+    #[serde(default)]
+    pub connection_hooks: ConnectionHookSettings,
+    #[serde(default)]
+    pub trash: TrashSettings,
+    #[serde(default)]
+    pub scratch: ScratchSettings,
+    #[serde(default)]
+    pub completion: CompletionSettings,
+    #[serde(default)]
+    pub landing: LandingSettings,
+    #[serde(default)]
+    pub filenames: FilenameSettings,
+    #[serde(default)]
+    pub recording: RecordingSettings,
+    #[serde(default)]
+    pub search: SearchSettings,
+    #[serde(default)]
+    pub integrity: IntegritySettings,
+    #[serde(default)]
+    pub extract: ExtractSettings,
+    #[serde(default)]
+    pub ftps: FtpsSettings,
+    #[serde(default)]
+    pub ui: UiSettings,
+    #[serde(default)]
+    pub tus: TusSettings,
+    #[serde(default)]
+    pub s3: S3Settings,
+    #[serde(default)]
+    pub grpc: GrpcSettings,
+    #[serde(default)]
+    pub mirror: MirrorSettings,
+    #[serde(default)]
+    pub geoip: GeoIpSettings,
+    #[serde(default)]
+    pub event_stream: EventStreamSettings,
+    #[serde(default)]
+    pub backup: BackupSettings,
+    #[serde(default)]
+    pub port_mapping: PortMappingSettings,
+    #[serde(default)]
+    pub mdns: MdnsSettings,
+    #[serde(default)]
+    pub delivery: DeliverySettings,
+    // SFTP servers managed next to the one configured under [sftp]
+    #[serde(default)]
+    pub instances: Vec<InstanceSettings>,
+    // Customers hosted side by side, each confined to its own directory
+    #[serde(default)]
+    pub tenants: Vec<TenantSettings>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -15,44 +105,2052 @@ pub struct ServerSettings {
 
     #[serde(default = "default_host")]
     pub host: String,
+
+    // Reload the configuration when its file changes
+    #[serde(default)]
+    pub watch_config: bool,
+
+    // Largest request body accepted outside the upload endpoints, which
+    // stream theirs to disk
+    #[serde(default = "default_max_body_bytes")]
+    pub max_body_bytes: usize,
+
+    // Largest file uploaded through the API, 0 for no limit
+    #[serde(default)]
+    pub max_upload_bytes: u64,
+
+    // Uploads fail when the client sends no data for this long, 0 to wait
+    // forever
+    #[serde(default = "default_upload_idle_timeout_secs")]
+    pub upload_idle_timeout_secs: u64,
+
+    // Uploads in progress at once through the API, further ones are
+    // refused; 0 for no limit
+    #[serde(default = "default_max_concurrent_uploads")]
+    pub max_concurrent_uploads: usize,
+
+    // Bearer keys granting full access. Once tenants or instances have
+    // API keys, requests without a scoped key need one of these or a
+    // client certificate.
+    #[serde(default)]
+    pub admin_api_keys: Vec<SecretString>,
+
+    #[serde(default)]
+    pub tls: ServerTlsSettings,
+}
+
+// HTTPS for the management API. With a client CA bundle, requests must
+// present a certificate issued by one of its CAs, and the certificate's
+// role decides what the caller may do. Read when the server starts,
+// except the role mapping.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ServerTlsSettings {
+    #[serde(default)]
+    pub enabled: bool,
+
+    // PEM certificate chain and private key presented to clients
+    #[serde(default)]
+    pub certificate_file: String,
+    #[serde(default)]
+    pub private_key_file: String,
+
+    // PEM bundle of CAs issuing client certificates; empty to not ask
+    // clients for certificates
+    #[serde(default)]
+    pub client_ca_file: String,
+
+    // Role of certificates matching no entry in client_roles; without one
+    // they are rejected
+    #[serde(default)]
+    pub default_role: Option<ApiRole>,
+
+    #[serde(default)]
+    pub client_roles: Vec<ClientRoleSettings>,
+}
+
+// Role granted to one client certificate
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientRoleSettings {
+    // SHA-256 fingerprint of the certificate, in hex with or without colons
+    pub fingerprint: String,
+    pub role: ApiRole,
+}
+
+impl ClientRoleSettings {
+    // The fingerprint as 64 lowercase hex digits, if it is one
+    pub fn normalized_fingerprint(&self) -> Option<String> {
+        let hex: String = self
+            .fingerprint
+            .chars()
+            .filter(|c| *c != ':')
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+        let valid =
+            hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit());
+        valid.then_some(hex)
+    }
+}
+
+// What a management API client may do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiRole {
+    // Every request
+    Admin,
+    // GET and HEAD requests only
+    ReadOnly,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SftpSettings {
+    #[serde(default = "default_sftp_port")]
+    pub port: u16,
+
+    #[serde(default = "default_bind_addrs")]
+    pub bind_addrs: String,
+
+    // Host and port clients are told to connect to, e.g. behind NAT or a
+    // load balancer; the listen address is reported when unset
+    #[serde(default)]
+    pub external_host: Option<String>,
+
+    #[serde(default)]
+    pub external_port: Option<u16>,
+
+    #[serde(default = "default_sftp_root")]
+    pub root_dir: String,
+
+    // Failed logins within the window that count as a spike
+    #[serde(default = "default_auth_failure_threshold")]
+    pub auth_failure_threshold: u32,
+
+    #[serde(default = "default_auth_failure_window_secs")]
+    pub auth_failure_window_secs: u64,
+
+    // Failed logins from one IP, or against one username, within the same
+    // window that raise a dedicated alert (0 disables)
+    #[serde(default = "default_auth_failure_ip_threshold")]
+    pub auth_failure_ip_threshold: u32,
+
+    #[serde(default = "default_auth_failure_user_threshold")]
+    pub auth_failure_user_threshold: u32,
+
+    // Seconds an IP reaching auth_failure_ip_threshold is banned (0
+    // disables banning)
+    #[serde(default)]
+    pub auth_failure_ban_secs: u64,
+
+    // Hold connections from banned IPs open, sending junk very slowly,
+    // instead of closing them
+    #[serde(default)]
+    pub tarpit: bool,
+
+    // Pause between the junk lines sent to a tarpitted client
+    #[serde(default = "default_tarpit_delay_ms")]
+    pub tarpit_delay_ms: u64,
+
+    // Banned clients held at once; further ones are closed right away
+    #[serde(default = "default_tarpit_max_connections")]
+    pub tarpit_max_connections: usize,
+
+    // Digests computed while files are uploaded; empty computes none
+    #[serde(default = "default_checksum_algorithms")]
+    pub checksum_algorithms: Vec<ChecksumAlgorithm>,
+
+    // Leave blocks of zeros in uploads as holes instead of writing them
+    #[serde(default = "default_true")]
+    pub sparse_files: bool,
+
+    // Writes are refused when they would leave less free space than this
+    // on the root filesystem; 0 disables the check
+    #[serde(default)]
+    pub disk_reserve_mb: u64,
+
+    // Free space below which the status reports the disk as low
+    #[serde(default = "default_disk_warning_mb")]
+    pub disk_warning_mb: u64,
+
+    // Wait for the SSH identification string when probing the listener
+    #[serde(default)]
+    pub self_check_handshake: bool,
+
+    #[serde(default = "default_self_check_timeout_ms")]
+    pub self_check_timeout_ms: u64,
+
+    // How long a computed disk usage report is reused
+    #[serde(default = "default_usage_cache_secs")]
+    pub usage_cache_secs: u64,
+
+    // How long a drain waits for open transfers before disconnecting
+    #[serde(default = "default_drain_timeout_secs")]
+    pub drain_timeout_secs: u64,
+
+    // How long stopping the server waits for open transfers to finish
+    #[serde(default = "default_stop_timeout_secs")]
+    pub stop_timeout_secs: u64,
+
+    // How often expiry, schedule and drain deadlines are checked
+    #[serde(default = "default_check_interval_secs")]
+    pub check_interval_secs: u64,
+
+    // Lifetime of credentials generated on enable; 0 means they never expire
+    #[serde(default = "default_expiration_days")]
+    pub expiration_days: u64,
+
+    // Seconds before credential expiry at which a warning is emitted
+    #[serde(default = "default_expiry_warning_secs")]
+    pub expiry_warning_secs: Vec<u64>,
+
+    // Failed starts retried before giving up; 0 retries forever
+    #[serde(default = "default_restart_max_attempts")]
+    pub restart_max_attempts: u32,
+
+    // Delay before the first retry, doubled after every failure
+    #[serde(default = "default_restart_backoff_secs")]
+    pub restart_backoff_secs: u64,
+
+    #[serde(default = "default_restart_backoff_max_secs")]
+    pub restart_backoff_max_secs: u64,
+
+    // File keeping the enabled state and credentials across restarts;
+    // empty disables persistence
+    #[serde(default = "default_state_file")]
+    pub state_file: String,
+
+    // Generated passwords remembered to prevent reuse; 0 keeps no history
+    #[serde(default = "default_password_history")]
+    pub password_history: usize,
+
+    // OpenSSH private key presented as host key; a new key is generated
+    // on every start when empty
+    #[serde(default)]
+    pub host_key_file: String,
+
+    // Bytes a client may send on an SSH channel before waiting for the
+    // server; larger windows keep fast, distant links busy
+    #[serde(default = "default_window_bytes")]
+    pub window_bytes: u32,
+
+    // Largest SSH packet payload
+    #[serde(default = "default_max_packet_bytes")]
+    pub max_packet_bytes: u32,
+
+    // Largest SFTP read served and write accepted, advertised to clients
+    // through the limits@openssh.com extension
+    #[serde(default = "default_max_read_bytes")]
+    pub max_read_bytes: u32,
+
+    #[serde(default = "default_max_write_bytes")]
+    pub max_write_bytes: u32,
+
+    // Buffer of FTPS data connections
+    #[serde(default = "default_transfer_buffer_bytes")]
+    pub transfer_buffer_bytes: usize,
+
+    // Chunks of sequential SFTP downloads read ahead from disk, each the
+    // size of the client's reads; 0 disables read-ahead
+    #[serde(default = "default_read_ahead_chunks")]
+    pub read_ahead_chunks: usize,
+
+    // Seconds of silence after which an SSH keepalive is sent to the
+    // client; 0 sends none
+    #[serde(default = "default_keepalive_interval_secs")]
+    pub keepalive_interval_secs: u64,
+
+    // Unanswered keepalives after which the connection is dropped
+    #[serde(default = "default_keepalive_max_missed")]
+    pub keepalive_max_missed: usize,
+
+    // Memory one SFTP session may hold in open handles, directory listings
+    // and read-ahead buffers; listings and handles beyond it are refused.
+    // 0 is unlimited.
+    #[serde(default = "default_session_memory_mb")]
+    pub session_memory_mb: usize,
+
+    // Files one SSH session may stream at once over its SFTP channels; a
+    // file takes a slot at its first read or write until it reaches the
+    // end or is closed. Past the limit a channel hands over the slot of
+    // its least recently used file, and channels holding none wait for
+    // one. 0 is unlimited.
+    #[serde(default = "default_session_transfers")]
+    pub session_transfers: usize,
+
+    // How SFTP file contents are read and written; io_uring needs a build
+    // with the io-uring feature and falls back to tokio when unavailable
+    #[serde(default)]
+    pub file_io: FileIoBackend,
+
+    // SSH identification strings of the client software let in, e.g.
+    // "SSH-2.0-OpenSSH_*"; `*` matches anything and empty allows all
+    #[serde(default)]
+    pub allowed_clients: Vec<String>,
+
+    // Client software refused even when allowed, e.g. "SSH-2.0-libssh-0.6*"
+    #[serde(default)]
+    pub denied_clients: Vec<String>,
+
+    // What clients may do below which paths, e.g. a write-only
+    // "/incoming/**"; the first matching rule decides and unmatched paths
+    // are read-write. Replaced at runtime through PUT /sftp/path-rules.
+    #[serde(default)]
+    pub path_rules: Vec<PathRule>,
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum FileIoBackend {
+    #[default]
+    Tokio,
+    IoUring,
+}
+
+// Outbound webhook delivery settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookSettings {
+    #[serde(default)]
+    pub endpoints: Vec<WebhookEndpoint>,
+
+    #[serde(default = "default_webhook_max_retries")]
+    pub max_retries: u32,
+
+    #[serde(default = "default_webhook_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+// Where new credentials are sent when SFTP is enabled or they are
+// rotated, so they need not be read back through the API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeliverySettings {
+    // Slack incoming webhook; whoever knows the URL can post, so it is
+    // kept like a secret
+    #[serde(default)]
+    pub slack_webhook_url: Option<SecretString>,
+
+    // File holding the webhook URL; takes precedence over
+    // `slack_webhook_url`
+    #[serde(default)]
+    pub slack_webhook_url_file: Option<String>,
+
+    #[serde(default)]
+    pub email: EmailDeliverySettings,
+
+    #[serde(default = "default_delivery_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+// Credentials mailed over SMTP
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailDeliverySettings {
+    // Recipients; no email is sent when empty
+    #[serde(default)]
+    pub to: Vec<String>,
+
+    #[serde(default = "default_email_from")]
+    pub from: String,
+
+    #[serde(default)]
+    pub smtp_host: String,
+
+    #[serde(default = "default_smtp_port")]
+    pub smtp_port: u16,
+
+    #[serde(default)]
+    pub smtp_tls: SmtpTls,
+
+    // Login for AUTH PLAIN; none is attempted when empty
+    #[serde(default)]
+    pub username: String,
+
+    #[serde(default)]
+    pub password: Option<SecretString>,
+
+    // File holding the password; takes precedence over `password`
+    #[serde(default)]
+    pub password_file: Option<String>,
+}
+
+// How the connection to the SMTP server is secured
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    // Upgraded with STARTTLS, usually on port 587
+    #[default]
+    StartTls,
+    // TLS from the start, usually on port 465
+    Tls,
+    // Plain text, only for relays on the local host or network
+    None,
+}
+
+// Windows during which the SFTP listener may run while enabled
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScheduleSettings {
+    // Five-field cron expressions evaluated in UTC; empty means always
+    #[serde(default)]
+    pub windows: Vec<String>,
+}
+
+// Durable storage for accounts, keys, shares and audit data
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatabaseSettings {
+    // e.g. "sqlite://./data/sftp-manager.db"; empty disables the database
+    #[serde(default = "default_database_url")]
+    pub url: String,
+
+    // Maximum pooled connections for PostgreSQL
+    #[serde(default = "default_database_pool_size")]
+    pub pool_size: usize,
+
+    // File holding the password to put into the URL, so it can be mounted
+    // as a secret instead of written into the configuration
+    #[serde(default)]
+    pub password_file: String,
+}
+
+// Shared SFTP state for replicas behind a load balancer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedisSettings {
+    // e.g. "redis://127.0.0.1:6379"; empty keeps the state local
+    #[serde(default)]
+    pub url: String,
+
+    // Prefix of every key written, so deployments can share a server
+    #[serde(default = "default_redis_key_prefix")]
+    pub key_prefix: String,
+
+    // Name of this replica; defaults to the host name
+    #[serde(default)]
+    pub replica_id: String,
+
+    // File holding the password to put into the URL
+    #[serde(default)]
+    pub password_file: String,
+}
+
+// Optional log file next to stdout, for hosts without journald
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggingSettings {
+    // e.g. "./logs/sftp-manager.log"; empty logs to stdout only
+    #[serde(default)]
+    pub file: String,
+
+    #[serde(default = "default_log_rotation")]
+    pub rotation: LogRotation,
+
+    // Rotated files kept; older ones are deleted
+    #[serde(default = "default_log_max_files")]
+    pub max_files: usize,
+
+    // Size at which the file is rotated when `rotation` is "size"
+    #[serde(default = "default_log_max_file_bytes")]
+    pub max_file_bytes: u64,
+
+    #[serde(default)]
+    pub syslog: SyslogSettings,
+}
+
+// When the log file is rotated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Minutely,
+    Hourly,
+    Daily,
+    Size,
+    Never,
+}
+
+// Copy of every log line sent to a syslog server as RFC 5424 messages
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyslogSettings {
+    #[serde(default)]
+    pub transport: SyslogTransport,
+
+    // "host:port" for udp and tcp, a socket path such as "/dev/log" for unix
+    #[serde(default)]
+    pub address: String,
+
+    #[serde(default = "default_syslog_facility")]
+    pub facility: SyslogFacility,
+
+    #[serde(default = "default_syslog_app_name")]
+    pub app_name: String,
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum SyslogTransport {
+    #[default]
+    None,
+    Udp,
+    Tcp,
+    Unix,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyslogFacility {
+    Kern,
+    User,
+    Mail,
+    Daemon,
+    Auth,
+    Syslog,
+    Lpr,
+    News,
+    Uucp,
+    Cron,
+    Authpriv,
+    Ftp,
+    Local0,
+    Local1,
+    Local2,
+    Local3,
+    Local4,
+    Local5,
+    Local6,
+    Local7,
+}
+
+impl SyslogFacility {
+    // Numeric facility from RFC 5424; local0-7 are 16-23
+    pub fn code(self) -> u32 {
+        match self {
+            Self::Kern => 0,
+            Self::User => 1,
+            Self::Mail => 2,
+            Self::Daemon => 3,
+            Self::Auth => 4,
+            Self::Syslog => 5,
+            Self::Lpr => 6,
+            Self::News => 7,
+            Self::Uucp => 8,
+            Self::Cron => 9,
+            Self::Authpriv => 10,
+            Self::Ftp => 11,
+            Self::Local0 => 16,
+            Self::Local1 => 17,
+            Self::Local2 => 18,
+            Self::Local3 => 19,
+            Self::Local4 => 20,
+            Self::Local5 => 21,
+            Self::Local6 => 22,
+            Self::Local7 => 23,
+        }
+    }
+}
+
+// Append-only log of every published event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalSettings {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    #[serde(default = "default_journal_dir")]
+    pub dir: String,
+
+    // Size at which the current file is rotated
+    #[serde(default = "default_journal_max_file_bytes")]
+    pub max_file_bytes: u64,
+
+    // Rotated files kept next to the current one
+    #[serde(default = "default_journal_max_files")]
+    pub max_files: usize,
+}
+
+// Virus scanning of uploads through clamd
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanSettings {
+    // "host:port", or a unix socket path such as
+    // "/run/clamav/clamd.ctl"; empty disables scanning
+    #[serde(default)]
+    pub clamd_address: String,
+
+    // Infected files are moved here, outside of the SFTP root
+    #[serde(default = "default_quarantine_dir")]
+    pub quarantine_dir: String,
+
+    #[serde(default = "default_scan_timeout_secs")]
+    pub timeout_secs: u64,
+
+    // Also quarantine files that could not be scanned
+    #[serde(default)]
+    pub quarantine_on_error: bool,
+}
+
+// Periodic cleanup of old files below the SFTP root
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetentionSettings {
+    #[serde(default = "default_retention_interval_secs")]
+    pub interval_secs: u64,
+
+    // Report what would be removed without touching any file
+    #[serde(default)]
+    pub dry_run: bool,
+
+    #[serde(default)]
+    pub rules: Vec<RetentionRule>,
+}
+
+// Limits for the files below one directory; 0 disables a limit
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetentionRule {
+    // Directory as seen over SFTP, e.g. "/incoming"
+    pub path: String,
+
+    #[serde(default)]
+    pub max_age_days: u64,
+
+    #[serde(default)]
+    pub max_total_bytes: u64,
+
+    #[serde(default)]
+    pub max_files: usize,
+
+    #[serde(default)]
+    pub action: RetentionAction,
+
+    // Archived files keep their path below the root in here
+    #[serde(default = "default_archive_dir")]
+    pub archive_dir: String,
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum RetentionAction {
+    #[default]
+    Delete,
+    Archive,
+}
+
+// Events for files that appear, change or disappear below the SFTP root,
+// whether through SFTP or not
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatcherSettings {
+    #[serde(default)]
+    pub enabled: bool,
+
+    // Quiet period before a file's change is reported
+    #[serde(default = "default_watcher_debounce_ms")]
+    pub debounce_ms: u64,
+}
+
+// Short-lived cache of SFTP directory listings and file attributes for
+// clients that re-list the same large directory every few seconds. Changes
+// made over SFTP clear the entries they affect; other changes show once the
+// watcher reports them, if enabled, or the entries expire. Read at startup.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListingCacheSettings {
+    #[serde(default)]
+    pub enabled: bool,
+
+    // How long a listing or a file's attributes are served from the cache
+    #[serde(default = "default_listing_cache_ttl_ms")]
+    pub ttl_ms: u64,
+
+    // Cached listings and attributes kept at most
+    #[serde(default = "default_listing_cache_max_entries")]
+    pub max_entries: usize,
+}
+
+impl ListingCacheSettings {
+    pub fn cache(&self) -> Option<ListingCache> {
+        self.enabled.then(|| {
+            ListingCache::new(
+                Duration::from_millis(self.ttl_ms),
+                self.max_entries,
+            )
+        })
+    }
+}
+
+// Keep removed files in a hidden ".trash" directory below each SFTP root
+// so they can be restored through the API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashSettings {
+    #[serde(default)]
+    pub enabled: bool,
+
+    // Items are deleted for good once they have been in the trash this long
+    #[serde(default = "default_trash_retention_days")]
+    pub retention_days: u64,
+}
+
+// A private scratch directory per SFTP session, deleted when the session
+// ends. Read when the server starts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScratchSettings {
+    #[serde(default)]
+    pub enabled: bool,
+
+    // Where sessions see their scratch directory
+    #[serde(default = "default_scratch_path")]
+    pub path: String,
+}
+
+impl ScratchSettings {
+    // Client path of the scratch directories, when sessions get one
+    pub fn dir(&self) -> Option<String> {
+        self.enabled.then(|| self.path.clone())
+    }
+}
+
+// When an SFTP or FTPS upload counts as complete, which is when the upload
+// hooks run and webhooks hear of it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompletionSettings {
+    // Uploads to names ending in one of these are partial until renamed
+    #[serde(default = "default_partial_suffixes")]
+    pub partial_suffixes: Vec<String>,
+
+    // Seconds the size of a closed upload must stay the same; 0 completes
+    // uploads when they are closed
+    #[serde(default)]
+    pub stable_secs: u64,
+}
+
+impl CompletionSettings {
+    pub fn rules(&self) -> CompletionRules {
+        CompletionRules {
+            partial_suffixes: self.partial_suffixes.clone(),
+            stable_for: Duration::from_secs(self.stable_secs),
+        }
+    }
+}
+
+// Directories whose SFTP uploads are filed into dated subdirectories, so
+// partners upload to the same path while batch jobs find files by day
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LandingSettings {
+    // Client paths, e.g. "/incoming"; only files directly inside are filed
+    #[serde(default)]
+    pub dirs: Vec<String>,
+
+    // strftime format of the subdirectories, evaluated in UTC
+    #[serde(default = "default_landing_layout")]
+    pub layout: String,
+}
+
+impl LandingSettings {
+    pub fn rules(&self) -> LandingRules {
+        LandingRules { dirs: self.dirs.clone(), layout: self.layout.clone() }
+    }
+}
+
+// Rewriting of the names of files and directories created by clients
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FilenameSettings {
+    // "none", "nfc" or "nfd"
+    #[serde(default)]
+    pub normalization: Normalization,
+
+    #[serde(default)]
+    pub reject_invalid_utf8: bool,
+
+    // Replace non-ASCII characters by ASCII ones; normalization is moot then
+    #[serde(default)]
+    pub transliterate: bool,
+
+    // "off", "lenient" or "strict"
+    #[serde(default)]
+    pub sanitize: Sanitization,
+
+    // Leave names starting with a dot out of directory listings
+    #[serde(default)]
+    pub hide_dotfiles: bool,
+
+    // Refuse to create files and directories whose names start with a dot
+    #[serde(default)]
+    pub block_dotfiles: bool,
+
+    // Glob patterns of dotfiles that are neither hidden nor refused
+    #[serde(default)]
+    pub dotfile_allow: Vec<String>,
+}
+
+impl FilenameSettings {
+    pub fn policy(&self) -> FilenamePolicy {
+        FilenamePolicy {
+            normalization: self.normalization,
+            reject_invalid_utf8: self.reject_invalid_utf8,
+            transliterate: self.transliterate,
+            sanitization: self.sanitize,
+            dotfiles: Dotfiles {
+                hide: self.hide_dotfiles,
+                block: self.block_dotfiles,
+                allow: self.dotfile_allow.clone(),
+            },
+        }
+    }
+}
+
+// Recording of the requests made in SFTP sessions, for reviewing what a
+// client did, e.g. after a suspected data leak
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordingSettings {
+    #[serde(default)]
+    pub enabled: bool,
+
+    // One file per session, listed by GET /sftp/recordings
+    #[serde(default = "default_recording_dir")]
+    pub dir: String,
+
+    // Users whose sessions are recorded; empty records all sessions
+    #[serde(default)]
+    pub users: Vec<String>,
+
+    // Also record the data read and written, not only paths and sizes
+    #[serde(default)]
+    pub include_data: bool,
+}
+
+impl Default for RecordingSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: default_recording_dir(),
+            users: Vec::new(),
+            include_data: false,
+        }
+    }
+}
+
+impl RecordingSettings {
+    pub fn policy(&self) -> Option<RecordingPolicy> {
+        self.enabled.then(|| RecordingPolicy {
+            dir: PathBuf::from(&self.dir),
+            include_data: self.include_data,
+            users: self.users.clone().into(),
+        })
+    }
+}
+
+// Index of file names below the SFTP root for GET /files/search
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchSettings {
+    #[serde(default)]
+    pub enabled: bool,
+
+    // The index follows the file watcher; a full rescan also picks up
+    // anything it missed
+    #[serde(default = "default_search_rescan_interval_secs")]
+    pub rescan_interval_secs: u64,
+
+    // Upper bound for the limit of a single query
+    #[serde(default = "default_search_max_results")]
+    pub max_results: usize,
+}
+
+// Re-hash uploaded files and compare them with the checksums recorded in
+// the transfer log to detect silent corruption
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IntegritySettings {
+    #[serde(default)]
+    pub enabled: bool,
+
+    // Five-field cron expression in UTC; a run starts in every matching
+    // minute unless the previous one is still going
+    #[serde(default = "default_integrity_schedule")]
+    pub schedule: String,
+}
+
+// LAN mode: advertise the default SFTP listener over mDNS/DNS-SD as
+// _sftp-ssh._tcp while it runs, so file managers on the local network
+// discover it. Read at startup.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MdnsSettings {
+    #[serde(default)]
+    pub enabled: bool,
+
+    // Name shown by browsing clients; defaults to "SFTP on <host name>"
+    #[serde(default)]
+    pub instance_name: String,
+}
+
+// Port mapping requested from the local router for the default SFTP
+// listener, so it is reachable from outside a home network without manual
+// router setup. Mappings are renewed until shutdown; read at startup.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortMappingSettings {
+    #[serde(default)]
+    pub enabled: bool,
+
+    #[serde(default = "default_port_mapping_protocol")]
+    pub protocol: PortMappingProtocol,
+
+    // NAT-PMP only: router address; defaults to the default gateway
+    #[serde(default)]
+    pub gateway: Option<String>,
+
+    // Port requested on the router; defaults to the listen port
+    #[serde(default)]
+    pub external_port: Option<u16>,
+
+    // Lifetime of a mapping; renewed halfway through
+    #[serde(default = "default_port_mapping_lease_secs")]
+    pub lease_secs: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PortMappingProtocol {
+    Upnp,
+    NatPmp,
+}
+
+// Snapshots of the default root as tar.zst archives, on a schedule or
+// through POST /admin/backup. Applies on reload.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupSettings {
+    #[serde(default)]
+    pub enabled: bool,
+
+    // Five-field cron expression in UTC; empty only backs up on request
+    #[serde(default = "default_backup_schedule")]
+    pub schedule: String,
+
+    // Archives are written here; must be outside the root
+    #[serde(default = "default_backup_dir")]
+    pub dir: String,
+
+    // Most recent archives kept, here and on the target; 0 keeps all
+    #[serde(default = "default_backup_keep")]
+    pub keep: usize,
+
+    // zstd level, 1 (fastest) to 19
+    #[serde(default = "default_backup_compression_level")]
+    pub compression_level: i32,
+
+    // Name of a [[mirror.targets]] entry archives are also copied to
+    #[serde(default)]
+    pub target: Option<String>,
+}
+
+// Limits for archives unpacked through POST /files/extract
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtractSettings {
+    // Total size of the extracted files
+    #[serde(default = "default_extract_max_bytes")]
+    pub max_bytes: u64,
+
+    #[serde(default = "default_extract_max_files")]
+    pub max_files: u64,
+}
+
+// FTP over explicit TLS, started and stopped together with the SFTP
+// server and serving the same root to the same credentials. Read when the
+// server starts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FtpsSettings {
+    #[serde(default)]
+    pub enabled: bool,
+
+    // Control connection port, on the SFTP bind address
+    #[serde(default = "default_ftps_port")]
+    pub port: u16,
+
+    // PEM certificate chain and private key presented to clients
+    #[serde(default)]
+    pub certificate_file: String,
+    #[serde(default)]
+    pub private_key_file: String,
+
+    // Ports used for passive data connections
+    #[serde(default = "default_ftps_passive_port_min")]
+    pub passive_port_min: u16,
+    #[serde(default = "default_ftps_passive_port_max")]
+    pub passive_port_max: u16,
+
+    // IPv4 address announced in PASV replies, e.g. behind NAT; the local
+    // address of the control connection otherwise
+    #[serde(default)]
+    pub passive_address: Option<String>,
+}
+
+// Embedded file manager served at /ui
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UiSettings {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+// Resumable HTTP uploads with the TUS 1.0 protocol at /files/tus
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TusSettings {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    // Partial uploads are kept here, outside the SFTP root, until complete
+    #[serde(default = "default_tus_dir")]
+    pub dir: String,
+
+    // Largest upload accepted; 0 for no limit
+    #[serde(default)]
+    pub max_bytes: u64,
+
+    // Unfinished uploads untouched for this long are deleted
+    #[serde(default = "default_tus_expire_hours")]
+    pub expire_hours: u64,
+}
+
+// S3-compatible gateway over the default SFTP root, for tools that only
+// speak S3. Requests are signed with AWS Signature Version 4.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct S3Settings {
+    #[serde(default)]
+    pub enabled: bool,
+
+    #[serde(default = "default_s3_port")]
+    pub port: u16,
+
+    // Name of the single bucket; its keys are paths below the root
+    #[serde(default = "default_s3_bucket")]
+    pub bucket: String,
+
+    #[serde(default)]
+    pub access_key_id: String,
+
+    #[serde(default)]
+    pub secret_access_key: SecretString,
+}
+
+// gRPC management API mirroring the REST operations. It shares the
+// management API's TLS settings, admin keys, client certificate roles
+// and country filter.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GrpcSettings {
+    #[serde(default)]
+    pub enabled: bool,
+
+    // Address to listen on; only local clients can reach the default
+    #[serde(default = "default_grpc_host")]
+    pub host: String,
+
+    #[serde(default = "default_grpc_port")]
+    pub port: u16,
+}
+
+// Publishing of events to a Kafka topic or NATS subjects for streaming
+// ingestion. The connection is made at startup.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventStreamSettings {
+    #[serde(default)]
+    pub enabled: bool,
+
+    #[serde(default = "default_event_stream_kind")]
+    pub kind: EventStreamKind,
+
+    // NATS server URLs, e.g. "nats://127.0.0.1:4222", or Kafka bootstrap
+    // brokers, e.g. "127.0.0.1:9092"
+    #[serde(default)]
+    pub brokers: Vec<String>,
+
+    // Kafka topic, or NATS subject prefix; events go to
+    // "{subject}.{event name}" on NATS
+    #[serde(default = "default_event_stream_subject")]
+    pub subject: String,
+
+    // Kafka partition the events are written to
+    #[serde(default)]
+    pub partition: i32,
+
+    // NATS user or Kafka SASL PLAIN login; none when empty
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub password: Option<SecretString>,
+
+    // Event names to publish; empty means all events
+    #[serde(default = "default_event_stream_events")]
+    pub events: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventStreamKind {
+    Nats,
+    Kafka,
+}
+
+// Country restrictions for SFTP, FTPS and management API clients, looked
+// up in a MaxMind DB file. The country lists apply on reload.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeoIpSettings {
+    // GeoLite2/GeoIP2 Country or City database; empty disables lookups
+    #[serde(default)]
+    pub database_file: String,
+
+    // ISO 3166-1 alpha-2 codes; when not empty, only these are let in
+    #[serde(default)]
+    pub allow_countries: Vec<String>,
+
+    // Codes that are always turned away
+    #[serde(default)]
+    pub deny_countries: Vec<String>,
+
+    // Whether addresses without a country, e.g. private networks, are let
+    // in while a country list is set
+    #[serde(default = "default_true")]
+    pub allow_unknown: bool,
+}
+
+impl GeoIpSettings {
+    // Whether a client from `country` may connect
+    pub fn allows(&self, country: Option<&str>) -> bool {
+        if self.allow_countries.is_empty() && self.deny_countries.is_empty() {
+            return true;
+        }
+        let Some(country) = country else {
+            return self.allow_unknown;
+        };
+        let listed = |codes: &[String]| {
+            codes.iter().any(|code| code.eq_ignore_ascii_case(country))
+        };
+        !listed(&self.deny_countries)
+            && (self.allow_countries.is_empty()
+                || listed(&self.allow_countries))
+    }
+}
+
+// Replication of new and changed files to remote SFTP servers or S3
+// buckets. Targets and rules apply on reload.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MirrorSettings {
+    #[serde(default)]
+    pub enabled: bool,
+
+    // Pending and failed jobs are kept here so they survive restarts
+    #[serde(default = "default_mirror_queue_dir")]
+    pub queue_dir: String,
+
+    // Attempts before a job is moved to the failed queue
+    #[serde(default = "default_mirror_max_attempts")]
+    pub max_attempts: u32,
+
+    // Delay before the first retry; doubled after every further failure
+    #[serde(default = "default_mirror_retry_base_secs")]
+    pub retry_base_secs: u64,
+
+    #[serde(default)]
+    pub targets: Vec<MirrorTarget>,
+
+    // A file is mirrored to the target of every rule whose glob matches
+    #[serde(default)]
+    pub rules: Vec<MirrorRule>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MirrorKind {
+    Sftp,
+    S3,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MirrorTarget {
+    pub name: String,
+    pub kind: MirrorKind,
+
+    // SFTP: server and login, with a password or a private key
+    #[serde(default)]
+    pub host: String,
+    #[serde(default = "default_mirror_sftp_port")]
+    pub port: u16,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub password: Option<SecretString>,
+    // File holding the password; takes precedence over `password`
+    #[serde(default)]
+    pub password_file: Option<String>,
+    #[serde(default)]
+    pub private_key_file: Option<String>,
+    // Expected host key, e.g. "SHA256:..."; any key is accepted and logged
+    // when unset
+    #[serde(default)]
+    pub host_key_fingerprint: Option<String>,
+    // SFTP: directory the root is mirrored into
+    #[serde(default = "default_mirror_remote_dir")]
+    pub remote_dir: String,
+
+    // S3: path-style endpoint, e.g. "https://s3.eu-west-1.amazonaws.com"
+    #[serde(default)]
+    pub endpoint: String,
+    #[serde(default = "default_mirror_region")]
+    pub region: String,
+    #[serde(default)]
+    pub bucket: String,
+    // S3: prepended to the key of every file
+    #[serde(default)]
+    pub prefix: String,
+    #[serde(default)]
+    pub access_key_id: String,
+    #[serde(default)]
+    pub secret_access_key: Option<SecretString>,
+    // File holding the secret key; takes precedence over
+    // `secret_access_key`
+    #[serde(default)]
+    pub secret_access_key_file: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MirrorRule {
+    // Matched against the path below the root, e.g. "/incoming/**"
+    pub glob: String,
+    // Name of a target
+    pub target: String,
+}
+
+// Actions run on every completed upload, after virus scanning
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PipelineSettings {
+    // The first rule whose glob matches the uploaded path runs
+    #[serde(default)]
+    pub rules: Vec<PipelineRule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineRule {
+    pub name: String,
+
+    // Matched against the path as seen over SFTP, e.g. "/in/**/*.csv"
+    #[serde(default = "default_pipeline_glob")]
+    pub glob: String,
+
+    // Run in order; a move changes the path seen by later actions
+    pub actions: Vec<PipelineAction>,
+
+    #[serde(default)]
+    pub on_failure: PipelineFailure,
+
+    // Directory below the SFTP root for files whose processing failed,
+    // used with on_failure = "move"
+    #[serde(default)]
+    pub failed_dir: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum PipelineAction {
+    // Move the file into a directory below the SFTP root
+    Move {
+        target: String,
+    },
+    // POST the upload details as JSON, signed like event webhooks
+    Webhook {
+        url: String,
+        #[serde(default)]
+        secret: Option<String>,
+    },
+    // Write a "<file>.<algorithm>" file in sha256sum format next to it
+    Checksum {
+        #[serde(default = "default_pipeline_algorithm")]
+        algorithm: ChecksumAlgorithm,
+    },
+    // Run a program; "{path}", "{virtual_path}" and "{username}" in the
+    // arguments are replaced. A non-zero exit status is a failure.
+    Command {
+        command: Vec<String>,
+        #[serde(default = "default_pipeline_timeout_secs")]
+        timeout_secs: u64,
+    },
+}
+
+// What happens when an action fails
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum PipelineFailure {
+    // Skip the remaining actions
+    #[default]
+    Stop,
+    // Run the remaining actions anyway
+    Continue,
+    // Skip the remaining actions and move the file to failed_dir
+    Move,
+}
+
+// Hooks told about SFTP connections opening and closing and about logins,
+// e.g. to update firewall rules. They run one after another for each event,
+// in the order events happen, and are read from the live settings.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConnectionHookSettings {
+    #[serde(default)]
+    pub hooks: Vec<ConnectionHookRule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionHookRule {
+    // Events the hook runs on; all of them when empty
+    #[serde(default)]
+    pub events: Vec<ConnectionEvent>,
+
+    #[serde(flatten)]
+    pub action: ConnectionHookAction,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ConnectionHookAction {
+    // POST the event and connection details as JSON, signed like event
+    // webhooks
+    Webhook {
+        url: String,
+        #[serde(default)]
+        secret: Option<String>,
+    },
+    // Run a program; "{event}", "{ip}", "{port}", "{username}",
+    // "{country}", "{reason}" and "{session}" in the arguments are
+    // replaced, by "" when unknown
+    Command {
+        command: Vec<String>,
+        #[serde(default = "default_connection_hook_timeout_secs")]
+        timeout_secs: u64,
+    },
+}
+
+// Key used to encrypt persisted credentials
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecretsSettings {
+    // File holding the key as 64 hex characters; takes precedence
+    #[serde(default)]
+    pub key_file: String,
+
+    // Environment variable holding the key when no file is set
+    #[serde(default = "default_secrets_key_env")]
+    pub key_env: String,
+}
+
+// HashiCorp Vault holding the host key and persisted credentials
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VaultSettings {
+    // e.g. "https://vault.example.com:8200"; empty disables Vault
+    #[serde(default)]
+    pub address: String,
+
+    // File holding the token; takes precedence
+    #[serde(default)]
+    pub token_file: String,
+
+    // Environment variable holding the token when no file is set
+    #[serde(default = "default_vault_token_env")]
+    pub token_env: String,
+
+    // Enterprise namespace, if any
+    #[serde(default)]
+    pub namespace: String,
+
+    // Mount point of the KV version 2 secrets engine
+    #[serde(default = "default_vault_mount")]
+    pub mount: String,
+
+    // Path below the mount where this deployment keeps its secrets
+    #[serde(default = "default_vault_path")]
+    pub path: String,
+}
+
+// An additional SFTP server with its own credentials and state. It shares
+// the [sftp] tuning settings but is not restricted by the schedule.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstanceSettings {
+    // Used in API paths and state file names
+    pub name: String,
+
+    pub port: u16,
+
+    #[serde(default = "default_bind_addrs")]
+    pub bind_addrs: String,
+
+    // Override [sftp] external_host; the port is not inherited
+    #[serde(default)]
+    pub external_host: Option<String>,
+
+    #[serde(default)]
+    pub external_port: Option<u16>,
+
+    pub root_dir: String,
+
+    // Bearer tokens granting access to /instances/{name}/... of this
+    // instance only
+    #[serde(default)]
+    pub api_keys: Vec<SecretString>,
+}
+
+// A customer with its own directory, users and API keys. Requests with one
+// of its keys may only use /tenants/{name}/...; an instance serving the
+// tenant over SFTP must have the tenant's directory as its root.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TenantSettings {
+    // Used in API paths
+    pub name: String,
+
+    pub root_dir: String,
+
+    // Bytes the tenant's files may take up; 0 for no limit
+    #[serde(default)]
+    pub quota_bytes: u64,
+
+    // Bearer tokens granting access to the tenant's API
+    #[serde(default)]
+    pub api_keys: Vec<SecretString>,
+
+    // Name of the [[instances]] entry serving the tenant over SFTP
+    #[serde(default)]
+    pub instance: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookEndpoint {
+    pub url: String,
+
+    // Shared secret used to sign payloads with HMAC-SHA256
+    #[serde(default)]
+    pub secret: Option<SecretString>,
+
+    // File holding the secret; takes precedence over `secret`
+    #[serde(default)]
+    pub secret_file: Option<String>,
+
+    // Event names to deliver; empty means all events
+    #[serde(default)]
+    pub events: Vec<String>,
+}
+
+// Default values
+fn default_port() -> u16 {
+    3000
+}
+fn default_host() -> String {
+    "0.0.0.0".to_string()
+}
+fn default_max_body_bytes() -> usize {
+    2 * 1024 * 1024
+}
+fn default_upload_idle_timeout_secs() -> u64 {
+    60
+}
+fn default_max_concurrent_uploads() -> usize {
+    16
+}
+fn default_sftp_port() -> u16 {
+    2222
+}
+fn default_bind_addrs() -> String {
+    "0.0.0.0".to_string()
+}
+fn default_sftp_root() -> String {
+    "./sftp_root_dir".to_string()
+}
+fn default_auth_failure_threshold() -> u32 {
+    10
+}
+fn default_auth_failure_window_secs() -> u64 {
+    60
+}
+fn default_auth_failure_ip_threshold() -> u32 {
+    5
+}
+fn default_auth_failure_user_threshold() -> u32 {
+    5
+}
+fn default_tarpit_delay_ms() -> u64 {
+    10_000
+}
+fn default_tarpit_max_connections() -> usize {
+    64
+}
+fn default_checksum_algorithms() -> Vec<ChecksumAlgorithm> {
+    vec![ChecksumAlgorithm::Sha256]
+}
+fn default_recording_dir() -> String {
+    "./recordings".to_string()
+}
+fn default_window_bytes() -> u32 {
+    TransferLimits::default().window_bytes
 }
+fn default_max_packet_bytes() -> u32 {
+    TransferLimits::default().max_packet_bytes
+}
+fn default_max_read_bytes() -> u32 {
+    TransferLimits::default().max_read_bytes
+}
+fn default_max_write_bytes() -> u32 {
+    TransferLimits::default().max_write_bytes
+}
+fn default_transfer_buffer_bytes() -> usize {
+    TransferLimits::default().transfer_buffer_bytes
+}
+fn default_read_ahead_chunks() -> usize {
+    TransferLimits::default().read_ahead_chunks
+}
+fn default_keepalive_interval_secs() -> u64 {
+    KeepAlive::default().interval.map_or(0, |interval| interval.as_secs())
+}
+fn default_keepalive_max_missed() -> usize {
+    KeepAlive::default().max_missed
+}
+fn default_session_memory_mb() -> usize {
+    64
+}
+fn default_session_transfers() -> usize {
+    8
+}
+fn default_disk_warning_mb() -> u64 {
+    1024
+}
+fn default_self_check_timeout_ms() -> u64 {
+    2000
+}
+fn default_usage_cache_secs() -> u64 {
+    60
+}
+fn default_drain_timeout_secs() -> u64 {
+    300
+}
+fn default_stop_timeout_secs() -> u64 {
+    30
+}
+fn default_check_interval_secs() -> u64 {
+    10
+}
+fn default_expiration_days() -> u64 {
+    30
+}
+fn default_expiry_warning_secs() -> Vec<u64> {
+    vec![24 * 60 * 60, 60 * 60]
+}
+fn default_restart_max_attempts() -> u32 {
+    5
+}
+fn default_restart_backoff_secs() -> u64 {
+    1
+}
+fn default_restart_backoff_max_secs() -> u64 {
+    60
+}
+fn default_state_file() -> String {
+    "./data/sftp_state.json".to_string()
+}
+fn default_password_history() -> usize {
+    5
+}
+fn default_database_url() -> String {
+    "sqlite://./data/sftp-manager.db".to_string()
+}
+fn default_database_pool_size() -> usize {
+    8
+}
+fn default_redis_key_prefix() -> String {
+    "sftp-manager".to_string()
+}
+fn default_true() -> bool {
+    true
+}
+fn default_journal_dir() -> String {
+    "./data/journal".to_string()
+}
+fn default_journal_max_file_bytes() -> u64 {
+    10 * 1024 * 1024
+}
+fn default_journal_max_files() -> usize {
+    5
+}
+fn default_log_rotation() -> LogRotation {
+    LogRotation::Daily
+}
+fn default_log_max_files() -> usize {
+    7
+}
+fn default_log_max_file_bytes() -> u64 {
+    50 * 1024 * 1024
+}
+fn default_syslog_facility() -> SyslogFacility {
+    SyslogFacility::Daemon
+}
+fn default_syslog_app_name() -> String {
+    "sftp-manager".to_string()
+}
+fn default_quarantine_dir() -> String {
+    "./data/quarantine".to_string()
+}
+fn default_scan_timeout_secs() -> u64 {
+    60
+}
+fn default_retention_interval_secs() -> u64 {
+    3600
+}
+fn default_archive_dir() -> String {
+    "./data/archive".to_string()
+}
+fn default_watcher_debounce_ms() -> u64 {
+    2000
+}
+fn default_listing_cache_ttl_ms() -> u64 {
+    2000
+}
+fn default_listing_cache_max_entries() -> usize {
+    10_000
+}
+fn default_partial_suffixes() -> Vec<String> {
+    vec![".part".to_string(), ".filepart".to_string()]
+}
+
+fn default_trash_retention_days() -> u64 {
+    30
+}
+fn default_scratch_path() -> String {
+    "/tmp".to_string()
+}
+fn default_search_rescan_interval_secs() -> u64 {
+    3600
+}
+fn default_search_max_results() -> usize {
+    1000
+}
+fn default_integrity_schedule() -> String {
+    "0 3 * * *".to_string()
+}
+fn default_extract_max_bytes() -> u64 {
+    10 * 1024 * 1024 * 1024
+}
+fn default_extract_max_files() -> u64 {
+    100_000
+}
+fn default_ftps_port() -> u16 {
+    2121
+}
+
+fn default_ftps_passive_port_min() -> u16 {
+    50000
+}
+
+fn default_ftps_passive_port_max() -> u16 {
+    50100
+}
+
+fn default_tus_dir() -> String {
+    "./data/tus".to_string()
+}
+
+fn default_tus_expire_hours() -> u64 {
+    24
+}
+
+fn default_s3_port() -> u16 {
+    9000
+}
+
+fn default_s3_bucket() -> String {
+    "sftp".to_string()
+}
+
+fn default_grpc_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_grpc_port() -> u16 {
+    50051
+}
+
+fn default_port_mapping_protocol() -> PortMappingProtocol {
+    PortMappingProtocol::Upnp
+}
+
+fn default_port_mapping_lease_secs() -> u32 {
+    3600
+}
+
+fn default_backup_schedule() -> String {
+    "0 2 * * *".to_string()
+}
+
+fn default_backup_dir() -> String {
+    "./data/backups".to_string()
+}
+
+fn default_backup_keep() -> usize {
+    7
+}
+
+fn default_backup_compression_level() -> i32 {
+    3
+}
+
+fn default_event_stream_kind() -> EventStreamKind {
+    EventStreamKind::Nats
+}
+
+fn default_event_stream_subject() -> String {
+    "sftp-manager.events".to_string()
+}
+
+fn default_event_stream_events() -> Vec<String> {
+    [
+        "file_uploaded",
+        "file_deleted",
+        "login_succeeded",
+        "login_failed",
+        "auth_failure_spike",
+    ]
+    .map(String::from)
+    .to_vec()
+}
+
+fn default_mirror_queue_dir() -> String {
+    "./data/mirror".to_string()
+}
+
+fn default_mirror_max_attempts() -> u32 {
+    10
+}
+
+fn default_mirror_retry_base_secs() -> u64 {
+    30
+}
+
+fn default_mirror_sftp_port() -> u16 {
+    22
+}
+
+fn default_mirror_remote_dir() -> String {
+    "/".to_string()
+}
+
+fn default_mirror_region() -> String {
+    "us-east-1".to_string()
+}
+
+fn default_pipeline_glob() -> String {
+    "**".to_string()
+}
+fn default_pipeline_algorithm() -> ChecksumAlgorithm {
+    ChecksumAlgorithm::Sha256
+}
+fn default_pipeline_timeout_secs() -> u64 {
+    300
+}
+fn default_connection_hook_timeout_secs() -> u64 {
+    10
+}
+fn default_secrets_key_env() -> String {
+    "SFTP_MANAGER_SECRET_KEY".to_string()
+}
+fn default_vault_token_env() -> String {
+    "VAULT_TOKEN".to_string()
+}
+fn default_vault_mount() -> String {
+    "secret".to_string()
+}
+fn default_vault_path() -> String {
+    "sftp-manager".to_string()
+}
+fn default_landing_layout() -> String {
+    "%Y/%m/%d".to_string()
+}
+fn default_delivery_timeout_secs() -> u64 {
+    30
+}
+fn default_email_from() -> String {
+    "sftp-manager@localhost".to_string()
+}
+fn default_smtp_port() -> u16 {
+    587
+}
+fn default_webhook_max_retries() -> u32 {
+    3
+}
+fn default_webhook_timeout_secs() -> u64 {
+    10
+}
+
+// Base configuration read when no file is chosen explicitly. The extension
+// is optional; any supported format is found.
+const DEFAULT_CONFIG: &str = "config/default";
+
+// Configuration file formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ConfigFormat {
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl From<ConfigFormat> for FileFormat {
+    fn from(format: ConfigFormat) -> Self {
+        match format {
+            ConfigFormat::Toml => FileFormat::Toml,
+            ConfigFormat::Yaml => FileFormat::Yaml,
+            ConfigFormat::Json => FileFormat::Json,
+        }
+    }
+}
+
+// Where the configuration is read from, plus values that take precedence
+// over the file, e.g. from command-line flags
+#[derive(Debug, Clone, Default)]
+pub struct SettingsSource {
+    // Explicit configuration file; `config/default` when unset
+    pub config_path: Option<String>,
+    // Format of the files; guessed from the extension when unset
+    pub format: Option<ConfigFormat>,
+    // Environment whose file is layered over the base file, e.g.
+    // `production` for `config/production.toml`
+    pub environment: Option<String>,
+    // Dotted setting names and their values
+    pub overrides: Vec<(String, String)>,
+}
+
+impl SftpSettings {
+    pub fn auth_failure_limits(&self) -> AuthFailureLimits {
+        AuthFailureLimits {
+            threshold: self.auth_failure_threshold,
+            per_ip_threshold: self.auth_failure_ip_threshold,
+            per_user_threshold: self.auth_failure_user_threshold,
+            window: Duration::from_secs(self.auth_failure_window_secs),
+            ban: Duration::from_secs(self.auth_failure_ban_secs),
+        }
+    }
+
+    pub fn tarpit_limits(&self) -> TarpitLimits {
+        TarpitLimits {
+            enabled: self.tarpit,
+            delay: Duration::from_millis(self.tarpit_delay_ms),
+            max_connections: self.tarpit_max_connections,
+        }
+    }
+
+    pub fn client_version_rules(&self) -> ClientVersionRules {
+        ClientVersionRules {
+            allow: self.allowed_clients.clone(),
+            deny: self.denied_clients.clone(),
+        }
+    }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SftpSettings {
-    #[serde(default = "default_sftp_port")]
-    pub port: u16,
+    pub fn transfer_limits(&self) -> TransferLimits {
+        TransferLimits {
+            window_bytes: self.window_bytes,
+            max_packet_bytes: self.max_packet_bytes,
+            max_read_bytes: self.max_read_bytes,
+            max_write_bytes: self.max_write_bytes,
+            transfer_buffer_bytes: self.transfer_buffer_bytes,
+            read_ahead_chunks: self.read_ahead_chunks,
+        }
+    }
 
-    #[serde(default = "default_bind_addrs")]
-    pub bind_addrs: String,
+    pub fn keepalive(&self) -> KeepAlive {
+        KeepAlive {
+            interval: (self.keepalive_interval_secs > 0)
+                .then(|| Duration::from_secs(self.keepalive_interval_secs)),
+            max_missed: self.keepalive_max_missed,
+        }
+    }
 
-    #[serde(default = "default_sftp_root")]
-    pub root_dir: String,
+    pub fn disk_limits(&self) -> DiskLimits {
+        DiskLimits {
+            reserve_bytes: self.disk_reserve_mb.saturating_mul(1024 * 1024),
+            warning_bytes: self.disk_warning_mb.saturating_mul(1024 * 1024),
+        }
+    }
 }
 
-// Default values
-fn default_port() -> u16 {
-    3000
-}
-fn default_host() -> String {
-    "0.0.0.0".to_string()
+impl SettingsSource {
+    // The base file, then the environment file next to it
+    pub fn paths(&self) -> Vec<PathBuf> {
+        let base = PathBuf::from(
+            self.config_path.as_deref().unwrap_or(DEFAULT_CONFIG),
+        );
+        let mut paths = vec![base.clone()];
+        if let Some(environment) = &self.environment {
+            paths.push(base.with_file_name(environment));
+        }
+        paths
+    }
+
+    fn file(&self, path: &Path) -> File<config::FileSourceFile, FileFormat> {
+        let file = File::from(path);
+        match self.format {
+            Some(format) => file.format(format.into()),
+            None => file,
+        }
+    }
 }
-fn default_sftp_port() -> u16 {
-    2222
+
+impl Settings {
+    // Whether any tenant or instance holds an API key, which confines the
+    // API to admins and the key holders
+    pub fn has_scoped_api_keys(&self) -> bool {
+        self.tenants.iter().any(|t| !t.api_keys.is_empty())
+            || self.instances.iter().any(|i| !i.api_keys.is_empty())
+    }
+
+    // Whether management API clients present verified certificates
+    pub fn verifies_client_certificates(&self) -> bool {
+        let tls = &self.server.tls;
+        tls.enabled && !tls.client_ca_file.is_empty()
+    }
+
+    // Host and port advertised for the listener on `port`, if an external
+    // host is configured for it
+    pub fn external_address(&self, port: u16) -> Option<(String, u16)> {
+        let (host, external_port) = if port == self.sftp.port {
+            (self.sftp.external_host.as_ref(), self.sftp.external_port)
+        } else {
+            let instance = self.instances.iter().find(|i| i.port == port)?;
+            (
+                instance
+                    .external_host
+                    .as_ref()
+                    .or(self.sftp.external_host.as_ref()),
+                instance.external_port,
+            )
+        };
+        Some((host?.clone(), external_port.unwrap_or(port)))
+    }
+
+    pub fn load(source: &SettingsSource) -> Result<Self, ConfigError> {
+        let paths = source.paths();
+
+        // An explicitly chosen file must exist
+        let mut builder = Config::builder().add_source(
+            source.file(&paths[0]).required(source.config_path.is_some()),
+        );
+        // So must the file of a selected environment
+        if let Some(path) = paths.get(1) {
+            builder = builder.add_source(source.file(path));
+        }
+        for (key, value) in &source.overrides {
+            builder = builder.set_override(key.as_str(), value.as_str())?;
+        }
+
+        let mut settings: Self = builder.build()?.try_deserialize()?;
+        settings.read_secret_files()?;
+        Ok(settings)
+    }
+
+    // Fill in secrets from their `*_file` settings. Files are read on every
+    // load, so a reload picks up rotated secrets.
+    fn read_secret_files(&mut self) -> Result<(), ConfigError> {
+        if !self.database.password_file.is_empty() {
+            let password = read_secret_file(&self.database.password_file)?;
+            self.database.url = with_password(
+                &self.database.url,
+                password.expose(),
+                "database.url",
+            )?;
+        }
+        if !self.redis.password_file.is_empty() {
+            let password = read_secret_file(&self.redis.password_file)?;
+            self.redis.url =
+                with_password(&self.redis.url, password.expose(), "redis.url")?;
+        }
+        for endpoint in &mut self.webhooks.endpoints {
+            if let Some(path) = &endpoint.secret_file {
+                endpoint.secret = Some(read_secret_file(path)?);
+            }
+        }
+        if let Some(path) = &self.delivery.slack_webhook_url_file {
+            self.delivery.slack_webhook_url = Some(read_secret_file(path)?);
+        }
+        if let Some(path) = &self.delivery.email.password_file {
+            self.delivery.email.password = Some(read_secret_file(path)?);
+        }
+        for target in &mut self.mirror.targets {
+            if let Some(path) = &target.password_file {
+                target.password = Some(read_secret_file(path)?);
+            }
+            if let Some(path) = &target.secret_access_key_file {
+                target.secret_access_key = Some(read_secret_file(path)?);
+            }
+        }
+        Ok(())
+    }
+
+    // A copy that can leave the process: every secret is replaced by a
+    // marker and passwords are dropped from connection URLs
+    pub fn redacted(&self) -> Self {
+        let mut settings = self.clone();
+        let redact = |secret: &mut SecretString| {
+            if !secret.expose().is_empty() {
+                *secret = REDACTED.to_string().into();
+            }
+        };
+
+        settings.database.url = without_password(&settings.database.url);
+        settings.redis.url = without_password(&settings.redis.url);
+        redact(&mut settings.s3.secret_access_key);
+        for endpoint in &mut settings.webhooks.endpoints {
+            endpoint.secret.iter_mut().for_each(redact);
+        }
+        for hook in &mut settings.connection_hooks.hooks {
+            if let ConnectionHookAction::Webhook {
+                secret: Some(secret), ..
+            } = &mut hook.action
+            {
+                *secret = REDACTED.to_string();
+            }
+        }
+        settings.delivery.slack_webhook_url.iter_mut().for_each(redact);
+        settings.delivery.email.password.iter_mut().for_each(redact);
+        settings.event_stream.password.iter_mut().for_each(redact);
+        for target in &mut settings.mirror.targets {
+            target.password.iter_mut().for_each(redact);
+            target.secret_access_key.iter_mut().for_each(redact);
+        }
+        settings.server.admin_api_keys.iter_mut().for_each(redact);
+        for instance in &mut settings.instances {
+            instance.api_keys.iter_mut().for_each(redact);
+        }
+        for tenant in &mut settings.tenants {
+            tenant.api_keys.iter_mut().for_each(redact);
+        }
+        settings
+    }
 }
-fn default_bind_addrs() -> String {
-    "0.0.0.0".to_string()
+
+// Stands in for secrets in redacted settings
+const REDACTED: &str = "[redacted]";
+
+// `url` without its password, or the marker when it cannot be parsed
+fn without_password(url: &str) -> String {
+    match reqwest::Url::parse(url) {
+        Ok(mut parsed) if parsed.password().is_some() => {
+            let _ = parsed.set_password(None);
+            parsed.to_string()
+        }
+        Ok(_) => url.to_string(),
+        Err(_) if url.is_empty() => String::new(),
+        Err(_) => REDACTED.to_string(),
+    }
 }
-fn default_sftp_root() -> String {
-    "./sftp_root_dir".to_string()
+
+// Read a mounted secret, ignoring the trailing newline most tools write
+fn read_secret_file(path: &str) -> Result<SecretString, ConfigError> {
+    let contents =
+        Zeroizing::new(std::fs::read_to_string(path).map_err(|e| {
+            ConfigError::Message(format!("cannot read secret {}: {}", path, e))
+        })?);
+    Ok(contents.trim_end_matches(['\r', '\n']).to_string().into())
 }
 
-impl Settings {
-    pub fn new() -> Result<Self, ConfigError> {
-        let config = Config::builder()
-            .add_source(File::with_name("config/default").required(false))
-            .build()?;
-        config.try_deserialize()
-    }
+fn with_password(
+    url: &str,
+    password: &str,
+    field: &str,
+) -> Result<String, ConfigError> {
+    let invalid =
+        || ConfigError::Message(format!("{} cannot hold a password", field));
+    let mut parsed = reqwest::Url::parse(url).map_err(|_| invalid())?;
+    parsed.set_password(Some(password)).map_err(|_| invalid())?;
+    Ok(parsed.to_string())
 }
 
 impl Default for Settings {
@@ -61,12 +2159,412 @@ impl Default for Settings {
             server: ServerSettings {
                 port: default_port(),
                 host: default_host(),
+                watch_config: false,
+                max_body_bytes: default_max_body_bytes(),
+                max_upload_bytes: 0,
+                upload_idle_timeout_secs: default_upload_idle_timeout_secs(),
+                max_concurrent_uploads: default_max_concurrent_uploads(),
+                admin_api_keys: Vec::new(),
+                tls: ServerTlsSettings::default(),
             },
             sftp: SftpSettings {
                 port: default_sftp_port(),
                 bind_addrs: default_bind_addrs(),
+                external_host: None,
+                external_port: None,
                 root_dir: default_sftp_root(),
+                auth_failure_threshold: default_auth_failure_threshold(),
+                auth_failure_window_secs: default_auth_failure_window_secs(),
+                auth_failure_ip_threshold: default_auth_failure_ip_threshold(),
+                auth_failure_user_threshold:
+                    default_auth_failure_user_threshold(),
+                auth_failure_ban_secs: 0,
+                tarpit: false,
+                tarpit_delay_ms: default_tarpit_delay_ms(),
+                tarpit_max_connections: default_tarpit_max_connections(),
+                checksum_algorithms: default_checksum_algorithms(),
+                sparse_files: true,
+                disk_reserve_mb: 0,
+                disk_warning_mb: default_disk_warning_mb(),
+                self_check_handshake: false,
+                self_check_timeout_ms: default_self_check_timeout_ms(),
+                usage_cache_secs: default_usage_cache_secs(),
+                drain_timeout_secs: default_drain_timeout_secs(),
+                stop_timeout_secs: default_stop_timeout_secs(),
+                check_interval_secs: default_check_interval_secs(),
+                expiration_days: default_expiration_days(),
+                expiry_warning_secs: default_expiry_warning_secs(),
+                restart_max_attempts: default_restart_max_attempts(),
+                restart_backoff_secs: default_restart_backoff_secs(),
+                restart_backoff_max_secs: default_restart_backoff_max_secs(),
+                state_file: default_state_file(),
+                password_history: default_password_history(),
+                host_key_file: String::new(),
+                window_bytes: default_window_bytes(),
+                max_packet_bytes: default_max_packet_bytes(),
+                max_read_bytes: default_max_read_bytes(),
+                max_write_bytes: default_max_write_bytes(),
+                transfer_buffer_bytes: default_transfer_buffer_bytes(),
+                read_ahead_chunks: default_read_ahead_chunks(),
+                keepalive_interval_secs: default_keepalive_interval_secs(),
+                keepalive_max_missed: default_keepalive_max_missed(),
+                session_memory_mb: default_session_memory_mb(),
+                session_transfers: default_session_transfers(),
+                file_io: FileIoBackend::default(),
+                allowed_clients: Vec::new(),
+                denied_clients: Vec::new(),
+                path_rules: Vec::new(),
             },
+            webhooks: WebhookSettings::default(),
+            schedule: ScheduleSettings::default(),
+            database: DatabaseSettings::default(),
+            redis: RedisSettings::default(),
+            journal: JournalSettings::default(),
+            logging: LoggingSettings::default(),
+            secrets: SecretsSettings::default(),
+            vault: VaultSettings::default(),
+            scan: ScanSettings::default(),
+            retention: RetentionSettings::default(),
+            watcher: WatcherSettings::default(),
+            listing_cache: ListingCacheSettings::default(),
+            pipeline: PipelineSettings::default(),
+            connection_hooks: ConnectionHookSettings::default(),
+            trash: TrashSettings::default(),
+            scratch: ScratchSettings::default(),
+            completion: CompletionSettings::default(),
+            landing: LandingSettings::default(),
+            filenames: FilenameSettings::default(),
+            recording: RecordingSettings::default(),
+            search: SearchSettings::default(),
+            integrity: IntegritySettings::default(),
+            extract: ExtractSettings::default(),
+            ftps: FtpsSettings::default(),
+            ui: UiSettings::default(),
+            tus: TusSettings::default(),
+            s3: S3Settings::default(),
+            grpc: GrpcSettings::default(),
+            mirror: MirrorSettings::default(),
+            geoip: GeoIpSettings::default(),
+            event_stream: EventStreamSettings::default(),
+            backup: BackupSettings::default(),
+            port_mapping: PortMappingSettings::default(),
+            mdns: MdnsSettings::default(),
+            delivery: DeliverySettings::default(),
+            instances: Vec::new(),
+            tenants: Vec::new(),
+        }
+    }
+}
+
+impl Default for RedisSettings {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            key_prefix: default_redis_key_prefix(),
+            replica_id: String::new(),
+            password_file: String::new(),
+        }
+    }
+}
+
+impl Default for LoggingSettings {
+    fn default() -> Self {
+        Self {
+            file: String::new(),
+            rotation: default_log_rotation(),
+            max_files: default_log_max_files(),
+            max_file_bytes: default_log_max_file_bytes(),
+            syslog: SyslogSettings::default(),
+        }
+    }
+}
+
+impl Default for SyslogSettings {
+    fn default() -> Self {
+        Self {
+            transport: SyslogTransport::None,
+            address: String::new(),
+            facility: default_syslog_facility(),
+            app_name: default_syslog_app_name(),
+        }
+    }
+}
+
+impl Default for JournalSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            dir: default_journal_dir(),
+            max_file_bytes: default_journal_max_file_bytes(),
+            max_files: default_journal_max_files(),
+        }
+    }
+}
+
+impl Default for ScanSettings {
+    fn default() -> Self {
+        Self {
+            clamd_address: String::new(),
+            quarantine_dir: default_quarantine_dir(),
+            timeout_secs: default_scan_timeout_secs(),
+            quarantine_on_error: false,
+        }
+    }
+}
+
+impl Default for RetentionSettings {
+    fn default() -> Self {
+        Self {
+            interval_secs: default_retention_interval_secs(),
+            dry_run: false,
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl Default for WatcherSettings {
+    fn default() -> Self {
+        Self { enabled: false, debounce_ms: default_watcher_debounce_ms() }
+    }
+}
+
+impl Default for ListingCacheSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ttl_ms: default_listing_cache_ttl_ms(),
+            max_entries: default_listing_cache_max_entries(),
+        }
+    }
+}
+
+impl Default for TrashSettings {
+    fn default() -> Self {
+        Self { enabled: false, retention_days: default_trash_retention_days() }
+    }
+}
+
+impl Default for ScratchSettings {
+    fn default() -> Self {
+        Self { enabled: false, path: default_scratch_path() }
+    }
+}
+
+impl Default for CompletionSettings {
+    fn default() -> Self {
+        Self { partial_suffixes: default_partial_suffixes(), stable_secs: 0 }
+    }
+}
+
+impl Default for LandingSettings {
+    fn default() -> Self {
+        Self { dirs: Vec::new(), layout: default_landing_layout() }
+    }
+}
+
+impl Default for SearchSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            rescan_interval_secs: default_search_rescan_interval_secs(),
+            max_results: default_search_max_results(),
+        }
+    }
+}
+
+impl Default for IntegritySettings {
+    fn default() -> Self {
+        Self { enabled: false, schedule: default_integrity_schedule() }
+    }
+}
+
+impl Default for ExtractSettings {
+    fn default() -> Self {
+        Self {
+            max_bytes: default_extract_max_bytes(),
+            max_files: default_extract_max_files(),
+        }
+    }
+}
+
+impl Default for FtpsSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: default_ftps_port(),
+            certificate_file: String::new(),
+            private_key_file: String::new(),
+            passive_port_min: default_ftps_passive_port_min(),
+            passive_port_max: default_ftps_passive_port_max(),
+            passive_address: None,
+        }
+    }
+}
+
+impl Default for UiSettings {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+impl Default for TusSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            dir: default_tus_dir(),
+            max_bytes: 0,
+            expire_hours: default_tus_expire_hours(),
+        }
+    }
+}
+
+impl Default for S3Settings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: default_s3_port(),
+            bucket: default_s3_bucket(),
+            access_key_id: String::new(),
+            secret_access_key: SecretString::default(),
+        }
+    }
+}
+
+impl Default for GeoIpSettings {
+    fn default() -> Self {
+        Self {
+            database_file: String::new(),
+            allow_countries: Vec::new(),
+            deny_countries: Vec::new(),
+            allow_unknown: true,
+        }
+    }
+}
+
+impl Default for GrpcSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: default_grpc_host(),
+            port: default_grpc_port(),
+        }
+    }
+}
+
+impl Default for PortMappingSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            protocol: default_port_mapping_protocol(),
+            gateway: None,
+            external_port: None,
+            lease_secs: default_port_mapping_lease_secs(),
+        }
+    }
+}
+
+impl Default for BackupSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            schedule: default_backup_schedule(),
+            dir: default_backup_dir(),
+            keep: default_backup_keep(),
+            compression_level: default_backup_compression_level(),
+            target: None,
+        }
+    }
+}
+
+impl Default for EventStreamSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            kind: default_event_stream_kind(),
+            brokers: Vec::new(),
+            subject: default_event_stream_subject(),
+            partition: 0,
+            username: String::new(),
+            password: None,
+            events: default_event_stream_events(),
+        }
+    }
+}
+
+impl Default for MirrorSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            queue_dir: default_mirror_queue_dir(),
+            max_attempts: default_mirror_max_attempts(),
+            retry_base_secs: default_mirror_retry_base_secs(),
+            targets: Vec::new(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl Default for SecretsSettings {
+    fn default() -> Self {
+        Self { key_file: String::new(), key_env: default_secrets_key_env() }
+    }
+}
+
+impl Default for VaultSettings {
+    fn default() -> Self {
+        Self {
+            address: String::new(),
+            token_file: String::new(),
+            token_env: default_vault_token_env(),
+            namespace: String::new(),
+            mount: default_vault_mount(),
+            path: default_vault_path(),
+        }
+    }
+}
+
+impl Default for DatabaseSettings {
+    fn default() -> Self {
+        Self {
+            url: default_database_url(),
+            pool_size: default_database_pool_size(),
+            password_file: String::new(),
+        }
+    }
+}
+
+impl Default for DeliverySettings {
+    fn default() -> Self {
+        Self {
+            slack_webhook_url: None,
+            slack_webhook_url_file: None,
+            email: EmailDeliverySettings::default(),
+            timeout_secs: default_delivery_timeout_secs(),
+        }
+    }
+}
+
+impl Default for EmailDeliverySettings {
+    fn default() -> Self {
+        Self {
+            to: Vec::new(),
+            from: default_email_from(),
+            smtp_host: String::new(),
+            smtp_port: default_smtp_port(),
+            smtp_tls: SmtpTls::default(),
+            username: String::new(),
+            password: None,
+            password_file: None,
+        }
+    }
+}
+
+impl Default for WebhookSettings {
+    fn default() -> Self {
+        Self {
+            endpoints: Vec::new(),
+            max_retries: default_webhook_max_retries(),
+            timeout_secs: default_webhook_timeout_secs(),
         }
     }
 }
@@ -83,7 +2581,116 @@ mod tests {
 
     #[test]
     fn test_settings_load() {
-        let result = Settings::new();
+        let result = Settings::load(&SettingsSource::default());
         assert!(result.is_ok() || result.is_err()); // Just test it doesn't panic
     }
+
+    #[test]
+    fn test_overrides_win_over_file() {
+        let source = SettingsSource {
+            overrides: vec![
+                ("sftp.port".to_string(), "2299".to_string()),
+                ("sftp.root_dir".to_string(), "/srv/sftp".to_string()),
+            ],
+            ..Default::default()
+        };
+        let settings = Settings::load(&source).unwrap();
+        assert_eq!(settings.sftp.port, 2299);
+        assert_eq!(settings.sftp.root_dir, "/srv/sftp");
+    }
+
+    #[test]
+    fn test_password_file_fills_url() {
+        let path = std::env::temp_dir()
+            .join(format!("sftp-manager-db-password-{}", std::process::id()));
+        std::fs::write(&path, "s3cr@t\n").unwrap();
+
+        let source = SettingsSource {
+            overrides: vec![
+                (
+                    "database.url".to_string(),
+                    "postgres://app@db:5432/sftp".to_string(),
+                ),
+                (
+                    "database.password_file".to_string(),
+                    path.display().to_string(),
+                ),
+            ],
+            ..Default::default()
+        };
+        let settings = Settings::load(&source).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            settings.database.url,
+            "postgres://app:s3cr%40t@db:5432/sftp"
+        );
+    }
+
+    #[test]
+    fn test_external_address_per_listener() {
+        let mut settings = Settings::default();
+        settings.instances.push(InstanceSettings {
+            name: "partner".to_string(),
+            port: 2223,
+            bind_addrs: default_bind_addrs(),
+            external_host: None,
+            external_port: Some(2023),
+            root_dir: "/srv/partner".to_string(),
+            api_keys: Vec::new(),
+        });
+        assert_eq!(settings.external_address(2222), None);
+
+        settings.sftp.external_host = Some("sftp.example.com".to_string());
+        settings.sftp.external_port = Some(22);
+        assert_eq!(
+            settings.external_address(2222),
+            Some(("sftp.example.com".to_string(), 22))
+        );
+        assert_eq!(
+            settings.external_address(2223),
+            Some(("sftp.example.com".to_string(), 2023))
+        );
+        assert_eq!(settings.external_address(2224), None);
+    }
+
+    #[test]
+    fn test_redacted_settings_hold_no_secrets() {
+        let mut settings = Settings::default();
+        settings.database.url = "postgres://app:db-secret@db/sftp".to_string();
+        settings.redis.url = "redis://:redis-secret@cache:6379".to_string();
+        settings.s3.secret_access_key = "s3-secret".to_string().into();
+        settings.event_stream.password = Some("nats-secret".to_string().into());
+        settings.webhooks.endpoints.push(WebhookEndpoint {
+            url: "https://hooks.example.com".to_string(),
+            secret: Some("hook-secret".to_string().into()),
+            secret_file: None,
+            events: Vec::new(),
+        });
+        settings.tenants.push(TenantSettings {
+            name: "acme".to_string(),
+            root_dir: "/srv/acme".to_string(),
+            quota_bytes: 0,
+            api_keys: vec!["tenant-secret-key".to_string().into()],
+            instance: None,
+        });
+        settings.server.admin_api_keys =
+            vec!["admin-secret-key".to_string().into()];
+
+        let json = serde_json::to_string(&settings.redacted()).unwrap();
+        for secret in [
+            "db-secret",
+            "redis-secret",
+            "s3-secret",
+            "nats-secret",
+            "hook-secret",
+            "tenant-secret-key",
+            "admin-secret-key",
+        ] {
+            assert!(!json.contains(secret), "{} in {}", secret, json);
+        }
+        let redacted = settings.redacted();
+        assert_eq!(redacted.database.url, "postgres://app@db/sftp");
+        assert_eq!(redacted.tenants[0].api_keys[0].expose(), REDACTED);
+    }
 }
@@ -0,0 +1,1558 @@
+use crate::config::settings::{
+    ConnectionHookAction, EventStreamKind, FileIoBackend, LogRotation,
+    MirrorKind, PipelineAction, PipelineFailure, PortMappingProtocol, Settings,
+    SmtpTls, SyslogTransport,
+};
+use crate::schedule::{CronExpr, Schedule};
+use crate::sftp::LandingRules;
+use crate::sftp::scratch::SCRATCH_DIR;
+use crate::sftp::trash::TRASH_DIR;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::net::{IpAddr, TcpListener, ToSocketAddrs};
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+// A single problem found in the configuration
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigIssue {
+    pub field: String,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl ConfigIssue {
+    pub fn error(field: &str, message: impl Into<String>) -> Self {
+        Self {
+            field: field.to_string(),
+            severity: Severity::Error,
+            message: message.into(),
+        }
+    }
+
+    pub fn warning(field: &str, message: impl Into<String>) -> Self {
+        Self {
+            field: field.to_string(),
+            severity: Severity::Warning,
+            message: message.into(),
+        }
+    }
+}
+
+// Ports that are already bound by this process and must not be re-checked.
+// `static_only` limits validation to the settings themselves, without
+// looking at files, resolving hosts or binding ports, for configurations
+// posted through the API rather than read from this host's files.
+#[derive(Debug, Default)]
+pub struct ValidationContext {
+    pub bound_ports: Vec<u16>,
+    pub static_only: bool,
+}
+
+// Check a configuration and return every problem found
+pub fn validate(
+    settings: &Settings,
+    context: &ValidationContext,
+) -> Vec<ConfigIssue> {
+    let mut issues = Vec::new();
+
+    validate_root_dir(
+        "sftp.root_dir",
+        &settings.sftp.root_dir,
+        context,
+        &mut issues,
+    );
+    validate_ports(settings, context, &mut issues);
+    validate_limits(settings, &mut issues);
+    validate_instances(settings, context, &mut issues);
+    validate_tenants(settings, context, &mut issues);
+    validate_external_address(
+        "sftp",
+        settings.sftp.external_host.as_deref(),
+        settings.sftp.external_port,
+        settings.sftp.external_host.is_some(),
+        &mut issues,
+    );
+    for (i, instance) in settings.instances.iter().enumerate() {
+        validate_external_address(
+            &format!("instances[{}]", i),
+            instance.external_host.as_deref(),
+            instance.external_port,
+            instance.external_host.is_some()
+                || settings.sftp.external_host.is_some(),
+            &mut issues,
+        );
+    }
+    if settings.server.max_body_bytes == 0 {
+        issues
+            .push(ConfigIssue::error("server.max_body_bytes", "must not be 0"));
+    }
+    validate_server_tls(settings, context, &mut issues);
+    validate_admin_access(settings, &mut issues);
+    validate_ftps(settings, context, &mut issues);
+    validate_s3(settings, &mut issues);
+    validate_grpc(settings, &mut issues);
+    validate_mirror(settings, context, &mut issues);
+    validate_event_stream(settings, &mut issues);
+    validate_backup(settings, context, &mut issues);
+    validate_port_mapping(settings, &mut issues);
+    validate_geoip(settings, context, &mut issues);
+    validate_recording(settings, &mut issues);
+    validate_delivery(settings, &mut issues);
+
+    // DNS-SD instance names are a single DNS label
+    if settings.mdns.enabled && settings.mdns.instance_name.len() > 63 {
+        issues.push(ConfigIssue::error(
+            "mdns.instance_name",
+            "must be at most 63 bytes long",
+        ));
+    }
+
+    let listing_cache = &settings.listing_cache;
+    if listing_cache.enabled {
+        if listing_cache.ttl_ms == 0 {
+            issues.push(ConfigIssue::error(
+                "listing_cache.ttl_ms",
+                "must be greater than 0",
+            ));
+        }
+        if listing_cache.max_entries == 0 {
+            issues.push(ConfigIssue::error(
+                "listing_cache.max_entries",
+                "must be greater than 0",
+            ));
+        }
+    }
+
+    let completion = &settings.completion;
+    for suffix in &completion.partial_suffixes {
+        if suffix.is_empty() || suffix.contains('/') {
+            issues.push(ConfigIssue::error(
+                "completion.partial_suffixes",
+                format!("'{}' is not a file name suffix", suffix),
+            ));
+        }
+    }
+    if completion.stable_secs > 3600 {
+        issues.push(ConfigIssue::error(
+            "completion.stable_secs",
+            "must be at most 3600",
+        ));
+    }
+
+    // The scratch directory shadows whatever the root has at its path
+    let scratch = &settings.scratch.path;
+    let parts: Vec<&str> =
+        scratch.split('/').filter(|part| !part.is_empty()).collect();
+    if settings.scratch.enabled
+        && (!scratch.starts_with('/')
+            || parts.is_empty()
+            || parts.iter().any(|part| matches!(*part, "." | "..")))
+    {
+        issues.push(ConfigIssue::error(
+            "scratch.path",
+            format!("'{}' must be an absolute path below the root", scratch),
+        ));
+    } else if settings.scratch.enabled
+        && matches!(parts.first(), Some(&(TRASH_DIR | SCRATCH_DIR)))
+    {
+        issues.push(ConfigIssue::error(
+            "scratch.path",
+            format!("'{}' is reserved", parts[0]),
+        ));
+    }
+
+    let landing = &settings.landing;
+    for dir in &landing.dirs {
+        if !dir.starts_with('/') {
+            issues.push(ConfigIssue::error(
+                "landing.dirs",
+                format!("'{}' must start with '/'", dir),
+            ));
+        }
+    }
+    // The layout must name a directory below the landing directory
+    let sample =
+        LandingRules { dirs: vec!["/".to_string()], ..landing.rules() }
+            .dated("/file", chrono::Utc::now().date_naive());
+    match sample {
+        Some(path)
+            if !path.split('/').any(|part| part == "..")
+                && path != "//file" => {}
+        _ => issues.push(ConfigIssue::error(
+            "landing.layout",
+            format!("'{}' is not a strftime directory layout", landing.layout),
+        )),
+    }
+
+    for pattern in &settings.filenames.dotfile_allow {
+        if pattern.contains('/') {
+            issues.push(ConfigIssue::error(
+                "filenames.dotfile_allow",
+                format!("'{}' must be a name, not a path", pattern),
+            ));
+        } else if !pattern.starts_with(['.', '*', '?']) {
+            issues.push(ConfigIssue::warning(
+                "filenames.dotfile_allow",
+                format!("'{}' matches no dotfile", pattern),
+            ));
+        }
+    }
+
+    if let Err(e) = Schedule::parse(&settings.schedule.windows) {
+        issues.push(ConfigIssue::error("schedule.windows", e));
+    }
+
+    for (i, endpoint) in settings.webhooks.endpoints.iter().enumerate() {
+        if let Err(e) = reqwest::Url::parse(&endpoint.url) {
+            issues.push(ConfigIssue::error(
+                &format!("webhooks.endpoints[{}].url", i),
+                format!("invalid URL '{}': {}", endpoint.url, e),
+            ));
+        }
+        if endpoint.secret.is_none() {
+            issues.push(ConfigIssue::warning(
+                &format!("webhooks.endpoints[{}].secret", i),
+                "payloads will be sent unsigned",
+            ));
+        }
+    }
+
+    issues
+}
+
+// Whether any issue prevents the configuration from being used
+pub fn has_errors(issues: &[ConfigIssue]) -> bool {
+    issues.iter().any(|i| i.severity == Severity::Error)
+}
+
+fn validate_root_dir(
+    field: &str,
+    root_dir: &str,
+    context: &ValidationContext,
+    issues: &mut Vec<ConfigIssue>,
+) {
+    if root_dir.is_empty() {
+        issues.push(ConfigIssue::error(field, "must be set"));
+        return;
+    }
+    if context.static_only {
+        return;
+    }
+    let path = Path::new(root_dir);
+
+    if !path.exists() {
+        issues.push(ConfigIssue::error(
+            field,
+            format!("'{}' does not exist", root_dir),
+        ));
+        return;
+    }
+    if !path.is_dir() {
+        issues.push(ConfigIssue::error(
+            field,
+            format!("'{}' is not a directory", root_dir),
+        ));
+        return;
+    }
+
+    // Probe writability by creating and removing a marker file
+    let probe = path.join(".sftp-manager-write-test");
+    match std::fs::write(&probe, b"") {
+        Ok(()) => {
+            let _ = std::fs::remove_file(&probe);
+        }
+        Err(e) => issues.push(ConfigIssue::error(
+            field,
+            format!("'{}' is not writable: {}", root_dir, e),
+        )),
+    }
+}
+
+fn validate_ports(
+    settings: &Settings,
+    context: &ValidationContext,
+    issues: &mut Vec<ConfigIssue>,
+) {
+    if settings.server.port == settings.sftp.port {
+        issues.push(ConfigIssue::error(
+            "sftp.port",
+            format!(
+                "port {} is also used by the HTTP server",
+                settings.sftp.port
+            ),
+        ));
+    }
+
+    let candidates = [
+        (
+            "server.port",
+            "server.host",
+            &settings.server.host,
+            settings.server.port,
+        ),
+        (
+            "sftp.port",
+            "sftp.bind_addrs",
+            &settings.sftp.bind_addrs,
+            settings.sftp.port,
+        ),
+    ];
+    for (field, host_field, host, port) in candidates {
+        if port == 0 {
+            issues.push(ConfigIssue::error(field, "port must not be 0"));
+            continue;
+        }
+        if context.static_only || context.bound_ports.contains(&port) {
+            continue;
+        }
+
+        let resolved = (host.as_str(), port).to_socket_addrs();
+        let addr = match resolved.map(|mut a| a.next()) {
+            Ok(Some(addr)) => addr,
+            Ok(None) | Err(_) => {
+                issues.push(ConfigIssue::error(
+                    host_field,
+                    format!("cannot resolve '{}'", host),
+                ));
+                continue;
+            }
+        };
+        if let Err(e) = TcpListener::bind(addr) {
+            issues.push(ConfigIssue::error(
+                field,
+                format!("cannot bind {}: {}", addr, e),
+            ));
+        }
+    }
+}
+
+fn validate_limits(settings: &Settings, issues: &mut Vec<ConfigIssue>) {
+    let sftp = &settings.sftp;
+
+    if sftp.auth_failure_threshold > 0 && sftp.auth_failure_window_secs == 0 {
+        issues.push(ConfigIssue::error(
+            "sftp.auth_failure_window_secs",
+            "must be greater than 0 when auth_failure_threshold is set",
+        ));
+    }
+    if sftp.auth_failure_ban_secs > 0 && sftp.auth_failure_ip_threshold == 0 {
+        issues.push(ConfigIssue::warning(
+            "sftp.auth_failure_ban_secs",
+            "no IP is banned while auth_failure_ip_threshold is 0",
+        ));
+    }
+    if sftp.tarpit {
+        if sftp.auth_failure_ban_secs == 0 {
+            issues.push(ConfigIssue::warning(
+                "sftp.tarpit",
+                "has no effect while auth_failure_ban_secs is 0",
+            ));
+        }
+        if sftp.tarpit_delay_ms == 0 {
+            issues.push(ConfigIssue::error(
+                "sftp.tarpit_delay_ms",
+                "must be greater than 0",
+            ));
+        }
+    }
+    if sftp.disk_reserve_mb > 0 && sftp.disk_warning_mb <= sftp.disk_reserve_mb
+    {
+        issues.push(ConfigIssue::warning(
+            "sftp.disk_warning_mb",
+            "the disk is only reported as low once writes are refused; \
+             set it above disk_reserve_mb",
+        ));
+    }
+    if sftp.self_check_timeout_ms == 0 {
+        issues.push(ConfigIssue::error(
+            "sftp.self_check_timeout_ms",
+            "must be greater than 0",
+        ));
+    }
+    if sftp.check_interval_secs == 0 {
+        issues.push(ConfigIssue::error(
+            "sftp.check_interval_secs",
+            "must be greater than 0",
+        ));
+    }
+    if sftp.restart_backoff_secs == 0 {
+        issues.push(ConfigIssue::error(
+            "sftp.restart_backoff_secs",
+            "must be greater than 0",
+        ));
+    }
+    if sftp.restart_backoff_max_secs < sftp.restart_backoff_secs {
+        issues.push(ConfigIssue::error(
+            "sftp.restart_backoff_max_secs",
+            "must not be less than restart_backoff_secs",
+        ));
+    }
+    // SSH requires room for 32 KiB payloads; OpenSSH caps SFTP messages
+    // at 256 KiB, and clients give up on longer ones
+    let sizes: [(&str, u64, u64, u64); 6] = [
+        ("window_bytes", sftp.window_bytes as u64, 32 * 1024, u32::MAX as u64),
+        (
+            "max_packet_bytes",
+            sftp.max_packet_bytes as u64,
+            32 * 1024,
+            256 * 1024,
+        ),
+        ("max_read_bytes", sftp.max_read_bytes as u64, 1024, 255 * 1024),
+        ("max_write_bytes", sftp.max_write_bytes as u64, 1024, 255 * 1024),
+        (
+            "transfer_buffer_bytes",
+            sftp.transfer_buffer_bytes as u64,
+            4096,
+            16 * 1024 * 1024,
+        ),
+        ("read_ahead_chunks", sftp.read_ahead_chunks as u64, 0, 64),
+    ];
+    for (name, value, min, max) in sizes {
+        if !(min..=max).contains(&value) {
+            issues.push(ConfigIssue::error(
+                &format!("sftp.{}", name),
+                format!("must be between {} and {}", min, max),
+            ));
+        }
+    }
+    if sftp.keepalive_interval_secs > 0 && sftp.keepalive_max_missed == 0 {
+        issues.push(ConfigIssue::error(
+            "sftp.keepalive_max_missed",
+            "must be greater than 0 when keepalives are sent",
+        ));
+    }
+    if sftp.window_bytes < sftp.max_packet_bytes {
+        issues.push(ConfigIssue::error(
+            "sftp.window_bytes",
+            "must not be less than max_packet_bytes",
+        ));
+    }
+    let read_ahead_bytes =
+        sftp.read_ahead_chunks * sftp.max_read_bytes as usize;
+    if sftp.session_memory_mb > 0
+        && sftp.session_memory_mb.saturating_mul(1024 * 1024) < read_ahead_bytes
+    {
+        issues.push(ConfigIssue::warning(
+            "sftp.session_memory_mb",
+            "is below the read-ahead of one download, which is left out",
+        ));
+    }
+    for (name, patterns) in [
+        ("allowed_clients", &sftp.allowed_clients),
+        ("denied_clients", &sftp.denied_clients),
+    ] {
+        for (i, pattern) in patterns.iter().enumerate() {
+            if !pattern.starts_with("SSH-") && !pattern.starts_with('*') {
+                issues.push(ConfigIssue::error(
+                    &format!("sftp.{}[{}]", name, i),
+                    "must start with \"SSH-\" or \"*\"",
+                ));
+            }
+        }
+    }
+    for (i, rule) in sftp.path_rules.iter().enumerate() {
+        if let Err(e) = rule.validate() {
+            issues.push(ConfigIssue::error(
+                &format!("sftp.path_rules[{}].pattern", i),
+                e,
+            ));
+        }
+    }
+    if sftp.file_io == FileIoBackend::IoUring
+        && !cfg!(all(target_os = "linux", feature = "io-uring"))
+    {
+        issues.push(ConfigIssue::warning(
+            "sftp.file_io",
+            "io_uring is not built in, tokio is used",
+        ));
+    }
+    if settings.journal.enabled && settings.journal.max_file_bytes < 4096 {
+        issues.push(ConfigIssue::error(
+            "journal.max_file_bytes",
+            "must be at least 4096",
+        ));
+    }
+    let logging = &settings.logging;
+    if !logging.file.is_empty() {
+        if logging.max_files == 0 {
+            issues.push(ConfigIssue::error(
+                "logging.max_files",
+                "must be greater than 0",
+            ));
+        }
+        if logging.rotation == LogRotation::Size
+            && logging.max_file_bytes < 4096
+        {
+            issues.push(ConfigIssue::error(
+                "logging.max_file_bytes",
+                "must be at least 4096",
+            ));
+        }
+    }
+    let syslog = &logging.syslog;
+    if syslog.transport != SyslogTransport::None && syslog.address.is_empty() {
+        issues.push(ConfigIssue::error(
+            "logging.syslog.address",
+            "must be set when a transport is selected",
+        ));
+    }
+    if !settings.scan.clamd_address.is_empty()
+        && settings.scan.timeout_secs == 0
+    {
+        issues.push(ConfigIssue::error(
+            "scan.timeout_secs",
+            "must be greater than 0",
+        ));
+    }
+    if settings.watcher.enabled && settings.watcher.debounce_ms < 10 {
+        issues.push(ConfigIssue::error(
+            "watcher.debounce_ms",
+            "must be at least 10",
+        ));
+    }
+    if settings.search.enabled {
+        if !settings.watcher.enabled {
+            issues.push(ConfigIssue::warning(
+                "search.enabled",
+                "without watcher.enabled the index only changes on rescans",
+            ));
+        }
+        if settings.search.rescan_interval_secs == 0 {
+            issues.push(ConfigIssue::error(
+                "search.rescan_interval_secs",
+                "must be greater than 0",
+            ));
+        }
+    }
+    if settings.integrity.enabled {
+        if let Err(e) = CronExpr::parse(&settings.integrity.schedule) {
+            issues.push(ConfigIssue::error("integrity.schedule", e));
+        }
+        if settings.database.url.is_empty() {
+            issues.push(ConfigIssue::warning(
+                "integrity.enabled",
+                "checksums are kept in the database, which is disabled",
+            ));
+        }
+    }
+    let retention = &settings.retention;
+    if !retention.rules.is_empty() && retention.interval_secs == 0 {
+        issues.push(ConfigIssue::error(
+            "retention.interval_secs",
+            "must be greater than 0",
+        ));
+    }
+    for (i, rule) in retention.rules.iter().enumerate() {
+        let field = |name: &str| format!("retention.rules[{}].{}", i, name);
+        if leaves_root(&rule.path) {
+            issues.push(ConfigIssue::error(
+                &field("path"),
+                "must not leave the SFTP root",
+            ));
+        }
+        if rule.max_age_days == 0
+            && rule.max_files == 0
+            && rule.max_total_bytes == 0
+        {
+            issues.push(ConfigIssue::warning(
+                &field("path"),
+                "no limit is set, so nothing is ever removed",
+            ));
+        }
+    }
+    for (i, rule) in settings.pipeline.rules.iter().enumerate() {
+        let field = |name: &str| format!("pipeline.rules[{}].{}", i, name);
+        if rule.name.trim().is_empty() {
+            issues
+                .push(ConfigIssue::error(&field("name"), "must not be empty"));
+        }
+        if rule.actions.is_empty() {
+            issues.push(ConfigIssue::warning(
+                &field("actions"),
+                "no actions, matching uploads are left alone",
+            ));
+        }
+        if rule.on_failure == PipelineFailure::Move {
+            if rule.failed_dir.is_empty() {
+                issues.push(ConfigIssue::error(
+                    &field("failed_dir"),
+                    "required when on_failure is \"move\"",
+                ));
+            } else if leaves_root(&rule.failed_dir) {
+                issues.push(ConfigIssue::error(
+                    &field("failed_dir"),
+                    "must not leave the SFTP root",
+                ));
+            }
+        }
+        for (j, action) in rule.actions.iter().enumerate() {
+            let field = |name: &str| {
+                format!("pipeline.rules[{}].actions[{}].{}", i, j, name)
+            };
+            match action {
+                PipelineAction::Move { target } if leaves_root(target) => {
+                    issues.push(ConfigIssue::error(
+                        &field("target"),
+                        "must not leave the SFTP root",
+                    ));
+                }
+                PipelineAction::Command { command, timeout_secs } => {
+                    if command.is_empty() {
+                        issues.push(ConfigIssue::error(
+                            &field("command"),
+                            "must not be empty",
+                        ));
+                    }
+                    if *timeout_secs == 0 {
+                        issues.push(ConfigIssue::error(
+                            &field("timeout_secs"),
+                            "must be greater than 0",
+                        ));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    for (i, hook) in settings.connection_hooks.hooks.iter().enumerate() {
+        let field =
+            |name: &str| format!("connection_hooks.hooks[{}].{}", i, name);
+        match &hook.action {
+            ConnectionHookAction::Webhook { url, .. } => {
+                if let Err(e) = reqwest::Url::parse(url) {
+                    issues.push(ConfigIssue::error(
+                        &field("url"),
+                        format!("invalid URL '{}': {}", url, e),
+                    ));
+                }
+            }
+            ConnectionHookAction::Command { command, timeout_secs } => {
+                if command.is_empty() {
+                    issues.push(ConfigIssue::error(
+                        &field("command"),
+                        "must not be empty",
+                    ));
+                }
+                if *timeout_secs == 0 {
+                    issues.push(ConfigIssue::error(
+                        &field("timeout_secs"),
+                        "must be greater than 0",
+                    ));
+                }
+            }
+        }
+    }
+    if settings.database.pool_size == 0 {
+        issues.push(ConfigIssue::error(
+            "database.pool_size",
+            "must be greater than 0",
+        ));
+    }
+    if settings.webhooks.timeout_secs == 0 {
+        issues.push(ConfigIssue::error(
+            "webhooks.timeout_secs",
+            "must be greater than 0",
+        ));
+    }
+    if settings.webhooks.max_retries > 10 {
+        issues.push(ConfigIssue::warning(
+            "webhooks.max_retries",
+            "retries back off exponentially; more than 10 delays delivery \
+             by over half an hour",
+        ));
+    }
+}
+
+// Whether a path below the SFTP root climbs out of it
+fn leaves_root(path: &str) -> bool {
+    Path::new(path).components().any(|c| matches!(c, Component::ParentDir))
+}
+
+fn validate_instances(
+    settings: &Settings,
+    context: &ValidationContext,
+    issues: &mut Vec<ConfigIssue>,
+) {
+    let mut names = vec!["default"];
+    let mut ports = vec![settings.server.port, settings.sftp.port];
+
+    for (i, instance) in settings.instances.iter().enumerate() {
+        let field = |name: &str| format!("instances[{}].{}", i, name);
+
+        let valid_name = !instance.name.is_empty()
+            && instance
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid_name {
+            issues.push(ConfigIssue::error(
+                &field("name"),
+                "must be letters, digits, '-' or '_'",
+            ));
+        } else if names.contains(&instance.name.as_str()) {
+            issues.push(ConfigIssue::error(
+                &field("name"),
+                format!("'{}' is already used", instance.name),
+            ));
+        }
+        names.push(&instance.name);
+
+        // Busy ports are reported by the instance's restart policy
+        if instance.port == 0 || ports.contains(&instance.port) {
+            issues.push(ConfigIssue::error(
+                &field("port"),
+                format!("port {} is 0 or already used", instance.port),
+            ));
+        }
+        ports.push(instance.port);
+
+        validate_root_dir(
+            &field("root_dir"),
+            &instance.root_dir,
+            context,
+            issues,
+        );
+
+        for key in &instance.api_keys {
+            if key.expose().len() < 16 {
+                issues.push(ConfigIssue::error(
+                    &field("api_keys"),
+                    "keys must be at least 16 characters long",
+                ));
+            }
+            let elsewhere = settings
+                .tenants
+                .iter()
+                .flat_map(|t| &t.api_keys)
+                .chain(settings.instances[..i].iter().flat_map(|i| &i.api_keys))
+                .any(|other| other.expose() == key.expose());
+            if elsewhere {
+                issues.push(ConfigIssue::error(
+                    &field("api_keys"),
+                    "a key may only belong to one tenant or instance",
+                ));
+            }
+        }
+    }
+}
+
+fn validate_tenants(
+    settings: &Settings,
+    context: &ValidationContext,
+    issues: &mut Vec<ConfigIssue>,
+) {
+    let mut names = HashSet::new();
+    let mut keys = HashSet::new();
+    let mut roots: Vec<(String, PathBuf)> = Vec::new();
+    let sftp_root = absolute(&settings.sftp.root_dir, context);
+
+    for (i, tenant) in settings.tenants.iter().enumerate() {
+        let field = |name: &str| format!("tenants[{}].{}", i, name);
+
+        let valid_name = !tenant.name.is_empty()
+            && tenant
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid_name {
+            issues.push(ConfigIssue::error(
+                &field("name"),
+                "must be letters, digits, '-' or '_'",
+            ));
+        } else if !names.insert(tenant.name.as_str()) {
+            issues.push(ConfigIssue::error(
+                &field("name"),
+                format!("'{}' is already used", tenant.name),
+            ));
+        }
+
+        validate_root_dir(
+            &field("root_dir"),
+            &tenant.root_dir,
+            context,
+            issues,
+        );
+        let root = absolute(&tenant.root_dir, context);
+        for (other, other_root) in &roots {
+            if root.starts_with(other_root) || other_root.starts_with(&root) {
+                issues.push(ConfigIssue::error(
+                    &field("root_dir"),
+                    format!("overlaps the directory of tenant '{}'", other),
+                ));
+            }
+        }
+        if root.starts_with(&sftp_root) {
+            issues.push(ConfigIssue::warning(
+                &field("root_dir"),
+                "inside sftp.root_dir, so the default SFTP server exposes \
+                 the tenant's files",
+            ));
+        }
+        roots.push((tenant.name.clone(), root.clone()));
+
+        if tenant.api_keys.is_empty() {
+            issues.push(ConfigIssue::warning(
+                &field("api_keys"),
+                "no keys, so only admins can use the tenant's API",
+            ));
+        }
+        for key in &tenant.api_keys {
+            if key.expose().len() < 16 {
+                issues.push(ConfigIssue::error(
+                    &field("api_keys"),
+                    "keys must be at least 16 characters long",
+                ));
+            }
+            if !keys.insert(key.expose()) {
+                issues.push(ConfigIssue::error(
+                    &field("api_keys"),
+                    "a key may only belong to one tenant",
+                ));
+            }
+        }
+
+        if let Some(name) = &tenant.instance {
+            match settings.instances.iter().find(|i| &i.name == name) {
+                None => issues.push(ConfigIssue::error(
+                    &field("instance"),
+                    format!("no instance is named '{}'", name),
+                )),
+                Some(instance)
+                    if absolute(&instance.root_dir, context) != root =>
+                {
+                    issues.push(ConfigIssue::error(
+                        &field("instance"),
+                        format!(
+                            "instance '{}' serves '{}' instead of the \
+                             tenant's directory",
+                            name, instance.root_dir
+                        ),
+                    ));
+                }
+                Some(_) => {}
+            }
+        }
+    }
+}
+
+// A directory as an absolute path, resolving links when it exists and
+// the filesystem may be consulted
+fn absolute(dir: &str, context: &ValidationContext) -> PathBuf {
+    let canonical = if context.static_only {
+        Err(std::io::ErrorKind::Unsupported.into())
+    } else {
+        std::fs::canonicalize(dir)
+    };
+    canonical
+        .or_else(|_| std::path::absolute(dir))
+        .unwrap_or_else(|_| PathBuf::from(dir))
+}
+
+// `has_host` tells whether a host applies, possibly inherited from [sftp]
+fn validate_external_address(
+    section: &str,
+    host: Option<&str>,
+    port: Option<u16>,
+    has_host: bool,
+    issues: &mut Vec<ConfigIssue>,
+) {
+    if let Some(host) = host {
+        // A colon is only valid in an IPv6 address
+        let invalid = host.is_empty()
+            || host.contains(|c: char| c.is_whitespace() || c == '/')
+            || (host.contains(':') && host.parse::<IpAddr>().is_err());
+        if invalid {
+            issues.push(ConfigIssue::error(
+                &format!("{}.external_host", section),
+                format!("'{}' is not a host name or IP address", host),
+            ));
+        }
+    }
+    match port {
+        Some(0) => issues.push(ConfigIssue::error(
+            &format!("{}.external_port", section),
+            "must not be 0",
+        )),
+        Some(_) if !has_host => issues.push(ConfigIssue::warning(
+            &format!("{}.external_port", section),
+            "ignored without an external_host",
+        )),
+        _ => {}
+    }
+}
+
+// Scoped API keys lock down the API, so admins need a credential of
+// their own: an admin key or a client certificate
+fn validate_admin_access(settings: &Settings, issues: &mut Vec<ConfigIssue>) {
+    for key in &settings.server.admin_api_keys {
+        if key.expose().len() < 16 {
+            issues.push(ConfigIssue::error(
+                "server.admin_api_keys",
+                "keys must be at least 16 characters long",
+            ));
+        }
+        let scoped = settings
+            .tenants
+            .iter()
+            .flat_map(|t| &t.api_keys)
+            .chain(settings.instances.iter().flat_map(|i| &i.api_keys))
+            .any(|other| other.expose() == key.expose());
+        if scoped {
+            issues.push(ConfigIssue::error(
+                "server.admin_api_keys",
+                "a key may not also belong to a tenant or instance",
+            ));
+        }
+    }
+
+    if settings.has_scoped_api_keys()
+        && settings.server.admin_api_keys.is_empty()
+        && !settings.verifies_client_certificates()
+    {
+        issues.push(ConfigIssue::error(
+            "server.admin_api_keys",
+            "required with tenant or instance API keys unless client \
+             certificates are verified",
+        ));
+    }
+}
+
+fn validate_server_tls(
+    settings: &Settings,
+    context: &ValidationContext,
+    issues: &mut Vec<ConfigIssue>,
+) {
+    let tls = &settings.server.tls;
+    if !tls.enabled {
+        return;
+    }
+
+    for (field, file) in [
+        ("server.tls.certificate_file", &tls.certificate_file),
+        ("server.tls.private_key_file", &tls.private_key_file),
+    ] {
+        if file.is_empty() {
+            issues.push(ConfigIssue::error(field, "required for HTTPS"));
+        } else if !context.static_only
+            && let Err(e) = std::fs::metadata(file)
+        {
+            issues.push(ConfigIssue::error(
+                field,
+                format!("cannot read '{}': {}", file, e),
+            ));
+        }
+    }
+
+    if tls.client_ca_file.is_empty() {
+        if !tls.client_roles.is_empty() || tls.default_role.is_some() {
+            issues.push(ConfigIssue::warning(
+                "server.tls.client_roles",
+                "ignored without client_ca_file",
+            ));
+        }
+        return;
+    }
+    if !context.static_only
+        && let Err(e) = std::fs::metadata(&tls.client_ca_file)
+    {
+        issues.push(ConfigIssue::error(
+            "server.tls.client_ca_file",
+            format!("cannot read '{}': {}", tls.client_ca_file, e),
+        ));
+    }
+    for (i, mapping) in tls.client_roles.iter().enumerate() {
+        if mapping.normalized_fingerprint().is_none() {
+            issues.push(ConfigIssue::error(
+                &format!("server.tls.client_roles[{}].fingerprint", i),
+                "must be a SHA-256 fingerprint of 64 hex digits",
+            ));
+        }
+    }
+}
+
+fn validate_ftps(
+    settings: &Settings,
+    context: &ValidationContext,
+    issues: &mut Vec<ConfigIssue>,
+) {
+    let ftps = &settings.ftps;
+    if !ftps.enabled {
+        return;
+    }
+
+    let mut ports = vec![settings.server.port, settings.sftp.port];
+    ports.extend(settings.instances.iter().map(|i| i.port));
+    if ftps.port == 0 || ports.contains(&ftps.port) {
+        issues.push(ConfigIssue::error(
+            "ftps.port",
+            format!("port {} is 0 or already used", ftps.port),
+        ));
+    }
+    if ftps.passive_port_min == 0
+        || ftps.passive_port_min > ftps.passive_port_max
+    {
+        issues.push(ConfigIssue::error(
+            "ftps.passive_port_min",
+            "must be between 1 and passive_port_max",
+        ));
+    } else if (ftps.passive_port_min..=ftps.passive_port_max)
+        .contains(&ftps.port)
+    {
+        issues.push(ConfigIssue::error(
+            "ftps.port",
+            "must not be inside the passive port range",
+        ));
+    }
+
+    for (field, file) in [
+        ("ftps.certificate_file", &ftps.certificate_file),
+        ("ftps.private_key_file", &ftps.private_key_file),
+    ] {
+        if file.is_empty() {
+            issues.push(ConfigIssue::error(field, "required for FTPS"));
+        } else if !context.static_only
+            && let Err(e) = std::fs::metadata(file)
+        {
+            issues.push(ConfigIssue::error(
+                field,
+                format!("cannot read '{}': {}", file, e),
+            ));
+        }
+    }
+
+    if let Some(address) = &ftps.passive_address
+        && address.parse::<std::net::Ipv4Addr>().is_err()
+    {
+        issues.push(ConfigIssue::error(
+            "ftps.passive_address",
+            format!("'{}' is not an IPv4 address", address),
+        ));
+    }
+}
+
+fn validate_s3(settings: &Settings, issues: &mut Vec<ConfigIssue>) {
+    let s3 = &settings.s3;
+    if !s3.enabled {
+        return;
+    }
+
+    let mut ports = vec![settings.server.port, settings.sftp.port];
+    ports.extend(settings.instances.iter().map(|i| i.port));
+    if settings.ftps.enabled {
+        ports.push(settings.ftps.port);
+    }
+    if s3.port == 0 || ports.contains(&s3.port) {
+        issues.push(ConfigIssue::error(
+            "s3.port",
+            format!("port {} is 0 or already used", s3.port),
+        ));
+    }
+
+    // Bucket names appear unencoded in request paths
+    let valid_bucket = (3..=63).contains(&s3.bucket.len())
+        && s3.bucket.bytes().all(|b| {
+            b.is_ascii_lowercase() || b.is_ascii_digit() || b"-.".contains(&b)
+        });
+    if !valid_bucket {
+        issues.push(ConfigIssue::error(
+            "s3.bucket",
+            "must be 3 to 63 lowercase letters, digits, '-' or '.'",
+        ));
+    }
+
+    for (field, value) in [
+        ("s3.access_key_id", s3.access_key_id.as_str()),
+        ("s3.secret_access_key", s3.secret_access_key.expose()),
+    ] {
+        if value.is_empty() {
+            issues.push(ConfigIssue::error(field, "required for S3"));
+        }
+    }
+}
+
+fn validate_grpc(settings: &Settings, issues: &mut Vec<ConfigIssue>) {
+    let grpc = &settings.grpc;
+    if !grpc.enabled {
+        return;
+    }
+
+    let mut ports = vec![settings.server.port, settings.sftp.port];
+    ports.extend(settings.instances.iter().map(|i| i.port));
+    if settings.ftps.enabled {
+        ports.push(settings.ftps.port);
+    }
+    if settings.s3.enabled {
+        ports.push(settings.s3.port);
+    }
+    if grpc.port == 0 || ports.contains(&grpc.port) {
+        issues.push(ConfigIssue::error(
+            "grpc.port",
+            format!("port {} is 0 or already used", grpc.port),
+        ));
+    }
+    if grpc.host.parse::<IpAddr>().is_err() {
+        issues.push(ConfigIssue::error(
+            "grpc.host",
+            format!("'{}' is not an IP address", grpc.host),
+        ));
+    }
+}
+
+fn validate_recording(settings: &Settings, issues: &mut Vec<ConfigIssue>) {
+    let recording = &settings.recording;
+    if !recording.enabled {
+        return;
+    }
+    if recording.dir.is_empty() {
+        issues.push(ConfigIssue::error("recording.dir", "must not be empty"));
+    }
+    if recording.include_data {
+        issues.push(ConfigIssue::warning(
+            "recording.include_data",
+            "recordings keep a copy of every file transferred",
+        ));
+    }
+}
+
+fn validate_geoip(
+    settings: &Settings,
+    context: &ValidationContext,
+    issues: &mut Vec<ConfigIssue>,
+) {
+    let geoip = &settings.geoip;
+    for (field, codes) in [
+        ("geoip.allow_countries", &geoip.allow_countries),
+        ("geoip.deny_countries", &geoip.deny_countries),
+    ] {
+        for code in codes {
+            if code.len() != 2 || !code.bytes().all(|b| b.is_ascii_alphabetic())
+            {
+                issues.push(ConfigIssue::error(
+                    field,
+                    format!("'{}' is not a two-letter country code", code),
+                ));
+            }
+        }
+    }
+    for code in &geoip.allow_countries {
+        if geoip.deny_countries.iter().any(|d| d.eq_ignore_ascii_case(code)) {
+            issues.push(ConfigIssue::warning(
+                "geoip.allow_countries",
+                format!("{} is also denied, so it is turned away", code),
+            ));
+        }
+    }
+
+    let lists =
+        !geoip.allow_countries.is_empty() || !geoip.deny_countries.is_empty();
+    if geoip.database_file.is_empty() {
+        if lists {
+            issues.push(ConfigIssue::warning(
+                "geoip.database_file",
+                "country lists have no effect without a database",
+            ));
+        }
+    } else if !context.static_only
+        && let Err(e) = std::fs::metadata(&geoip.database_file)
+    {
+        issues.push(ConfigIssue::error(
+            "geoip.database_file",
+            format!("cannot read '{}': {}", geoip.database_file, e),
+        ));
+    }
+}
+
+fn validate_event_stream(settings: &Settings, issues: &mut Vec<ConfigIssue>) {
+    let stream = &settings.event_stream;
+    if !stream.enabled {
+        return;
+    }
+    if stream.brokers.is_empty() {
+        issues.push(ConfigIssue::error(
+            "event_stream.brokers",
+            "at least one broker is required",
+        ));
+    }
+    if stream.subject.is_empty() {
+        issues.push(ConfigIssue::error("event_stream.subject", "must be set"));
+    }
+    if stream.kind == EventStreamKind::Kafka && stream.partition < 0 {
+        issues.push(ConfigIssue::error(
+            "event_stream.partition",
+            "must not be negative",
+        ));
+    }
+    if stream.username.is_empty() != stream.password.is_none() {
+        issues.push(ConfigIssue::error(
+            "event_stream.password",
+            "username and password must be set together",
+        ));
+    }
+}
+
+fn validate_port_mapping(settings: &Settings, issues: &mut Vec<ConfigIssue>) {
+    let mapping = &settings.port_mapping;
+    if !mapping.enabled {
+        return;
+    }
+    if mapping.lease_secs < 120 {
+        issues.push(ConfigIssue::error(
+            "port_mapping.lease_secs",
+            "must be at least 120",
+        ));
+    }
+    if mapping.external_port == Some(0) {
+        issues.push(ConfigIssue::error(
+            "port_mapping.external_port",
+            "must not be 0",
+        ));
+    }
+    if let Some(gateway) = &mapping.gateway {
+        if gateway.parse::<std::net::Ipv4Addr>().is_err() {
+            issues.push(ConfigIssue::error(
+                "port_mapping.gateway",
+                format!("'{}' is not an IPv4 address", gateway),
+            ));
+        }
+        if mapping.protocol == PortMappingProtocol::Upnp {
+            issues.push(ConfigIssue::warning(
+                "port_mapping.gateway",
+                "only used with NAT-PMP; UPnP discovers the router",
+            ));
+        }
+    }
+    if settings.sftp.external_host.is_some() {
+        issues.push(ConfigIssue::warning(
+            "port_mapping.enabled",
+            "sftp.external_host is set, so the mapped address is not \
+             advertised",
+        ));
+    }
+}
+
+fn validate_delivery(settings: &Settings, issues: &mut Vec<ConfigIssue>) {
+    let delivery = &settings.delivery;
+    if let Some(url) = &delivery.slack_webhook_url
+        && !url.expose().starts_with("https://")
+    {
+        issues.push(ConfigIssue::error(
+            "delivery.slack_webhook_url",
+            "must be an https:// URL",
+        ));
+    }
+    if delivery.timeout_secs == 0 {
+        issues.push(ConfigIssue::error(
+            "delivery.timeout_secs",
+            "must be greater than 0",
+        ));
+    }
+
+    let email = &delivery.email;
+    if email.to.is_empty() {
+        return;
+    }
+    // Addresses end up in SMTP commands and mail headers
+    let addresses = email.to.iter().map(|a| ("delivery.email.to", a));
+    for (field, address) in
+        addresses.chain([("delivery.email.from", &email.from)])
+    {
+        if !address.contains('@')
+            || address.contains(['\r', '\n', '<', '>', ' '])
+        {
+            issues.push(ConfigIssue::error(
+                field,
+                format!("'{}' is not an email address", address),
+            ));
+        }
+    }
+    if email.smtp_host.is_empty() {
+        issues.push(ConfigIssue::error(
+            "delivery.email.smtp_host",
+            "must be set when recipients are configured",
+        ));
+    }
+    if email.smtp_tls == SmtpTls::None && email.password.is_some() {
+        issues.push(ConfigIssue::warning(
+            "delivery.email.smtp_tls",
+            "the SMTP password and the mailed credentials are sent in \
+             plain text",
+        ));
+    }
+}
+
+fn validate_backup(
+    settings: &Settings,
+    context: &ValidationContext,
+    issues: &mut Vec<ConfigIssue>,
+) {
+    let backup = &settings.backup;
+    if !backup.enabled {
+        return;
+    }
+    if !backup.schedule.is_empty()
+        && let Err(e) = CronExpr::parse(&backup.schedule)
+    {
+        issues.push(ConfigIssue::error("backup.schedule", e));
+    }
+    if backup.dir.is_empty() {
+        issues.push(ConfigIssue::error("backup.dir", "must be set"));
+    } else if absolute(&backup.dir, context)
+        .starts_with(absolute(&settings.sftp.root_dir, context))
+    {
+        issues.push(ConfigIssue::error(
+            "backup.dir",
+            "must be outside sftp.root_dir, or backups would contain \
+             earlier backups",
+        ));
+    }
+    if !(1..=19).contains(&backup.compression_level) {
+        issues.push(ConfigIssue::error(
+            "backup.compression_level",
+            "must be between 1 and 19",
+        ));
+    }
+    if let Some(target) = &backup.target
+        && !settings.mirror.targets.iter().any(|t| &t.name == target)
+    {
+        issues.push(ConfigIssue::error(
+            "backup.target",
+            format!("no mirror target is named '{}'", target),
+        ));
+    }
+    if backup.keep == 0 {
+        issues.push(ConfigIssue::warning(
+            "backup.keep",
+            "all backups are kept, so they grow without bound",
+        ));
+    }
+}
+
+fn validate_mirror(
+    settings: &Settings,
+    context: &ValidationContext,
+    issues: &mut Vec<ConfigIssue>,
+) {
+    let mirror = &settings.mirror;
+    if !mirror.enabled {
+        return;
+    }
+    if mirror.max_attempts == 0 {
+        issues.push(ConfigIssue::error(
+            "mirror.max_attempts",
+            "must be at least 1",
+        ));
+    }
+
+    let mut names = HashSet::new();
+    for (i, target) in mirror.targets.iter().enumerate() {
+        let field = |name: &str| format!("mirror.targets[{}].{}", i, name);
+        if target.name.is_empty() || !names.insert(target.name.as_str()) {
+            issues.push(ConfigIssue::error(
+                &field("name"),
+                "must be set and unique",
+            ));
+        }
+        let required: &[(&str, bool)] = match target.kind {
+            MirrorKind::Sftp => &[
+                ("host", target.host.is_empty()),
+                ("username", target.username.is_empty()),
+                (
+                    "password",
+                    target.password.is_none()
+                        && target.private_key_file.is_none(),
+                ),
+            ],
+            MirrorKind::S3 => &[
+                ("endpoint", target.endpoint.is_empty()),
+                ("bucket", target.bucket.is_empty()),
+                ("access_key_id", target.access_key_id.is_empty()),
+                ("secret_access_key", target.secret_access_key.is_none()),
+            ],
+        };
+        for (name, missing) in required {
+            if *missing {
+                issues.push(ConfigIssue::error(
+                    &field(name),
+                    "required for this kind of target",
+                ));
+            }
+        }
+        if target.kind == MirrorKind::S3
+            && reqwest::Url::parse(&target.endpoint).is_err()
+        {
+            issues.push(ConfigIssue::error(
+                &field("endpoint"),
+                format!("'{}' is not a valid URL", target.endpoint),
+            ));
+        }
+        if let Some(key_file) = &target.private_key_file
+            && !context.static_only
+            && let Err(e) = std::fs::metadata(key_file)
+        {
+            issues.push(ConfigIssue::error(
+                &field("private_key_file"),
+                format!("cannot read '{}': {}", key_file, e),
+            ));
+        }
+    }
+
+    for (i, rule) in mirror.rules.iter().enumerate() {
+        if !names.contains(rule.target.as_str()) {
+            issues.push(ConfigIssue::error(
+                &format!("mirror.rules[{}].target", i),
+                format!("no target is named '{}'", rule.target),
+            ));
+        }
+        if !rule.glob.starts_with('/') {
+            issues.push(ConfigIssue::error(
+                &format!("mirror.rules[{}].glob", i),
+                "must start with '/'",
+            ));
+        }
+    }
+    if mirror.rules.is_empty() {
+        issues.push(ConfigIssue::warning(
+            "mirror.rules",
+            "no rules, so nothing is mirrored",
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::settings::{InstanceSettings, TenantSettings};
+
+    #[test]
+    fn test_missing_root_and_port_clash_are_reported() {
+        let mut settings = Settings::default();
+        settings.sftp.root_dir = "/nonexistent/sftp-manager-root".to_string();
+        settings.sftp.port = settings.server.port;
+
+        let context = ValidationContext {
+            bound_ports: vec![settings.server.port],
+            ..Default::default()
+        };
+        let issues = validate(&settings, &context);
+
+        assert!(has_errors(&issues));
+        assert!(issues.iter().any(|i| i.field == "sftp.root_dir"));
+        assert!(issues.iter().any(|i| i.field == "sftp.port"));
+    }
+
+    #[test]
+    fn test_tenants_must_not_share_directories_or_keys() {
+        let root = std::env::temp_dir().join("sftp-manager-tenants-test");
+        for dir in ["a", "a/b"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        let tenant = |name: &str, dir: &str| TenantSettings {
+            name: name.to_string(),
+            root_dir: root.join(dir).display().to_string(),
+            quota_bytes: 0,
+            api_keys: vec!["0123456789abcdef".to_string().into()],
+            instance: None,
+        };
+        let mut settings = Settings {
+            tenants: vec![tenant("a", "a"), tenant("b", "a/b")],
+            ..Default::default()
+        };
+        settings.tenants[1].instance = Some("missing".to_string());
+
+        let issues = validate(&settings, &ValidationContext::default());
+        let errors: Vec<&str> = issues
+            .iter()
+            .filter(|i| i.severity == Severity::Error)
+            .map(|i| i.field.as_str())
+            .collect();
+        assert!(errors.contains(&"tenants[1].root_dir"));
+        assert!(errors.contains(&"tenants[1].api_keys"));
+        assert!(errors.contains(&"tenants[1].instance"));
+        assert!(!errors.iter().any(|f| f.starts_with("tenants[0]")));
+        // Nobody could administer the server past the tenants' keys
+        assert!(errors.contains(&"server.admin_api_keys"));
+
+        settings.server.admin_api_keys =
+            vec!["0123456789abcdef".to_string().into()];
+        let issues = validate(&settings, &ValidationContext::default());
+        assert!(issues.iter().any(|i| i.field == "server.admin_api_keys"
+            && i.message.contains("tenant or instance")));
+    }
+
+    #[test]
+    fn test_instances_need_unique_names_and_ports() {
+        let root = std::env::temp_dir().to_string_lossy().to_string();
+        let instance = |name: &str, port: u16| InstanceSettings {
+            name: name.to_string(),
+            port,
+            bind_addrs: "0.0.0.0".to_string(),
+            external_host: None,
+            external_port: None,
+            root_dir: root.clone(),
+            api_keys: Vec::new(),
+        };
+        let mut settings = Settings::default();
+        settings.sftp.root_dir = root.clone();
+        settings.instances = vec![
+            instance("ingest", 2300),
+            instance("default", 2301),
+            instance("ingest", 2302),
+            instance("bad name", 2303),
+            instance("export", 2300),
+        ];
+
+        let issues = validate(&settings, &ValidationContext::default());
+        let errors: Vec<&str> = issues
+            .iter()
+            .filter(|i| i.severity == Severity::Error)
+            .filter(|i| i.field.starts_with("instances"))
+            .map(|i| i.field.as_str())
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                "instances[1].name",
+                "instances[2].name",
+                "instances[3].name",
+                "instances[4].port",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_static_validation_leaves_the_host_alone() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let busy = listener.local_addr().unwrap().port();
+
+        let mut settings = Settings::default();
+        settings.sftp.root_dir = "/nonexistent/sftp-manager-root".to_string();
+        settings.sftp.bind_addrs = "127.0.0.1".to_string();
+        settings.sftp.port = busy;
+        settings.server.tls.enabled = true;
+        settings.server.tls.certificate_file = "/nonexistent/cert.pem".into();
+        settings.server.tls.private_key_file = "/nonexistent/key.pem".into();
+
+        let context =
+            ValidationContext { static_only: true, ..Default::default() };
+        let issues = validate(&settings, &context);
+        assert!(!has_errors(&issues), "{:?}", issues);
+
+        // The same settings read from this host's files are probed
+        let issues = validate(&settings, &ValidationContext::default());
+        let errors: Vec<&str> = issues
+            .iter()
+            .filter(|i| i.severity == Severity::Error)
+            .map(|i| i.field.as_str())
+            .collect();
+        assert!(errors.contains(&"sftp.root_dir"));
+        assert!(errors.contains(&"sftp.port"));
+        assert!(errors.contains(&"server.tls.certificate_file"));
+    }
+}
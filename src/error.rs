@@ -0,0 +1,170 @@
+use std::io;
+
+// Errors of the server startup, the SFTP lifecycle manager and the SFTP
+// and FTPS listeners. A listener that fails to start or stops has its
+// error recorded in the SFTP status; errors at startup are logged and end
+// the process with `exit_code`.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("invalid configuration: {0}")]
+    Config(String),
+
+    #[error("cannot load certificate {file}: {message}")]
+    Certificate { file: String, message: String },
+
+    #[error("TLS setup failed: {0}")]
+    Tls(#[from] tokio_rustls::rustls::Error),
+
+    #[error("cannot bind {address}: {source}")]
+    Bind {
+        address: String,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("cannot use the socket passed by systemd: {0}")]
+    ActivatedSocket(#[source] io::Error),
+
+    #[error("no credentials available")]
+    NoCredentials,
+
+    #[error("cannot generate an SSH host key: {0}")]
+    HostKey(String),
+
+    #[error("cannot load the SSH host key {file}: {message}")]
+    HostKeyFile { file: String, message: String },
+
+    #[error("cannot open log file {file}: {source}")]
+    LogFile {
+        file: String,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("cannot open the event journal: {0}")]
+    Journal(#[source] io::Error),
+
+    #[error("cannot open the database: {0}")]
+    Database(String),
+
+    #[error("Vault failed: {0}")]
+    Vault(String),
+
+    #[error("cannot set up virus scanning: {0}")]
+    VirusScan(String),
+
+    #[error("cannot load the GeoIP database {file}: {message}")]
+    GeoIp { file: String, message: String },
+
+    #[error("cannot open the SFTP state storage: {0}")]
+    StateStorage(String),
+
+    #[error("cannot load the secret key: {0}")]
+    SecretKey(String),
+
+    #[error("cannot re-encrypt the secrets: {0}")]
+    Rekey(String),
+
+    #[error("cannot watch {dir}: {message}")]
+    Watcher { dir: String, message: String },
+
+    #[error("{listener} listener failed: {source}")]
+    Listener {
+        listener: &'static str,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("cannot install the {signal} handler: {source}")]
+    Signal {
+        signal: &'static str,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("server task failed: {0}")]
+    Task(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn bind(address: impl ToString, source: io::Error) -> Self {
+        Self::Bind { address: address.to_string(), source }
+    }
+
+    pub fn listener(listener: &'static str, source: io::Error) -> Self {
+        Self::Listener { listener, source }
+    }
+
+    // Identifies the kind of failure in the SFTP status, so monitoring can
+    // tell a busy port from an expired certificate without parsing reasons
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Config(_) => "config",
+            Error::Certificate { .. } => "certificate",
+            Error::Tls(_) => "tls",
+            Error::Bind { .. } => "bind",
+            Error::ActivatedSocket(_) => "activated_socket",
+            Error::NoCredentials => "no_credentials",
+            Error::HostKey(_) => "host_key",
+            Error::HostKeyFile { .. } => "host_key_file",
+            Error::LogFile { .. } => "log_file",
+            Error::Journal(_) => "journal",
+            Error::Database(_) => "database",
+            Error::Vault(_) => "vault",
+            Error::VirusScan(_) => "virus_scan",
+            Error::GeoIp { .. } => "geoip",
+            Error::StateStorage(_) => "state_storage",
+            Error::SecretKey(_) => "secret_key",
+            Error::Rekey(_) => "rekey",
+            Error::Watcher { .. } => "watcher",
+            Error::Listener { .. } => "listener",
+            Error::Signal { .. } => "signal",
+            Error::Task(_) => "task",
+        }
+    }
+
+    // Exit status of a process that failed to start: EX_CONFIG (78) for
+    // errors retrying cannot fix, so service managers need not restart it,
+    // and 1 otherwise
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Config(_)
+            | Error::Certificate { .. }
+            | Error::Tls(_)
+            | Error::HostKeyFile { .. }
+            | Error::GeoIp { .. }
+            | Error::SecretKey(_) => 78,
+            _ => 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_errors_name_what_failed() {
+        let busy = io::Error::from(io::ErrorKind::AddrInUse);
+        let error = Error::bind("0.0.0.0:2222", busy);
+        assert!(error.to_string().starts_with("cannot bind 0.0.0.0:2222: "));
+        assert_eq!((error.kind(), error.exit_code()), ("bind", 1));
+
+        let error = Error::Certificate {
+            file: "/etc/ftps.pem".to_string(),
+            message: "no such file".to_string(),
+        };
+        assert_eq!(
+            error.to_string(),
+            "cannot load certificate /etc/ftps.pem: no such file"
+        );
+        assert_eq!((error.kind(), error.exit_code()), ("certificate", 78));
+
+        let error = Error::Database("connection refused".to_string());
+        assert_eq!((error.kind(), error.exit_code()), ("database", 1));
+        let error = Error::Config("2 errors".to_string());
+        assert_eq!(error.exit_code(), 78);
+    }
+}
@@ -0,0 +1,346 @@
+use crate::sftp::negotiation::Negotiated;
+use chrono::Utc;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use tokio::sync::broadcast;
+use tracing::debug;
+
+// Significant things that happen inside the manager
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum Event {
+    // An upload is complete: its handle was closed after receiving data,
+    // it was renamed from any partial name and stopped changing
+    FileUploaded {
+        username: String,
+        peer: Option<String>,
+        path: String,
+        bytes: u64,
+        duration_ms: u64,
+        // Hex digests by algorithm name, e.g. "sha256"
+        checksums: BTreeMap<String, String>,
+    },
+    // A file handle was closed after data was read from it
+    FileDownloaded {
+        username: String,
+        peer: Option<String>,
+        path: String,
+        bytes: u64,
+        duration_ms: u64,
+    },
+    // An upload was found infected, or could not be scanned, and was moved
+    // to the quarantine directory
+    FileQuarantined {
+        username: String,
+        peer: Option<String>,
+        path: String,
+        reason: String,
+    },
+    // A file appeared below the SFTP root
+    FileCreated {
+        path: String,
+    },
+    // The contents of a file below the SFTP root changed
+    FileModified {
+        path: String,
+    },
+    // A file or directory below the SFTP root disappeared
+    FileDeleted {
+        path: String,
+    },
+    // An item was moved back out of the trash
+    FileRestored {
+        path: String,
+        // ID the item had in the trash
+        id: String,
+    },
+    // A retention rule removed a file, or would have in a dry run
+    FileExpired {
+        path: String,
+        bytes: u64,
+        // "deleted" or "archived"
+        action: String,
+        // Limit that was exceeded: "max_age", "max_files" or
+        // "max_total_bytes"
+        reason: String,
+        dry_run: bool,
+    },
+    // Every action of a pipeline rule succeeded for an upload
+    PipelineCompleted {
+        rule: String,
+        // Where the file ended up after any move actions
+        path: String,
+        username: String,
+    },
+    // A pipeline action failed for an upload
+    PipelineFailed {
+        rule: String,
+        path: String,
+        username: String,
+        // "move", "webhook", "checksum" or "command"
+        action: String,
+        error: String,
+    },
+    // A file was copied to a mirror target
+    FileMirrored {
+        path: String,
+        target: String,
+        bytes: u64,
+    },
+    // Copying a file to a mirror target failed for the last time and the
+    // job was moved to the failed queue
+    MirrorFailed {
+        path: String,
+        target: String,
+        attempts: u32,
+        error: String,
+    },
+    // An archive was unpacked through the API
+    ArchiveExtracted {
+        path: String,
+        target: String,
+        files: u64,
+        bytes: u64,
+    },
+    // A stored file no longer matches the checksum recorded at upload
+    IntegrityMismatch {
+        path: String,
+        algorithm: String,
+        expected: String,
+        actual: String,
+    },
+    // A run of the integrity verification finished
+    IntegrityCheckCompleted {
+        checked: u64,
+        mismatches: u64,
+        missing: u64,
+    },
+    // A backup archive of the root was written
+    BackupCompleted {
+        archive: String,
+        bytes: u64,
+    },
+    BackupFailed {
+        error: String,
+    },
+    // Credentials reached their expiration time and SFTP was disabled
+    CredentialsExpired {
+        username: Option<String>,
+    },
+    // New credentials replaced the current ones while SFTP stayed enabled
+    CredentialsRotated {
+        username: String,
+    },
+    // Credentials were retrieved through the management API
+    CredentialsAccessed {
+        instance: String,
+        username: String,
+        // "rest" or "grpc"
+        api: String,
+        // Caller's IP address and, behind a proxy, X-Forwarded-For
+        client: Option<String>,
+        forwarded_for: Option<String>,
+        // SHA-256 fingerprint of the caller's client certificate
+        certificate: Option<String>,
+        // Admin, tenant or instance whose API key was used, as
+        // "admin", "tenant:<name>" or "instance:<name>"
+        api_key: Option<String>,
+    },
+    // New credentials were sent out; `channel` is "email" or "slack"
+    CredentialsDelivered {
+        username: String,
+        channel: String,
+    },
+    CredentialDeliveryFailed {
+        username: String,
+        channel: String,
+        error: String,
+    },
+    // Credentials are about to expire
+    CredentialsExpiring {
+        username: Option<String>,
+        expires_at: String,
+        remaining_secs: u64,
+    },
+    // Failed logins crossed the configured threshold within the window
+    AuthFailureSpike {
+        failures: u32,
+        window_secs: u64,
+    },
+    // Failed logins from one IP address crossed the configured threshold
+    AuthFailuresFromIp {
+        ip: String,
+        failures: u32,
+        window_secs: u64,
+        usernames: Vec<String>,
+    },
+    // Failed logins against one username crossed the configured threshold
+    AuthFailuresForUser {
+        username: String,
+        failures: u32,
+        window_secs: u64,
+        ips: Vec<String>,
+    },
+    // SFTP was enabled or disabled through the API
+    ServerToggled {
+        enabled: bool,
+    },
+    // A client authenticated successfully
+    LoginSucceeded {
+        username: String,
+        peer: Option<String>,
+        country: Option<String>,
+        // SSH identification string of the client software
+        client: Option<String>,
+        // e.g. "password"
+        auth_method: String,
+        // Key exchange, host key, cipher and MAC of SFTP sessions
+        negotiated: Option<Negotiated>,
+    },
+    // A client failed to authenticate
+    LoginFailed {
+        username: String,
+        peer: Option<String>,
+        country: Option<String>,
+    },
+    // A client with valid credentials was refused outside the access
+    // windows of its user
+    LoginOutsideAccessHours {
+        username: String,
+        // "sftp" or "ftps"
+        protocol: String,
+        peer: Option<String>,
+    },
+    // A connection was turned away before authentication, e.g. by country
+    ConnectionRejected {
+        protocol: String,
+        peer: String,
+        country: Option<String>,
+    },
+    // A client with valid credentials was refused for the software it
+    // identified itself as
+    ClientRejected {
+        username: String,
+        peer: Option<String>,
+        client: String,
+    },
+    // Configuration was reloaded and some settings changed
+    ConfigReloaded {
+        applied: Vec<String>,
+        requires_restart: Vec<String>,
+    },
+    // The SFTP server failed to start or stopped unexpectedly
+    ServerFailed {
+        reason: String,
+        attempts: u32,
+        gave_up: bool,
+    },
+}
+
+impl Event {
+    // Stable name used for filtering and in outgoing payloads
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::FileUploaded { .. } => "file_uploaded",
+            Event::FileDownloaded { .. } => "file_downloaded",
+            Event::FileQuarantined { .. } => "file_quarantined",
+            Event::FileRestored { .. } => "file_restored",
+            Event::FileExpired { .. } => "file_expired",
+            Event::FileCreated { .. } => "file_created",
+            Event::FileModified { .. } => "file_modified",
+            Event::FileDeleted { .. } => "file_deleted",
+            Event::PipelineCompleted { .. } => "pipeline_completed",
+            Event::PipelineFailed { .. } => "pipeline_failed",
+            Event::FileMirrored { .. } => "file_mirrored",
+            Event::MirrorFailed { .. } => "mirror_failed",
+            Event::ArchiveExtracted { .. } => "archive_extracted",
+            Event::IntegrityMismatch { .. } => "integrity_mismatch",
+            Event::IntegrityCheckCompleted { .. } => {
+                "integrity_check_completed"
+            }
+            Event::BackupCompleted { .. } => "backup_completed",
+            Event::BackupFailed { .. } => "backup_failed",
+            Event::CredentialsExpired { .. } => "credentials_expired",
+            Event::CredentialsExpiring { .. } => "credentials_expiring",
+            Event::CredentialsRotated { .. } => "credentials_rotated",
+            Event::CredentialsAccessed { .. } => "credentials_accessed",
+            Event::CredentialsDelivered { .. } => "credentials_delivered",
+            Event::CredentialDeliveryFailed { .. } => {
+                "credential_delivery_failed"
+            }
+            Event::AuthFailureSpike { .. } => "auth_failure_spike",
+            Event::AuthFailuresFromIp { .. } => "auth_failures_from_ip",
+            Event::AuthFailuresForUser { .. } => "auth_failures_for_user",
+            Event::ServerToggled { .. } => "server_toggled",
+            Event::LoginSucceeded { .. } => "login_succeeded",
+            Event::LoginFailed { .. } => "login_failed",
+            Event::LoginOutsideAccessHours { .. } => {
+                "login_outside_access_hours"
+            }
+            Event::ConnectionRejected { .. } => "connection_rejected",
+            Event::ClientRejected { .. } => "client_rejected",
+            Event::ConfigReloaded { .. } => "config_reloaded",
+            Event::ServerFailed { .. } => "server_failed",
+        }
+    }
+
+    // Path of the file the event is about, as seen over SFTP
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            Event::FileUploaded { path, .. }
+            | Event::FileDownloaded { path, .. }
+            | Event::FileQuarantined { path, .. }
+            | Event::FileCreated { path }
+            | Event::FileModified { path }
+            | Event::FileDeleted { path }
+            | Event::FileRestored { path, .. }
+            | Event::FileExpired { path, .. }
+            | Event::PipelineCompleted { path, .. }
+            | Event::PipelineFailed { path, .. }
+            | Event::FileMirrored { path, .. }
+            | Event::MirrorFailed { path, .. }
+            | Event::ArchiveExtracted { path, .. }
+            | Event::IntegrityMismatch { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+}
+
+// Event together with the time it was published
+#[derive(Debug, Clone, Serialize)]
+pub struct EventEnvelope {
+    pub timestamp: String,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+// Broadcast bus that fans events out to every subscriber
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<EventEnvelope>,
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    // Publish an event; it is dropped silently when nobody is listening
+    pub fn publish(&self, event: Event) {
+        debug!("Publishing event: {}", event.name());
+        let envelope =
+            EventEnvelope { timestamp: Utc::now().to_rfc3339(), event };
+        let _ = self.sender.send(envelope);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<EventEnvelope> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(1024)
+    }
+}
@@ -0,0 +1,157 @@
+mod session;
+
+use crate::error::{Error, Result};
+use crate::sftp::{ServerContext, SharedCredentials};
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+use session::FtpSession;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::{ServerConfig, crypto};
+use tracing::{debug, info, warn};
+
+// Listener settings, taken from the configuration when the server starts
+#[derive(Debug, Clone)]
+pub struct FtpsConfig {
+    // Ports used for passive data connections
+    pub passive_ports: RangeInclusive<u16>,
+    // Address announced in PASV replies instead of the local one
+    pub passive_address: Option<Ipv4Addr>,
+}
+
+// State shared by all FTPS sessions
+pub struct FtpsServer {
+    root_dir: PathBuf,
+    // Credentials accepted at login, shared with the SFTP server
+    credentials: SharedCredentials,
+    context: ServerContext,
+    config: FtpsConfig,
+    tls: TlsAcceptor,
+}
+
+// Load a PEM certificate chain and private key for the control and data
+// connections
+pub fn tls_acceptor(
+    certificate_file: &str,
+    private_key_file: &str,
+) -> Result<TlsAcceptor> {
+    let (certificates, key) =
+        load_certificate(certificate_file, private_key_file)?;
+    let config = ServerConfig::builder_with_provider(Arc::new(
+        crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()?
+    .with_no_client_auth()
+    .with_single_cert(certificates, key)?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+// Read a PEM certificate chain and its private key
+pub fn load_certificate(
+    certificate_file: &str,
+    private_key_file: &str,
+) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+    let invalid = |file: &str, e: &dyn std::fmt::Display| Error::Certificate {
+        file: file.to_string(),
+        message: e.to_string(),
+    };
+    let certificates = CertificateDer::pem_file_iter(certificate_file)
+        .map_err(|e| invalid(certificate_file, &e))?
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|e| invalid(certificate_file, &e))?;
+    let key = PrivateKeyDer::from_pem_file(private_key_file)
+        .map_err(|e| invalid(private_key_file, &e))?;
+    Ok((certificates, key))
+}
+
+// Serves FTPS on an already bound listener, next to the SFTP server.
+// Follows the same `accepting` switch: while it is false the listener is
+// closed, and once the sender is dropped the server ends its sessions and
+// returns.
+pub async fn run_ftps_server(
+    listener: TcpListener,
+    root_dir: String,
+    credentials: SharedCredentials,
+    context: ServerContext,
+    config: FtpsConfig,
+    tls: TlsAcceptor,
+    mut accepting: watch::Receiver<bool>,
+) -> Result<()> {
+    let server = Arc::new(FtpsServer {
+        root_dir: PathBuf::from(root_dir),
+        credentials,
+        context,
+        config,
+        tls,
+    });
+    let local_addr =
+        listener.local_addr().map_err(|e| Error::listener("FTPS", e))?;
+    info!("Starting FTPS server on {}", local_addr);
+    let mut listener = Some(listener);
+    let mut sessions = JoinSet::new();
+
+    loop {
+        tokio::select! {
+            accepted = accept(&listener) => {
+                let (stream, peer_addr) =
+                    accepted.map_err(|e| Error::listener("FTPS", e))?;
+                let Some(stream) =
+                    server.context.unless_banned(stream, peer_addr, "220-")
+                else {
+                    continue;
+                };
+                let check = server.context.check_peer(peer_addr, "ftps");
+                if !check.allowed {
+                    continue;
+                }
+                let server = server.clone();
+                sessions.spawn(async move {
+                    let session =
+                        FtpSession::new(server, peer_addr, check.country);
+                    if let Err(e) = session.run(stream).await {
+                        debug!("FTPS session from {} ended with error: {}", peer_addr, e);
+                    }
+                });
+            }
+            changed = accepting.changed() => {
+                if changed.is_err() {
+                    break;
+                }
+                if !*accepting.borrow() {
+                    if listener.take().is_some() {
+                        warn!("FTPS listener closed, no new connections accepted");
+                    }
+                } else if listener.is_none() {
+                    info!("Resuming FTPS listener on {}", local_addr);
+                    let resumed = TcpListener::bind(local_addr)
+                        .await
+                        .map_err(|e| Error::bind(local_addr, e))?;
+                    listener = Some(resumed);
+                }
+            }
+            // Reap finished sessions
+            Some(_) = sessions.join_next() => {}
+        }
+    }
+
+    sessions.shutdown().await;
+    info!("FTPS server has shut down");
+    Ok(())
+}
+
+// Next connection on the listener; never completes while it is closed
+async fn accept(
+    listener: &Option<TcpListener>,
+) -> io::Result<(TcpStream, SocketAddr)> {
+    match listener {
+        Some(listener) => listener.accept().await,
+        None => std::future::pending().await,
+    }
+}
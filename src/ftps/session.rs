@@ -0,0 +1,1159 @@
+use super::FtpsServer;
+use crate::events::Event;
+use crate::sftp::acl::Operation;
+use crate::sftp::checksum::file_checksums;
+use crate::sftp::filenames::FilenamePolicy;
+use crate::sftp::hooks::CompletedUpload;
+use crate::sftp::scratch::{SCRATCH_DIR, ScratchArea};
+use crate::sftp::sparse;
+use crate::sftp::trash::{TRASH_DIR, Trash};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use rand::RngExt;
+use std::fs::Metadata;
+use std::io::{self, SeekFrom};
+use std::net::{IpAddr, SocketAddr};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::{
+    AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite,
+    AsyncWriteExt, BufReader,
+};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+use tokio_rustls::server::TlsStream;
+use tracing::{debug, info, warn};
+
+/// Longest command line accepted on the control connection
+const MAX_LINE: u64 = 4096;
+/// Time a client has to open a data connection after requesting it
+const DATA_TIMEOUT: Duration = Duration::from_secs(30);
+/// Control connections without a command for this long are closed
+const IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+/// Delay before answering a wrong password, as for SSH
+const REJECTION_DELAY: Duration = Duration::from_secs(3);
+/// Wrong passwords after which the control connection is closed
+const MAX_LOGIN_FAILURES: u32 = 3;
+/// How often the access hours of a logged in user are checked again
+const ACCESS_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+type Control = BufReader<TlsStream<TcpStream>>;
+type Data = TlsStream<TcpStream>;
+
+/// One FTPS control connection. Only TLS negotiation is allowed until
+/// AUTH TLS succeeded; data connections are passive and always encrypted.
+pub struct FtpSession {
+    server: Arc<FtpsServer>,
+    peer_addr: SocketAddr,
+    /// Country the client connects from, when looked up
+    country: Option<String>,
+    /// Address the client connected to, used for passive listeners
+    local_ip: Option<IpAddr>,
+    /// User named by USER, waiting for PASS
+    pending_user: Option<String>,
+    /// Authenticated user
+    username: Option<String>,
+    login_failures: u32,
+    /// When the access hours of the user were last checked
+    access_checked_at: Option<Instant>,
+    /// Working directory as seen by the client
+    cwd: String,
+    /// PROT P was sent, data connections use TLS
+    protected: bool,
+    /// Listener opened by PASV or EPSV for the next transfer
+    passive: Option<TcpListener>,
+    /// Source of a rename, set by RNFR
+    rename_from: Option<String>,
+    /// Offset for the next transfer, set by REST
+    restart_at: u64,
+}
+
+impl FtpSession {
+    pub fn new(
+        server: Arc<FtpsServer>,
+        peer_addr: SocketAddr,
+        country: Option<String>,
+    ) -> Self {
+        Self {
+            server,
+            peer_addr,
+            country,
+            local_ip: None,
+            pending_user: None,
+            username: None,
+            login_failures: 0,
+            access_checked_at: None,
+            cwd: "/".to_string(),
+            protected: false,
+            passive: None,
+            rename_from: None,
+            restart_at: 0,
+        }
+    }
+
+    pub async fn run(mut self, stream: TcpStream) -> io::Result<()> {
+        self.local_ip = stream.local_addr().ok().map(|a| a.ip());
+        let mut plain = BufReader::new(stream);
+        reply(&mut plain, 220, "sftp-manager FTPS ready").await?;
+
+        // Only TLS negotiation is allowed on the plain connection
+        loop {
+            let Some((command, arg)) = read_command(&mut plain).await? else {
+                return Ok(());
+            };
+            match command.as_str() {
+                "AUTH"
+                    if matches!(
+                        arg.to_ascii_uppercase().as_str(),
+                        "TLS" | "SSL"
+                    ) =>
+                {
+                    reply(&mut plain, 234, "Starting TLS").await?;
+                    break;
+                }
+                "AUTH" => {
+                    reply(&mut plain, 504, "Only AUTH TLS is supported").await?
+                }
+                "FEAT" => features(&mut plain).await?,
+                "NOOP" => reply(&mut plain, 200, "OK").await?,
+                "QUIT" => return reply(&mut plain, 221, "Goodbye").await,
+                _ => {
+                    reply(&mut plain, 530, "Use AUTH TLS before logging in")
+                        .await?
+                }
+            }
+        }
+
+        let stream = self.server.tls.accept(plain.into_inner()).await?;
+        let mut control = BufReader::new(stream);
+        debug!("FTPS control connection from {} secured", self.peer_addr);
+
+        loop {
+            let command = timeout(IDLE_TIMEOUT, read_command(&mut control))
+                .await
+                .map_err(|_| {
+                    io::Error::new(io::ErrorKind::TimedOut, "idle timeout")
+                })??;
+            let Some((command, arg)) = command else { break };
+            if !self.within_access_hours().await {
+                reply(&mut control, 421, "Access hours have ended").await?;
+                break;
+            }
+            if !self.handle(&mut control, &command, &arg).await? {
+                break;
+            }
+        }
+        let _ = control.get_mut().shutdown().await;
+        Ok(())
+    }
+
+    /// Whether the logged in user is still inside their access windows.
+    /// Checked at most once a minute; a failed lookup keeps the session.
+    async fn within_access_hours(&mut self) -> bool {
+        let (Some(user), Some(hours)) =
+            (&self.username, &self.server.context.access_hours)
+        else {
+            return true;
+        };
+        if self
+            .access_checked_at
+            .is_some_and(|at| at.elapsed() < ACCESS_CHECK_INTERVAL)
+        {
+            return true;
+        }
+        self.access_checked_at = Some(Instant::now());
+        match hours.allows(user, Utc::now()).await {
+            Ok(allowed) => {
+                if !allowed {
+                    info!(
+                        "Closing FTPS session of {}: access hours ended",
+                        user
+                    );
+                }
+                allowed
+            }
+            Err(e) => {
+                warn!("Failed to look up access hours of {}: {}", user, e);
+                true
+            }
+        }
+    }
+
+    /// Executes one command; returns false once the session should end
+    async fn handle(
+        &mut self,
+        control: &mut Control,
+        command: &str,
+        arg: &str,
+    ) -> io::Result<bool> {
+        debug!("FTPS command from {}: {}", self.peer_addr, command);
+        // A rename must follow its RNFR immediately
+        let rename_from = self.rename_from.take();
+        // REST only applies to the next transfer
+        let restart_at = std::mem::take(&mut self.restart_at);
+
+        match command {
+            "USER" => {
+                self.username = None;
+                self.pending_user = Some(arg.to_string());
+                reply(control, 331, "Password required").await?;
+            }
+            "PASS" => return self.login(control, arg).await,
+            "PBSZ" => reply(control, 200, "PBSZ=0").await?,
+            "PROT" => match arg.to_ascii_uppercase().as_str() {
+                "P" => {
+                    self.protected = true;
+                    reply(control, 200, "Data connections are protected")
+                        .await?;
+                }
+                "C" => {
+                    reply(control, 536, "Data connections must be protected")
+                        .await?
+                }
+                _ => {
+                    reply(control, 504, "Unsupported protection level").await?
+                }
+            },
+            "AUTH" => reply(control, 503, "TLS is already active").await?,
+            "FEAT" => features(control).await?,
+            "SYST" => reply(control, 215, "UNIX Type: L8").await?,
+            "OPTS" if arg.eq_ignore_ascii_case("UTF8 ON") => {
+                reply(control, 200, "UTF8 is always on").await?
+            }
+            "OPTS" => reply(control, 501, "Unsupported option").await?,
+            "NOOP" => reply(control, 200, "OK").await?,
+            "QUIT" => {
+                reply(control, 221, "Goodbye").await?;
+                return Ok(false);
+            }
+            _ if self.username.is_none() => {
+                reply(control, 530, "Log in with USER and PASS").await?
+            }
+            _ if !self.permitted(command, arg) => {
+                reply(control, 550, "Permission denied").await?
+            }
+            "PWD" | "XPWD" => {
+                let message = format!(
+                    "\"{}\" is the current directory",
+                    self.cwd.replace('"', "\"\"")
+                );
+                reply(control, 257, &message).await?;
+            }
+            "CWD" | "XCWD" => self.change_dir(control, arg).await?,
+            "CDUP" | "XCUP" => self.change_dir(control, "..").await?,
+            "TYPE" => match arg.to_ascii_uppercase().as_str() {
+                "I" | "L 8" => reply(control, 200, "Type set to I").await?,
+                // Files are always sent unchanged
+                "A" | "A N" => reply(control, 200, "Type set to A").await?,
+                _ => reply(control, 504, "Unsupported type").await?,
+            },
+            "MODE" if arg.eq_ignore_ascii_case("S") => {
+                reply(control, 200, "Mode set to S").await?
+            }
+            "STRU" if arg.eq_ignore_ascii_case("F") => {
+                reply(control, 200, "Structure set to F").await?
+            }
+            "MODE" | "STRU" => reply(control, 504, "Unsupported").await?,
+            "PASV" => self.passive(control, false).await?,
+            "EPSV" => self.passive(control, true).await?,
+            "PORT" | "EPRT" => {
+                reply(control, 502, "Active mode is not supported, use PASV")
+                    .await?
+            }
+            "REST" => match arg.parse() {
+                Ok(offset) => {
+                    self.restart_at = offset;
+                    let message = format!("Restarting at {}", offset);
+                    reply(control, 350, &message).await?;
+                }
+                Err(_) => reply(control, 501, "Invalid offset").await?,
+            },
+            "LIST" | "NLST" | "MLSD" => {
+                self.list(control, command, arg).await?
+            }
+            "RETR" => self.retrieve(control, arg, restart_at).await?,
+            "STOR" => self.store(control, arg, restart_at, false).await?,
+            "APPE" => self.store(control, arg, 0, true).await?,
+            "DELE" => self.remove(control, arg, false).await?,
+            "RMD" | "XRMD" => self.remove(control, arg, true).await?,
+            "MKD" | "XMKD" => self.make_dir(control, arg).await?,
+            "RNFR" => self.rename_from(control, arg).await?,
+            "RNTO" => self.rename_to(control, arg, rename_from).await?,
+            "SIZE" => self.size(control, arg).await?,
+            "MDTM" => self.modified(control, arg).await?,
+            _ => reply(control, 502, "Command not implemented").await?,
+        }
+        Ok(true)
+    }
+
+    /// Checks the password against the SFTP credentials and the accounts
+    async fn login(
+        &mut self,
+        control: &mut Control,
+        password: &str,
+    ) -> io::Result<bool> {
+        let Some(user) = self.pending_user.take() else {
+            reply(control, 503, "Send USER first").await?;
+            return Ok(true);
+        };
+
+        // Read at every attempt so rotated credentials apply immediately
+        let accepted = self
+            .server
+            .credentials
+            .load()
+            .as_deref()
+            .is_some_and(|c| c.matches(&user, password));
+        let context = &self.server.context;
+        let accepted =
+            accepted || context.account_password_matches(&user, password).await;
+        if accepted
+            && !context
+                .within_access_hours(&user, Some(self.peer_addr), "ftps")
+                .await
+        {
+            reply(control, 530, "Login not allowed at this time").await?;
+            return Ok(false);
+        }
+        if accepted {
+            info!("FTPS authentication successful for user: {}", user);
+            context.stats.record_session(&user);
+            context.events.publish(Event::LoginSucceeded {
+                username: user.clone(),
+                peer: Some(self.peer_addr.to_string()),
+                country: self.country.clone(),
+                client: None,
+                auth_method: "password".to_string(),
+                negotiated: None,
+            });
+            self.username = Some(user);
+            self.access_checked_at = Some(Instant::now());
+            reply(control, 230, "Logged in").await?;
+            return Ok(true);
+        }
+
+        warn!("FTPS authentication failed for user: {}", user);
+        context.login_failed(
+            &user,
+            Some(self.peer_addr),
+            self.country.as_deref(),
+        );
+        self.login_failures += 1;
+        tokio::time::sleep(REJECTION_DELAY).await;
+        reply(control, 530, "Login incorrect").await?;
+        Ok(self.login_failures < MAX_LOGIN_FAILURES)
+    }
+
+    async fn change_dir(
+        &mut self,
+        control: &mut Control,
+        arg: &str,
+    ) -> io::Result<()> {
+        let path = self.client_path(arg);
+        match self.metadata(&path).await {
+            Ok(metadata) if metadata.is_dir() => {
+                self.cwd = path;
+                reply(control, 250, "Directory changed").await
+            }
+            Ok(_) => reply(control, 550, "Not a directory").await,
+            Err(e) => reply_error(control, e).await,
+        }
+    }
+
+    /// Opens a passive listener and tells the client where to connect
+    async fn passive(
+        &mut self,
+        control: &mut Control,
+        extended: bool,
+    ) -> io::Result<()> {
+        self.passive = None;
+        let listener = match self.bind_passive().await {
+            Ok(listener) => listener,
+            Err(e) => {
+                warn!("Failed to open a passive FTPS port: {}", e);
+                return reply(control, 425, "No passive port available").await;
+            }
+        };
+        let port = listener.local_addr()?.port();
+
+        if extended {
+            self.passive = Some(listener);
+            let message =
+                format!("Entering Extended Passive Mode (|||{}|)", port);
+            return reply(control, 229, &message).await;
+        }
+
+        let local_ip = self.local_ip.and_then(|ip| match ip {
+            IpAddr::V4(ip) => Some(ip),
+            IpAddr::V6(ip) => ip.to_ipv4_mapped(),
+        });
+        let Some(ip) = self.server.config.passive_address.or(local_ip) else {
+            return reply(control, 522, "Use EPSV on IPv6").await;
+        };
+        self.passive = Some(listener);
+        let [a, b, c, d] = ip.octets();
+        let message = format!(
+            "Entering Passive Mode ({},{},{},{},{},{})",
+            a,
+            b,
+            c,
+            d,
+            port >> 8,
+            port & 0xff
+        );
+        reply(control, 227, &message).await
+    }
+
+    /// Binds a free port of the passive range, starting at a random one
+    async fn bind_passive(&self) -> io::Result<TcpListener> {
+        let ports = &self.server.config.passive_ports;
+        let ip = self.local_ip.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::AddrNotAvailable, "no local address")
+        })?;
+        let count = (*ports.end() - *ports.start()) as u32 + 1;
+        let offset = rand::rng().random_range(0..count);
+        for i in 0..count {
+            let port = *ports.start() + ((offset + i) % count) as u16;
+            match TcpListener::bind((ip, port)).await {
+                Ok(listener) => return Ok(listener),
+                Err(e) if e.kind() == io::ErrorKind::AddrInUse => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::AddrInUse, "passive ports in use"))
+    }
+
+    /// Accepts the data connection of the client on the passive listener
+    /// and secures it
+    async fn open_data(&mut self) -> Result<Data, &'static str> {
+        let Some(listener) = self.passive.take() else {
+            return Err("Use PASV or EPSV first");
+        };
+        if !self.protected {
+            return Err("Use PROT P first");
+        }
+        let (stream, peer_addr) = timeout(DATA_TIMEOUT, listener.accept())
+            .await
+            .map_err(|_| "Data connection timed out")?
+            .map_err(|_| "Data connection failed")?;
+        // Another host must not be able to take over the transfer
+        if peer_addr.ip() != self.peer_addr.ip() {
+            warn!(
+                "Rejected FTPS data connection from {}, expected {}",
+                peer_addr,
+                self.peer_addr.ip()
+            );
+            return Err("Data connection from an unexpected address");
+        }
+        timeout(DATA_TIMEOUT, self.server.tls.accept(stream))
+            .await
+            .map_err(|_| "TLS negotiation timed out")?
+            .map_err(|_| "TLS negotiation failed")
+    }
+
+    async fn list(
+        &mut self,
+        control: &mut Control,
+        command: &str,
+        arg: &str,
+    ) -> io::Result<()> {
+        let path = self.client_path(&listed_path(arg));
+        let local_path = match self.local_path(&path).await {
+            Ok(local_path) => local_path,
+            Err(e) => return reply_error(control, e).await,
+        };
+        let entries = match list_entries(
+            &local_path,
+            &path,
+            self.hide_trash(&path),
+            &self.server.context.filenames,
+        )
+        .await
+        {
+            Ok(entries) => entries,
+            Err(e) => return reply_error(control, e).await,
+        };
+
+        let now = Utc::now();
+        let mut listing = String::new();
+        for (name, metadata) in &entries {
+            let line = match command {
+                "NLST" => name.clone(),
+                "MLSD" => mlsd_line(name, metadata),
+                _ => list_line(name, metadata, now),
+            };
+            listing.push_str(&line);
+            listing.push_str("\r\n");
+        }
+
+        reply(control, 150, "Opening data connection").await?;
+        let mut data = match self.open_data().await {
+            Ok(data) => data,
+            Err(message) => return reply(control, 425, message).await,
+        };
+        let sent = async {
+            data.write_all(listing.as_bytes()).await?;
+            data.shutdown().await
+        }
+        .await;
+        match sent {
+            Ok(()) => reply(control, 226, "Transfer complete").await,
+            Err(_) => reply(control, 426, "Transfer aborted").await,
+        }
+    }
+
+    async fn retrieve(
+        &mut self,
+        control: &mut Control,
+        arg: &str,
+        restart_at: u64,
+    ) -> io::Result<()> {
+        let path = self.client_path(arg);
+        let opened = async {
+            let local_path = self.local_path(&path).await?;
+            let mut file = File::open(&local_path).await?;
+            if !file.metadata().await?.is_file() {
+                return Err(io::Error::other("Not a regular file"));
+            }
+            file.seek(SeekFrom::Start(restart_at)).await?;
+            Ok(file)
+        }
+        .await;
+        let mut file = match opened {
+            Ok(file) => file,
+            Err(e) => return reply_error(control, e).await,
+        };
+
+        reply(control, 150, "Opening data connection").await?;
+        let mut data = match self.open_data().await {
+            Ok(data) => data,
+            Err(message) => return reply(control, 425, message).await,
+        };
+        let started = Instant::now();
+        let buffer = self.server.context.transfer_limits.transfer_buffer_bytes;
+        let sent = async {
+            let mut file = BufReader::with_capacity(buffer, &mut file);
+            let bytes = tokio::io::copy_buf(&mut file, &mut data).await?;
+            data.shutdown().await?;
+            Ok::<_, io::Error>(bytes)
+        }
+        .await;
+        let bytes = match sent {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                debug!("FTPS download of {} aborted: {}", path, e);
+                return reply(control, 426, "Transfer aborted").await;
+            }
+        };
+
+        let context = &self.server.context;
+        info!(
+            "FTPS transfer finished: download {} ({} bytes) by {}",
+            path,
+            bytes,
+            self.user()
+        );
+        context.stats.record_bytes_out(bytes);
+        context.stats.record_download();
+        context.events.publish(Event::FileDownloaded {
+            username: self.user().to_string(),
+            peer: Some(self.peer_addr.ip().to_string()),
+            path,
+            bytes,
+            duration_ms: started.elapsed().as_millis() as u64,
+        });
+        reply(control, 226, "Transfer complete").await
+    }
+
+    async fn store(
+        &mut self,
+        control: &mut Control,
+        arg: &str,
+        restart_at: u64,
+        append: bool,
+    ) -> io::Result<()> {
+        let path = match self.new_path(arg) {
+            Ok(path) => path,
+            Err(e) => return reply_error(control, e).await,
+        };
+        if !self.has_space(0).await {
+            warn!("Refusing FTPS upload of {}: no space left", path);
+            return reply(control, 452, "Insufficient storage space").await;
+        }
+        let opened = async {
+            let local_path = self.local_path(&path).await?;
+            let mut options = OpenOptions::new();
+            options.create(true);
+            if append {
+                options.append(true);
+            } else {
+                options.write(true).truncate(restart_at == 0);
+            }
+            let mut file = options.open(&local_path).await?;
+            if restart_at > 0 {
+                file.set_len(restart_at).await?;
+                file.seek(SeekFrom::Start(restart_at)).await?;
+            }
+            Ok((local_path, file))
+        }
+        .await;
+        let (local_path, mut file) = match opened {
+            Ok(opened) => opened,
+            Err(e) => return reply_error(control, e).await,
+        };
+
+        reply(control, 150, "Opening data connection").await?;
+        let mut data = match self.open_data().await {
+            Ok(data) => data,
+            Err(message) => return reply(control, 425, message).await,
+        };
+        let started = Instant::now();
+        // Appends go to the end whatever the offset, so no holes there
+        let sparse = self.server.context.sparse_files && !append;
+        let received = async {
+            // Uploads are written in pieces of this size, so blocks of
+            // zeros can be recognized
+            let mut buffer =
+                vec![
+                    0;
+                    self.server.context.transfer_limits.transfer_buffer_bytes
+                ];
+            let mut bytes = 0;
+            loop {
+                let read = read_full(&mut data, &mut buffer).await?;
+                if read == 0 {
+                    break;
+                }
+                if !self.has_space(read as u64).await {
+                    return Err(io::Error::from(io::ErrorKind::StorageFull));
+                }
+                let offset = restart_at + bytes;
+                sparse::write_at(&mut file, offset, &buffer[..read], sparse)
+                    .await?;
+                bytes += read as u64;
+            }
+            file.flush().await?;
+            Ok::<_, io::Error>(bytes)
+        }
+        .await;
+        drop(file);
+        let bytes = match received {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::StorageFull => {
+                warn!("FTPS upload of {} stopped: disk full", path);
+                return reply(control, 452, "Insufficient storage space").await;
+            }
+            Err(e) => {
+                debug!("FTPS upload of {} aborted: {}", path, e);
+                return reply(control, 426, "Transfer aborted").await;
+            }
+        };
+        let duration_ms = started.elapsed().as_millis() as u64;
+
+        let context = &self.server.context;
+        let peer = Some(self.peer_addr.ip().to_string());
+        info!(
+            "FTPS transfer finished: upload {} ({} bytes) by {}",
+            path,
+            bytes,
+            self.user()
+        );
+        context.stats.record_bytes_in(bytes);
+        context.stats.record_upload();
+        let checksums = if context.checksum_algorithms.is_empty() {
+            Default::default()
+        } else {
+            file_checksums(&context.checksum_algorithms, &local_path)
+                .await
+                .unwrap_or_else(|e| {
+                    warn!("Failed to compute checksums of {}: {}", path, e);
+                    Default::default()
+                })
+        };
+        context.completion.closed(CompletedUpload {
+            username: self.user().to_string(),
+            peer,
+            path,
+            local_path,
+            root_dir: self.server.root_dir.clone(),
+            bytes,
+            duration_ms,
+            checksums,
+        });
+        reply(control, 226, "Transfer complete").await
+    }
+
+    /// Removes a file, or an empty directory for RMD, into the trash when
+    /// it is enabled
+    async fn remove(
+        &mut self,
+        control: &mut Control,
+        arg: &str,
+        directory: bool,
+    ) -> io::Result<()> {
+        let path = self.client_path(arg);
+        if path == "/" {
+            return reply(control, 550, "Permission denied").await;
+        }
+        let removed = async {
+            let local_path = self.local_path(&path).await?;
+            let metadata = fs::symlink_metadata(&local_path).await?;
+            if directory != metadata.is_dir() {
+                return Err(io::Error::other(if directory {
+                    "Not a directory"
+                } else {
+                    "Is a directory"
+                }));
+            }
+            if directory
+                && fs::read_dir(&local_path)
+                    .await?
+                    .next_entry()
+                    .await?
+                    .is_some()
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::DirectoryNotEmpty,
+                    "Directory not empty",
+                ));
+            }
+            if self.server.context.trash {
+                let entry = Trash::new(&self.server.root_dir)
+                    .put(&local_path, &path, self.user())
+                    .await?;
+                info!("Moved {} to the trash as {}", path, entry.id);
+            } else if directory {
+                fs::remove_dir(&local_path).await?;
+            } else {
+                fs::remove_file(&local_path).await?;
+            }
+            self.server.context.completion.removed(&local_path);
+            Ok(())
+        }
+        .await;
+
+        match removed {
+            Ok(()) => {
+                if !directory {
+                    self.server.context.stats.record_delete();
+                }
+                info!("FTPS user {} removed {}", self.user(), path);
+                reply(control, 250, "Removed").await
+            }
+            Err(e) => reply_error(control, e).await,
+        }
+    }
+
+    async fn make_dir(
+        &mut self,
+        control: &mut Control,
+        arg: &str,
+    ) -> io::Result<()> {
+        let path = match self.new_path(arg) {
+            Ok(path) => path,
+            Err(e) => return reply_error(control, e).await,
+        };
+        let created =
+            async { fs::create_dir(self.local_path(&path).await?).await }.await;
+        match created {
+            Ok(()) => {
+                let message =
+                    format!("\"{}\" created", path.replace('"', "\"\""));
+                reply(control, 257, &message).await
+            }
+            Err(e) => reply_error(control, e).await,
+        }
+    }
+
+    async fn rename_from(
+        &mut self,
+        control: &mut Control,
+        arg: &str,
+    ) -> io::Result<()> {
+        let path = self.client_path(arg);
+        if path == "/" {
+            return reply(control, 550, "Permission denied").await;
+        }
+        match self.metadata(&path).await {
+            Ok(_) => {
+                self.rename_from = Some(path);
+                reply(control, 350, "Ready for RNTO").await
+            }
+            Err(e) => reply_error(control, e).await,
+        }
+    }
+
+    async fn rename_to(
+        &mut self,
+        control: &mut Control,
+        arg: &str,
+        from: Option<String>,
+    ) -> io::Result<()> {
+        let Some(from) = from else {
+            return reply(control, 503, "Send RNFR first").await;
+        };
+        let to = match self.new_path(arg) {
+            Ok(to) => to,
+            Err(e) => return reply_error(control, e).await,
+        };
+        let renamed = async {
+            let source = self.local_path(&from).await?;
+            let target = self.local_path(&to).await?;
+            fs::rename(&source, &target).await?;
+            Ok::<_, io::Error>((source, target))
+        }
+        .await;
+        match renamed {
+            Ok((source, target)) => {
+                info!("FTPS user {} renamed {} to {}", self.user(), from, to);
+                self.server
+                    .context
+                    .completion
+                    .renamed(&source, &target, to, false);
+                reply(control, 250, "Renamed").await
+            }
+            Err(e) => reply_error(control, e).await,
+        }
+    }
+
+    async fn size(
+        &mut self,
+        control: &mut Control,
+        arg: &str,
+    ) -> io::Result<()> {
+        let path = self.client_path(arg);
+        match self.metadata(&path).await {
+            Ok(metadata) if metadata.is_file() => {
+                reply(control, 213, &metadata.len().to_string()).await
+            }
+            Ok(_) => reply(control, 550, "Not a regular file").await,
+            Err(e) => reply_error(control, e).await,
+        }
+    }
+
+    async fn modified(
+        &mut self,
+        control: &mut Control,
+        arg: &str,
+    ) -> io::Result<()> {
+        let path = self.client_path(arg);
+        match self.metadata(&path).await {
+            Ok(metadata) => {
+                let modified = modified_at(&metadata).format("%Y%m%d%H%M%S");
+                reply(control, 213, &modified.to_string()).await
+            }
+            Err(e) => reply_error(control, e).await,
+        }
+    }
+
+    fn user(&self) -> &str {
+        self.username.as_deref().unwrap_or_default()
+    }
+
+    fn client_path(&self, arg: &str) -> String {
+        resolve_path(&self.cwd, arg)
+    }
+
+    /// Whether the path rules allow `command` on the path it names
+    fn permitted(&self, command: &str, arg: &str) -> bool {
+        let operation = match command {
+            "RETR" => Operation::Read,
+            "LIST" | "NLST" | "MLSD" => Operation::List,
+            "CWD" | "XCWD" | "SIZE" | "MDTM" => Operation::Stat,
+            "STOR" | "APPE" | "MKD" | "XMKD" | "RNFR" | "RNTO" => {
+                Operation::Write
+            }
+            "DELE" | "RMD" | "XRMD" => Operation::Delete,
+            _ => return true,
+        };
+        let path = match operation {
+            Operation::List => self.client_path(&listed_path(arg)),
+            _ => self.client_path(arg),
+        };
+        if self.server.context.path_rules.allows(&path, operation) {
+            return true;
+        }
+        warn!(
+            "Refusing FTPS user {} to {} {}: not allowed by the path rules",
+            self.user(),
+            operation,
+            path
+        );
+        false
+    }
+
+    /// Whether `incoming` more bytes may be written under the root without
+    /// eating into the disk reserve or going over the quota
+    async fn has_space(&self, incoming: u64) -> bool {
+        let root = &self.server.root_dir;
+        self.server.context.has_space(root, incoming).await
+    }
+
+    /// Client path of a file or directory about to be created, under the
+    /// configured filename policy
+    fn new_path(&self, arg: &str) -> io::Result<String> {
+        self.server
+            .context
+            .filenames
+            .apply(&self.client_path(arg))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidFilename, e))
+    }
+
+    /// Location of a client path on disk. Paths inside the trash and paths
+    /// a symbolic link leads out of the root are refused.
+    async fn local_path(&self, path: &str) -> io::Result<PathBuf> {
+        let relative = path.trim_start_matches('/');
+        if self.server.context.trash && Trash::contains(Path::new(relative)) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "Trash is not accessible",
+            ));
+        }
+        // Scratch areas belong to SFTP sessions
+        if ScratchArea::contains(Path::new(relative)) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "Scratch areas are not accessible",
+            ));
+        }
+        let root = fs::canonicalize(&self.server.root_dir).await?;
+        let local_path = root.join(relative);
+
+        // The closest existing ancestor must stay below the root
+        let mut existing = local_path.as_path();
+        loop {
+            match fs::canonicalize(existing).await {
+                Ok(canonical) if canonical.starts_with(&root) => break,
+                Ok(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        "Path leaves the root directory",
+                    ));
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    existing = existing.parent().ok_or(e)?;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(local_path)
+    }
+
+    async fn metadata(&self, path: &str) -> io::Result<Metadata> {
+        fs::metadata(self.local_path(path).await?).await
+    }
+
+    fn hide_trash(&self, path: &str) -> bool {
+        self.server.context.trash && path == "/"
+    }
+}
+
+/// Path argument of a listing command; options such as -la are accepted
+/// and ignored
+fn listed_path(arg: &str) -> String {
+    arg.split_whitespace()
+        .filter(|a| !a.starts_with('-'))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Absolute client path of an argument, resolved against the working
+/// directory; `..` stops at the root
+fn resolve_path(cwd: &str, arg: &str) -> String {
+    let joined = if arg.starts_with('/') {
+        arg.to_string()
+    } else {
+        format!("{}/{}", cwd, arg)
+    };
+    let mut parts: Vec<&str> = Vec::new();
+    for part in joined.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    format!("/{}", parts.join("/"))
+}
+
+/// Entries of a directory sorted by name, or the file itself; the trash,
+/// the scratch areas and names the filename policy hides are left out
+async fn list_entries(
+    local_path: &Path,
+    path: &str,
+    hide_trash: bool,
+    filenames: &FilenamePolicy,
+) -> io::Result<Vec<(String, Metadata)>> {
+    let metadata = fs::metadata(local_path).await?;
+    if !metadata.is_dir() {
+        let name = path.rsplit('/').next().unwrap_or(path).to_string();
+        return Ok(vec![(name, metadata)]);
+    }
+
+    let mut entries = Vec::new();
+    let mut dir = fs::read_dir(local_path).await?;
+    while let Some(entry) = dir.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if hide_trash && name == TRASH_DIR
+            || name == SCRATCH_DIR
+            || filenames.hides(&name)
+        {
+            continue;
+        }
+        // Skip entries removed while listing and dangling links
+        if let Ok(metadata) = fs::metadata(entry.path()).await {
+            entries.push((name, metadata));
+        }
+    }
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(entries)
+}
+
+fn modified_at(metadata: &Metadata) -> DateTime<Utc> {
+    metadata.modified().map(DateTime::<Utc>::from).unwrap_or_default()
+}
+
+/// A line in the format of `ls -l`, which clients parse for LIST
+fn list_line(name: &str, metadata: &Metadata, now: DateTime<Utc>) -> String {
+    let mode = metadata.permissions().mode();
+    let mut permissions =
+        String::from(if metadata.is_dir() { "d" } else { "-" });
+    for shift in [6, 3, 0] {
+        let bits = mode >> shift;
+        permissions.push(if bits & 4 != 0 { 'r' } else { '-' });
+        permissions.push(if bits & 2 != 0 { 'w' } else { '-' });
+        permissions.push(if bits & 1 != 0 { 'x' } else { '-' });
+    }
+    let modified = modified_at(metadata);
+    // Older entries show the year instead of the time
+    let date = if now - modified < ChronoDuration::days(180) {
+        modified.format("%b %e %H:%M")
+    } else {
+        modified.format("%b %e  %Y")
+    };
+    format!(
+        "{} 1 ftp ftp {:>12} {} {}",
+        permissions,
+        metadata.len(),
+        date,
+        name
+    )
+}
+
+/// A machine-readable line as defined for MLSD in RFC 3659
+fn mlsd_line(name: &str, metadata: &Metadata) -> String {
+    let modified = modified_at(metadata).format("%Y%m%d%H%M%S");
+    if metadata.is_dir() {
+        format!("type=dir;modify={};perm=cdelmp; {}", modified, name)
+    } else {
+        format!(
+            "type=file;size={};modify={};perm=adfrw; {}",
+            metadata.len(),
+            modified,
+            name
+        )
+    }
+}
+
+/// Reads one command line; `None` once the client closed the connection
+async fn read_command<S: AsyncRead + Unpin>(
+    control: &mut BufReader<S>,
+) -> io::Result<Option<(String, String)>> {
+    let mut line = String::new();
+    if (&mut *control).take(MAX_LINE).read_line(&mut line).await? == 0 {
+        return Ok(None);
+    }
+    if !line.ends_with('\n') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "command line too long",
+        ));
+    }
+    let line = line.trim_end_matches(['\r', '\n']);
+    let (command, arg) = line.split_once(' ').unwrap_or((line, ""));
+    Ok(Some((command.to_ascii_uppercase(), arg.to_string())))
+}
+
+async fn reply<S: AsyncRead + AsyncWrite + Unpin>(
+    control: &mut BufReader<S>,
+    code: u16,
+    message: &str,
+) -> io::Result<()> {
+    let stream = control.get_mut();
+    stream.write_all(format!("{} {}\r\n", code, message).as_bytes()).await?;
+    stream.flush().await
+}
+
+/// Reads until `buffer` is full or the data connection is closed
+async fn read_full<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    buffer: &mut [u8],
+) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]).await? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+    Ok(filled)
+}
+
+/// Answers a failed file operation with 550, 553 for names refused by the
+/// filename policy, or 451 for local errors
+async fn reply_error(control: &mut Control, e: io::Error) -> io::Result<()> {
+    match e.kind() {
+        io::ErrorKind::InvalidFilename => {
+            reply(control, 553, &format!("File name not allowed: {}", e)).await
+        }
+        io::ErrorKind::NotFound => {
+            reply(control, 550, "No such file or directory").await
+        }
+        io::ErrorKind::PermissionDenied => {
+            reply(control, 550, "Permission denied").await
+        }
+        io::ErrorKind::AlreadyExists => {
+            reply(control, 550, "File exists").await
+        }
+        io::ErrorKind::DirectoryNotEmpty
+        | io::ErrorKind::IsADirectory
+        | io::ErrorKind::NotADirectory
+        | io::ErrorKind::Other => reply(control, 550, &e.to_string()).await,
+        _ => {
+            warn!("FTPS file operation failed: {}", e);
+            reply(control, 451, "Local error").await
+        }
+    }
+}
+
+async fn features<S: AsyncRead + AsyncWrite + Unpin>(
+    control: &mut BufReader<S>,
+) -> io::Result<()> {
+    let stream = control.get_mut();
+    stream
+        .write_all(
+            b"211-Features:\r\n AUTH TLS\r\n PBSZ\r\n PROT\r\n EPSV\r\n \
+              SIZE\r\n MDTM\r\n REST STREAM\r\n \
+              MLST type*;size*;modify*;perm*;\r\n UTF8\r\n211 End\r\n",
+        )
+        .await?;
+    stream.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_path() {
+        assert_eq!(resolve_path("/", ""), "/");
+        assert_eq!(resolve_path("/in", "a.csv"), "/in/a.csv");
+        assert_eq!(resolve_path("/in", "/out//b.csv"), "/out/b.csv");
+        assert_eq!(resolve_path("/in/sub", "../a/./b"), "/in/a/b");
+        assert_eq!(resolve_path("/in", "../../../etc/passwd"), "/etc/passwd");
+    }
+}
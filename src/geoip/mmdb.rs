@@ -0,0 +1,388 @@
+// Reader for MaxMind DB files (GeoLite2 and GeoIP2 databases), following
+// version 2 of the format: a binary search tree over address bits whose
+// leaves point into a data section of typed values. The file is held in
+// memory and only looked up, never written.
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::net::IpAddr;
+
+type Result<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
+
+// Starts the metadata at the end of the file
+const METADATA_MARKER: &[u8] = b"\xab\xcd\xefMaxMind.com";
+// Zero bytes between the search tree and the data section
+const DATA_SEPARATOR: usize = 16;
+// Nested maps and pointers deeper than this are treated as corrupt
+const MAX_DEPTH: usize = 32;
+
+// A decoded data section value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Double(f64),
+    Bytes(Vec<u8>),
+    Uint(u128),
+    Int(i32),
+    Map(BTreeMap<String, Value>),
+    Array(Vec<Value>),
+    Bool(bool),
+    Float(f32),
+}
+
+impl Value {
+    // Entry of a map
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Map(map) => map.get(key),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Uint(n) => u64::try_from(*n).ok(),
+            _ => None,
+        }
+    }
+}
+
+pub struct Reader {
+    data: Vec<u8>,
+    node_count: u32,
+    record_size: u16,
+    ip_version: u16,
+    // Data section bounds within `data`
+    data_start: usize,
+    data_end: usize,
+    // Node reached by IPv4 addresses, which live below ::/96 in IPv6 trees
+    ipv4_start: u32,
+    // E.g. "GeoLite2-Country"
+    pub database_type: String,
+}
+
+impl Reader {
+    pub fn open(path: &str) -> crate::error::Result<Self> {
+        let error = |message: String| crate::error::Error::GeoIp {
+            file: path.to_string(),
+            message,
+        };
+        let data = std::fs::read(path).map_err(|e| error(e.to_string()))?;
+        Self::from_bytes(data).map_err(|e| error(e.to_string()))
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self> {
+        let marker = data
+            .windows(METADATA_MARKER.len())
+            .rposition(|window| window == METADATA_MARKER)
+            .ok_or("not a MaxMind DB file")?;
+        let section = &data[marker + METADATA_MARKER.len()..];
+        let (metadata, _) = Decoder { section }.decode(0, 0)?;
+        let field = |name: &str| {
+            metadata
+                .get(name)
+                .and_then(Value::as_u64)
+                .ok_or_else(|| format!("metadata lacks {}", name))
+        };
+        let node_count = u32::try_from(field("node_count")?)
+            .map_err(|_| "node_count out of range")?;
+        let record_size = field("record_size")? as u16;
+        let ip_version = field("ip_version")? as u16;
+        if ![24, 28, 32].contains(&record_size) {
+            return Err(
+                format!("unsupported record size {}", record_size).into()
+            );
+        }
+        if ![4, 6].contains(&ip_version) {
+            return Err(format!("unsupported IP version {}", ip_version).into());
+        }
+        let tree_size = node_count as usize * record_size as usize / 4;
+        if tree_size + DATA_SEPARATOR > marker {
+            return Err("search tree overlaps the metadata".into());
+        }
+        let database_type = metadata
+            .get("database_type")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+
+        let mut reader = Self {
+            data,
+            node_count,
+            record_size,
+            ip_version,
+            data_start: tree_size + DATA_SEPARATOR,
+            data_end: marker,
+            ipv4_start: 0,
+            database_type,
+        };
+        if ip_version == 6 {
+            let mut node = 0;
+            for _ in 0..96 {
+                if node >= node_count {
+                    break;
+                }
+                node = reader.record(node, 0);
+            }
+            reader.ipv4_start = node;
+        }
+        Ok(reader)
+    }
+
+    // The record stored for `ip`, or None when the database has none
+    pub fn lookup(&self, ip: IpAddr) -> Result<Option<Value>> {
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+            v4 => v4,
+        };
+        let (address, bits, mut node) = match ip {
+            IpAddr::V4(v4) => (u32::from(v4) as u128, 32, self.ipv4_start),
+            IpAddr::V6(_) if self.ip_version == 4 => return Ok(None),
+            IpAddr::V6(v6) => (u128::from(v6), 128, 0),
+        };
+
+        for i in (0..bits).rev() {
+            if node >= self.node_count {
+                break;
+            }
+            node = self.record(node, ((address >> i) & 1) as u8);
+        }
+        if node == self.node_count {
+            return Ok(None);
+        }
+        let offset = node.saturating_sub(self.node_count) as usize;
+        if offset < DATA_SEPARATOR {
+            return Err("invalid node in the search tree".into());
+        }
+        let section = &self.data[self.data_start..self.data_end];
+        let (value, _) =
+            Decoder { section }.decode(offset - DATA_SEPARATOR, 0)?;
+        Ok(Some(value))
+    }
+
+    // ISO code of the country `ip` is located in, else the country it is
+    // registered to
+    pub fn country(&self, ip: IpAddr) -> Result<Option<String>> {
+        let Some(record) = self.lookup(ip)? else {
+            return Ok(None);
+        };
+        Ok(["country", "registered_country"].iter().find_map(|key| {
+            record
+                .get(key)
+                .and_then(|c| c.get("iso_code"))
+                .and_then(Value::as_str)
+                .map(String::from)
+        }))
+    }
+
+    // Left (bit 0) or right (bit 1) record of a node
+    fn record(&self, node: u32, bit: u8) -> u32 {
+        let start = node as usize * self.record_size as usize / 4;
+        let bytes = &self.data[start..];
+        match (self.record_size, bit) {
+            (24, 0) => be(&bytes[0..3]) as u32,
+            (24, _) => be(&bytes[3..6]) as u32,
+            (28, 0) => {
+                ((bytes[3] as u32 & 0xf0) << 20) | be(&bytes[0..3]) as u32
+            }
+            (28, _) => {
+                ((bytes[3] as u32 & 0x0f) << 24) | be(&bytes[4..7]) as u32
+            }
+            (_, 0) => be(&bytes[0..4]) as u32,
+            (_, _) => be(&bytes[4..8]) as u32,
+        }
+    }
+}
+
+// Big-endian unsigned integer of up to 16 bytes
+fn be(bytes: &[u8]) -> u128 {
+    bytes.iter().fold(0, |n, &b| (n << 8) | b as u128)
+}
+
+// Decodes values of one section; pointers are offsets into the section
+struct Decoder<'a> {
+    section: &'a [u8],
+}
+
+impl Decoder<'_> {
+    // The value at `offset` and the offset following it
+    fn decode(&self, offset: usize, depth: usize) -> Result<(Value, usize)> {
+        if depth > MAX_DEPTH {
+            return Err("data nested too deeply".into());
+        }
+        let control = self.byte(offset)?;
+        let mut offset = offset + 1;
+        let mut kind = control >> 5;
+        if kind == 1 {
+            let (target, next) = self.pointer(control, offset)?;
+            let (value, _) = self.decode(target, depth + 1)?;
+            return Ok((value, next));
+        }
+        if kind == 0 {
+            kind = 7u8
+                .checked_add(self.byte(offset)?)
+                .ok_or("invalid extended type")?;
+            offset += 1;
+        }
+        let (size, offset) = self.size(control & 0x1f, offset)?;
+
+        let value = match kind {
+            2 => Value::String(
+                String::from_utf8(self.bytes(offset, size)?.to_vec())
+                    .map_err(|_| "invalid UTF-8 string")?,
+            ),
+            3 => {
+                let bytes: [u8; 8] = self
+                    .bytes(offset, size)?
+                    .try_into()
+                    .map_err(|_| "invalid double")?;
+                Value::Double(f64::from_be_bytes(bytes))
+            }
+            4 => Value::Bytes(self.bytes(offset, size)?.to_vec()),
+            5 | 6 | 9 | 10 if size <= 16 => {
+                Value::Uint(be(self.bytes(offset, size)?))
+            }
+            8 if size <= 4 => Value::Int(be(self.bytes(offset, size)?) as i32),
+            7 => {
+                let mut map = BTreeMap::new();
+                let mut next = offset;
+                for _ in 0..size {
+                    let (key, after_key) = self.decode(next, depth + 1)?;
+                    let Value::String(key) = key else {
+                        return Err("map key is not a string".into());
+                    };
+                    let (value, after_value) =
+                        self.decode(after_key, depth + 1)?;
+                    map.insert(key, value);
+                    next = after_value;
+                }
+                return Ok((Value::Map(map), next));
+            }
+            11 => {
+                let mut array = Vec::new();
+                let mut next = offset;
+                for _ in 0..size {
+                    let (value, after) = self.decode(next, depth + 1)?;
+                    array.push(value);
+                    next = after;
+                }
+                return Ok((Value::Array(array), next));
+            }
+            // The size is the value; nothing follows
+            14 => return Ok((Value::Bool(size != 0), offset)),
+            15 => {
+                let bytes: [u8; 4] = self
+                    .bytes(offset, size)?
+                    .try_into()
+                    .map_err(|_| "invalid float")?;
+                Value::Float(f32::from_be_bytes(bytes))
+            }
+            _ => return Err(format!("invalid data type {}", kind).into()),
+        };
+        Ok((value, offset + size))
+    }
+
+    // Target and following offset of a pointer
+    fn pointer(&self, control: u8, offset: usize) -> Result<(usize, usize)> {
+        let high = (control & 0x07) as usize;
+        let (target, length) = match (control >> 3) & 0x03 {
+            0 => ((high << 8) | be(self.bytes(offset, 1)?) as usize, 1),
+            1 => {
+                (((high << 16) | be(self.bytes(offset, 2)?) as usize) + 2048, 2)
+            }
+            2 => {
+                let low = be(self.bytes(offset, 3)?) as usize;
+                (((high << 24) | low) + 526_336, 3)
+            }
+            _ => (be(self.bytes(offset, 4)?) as usize, 4),
+        };
+        Ok((target, offset + length))
+    }
+
+    // Payload size and the offset of the payload
+    fn size(&self, size: u8, offset: usize) -> Result<(usize, usize)> {
+        Ok(match size {
+            0..=28 => (size as usize, offset),
+            29 => (29 + be(self.bytes(offset, 1)?) as usize, offset + 1),
+            30 => (285 + be(self.bytes(offset, 2)?) as usize, offset + 2),
+            _ => (65_821 + be(self.bytes(offset, 3)?) as usize, offset + 3),
+        })
+    }
+
+    fn byte(&self, offset: usize) -> Result<u8> {
+        Ok(self.bytes(offset, 1)?[0])
+    }
+
+    fn bytes(&self, offset: usize, length: usize) -> Result<&[u8]> {
+        offset
+            .checked_add(length)
+            .and_then(|end| self.section.get(offset..end))
+            .ok_or_else(|| "data section truncated".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(s: &str) -> Vec<u8> {
+        let mut encoded = vec![0x40 | s.len() as u8];
+        encoded.extend_from_slice(s.as_bytes());
+        encoded
+    }
+
+    // IPv4 database mapping 0.0.0.0/2 to AU and 64.0.0.0/2 to FR
+    fn database() -> Vec<u8> {
+        // {"country": {"iso_code": "AU"}}
+        let mut data = vec![0xe1];
+        data.extend(string("country"));
+        data.push(0xe1);
+        data.extend(string("iso_code"));
+        data.extend(string("AU"));
+        // {"country": {"iso_code": "FR"}}, keys pointing at the first record
+        let france = data.len() as u32;
+        data.extend([0xe1, 0x20, 0x01, 0xe1, 0x20, 0x0a]);
+        data.extend(string("FR"));
+
+        // Node 0 leads to node 1 for 0/1; node 1 splits into the records
+        let node_count = 2u32;
+        let mut file = Vec::new();
+        for record in [1, node_count, node_count + 16, node_count + 16 + france]
+        {
+            file.extend_from_slice(&record.to_be_bytes()[1..]);
+        }
+        file.extend([0; DATA_SEPARATOR]);
+        file.extend(data);
+        file.extend_from_slice(METADATA_MARKER);
+        file.push(0xe4);
+        file.extend(string("node_count"));
+        file.extend([0xc1, node_count as u8]);
+        file.extend(string("record_size"));
+        file.extend([0xa1, 24]);
+        file.extend(string("ip_version"));
+        file.extend([0xa1, 4]);
+        file.extend(string("database_type"));
+        file.extend(string("Test-Country"));
+        file
+    }
+
+    #[test]
+    fn test_countries_are_looked_up() {
+        let reader = Reader::from_bytes(database()).unwrap();
+        assert_eq!(reader.database_type, "Test-Country");
+        let country = |ip: &str| reader.country(ip.parse().unwrap()).unwrap();
+        assert_eq!(country("1.2.3.4").as_deref(), Some("AU"));
+        assert_eq!(country("100.64.0.1").as_deref(), Some("FR"));
+        assert_eq!(country("::ffff:1.2.3.4").as_deref(), Some("AU"));
+        assert_eq!(country("200.0.0.1"), None);
+        assert_eq!(country("2001:db8::1"), None);
+
+        assert!(Reader::from_bytes(b"not a database".to_vec()).is_err());
+    }
+}
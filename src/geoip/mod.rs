@@ -0,0 +1,68 @@
+// Country lookups for client addresses, used to turn away SFTP, FTPS and
+// management API clients by country and to tag sessions and audit entries
+pub mod mmdb;
+
+use crate::config::settings::Settings;
+use crate::error::Result;
+use crate::sftp::PeerFilter;
+use mmdb::Reader;
+use std::net::IpAddr;
+use tokio::sync::watch;
+use tracing::{debug, info};
+
+pub struct GeoIp {
+    reader: Reader,
+    // Country lists are read at every check so they apply on reload
+    settings: watch::Receiver<Settings>,
+}
+
+impl GeoIp {
+    // Load the configured database; None when geoip.database_file is empty
+    pub fn open(settings: watch::Receiver<Settings>) -> Result<Option<Self>> {
+        let path = settings.borrow().geoip.database_file.clone();
+        if path.is_empty() {
+            return Ok(None);
+        }
+        let reader = Reader::open(&path)?;
+        info!("Loaded GeoIP database {} ({})", path, reader.database_type);
+        Ok(Some(Self { reader, settings }))
+    }
+}
+
+impl PeerFilter for GeoIp {
+    fn country(&self, ip: IpAddr) -> Option<String> {
+        self.reader.country(ip).unwrap_or_else(|e| {
+            debug!("GeoIP lookup of {} failed: {}", ip, e);
+            None
+        })
+    }
+
+    fn allows(&self, country: Option<&str>) -> bool {
+        self.settings.borrow().geoip.allows(country)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::settings::GeoIpSettings;
+
+    #[test]
+    fn test_country_lists() {
+        let mut settings = GeoIpSettings::default();
+        assert!(settings.allows(Some("RU")));
+        assert!(settings.allows(None));
+
+        settings.allow_countries = vec!["de".to_string(), "FR".to_string()];
+        assert!(settings.allows(Some("DE")));
+        assert!(!settings.allows(Some("US")));
+        assert!(settings.allows(None));
+        settings.allow_unknown = false;
+        assert!(!settings.allows(None));
+
+        // Denied wins over allowed
+        settings.deny_countries = vec!["FR".to_string()];
+        assert!(!settings.allows(Some("FR")));
+        settings.allow_countries.clear();
+        assert!(settings.allows(Some("US")));
+    }
+}
@@ -0,0 +1,170 @@
+// Authentication of gRPC calls, matching the REST middleware: the country
+// filter first, then an admin key or a client certificate once access to
+// the management API is restricted
+use crate::api::scoped_keys::{KeyHolder, holder_of_key};
+use crate::api::tls::{ApiClient, certificate_role};
+use crate::config::settings::{ApiRole, Settings};
+use crate::sftp::PeerFilter;
+use std::sync::Arc;
+use tokio::sync::watch;
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+use tracing::warn;
+
+#[derive(Clone)]
+pub struct GrpcAuth {
+    settings: watch::Receiver<Settings>,
+    peer_filter: Option<Arc<dyn PeerFilter>>,
+}
+
+impl GrpcAuth {
+    pub fn new(
+        settings: watch::Receiver<Settings>,
+        peer_filter: Option<Arc<dyn PeerFilter>>,
+    ) -> Self {
+        Self { settings, peer_filter }
+    }
+}
+
+impl Interceptor for GrpcAuth {
+    fn call(
+        &mut self,
+        mut request: Request<()>,
+    ) -> Result<Request<()>, Status> {
+        let client = request.extensions().get::<ApiClient>().cloned();
+        if let (Some(filter), Some(client)) = (&self.peer_filter, &client) {
+            let country = filter.country(client.addr.ip());
+            if !filter.allows(country.as_deref()) {
+                warn!(
+                    "Rejected gRPC call from {} (country {})",
+                    client.addr,
+                    country.as_deref().unwrap_or("unknown")
+                );
+                return Err(Status::permission_denied(
+                    "Calls from this location are not allowed",
+                ));
+            }
+        }
+
+        let key = request
+            .metadata()
+            .get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(str::trim);
+        let by_key = key.is_some();
+        match authorize(&self.settings.borrow(), client.as_ref(), key) {
+            Ok(Some(role)) => {
+                request.extensions_mut().insert(role);
+                // Only admins' keys are let through
+                if by_key {
+                    request.extensions_mut().insert(KeyHolder::Admin);
+                }
+                Ok(request)
+            }
+            Ok(None) => Ok(request),
+            Err(status) => {
+                warn!(
+                    "Rejected gRPC call from {}: {}",
+                    client
+                        .map_or("unknown".to_string(), |c| c.addr.to_string()),
+                    status.message()
+                );
+                Err(status)
+            }
+        }
+    }
+}
+
+// Role a call is made with, None while the management API is open to all
+fn authorize(
+    settings: &Settings,
+    client: Option<&ApiClient>,
+    key: Option<&str>,
+) -> Result<Option<ApiRole>, Status> {
+    if let Some(key) = key {
+        // Every call manages the default server or all users, which no
+        // tenant's or instance's key may
+        return match holder_of_key(settings, key) {
+            Some(KeyHolder::Admin) => Ok(Some(ApiRole::Admin)),
+            Some(KeyHolder::Scoped(scope)) => {
+                Err(Status::permission_denied(format!(
+                    "The API key only grants access to {} over REST",
+                    scope
+                )))
+            }
+            None => Err(Status::unauthenticated("Unknown API key")),
+        };
+    }
+    if let Some(fingerprint) = client.and_then(|c| c.certificate.as_deref()) {
+        return match certificate_role(&settings.server.tls, fingerprint) {
+            Some(role) => Ok(Some(role)),
+            None => Err(Status::permission_denied(
+                "The client certificate has no role",
+            )),
+        };
+    }
+    let restricted = settings.has_scoped_api_keys()
+        || !settings.server.admin_api_keys.is_empty()
+        || settings.verifies_client_certificates();
+    if restricted {
+        return Err(Status::unauthenticated(
+            "An API key or client certificate is required",
+        ));
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::settings::{ClientRoleSettings, TenantSettings};
+    use tonic::Code;
+
+    #[test]
+    fn test_calls_need_an_admin_key_or_certificate_once_restricted() {
+        let mut settings = Settings::default();
+        let client = ApiClient {
+            addr: "192.0.2.1:4000".parse().unwrap(),
+            certificate: None,
+        };
+        // Nothing configured, like the REST API
+        assert_eq!(authorize(&settings, Some(&client), None).unwrap(), None);
+
+        settings.server.admin_api_keys =
+            vec!["admin-0123456789ab".to_string().into()];
+        let denied = authorize(&settings, Some(&client), None).unwrap_err();
+        assert_eq!(denied.code(), Code::Unauthenticated);
+        let wrong = authorize(&settings, None, Some("admin-0123456789ac"));
+        assert_eq!(wrong.unwrap_err().code(), Code::Unauthenticated);
+        assert_eq!(
+            authorize(&settings, None, Some("admin-0123456789ab")).unwrap(),
+            Some(ApiRole::Admin)
+        );
+
+        settings.tenants.push(TenantSettings {
+            name: "acme".to_string(),
+            root_dir: "/srv/tenants/acme".to_string(),
+            quota_bytes: 0,
+            api_keys: vec!["acme-0123456789ab".to_string().into()],
+            instance: None,
+        });
+        let scoped = authorize(&settings, None, Some("acme-0123456789ab"));
+        assert_eq!(scoped.unwrap_err().code(), Code::PermissionDenied);
+
+        settings.server.tls.client_roles.push(ClientRoleSettings {
+            fingerprint: "ab".repeat(32),
+            role: ApiRole::ReadOnly,
+        });
+        let certified =
+            ApiClient { certificate: Some("ab".repeat(32)), ..client.clone() };
+        assert_eq!(
+            authorize(&settings, Some(&certified), None).unwrap(),
+            Some(ApiRole::ReadOnly)
+        );
+        let unknown =
+            ApiClient { certificate: Some("cd".repeat(32)), ..client };
+        let denied = authorize(&settings, Some(&unknown), None).unwrap_err();
+        assert_eq!(denied.code(), Code::PermissionDenied);
+    }
+}
@@ -0,0 +1,104 @@
+// Connections to the gRPC server, over TLS when the management API uses
+// it. Each carries its ApiClient for the interceptor and the handlers.
+use crate::api::tls::{ApiClient, TlsListener};
+use axum::serve::Listener;
+use futures_util::stream::{self, BoxStream, StreamExt};
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tonic::transport::server::Connected;
+use tracing::warn;
+
+trait Io: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Io for T {}
+
+pub struct GrpcConnection {
+    io: Box<dyn Io>,
+    client: ApiClient,
+}
+
+impl Connected for GrpcConnection {
+    type ConnectInfo = ApiClient;
+
+    fn connect_info(&self) -> ApiClient {
+        self.client.clone()
+    }
+}
+
+impl AsyncRead for GrpcConnection {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for GrpcConnection {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().io).poll_write(cx, buf)
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_shutdown(cx)
+    }
+}
+
+// Connections accepted on `listener`, after the TLS handshake with
+// `acceptor` when one is given
+pub fn incoming(
+    listener: TcpListener,
+    acceptor: Option<TlsAcceptor>,
+) -> io::Result<BoxStream<'static, io::Result<GrpcConnection>>> {
+    let Some(acceptor) = acceptor else {
+        return Ok(stream::unfold(listener, accept_plain).boxed());
+    };
+    let listener = TlsListener::new(listener, acceptor)?;
+    Ok(stream::unfold(listener, |mut listener| async move {
+        let (stream, addr) = listener.accept().await;
+        let client = ApiClient::tls(&stream, addr);
+        let connection = GrpcConnection { io: Box::new(stream), client };
+        Some((Ok(connection), listener))
+    })
+    .boxed())
+}
+
+async fn accept_plain(
+    listener: TcpListener,
+) -> Option<(io::Result<GrpcConnection>, TcpListener)> {
+    loop {
+        match listener.accept().await {
+            Ok((stream, addr)) => {
+                let client = ApiClient { addr, certificate: None };
+                let connection =
+                    GrpcConnection { io: Box::new(stream), client };
+                return Some((Ok(connection), listener));
+            }
+            Err(e) => {
+                // Usually out of file descriptors; let some close
+                warn!("Failed to accept gRPC connection: {}", e);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        }
+    }
+}
@@ -0,0 +1,283 @@
+// gRPC management API. Every call goes through the same services as the
+// REST handlers, so both APIs behave alike; REST errors become gRPC
+// statuses carrying the same message.
+use crate::api::scoped_keys::KeyHolder;
+use crate::api::tls::ApiClient;
+use crate::config::settings::ApiRole;
+use crate::models::accounts::CreateUserRequest as CreateAccountRequest;
+use crate::models::sftp::{CredentialsAccessor, ToggleSftpResponse};
+use crate::responses::sftp::SftpApiResponse;
+use crate::services::supervisor::DEFAULT_INSTANCE;
+use crate::state::AppState;
+use axum::http::StatusCode;
+use proto::management_server::Management;
+use tonic::{Code, Request, Response, Status};
+use tracing::info;
+
+mod auth;
+mod listener;
+
+pub use auth::GrpcAuth;
+pub use listener::incoming;
+
+pub mod proto {
+    tonic::include_proto!("sftpmanager.v1");
+}
+
+pub use proto::management_server::ManagementServer;
+
+pub struct ManagementService {
+    state: AppState,
+}
+
+impl ManagementService {
+    pub fn new(state: AppState) -> Self {
+        Self { state }
+    }
+}
+
+#[tonic::async_trait]
+impl Management for ManagementService {
+    async fn toggle(
+        &self,
+        request: Request<proto::ToggleRequest>,
+    ) -> Result<Response<proto::ToggleResponse>, Status> {
+        info!("gRPC toggle SFTP request");
+        require_admin(&request)?;
+        let accessor = grpc_accessor(&request);
+        let service = &self.state.sftp_service;
+        let response =
+            into_result(service.toggle(DEFAULT_INSTANCE, accessor).await)?;
+        Ok(Response::new(toggle_response(response)))
+    }
+
+    async fn get_status(
+        &self,
+        _: Request<proto::GetStatusRequest>,
+    ) -> Result<Response<proto::Status>, Status> {
+        info!("gRPC get SFTP status request");
+        let status = into_result(self.state.sftp_service.get_status().await)?;
+        Ok(Response::new(proto::Status {
+            enabled: status.enabled,
+            expires_at: status.expires_at,
+            expiring_soon: status.expiring_soon,
+            schedule_open: status.schedule_open,
+            failure: status.failure.map(|f| f.reason),
+            draining: status.drain.is_some_and(|d| !d.completed),
+            low_disk: status.disk.is_some_and(|d| d.low),
+            running: status.running,
+            listen_addr: status.listen_addr,
+            active_sessions: status.active_sessions as u32,
+            last_error: status.last_error,
+        }))
+    }
+
+    async fn get_credentials(
+        &self,
+        request: Request<proto::GetCredentialsRequest>,
+    ) -> Result<Response<proto::Credentials>, Status> {
+        info!("gRPC get SFTP credentials request");
+        require_admin(&request)?;
+        let accessor = grpc_accessor(&request);
+        let service = &self.state.sftp_service;
+        let credentials =
+            match service.get_credentials(DEFAULT_INSTANCE, accessor).await {
+                Ok(response) => into_result(response)?,
+                Err(response) => return Err(into_result(response).unwrap_err()),
+            };
+        Ok(Response::new(proto::Credentials {
+            username: credentials.username,
+            password: credentials.password.expose().to_string(),
+            bind_addrs: credentials.bind_addrs,
+            port: credentials.port.into(),
+            root_dir: credentials.root_dir,
+            host: credentials.host,
+        }))
+    }
+
+    async fn rotate_credentials(
+        &self,
+        request: Request<proto::RotateCredentialsRequest>,
+    ) -> Result<Response<proto::ToggleResponse>, Status> {
+        info!("gRPC rotate SFTP credentials request");
+        require_admin(&request)?;
+        let accessor = grpc_accessor(&request);
+        let service = &self.state.sftp_service;
+        let response = into_result(
+            service.rotate_credentials(DEFAULT_INSTANCE, accessor).await,
+        )?;
+        Ok(Response::new(toggle_response(response)))
+    }
+
+    async fn list_users(
+        &self,
+        _: Request<proto::ListUsersRequest>,
+    ) -> Result<Response<proto::ListUsersResponse>, Status> {
+        info!("gRPC list users request");
+        let users = into_result(self.state.accounts.list_users().await)?;
+        Ok(Response::new(proto::ListUsersResponse {
+            users: users.into_iter().map(user).collect(),
+        }))
+    }
+
+    async fn create_user(
+        &self,
+        request: Request<proto::CreateUserRequest>,
+    ) -> Result<Response<proto::User>, Status> {
+        info!("gRPC create user request");
+        require_admin(&request)?;
+        let request = request.into_inner();
+        let request = CreateAccountRequest {
+            username: request.username,
+            access_windows: request.access_windows,
+        };
+        let account =
+            into_result(self.state.accounts.create_user(request).await)?;
+        Ok(Response::new(user(account)))
+    }
+
+    async fn delete_user(
+        &self,
+        request: Request<proto::DeleteUserRequest>,
+    ) -> Result<Response<proto::DeleteUserResponse>, Status> {
+        info!("gRPC delete user request");
+        require_admin(&request)?;
+        let username = request.into_inner().username;
+        into_result(self.state.accounts.delete_user(&username).await)?;
+        Ok(Response::new(proto::DeleteUserResponse {}))
+    }
+
+    async fn list_sessions(
+        &self,
+        _: Request<proto::ListSessionsRequest>,
+    ) -> Result<Response<proto::ListSessionsResponse>, Status> {
+        info!("gRPC list SFTP sessions request");
+        let sessions = into_result(self.state.sftp_service.get_sessions())?;
+        Ok(Response::new(proto::ListSessionsResponse {
+            sessions: sessions
+                .into_iter()
+                .map(|s| {
+                    let negotiated = s.negotiated;
+                    proto::Session {
+                        id: s.id,
+                        peer: s.peer_addr.map(|a| a.to_string()),
+                        username: s.username,
+                        connected_at: s.connected_at.to_rfc3339(),
+                        open_files: s.open_files as u64,
+                        country: s.country,
+                        memory_bytes: s.memory_bytes as u64,
+                        client: s.client,
+                        auth_method: s.auth_method,
+                        kex: negotiated.as_ref().map(|n| n.kex.clone()),
+                        host_key: negotiated
+                            .as_ref()
+                            .map(|n| n.host_key.clone()),
+                        cipher: negotiated.as_ref().map(|n| n.cipher.clone()),
+                        mac: negotiated.and_then(|n| n.mac),
+                    }
+                })
+                .collect(),
+        }))
+    }
+}
+
+// Refuses read-only clients calls that change something or return
+// credentials, as the REST API does
+fn require_admin<T>(request: &Request<T>) -> Result<(), Status> {
+    match request.extensions().get::<ApiRole>() {
+        Some(ApiRole::ReadOnly) => Err(Status::permission_denied(
+            "The client certificate's role does not allow this",
+        )),
+        _ => Ok(()),
+    }
+}
+
+// Who is asking for credentials over gRPC
+fn grpc_accessor<T>(request: &Request<T>) -> CredentialsAccessor {
+    let client = request.extensions().get::<ApiClient>();
+    CredentialsAccessor {
+        api: "grpc",
+        client: client.map(|c| c.addr.ip().to_string()),
+        certificate: client.and_then(|c| c.certificate.clone()),
+        api_key: request.extensions().get::<KeyHolder>().map(KeyHolder::label),
+        ..Default::default()
+    }
+}
+
+fn toggle_response(response: ToggleSftpResponse) -> proto::ToggleResponse {
+    let (username, password) = match response.credentials {
+        Some(c) => (Some(c.username), Some(c.password.expose().to_string())),
+        None => (None, None),
+    };
+    proto::ToggleResponse {
+        status: response.status,
+        enabled: response.enabled,
+        username,
+        password,
+        expires_at: response.expires_at,
+    }
+}
+
+fn user(account: crate::store::UserAccount) -> proto::User {
+    proto::User {
+        id: account.id,
+        username: account.username,
+        created_at: account.created_at.to_rfc3339(),
+        access_windows: account.access_windows,
+    }
+}
+
+// Payload of a successful REST response, or its error as a gRPC status
+fn into_result<T: serde::Serialize>(
+    response: SftpApiResponse<T>,
+) -> Result<T, Status> {
+    match response.sftp {
+        Some(value) if response.status.is_success() => Ok(value),
+        _ => Err(Status::new(
+            status_code(response.status),
+            response.message.unwrap_or_default(),
+        )),
+    }
+}
+
+fn status_code(status: StatusCode) -> Code {
+    match status {
+        StatusCode::BAD_REQUEST => Code::InvalidArgument,
+        StatusCode::NOT_FOUND => Code::NotFound,
+        StatusCode::CONFLICT => Code::AlreadyExists,
+        StatusCode::FORBIDDEN => Code::PermissionDenied,
+        StatusCode::SERVICE_UNAVAILABLE => Code::FailedPrecondition,
+        StatusCode::INTERNAL_SERVER_ERROR => Code::Internal,
+        _ => Code::Unknown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rest_errors_become_statuses() {
+        let ok = into_result(SftpApiResponse::success(7));
+        assert_eq!(ok.unwrap(), 7);
+
+        let missing = into_result::<()>(SftpApiResponse::error(
+            StatusCode::NOT_FOUND,
+            "User 'x' not found",
+        ))
+        .unwrap_err();
+        assert_eq!(missing.code(), Code::NotFound);
+        assert_eq!(missing.message(), "User 'x' not found");
+    }
+
+    #[test]
+    fn test_read_only_clients_cannot_change_or_read_secrets() {
+        let mut request = Request::new(proto::GetCredentialsRequest {});
+        assert!(require_admin(&request).is_ok());
+        request.extensions_mut().insert(ApiRole::Admin);
+        assert!(require_admin(&request).is_ok());
+        request.extensions_mut().insert(ApiRole::ReadOnly);
+        let denied = require_admin(&request).unwrap_err();
+        assert_eq!(denied.code(), Code::PermissionDenied);
+    }
+}
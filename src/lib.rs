@@ -1 +1,4 @@
+pub mod error;
+pub mod events;
 pub mod sftp;
+pub mod stats;
@@ -1,101 +1,955 @@
 mod api;
+mod cli;
 mod config;
+mod error;
+mod events;
+mod ftps;
+mod geoip;
+mod grpc;
 mod models;
 mod responses;
+mod s3;
+mod schedule;
 mod services;
 mod sftp;
 mod state;
+mod stats;
+mod store;
+mod systemd;
 mod utils;
 
-use crate::api::routes::{configure_health_routes, configure_sftp_routes};
-use crate::config::settings::Settings;
-use crate::models::sftp::SftpState;
-use crate::services::sftp_lifecycle::start_sftp_lifecycle;
+use crate::api::access_log::access_log_layer;
+use crate::api::geoip::restrict_countries;
+use crate::api::routes::{
+    configure_admin_routes, configure_cluster_routes, configure_config_routes,
+    configure_files_routes, configure_health_routes, configure_instance_routes,
+    configure_sftp_routes, configure_tenant_routes, configure_ui_routes,
+};
+use crate::api::scoped_keys::scope_api_keys;
+use crate::api::tls::{
+    ApiClient, TlsListener, api_tls_acceptor, require_client_role,
+};
+use crate::cli::{Cli, Command};
+use crate::config::settings::{
+    FileIoBackend, LoggingSettings, Settings, SettingsSource,
+};
+use crate::config::validation::{
+    Severity, ValidationContext, has_errors, validate,
+};
+use crate::error::Error;
+use crate::events::EventBus;
+use crate::geoip::GeoIp;
+use crate::grpc::{GrpcAuth, ManagementServer, ManagementService};
+use crate::models::sftp::{ListenAddress, SftpState};
+use crate::s3::S3Gateway;
+use crate::schedule::Schedule;
+use crate::services::access_hours::AccountAccessHours;
+use crate::services::account_logins::RepositoryLogins;
+use crate::services::accounts::AccountService;
+use crate::services::audit::{AuditRecorder, AuditService};
+use crate::services::backup::BackupService;
+use crate::services::cluster::ClusterService;
+use crate::services::config_reload::ConfigReloader;
+use crate::services::connection_hooks::ConfiguredConnectionHooks;
+use crate::services::credential_delivery::CredentialDelivery;
+use crate::services::disk_usage::DiskUsageService;
+use crate::services::event_stream::EventStreamer;
+use crate::services::extract::ExtractService;
+use crate::services::file_index::FileIndex;
+use crate::services::file_tags::FileTagService;
+use crate::services::files::FileService;
+use crate::services::fs_watcher::FsWatcher;
+use crate::services::integrity::IntegrityService;
+use crate::services::journal::{EventJournal, JournalService};
+use crate::services::mdns::MdnsAdvertiser;
+use crate::services::mirror::MirrorService;
+use crate::services::pipeline::UploadPipeline;
+use crate::services::port_mapping::PortMapper;
+use crate::services::privacy::PrivacyService;
+use crate::services::redis_state::RedisStateStore;
+use crate::services::retention::RetentionService;
+use crate::services::secrets::{self, EncryptedStateBackend, SecretCipher};
 use crate::services::sftp_service::SftpService;
+use crate::services::state_store::{FileStateStore, StateBackend};
+use crate::services::supervisor::{DEFAULT_INSTANCE, SftpSupervisor};
+use crate::services::tenants::TenantService;
+use crate::services::tus::TusService;
+use crate::services::upload_limits::UploadLimiter;
+use crate::services::vault::{VaultClient, VaultStateStore};
+use crate::services::virus_scan::VirusScanner;
+use crate::services::webhook::WebhookDispatcher;
+use crate::sftp::hooks::{ConnectionHook, ConnectionHooks, UploadHook};
+use crate::sftp::{
+    AccessHours, AccountLogins, AuthFailureTracker, ByteRangeLocks,
+    ClientVersions, DatedLanding, DiskGuard, FileIo, OwnerNames, PathRules,
+    PeerFilter, ServerContext, SessionRegistry, Tarpit, UploadCompletion,
+    UploadProgress,
+};
+use crate::stats::SftpStats;
 use crate::utils::logger::init_logging;
 
-use axum::Router;
+use axum::{Router, extract::DefaultBodyLimit, middleware};
 use chrono::Utc;
+use clap::Parser;
 use state::AppState;
-use std::{net::SocketAddr, sync::Arc};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::{sync::Arc, time::Duration};
 use tokio::signal;
-use tower_http::trace::TraceLayer;
-use tracing::info;
+use tokio::sync::watch;
+use tracing::{error, info, warn};
+
+// Environment variable holding the new key for the `rekey` command
+const NEW_SECRET_KEY_ENV: &str = "SFTP_MANAGER_NEW_SECRET_KEY";
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    init_logging();
+async fn main() {
+    let cli = Cli::parse();
+
+    let source = cli.settings_source();
+    let settings = match Settings::load(&source) {
+        Ok(settings) => settings,
+        Err(e) if cli.healthcheck => {
+            eprintln!("Failed to load configuration: {}", e);
+            std::process::exit(1);
+        }
+        Err(e) => {
+            let _guard =
+                init_logging(cli.log_format, &LoggingSettings::default());
+            let e = Error::Config(e.to_string());
+            error!("❌ {}", e);
+            drop(_guard);
+            std::process::exit(e.exit_code());
+        }
+    };
+    // Runs before logging is set up, so probes leave no trace in the logs
+    if cli.healthcheck {
+        std::process::exit(healthcheck(&settings).await);
+    }
+
+    // Flushes the log file on exit
+    let _log_guard = match init_logging(cli.log_format, &settings.logging) {
+        Ok(guard) => guard,
+        Err(source) => {
+            let e =
+                Error::LogFile { file: settings.logging.file.clone(), source };
+            eprintln!("error: {}", e);
+            std::process::exit(e.exit_code());
+        }
+    };
+
+    if cli.validate_config {
+        let issues = validate(&settings, &ValidationContext::default());
+        for issue in &issues {
+            let severity = match issue.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            };
+            println!("{}: {}: {}", severity, issue.field, issue.message);
+        }
+        if has_errors(&issues) {
+            std::process::exit(1);
+        }
+        println!("Configuration is valid");
+        return;
+    }
 
-    let settings = Settings::new().expect("Failed to load configuration");
+    if let Some(Command::Rekey) = cli.command {
+        if let Err(e) = rekey_secrets(&settings).await {
+            error!("❌ {}", e);
+            drop(_log_guard);
+            std::process::exit(e.exit_code());
+        }
+        return;
+    }
+
+    if let Err(e) = serve(settings, source).await {
+        error!("❌ {}", e);
+        // Exiting skips destructors, so flush the log file first
+        drop(_log_guard);
+        std::process::exit(e.exit_code());
+    }
+}
+
+// Runs the API and SFTP servers until a shutdown signal
+async fn serve(
+    settings: Settings,
+    source: SettingsSource,
+) -> Result<(), Error> {
     info!("Starting SFTP Manager API Server");
     info!("Version: {}", env!("CARGO_PKG_VERSION"));
+    if let Some(environment) = &source.environment {
+        info!("Environment: {}", environment);
+    }
+
+    // A fresh install has no root directories yet
+    let root_dirs = std::iter::once(&settings.sftp.root_dir)
+        .chain(settings.instances.iter().map(|i| &i.root_dir))
+        .chain(settings.tenants.iter().map(|t| &t.root_dir));
+    for root_dir in root_dirs {
+        if let Err(e) = std::fs::create_dir_all(root_dir) {
+            warn!("Could not create {}: {}", root_dir, e);
+        }
+    }
+
+    let issues = validate(&settings, &ValidationContext::default());
+    for issue in &issues {
+        match issue.severity {
+            Severity::Error => error!("{}: {}", issue.field, issue.message),
+            Severity::Warning => warn!("{}: {}", issue.field, issue.message),
+        }
+    }
+    if has_errors(&issues) {
+        let errors = issues
+            .iter()
+            .filter(|issue| matches!(issue.severity, Severity::Error))
+            .count();
+        return Err(Error::Config(format!(
+            "{} error(s), refusing to start",
+            errors
+        )));
+    }
+
+    // Settings that may change at runtime through a configuration reload
+    let (settings_tx, settings_rx) = watch::channel(settings.clone());
+
+    // Event bus shared by the API, lifecycle manager and SFTP sessions
+    let events = EventBus::default();
+    let journal = if settings.journal.enabled {
+        match EventJournal::open(
+            &settings.journal.dir,
+            settings.journal.max_file_bytes,
+            settings.journal.max_files,
+        ) {
+            Ok(journal) => {
+                let journal = Arc::new(std::sync::Mutex::new(journal));
+                let _journal_handle =
+                    EventJournal::start(journal.clone(), &events);
+                Some(journal)
+            }
+            Err(e) => return Err(Error::Journal(e)),
+        }
+    } else {
+        None
+    };
+    let mirror = Arc::new(MirrorService::new(
+        settings.sftp.root_dir.clone(),
+        settings_rx.clone(),
+        events.clone(),
+    ));
+    if settings.mirror.enabled {
+        let _mirror_handles = mirror.start();
+    }
+
+    let repository = if settings.database.url.is_empty() {
+        None
+    } else {
+        match store::open(&settings.database.url, settings.database.pool_size)
+            .await
+        {
+            Ok(repository) => Some(repository),
+            Err(e) => return Err(Error::Database(format!("{:#}", e))),
+        }
+    };
+    if let Some(repository) = &repository {
+        let _audit_handle =
+            AuditRecorder::new(repository.clone()).start(&events);
+    }
+    let _webhook_handle =
+        WebhookDispatcher::new(settings_rx.clone(), repository.clone())
+            .start(&events);
+    if settings.event_stream.enabled {
+        match EventStreamer::connect(&settings.event_stream).await {
+            Ok(streamer) => {
+                let _stream_handle = streamer.start(&events);
+            }
+            Err(e) => error!("Event streaming is disabled: {}", e),
+        }
+    }
+
+    let vault = match VaultClient::from_settings(&settings.vault).await {
+        Ok(vault) => vault,
+        Err(e) => return Err(Error::Vault(format!("{:#}", e))),
+    };
+    if let Some(vault) = &vault {
+        let _renew_handle = vault.clone().renew();
+    }
+
+    // A fixed host key spares clients a changed fingerprint on every start
+    let host_key = if !settings.sftp.host_key_file.is_empty() {
+        match russh::keys::load_secret_key(&settings.sftp.host_key_file, None) {
+            Ok(key) => Some(key),
+            Err(e) => {
+                return Err(Error::HostKeyFile {
+                    file: settings.sftp.host_key_file.clone(),
+                    message: e.to_string(),
+                });
+            }
+        }
+    } else if let Some(vault) = &vault {
+        match vault.host_key().await {
+            Ok(key) => Some(key),
+            Err(e) => {
+                return Err(Error::Vault(format!(
+                    "cannot load the host key: {:#}",
+                    e
+                )));
+            }
+        }
+    } else {
+        None
+    };
+
+    let mut upload_hooks: Vec<Arc<dyn UploadHook>> = Vec::new();
+    match VirusScanner::from_settings(&settings.scan, events.clone()).await {
+        Ok(Some(scanner)) => upload_hooks.push(Arc::new(scanner)),
+        Ok(None) => {}
+        Err(e) => return Err(Error::VirusScan(format!("{:#}", e))),
+    }
+    // Runs after scanning so infected files are never processed
+    upload_hooks.push(Arc::new(UploadPipeline::new(
+        settings_rx.clone(),
+        events.clone(),
+    )));
+
+    let peer_filter: Option<Arc<dyn PeerFilter>> =
+        GeoIp::open(settings_rx.clone())?
+            .map(|geoip| Arc::new(geoip) as Arc<dyn PeerFilter>);
+
+    let file_io = match settings.sftp.file_io {
+        FileIoBackend::Tokio => FileIo::default(),
+        FileIoBackend::IoUring => FileIo::io_uring().unwrap_or_else(|e| {
+            warn!("io_uring is unavailable, using tokio for file I/O: {}", e);
+            FileIo::default()
+        }),
+    };
+    if file_io.is_io_uring() {
+        info!("SFTP file reads and writes go through io_uring");
+    }
+
+    // Read from the live settings, so hooks configured on reload run too
+    let connection_hooks: Vec<Arc<dyn ConnectionHook>> =
+        vec![Arc::new(ConfiguredConnectionHooks::new(settings_rx.clone()))];
+
+    let completion = UploadCompletion::new(
+        settings.completion.rules(),
+        upload_hooks.into(),
+        events.clone(),
+    );
+
+    // Shared by the SFTP server across restarts and by the API
+    let context = ServerContext {
+        events,
+        auth_failures: AuthFailureTracker::new(
+            settings.sftp.auth_failure_limits(),
+        ),
+        stats: SftpStats::new(),
+        sessions: SessionRegistry::default(),
+        host_key,
+        checksum_algorithms: settings.sftp.checksum_algorithms.clone().into(),
+        sparse_files: settings.sftp.sparse_files,
+        completion,
+        connection_hooks: ConnectionHooks::new(connection_hooks),
+        trash: settings.trash.enabled,
+        scratch: settings.scratch.dir(),
+        filenames: settings.filenames.policy(),
+        path_rules: PathRules::new(settings.sftp.path_rules.clone()),
+        landing: DatedLanding::new(settings.landing.rules()),
+        locks: ByteRangeLocks::default(),
+        uploads: UploadProgress::default(),
+        peer_filter: peer_filter.clone(),
+        client_versions: ClientVersions::new(
+            settings.sftp.client_version_rules(),
+        ),
+        tarpit: Tarpit::new(settings.sftp.tarpit_limits()),
+        owner_names: OwnerNames::default(),
+        disk_space: DiskGuard::new(settings.sftp.disk_limits()),
+        recordings: settings.recording.policy(),
+        access_hours: repository.clone().map(|repository| {
+            Arc::new(AccountAccessHours::new(repository))
+                as Arc<dyn AccessHours>
+        }),
+        accounts: repository.clone().map(|repository| {
+            Arc::new(RepositoryLogins::new(repository))
+                as Arc<dyn AccountLogins>
+        }),
+        quota: None,
+        transfer_limits: settings.sftp.transfer_limits(),
+        keepalive: settings.sftp.keepalive(),
+        listing_cache: settings.listing_cache.cache(),
+        file_io,
+        session_memory_bytes: settings
+            .sftp
+            .session_memory_mb
+            .saturating_mul(1024 * 1024),
+        session_transfers: settings.sftp.session_transfers,
+    };
 
     // Initialize SFTP state
-    let sftp_bind_addrs = settings.sftp.bind_addrs.clone();
-    let sftp_port = settings.sftp.port;
     let sftp_root = settings.sftp.root_dir.clone();
-    let sftp_state = SftpState::new();
-    let sftp_service = Arc::new(SftpService::new(
-        sftp_bind_addrs.clone(),
-        sftp_port,
+    let (state_backend, redis_store) =
+        match open_state_backend(&settings, vault.as_ref()).await {
+            Ok(backends) => backends,
+            Err(e) => return Err(Error::StateStorage(format!("{:#}", e))),
+        };
+    let cipher = SecretCipher::from_settings(&settings.secrets)
+        .map_err(|e| Error::SecretKey(format!("{:#}", e)))?;
+    if cipher.is_none() && state_backend.is_some() {
+        warn!(
+            "No secret key configured, credentials are persisted in plaintext"
+        );
+    }
+    let encrypted = |backend: Arc<dyn StateBackend>| match &cipher {
+        Some(cipher) => {
+            Arc::new(EncryptedStateBackend::new(backend, cipher.clone()))
+                as Arc<dyn StateBackend>
+        }
+        None => backend,
+    };
+    let state_backend = state_backend.map(encrypted);
+
+    // Sockets passed by systemd socket activation, if any
+    let mut activated = systemd::ActivatedSockets::from_env();
+    let sftp_socket = activated.take(systemd::SFTP_SOCKET);
+
+    let mut sftp_state = match sftp_socket.as_ref().map(|s| s.local_addr()) {
+        Some(Ok(addr)) => {
+            info!("Using the SFTP socket passed by systemd on {}", addr);
+            SftpState::new(ListenAddress {
+                bind_addrs: addr.ip().to_string(),
+                port: addr.port(),
+            })
+            .with_fixed_listen_address()
+        }
+        _ => SftpState::new(ListenAddress {
+            bind_addrs: settings.sftp.bind_addrs.clone(),
+            port: settings.sftp.port,
+        }),
+    };
+    if let Some(backend) = &state_backend {
+        sftp_state = sftp_state
+            .with_store(backend.clone())
+            .with_bans(context.auth_failures.clone());
+    }
+    // The lifecycle manager resumes the server if it was enabled
+    sftp_state.restore().await;
+    let _bans_handle = sftp_state.persist_bans();
+    if let (Some(store), Some(backend)) = (&redis_store, &state_backend) {
+        let _watch_handle =
+            store.clone().watch(sftp_state.clone(), backend.clone());
+        let _heartbeat_handle =
+            store.clone().heartbeat(context.sessions.clone());
+    }
+    let schedule = Schedule::parse(&settings.schedule.windows)
+        .map_err(|e| Error::Config(format!("schedule.windows: {}", e)))?;
+    sftp_state.set_schedule(schedule).await;
+    let credential_delivery =
+        CredentialDelivery::new(settings_rx.clone(), context.events.clone());
+    let mut sftp_service = SftpService::new(
         sftp_root.clone(),
         sftp_state.clone(),
+        context.clone(),
+        settings_rx.clone(),
+    )
+    .with_credential_delivery(credential_delivery.clone());
+    if let Some(repository) = &repository {
+        sftp_service = sftp_service.with_repository(repository.clone());
+    }
+    if settings.port_mapping.enabled {
+        let port_mapper = Arc::new(PortMapper::new(
+            settings.port_mapping.clone(),
+            sftp_state.clone(),
+        ));
+        let _port_mapping_handle = port_mapper.start();
+        sftp_service = sftp_service.with_port_mapper(port_mapper);
+    }
+    let sftp_service = Arc::new(sftp_service);
+    if settings.mdns.enabled {
+        match MdnsAdvertiser::new(&settings.mdns, sftp_state.clone()) {
+            Ok(advertiser) => {
+                let _mdns_handle = advertiser.start();
+            }
+            Err(e) => warn!("mDNS advertisement disabled: {}", e),
+        }
+    }
+
+    let tenants =
+        Arc::new(TenantService::new(&settings, &context, settings_rx.clone()));
+
+    let mut supervisor = SftpSupervisor::new();
+    supervisor.add_with_listener(
+        DEFAULT_INSTANCE,
+        sftp_service.clone(),
+        sftp_socket,
+    );
+    for instance in &settings.instances {
+        let mut state = SftpState::new(ListenAddress {
+            bind_addrs: instance.bind_addrs.clone(),
+            port: instance.port,
+        });
+        if let Some(vault) = &vault {
+            let store = VaultStateStore::new(
+                vault.clone(),
+                format!("state.{}", instance.name),
+            );
+            state = state.with_store(encrypted(Arc::new(store)));
+        } else if !settings.sftp.state_file.is_empty() {
+            let path =
+                instance_state_file(&settings.sftp.state_file, &instance.name);
+            state = state
+                .with_store(encrypted(Arc::new(FileStateStore::new(path))));
+        }
+        state.restore().await;
+
+        // Sessions and locks are per instance so draining one leaves the
+        // others alone. Accounts log in on the default server only.
+        let context = ServerContext {
+            sessions: SessionRegistry::default(),
+            locks: ByteRangeLocks::default(),
+            accounts: None,
+            quota: tenants.instance_quota(&instance.name),
+            ..context.clone()
+        };
+        let mut service = SftpService::new(
+            instance.root_dir.clone(),
+            state,
+            context,
+            settings_rx.clone(),
+        )
+        .with_credential_delivery(credential_delivery.clone());
+        if let Some(repository) = &repository {
+            service = service.with_repository(repository.clone());
+        }
+        supervisor.add(&instance.name, Arc::new(service));
+    }
+    let supervisor = Arc::new(supervisor);
+
+    let _retention_handle = RetentionService::new(
+        sftp_root.clone(),
+        settings_rx.clone(),
+        context.events.clone(),
+    )
+    .start();
+
+    if settings.watcher.enabled {
+        let watcher = FsWatcher::new(
+            sftp_root.clone(),
+            Duration::from_millis(settings.watcher.debounce_ms),
+            context.events.clone(),
+        )
+        .with_listing_cache(context.listing_cache.clone());
+        if let Err(e) = watcher.start() {
+            return Err(Error::Watcher {
+                dir: sftp_root.clone(),
+                message: e.to_string(),
+            });
+        }
+    }
+
+    let disk_usage =
+        Arc::new(DiskUsageService::new(sftp_root.clone(), settings_rx.clone()));
+
+    let files = Arc::new(FileService::new(
+        sftp_root.clone(),
+        settings_rx.clone(),
+        context.clone(),
+    ));
+    let _trash_handle = files.start_purging();
+    let file_tags =
+        Arc::new(FileTagService::new(files.clone(), repository.clone()));
+    let uploads = Arc::new(UploadLimiter::new(settings_rx.clone()));
+    let tus = Arc::new(TusService::new(
+        files.clone(),
+        uploads.clone(),
+        settings_rx.clone(),
+    ));
+    let _tus_handle = tus.start_purging();
+
+    if settings.s3.enabled {
+        let gateway = Arc::new(S3Gateway::new(
+            files.clone(),
+            sftp_root.clone(),
+            uploads.clone(),
+            settings_rx.clone(),
+        ));
+        let addr = SocketAddr::from(([0, 0, 0, 0], settings.s3.port));
+        match tokio::net::TcpListener::bind(addr).await {
+            Ok(listener) => {
+                info!("S3 gateway listening on http://{}", addr);
+                let app = s3::router(gateway).layer(access_log_layer());
+                tokio::spawn(async move {
+                    let service =
+                        app.into_make_service_with_connect_info::<SocketAddr>();
+                    if let Err(e) = axum::serve(listener, service).await {
+                        error!("S3 gateway stopped: {}", e);
+                    }
+                });
+            }
+            Err(e) => {
+                error!("Failed to bind the S3 gateway to {}: {}", addr, e)
+            }
+        }
+    }
+
+    let file_index =
+        Arc::new(FileIndex::new(sftp_root.clone(), settings_rx.clone()));
+    if settings.search.enabled {
+        let _index_handles = file_index.start(&context.events);
+    }
+
+    let integrity = Arc::new(IntegrityService::new(
+        sftp_root.clone(),
+        repository.clone(),
+        settings_rx.clone(),
+        context.events.clone(),
+    ));
+    let _integrity_handle = integrity.start();
+
+    let backup = Arc::new(BackupService::new(
+        sftp_root.clone(),
+        settings_rx.clone(),
+        context.events.clone(),
     ));
+    let _backup_handle = backup.start();
 
-    let app_state = AppState { sftp_service, uptime: Utc::now() };
+    let extract = Arc::new(ExtractService::new(
+        sftp_root.clone(),
+        settings_rx.clone(),
+        context.events.clone(),
+    ));
+
+    let audit = Arc::new(AuditService::new(repository.clone()));
+    let journal = Arc::new(JournalService::new(journal));
+    let privacy = Arc::new(PrivacyService::new(
+        repository.clone(),
+        files.clone(),
+        tenants.clone(),
+        PathBuf::from(&settings.recording.dir),
+        journal.clone(),
+    ));
+    let accounts =
+        Arc::new(AccountService::new(repository, settings_rx.clone()));
+    let cluster = Arc::new(ClusterService::new(redis_store));
+
+    let config_reloader = Arc::new(ConfigReloader::new(
+        source,
+        settings_tx,
+        sftp_state.clone(),
+        context.clone(),
+    ));
+    if settings.server.watch_config
+        && let Err(e) = config_reloader.clone().watch()
+    {
+        warn!("Could not watch configuration directory: {}", e);
+    }
+
+    let app_state = AppState {
+        sftp_service,
+        supervisor: supervisor.clone(),
+        disk_usage,
+        audit,
+        files,
+        file_tags,
+        tus,
+        uploads,
+        file_index,
+        integrity,
+        extract,
+        accounts,
+        tenants,
+        privacy,
+        cluster,
+        journal,
+        mirror,
+        backup,
+        settings: settings_rx.clone(),
+        config_reloader,
+        uptime: Utc::now(),
+    };
+
+    if settings.grpc.enabled {
+        let host =
+            settings.grpc.host.parse().unwrap_or(Ipv4Addr::LOCALHOST.into());
+        let addr = SocketAddr::new(host, settings.grpc.port);
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .map_err(|e| Error::bind(addr, e))?;
+        // Served like the REST API: over its TLS settings, checking the
+        // same keys, client certificates and countries
+        let tls = &settings.server.tls;
+        let acceptor =
+            tls.enabled.then(|| api_tls_acceptor(tls)).transpose()?;
+        let incoming = grpc::incoming(listener, acceptor)
+            .map_err(|e| Error::listener("gRPC", e))?;
+        let service = ManagementServer::with_interceptor(
+            ManagementService::new(app_state.clone()),
+            GrpcAuth::new(settings_rx.clone(), peer_filter.clone()),
+        );
+        info!("gRPC management API listening on {}", addr);
+        tokio::spawn(async move {
+            if let Err(e) = tonic::transport::Server::builder()
+                .add_service(service)
+                .serve_with_incoming(incoming)
+                .await
+            {
+                error!("gRPC management API stopped: {}", e);
+            }
+        });
+    }
 
     let app = Router::new()
         .merge(configure_health_routes())
+        .merge(configure_config_routes())
+        .merge(configure_admin_routes())
+        .merge(configure_cluster_routes())
         .merge(configure_sftp_routes())
-        .with_state(app_state.clone())
-        .layer(TraceLayer::new_for_http());
-
-    // Create the TCP listener
-    let addr = SocketAddr::from(([0, 0, 0, 0], settings.server.port));
-    let listener = tokio::net::TcpListener::bind(addr)
-        .await
-        .expect("Failed to bind to address.");
-    info!("🚀 Server started successfully, listening on http://{}", addr);
+        .merge(configure_instance_routes())
+        .merge(configure_tenant_routes())
+        .merge(configure_files_routes())
+        .merge(configure_ui_routes())
+        .layer(DefaultBodyLimit::max(settings.server.max_body_bytes))
+        .with_state(app_state.clone());
+    // Client certificates are only verified with a client CA bundle
+    let tls = &settings.server.tls;
+    let app = if tls.enabled && !tls.client_ca_file.is_empty() {
+        app.layer(middleware::from_fn_with_state(
+            settings_rx.clone(),
+            require_client_role,
+        ))
+    } else {
+        app
+    };
+    // API keys are looked up in the current settings, so keys added by a
+    // reload take effect
+    let app = app.layer(middleware::from_fn_with_state(
+        settings_rx.clone(),
+        scope_api_keys,
+    ));
+    let app = match peer_filter {
+        Some(filter) => app
+            .layer(middleware::from_fn_with_state(filter, restrict_countries)),
+        None => app,
+    };
+    let app = app.layer(access_log_layer());
 
-    let _sftp_handle =
-        start_sftp_lifecycle(sftp_state, sftp_bind_addrs, sftp_port, sftp_root);
+    // Create the TCP listener, unless systemd passed one
+    let listener = match activated.take(systemd::HTTP_SOCKET) {
+        Some(listener) => tokio::net::TcpListener::from_std(listener)
+            .map_err(Error::ActivatedSocket)?,
+        None => {
+            let addr = (settings.server.host.as_str(), settings.server.port);
+            tokio::net::TcpListener::bind(addr).await.map_err(|e| {
+                Error::bind(
+                    format!(
+                        "{}:{}",
+                        settings.server.host, settings.server.port
+                    ),
+                    e,
+                )
+            })?
+        }
+    };
+    let addr = listener.local_addr().map_err(|e| Error::listener("API", e))?;
+    let make_service = app.into_make_service_with_connect_info::<ApiClient>();
+    let shutdown = shutdown_signal()?;
+    // Stop the SFTP servers below even when the API server fails
+    let served = if tls.enabled {
+        let acceptor = api_tls_acceptor(tls)?;
+        let listener = TlsListener::new(listener, acceptor)
+            .map_err(|e| Error::listener("API", e))?;
+        info!("🚀 Server started successfully, listening on https://{}", addr);
+        tokio::spawn(notify_ready(sftp_state.clone()));
+        axum::serve(listener, make_service)
+            .with_graceful_shutdown(shutdown)
+            .await
+    } else {
+        info!("🚀 Server started successfully, listening on http://{}", addr);
+        tokio::spawn(notify_ready(sftp_state.clone()));
+        axum::serve(listener, make_service)
+            .with_graceful_shutdown(shutdown)
+            .await
+    };
+    systemd::notify("STOPPING=1");
 
-    axum::serve(listener, app.into_make_service())
-        .with_graceful_shutdown(shutdown_signal())
-        .await
-        .expect("Server error!");
+    // Stop the SFTP listeners and close sessions; the enabled state is kept
+    // so the servers resume on the next start
+    supervisor.shutdown().await;
+    sftp_state.persist().await;
 
+    served.map_err(|e| Error::listener("API", e))?;
     info!("Server stopped gracefully! 🧘");
-
     Ok(())
 }
 
-async fn shutdown_signal() {
-    let ctrl_c = async {
-        signal::ctrl_c().await.expect("Failed to install Ctrl+C handler");
+// Tell systemd the service is ready once the SFTP listener is bound, or
+// has failed and is being retried, or is not meant to run
+async fn notify_ready(state: SftpState) {
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(30);
+    while state.should_run().await
+        && !state.is_running().await
+        && state.get_failure().await.is_none()
+        && tokio::time::Instant::now() < deadline
+    {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    systemd::notify("READY=1");
+}
+
+// Where the SFTP state is persisted: Redis when configured, otherwise the
+// state file. The Redis store is also returned for replica coordination.
+async fn open_state_backend(
+    settings: &Settings,
+    vault: Option<&Arc<VaultClient>>,
+) -> anyhow::Result<(Option<Arc<dyn StateBackend>>, Option<Arc<RedisStateStore>>)>
+{
+    if !settings.redis.url.is_empty() {
+        let replica_id = if settings.redis.replica_id.is_empty() {
+            std::env::var("HOSTNAME")
+                .unwrap_or_else(|_| format!("replica-{}", std::process::id()))
+        } else {
+            settings.redis.replica_id.clone()
+        };
+        let store = Arc::new(
+            RedisStateStore::connect(
+                &settings.redis.url,
+                &settings.redis.key_prefix,
+                &replica_id,
+            )
+            .await?,
+        );
+        return Ok((Some(store.clone()), Some(store)));
+    }
+
+    if let Some(vault) = vault {
+        let store = Arc::new(VaultStateStore::new(vault.clone(), "state"));
+        return Ok((Some(store), None));
+    }
+
+    if !settings.sftp.state_file.is_empty() {
+        let store = Arc::new(FileStateStore::new(&settings.sftp.state_file));
+        return Ok((Some(store), None));
+    }
+
+    Ok((None, None))
+}
+
+// State file of an additional instance, e.g. "sftp_state.partner-b.json"
+// next to the default instance's "sftp_state.json"
+fn instance_state_file(state_file: &str, name: &str) -> PathBuf {
+    let path = Path::new(state_file);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match path.extension() {
+        Some(ext) => format!("{}.{}.{}", stem, name, ext.to_string_lossy()),
+        None => format!("{}.{}", stem, name),
     };
+    path.with_file_name(file_name)
+}
 
+// Exit code for --healthcheck: 0 when the local server reports ready
+async fn healthcheck(settings: &Settings) -> i32 {
+    let scheme = if settings.server.tls.enabled { "https" } else { "http" };
+    // A server listening on every address is reached over loopback
+    let host = match settings.server.host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) if ip.is_unspecified() => {
+            Ipv4Addr::LOCALHOST.to_string()
+        }
+        Ok(IpAddr::V6(ip)) if ip.is_unspecified() => {
+            format!("[{}]", Ipv6Addr::LOCALHOST)
+        }
+        Ok(IpAddr::V6(ip)) => format!("[{}]", ip),
+        _ => settings.server.host.clone(),
+    };
+    let url =
+        format!("{}://{}:{}/health/ready", scheme, host, settings.server.port);
+    // The certificate is issued for the public name, not this address
+    let client = match reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(5))
+        .danger_accept_invalid_certs(true)
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("Failed to build HTTP client: {}", e);
+            return 1;
+        }
+    };
+    match client.get(&url).send().await {
+        Ok(response) if response.status().is_success() => 0,
+        Ok(response) => {
+            eprintln!("{} returned {}", url, response.status());
+            1
+        }
+        Err(e) => {
+            eprintln!("{} is unreachable: {}", url, e);
+            1
+        }
+    }
+}
+
+// Re-encrypt persisted secrets with the key in SFTP_MANAGER_NEW_SECRET_KEY.
+// The current key is read as configured; afterwards configure the new one.
+async fn rekey_secrets(settings: &Settings) -> Result<(), Error> {
+    let new_key = std::env::var(NEW_SECRET_KEY_ENV).map_err(|_| {
+        Error::Config(format!("{} is not set", NEW_SECRET_KEY_ENV))
+    })?;
+    let new = SecretCipher::from_hex(&new_key).map_err(|e| {
+        Error::Config(format!("{}: {:#}", NEW_SECRET_KEY_ENV, e))
+    })?;
+    let old = SecretCipher::from_settings(&settings.secrets)
+        .map_err(|e| Error::SecretKey(format!("{:#}", e)))?;
+
+    let vault = VaultClient::from_settings(&settings.vault)
+        .await
+        .map_err(|e| Error::Vault(format!("{:#}", e)))?;
+    let (Some(backend), _) = open_state_backend(settings, vault.as_ref())
+        .await
+        .map_err(|e| Error::StateStorage(format!("{:#}", e)))?
+    else {
+        return Err(Error::Config(
+            "SFTP state persistence is not configured".to_string(),
+        ));
+    };
+
+    info!("Re-encrypting {} with key {}", backend.describe(), new.key_id());
+    let new_key_id = new.key_id().to_string();
+    secrets::rekey(backend, old, new)
+        .await
+        .map_err(|e| Error::Rekey(format!("{:#}", e)))?;
+    info!("✅ Secrets re-encrypted with key {}", new_key_id);
+    Ok(())
+}
+
+// Installs the Ctrl+C and SIGTERM handlers; the future returned completes
+// on the first of the signals
+fn shutdown_signal() -> Result<impl Future<Output = ()>, Error> {
     #[cfg(unix)]
-    let terminate = async {
+    let mut terminate =
         signal::unix::signal(signal::unix::SignalKind::terminate())
-            .expect("Failed to install signal handler")
-            .recv()
-            .await;
-    };
+            .map_err(|source| Error::Signal { signal: "SIGTERM", source })?;
 
-    #[cfg(not(unix))]
-    let terminate = std::future::pending::<()>();
+    Ok(async move {
+        let ctrl_c = async {
+            if let Err(e) = signal::ctrl_c().await {
+                error!("Cannot listen for Ctrl+C: {}", e);
+                std::future::pending::<()>().await;
+            }
+        };
 
-    tokio::select! {
-        _ = ctrl_c => {
-            info!("Received Ctrl+C signal, shutting down gracefully...");
-        },
-        _ = terminate => {
-            info!("Received SIGTERM signal, shutting down gracefully...");
-        },
-    }
+        #[cfg(unix)]
+        let terminate = async move {
+            terminate.recv().await;
+        };
+
+        #[cfg(not(unix))]
+        let terminate = std::future::pending::<()>();
 
-    // TODO: Add cleanup tasks here:
-    // - Close database connections
-    // - Stop SFTP server
+        tokio::select! {
+            _ = ctrl_c => {
+                info!("Received Ctrl+C signal, shutting down gracefully...");
+            },
+            _ = terminate => {
+                info!("Received SIGTERM signal, shutting down gracefully...");
+            },
+        }
+    })
 }
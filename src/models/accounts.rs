@@ -0,0 +1,94 @@
+use crate::sftp::SecretString;
+use crate::store::{ErasedUserData, UserData};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+// Request to create a user account
+#[derive(Debug, Deserialize)]
+pub struct CreateUserRequest {
+    pub username: String,
+    // Cron windows in UTC, e.g. "* 8-17 * * mon-fri"; empty allows any time
+    #[serde(default)]
+    pub access_windows: Vec<String>,
+}
+
+// Request to replace the access windows of a user
+#[derive(Debug, Deserialize)]
+pub struct SetAccessWindowsRequest {
+    pub access_windows: Vec<String>,
+}
+
+// Request to set the password a user logs in with over SFTP and FTPS
+#[derive(Debug, Deserialize)]
+pub struct SetPasswordRequest {
+    // None removes the password, leaving only the authorized keys
+    pub password: Option<SecretString>,
+}
+
+// Request to authorize a public key for a user
+#[derive(Debug, Deserialize)]
+pub struct AddKeyRequest {
+    // OpenSSH format, e.g. "ssh-ed25519 AAAA... comment"
+    pub public_key: String,
+    pub comment: Option<String>,
+}
+
+// Request to share a path below the SFTP root
+#[derive(Debug, Deserialize)]
+pub struct CreateShareRequest {
+    pub path: String,
+    pub created_by: Option<String>,
+    // Lifetime of the link; omitted means it does not expire
+    pub expires_in_secs: Option<u64>,
+}
+
+// A tenant and how much of its quota is used; API keys are not shown
+#[derive(Debug, Serialize)]
+pub struct TenantInfo {
+    pub name: String,
+    pub root_dir: String,
+    // Absent when the tenant has no quota
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quota_bytes: Option<u64>,
+    // Absent when the usage cannot be measured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub used_bytes: Option<u64>,
+    // SFTP instance serving the tenant, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+}
+
+// Everything kept about a user, answering a data-subject access request
+#[derive(Debug, Serialize)]
+pub struct UserDataExport {
+    pub username: String,
+    pub exported_at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub data: UserData,
+    // Files last uploaded by the user that are still stored
+    pub files: Vec<String>,
+}
+
+// Request to irreversibly erase a user's data
+#[derive(Debug, Deserialize)]
+pub struct EraseUserRequest {
+    // Must repeat the username, guarding against erasing the wrong user
+    pub confirm: String,
+    // Kept in the tombstone, e.g. the ticket of the erasure request
+    pub reference: Option<String>,
+}
+
+// What was erased for a user
+#[derive(Debug, Serialize)]
+pub struct ErasureReport {
+    pub username: String,
+    pub erased_at: DateTime<Utc>,
+    // Files and trashed items removed
+    pub files: usize,
+    // Session recordings deleted
+    pub recordings: usize,
+    // Event journal entries the username was redacted from
+    pub journal_entries: u64,
+    #[serde(flatten)]
+    pub records: ErasedUserData,
+}
@@ -0,0 +1,217 @@
+use crate::sftp::trash::TrashEntry;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Query naming a file by its path as seen over SFTP, e.g. "/in/a.csv"
+#[derive(Debug, Deserialize)]
+pub struct FilePathQuery {
+    pub path: String,
+}
+
+// Digests recorded when a file was last uploaded
+#[derive(Debug, Serialize)]
+pub struct FileChecksumResponse {
+    pub path: String,
+    pub checksums: BTreeMap<String, String>,
+    pub bytes: u64,
+    pub uploaded_at: DateTime<Utc>,
+    pub username: String,
+}
+
+// Result of deleting a file through the API
+#[derive(Debug, Serialize)]
+pub struct FileDeleteResponse {
+    pub path: String,
+    // Set when the item was moved to the trash instead of deleted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trash_id: Option<String>,
+}
+
+// Entries of a directory below the SFTP root
+#[derive(Debug, Serialize)]
+pub struct DirectoryListing {
+    pub path: String,
+    // Directories first, then files, each sorted by name
+    pub entries: Vec<DirectoryEntry>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DirectoryEntry {
+    pub name: String,
+    pub is_dir: bool,
+    pub bytes: u64,
+    pub modified: DateTime<Utc>,
+}
+
+// Target of an upload through the API; the file body is the request body
+#[derive(Debug, Deserialize)]
+pub struct FileUploadQuery {
+    pub path: String,
+    // Replace an existing file instead of failing
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileUploadResponse {
+    pub path: String,
+    pub bytes: u64,
+    pub checksums: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrashListResponse {
+    pub entries: Vec<TrashEntry>,
+}
+
+// Item to restore, by trash ID or by its original path. A path restores the
+// most recently deleted item that had it.
+#[derive(Debug, Deserialize)]
+pub struct RestoreRequest {
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+// Filename search. `q` is a glob when it contains `*` or `?`, matched
+// against the whole path when it contains `/` and the file name otherwise;
+// any other query matches file names containing it, ignoring case.
+#[derive(Debug, Deserialize)]
+pub struct FileSearchQuery {
+    pub q: String,
+    // Directory to search below
+    #[serde(default)]
+    pub path: String,
+    // Directory levels to descend, 1 only searches `path` itself
+    #[serde(default)]
+    pub depth: Option<usize>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+// Files carrying every one of the given tags
+#[derive(Debug, Deserialize)]
+pub struct FileTagSearchRequest {
+    pub tags: BTreeMap<String, String>,
+    #[serde(default)]
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    pub path: String,
+    pub bytes: u64,
+    pub modified: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileSearchResponse {
+    pub results: Vec<SearchResult>,
+    // More files matched than the limit allowed
+    pub truncated: bool,
+}
+
+// Outcome of the most recent integrity verification run
+#[derive(Debug, Clone, Serialize)]
+pub struct IntegrityReport {
+    pub started_at: DateTime<Utc>,
+    // Unset while the run is in progress
+    pub finished_at: Option<DateTime<Utc>>,
+    // Files re-hashed
+    pub checked: u64,
+    // Files no longer at their uploaded path
+    pub missing: u64,
+    // Files changed after their last upload, which are not compared
+    pub modified: u64,
+    // Files that could not be read
+    pub errors: u64,
+    pub mismatches: Vec<IntegrityMismatch>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IntegrityMismatch {
+    pub path: String,
+    pub algorithm: String,
+    pub expected: String,
+    pub actual: String,
+    pub uploaded_at: DateTime<Utc>,
+    pub uploaded_by: String,
+}
+
+// Archive to unpack. The target directory defaults to the archive's path
+// without its extension.
+#[derive(Debug, Deserialize)]
+pub struct ExtractRequest {
+    pub path: String,
+    #[serde(default)]
+    pub target: Option<String>,
+    // Replace files that already exist instead of failing
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExtractState {
+    Running,
+    Completed,
+    Failed,
+}
+
+// Progress of an extraction, updated after every file
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtractJob {
+    pub id: String,
+    pub path: String,
+    pub target: String,
+    pub state: ExtractState,
+    // Files and bytes written so far
+    pub files: u64,
+    pub bytes: u64,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// A file waiting to be copied to a mirror target
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MirrorJob {
+    pub id: String,
+    pub target: String,
+    pub path: String,
+    // Failed attempts so far
+    pub attempts: u32,
+    // Last time the file was queued; a change while the copy runs queues
+    // it again
+    pub queued_at: DateTime<Utc>,
+    pub next_attempt_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+// Jobs still to be attempted and jobs that ran out of attempts
+#[derive(Debug, Serialize)]
+pub struct MirrorQueueResponse {
+    pub pending: Vec<MirrorJob>,
+    pub failed: Vec<MirrorJob>,
+}
+
+// State of the backups of the root, from GET /admin/backup and /health
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BackupStatus {
+    pub running: bool,
+    pub last_started_at: Option<DateTime<Utc>>,
+    pub last_finished_at: Option<DateTime<Utc>>,
+    // End of the most recent backup that succeeded
+    pub last_success_at: Option<DateTime<Utc>>,
+    // File name and size of the most recent archive
+    pub last_archive: Option<String>,
+    pub last_bytes: Option<u64>,
+    // Set when the most recent backup failed
+    pub last_error: Option<String>,
+    // Archives kept in the backup directory
+    pub archives: usize,
+}
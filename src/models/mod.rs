@@ -1 +1,3 @@
+pub mod accounts;
+pub mod files;
 pub mod sftp;
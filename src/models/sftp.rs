@@ -1,27 +1,274 @@
+use crate::config::settings::Settings;
+use crate::schedule::Schedule;
+use crate::services::state_store::{
+    BannedIp, PersistedState, StaleRevision, StateBackend,
+};
+use crate::sftp::{
+    AuthFailureTracker, CredentialsSource, DiskStatus, PathRule,
+    SharedCredentials,
+};
+pub use crate::sftp::{SecretString, SftpCredentials};
+use crate::store::AccountsSnapshot;
+use arc_swap::ArcSwap;
+use chrono::{DateTime, Utc};
+use rand::RngExt;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
 use std::sync::Arc;
-use std::time::SystemTime;
-use tokio::sync::RwLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+use tokio::sync::{Notify, RwLock};
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+// Address the SFTP listener binds to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListenAddress {
+    pub bind_addrs: String,
+    pub port: u16,
+}
+
+impl fmt::Display for ListenAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.bind_addrs, self.port)
+    }
+}
+
+// Everything about the SFTP server that status checks, logins and the
+// lifecycle manager read. Changes publish a new copy, so readers never
+// wait for writers or for each other.
+#[derive(Debug, Clone)]
+pub struct CurrentState {
+    pub enabled: bool,
+    // Accepted at login; only set while enabled, in the same copy
+    pub credentials: Option<Arc<SftpCredentials>>,
+    pub expiration: Option<SystemTime>,
+    pub schedule: Schedule,
+    pub drain: Option<DrainState>,
+    // Why the listener is down although it should run, if it is
+    pub failure: Option<ServerFailure>,
+    // Whether the lifecycle manager has the listener up
+    pub running: bool,
+    // Where the listener should accept connections; changing it moves a
+    // running listener without dropping sessions
+    pub listen: ListenAddress,
+    // Reason of the latest failed start, kept after a later success
+    pub last_error: Option<String>,
+}
+
+impl CredentialsSource for ArcSwap<CurrentState> {
+    fn load(&self) -> Option<Arc<SftpCredentials>> {
+        ArcSwap::load(self).credentials.clone()
+    }
+}
+
+impl CurrentState {
+    // The listener runs while enabled, inside a scheduled window and not
+    // draining for maintenance
+    pub fn should_run(&self) -> bool {
+        self.enabled
+            && self.schedule.is_open(Utc::now())
+            && self.drain.is_none()
+    }
+}
 
 // SFTP server state management
 #[derive(Clone)]
 pub struct SftpState {
-    pub enabled: Arc<RwLock<bool>>,
-    pub expiration: Arc<RwLock<Option<SystemTime>>>,
-    pub credentials: Arc<RwLock<Option<SftpCredentials>>>,
+    current: Arc<ArcSwap<CurrentState>>,
+    // Recently generated passwords, oldest first
+    pub history: Arc<RwLock<Vec<CredentialRotation>>>,
+    // Set when the listener socket is passed in, e.g. by systemd, so the
+    // configured address no longer applies
+    listen_fixed: bool,
+    // Signalled whenever the listener may need to start or stop
+    changed: Arc<Notify>,
+    // Where enabled state and credentials are saved, if anywhere
+    store: Option<Arc<dyn StateBackend>>,
+    // IPs banned after failed logins, saved along with the rest
+    bans: Option<AuthFailureTracker>,
+    // Revision of the stored state last read or written here
+    revision: Arc<AtomicU64>,
 }
 
 impl SftpState {
-    pub fn new() -> Self {
+    pub fn new(listen: ListenAddress) -> Self {
         Self {
-            enabled: Arc::new(RwLock::new(false)),
-            expiration: Arc::new(RwLock::new(None)),
-            credentials: Arc::new(RwLock::new(None)),
+            current: Arc::new(ArcSwap::from_pointee(CurrentState {
+                enabled: false,
+                credentials: None,
+                expiration: None,
+                schedule: Schedule::default(),
+                drain: None,
+                failure: None,
+                running: false,
+                listen,
+                last_error: None,
+            })),
+            history: Arc::new(RwLock::new(Vec::new())),
+            listen_fixed: false,
+            changed: Arc::new(Notify::new()),
+            store: None,
+            bans: None,
+            revision: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    // Save every enable/disable to the given store
+    pub fn with_store(mut self, store: Arc<dyn StateBackend>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    // Save the bans of `tracker` with the state and adopt stored ones
+    pub fn with_bans(mut self, tracker: AuthFailureTracker) -> Self {
+        self.bans = Some(tracker);
+        self
+    }
+
+    // Keep the listen address given to `new`, ignoring later changes
+    pub fn with_fixed_listen_address(mut self) -> Self {
+        self.listen_fixed = true;
+        self
+    }
+
+    // The current state, consistent across its fields
+    pub fn current(&self) -> Arc<CurrentState> {
+        self.current.load_full()
+    }
+
+    // The credentials as the listeners check them on every login
+    pub fn credentials(&self) -> SharedCredentials {
+        self.current.clone()
+    }
+
+    // Publish a changed copy of the state, returning the previous one.
+    // `change` runs again when writers race, so it must only depend on the
+    // state it is given.
+    fn update(&self, change: impl Fn(&mut CurrentState)) -> Arc<CurrentState> {
+        self.current.rcu(|current| {
+            let mut next = CurrentState::clone(current);
+            change(&mut next);
+            next
+        })
+    }
+
+    // Restore the state saved by a previous run, if any
+    pub async fn restore(&self) {
+        let Some(store) = &self.store else { return };
+
+        match store.load().await {
+            Ok(Some(persisted)) => self.apply_persisted(&persisted).await,
+            Ok(None) => {}
+            Err(e) => error!(
+                "Failed to read SFTP state from {}: {}",
+                store.describe(),
+                e
+            ),
+        }
+    }
+
+    // Adopt state saved elsewhere without saving it again
+    pub async fn apply_persisted(&self, persisted: &PersistedState) {
+        if persisted.credentials.is_none()
+            && persisted.sealed_credentials.is_some()
+        {
+            warn!("Stored SFTP credentials are encrypted but no key is set");
+        }
+
+        *self.history.write().await = persisted.password_history.clone();
+        self.revision.store(persisted.revision, Ordering::SeqCst);
+        if let Some(tracker) = &self.bans {
+            tracker.adopt_bans(&persisted.ban_list());
+        }
+
+        match persisted.credentials.clone() {
+            Some(credentials) if persisted.enabled => {
+                info!(
+                    "Restored enabled SFTP state for user {}",
+                    credentials.username
+                );
+                let credentials = Arc::new(credentials);
+                let expiration = persisted.expiration();
+                self.update(|state| {
+                    state.enabled = true;
+                    state.credentials = Some(credentials.clone());
+                    state.expiration = expiration;
+                });
+            }
+            _ => {
+                info!("Restored disabled SFTP state");
+                self.update(|state| {
+                    state.enabled = false;
+                    state.credentials = None;
+                    state.expiration = None;
+                });
+            }
+        }
+        self.changed.notify_one();
+    }
+
+    // Save the current state to the configured store, if any. When another
+    // replica saved first, its state is adopted instead.
+    pub async fn persist(&self) {
+        let Some(store) = &self.store else { return };
+
+        let current = self.current();
+        let state = PersistedState {
+            revision: self.revision.load(Ordering::SeqCst) + 1,
+            enabled: current.enabled,
+            credentials: current.credentials.as_deref().cloned(),
+            expires_at: current.expiration.map(|exp| {
+                exp.duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs()
+            }),
+            password_history: self.history.read().await.clone(),
+            bans: self.ban_list(),
+            ..Default::default()
+        };
+        match store.save(&state).await {
+            Ok(()) => self.revision.store(state.revision, Ordering::SeqCst),
+            Err(e) if StaleRevision::find(&e).is_some() => {
+                warn!("SFTP state was changed by another replica, reloading");
+                self.restore().await;
+            }
+            Err(e) => error!(
+                "Failed to save SFTP state to {}: {}",
+                store.describe(),
+                e
+            ),
         }
     }
 
+    fn ban_list(&self) -> Vec<BannedIp> {
+        self.bans.iter().flat_map(|t| t.bans()).map(BannedIp::from).collect()
+    }
+
+    // Save the bans whenever an IP is banned, so other replicas sharing
+    // the store refuse it too. Only the bans are written, never enabled
+    // state or credentials. Nothing runs without a store or bans.
+    pub fn persist_bans(&self) -> Option<JoinHandle<()>> {
+        let tracker = self.bans.clone()?;
+        let store = self.store.clone()?;
+        let state = self.clone();
+        Some(tokio::spawn(async move {
+            loop {
+                tracker.ban_added().await;
+                if let Err(e) = store.save_bans(&state.ban_list()).await {
+                    error!(
+                        "Failed to save SFTP bans to {}: {}",
+                        store.describe(),
+                        e
+                    );
+                }
+            }
+        }))
+    }
+
     pub async fn is_enabled(&self) -> bool {
-        *self.enabled.read().await
+        self.current.load().enabled
     }
 
     pub async fn enable(
@@ -29,43 +276,220 @@ impl SftpState {
         credentials: SftpCredentials,
         expiration: Option<SystemTime>,
     ) {
-        *self.enabled.write().await = true;
-        *self.credentials.write().await = Some(credentials);
-        *self.expiration.write().await = expiration;
+        // One copy carries both, so nobody sees the server enabled without
+        // credentials or the other way round
+        let credentials = Arc::new(credentials);
+        self.update(|state| {
+            state.enabled = true;
+            state.credentials = Some(credentials.clone());
+            state.expiration = expiration;
+            state.drain = None;
+        });
+        self.persist().await;
+        self.changed.notify_one();
     }
 
     pub async fn disable(&self) {
-        *self.enabled.write().await = false;
-        *self.credentials.write().await = None;
-        *self.expiration.write().await = None;
+        self.update(|state| {
+            state.enabled = false;
+            state.credentials = None;
+            state.expiration = None;
+            state.drain = None;
+        });
+        self.persist().await;
+        self.changed.notify_one();
+    }
+
+    // Time left until the credentials expire, if they do
+    pub async fn remaining(&self) -> Option<Duration> {
+        self.current.load().expiration.map(|exp| {
+            exp.duration_since(SystemTime::now()).unwrap_or_default()
+        })
     }
 
     pub async fn is_expired(&self) -> bool {
-        if let Some(exp) = *self.expiration.read().await {
-            SystemTime::now() >= exp
-        } else {
-            false
-        }
+        self.current
+            .load()
+            .expiration
+            .is_some_and(|exp| SystemTime::now() >= exp)
     }
 
     pub async fn get_credentials(&self) -> Option<SftpCredentials> {
-        self.credentials.read().await.clone()
+        self.current.load().credentials.as_deref().cloned()
+    }
+
+    // Replace the credentials of an enabled server; the running listener
+    // checks them on every login, so no restart is needed
+    pub async fn rotate_credentials(&self, credentials: SftpCredentials) {
+        let credentials = Arc::new(credentials);
+        self.update(|state| {
+            // A disable that got in first wins
+            if state.enabled {
+                state.credentials = Some(credentials.clone());
+            }
+        });
+        self.persist().await;
+    }
+
+    // Whether the password was generated within the remembered history
+    pub async fn is_recent_password(&self, password: &str) -> bool {
+        self.history.read().await.iter().any(|r| r.matches(password))
+    }
+
+    // Remember a newly generated password, keeping the newest `keep`
+    pub async fn record_rotation(&self, password: &str, keep: usize) {
+        let mut history = self.history.write().await;
+        history.push(CredentialRotation::new(password));
+        let excess = history.len().saturating_sub(keep);
+        history.drain(..excess);
+    }
+
+    pub async fn set_schedule(&self, schedule: Schedule) {
+        self.update(|state| state.schedule = schedule.clone());
+        self.changed.notify_one();
+    }
+
+    // Whether the current time falls inside a scheduled window
+    pub async fn is_schedule_open(&self) -> bool {
+        self.current.load().schedule.is_open(Utc::now())
+    }
+
+    pub async fn get_drain(&self) -> Option<DrainState> {
+        self.current.load().drain.clone()
+    }
+
+    pub async fn set_drain(&self, drain: Option<DrainState>) {
+        self.update(|state| state.drain = drain.clone());
+        self.changed.notify_one();
+    }
+
+    pub async fn get_failure(&self) -> Option<ServerFailure> {
+        self.current.load().failure.clone()
+    }
+
+    pub async fn set_failure(&self, failure: Option<ServerFailure>) {
+        self.update(|state| {
+            if let Some(failure) = &failure {
+                state.last_error = Some(failure.reason.clone());
+            }
+            state.failure = failure.clone();
+        });
+    }
+
+    pub async fn listen_address(&self) -> ListenAddress {
+        self.current.load().listen.clone()
+    }
+
+    pub async fn set_listen_address(&self, listen: ListenAddress) {
+        let current = self.current.load();
+        if current.listen == listen {
+            return;
+        }
+        if self.listen_fixed {
+            warn!(
+                "Ignoring SFTP listen address {}: the listener socket was \
+                 passed in on {}",
+                listen, current.listen
+            );
+            return;
+        }
+        let previous = self.update(|state| state.listen = listen.clone());
+        if previous.listen != listen {
+            self.changed.notify_one();
+        }
+    }
+
+    pub async fn is_running(&self) -> bool {
+        self.current.load().running
+    }
+
+    pub async fn set_running(&self, running: bool) {
+        self.update(|state| state.running = running);
+    }
+
+    // Wait until the state changes in a way the lifecycle manager reacts
+    // to. Only one task should wait; a change made while nobody waits is
+    // remembered for the next call.
+    pub async fn changed(&self) {
+        self.changed.notified().await;
+    }
+
+    // The listener runs while enabled, inside a scheduled window and not
+    // draining for maintenance
+    pub async fn should_run(&self) -> bool {
+        self.current.load().should_run()
     }
 }
 
-// SFTP credentials
+// An in-progress or completed drain of the SFTP listener
+#[derive(Debug, Clone)]
+pub struct DrainState {
+    pub started_at: SystemTime,
+    pub deadline: SystemTime,
+    pub completed_at: Option<SystemTime>,
+}
+
+// Failed attempts to run the SFTP listener
+#[derive(Debug, Clone)]
+pub struct ServerFailure {
+    pub reason: String,
+    // What failed, see `Error::kind`
+    pub kind: &'static str,
+    pub attempts: u32,
+    pub failed_at: SystemTime,
+    // Next start attempt; `None` once the attempts are exhausted
+    pub retry_at: Option<SystemTime>,
+}
+
+// A generated password, remembered only as a salted hash
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SftpCredentials {
-    pub username: String,
-    pub password: String,
+pub struct CredentialRotation {
+    // Seconds since the Unix epoch
+    pub rotated_at: u64,
+    // "<salt>:<sha256(salt || password)>", both hex encoded
+    pub password_hash: String,
 }
 
-impl SftpCredentials {
-    pub fn new(username: String, password: String) -> Self {
-        Self { username, password }
+impl CredentialRotation {
+    pub fn new(password: &str) -> Self {
+        let salt: [u8; 16] = rand::rng().random();
+        Self {
+            rotated_at: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            password_hash: format!(
+                "{}:{}",
+                hex::encode(salt),
+                salted_hash(&salt, password)
+            ),
+        }
+    }
+
+    pub fn matches(&self, password: &str) -> bool {
+        let Some((salt, hash)) = self.password_hash.split_once(':') else {
+            return false;
+        };
+        hex::decode(salt).is_ok_and(|salt| salted_hash(&salt, password) == hash)
     }
 }
 
+fn salted_hash(salt: &[u8], password: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt);
+    hasher.update(password.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+// Timestamps of recent credential rotations, for compliance evidence
+#[derive(Debug, Serialize)]
+pub struct CredentialHistoryResponse {
+    // How many rotations are remembered
+    pub limit: usize,
+    // Newest first
+    pub rotated_at: Vec<String>,
+}
+
 // Response when toggling SFTP
 #[derive(Debug, Serialize)]
 pub struct ToggleSftpResponse {
@@ -83,14 +507,274 @@ pub struct SftpStatusResponse {
     pub enabled: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub expires_at: Option<String>,
+    // Inside the earliest expiry warning threshold
+    pub expiring_soon: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schedule_open: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub listener: Option<ListenerCheck>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub drain: Option<DrainStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure: Option<FailureStatus>,
+    // Space left on the filesystem of the root
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disk: Option<DiskStatus>,
+    // Whether the lifecycle manager has the listener up
+    pub running: bool,
+    // Where the listener accepts connections while running
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub listen_addr: Option<String>,
+    pub active_sessions: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+// Recording of one SFTP session
+#[derive(Debug, Serialize)]
+pub struct RecordingInfo {
+    pub name: String,
+    pub session_id: u64,
+    pub username: String,
+    pub started_at: String,
+    pub size_bytes: u64,
+}
+
+// Desired and actual state of one supervised SFTP server
+#[derive(Debug, Serialize)]
+pub struct InstanceStatus {
+    pub name: String,
+    pub bind_addrs: String,
+    pub port: u16,
+    pub root_dir: String,
+    pub enabled: bool,
+    // Whether the listener should be up right now
+    pub desired_running: bool,
+    pub running: bool,
+    pub healthy: bool,
+    pub sessions: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure: Option<FailureStatus>,
+}
+
+// Why the listener is not running although SFTP is enabled
+#[derive(Debug, Clone, Serialize)]
+pub struct FailureStatus {
+    pub reason: String,
+    pub kind: String,
+    pub attempts: u32,
+    pub failed_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_at: Option<String>,
+    pub gave_up: bool,
+}
+
+// Result of connecting to the SFTP listener as a client would
+#[derive(Debug, Clone, Serialize)]
+pub struct ListenerCheck {
+    pub reachable: bool,
+    pub address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server_banner: Option<String>,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
 }
 
 // Response for credentials endpoint
 #[derive(Debug, Serialize)]
 pub struct CredentialsResponse {
     pub username: String,
-    pub password: String,
+    pub password: SecretString,
+    // Where clients connect: the external address, or the listen address
+    pub host: String,
     pub bind_addrs: String,
     pub port: u16,
     pub root_dir: String,
+    // Mapping obtained from the router, when port mapping is enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port_mapping: Option<PortMapping>,
+}
+
+// Port mapping on the local router, with the router's external address
+#[derive(Debug, Clone, Serialize)]
+pub struct PortMapping {
+    // "upnp" or "natpmp"
+    pub protocol: &'static str,
+    pub external_ip: String,
+    pub external_port: u16,
+    pub internal_port: u16,
+    pub expires_at: DateTime<Utc>,
+}
+
+// Caller retrieving credentials, as far as the API can tell
+#[derive(Debug, Clone, Default)]
+pub struct CredentialsAccessor {
+    // "rest" or "grpc"
+    pub api: &'static str,
+    pub client: Option<String>,
+    pub forwarded_for: Option<String>,
+    // SHA-256 fingerprint of the client certificate
+    pub certificate: Option<String>,
+    // Holder of the API key used, see `KeyHolder::label`
+    pub api_key: Option<String>,
+}
+
+// One retrieval of credentials, from the audit log
+#[derive(Debug, Clone, Serialize)]
+pub struct CredentialsAccessEntry {
+    pub timestamp: DateTime<Utc>,
+    pub instance: String,
+    pub username: String,
+    pub api: String,
+    pub client: Option<String>,
+    pub forwarded_for: Option<String>,
+    pub certificate: Option<String>,
+    pub api_key: Option<String>,
+}
+
+// Disk usage of the SFTP root
+#[derive(Debug, Clone, Serialize)]
+pub struct DiskUsageResponse {
+    pub root_dir: String,
+    pub total_bytes: u64,
+    pub file_count: u64,
+    pub computed_at: String,
+    pub entries: Vec<UsageEntry>,
+}
+
+// Usage of a single top-level entry in the root
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageEntry {
+    pub name: String,
+    pub is_dir: bool,
+    pub bytes: u64,
+    pub files: u64,
+}
+
+// Request to replace the schedule windows
+#[derive(Debug, Deserialize)]
+pub struct ScheduleRequest {
+    pub windows: Vec<String>,
+}
+
+// Configured schedule windows and whether one is open now
+#[derive(Debug, Serialize)]
+pub struct ScheduleResponse {
+    pub windows: Vec<String>,
+    pub open: bool,
+}
+
+// Path rules, both to replace them and as the answer
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PathRulesBody {
+    pub rules: Vec<PathRule>,
+}
+
+// Request to enable the SFTP server
+#[derive(Debug, Default, Deserialize)]
+pub struct EnableRequest {
+    // Lifetime of the new credentials; [sftp] expiration_days when unset,
+    // 0 means they never expire
+    pub expiration_days: Option<u64>,
+}
+
+// Request to start draining the SFTP listener
+#[derive(Debug, Default, Deserialize)]
+pub struct DrainRequest {
+    // Seconds to wait for open transfers before disconnecting everyone
+    pub timeout_secs: Option<u64>,
+}
+
+// Progress of a drain
+#[derive(Debug, Serialize)]
+pub struct DrainStatus {
+    pub started_at: String,
+    pub deadline: String,
+    pub completed: bool,
+    pub active_sessions: usize,
+    pub open_files: usize,
+}
+
+// Portable snapshot of the manager state, used to migrate a deployment
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StateSnapshot {
+    pub format_version: u32,
+    pub exported_at: String,
+    pub sftp: SftpSnapshot,
+    pub schedule: Vec<String>,
+    // Accounts, keys and shares of the database; None without one, and
+    // importing a snapshot without them leaves the accounts alone
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accounts: Option<AccountsSnapshot>,
+    // Configuration of the exporting instance, for reference on import
+    pub config: Settings,
+}
+
+// Account state of the SFTP server within a snapshot
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SftpSnapshot {
+    pub enabled: bool,
+    pub credentials: Option<SftpCredentials>,
+    pub expires_at: Option<String>,
+}
+
+// Outcome of importing a snapshot
+#[derive(Debug, Serialize)]
+pub struct ImportResponse {
+    pub enabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+    pub schedule: Vec<String>,
+    // Number of accounts imported, when the snapshot carried them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accounts: Option<usize>,
+    // Configuration fields that differ from the running instance; the
+    // configuration files are not rewritten by an import
+    pub config_differences: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_enabled_flag_and_credentials_change_together() {
+        let state = SftpState::new(ListenAddress {
+            bind_addrs: "127.0.0.1".to_string(),
+            port: 2222,
+        });
+        let logins = state.credentials();
+
+        let writers: Vec<_> = (0..4)
+            .map(|n| {
+                let state = state.clone();
+                tokio::spawn(async move {
+                    for i in 0..500 {
+                        if (i + n) % 2 == 0 {
+                            let credentials = SftpCredentials::new(
+                                format!("user{}", i),
+                                "secret".to_string(),
+                            );
+                            state.enable(credentials, None).await;
+                        } else {
+                            state.disable().await;
+                        }
+                    }
+                })
+            })
+            .collect();
+        while writers.iter().any(|w| !w.is_finished()) {
+            let current = state.current();
+            assert_eq!(current.enabled, current.credentials.is_some());
+            tokio::task::yield_now().await;
+        }
+
+        let current = state.current();
+        assert_eq!(current.enabled, current.credentials.is_some());
+        assert_eq!(
+            logins.load().map(|c| c.username.clone()),
+            current.credentials.as_ref().map(|c| c.username.clone())
+        );
+    }
 }
@@ -0,0 +1,1007 @@
+pub mod sigv4;
+
+use crate::config::settings::Settings;
+use crate::services::files::FileService;
+use crate::services::upload_limits::{UploadAborted, UploadLimiter};
+use crate::sftp::scratch::SCRATCH_DIR;
+use crate::sftp::sparse;
+use crate::sftp::trash::TRASH_DIR;
+use axum::Router;
+use axum::body::Body;
+use axum::extract::{Path as UrlPath, Request, State};
+use axum::http::{HeaderMap, Method, StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{any, get};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use rand::RngExt;
+use sha2::{Digest, Sha256};
+use sigv4::{AuthError, ChunkDecoder, ChunkError, Payload};
+use std::fmt::Write as _;
+use std::io::{self, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::watch;
+use tokio_util::io::ReaderStream;
+use tracing::{error, info, warn};
+
+const XML_NAMESPACE: &str = "http://s3.amazonaws.com/doc/2006-03-01/";
+// Keys returned by a listing unless the client asks for fewer
+const MAX_KEYS: usize = 1000;
+
+// Minimal S3 API over the default SFTP root: a single bucket whose keys
+// are paths below the root. Uploads are reported through the file service
+// and held to the same limits as any other API upload.
+pub struct S3Gateway {
+    files: Arc<FileService>,
+    root_dir: PathBuf,
+    uploads: Arc<UploadLimiter>,
+    settings: watch::Receiver<Settings>,
+}
+
+impl S3Gateway {
+    pub fn new(
+        files: Arc<FileService>,
+        root_dir: String,
+        uploads: Arc<UploadLimiter>,
+        settings: watch::Receiver<Settings>,
+    ) -> Self {
+        Self { files, root_dir: PathBuf::from(root_dir), uploads, settings }
+    }
+}
+
+// Path-style routes: `/`, `/<bucket>` and `/<bucket>/<key>`, all signed
+pub fn router(gateway: Arc<S3Gateway>) -> Router {
+    Router::new()
+        .route("/", get(list_buckets))
+        .route("/{bucket}", any(bucket_request))
+        .route("/{bucket}/", any(bucket_request))
+        .route("/{bucket}/{*key}", any(object_request))
+        .layer(middleware::from_fn_with_state(gateway.clone(), authenticate))
+        .with_state(gateway)
+}
+
+// Error replied in the S3 XML format
+struct S3Error {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+}
+
+impl S3Error {
+    fn new(
+        status: StatusCode,
+        code: &'static str,
+        message: impl Into<String>,
+    ) -> Self {
+        Self { status, code, message: message.into() }
+    }
+
+    fn not_implemented() -> Self {
+        Self::new(
+            StatusCode::NOT_IMPLEMENTED,
+            "NotImplemented",
+            "This operation is not supported by the gateway",
+        )
+    }
+
+    fn no_such_key() -> Self {
+        Self::new(
+            StatusCode::NOT_FOUND,
+            "NoSuchKey",
+            "The specified key does not exist",
+        )
+    }
+
+    fn internal(message: &str, e: impl std::fmt::Display) -> Self {
+        error!("S3 gateway: {}: {}", message, e);
+        Self::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "InternalError",
+            format!("{}: {}", message, e),
+        )
+    }
+
+    // Errors of the file service, which carry an HTTP status
+    fn from_files((status, message): (StatusCode, String)) -> Self {
+        let code = match status {
+            StatusCode::BAD_REQUEST => "InvalidArgument",
+            StatusCode::NOT_FOUND => "NoSuchKey",
+            StatusCode::CONFLICT => "InvalidRequest",
+            _ => "InternalError",
+        };
+        Self::new(status, code, message)
+    }
+}
+
+impl From<AuthError> for S3Error {
+    fn from(e: AuthError) -> Self {
+        let (status, code) = match e {
+            AuthError::Missing | AuthError::Expired => {
+                (StatusCode::FORBIDDEN, "AccessDenied")
+            }
+            AuthError::Malformed => {
+                (StatusCode::BAD_REQUEST, "AuthorizationHeaderMalformed")
+            }
+            AuthError::UnknownKey => {
+                (StatusCode::FORBIDDEN, "InvalidAccessKeyId")
+            }
+            AuthError::Skewed => {
+                (StatusCode::FORBIDDEN, "RequestTimeTooSkewed")
+            }
+            AuthError::Mismatch => {
+                (StatusCode::FORBIDDEN, "SignatureDoesNotMatch")
+            }
+        };
+        Self::new(status, code, e.to_string())
+    }
+}
+
+impl From<ChunkError> for S3Error {
+    fn from(e: ChunkError) -> Self {
+        match e {
+            ChunkError::Malformed => Self::new(
+                StatusCode::BAD_REQUEST,
+                "InvalidRequest",
+                "The aws-chunked body is malformed",
+            ),
+            ChunkError::Signature => Self::new(
+                StatusCode::FORBIDDEN,
+                "SignatureDoesNotMatch",
+                "A chunk signature does not match",
+            ),
+            ChunkError::Incomplete => Self::new(
+                StatusCode::BAD_REQUEST,
+                "IncompleteBody",
+                "The body ended before the final chunk",
+            ),
+        }
+    }
+}
+
+impl From<UploadAborted> for S3Error {
+    fn from(aborted: UploadAborted) -> Self {
+        let code = match aborted.status {
+            StatusCode::PAYLOAD_TOO_LARGE => "EntityTooLarge",
+            StatusCode::REQUEST_TIMEOUT => "RequestTimeout",
+            StatusCode::SERVICE_UNAVAILABLE => "SlowDown",
+            _ => "InvalidRequest",
+        };
+        Self::new(aborted.status, code, aborted.message)
+    }
+}
+
+impl IntoResponse for S3Error {
+    fn into_response(self) -> Response {
+        let body = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <Error><Code>{}</Code><Message>{}</Message></Error>",
+            self.code,
+            escape(&self.message)
+        );
+        xml(self.status, body)
+    }
+}
+
+type S3Result = Result<Response, S3Error>;
+
+async fn authenticate(
+    State(gateway): State<Arc<S3Gateway>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let (access_key_id, secret_access_key) = {
+        let settings = gateway.settings.borrow();
+        (
+            settings.s3.access_key_id.clone(),
+            settings.s3.secret_access_key.clone(),
+        )
+    };
+    match sigv4::verify(
+        request.method(),
+        request.uri(),
+        request.headers(),
+        &access_key_id,
+        secret_access_key.expose(),
+        Utc::now(),
+    ) {
+        Ok(payload) => {
+            request.extensions_mut().insert(payload);
+            next.run(request).await
+        }
+        Err(e) => {
+            warn!(
+                "Rejected S3 request {} {}: {}",
+                request.method(),
+                request.uri().path(),
+                e
+            );
+            S3Error::from(e).into_response()
+        }
+    }
+}
+
+async fn list_buckets(State(gateway): State<Arc<S3Gateway>>) -> Response {
+    let bucket = gateway.settings.borrow().s3.bucket.clone();
+    let created = fs::metadata(&gateway.root_dir)
+        .await
+        .and_then(|m| m.created().or_else(|_| m.modified()))
+        .unwrap_or_else(|_| SystemTime::now());
+    let body = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <ListAllMyBucketsResult xmlns=\"{}\">\
+         <Owner><ID>sftp-manager</ID><DisplayName>sftp-manager</DisplayName></Owner>\
+         <Buckets><Bucket><Name>{}</Name><CreationDate>{}</CreationDate></Bucket></Buckets>\
+         </ListAllMyBucketsResult>",
+        XML_NAMESPACE,
+        escape(&bucket),
+        iso_time(created)
+    );
+    xml(StatusCode::OK, body)
+}
+
+async fn bucket_request(
+    State(gateway): State<Arc<S3Gateway>>,
+    UrlPath(bucket): UrlPath<String>,
+    method: Method,
+    request: Request,
+) -> S3Result {
+    gateway.check_bucket(&bucket)?;
+    let query = sigv4::query_pairs(request.uri().query().unwrap_or_default());
+    let param = |name: &str| {
+        query.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    };
+
+    match method {
+        Method::HEAD => Ok(StatusCode::OK.into_response()),
+        Method::GET if param("location").is_some() => Ok(xml(
+            StatusCode::OK,
+            format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                 <LocationConstraint xmlns=\"{}\"/>",
+                XML_NAMESPACE
+            ),
+        )),
+        Method::GET => {
+            const LIST_PARAMETERS: &[&str] = &[
+                "list-type",
+                "prefix",
+                "delimiter",
+                "max-keys",
+                "continuation-token",
+                "start-after",
+                "marker",
+                "encoding-type",
+                "fetch-owner",
+            ];
+            if query.iter().any(|(n, _)| !LIST_PARAMETERS.contains(&&**n)) {
+                return Err(S3Error::not_implemented());
+            }
+            gateway.list_objects(&bucket, &query).await
+        }
+        _ => Err(S3Error::not_implemented()),
+    }
+}
+
+async fn object_request(
+    State(gateway): State<Arc<S3Gateway>>,
+    UrlPath((bucket, key)): UrlPath<(String, String)>,
+    method: Method,
+    request: Request,
+) -> S3Result {
+    gateway.check_bucket(&bucket)?;
+    // Multipart uploads, copies, tagging and ACLs are not supported
+    if request.uri().query().is_some_and(|q| {
+        !sigv4::query_pairs(q).iter().all(|(n, _)| n.starts_with("X-Amz-"))
+    }) || request.headers().contains_key("x-amz-copy-source")
+    {
+        return Err(S3Error::not_implemented());
+    }
+
+    match method {
+        Method::GET | Method::HEAD => {
+            gateway
+                .get_object(&key, request.headers(), method == Method::HEAD)
+                .await
+        }
+        Method::PUT => gateway.put_object(&key, request).await,
+        Method::DELETE => gateway.delete_object(&key).await,
+        _ => Err(S3Error::not_implemented()),
+    }
+}
+
+// A stored file as listed
+#[derive(Debug, Clone, PartialEq)]
+struct Object {
+    key: String,
+    size: u64,
+    modified: SystemTime,
+}
+
+// One page of a listing
+#[derive(Debug, Default, PartialEq)]
+struct ListPage<'a> {
+    objects: Vec<&'a Object>,
+    prefixes: Vec<String>,
+    // Last key or common prefix returned, when more follow
+    next: Option<String>,
+}
+
+impl S3Gateway {
+    fn check_bucket(&self, bucket: &str) -> Result<(), S3Error> {
+        if self.settings.borrow().s3.bucket == bucket {
+            Ok(())
+        } else {
+            Err(S3Error::new(
+                StatusCode::NOT_FOUND,
+                "NoSuchBucket",
+                "The specified bucket does not exist",
+            ))
+        }
+    }
+
+    // ListObjectsV2 when `list-type=2`, the original ListObjects otherwise
+    async fn list_objects(
+        &self,
+        bucket: &str,
+        query: &[(String, String)],
+    ) -> S3Result {
+        let param = |name: &str| {
+            query.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+        };
+        let v2 = param("list-type") == Some("2");
+        let prefix = param("prefix").unwrap_or_default().to_string();
+        let delimiter = param("delimiter").unwrap_or_default();
+        let max_keys = match param("max-keys") {
+            Some(value) => value.parse::<usize>().map_err(|_| {
+                S3Error::new(
+                    StatusCode::BAD_REQUEST,
+                    "InvalidArgument",
+                    "max-keys must be a non-negative integer",
+                )
+            })?,
+            None => MAX_KEYS,
+        }
+        .min(MAX_KEYS);
+        let token = param("continuation-token");
+        let after = if v2 {
+            match token {
+                Some(token) => Some(decode_token(token)?),
+                None => param("start-after").map(str::to_string),
+            }
+        } else {
+            param("marker").map(str::to_string)
+        };
+        let url_encoded = param("encoding-type") == Some("url");
+        let encode = |value: &str| {
+            if url_encoded {
+                sigv4::uri_encode(value, false)
+            } else {
+                value.to_string()
+            }
+        };
+
+        let root = self.root_dir.clone();
+        let walk_prefix = prefix.clone();
+        let objects = tokio::task::spawn_blocking(move || {
+            walk_objects(&root, &walk_prefix)
+        })
+        .await
+        .map_err(|e| S3Error::internal("Listing failed", e))?
+        .map_err(|e| S3Error::internal("Failed to list objects", e))?;
+        let page =
+            list_page(&objects, &prefix, delimiter, after.as_deref(), max_keys);
+
+        let mut body = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <ListBucketResult xmlns=\"{}\"><Name>{}</Name>\
+             <Prefix>{}</Prefix><MaxKeys>{}</MaxKeys>\
+             <IsTruncated>{}</IsTruncated>",
+            XML_NAMESPACE,
+            escape(bucket),
+            escape(&encode(&prefix)),
+            max_keys,
+            page.next.is_some()
+        );
+        if !delimiter.is_empty() {
+            let _ = write!(
+                body,
+                "<Delimiter>{}</Delimiter>",
+                escape(&encode(delimiter))
+            );
+        }
+        if url_encoded {
+            body.push_str("<EncodingType>url</EncodingType>");
+        }
+        if v2 {
+            let _ = write!(
+                body,
+                "<KeyCount>{}</KeyCount>",
+                page.objects.len() + page.prefixes.len()
+            );
+            if let Some(token) = token {
+                let _ = write!(
+                    body,
+                    "<ContinuationToken>{}</ContinuationToken>",
+                    escape(token)
+                );
+            }
+            if let Some(start_after) = param("start-after") {
+                let _ = write!(
+                    body,
+                    "<StartAfter>{}</StartAfter>",
+                    escape(&encode(start_after))
+                );
+            }
+            if let Some(next) = &page.next {
+                let _ = write!(
+                    body,
+                    "<NextContinuationToken>{}</NextContinuationToken>",
+                    URL_SAFE_NO_PAD.encode(next)
+                );
+            }
+        } else {
+            let _ = write!(
+                body,
+                "<Marker>{}</Marker>",
+                escape(&encode(param("marker").unwrap_or_default()))
+            );
+            if let Some(next) = &page.next {
+                let _ = write!(
+                    body,
+                    "<NextMarker>{}</NextMarker>",
+                    escape(&encode(next))
+                );
+            }
+        }
+        for object in &page.objects {
+            let _ = write!(
+                body,
+                "<Contents><Key>{}</Key><LastModified>{}</LastModified>\
+                 <ETag>{}</ETag><Size>{}</Size>\
+                 <StorageClass>STANDARD</StorageClass></Contents>",
+                escape(&encode(&object.key)),
+                iso_time(object.modified),
+                escape(&etag(object.size, object.modified)),
+                object.size
+            );
+        }
+        for prefix in &page.prefixes {
+            let _ = write!(
+                body,
+                "<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>",
+                escape(&encode(prefix))
+            );
+        }
+        body.push_str("</ListBucketResult>");
+        Ok(xml(StatusCode::OK, body))
+    }
+
+    async fn get_object(
+        &self,
+        key: &str,
+        headers: &HeaderMap,
+        head_only: bool,
+    ) -> S3Result {
+        let local_path =
+            self.files.resolve(key).await.ok_or_else(S3Error::no_such_key)?;
+        let mut file = match fs::File::open(&local_path).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(S3Error::no_such_key());
+            }
+            Err(e) => return Err(S3Error::internal("Failed to open file", e)),
+        };
+        let metadata = file
+            .metadata()
+            .await
+            .map_err(|e| S3Error::internal("Failed to read file", e))?;
+        if !metadata.is_file() {
+            return Err(S3Error::no_such_key());
+        }
+        let size = metadata.len();
+        let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+
+        let range = match headers
+            .get(header::RANGE)
+            .and_then(|v| v.to_str().ok())
+        {
+            Some(range) => Some(parse_range(range, size).ok_or_else(|| {
+                S3Error::new(
+                    StatusCode::RANGE_NOT_SATISFIABLE,
+                    "InvalidRange",
+                    "The requested range is not satisfiable",
+                )
+            })?),
+            None => None,
+        };
+        let (start, length) = range.unwrap_or((0, size));
+
+        let mut response = Response::builder()
+            .status(if range.is_some() {
+                StatusCode::PARTIAL_CONTENT
+            } else {
+                StatusCode::OK
+            })
+            .header(header::CONTENT_TYPE, "application/octet-stream")
+            .header(header::CONTENT_LENGTH, length)
+            .header(header::ACCEPT_RANGES, "bytes")
+            .header(header::ETAG, etag(size, modified))
+            .header(header::LAST_MODIFIED, http_time(modified));
+        if range.is_some() {
+            response = response.header(
+                header::CONTENT_RANGE,
+                format!(
+                    "bytes {}-{}/{}",
+                    start,
+                    (start + length).saturating_sub(1),
+                    size
+                ),
+            );
+        }
+
+        let body = if head_only {
+            Body::empty()
+        } else {
+            file.seek(SeekFrom::Start(start))
+                .await
+                .map_err(|e| S3Error::internal("Failed to read file", e))?;
+            self.files.report_download(
+                &format!("/{}", key.trim_start_matches('/')),
+                length,
+            );
+            Body::from_stream(ReaderStream::new(file.take(length)))
+        };
+        response
+            .body(body)
+            .map_err(|e| S3Error::internal("Failed to build response", e))
+    }
+
+    // Store an object, creating its parent directories. The body is
+    // written under a temporary name and checked against the signed
+    // payload before it replaces the target.
+    async fn put_object(&self, key: &str, request: Request) -> S3Result {
+        let payload = request
+            .extensions()
+            .get::<Payload>()
+            .cloned()
+            .unwrap_or(Payload::Unsigned);
+
+        // Keys ending in '/' are directory markers
+        if key.ends_with('/') {
+            self.files
+                .create_dir_all(key)
+                .await
+                .map_err(S3Error::from_files)?;
+            return Ok(StatusCode::OK.into_response());
+        }
+        let (parts, body) = request.into_parts();
+        let body = self.uploads.admit(&parts.headers, body)?;
+        if let Some((parent, _)) = key.rsplit_once('/')
+            && !parent.is_empty()
+        {
+            self.files
+                .create_dir_all(parent)
+                .await
+                .map_err(S3Error::from_files)?;
+        }
+        let (path, local_path) = self
+            .files
+            .upload_target(key, true)
+            .await
+            .map_err(S3Error::from_files)?;
+        let decoded_length = parts
+            .headers
+            .get("x-amz-decoded-content-length")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+
+        let started = Instant::now();
+        let sparse = self.files.sparse_files();
+        let temp_path = local_path.with_file_name(format!(
+            ".{}.s3-{:08x}",
+            local_path.file_name().unwrap_or_default().to_string_lossy(),
+            rand::rng().random::<u32>()
+        ));
+        let written = write_object(&temp_path, body, payload, sparse).await;
+        let bytes = match written {
+            Ok(bytes)
+                if decoded_length.is_none_or(|length| length == bytes) =>
+            {
+                bytes
+            }
+            Ok(_) => {
+                let _ = fs::remove_file(&temp_path).await;
+                return Err(S3Error::new(
+                    StatusCode::BAD_REQUEST,
+                    "IncompleteBody",
+                    "The body does not match x-amz-decoded-content-length",
+                ));
+            }
+            Err(e) => {
+                let _ = fs::remove_file(&temp_path).await;
+                warn!("Upload of {} through S3 failed: {}", path, e.message);
+                return Err(e);
+            }
+        };
+        if let Err(e) = fs::rename(&temp_path, &local_path).await {
+            let _ = fs::remove_file(&temp_path).await;
+            return Err(S3Error::internal("Failed to store file", e));
+        }
+
+        let modified = fs::metadata(&local_path)
+            .await
+            .and_then(|m| m.modified())
+            .unwrap_or_else(|_| SystemTime::now());
+        self.files.complete_upload(path, local_path, bytes, started).await;
+        Ok(([(header::ETAG, etag(bytes, modified))], StatusCode::OK)
+            .into_response())
+    }
+
+    // Deleting a missing key succeeds, as in S3
+    async fn delete_object(&self, key: &str) -> S3Result {
+        let response = self.files.delete(key.trim_end_matches('/')).await;
+        match response.status {
+            StatusCode::OK | StatusCode::NOT_FOUND => {
+                Ok(StatusCode::NO_CONTENT.into_response())
+            }
+            StatusCode::CONFLICT => Err(S3Error::new(
+                StatusCode::CONFLICT,
+                "InvalidRequest",
+                "The directory is not empty",
+            )),
+            status => Err(S3Error::new(
+                status,
+                "InternalError",
+                "Failed to delete the object",
+            )),
+        }
+    }
+}
+
+async fn write_object(
+    path: &Path,
+    body: Body,
+    payload: Payload,
+    sparse: bool,
+) -> Result<u64, S3Error> {
+    let failed = |e: io::Error| S3Error::internal("Failed to write file", e);
+    let mut file = fs::File::create_new(path).await.map_err(failed)?;
+    let mut stream = body.into_data_stream();
+    let mut hasher = Sha256::new();
+    let (mut decoder, expected_hash) = match payload {
+        Payload::Unsigned => (None, None),
+        Payload::Sha256(hash) => (None, Some(hash)),
+        Payload::Chunked(signer) => (Some(ChunkDecoder::new(signer)), None),
+    };
+    let mut decoded = Vec::new();
+    let mut bytes = 0;
+    while let Some(chunk) = stream.next().await {
+        // Bodies cut short by the upload limits say why
+        let chunk = chunk.map_err(|e| {
+            let e = anyhow::Error::new(e);
+            match UploadAborted::find(&e) {
+                Some(aborted) => S3Error::from(aborted.clone()),
+                None => S3Error::new(
+                    StatusCode::BAD_REQUEST,
+                    "IncompleteBody",
+                    e.to_string(),
+                ),
+            }
+        })?;
+        let data = match &mut decoder {
+            Some(decoder) => {
+                decoded.clear();
+                decoder.feed(&chunk, &mut decoded)?;
+                &decoded[..]
+            }
+            None => &chunk[..],
+        };
+        if expected_hash.is_some() {
+            hasher.update(data);
+        }
+        sparse::write_at(&mut file, bytes, data, sparse)
+            .await
+            .map_err(failed)?;
+        bytes += data.len() as u64;
+    }
+    if let Some(decoder) = &decoder {
+        decoder.finish()?;
+    }
+    if let Some(expected) = expected_hash
+        && hex::encode(hasher.finalize()) != expected
+    {
+        return Err(S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "XAmzContentSHA256Mismatch",
+            "The body does not match x-amz-content-sha256",
+        ));
+    }
+    file.flush().await.map_err(failed)?;
+    info!("Stored {} bytes through S3 in {}", bytes, path.display());
+    Ok(bytes)
+}
+
+// Every regular file below the directory part of the prefix, as keys
+// relative to the root. The trash and the scratch areas are left out, and
+// so is everything for a prefix with an empty segment: keys never start
+// with '/', and joining one that does would replace the root.
+fn walk_objects(root: &Path, prefix: &str) -> io::Result<Vec<Object>> {
+    let dir = &prefix[..prefix.rfind('/').map_or(0, |i| i + 1)];
+    let valid = dir
+        .split_terminator('/')
+        .all(|s| !s.is_empty() && s != "." && s != "..");
+    let top = dir.split('/').next();
+    if !valid || top == Some(TRASH_DIR) || top == Some(SCRATCH_DIR) {
+        return Ok(Vec::new());
+    }
+
+    let mut objects = Vec::new();
+    let mut pending = vec![(root.join(dir), dir.to_string())];
+    while let Some((local_dir, key_prefix)) = pending.pop() {
+        let entries = match std::fs::read_dir(&local_dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+            if key_prefix.is_empty()
+                && (name == TRASH_DIR || name == SCRATCH_DIR)
+            {
+                continue;
+            }
+            let metadata = entry.metadata()?;
+            let key = format!("{}{}", key_prefix, name);
+            if metadata.is_dir() {
+                pending.push((entry.path(), format!("{}/", key)));
+            } else if metadata.is_file() {
+                objects.push(Object {
+                    key,
+                    size: metadata.len(),
+                    modified: metadata
+                        .modified()
+                        .unwrap_or(SystemTime::UNIX_EPOCH),
+                });
+            }
+        }
+    }
+    objects.sort_by(|a, b| a.key.cmp(&b.key));
+    Ok(objects)
+}
+
+// Select the keys after `after` that start with the prefix, rolling keys
+// that contain the delimiter after the prefix into common prefixes.
+// `objects` must be sorted by key.
+fn list_page<'a>(
+    objects: &'a [Object],
+    prefix: &str,
+    delimiter: &str,
+    after: Option<&str>,
+    max_keys: usize,
+) -> ListPage<'a> {
+    let mut page = ListPage::default();
+    let mut last: Option<String> = None;
+    for object in objects {
+        if !object.key.starts_with(prefix)
+            || after.is_some_and(|after| object.key.as_str() <= after)
+        {
+            continue;
+        }
+        let common_prefix = (!delimiter.is_empty())
+            .then(|| {
+                object.key[prefix.len()..].find(delimiter).map(|i| {
+                    object.key[..prefix.len() + i + delimiter.len()].to_string()
+                })
+            })
+            .flatten();
+        if let Some(common_prefix) = &common_prefix
+            && (page.prefixes.last() == Some(common_prefix)
+                || after.is_some_and(|after| common_prefix.as_str() <= after))
+        {
+            continue;
+        }
+        if page.objects.len() + page.prefixes.len() == max_keys {
+            page.next = last;
+            return page;
+        }
+        match common_prefix {
+            Some(common_prefix) => {
+                last = Some(common_prefix.clone());
+                page.prefixes.push(common_prefix);
+            }
+            None => {
+                last = Some(object.key.clone());
+                page.objects.push(object);
+            }
+        }
+    }
+    page
+}
+
+fn decode_token(token: &str) -> Result<String, S3Error> {
+    URL_SAFE_NO_PAD
+        .decode(token)
+        .ok()
+        .and_then(|key| String::from_utf8(key).ok())
+        .ok_or_else(|| {
+            S3Error::new(
+                StatusCode::BAD_REQUEST,
+                "InvalidArgument",
+                "The continuation token is not valid",
+            )
+        })
+}
+
+// First byte and length of a single `bytes=` range
+fn parse_range(range: &str, size: u64) -> Option<(u64, u64)> {
+    if size == 0 {
+        return None;
+    }
+    let (start, end) = range.strip_prefix("bytes=")?.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix = suffix.parse::<u64>().ok()?.min(size);
+            (size - suffix, size - 1)
+        }
+        (start, "") => (start.parse().ok()?, size - 1),
+        (start, end) => {
+            (start.parse().ok()?, end.parse::<u64>().ok()?.min(size - 1))
+        }
+    };
+    (start <= end && end < size).then(|| (start, end - start + 1))
+}
+
+// Entity tag from the size and modification time, as content hashes
+// would mean reading every listed file. The '-' keeps clients from
+// taking it for an MD5 digest.
+fn etag(size: u64, modified: SystemTime) -> String {
+    let nanos = modified
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    format!("\"{:x}-{:x}\"", nanos, size)
+}
+
+fn iso_time(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+}
+
+fn http_time(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn xml(status: StatusCode, body: String) -> Response {
+    (status, [(header::CONTENT_TYPE, "application/xml")], body).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listing_pages_through_common_prefixes() {
+        let objects: Vec<Object> = ["a.txt", "dir/one", "dir/two", "z/x"]
+            .iter()
+            .map(|key| Object {
+                key: key.to_string(),
+                size: 1,
+                modified: SystemTime::UNIX_EPOCH,
+            })
+            .collect();
+        let keys = |page: &ListPage| {
+            page.objects.iter().map(|o| o.key.clone()).collect::<Vec<_>>()
+        };
+
+        let first = list_page(&objects, "", "/", None, 2);
+        assert_eq!(keys(&first), ["a.txt"]);
+        assert_eq!(first.prefixes, ["dir/"]);
+        assert_eq!(first.next.as_deref(), Some("dir/"));
+
+        let second = list_page(&objects, "", "/", first.next.as_deref(), 2);
+        assert!(second.objects.is_empty());
+        assert_eq!(second.prefixes, ["z/"]);
+        assert_eq!(second.next, None);
+
+        let nested = list_page(&objects, "dir/", "", None, 1000);
+        assert_eq!(keys(&nested), ["dir/one", "dir/two"]);
+
+        assert_eq!(parse_range("bytes=2-", 10), Some((2, 8)));
+        assert_eq!(parse_range("bytes=-3", 10), Some((7, 3)));
+        assert_eq!(parse_range("bytes=0-99", 10), Some((0, 10)));
+        assert_eq!(parse_range("bytes=10-", 10), None);
+    }
+
+    #[tokio::test]
+    async fn test_put_object_is_held_to_the_upload_limits() {
+        use crate::sftp::ServerContext;
+
+        let root = std::env::temp_dir()
+            .join(format!("sftp-manager-s3-limits-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        let mut settings = Settings::default();
+        settings.server.max_upload_bytes = 4;
+        let (_settings_tx, settings_rx) = watch::channel(settings);
+        let files = Arc::new(FileService::new(
+            root.to_string_lossy().into_owned(),
+            settings_rx.clone(),
+            ServerContext::for_tests(),
+        ));
+        let uploads = Arc::new(UploadLimiter::new(settings_rx.clone()));
+        let gateway = S3Gateway::new(
+            files,
+            root.to_string_lossy().into_owned(),
+            uploads,
+            settings_rx,
+        );
+        let put = |length: Option<usize>, body: &'static str| {
+            let mut request = Request::builder().method(Method::PUT);
+            if let Some(length) = length {
+                request = request.header(header::CONTENT_LENGTH, length);
+            }
+            request.body(Body::from(body)).unwrap()
+        };
+
+        // Refused up front when the declared length is over the limit
+        let refused =
+            gateway.put_object("big.txt", put(Some(10), "0123456789")).await;
+        let refused = refused.unwrap_err();
+        assert_eq!(refused.status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(refused.code, "EntityTooLarge");
+
+        // Cut short while streaming when no length is declared
+        let cut = gateway.put_object("big.txt", put(None, "0123456789")).await;
+        assert_eq!(cut.unwrap_err().code, "EntityTooLarge");
+        let left: Vec<_> = std::fs::read_dir(&root).unwrap().collect();
+        assert!(left.is_empty(), "{:?}", left);
+
+        let stored = gateway.put_object("small.txt", put(Some(3), "abc")).await;
+        assert_eq!(stored.ok().unwrap().status(), StatusCode::OK);
+        assert_eq!(std::fs::read(root.join("small.txt")).unwrap(), b"abc");
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_walk_stays_below_the_root() {
+        let root = std::env::temp_dir()
+            .join(format!("sftp-manager-s3-walk-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("dir")).unwrap();
+        std::fs::write(root.join("dir/one"), b"1").unwrap();
+
+        let keys = |prefix: &str| {
+            walk_objects(&root, prefix)
+                .unwrap()
+                .into_iter()
+                .map(|o| o.key)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(keys(""), ["dir/one"]);
+        assert_eq!(keys("dir/"), ["dir/one"]);
+        for prefix in ["/", "/etc/", "/dir/", "//", "dir//", "../"] {
+            assert!(keys(prefix).is_empty(), "{:?}", prefix);
+        }
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
@@ -0,0 +1,216 @@
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use std::fmt;
+
+// A five-field cron expression (minute hour day-of-month month day-of-week)
+// evaluated in UTC. A window is open during every minute the expression
+// matches, so "* 8-17 * * mon-fri" means weekdays from 08:00 to 17:59.
+#[derive(Debug, Clone)]
+pub struct CronExpr {
+    source: String,
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    // Day fields that are not "*" are OR-ed together, as in classic cron
+    dom_restricted: bool,
+    dow_restricted: bool,
+}
+
+const MONTH_NAMES: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct",
+    "nov", "dec",
+];
+const DAY_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+impl CronExpr {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = source.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!(
+                "expected 5 fields in cron expression '{}', found {}",
+                source,
+                fields.len()
+            ));
+        }
+
+        // Sunday may be written as 0 or 7
+        let mut days_of_week = parse_field(fields[4], 0, 7, &DAY_NAMES, 0)?;
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week |= 1;
+        }
+
+        Ok(Self {
+            source: source.to_string(),
+            minutes: parse_field(fields[0], 0, 59, &[], 0)?,
+            hours: parse_field(fields[1], 0, 23, &[], 0)?,
+            days_of_month: parse_field(fields[2], 1, 31, &[], 0)?,
+            months: parse_field(fields[3], 1, 12, &MONTH_NAMES, 1)?,
+            days_of_week,
+            dom_restricted: fields[2] != "*",
+            dow_restricted: fields[4] != "*",
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    // Whether the minute containing `time` is inside the window
+    pub fn matches(&self, time: DateTime<Utc>) -> bool {
+        let bit = |mask: u64, value: u32| mask & (1 << value) != 0;
+
+        let dom = bit(self.days_of_month, time.day());
+        let dow = bit(self.days_of_week, time.weekday().num_days_from_sunday());
+        let day = match (self.dom_restricted, self.dow_restricted) {
+            (true, true) => dom || dow,
+            _ => dom && dow,
+        };
+
+        bit(self.minutes, time.minute())
+            && bit(self.hours, time.hour())
+            && bit(self.months, time.month())
+            && day
+    }
+}
+
+impl fmt::Display for CronExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+// Parse one field into a bitmask of allowed values.
+// Supports "*", single values, ranges "a-b", lists "a,b" and steps "*/n".
+fn parse_field(
+    field: &str,
+    min: u32,
+    max: u32,
+    names: &[&str],
+    name_offset: u32,
+) -> Result<u64, String> {
+    let value = |token: &str| -> Result<u32, String> {
+        let lower = token.to_ascii_lowercase();
+        if let Some(index) = names.iter().position(|n| *n == lower) {
+            return Ok(index as u32 + name_offset);
+        }
+        let parsed: u32 =
+            token.parse().map_err(|_| format!("invalid value '{}'", token))?;
+        if parsed < min || parsed > max {
+            return Err(format!(
+                "value {} out of range {}-{}",
+                parsed, min, max
+            ));
+        }
+        Ok(parsed)
+    };
+
+    let mut mask = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .ok()
+                    .filter(|s| *s > 0)
+                    .ok_or_else(|| format!("invalid step in '{}'", part))?;
+                (range, step)
+            }
+            None => (part, 1),
+        };
+
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((a, b)) => (value(a)?, value(b)?),
+                None if step > 1 => (value(range)?, max),
+                None => {
+                    let v = value(range)?;
+                    (v, v)
+                }
+            },
+        };
+
+        if start > end {
+            return Err(format!("invalid range '{}'", range));
+        }
+
+        for v in (start..=end).step_by(step as usize) {
+            mask |= 1 << v;
+        }
+    }
+
+    Ok(mask)
+}
+
+// Set of windows during which the SFTP listener may run
+#[derive(Debug, Clone, Default)]
+pub struct Schedule {
+    windows: Vec<CronExpr>,
+}
+
+impl Schedule {
+    pub fn parse<S: AsRef<str>>(windows: &[S]) -> Result<Self, String> {
+        let windows = windows
+            .iter()
+            .map(|w| CronExpr::parse(w.as_ref()))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { windows })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+
+    pub fn windows(&self) -> Vec<String> {
+        self.windows.iter().map(|w| w.source().to_string()).collect()
+    }
+
+    // An empty schedule places no restriction
+    pub fn is_open(&self, time: DateTime<Utc>) -> bool {
+        self.windows.is_empty() || self.windows.iter().any(|w| w.matches(time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap()
+    }
+
+    #[test]
+    fn test_business_hours_window() {
+        let expr = CronExpr::parse("* 8-17 * * mon-fri").unwrap();
+        // 2024-06-14 is a Friday, 2024-06-15 a Saturday
+        assert!(expr.matches(at(2024, 6, 14, 8, 0)));
+        assert!(expr.matches(at(2024, 6, 14, 17, 59)));
+        assert!(!expr.matches(at(2024, 6, 14, 18, 0)));
+        assert!(!expr.matches(at(2024, 6, 15, 12, 0)));
+    }
+
+    #[test]
+    fn test_steps_lists_and_sunday_alias() {
+        let expr = CronExpr::parse("*/15 0,12 * * 7").unwrap();
+        // 2024-06-16 is a Sunday
+        assert!(expr.matches(at(2024, 6, 16, 12, 30)));
+        assert!(!expr.matches(at(2024, 6, 16, 12, 31)));
+        assert!(!expr.matches(at(2024, 6, 17, 12, 30)));
+    }
+
+    #[test]
+    fn test_invalid_expressions() {
+        assert!(CronExpr::parse("* * * *").is_err());
+        assert!(CronExpr::parse("60 * * * *").is_err());
+        assert!(CronExpr::parse("* 5-2 * * *").is_err());
+        assert!(CronExpr::parse("*/0 * * * *").is_err());
+    }
+
+    #[test]
+    fn test_empty_schedule_is_always_open() {
+        let schedule = Schedule::default();
+        assert!(schedule.is_open(at(2024, 1, 1, 3, 0)));
+    }
+}
@@ -0,0 +1,60 @@
+use crate::schedule::Schedule;
+use crate::sftp::AccessHours;
+use crate::store::Repository;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+
+// Access windows kept on the user accounts in the database. Logins whose
+// username has no account are not restricted.
+pub struct AccountAccessHours {
+    repository: Arc<dyn Repository>,
+}
+
+impl AccountAccessHours {
+    pub fn new(repository: Arc<dyn Repository>) -> Self {
+        Self { repository }
+    }
+}
+
+#[async_trait]
+impl AccessHours for AccountAccessHours {
+    async fn allows(
+        &self,
+        username: &str,
+        time: DateTime<Utc>,
+    ) -> anyhow::Result<bool> {
+        let Some(user) = self.repository.get_user(username).await? else {
+            return Ok(true);
+        };
+        let schedule = Schedule::parse(&user.access_windows)
+            .map_err(anyhow::Error::msg)?;
+        Ok(schedule.is_open(time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::sqlite::SqliteRepository;
+    use chrono::TimeZone;
+
+    #[tokio::test]
+    async fn test_windows_of_accounts() {
+        let repository = Arc::new(SqliteRepository::in_memory().unwrap());
+        repository
+            .create_user("alice", &["* 8-17 * * mon-fri".to_string()], None)
+            .await
+            .unwrap();
+        repository.create_user("bob", &[], None).await.unwrap();
+        let hours = AccountAccessHours::new(repository);
+
+        // 2024-06-14 is a Friday, 2024-06-15 a Saturday
+        let friday = Utc.with_ymd_and_hms(2024, 6, 14, 9, 0, 0).unwrap();
+        let saturday = Utc.with_ymd_and_hms(2024, 6, 15, 9, 0, 0).unwrap();
+        assert!(hours.allows("alice", friday).await.unwrap());
+        assert!(!hours.allows("alice", saturday).await.unwrap());
+        assert!(hours.allows("bob", saturday).await.unwrap());
+        assert!(hours.allows("nobody", saturday).await.unwrap());
+    }
+}
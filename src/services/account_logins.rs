@@ -0,0 +1,149 @@
+use crate::sftp::AccountLogins;
+use crate::store::Repository;
+use argon2::Argon2;
+use argon2::password_hash::{
+    PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+};
+use async_trait::async_trait;
+use rand::RngExt;
+use russh::keys::PublicKey;
+use std::sync::Arc;
+use tracing::warn;
+
+// Logins of the user accounts in the database, by argon2 password or by
+// one of their authorized keys. Serves the default root, so accounts of a
+// tenant never log in here.
+pub struct RepositoryLogins {
+    repository: Arc<dyn Repository>,
+}
+
+impl RepositoryLogins {
+    pub fn new(repository: Arc<dyn Repository>) -> Self {
+        Self { repository }
+    }
+
+    // Whether `username` is an account outside every tenant
+    async fn on_default_root(&self, username: &str) -> anyhow::Result<bool> {
+        let user = self.repository.get_user(username).await?;
+        Ok(user.is_some_and(|user| user.tenant.is_none()))
+    }
+}
+
+#[async_trait]
+impl AccountLogins for RepositoryLogins {
+    async fn password_matches(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> anyhow::Result<bool> {
+        if !self.on_default_root(username).await? {
+            return Ok(false);
+        }
+        let Some(hash) = self.repository.password_hash(username).await? else {
+            return Ok(false);
+        };
+        let password = password.to_string();
+        // Hashing is slow on purpose, keep it off the runtime threads
+        tokio::task::spawn_blocking(move || verify_password(&hash, &password))
+            .await?
+    }
+
+    async fn key_authorized(
+        &self,
+        username: &str,
+        key: &PublicKey,
+    ) -> anyhow::Result<bool> {
+        if !self.on_default_root(username).await? {
+            return Ok(false);
+        }
+        let keys = self.repository.list_keys(username).await?;
+        Ok(keys.iter().any(|authorized| {
+            match PublicKey::from_openssh(&authorized.public_key) {
+                Ok(parsed) => parsed.key_data() == key.key_data(),
+                Err(e) => {
+                    warn!("Ignoring unreadable key {}: {}", authorized.id, e);
+                    false
+                }
+            }
+        }))
+    }
+}
+
+// PHC string of an argon2 hash of `password` with a random salt
+pub fn hash_password(password: &str) -> anyhow::Result<String> {
+    let salt = SaltString::encode_b64(&rand::rng().random::<[u8; 16]>())
+        .map_err(|e| anyhow::anyhow!("Invalid salt: {}", e))?;
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| anyhow::anyhow!("Failed to hash the password: {}", e))?;
+    Ok(hash.to_string())
+}
+
+fn verify_password(hash: &str, password: &str) -> anyhow::Result<bool> {
+    let hash = PasswordHash::new(hash)
+        .map_err(|e| anyhow::anyhow!("Unreadable password hash: {}", e))?;
+    Ok(Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::sqlite::SqliteRepository;
+    use russh::keys::ssh_key::rand_core::OsRng;
+    use russh::keys::{Algorithm, PrivateKey};
+
+    #[tokio::test]
+    async fn test_accounts_log_in_by_password_or_authorized_key() {
+        let repository = Arc::new(SqliteRepository::in_memory().unwrap());
+        repository.create_user("alice", &[], None).await.unwrap();
+        repository.create_user("bob", &[], None).await.unwrap();
+        let hash = hash_password("correct horse battery").unwrap();
+        repository.set_password_hash("alice", Some(&hash)).await.unwrap();
+        let key = PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap();
+        let other = PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap();
+        let openssh = key.public_key().to_openssh().unwrap();
+        repository.add_key("bob", &openssh, None).await.unwrap();
+        let logins = RepositoryLogins::new(repository);
+
+        assert!(
+            logins
+                .password_matches("alice", "correct horse battery")
+                .await
+                .unwrap()
+        );
+        assert!(!logins.password_matches("alice", "wrong").await.unwrap());
+        // No password set, or no account at all
+        assert!(!logins.password_matches("bob", "").await.unwrap());
+        assert!(!logins.password_matches("nobody", "x").await.unwrap());
+
+        assert!(logins.key_authorized("bob", key.public_key()).await.unwrap());
+        assert!(
+            !logins.key_authorized("bob", other.public_key()).await.unwrap()
+        );
+        assert!(
+            !logins.key_authorized("alice", key.public_key()).await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tenant_accounts_are_refused_on_the_default_root() {
+        let repository = Arc::new(SqliteRepository::in_memory().unwrap());
+        repository.create_user("carol", &[], Some("acme")).await.unwrap();
+        let hash = hash_password("correct horse battery").unwrap();
+        repository.set_password_hash("carol", Some(&hash)).await.unwrap();
+        let key = PrivateKey::random(&mut OsRng, Algorithm::Ed25519).unwrap();
+        let openssh = key.public_key().to_openssh().unwrap();
+        repository.add_key("carol", &openssh, None).await.unwrap();
+        let logins = RepositoryLogins::new(repository);
+
+        assert!(
+            !logins
+                .password_matches("carol", "correct horse battery")
+                .await
+                .unwrap()
+        );
+        assert!(
+            !logins.key_authorized("carol", key.public_key()).await.unwrap()
+        );
+    }
+}
@@ -0,0 +1,429 @@
+use crate::config::settings::Settings;
+use crate::models::accounts::{
+    AddKeyRequest, CreateShareRequest, CreateUserRequest,
+    SetAccessWindowsRequest, SetPasswordRequest,
+};
+use crate::responses::sftp::SftpApiResponse;
+use crate::schedule::Schedule;
+use crate::services::account_logins::hash_password;
+use crate::store::{AuthorizedKey, Repository, ShareLink, UserAccount};
+use axum::http::StatusCode;
+use chrono::Utc;
+use rand::RngExt;
+use rand::distr::Alphanumeric;
+use std::path::{Component, Path};
+use std::sync::Arc;
+use tokio::sync::watch;
+use tracing::{error, info};
+
+// Shortest password accepted for a user account
+const MIN_PASSWORD_LEN: usize = 12;
+
+// Manages user accounts, authorized keys and share links in the database
+pub struct AccountService {
+    repository: Option<Arc<dyn Repository>>,
+    // Live settings, for the external address in share URLs
+    settings: watch::Receiver<Settings>,
+}
+
+impl AccountService {
+    pub fn new(
+        repository: Option<Arc<dyn Repository>>,
+        settings: watch::Receiver<Settings>,
+    ) -> Self {
+        Self { repository, settings }
+    }
+
+    pub async fn list_users(&self) -> SftpApiResponse<Vec<UserAccount>> {
+        let Some(repository) = &self.repository else {
+            return no_database();
+        };
+        respond(repository.list_users().await, "list users")
+    }
+
+    // Users belonging to a tenant
+    pub async fn list_tenant_users(
+        &self,
+        tenant: &str,
+    ) -> SftpApiResponse<Vec<UserAccount>> {
+        let Some(repository) = &self.repository else {
+            return no_database();
+        };
+        let users = repository.list_users().await.map(|users| {
+            users
+                .into_iter()
+                .filter(|u| u.tenant.as_deref() == Some(tenant))
+                .collect()
+        });
+        respond(users, "list users")
+    }
+
+    pub async fn create_user(
+        &self,
+        request: CreateUserRequest,
+    ) -> SftpApiResponse<UserAccount> {
+        self.create_tenant_user(request, None).await
+    }
+
+    // Create a user, of a tenant or of the default root. Usernames are
+    // unique across tenants.
+    pub async fn create_tenant_user(
+        &self,
+        request: CreateUserRequest,
+        tenant: Option<&str>,
+    ) -> SftpApiResponse<UserAccount> {
+        let Some(repository) = &self.repository else {
+            return no_database();
+        };
+        if !valid_username(&request.username) {
+            return SftpApiResponse::error(
+                StatusCode::BAD_REQUEST,
+                "Usernames may only contain letters, digits, '.', '_' and '-'",
+            );
+        }
+        if let Err(e) = Schedule::parse(&request.access_windows) {
+            return invalid_windows(e);
+        }
+
+        match repository.get_user(&request.username).await {
+            Ok(Some(_)) => {
+                return SftpApiResponse::error(
+                    StatusCode::CONFLICT,
+                    format!("User '{}' already exists", request.username),
+                );
+            }
+            Ok(None) => {}
+            Err(e) => return internal_error("look up user", e),
+        }
+
+        match tenant {
+            Some(tenant) => {
+                info!("Creating user {} of tenant {}", request.username, tenant)
+            }
+            None => info!("Creating user {}", request.username),
+        }
+        respond(
+            repository
+                .create_user(&request.username, &request.access_windows, tenant)
+                .await,
+            "create user",
+        )
+    }
+
+    // Replace the windows during which a user may be connected; sessions
+    // outside the new windows are cut off at the next check
+    pub async fn set_access_windows(
+        &self,
+        username: &str,
+        request: SetAccessWindowsRequest,
+    ) -> SftpApiResponse<UserAccount> {
+        let Some(repository) = &self.repository else {
+            return no_database();
+        };
+        if let Err(e) = Schedule::parse(&request.access_windows) {
+            return invalid_windows(e);
+        }
+
+        match repository
+            .set_access_windows(username, &request.access_windows)
+            .await
+        {
+            Ok(true) => {}
+            Ok(false) => {
+                return not_found(format!("User '{}' not found", username));
+            }
+            Err(e) => return internal_error("set access windows", e),
+        }
+        info!("Set access windows of user {}", username);
+        match repository.get_user(username).await {
+            Ok(Some(user)) => SftpApiResponse::success(user),
+            Ok(None) => not_found(format!("User '{}' not found", username)),
+            Err(e) => internal_error("look up user", e),
+        }
+    }
+
+    // Set or remove the password of a user; only its argon2 hash is kept
+    pub async fn set_password(
+        &self,
+        username: &str,
+        request: SetPasswordRequest,
+    ) -> SftpApiResponse<UserAccount> {
+        let Some(repository) = &self.repository else {
+            return no_database();
+        };
+        let hash = match request.password {
+            Some(password) if password.expose().len() < MIN_PASSWORD_LEN => {
+                return SftpApiResponse::error(
+                    StatusCode::BAD_REQUEST,
+                    format!(
+                        "Passwords need at least {} characters",
+                        MIN_PASSWORD_LEN
+                    ),
+                );
+            }
+            Some(password) => {
+                let hashed = tokio::task::spawn_blocking(move || {
+                    hash_password(password.expose())
+                })
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|hash| hash);
+                match hashed {
+                    Ok(hash) => Some(hash),
+                    Err(e) => return internal_error("hash password", e),
+                }
+            }
+            None => None,
+        };
+
+        match repository.set_password_hash(username, hash.as_deref()).await {
+            Ok(true) => {}
+            Ok(false) => {
+                return not_found(format!("User '{}' not found", username));
+            }
+            Err(e) => return internal_error("set password", e),
+        }
+        info!("Set password of user {}", username);
+        match repository.get_user(username).await {
+            Ok(Some(user)) => SftpApiResponse::success(user),
+            Ok(None) => not_found(format!("User '{}' not found", username)),
+            Err(e) => internal_error("look up user", e),
+        }
+    }
+
+    pub async fn delete_user(&self, username: &str) -> SftpApiResponse<()> {
+        let Some(repository) = &self.repository else {
+            return no_database();
+        };
+        match repository.delete_user(username).await {
+            Ok(true) => {
+                info!("Deleted user {}", username);
+                SftpApiResponse::success(())
+            }
+            Ok(false) => not_found(format!("User '{}' not found", username)),
+            Err(e) => internal_error("delete user", e),
+        }
+    }
+
+    // Delete a user of a tenant; users of other tenants are not found
+    pub async fn delete_tenant_user(
+        &self,
+        tenant: &str,
+        username: &str,
+    ) -> SftpApiResponse<()> {
+        let Some(repository) = &self.repository else {
+            return no_database();
+        };
+        match repository.get_user(username).await {
+            Ok(Some(user)) if user.tenant.as_deref() == Some(tenant) => {}
+            Ok(_) => {
+                return not_found(format!("User '{}' not found", username));
+            }
+            Err(e) => return internal_error("look up user", e),
+        }
+        self.delete_user(username).await
+    }
+
+    pub async fn list_keys(
+        &self,
+        username: &str,
+    ) -> SftpApiResponse<Vec<AuthorizedKey>> {
+        let Some(repository) = &self.repository else {
+            return no_database();
+        };
+        respond(repository.list_keys(username).await, "list keys")
+    }
+
+    pub async fn add_key(
+        &self,
+        username: &str,
+        request: AddKeyRequest,
+    ) -> SftpApiResponse<AuthorizedKey> {
+        let Some(repository) = &self.repository else {
+            return no_database();
+        };
+        if let Err(e) =
+            russh::keys::PublicKey::from_openssh(&request.public_key)
+        {
+            return SftpApiResponse::error(
+                StatusCode::BAD_REQUEST,
+                format!("Invalid public key: {}", e),
+            );
+        }
+
+        match repository.get_user(username).await {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                return not_found(format!("User '{}' not found", username));
+            }
+            Err(e) => return internal_error("look up user", e),
+        }
+
+        info!("Adding key for user {}", username);
+        respond(
+            repository
+                .add_key(
+                    username,
+                    request.public_key.trim(),
+                    request.comment.as_deref(),
+                )
+                .await,
+            "add key",
+        )
+    }
+
+    pub async fn remove_key(&self, id: i64) -> SftpApiResponse<()> {
+        let Some(repository) = &self.repository else {
+            return no_database();
+        };
+        match repository.remove_key(id).await {
+            Ok(true) => SftpApiResponse::success(()),
+            Ok(false) => not_found(format!("Key {} not found", id)),
+            Err(e) => internal_error("remove key", e),
+        }
+    }
+
+    pub async fn list_shares(&self) -> SftpApiResponse<Vec<ShareLink>> {
+        let Some(repository) = &self.repository else {
+            return no_database();
+        };
+        let shares = repository.list_shares().await.map(|shares| {
+            shares.into_iter().map(|share| self.with_url(share)).collect()
+        });
+        respond(shares, "list shares")
+    }
+
+    pub async fn get_share(&self, token: &str) -> SftpApiResponse<ShareLink> {
+        let Some(repository) = &self.repository else {
+            return no_database();
+        };
+        match repository.get_share(token).await {
+            Ok(Some(share)) => SftpApiResponse::success(self.with_url(share)),
+            Ok(None) => not_found("Share not found"),
+            Err(e) => internal_error("look up share", e),
+        }
+    }
+
+    pub async fn create_share(
+        &self,
+        request: CreateShareRequest,
+    ) -> SftpApiResponse<ShareLink> {
+        let Some(repository) = &self.repository else {
+            return no_database();
+        };
+        if !is_relative_inside_root(&request.path) {
+            return SftpApiResponse::error(
+                StatusCode::BAD_REQUEST,
+                "Path must be relative to the SFTP root and must not contain '..'",
+            );
+        }
+
+        let created_at = Utc::now();
+        let share = ShareLink {
+            token: rand::rng()
+                .sample_iter(&Alphanumeric)
+                .take(32)
+                .map(char::from)
+                .collect(),
+            path: request.path,
+            created_by: request.created_by,
+            created_at,
+            expires_at: request.expires_in_secs.map(|secs| {
+                created_at + chrono::Duration::seconds(secs as i64)
+            }),
+            url: None,
+        };
+
+        info!("Creating share for {}", share.path);
+        match repository.create_share(&share).await {
+            Ok(()) => SftpApiResponse::success(self.with_url(share)),
+            Err(e) => internal_error("create share", e),
+        }
+    }
+
+    // Point a share at the external SFTP address, when one is configured
+    fn with_url(&self, mut share: ShareLink) -> ShareLink {
+        let settings = self.settings.borrow();
+        if let Some((host, port)) =
+            settings.external_address(settings.sftp.port)
+        {
+            share.url = share_url(&host, port, &share.path);
+        }
+        share
+    }
+
+    pub async fn delete_share(&self, token: &str) -> SftpApiResponse<()> {
+        let Some(repository) = &self.repository else {
+            return no_database();
+        };
+        match repository.delete_share(token).await {
+            Ok(true) => SftpApiResponse::success(()),
+            Ok(false) => not_found("Share not found"),
+            Err(e) => internal_error("delete share", e),
+        }
+    }
+}
+
+fn valid_username(username: &str) -> bool {
+    !username.is_empty()
+        && username.len() <= 64
+        && username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
+
+fn is_relative_inside_root(path: &str) -> bool {
+    let path = Path::new(path.trim_start_matches('/'));
+    path.components().all(|c| matches!(c, Component::Normal(_)))
+}
+
+fn share_url(host: &str, port: u16, path: &str) -> Option<String> {
+    let host = if host.contains(':') {
+        format!("[{}]", host)
+    } else {
+        host.to_string()
+    };
+    let url = format!("sftp://{}:{}/{}", host, port, path);
+    reqwest::Url::parse(&url).ok().map(String::from)
+}
+
+fn respond<T: serde::Serialize>(
+    result: anyhow::Result<T>,
+    action: &str,
+) -> SftpApiResponse<T> {
+    match result {
+        Ok(value) => SftpApiResponse::success(value),
+        Err(e) => internal_error(action, e),
+    }
+}
+
+fn internal_error<T: serde::Serialize>(
+    action: &str,
+    e: anyhow::Error,
+) -> SftpApiResponse<T> {
+    error!("Failed to {}: {}", action, e);
+    SftpApiResponse::error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Failed to {}", action),
+    )
+}
+
+fn invalid_windows<T: serde::Serialize>(e: String) -> SftpApiResponse<T> {
+    SftpApiResponse::error(
+        StatusCode::BAD_REQUEST,
+        format!("Invalid access window: {}", e),
+    )
+}
+
+fn not_found<T: serde::Serialize>(
+    message: impl Into<String>,
+) -> SftpApiResponse<T> {
+    SftpApiResponse::error(StatusCode::NOT_FOUND, message)
+}
+
+pub(crate) fn no_database<T: serde::Serialize>() -> SftpApiResponse<T> {
+    SftpApiResponse::error(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "No database is configured",
+    )
+}
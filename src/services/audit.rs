@@ -0,0 +1,299 @@
+use crate::events::{Event, EventBus, EventEnvelope};
+use crate::models::files::FileChecksumResponse;
+use crate::models::sftp::CredentialsAccessEntry;
+use crate::responses::sftp::SftpApiResponse;
+use crate::services::accounts::no_database;
+use crate::store::{
+    AuditEntry, NewTransfer, Repository, TransferDirection, TransferRecord,
+};
+use axum::http::StatusCode;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+// Most entries returned by a single listing request
+const MAX_LIMIT: u32 = 1000;
+
+// Writes every published event to the audit log and uploads to the
+// transfer log
+pub struct AuditRecorder {
+    repository: Arc<dyn Repository>,
+}
+
+impl AuditRecorder {
+    pub fn new(repository: Arc<dyn Repository>) -> Self {
+        Self { repository }
+    }
+
+    // Subscribe to the bus and record events until the bus is closed
+    pub fn start(self, events: &EventBus) -> JoinHandle<()> {
+        let mut receiver = events.subscribe();
+
+        tokio::spawn(async move {
+            info!("Audit recorder started");
+
+            loop {
+                match receiver.recv().await {
+                    Ok(envelope) => self.record(envelope).await,
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!(
+                            "Audit recorder lagged, {} events not recorded",
+                            skipped
+                        );
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+
+            info!("Audit recorder stopped");
+        })
+    }
+
+    async fn record(&self, envelope: EventEnvelope) {
+        // Written directly by the service handing the credentials out, so
+        // none is lost when this recorder falls behind the bus
+        if !matches!(envelope.event, Event::CredentialsAccessed { .. }) {
+            record_audit_event(self.repository.as_ref(), &envelope.event).await;
+        }
+
+        let transfer = match envelope.event {
+            Event::FileUploaded {
+                username,
+                peer,
+                path,
+                bytes,
+                duration_ms,
+                checksums,
+            } => Some(NewTransfer {
+                username,
+                peer,
+                path,
+                direction: TransferDirection::Upload,
+                bytes,
+                duration_ms,
+                checksums,
+            }),
+            Event::FileDownloaded {
+                username,
+                peer,
+                path,
+                bytes,
+                duration_ms,
+            } => Some(NewTransfer {
+                username,
+                peer,
+                path,
+                direction: TransferDirection::Download,
+                bytes,
+                duration_ms,
+                checksums: Default::default(),
+            }),
+            _ => None,
+        };
+        if let Some(transfer) = transfer
+            && let Err(e) = self.repository.record_transfer(transfer).await
+        {
+            error!("Failed to record transfer: {}", e);
+        }
+    }
+}
+
+// Write one event to the audit log
+pub async fn record_audit_event(repository: &dyn Repository, event: &Event) {
+    let username = match event {
+        Event::FileUploaded { username, .. }
+        | Event::FileDownloaded { username, .. }
+        | Event::FileQuarantined { username, .. }
+        | Event::PipelineCompleted { username, .. }
+        | Event::PipelineFailed { username, .. } => Some(username.as_str()),
+        Event::CredentialsExpired { username }
+        | Event::CredentialsExpiring { username, .. } => username.as_deref(),
+        Event::CredentialsRotated { username }
+        | Event::CredentialsAccessed { username, .. }
+        | Event::CredentialsDelivered { username, .. }
+        | Event::CredentialDeliveryFailed { username, .. }
+        | Event::LoginSucceeded { username, .. }
+        | Event::LoginFailed { username, .. }
+        | Event::LoginOutsideAccessHours { username, .. }
+        | Event::ClientRejected { username, .. }
+        | Event::AuthFailuresForUser { username, .. } => {
+            Some(username.as_str())
+        }
+        _ => None,
+    };
+    let detail = serde_json::to_value(event)
+        .ok()
+        .and_then(|mut v| v.get_mut("data").map(|d| d.take()))
+        .unwrap_or_default();
+
+    if let Err(e) =
+        repository.record_audit(event.name(), username, detail).await
+    {
+        error!("Failed to record audit entry: {}", e);
+    }
+}
+
+// Read access to the audit and transfer logs
+pub struct AuditService {
+    repository: Option<Arc<dyn Repository>>,
+}
+
+impl AuditService {
+    pub fn new(repository: Option<Arc<dyn Repository>>) -> Self {
+        Self { repository }
+    }
+
+    // Most recent audit entries, newest first
+    pub async fn list_audit(
+        &self,
+        limit: u32,
+    ) -> SftpApiResponse<Vec<AuditEntry>> {
+        let Some(repository) = &self.repository else {
+            return no_database();
+        };
+        match repository.list_audit(limit.min(MAX_LIMIT), None).await {
+            Ok(entries) => SftpApiResponse::success(entries),
+            Err(e) => {
+                error!("Failed to read audit log: {}", e);
+                SftpApiResponse::error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to read audit log",
+                )
+            }
+        }
+    }
+
+    // Most recent retrievals of credentials, newest first
+    pub async fn list_credentials_access(
+        &self,
+        limit: u32,
+    ) -> SftpApiResponse<Vec<CredentialsAccessEntry>> {
+        let Some(repository) = &self.repository else {
+            return no_database();
+        };
+        let action = Some("credentials_accessed");
+        match repository.list_audit(limit.min(MAX_LIMIT), action).await {
+            Ok(entries) => SftpApiResponse::success(
+                entries.into_iter().map(credentials_access).collect(),
+            ),
+            Err(e) => {
+                error!("Failed to read audit log: {}", e);
+                SftpApiResponse::error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to read audit log",
+                )
+            }
+        }
+    }
+
+    // Most recent transfers, newest first
+    pub async fn list_transfers(
+        &self,
+        limit: u32,
+    ) -> SftpApiResponse<Vec<TransferRecord>> {
+        let Some(repository) = &self.repository else {
+            return no_database();
+        };
+        match repository.list_transfers(limit.min(MAX_LIMIT)).await {
+            Ok(records) => SftpApiResponse::success(records),
+            Err(e) => {
+                error!("Failed to read transfer log: {}", e);
+                SftpApiResponse::error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to read transfer log",
+                )
+            }
+        }
+    }
+
+    // Digests recorded for the latest upload to a path
+    pub async fn get_checksum(
+        &self,
+        path: &str,
+    ) -> SftpApiResponse<FileChecksumResponse> {
+        let Some(repository) = &self.repository else {
+            return no_database();
+        };
+        match repository.latest_upload(path).await {
+            Ok(Some(record)) if !record.checksums.is_empty() => {
+                SftpApiResponse::success(FileChecksumResponse {
+                    path: record.path,
+                    checksums: record.checksums,
+                    bytes: record.bytes,
+                    uploaded_at: record.timestamp,
+                    username: record.username,
+                })
+            }
+            Ok(_) => SftpApiResponse::error(
+                StatusCode::NOT_FOUND,
+                "No checksum recorded for this path",
+            ),
+            Err(e) => {
+                error!("Failed to read transfer log: {}", e);
+                SftpApiResponse::error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to read transfer log",
+                )
+            }
+        }
+    }
+}
+
+fn credentials_access(entry: AuditEntry) -> CredentialsAccessEntry {
+    let field = |name: &str| {
+        entry.detail.get(name).and_then(|v| v.as_str()).map(String::from)
+    };
+    CredentialsAccessEntry {
+        timestamp: entry.timestamp,
+        instance: field("instance").unwrap_or_default(),
+        username: field("username")
+            .or(entry.username.clone())
+            .unwrap_or_default(),
+        api: field("api").unwrap_or_default(),
+        client: field("client"),
+        forwarded_for: field("forwarded_for"),
+        certificate: field("certificate"),
+        api_key: field("api_key"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::sqlite::SqliteRepository;
+
+    #[tokio::test]
+    async fn test_credentials_access_is_recorded_once() {
+        let repository: Arc<dyn Repository> =
+            Arc::new(SqliteRepository::in_memory().unwrap());
+        let events = EventBus::new(16);
+        let recorder = AuditRecorder::new(repository.clone()).start(&events);
+
+        let accessed = Event::CredentialsAccessed {
+            instance: "default".to_string(),
+            username: "alice".to_string(),
+            api: "rest".to_string(),
+            client: Some("192.0.2.1".to_string()),
+            forwarded_for: None,
+            certificate: None,
+            api_key: Some("tenant:acme".to_string()),
+        };
+        // What a service handing out credentials does
+        record_audit_event(repository.as_ref(), &accessed).await;
+        events.publish(accessed);
+        events.publish(Event::CredentialsRotated {
+            username: "alice".to_string(),
+        });
+        drop(events);
+        recorder.await.unwrap();
+
+        let audit = AuditService::new(Some(repository));
+        let accesses = audit.list_credentials_access(10).await.sftp.unwrap();
+        assert_eq!(accesses.len(), 1);
+        assert_eq!(accesses[0].username, "alice");
+        assert_eq!(accesses[0].client.as_deref(), Some("192.0.2.1"));
+        assert_eq!(accesses[0].api_key.as_deref(), Some("tenant:acme"));
+        assert_eq!(audit.list_audit(10).await.sftp.unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,295 @@
+use crate::config::settings::{BackupSettings, Settings};
+use crate::events::{Event, EventBus};
+use crate::models::files::BackupStatus;
+use crate::responses::sftp::SftpApiResponse;
+use crate::schedule::CronExpr;
+use crate::services::mirror;
+use axum::http::StatusCode;
+use chrono::{DateTime, Timelike, Utc};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{RwLock, watch};
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+const PREFIX: &str = "backup-";
+const SUFFIX: &str = ".tar.zst";
+
+// Snapshots the default root into tar.zst archives in the backup directory,
+// optionally copied to a mirror target, keeping the most recent ones. One
+// backup runs at a time.
+pub struct BackupService {
+    root_dir: PathBuf,
+    settings: watch::Receiver<Settings>,
+    events: EventBus,
+    status: RwLock<BackupStatus>,
+}
+
+impl BackupService {
+    // Archives left by earlier runs seed the status
+    pub fn new(
+        root_dir: String,
+        settings: watch::Receiver<Settings>,
+        events: EventBus,
+    ) -> Self {
+        let dir = PathBuf::from(&settings.borrow().backup.dir);
+        let archives = archive_names(&dir).unwrap_or_default();
+        let mut status =
+            BackupStatus { archives: archives.len(), ..Default::default() };
+        if let Some(name) = archives.last()
+            && let Ok(metadata) = std::fs::metadata(dir.join(name))
+        {
+            status.last_archive = Some(name.clone());
+            status.last_bytes = Some(metadata.len());
+            status.last_success_at =
+                metadata.modified().ok().map(DateTime::<Utc>::from);
+        }
+        Self {
+            root_dir: PathBuf::from(root_dir),
+            settings,
+            events,
+            status: RwLock::new(status),
+        }
+    }
+
+    // Check the schedule at the start of every minute. The schedule is
+    // re-read each time so changes apply on reload.
+    pub fn start(self: &Arc<Self>) -> JoinHandle<()> {
+        let service = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let now = Utc::now();
+                let wait = 60 - now.second() as u64;
+                tokio::time::sleep(Duration::from_secs(wait)).await;
+
+                let backup = service.settings.borrow().backup.clone();
+                if !backup.enabled || backup.schedule.is_empty() {
+                    continue;
+                }
+                match CronExpr::parse(&backup.schedule) {
+                    Ok(schedule) if schedule.matches(Utc::now()) => {
+                        if service.begin().await {
+                            service.run().await;
+                        } else {
+                            warn!("Scheduled backup skipped, one is running");
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!("Invalid backup schedule: {}", e),
+                }
+            }
+        })
+    }
+
+    // Start a backup in the background, for POST /admin/backup
+    pub async fn run_now(self: &Arc<Self>) -> SftpApiResponse<BackupStatus> {
+        if !self.settings.borrow().backup.enabled {
+            return SftpApiResponse::error(
+                StatusCode::BAD_REQUEST,
+                "Backups are disabled",
+            );
+        }
+        if !self.begin().await {
+            return SftpApiResponse::error(
+                StatusCode::CONFLICT,
+                "A backup is already running",
+            );
+        }
+        let service = self.clone();
+        tokio::spawn(async move { service.run().await });
+        SftpApiResponse::success(self.status().await)
+    }
+
+    pub async fn status(&self) -> BackupStatus {
+        self.status.read().await.clone()
+    }
+
+    pub async fn get_status(&self) -> SftpApiResponse<BackupStatus> {
+        SftpApiResponse::success(self.status().await)
+    }
+
+    // Mark a backup as running, unless one already is
+    async fn begin(&self) -> bool {
+        let mut status = self.status.write().await;
+        if status.running {
+            return false;
+        }
+        status.running = true;
+        status.last_started_at = Some(Utc::now());
+        true
+    }
+
+    async fn run(&self) {
+        let backup = self.settings.borrow().backup.clone();
+        info!("Backing up {}", self.root_dir.display());
+        let result = self.backup(&backup).await;
+
+        let mut status = self.status.write().await;
+        status.running = false;
+        status.last_finished_at = Some(Utc::now());
+        match result {
+            Ok((archive, bytes, archives)) => {
+                info!("Backup {} written, {} bytes", archive, bytes);
+                status.last_success_at = status.last_finished_at;
+                status.last_archive = Some(archive.clone());
+                status.last_bytes = Some(bytes);
+                status.last_error = None;
+                status.archives = archives;
+                self.events.publish(Event::BackupCompleted { archive, bytes });
+            }
+            Err(e) => {
+                error!("Backup failed: {:#}", e);
+                status.last_error = Some(format!("{:#}", e));
+                self.events
+                    .publish(Event::BackupFailed { error: format!("{:#}", e) });
+            }
+        }
+    }
+
+    // Write an archive, copy it to the target and prune old ones. Returns
+    // the archive's name and size and the number of archives kept.
+    async fn backup(
+        &self,
+        backup: &BackupSettings,
+    ) -> anyhow::Result<(String, u64, usize)> {
+        let dir = PathBuf::from(&backup.dir);
+        tokio::fs::create_dir_all(&dir).await?;
+        let name = archive_name(Utc::now());
+        let path = dir.join(&name);
+        let partial = dir.join(format!("{}.partial", name));
+
+        let root = self.root_dir.clone();
+        let level = backup.compression_level;
+        let written = partial.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            write_archive(&root, &written, level)
+        })
+        .await
+        .map_err(io::Error::other)
+        .and_then(|result| result);
+        let bytes = match result {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&partial).await;
+                return Err(anyhow::anyhow!(e).context("cannot write archive"));
+            }
+        };
+        tokio::fs::rename(&partial, &path).await?;
+
+        let target = backup.target.as_ref().and_then(|name| {
+            let settings = self.settings.borrow();
+            settings.mirror.targets.iter().find(|t| &t.name == name).cloned()
+        });
+        let mut client = match &target {
+            Some(target) => {
+                let mut client =
+                    mirror::connect(target).await.map_err(|e| {
+                        e.context(format!("cannot connect to {}", target.name))
+                    })?;
+                client.upload(&path, &format!("/{}", name)).await.map_err(
+                    |e| e.context(format!("cannot copy to {}", target.name)),
+                )?;
+                Some(client)
+            }
+            None => None,
+        };
+
+        let mut archives = archive_names(&dir)?;
+        for old in expired(&mut archives, backup.keep) {
+            if let Err(e) = tokio::fs::remove_file(dir.join(&old)).await {
+                warn!("Failed to remove backup {}: {}", old, e);
+            }
+            if let Some(client) = &mut client
+                && let Err(e) = client.remove(&format!("/{}", old)).await
+            {
+                warn!("Failed to remove backup {} from target: {}", old, e);
+            }
+        }
+        Ok((name, bytes, archives.len()))
+    }
+}
+
+fn archive_name(at: DateTime<Utc>) -> String {
+    format!("{}{}{}", PREFIX, at.format("%Y%m%dT%H%M%SZ"), SUFFIX)
+}
+
+// Names of the archives in `dir`, oldest first
+fn archive_names(dir: &Path) -> io::Result<Vec<String>> {
+    let mut names = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        if name.starts_with(PREFIX) && name.ends_with(SUFFIX) {
+            names.push(name);
+        }
+    }
+    // The timestamp in the name sorts them by age
+    names.sort();
+    Ok(names)
+}
+
+// Split off the archives beyond the `keep` most recent; 0 keeps all
+fn expired(archives: &mut Vec<String>, keep: usize) -> Vec<String> {
+    if keep == 0 || archives.len() <= keep {
+        return Vec::new();
+    }
+    let kept = archives.split_off(archives.len() - keep);
+    std::mem::replace(archives, kept)
+}
+
+// Tar the tree below `root` into a zstd-compressed file; links are stored
+// as links rather than followed
+fn write_archive(root: &Path, path: &Path, level: i32) -> io::Result<u64> {
+    let file = std::fs::File::create(path)?;
+    let mut builder = tar::Builder::new(zstd::Encoder::new(file, level)?);
+    builder.follow_symlinks(false);
+    builder.append_dir_all(".", root)?;
+    let file = builder.into_inner()?.finish()?;
+    file.sync_all()?;
+    Ok(file.metadata()?.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_archives_round_trip_and_expire_oldest_first() {
+        let dir = std::env::temp_dir().join("sftp-manager-backup-test");
+        let _ = std::fs::remove_dir_all(&dir);
+        let root = dir.join("root");
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        std::fs::write(root.join("sub/a.txt"), b"hello").unwrap();
+
+        let path = dir.join(archive_name(Utc::now()));
+        write_archive(&root, &path, 3).unwrap();
+        let decoder =
+            zstd::Decoder::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let mut archive = tar::Archive::new(decoder);
+        let mut found = false;
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            if entry.path().unwrap().ends_with("sub/a.txt") {
+                let mut content = String::new();
+                entry.read_to_string(&mut content).unwrap();
+                assert_eq!(content, "hello");
+                found = true;
+            }
+        }
+        assert!(found);
+        assert_eq!(archive_names(&dir).unwrap().len(), 1);
+
+        let mut archives: Vec<String> = ["1", "2", "3"]
+            .iter()
+            .map(|n| format!("{}{}{}", PREFIX, n, SUFFIX))
+            .collect();
+        assert!(expired(&mut archives.clone(), 0).is_empty());
+        assert!(expired(&mut archives.clone(), 3).is_empty());
+        let old = expired(&mut archives, 1);
+        assert_eq!(old.len(), 2);
+        assert!(old[0].contains('1') && old[1].contains('2'));
+        assert_eq!(archives, vec![format!("{}3{}", PREFIX, SUFFIX)]);
+    }
+}
@@ -0,0 +1,36 @@
+use crate::responses::sftp::SftpApiResponse;
+use crate::services::redis_state::{RedisStateStore, ReplicaSummary};
+use axum::http::StatusCode;
+use std::sync::Arc;
+use tracing::error;
+
+// Information about the replicas sharing state through Redis
+pub struct ClusterService {
+    store: Option<Arc<RedisStateStore>>,
+}
+
+impl ClusterService {
+    pub fn new(store: Option<Arc<RedisStateStore>>) -> Self {
+        Self { store }
+    }
+
+    pub async fn list_replicas(&self) -> SftpApiResponse<Vec<ReplicaSummary>> {
+        let Some(store) = &self.store else {
+            return SftpApiResponse::error(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Redis is not configured",
+            );
+        };
+
+        match store.replicas().await {
+            Ok(replicas) => SftpApiResponse::success(replicas),
+            Err(e) => {
+                error!("Failed to list replicas: {}", e);
+                SftpApiResponse::error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to list replicas",
+                )
+            }
+        }
+    }
+}
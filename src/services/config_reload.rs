@@ -0,0 +1,405 @@
+use crate::config::settings::{Settings, SettingsSource};
+use crate::config::validation::{
+    ConfigIssue, ValidationContext, has_errors, validate,
+};
+use crate::events::Event;
+use crate::models::sftp::{ListenAddress, SftpState};
+use crate::schedule::Schedule;
+use crate::sftp::ServerContext;
+use notify::{EventKind, RecursiveMode, Watcher};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, mpsc, watch};
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+// Settings that are only read while starting up
+const RESTART_REQUIRED: &[&str] = &[
+    "server.port",
+    "server.host",
+    "server.watch_config",
+    "server.max_body_bytes",
+    "server.max_concurrent_uploads",
+    "server.tls.enabled",
+    "server.tls.certificate_file",
+    "server.tls.private_key_file",
+    "server.tls.client_ca_file",
+    "sftp.root_dir",
+    "sftp.state_file",
+    "sftp.host_key_file",
+    "sftp.checksum_algorithms",
+    "sftp.sparse_files",
+    "sftp.window_bytes",
+    "sftp.max_packet_bytes",
+    "sftp.max_read_bytes",
+    "sftp.max_write_bytes",
+    "sftp.transfer_buffer_bytes",
+    "sftp.read_ahead_chunks",
+    "sftp.keepalive_interval_secs",
+    "sftp.keepalive_max_missed",
+    "sftp.session_memory_mb",
+    "sftp.session_transfers",
+    "sftp.file_io",
+    "database.url",
+    "database.pool_size",
+    "database.password_file",
+    "redis.url",
+    "redis.password_file",
+    "redis.key_prefix",
+    "redis.replica_id",
+    "journal.enabled",
+    "journal.dir",
+    "journal.max_file_bytes",
+    "journal.max_files",
+    "logging.file",
+    "logging.rotation",
+    "logging.max_files",
+    "logging.max_file_bytes",
+    "logging.syslog.transport",
+    "logging.syslog.address",
+    "logging.syslog.facility",
+    "logging.syslog.app_name",
+    "scan.clamd_address",
+    "scan.quarantine_dir",
+    "scan.timeout_secs",
+    "scan.quarantine_on_error",
+    "watcher.enabled",
+    "watcher.debounce_ms",
+    "listing_cache.enabled",
+    "listing_cache.ttl_ms",
+    "listing_cache.max_entries",
+    "trash.enabled",
+    "scratch.enabled",
+    "scratch.path",
+    "filenames.normalization",
+    "filenames.reject_invalid_utf8",
+    "filenames.transliterate",
+    "filenames.sanitize",
+    "filenames.hide_dotfiles",
+    "filenames.block_dotfiles",
+    "filenames.dotfile_allow",
+    "recording.enabled",
+    "recording.dir",
+    "recording.users",
+    "recording.include_data",
+    "search.enabled",
+    "s3.enabled",
+    "s3.port",
+    "grpc.enabled",
+    "grpc.host",
+    "grpc.port",
+    "mirror.enabled",
+    "mirror.queue_dir",
+    "geoip.database_file",
+    "secrets.key_file",
+    "secrets.key_env",
+    "vault.address",
+    "vault.token_file",
+    "vault.token_env",
+    "vault.namespace",
+    "vault.mount",
+    "vault.path",
+    "instances",
+    "tenants",
+    "event_stream.enabled",
+    "event_stream.kind",
+    "event_stream.brokers",
+    "event_stream.subject",
+    "event_stream.partition",
+    "event_stream.username",
+    "event_stream.password",
+    "event_stream.events",
+    "port_mapping.enabled",
+    "port_mapping.protocol",
+    "port_mapping.gateway",
+    "port_mapping.external_port",
+    "port_mapping.lease_secs",
+    "mdns.enabled",
+    "mdns.instance_name",
+];
+
+// Outcome of a reload attempt
+#[derive(Debug, Serialize)]
+pub struct ReloadReport {
+    pub reloaded: bool,
+    pub applied: Vec<String>,
+    pub requires_restart: Vec<String>,
+    pub issues: Vec<ConfigIssue>,
+}
+
+// Re-reads the configuration and applies settings that can change at runtime
+pub struct ConfigReloader {
+    source: SettingsSource,
+    live: watch::Sender<Settings>,
+    state: SftpState,
+    context: ServerContext,
+    // Serializes reloads triggered by the API and the file watcher
+    lock: Mutex<()>,
+}
+
+impl ConfigReloader {
+    pub fn new(
+        source: SettingsSource,
+        live: watch::Sender<Settings>,
+        state: SftpState,
+        context: ServerContext,
+    ) -> Self {
+        Self { source, live, state, context, lock: Mutex::new(()) }
+    }
+
+    // Load the configuration from disk and apply it
+    pub async fn reload(&self) -> ReloadReport {
+        let _guard = self.lock.lock().await;
+
+        let loaded = match Settings::load(&self.source) {
+            Ok(settings) => settings,
+            Err(e) => {
+                error!("Failed to reload configuration: {}", e);
+                return ReloadReport {
+                    reloaded: false,
+                    applied: Vec::new(),
+                    requires_restart: Vec::new(),
+                    issues: vec![ConfigIssue::error("config", e.to_string())],
+                };
+            }
+        };
+
+        let current = self.live.borrow().clone();
+        let context = ValidationContext {
+            bound_ports: vec![current.server.port, current.sftp.port],
+            ..Default::default()
+        };
+        let issues = validate(&loaded, &context);
+        if has_errors(&issues) {
+            warn!("Reloaded configuration is invalid, keeping current one");
+            return ReloadReport {
+                reloaded: false,
+                applied: Vec::new(),
+                requires_restart: Vec::new(),
+                issues,
+            };
+        }
+
+        let (merged, applied, requires_restart) =
+            match merge_changes(&current, &loaded) {
+                Ok(result) => result,
+                Err(e) => {
+                    error!("Failed to merge reloaded configuration: {}", e);
+                    return ReloadReport {
+                        reloaded: false,
+                        applied: Vec::new(),
+                        requires_restart: Vec::new(),
+                        issues: vec![ConfigIssue::error("config", e)],
+                    };
+                }
+            };
+
+        self.apply(&merged).await;
+        self.live.send_replace(merged);
+
+        for field in &applied {
+            info!("Applied configuration change: {}", field);
+        }
+        for field in &requires_restart {
+            warn!("Configuration change needs a restart: {}", field);
+        }
+        if !applied.is_empty() || !requires_restart.is_empty() {
+            self.context.events.publish(Event::ConfigReloaded {
+                applied: applied.clone(),
+                requires_restart: requires_restart.clone(),
+            });
+        }
+
+        ReloadReport { reloaded: true, applied, requires_restart, issues }
+    }
+
+    // Push settings into components that do not read the live settings
+    async fn apply(&self, settings: &Settings) {
+        // A running listener moves to the new address without downtime
+        self.state
+            .set_listen_address(ListenAddress {
+                bind_addrs: settings.sftp.bind_addrs.clone(),
+                port: settings.sftp.port,
+            })
+            .await;
+
+        // Validation guarantees the windows parse
+        if let Ok(schedule) = Schedule::parse(&settings.schedule.windows) {
+            self.state.set_schedule(schedule).await;
+        }
+
+        self.context
+            .auth_failures
+            .reconfigure(settings.sftp.auth_failure_limits());
+        self.context.tarpit.reconfigure(settings.sftp.tarpit_limits());
+        self.context.disk_space.reconfigure(settings.sftp.disk_limits());
+        self.context
+            .client_versions
+            .reconfigure(settings.sftp.client_version_rules());
+        self.context.completion.reconfigure(settings.completion.rules());
+        self.context.path_rules.set(settings.sftp.path_rules.clone());
+        self.context.landing.reconfigure(settings.landing.rules());
+    }
+
+    // Reload whenever one of the configuration files changes
+    pub fn watch(self: Arc<Self>) -> notify::Result<JoinHandle<()>> {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        // The environment file sits next to the base file
+        let paths = self.source.paths();
+        let dir = match paths[0].parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let stems: Vec<OsString> = paths
+            .iter()
+            .filter_map(|p| p.file_stem().map(|s| s.to_os_string()))
+            .collect();
+
+        let mut watcher = notify::recommended_watcher(
+            move |result: notify::Result<notify::Event>| {
+                if let Ok(event) = result
+                    && matches!(
+                        event.kind,
+                        EventKind::Create(_) | EventKind::Modify(_)
+                    )
+                    && event.paths.iter().any(|p| {
+                        p.file_stem()
+                            .is_some_and(|s| stems.iter().any(|t| t == s))
+                    })
+                {
+                    let _ = tx.send(());
+                }
+            },
+        )?;
+        watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+        info!("Watching {} for configuration changes", dir.display());
+
+        Ok(tokio::spawn(async move {
+            // The watcher stops when dropped, keep it alive with the task
+            let _watcher = watcher;
+
+            while rx.recv().await.is_some() {
+                // Editors write files in several steps; wait for them to settle
+                tokio::time::sleep(Duration::from_millis(500)).await;
+                while rx.try_recv().is_ok() {}
+
+                info!("Configuration file changed, reloading");
+                self.reload().await;
+            }
+        }))
+    }
+}
+
+// Fields whose value differs between two configurations
+pub fn changed_fields(
+    current: &Settings,
+    other: &Settings,
+) -> Result<Vec<String>, String> {
+    let (_, mut applied, requires_restart) = merge_changes(current, other)?;
+    applied.extend(requires_restart);
+    applied.sort();
+    Ok(applied)
+}
+
+// Apply every runtime-changeable difference from `loaded` onto `current`.
+// Returns the merged settings plus the changed fields, split into those
+// that were applied and those that need a restart.
+fn merge_changes(
+    current: &Settings,
+    loaded: &Settings,
+) -> Result<(Settings, Vec<String>, Vec<String>), String> {
+    let current_value =
+        serde_json::to_value(current).map_err(|e| e.to_string())?;
+    let loaded_value =
+        serde_json::to_value(loaded).map_err(|e| e.to_string())?;
+
+    let mut current_fields = Map::new();
+    flatten("", &current_value, &mut current_fields);
+    let mut loaded_fields = Map::new();
+    flatten("", &loaded_value, &mut loaded_fields);
+
+    let mut merged = current_value;
+    let mut applied = Vec::new();
+    let mut requires_restart = Vec::new();
+
+    for (field, value) in &loaded_fields {
+        if current_fields.get(field) == Some(value) {
+            continue;
+        }
+
+        if RESTART_REQUIRED.contains(&field.as_str()) {
+            requires_restart.push(field.clone());
+        } else {
+            set_path(&mut merged, field, value.clone());
+            applied.push(field.clone());
+        }
+    }
+
+    let merged = serde_json::from_value(merged).map_err(|e| e.to_string())?;
+    Ok((merged, applied, requires_restart))
+}
+
+// Flatten nested objects into dotted paths; arrays are kept whole
+fn flatten(prefix: &str, value: &Value, out: &mut Map<String, Value>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten(&path, value, out);
+            }
+        }
+        _ => {
+            out.insert(prefix.to_string(), value.clone());
+        }
+    }
+}
+
+fn set_path(root: &mut Value, path: &str, value: Value) {
+    let mut target = root;
+    let mut parts = path.split('.').peekable();
+
+    while let Some(part) = parts.next() {
+        let Value::Object(map) = target else { return };
+        if parts.peek().is_none() {
+            map.insert(part.to_string(), value);
+            return;
+        }
+        target = map.entry(part).or_insert_with(|| Value::Object(Map::new()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_splits_live_and_restart_fields() {
+        let current = Settings::default();
+        let mut loaded = Settings::default();
+        loaded.server.port = 8080;
+        loaded.mdns.enabled = true;
+        loaded.sftp.drain_timeout_secs = 42;
+        loaded.schedule.windows = vec!["* 8-17 * * *".to_string()];
+
+        let (merged, applied, requires_restart) =
+            merge_changes(&current, &loaded).unwrap();
+
+        assert_eq!(
+            requires_restart,
+            vec!["mdns.enabled".to_string(), "server.port".to_string()]
+        );
+        assert!(applied.contains(&"sftp.drain_timeout_secs".to_string()));
+        assert!(applied.contains(&"schedule.windows".to_string()));
+        assert_eq!(merged.server.port, current.server.port);
+        assert_eq!(merged.sftp.drain_timeout_secs, 42);
+    }
+}
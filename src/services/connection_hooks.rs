@@ -0,0 +1,159 @@
+use crate::config::settings::{ConnectionHookAction, Settings};
+use crate::services::pipeline::run_program;
+use crate::services::webhook::{EVENT_HEADER, SIGNATURE_HEADER, sign};
+use crate::sftp::hooks::{ConnectionHook, ConnectionInfo};
+use anyhow::bail;
+use async_trait::async_trait;
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{debug, warn};
+
+// Runs the hooks configured in [connection_hooks] whose events include the
+// one at hand. Hooks are read from the live settings so they apply on
+// reload; a failing hook is logged and the next one runs.
+pub struct ConfiguredConnectionHooks {
+    settings: watch::Receiver<Settings>,
+    client: reqwest::Client,
+}
+
+impl ConfiguredConnectionHooks {
+    pub fn new(settings: watch::Receiver<Settings>) -> Self {
+        let client = reqwest::Client::builder()
+            .build()
+            .expect("Failed to build connection hook HTTP client");
+
+        Self { settings, client }
+    }
+
+    async fn notify(
+        &self,
+        url: &str,
+        secret: Option<&str>,
+        info: &ConnectionInfo,
+    ) -> anyhow::Result<()> {
+        let body = serde_json::to_vec(info)?;
+        let timeout =
+            Duration::from_secs(self.settings.borrow().webhooks.timeout_secs);
+
+        let mut request = self
+            .client
+            .post(url)
+            .header("Content-Type", "application/json")
+            .header(EVENT_HEADER, info.event.name())
+            .timeout(timeout);
+        if let Some(secret) = secret {
+            request = request.header(SIGNATURE_HEADER, sign(secret, &body));
+        }
+
+        let response = request.body(body).send().await?;
+        if !response.status().is_success() {
+            bail!("{} returned {}", url, response.status());
+        }
+        debug!(
+            "Connection hook {} accepted {} of session {}",
+            url,
+            info.event.name(),
+            info.session_id
+        );
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ConnectionHook for ConfiguredConnectionHooks {
+    async fn on_connection(&self, info: &ConnectionInfo) {
+        let actions: Vec<ConnectionHookAction> = self
+            .settings
+            .borrow()
+            .connection_hooks
+            .hooks
+            .iter()
+            .filter(|hook| {
+                hook.events.is_empty() || hook.events.contains(&info.event)
+            })
+            .map(|hook| hook.action.clone())
+            .collect();
+
+        for action in &actions {
+            let result = match action {
+                ConnectionHookAction::Webhook { url, secret } => {
+                    self.notify(url, secret.as_deref(), info).await
+                }
+                ConnectionHookAction::Command { command, timeout_secs } => {
+                    let timeout = Duration::from_secs(*timeout_secs);
+                    run_program(&arguments(command, info), timeout).await
+                }
+            };
+            if let Err(e) = result {
+                warn!(
+                    "Connection hook failed on {} of session {}: {:#}",
+                    info.event.name(),
+                    info.session_id,
+                    e
+                );
+            }
+        }
+    }
+}
+
+// The command with the details of the connection filled in
+fn arguments(command: &[String], info: &ConnectionInfo) -> Vec<String> {
+    let ip = info.peer.map(|a| a.ip().to_string()).unwrap_or_default();
+    let port = info.peer.map(|a| a.port().to_string()).unwrap_or_default();
+    let session = info.session_id.to_string();
+    command
+        .iter()
+        .map(|arg| {
+            arg.replace("{event}", info.event.name())
+                .replace("{ip}", &ip)
+                .replace("{port}", &port)
+                .replace("{username}", info.username.as_deref().unwrap_or(""))
+                .replace("{country}", info.country.as_deref().unwrap_or(""))
+                .replace("{reason}", info.reason.unwrap_or(""))
+                .replace("{session}", &session)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sftp::hooks::ConnectionEvent;
+
+    #[test]
+    fn test_command_arguments_describe_the_connection() {
+        let info = ConnectionInfo {
+            event: ConnectionEvent::AuthRejected,
+            session_id: 42,
+            peer: Some("203.0.113.7:50022".parse().unwrap()),
+            country: None,
+            username: Some("alice".to_string()),
+            auth_method: Some("password"),
+            client: None,
+            reason: Some("invalid_credentials"),
+        };
+        let command: Vec<String> = [
+            "/usr/local/bin/fw",
+            "{event}",
+            "{ip}:{port}",
+            "{username}",
+            "{country}",
+            "{reason}",
+            "#{session}",
+        ]
+        .map(String::from)
+        .to_vec();
+        assert_eq!(
+            arguments(&command, &info),
+            [
+                "/usr/local/bin/fw",
+                "auth_rejected",
+                "203.0.113.7:50022",
+                "alice",
+                "",
+                "invalid_credentials",
+                "#42"
+            ]
+        );
+    }
+}
@@ -0,0 +1,165 @@
+use crate::config::settings::{DeliverySettings, Settings};
+use crate::events::{Event, EventBus};
+use crate::services::smtp::{self, Mail};
+use crate::sftp::SecretString;
+use anyhow::{anyhow, bail};
+use serde_json::json;
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{info, warn};
+use zeroize::Zeroizing;
+
+// Newly issued credentials and where clients use them
+pub struct IssuedCredentials {
+    // "enabled" or "rotated"
+    pub reason: &'static str,
+    pub username: String,
+    pub password: SecretString,
+    pub host: String,
+    pub port: u16,
+    pub expires_at: Option<String>,
+}
+
+impl IssuedCredentials {
+    fn subject(&self) -> String {
+        match self.reason {
+            "rotated" => format!("SFTP credentials rotated for {}", self.host),
+            _ => format!("SFTP access enabled on {}", self.host),
+        }
+    }
+
+    // Everything a client needs to connect, password included
+    fn text(&self) -> Zeroizing<String> {
+        // IPv6 addresses are bracketed in URLs
+        let host = if self.host.contains(':') {
+            format!("[{}]", self.host)
+        } else {
+            self.host.clone()
+        };
+        Zeroizing::new(format!(
+            "{}\n\nHost: {}\nPort: {}\nUsername: {}\nPassword: {}\n\
+             Expires: {}\nConnect: sftp://{}@{}:{}\n",
+            self.subject(),
+            self.host,
+            self.port,
+            self.username,
+            self.password.expose(),
+            self.expires_at.as_deref().unwrap_or("never"),
+            self.username,
+            host,
+            self.port
+        ))
+    }
+}
+
+// Sends new credentials by email and to Slack, as configured under
+// [delivery]
+#[derive(Clone)]
+pub struct CredentialDelivery {
+    client: reqwest::Client,
+    // Live settings so channel changes apply on configuration reload
+    settings: watch::Receiver<Settings>,
+    events: EventBus,
+}
+
+impl CredentialDelivery {
+    pub fn new(settings: watch::Receiver<Settings>, events: EventBus) -> Self {
+        let client = reqwest::Client::builder()
+            .build()
+            .expect("Failed to build delivery HTTP client");
+
+        Self { client, settings, events }
+    }
+
+    // Send the credentials on every configured channel in the background.
+    // Failures are logged and published but do not fail the request that
+    // issued the credentials; they can still be read through the API.
+    pub fn deliver(&self, credentials: IssuedCredentials) {
+        let settings = self.settings.borrow().delivery.clone();
+        if settings.slack_webhook_url.is_none() && settings.email.to.is_empty()
+        {
+            return;
+        }
+        let delivery = self.clone();
+        tokio::spawn(async move {
+            delivery.send_all(&settings, &credentials).await;
+        });
+    }
+
+    async fn send_all(
+        &self,
+        settings: &DeliverySettings,
+        credentials: &IssuedCredentials,
+    ) {
+        let timeout = Duration::from_secs(settings.timeout_secs);
+        let text = credentials.text();
+
+        if let Some(url) = &settings.slack_webhook_url {
+            let result = self.post_to_slack(url, &text, timeout).await;
+            self.report("slack", credentials, result);
+        }
+
+        if !settings.email.to.is_empty() {
+            let subject = credentials.subject();
+            let mail =
+                Mail { to: &settings.email.to, subject: &subject, body: &text };
+            let result = tokio::time::timeout(
+                timeout,
+                smtp::send(&settings.email, &mail),
+            )
+            .await
+            .unwrap_or_else(|_| Err(anyhow!("timed out")));
+            self.report("email", credentials, result);
+        }
+    }
+
+    async fn post_to_slack(
+        &self,
+        url: &SecretString,
+        text: &str,
+        timeout: Duration,
+    ) -> anyhow::Result<()> {
+        let response = self
+            .client
+            .post(url.expose())
+            .json(&json!({ "text": text }))
+            .timeout(timeout)
+            .send()
+            .await
+            // The URL is the secret, keep it out of the error
+            .map_err(|e| e.without_url())?;
+        if !response.status().is_success() {
+            bail!("webhook returned {}", response.status());
+        }
+        Ok(())
+    }
+
+    fn report(
+        &self,
+        channel: &str,
+        credentials: &IssuedCredentials,
+        result: anyhow::Result<()>,
+    ) {
+        let username = credentials.username.clone();
+        let channel = channel.to_string();
+        match result {
+            Ok(()) => {
+                info!("Sent SFTP credentials of {} by {}", username, channel);
+                self.events
+                    .publish(Event::CredentialsDelivered { username, channel });
+            }
+            Err(e) => {
+                let error = format!("{:#}", e);
+                warn!(
+                    "Sending SFTP credentials of {} by {} failed: {}",
+                    username, channel, error
+                );
+                self.events.publish(Event::CredentialDeliveryFailed {
+                    username,
+                    channel,
+                    error,
+                });
+            }
+        }
+    }
+}
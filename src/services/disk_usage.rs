@@ -0,0 +1,175 @@
+use crate::config::settings::Settings;
+use crate::models::sftp::{DiskUsageResponse, UsageEntry};
+use crate::responses::sftp::SftpApiResponse;
+use axum::http::StatusCode;
+use chrono::Utc;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, watch};
+use tracing::{debug, error};
+
+// Computes and caches disk usage of the SFTP root
+pub struct DiskUsageService {
+    root_dir: PathBuf,
+    settings: watch::Receiver<Settings>,
+    cache: Mutex<Option<(Instant, DiskUsageResponse)>>,
+}
+
+impl DiskUsageService {
+    pub fn new(root_dir: String, settings: watch::Receiver<Settings>) -> Self {
+        Self {
+            root_dir: PathBuf::from(root_dir),
+            settings,
+            cache: Mutex::new(None),
+        }
+    }
+
+    // Get usage, recomputing only when the cached value is stale.
+    // The lock is held while scanning so concurrent requests share one walk.
+    pub async fn get_usage(&self) -> SftpApiResponse<DiskUsageResponse> {
+        let cache_ttl =
+            Duration::from_secs(self.settings.borrow().sftp.usage_cache_secs);
+        let mut cache = self.cache.lock().await;
+
+        if let Some((computed, usage)) = cache.as_ref()
+            && computed.elapsed() < cache_ttl
+        {
+            return SftpApiResponse::success(usage.clone());
+        }
+
+        let root = self.root_dir.clone();
+        match tokio::task::spawn_blocking(move || scan_root(&root)).await {
+            Ok(Ok(usage)) => {
+                *cache = Some((Instant::now(), usage.clone()));
+                SftpApiResponse::success(usage)
+            }
+            Ok(Err(e)) => {
+                error!("Failed to scan {}: {}", self.root_dir.display(), e);
+                SftpApiResponse::error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to compute disk usage: {}", e),
+                )
+            }
+            Err(e) => {
+                error!("Disk usage task failed: {}", e);
+                SftpApiResponse::error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to compute disk usage",
+                )
+            }
+        }
+    }
+}
+
+// Walk the root and aggregate sizes per top-level entry
+fn scan_root(root: &Path) -> std::io::Result<DiskUsageResponse> {
+    debug!("Scanning disk usage of {}", root.display());
+
+    let mut entries = Vec::new();
+    for entry in std::fs::read_dir(root)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        let name = entry.file_name().to_string_lossy().to_string();
+
+        let (bytes, files) = if metadata.is_dir() {
+            scan_dir(&entry.path())
+        } else if metadata.is_file() {
+            (metadata.len(), 1)
+        } else {
+            (0, 0)
+        };
+
+        entries.push(UsageEntry {
+            name,
+            is_dir: metadata.is_dir(),
+            bytes,
+            files,
+        });
+    }
+
+    entries.sort_by_key(|e| std::cmp::Reverse(e.bytes));
+
+    Ok(DiskUsageResponse {
+        root_dir: root.display().to_string(),
+        total_bytes: entries.iter().map(|e| e.bytes).sum(),
+        file_count: entries.iter().map(|e| e.files).sum(),
+        computed_at: Utc::now().to_rfc3339(),
+        entries,
+    })
+}
+
+// Recursively sum regular files below a directory without following symlinks
+fn scan_dir(dir: &Path) -> (u64, u64) {
+    let mut bytes = 0;
+    let mut files = 0;
+    let mut pending = vec![dir.to_path_buf()];
+
+    while let Some(current) = pending.pop() {
+        let Ok(read_dir) = std::fs::read_dir(&current) else {
+            continue;
+        };
+
+        for entry in read_dir.flatten() {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+
+            if file_type.is_dir() {
+                pending.push(entry.path());
+            } else if file_type.is_file()
+                && let Ok(metadata) = entry.metadata()
+            {
+                bytes += metadata.len();
+                files += 1;
+            }
+        }
+    }
+
+    (bytes, files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_usage_breaks_down_the_root_and_is_cached() {
+        let root = std::env::temp_dir()
+            .join(format!("sftp-manager-usage-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("big/nested")).unwrap();
+        std::fs::create_dir_all(root.join("empty")).unwrap();
+        std::fs::write(root.join("big/a.bin"), vec![0u8; 300]).unwrap();
+        std::fs::write(root.join("big/nested/b.bin"), vec![0u8; 200]).unwrap();
+        std::fs::write(root.join("top.txt"), vec![0u8; 10]).unwrap();
+
+        let (_tx, settings) = watch::channel(Settings::default());
+        let service =
+            DiskUsageService::new(root.to_string_lossy().to_string(), settings);
+
+        let usage = service.get_usage().await.sftp.unwrap();
+        assert_eq!(usage.total_bytes, 510);
+        assert_eq!(usage.file_count, 3);
+        let entries: Vec<(&str, bool, u64, u64)> = usage
+            .entries
+            .iter()
+            .map(|e| (e.name.as_str(), e.is_dir, e.bytes, e.files))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                ("big", true, 500, 2),
+                ("top.txt", false, 10, 1),
+                ("empty", true, 0, 0),
+            ]
+        );
+
+        // A file written within the cache lifetime does not show up yet
+        std::fs::write(root.join("late.txt"), vec![0u8; 5]).unwrap();
+        let cached = service.get_usage().await.sftp.unwrap();
+        assert_eq!(cached.total_bytes, 510);
+        assert_eq!(cached.computed_at, usage.computed_at);
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
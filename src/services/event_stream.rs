@@ -0,0 +1,180 @@
+use crate::config::settings::{EventStreamKind, EventStreamSettings};
+use crate::events::{EventBus, EventEnvelope};
+use async_trait::async_trait;
+use chrono::Utc;
+use rskafka::client::partition::{
+    Compression, PartitionClient, UnknownTopicHandling,
+};
+use rskafka::client::{ClientBuilder, Credentials, SaslConfig};
+use rskafka::record::Record;
+use std::collections::BTreeMap;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+// Where published events are written
+#[async_trait]
+trait EventSink: Send + Sync {
+    async fn publish(
+        &self,
+        event_name: &str,
+        body: Vec<u8>,
+    ) -> anyhow::Result<()>;
+}
+
+// Events on NATS subjects named after them below a prefix
+struct NatsSink {
+    client: async_nats::Client,
+    prefix: String,
+}
+
+#[async_trait]
+impl EventSink for NatsSink {
+    async fn publish(
+        &self,
+        event_name: &str,
+        body: Vec<u8>,
+    ) -> anyhow::Result<()> {
+        let subject = nats_subject(&self.prefix, event_name);
+        self.client.publish(subject, body.into()).await?;
+        Ok(())
+    }
+}
+
+// Events in one partition of a Kafka topic, keyed by their name
+struct KafkaSink {
+    partition: PartitionClient,
+}
+
+#[async_trait]
+impl EventSink for KafkaSink {
+    async fn publish(
+        &self,
+        event_name: &str,
+        body: Vec<u8>,
+    ) -> anyhow::Result<()> {
+        let record = Record {
+            key: Some(event_name.as_bytes().to_vec()),
+            value: Some(body),
+            headers: BTreeMap::new(),
+            timestamp: Utc::now(),
+        };
+        self.partition
+            .produce(vec![record], Compression::NoCompression)
+            .await?;
+        Ok(())
+    }
+}
+
+// Publishes events from the bus to Kafka or NATS, so ingestion can react
+// to landed files without polling. Events that fail to publish are logged
+// and dropped; the clients reconnect on their own.
+pub struct EventStreamer {
+    sink: Box<dyn EventSink>,
+    // Event names to publish; empty means all
+    events: Vec<String>,
+}
+
+impl EventStreamer {
+    pub async fn connect(
+        settings: &EventStreamSettings,
+    ) -> anyhow::Result<Self> {
+        let credentials = settings.password.as_ref().map(|password| {
+            (settings.username.clone(), password.expose().to_string())
+        });
+        let sink: Box<dyn EventSink> = match settings.kind {
+            EventStreamKind::Nats => {
+                let options = match credentials {
+                    Some((user, password)) => {
+                        async_nats::ConnectOptions::with_user_and_password(
+                            user, password,
+                        )
+                    }
+                    None => async_nats::ConnectOptions::new(),
+                };
+                let client = options.connect(settings.brokers.clone()).await?;
+                Box::new(NatsSink { client, prefix: settings.subject.clone() })
+            }
+            EventStreamKind::Kafka => {
+                let mut builder = ClientBuilder::new(settings.brokers.clone());
+                if let Some((user, password)) = credentials {
+                    builder = builder.sasl_config(SaslConfig::Plain(
+                        Credentials::new(user, password),
+                    ));
+                }
+                let partition = builder
+                    .build()
+                    .await?
+                    .partition_client(
+                        settings.subject.clone(),
+                        settings.partition,
+                        UnknownTopicHandling::Retry,
+                    )
+                    .await?;
+                Box::new(KafkaSink { partition })
+            }
+        };
+        info!(
+            "Connected to {:?} for event streaming to {}",
+            settings.kind, settings.subject
+        );
+        Ok(Self { sink, events: settings.events.clone() })
+    }
+
+    // Subscribe to the bus and publish events until the bus is closed
+    pub fn start(self, events: &EventBus) -> JoinHandle<()> {
+        let mut receiver = events.subscribe();
+        tokio::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(envelope) => self.publish(&envelope).await,
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!(
+                            "Event streamer lagged, {} events dropped",
+                            skipped
+                        );
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+            info!("Event streamer stopped");
+        })
+    }
+
+    async fn publish(&self, envelope: &EventEnvelope) {
+        let event_name = envelope.event.name();
+        if !self.events.is_empty()
+            && !self.events.iter().any(|e| e == event_name)
+        {
+            return;
+        }
+        let body = match serde_json::to_vec(envelope) {
+            Ok(body) => body,
+            Err(e) => {
+                error!("Failed to serialize event {}: {}", event_name, e);
+                return;
+            }
+        };
+        if let Err(e) = self.sink.publish(event_name, body).await {
+            warn!("Failed to publish event {}: {}", event_name, e);
+        }
+    }
+}
+
+fn nats_subject(prefix: &str, event_name: &str) -> String {
+    format!("{}.{}", prefix.trim_end_matches('.'), event_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nats_subjects_are_named_after_events() {
+        assert_eq!(
+            nats_subject("sftp-manager.events", "file_uploaded"),
+            "sftp-manager.events.file_uploaded"
+        );
+        assert_eq!(nats_subject("sftp.", "login_failed"), "sftp.login_failed");
+    }
+}
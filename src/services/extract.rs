@@ -0,0 +1,475 @@
+use crate::config::settings::{ExtractSettings, Settings};
+use crate::events::{Event, EventBus};
+use crate::models::files::{ExtractJob, ExtractRequest, ExtractState};
+use crate::responses::sftp::SftpApiResponse;
+use crate::sftp::scratch::ScratchArea;
+use crate::sftp::trash::Trash;
+use anyhow::{Context, anyhow, bail};
+use axum::http::StatusCode;
+use chrono::Utc;
+use flate2::read::GzDecoder;
+use rand::RngExt;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+// Finished jobs kept for progress queries
+const KEEP_FINISHED_JOBS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl Format {
+    fn detect(path: &str) -> Option<Self> {
+        let lower = path.to_lowercase();
+        if lower.ends_with(".zip") {
+            Some(Format::Zip)
+        } else if lower.ends_with(".tar.gz") || lower.ends_with(".tgz") {
+            Some(Format::TarGz)
+        } else if lower.ends_with(".tar") {
+            Some(Format::Tar)
+        } else {
+            None
+        }
+    }
+}
+
+type Jobs = Arc<Mutex<HashMap<String, ExtractJob>>>;
+
+// Unpacks uploaded archives below the SFTP root in background jobs whose
+// progress can be polled
+pub struct ExtractService {
+    root_dir: PathBuf,
+    settings: watch::Receiver<Settings>,
+    events: EventBus,
+    jobs: Jobs,
+}
+
+impl ExtractService {
+    pub fn new(
+        root_dir: String,
+        settings: watch::Receiver<Settings>,
+        events: EventBus,
+    ) -> Self {
+        Self {
+            root_dir: PathBuf::from(root_dir),
+            settings,
+            events,
+            jobs: Arc::default(),
+        }
+    }
+
+    pub async fn start(
+        &self,
+        request: ExtractRequest,
+    ) -> SftpApiResponse<ExtractJob> {
+        let Some(format) = Format::detect(&request.path) else {
+            return SftpApiResponse::error(
+                StatusCode::BAD_REQUEST,
+                "Only .zip, .tar, .tar.gz and .tgz archives are supported",
+            );
+        };
+        let Some(archive) = safe_relative(&request.path) else {
+            return SftpApiResponse::error(
+                StatusCode::BAD_REQUEST,
+                "Invalid archive path",
+            );
+        };
+        // Next to the archive, named after it, unless given
+        let target = match &request.target {
+            Some(target) => target.clone(),
+            None => strip_extension(&request.path),
+        };
+        let Some(target_dir) = safe_relative(&target) else {
+            return SftpApiResponse::error(
+                StatusCode::BAD_REQUEST,
+                "Invalid target directory",
+            );
+        };
+        let archive = self.root_dir.join(archive);
+        if !archive.is_file() {
+            return SftpApiResponse::error(
+                StatusCode::NOT_FOUND,
+                "No such archive",
+            );
+        }
+
+        let id = format!("{:016x}", rand::rng().random::<u64>());
+        let job = ExtractJob {
+            id: id.clone(),
+            path: request.path.clone(),
+            target: format!("/{}", target.trim_matches('/')),
+            state: ExtractState::Running,
+            files: 0,
+            bytes: 0,
+            started_at: Utc::now(),
+            finished_at: None,
+            error: None,
+        };
+        {
+            let mut jobs = self.jobs.lock().unwrap();
+            prune(&mut jobs);
+            jobs.insert(id.clone(), job.clone());
+        }
+        info!("Extracting {} into {}", job.path, job.target);
+
+        let extractor = Extractor {
+            root: self.root_dir.clone(),
+            target: self.root_dir.join(target_dir),
+            limits: self.settings.borrow().extract.clone(),
+            overwrite: request.overwrite,
+            jobs: self.jobs.clone(),
+            id: id.clone(),
+            files: 0,
+            bytes: 0,
+        };
+        let jobs = self.jobs.clone();
+        let events = self.events.clone();
+        tokio::spawn(async move {
+            let result = tokio::task::spawn_blocking(move || {
+                extractor.run(&archive, format)
+            })
+            .await
+            .unwrap_or_else(|e| Err(anyhow!("extraction task failed: {}", e)));
+
+            let mut jobs = jobs.lock().unwrap();
+            let Some(job) = jobs.get_mut(&id) else { return };
+            job.finished_at = Some(Utc::now());
+            match result {
+                Ok(()) => {
+                    job.state = ExtractState::Completed;
+                    info!(
+                        "Extracted {} files ({} bytes) from {}",
+                        job.files, job.bytes, job.path
+                    );
+                    events.publish(Event::ArchiveExtracted {
+                        path: job.path.clone(),
+                        target: job.target.clone(),
+                        files: job.files,
+                        bytes: job.bytes,
+                    });
+                }
+                Err(e) => {
+                    job.state = ExtractState::Failed;
+                    job.error = Some(format!("{:#}", e));
+                    warn!("Failed to extract {}: {:#}", job.path, e);
+                }
+            }
+        });
+
+        SftpApiResponse::success(job)
+    }
+
+    pub fn get_job(&self, id: &str) -> SftpApiResponse<ExtractJob> {
+        match self.jobs.lock().unwrap().get(id) {
+            Some(job) => SftpApiResponse::success(job.clone()),
+            None => SftpApiResponse::error(
+                StatusCode::NOT_FOUND,
+                "No such extraction job",
+            ),
+        }
+    }
+}
+
+// Drop the oldest finished jobs beyond the ones kept
+fn prune(jobs: &mut HashMap<String, ExtractJob>) {
+    let mut finished: Vec<(String, chrono::DateTime<Utc>)> = jobs
+        .values()
+        .filter(|j| j.state != ExtractState::Running)
+        .map(|j| (j.id.clone(), j.started_at))
+        .collect();
+    if finished.len() < KEEP_FINISHED_JOBS {
+        return;
+    }
+    finished.sort_by_key(|(_, started)| *started);
+    for (id, _) in &finished[..=finished.len() - KEEP_FINISHED_JOBS] {
+        jobs.remove(id);
+    }
+}
+
+// Writes the entries of one archive below the target directory
+struct Extractor {
+    root: PathBuf,
+    target: PathBuf,
+    limits: ExtractSettings,
+    overwrite: bool,
+    jobs: Jobs,
+    id: String,
+    files: u64,
+    bytes: u64,
+}
+
+impl Extractor {
+    fn run(mut self, archive: &Path, format: Format) -> anyhow::Result<()> {
+        let relative = self.target.strip_prefix(&self.root)?;
+        create_below(&self.root, relative)
+            .with_context(|| "cannot create the target directory")?;
+        let root = self.root.canonicalize()?;
+        if !self.target.canonicalize()?.starts_with(&root) {
+            bail!("the target directory leads out of the root directory");
+        }
+        let file = File::open(archive)?;
+        match format {
+            Format::Zip => self.unzip(file),
+            Format::Tar => self.untar(tar::Archive::new(file)),
+            Format::TarGz => {
+                self.untar(tar::Archive::new(GzDecoder::new(file)))
+            }
+        }
+    }
+
+    fn unzip(&mut self, file: File) -> anyhow::Result<()> {
+        let mut archive = zip::ZipArchive::new(file)?;
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i)?;
+            let name = entry.name().to_string();
+            let relative = safe_relative(&name)
+                .ok_or_else(|| anyhow!("unsafe path in archive: {}", name))?;
+            if entry.is_dir() {
+                self.create_dir(&relative)?;
+            } else if entry.is_symlink() {
+                warn!("Skipping symbolic link {} in archive", name);
+            } else {
+                self.write_file(&relative, &mut entry)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn untar<R: Read>(
+        &mut self,
+        mut archive: tar::Archive<R>,
+    ) -> anyhow::Result<()> {
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let name = entry.path()?.to_string_lossy().to_string();
+            let relative = safe_relative(&name)
+                .ok_or_else(|| anyhow!("unsafe path in archive: {}", name))?;
+            let kind = entry.header().entry_type();
+            if kind.is_dir() {
+                self.create_dir(&relative)?;
+            } else if kind.is_file() {
+                self.write_file(&relative, &mut entry)?;
+            } else {
+                warn!("Skipping {} in archive, not a regular file", name);
+            }
+        }
+        Ok(())
+    }
+
+    fn create_dir(&self, relative: &Path) -> anyhow::Result<()> {
+        create_below(&self.target, relative)
+    }
+
+    fn write_file(
+        &mut self,
+        relative: &Path,
+        reader: &mut dyn Read,
+    ) -> anyhow::Result<()> {
+        if self.files >= self.limits.max_files {
+            bail!("archive has more than {} files", self.limits.max_files);
+        }
+        let path = self.target.join(relative);
+        if let Some(parent) = relative.parent() {
+            create_below(&self.target, parent)?;
+        }
+        match fs::symlink_metadata(&path) {
+            Ok(_) if !self.overwrite => {
+                bail!("{} already exists", relative.display());
+            }
+            Ok(metadata) if metadata.is_dir() => {
+                bail!("{} is a directory", relative.display());
+            }
+            // Replace the entry itself, as writing to it would follow a
+            // symbolic link wherever it leads
+            Ok(_) => fs::remove_file(&path)?,
+            Err(_) => {}
+        }
+
+        // Sizes in archive headers cannot be trusted, so count while writing
+        let remaining = self.limits.max_bytes - self.bytes;
+        let mut output =
+            File::options().write(true).create_new(true).open(&path)?;
+        let written = io::copy(&mut reader.take(remaining + 1), &mut output)?;
+        if written > remaining {
+            drop(output);
+            let _ = fs::remove_file(&path);
+            bail!(
+                "archive expands to more than {} bytes",
+                self.limits.max_bytes
+            );
+        }
+
+        self.files += 1;
+        self.bytes += written;
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(&self.id) {
+            job.files = self.files;
+            job.bytes = self.bytes;
+        }
+        Ok(())
+    }
+}
+
+// Create `relative` below `base` one directory at a time. Existing
+// directories, possibly symbolic links, must not lead out of `base`, so
+// nothing is ever created outside it.
+fn create_below(base: &Path, relative: &Path) -> anyhow::Result<()> {
+    let canonical_base = base.canonicalize()?;
+    let mut path = base.to_path_buf();
+    for component in relative.components() {
+        path.push(component);
+        match fs::create_dir(&path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                if !path.canonicalize()?.starts_with(&canonical_base) {
+                    bail!("{} leads out of {}", path.display(), base.display());
+                }
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+// A path relative to a directory that cannot leave it, or `None`
+fn safe_relative(path: &str) -> Option<PathBuf> {
+    let mut relative = PathBuf::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(part) => relative.push(part),
+            Component::RootDir | Component::CurDir => {}
+            Component::ParentDir | Component::Prefix(_) => return None,
+        }
+    }
+    if relative.as_os_str().is_empty()
+        || Trash::contains(&relative)
+        || ScratchArea::contains(&relative)
+    {
+        return None;
+    }
+    Some(relative)
+}
+
+fn strip_extension(path: &str) -> String {
+    let lower = path.to_lowercase();
+    for extension in [".tar.gz", ".tgz", ".tar", ".zip"] {
+        if lower.ends_with(extension) {
+            return path[..path.len() - extension.len()].to_string();
+        }
+    }
+    path.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_safe_relative() {
+        assert_eq!(safe_relative("/in/a.csv"), Some(PathBuf::from("in/a.csv")));
+        assert_eq!(safe_relative("./a/./b"), Some(PathBuf::from("a/b")));
+        assert_eq!(safe_relative("../../etc/passwd"), None);
+        assert_eq!(safe_relative("a/../../b"), None);
+        assert_eq!(safe_relative("/.trash/x"), None);
+        assert_eq!(safe_relative("/"), None);
+    }
+
+    #[test]
+    fn test_extract_respects_size_limit() {
+        let dir = std::env::temp_dir()
+            .join(format!("sftp-manager-extract-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let archive = dir.join("bundle.tar");
+        let mut builder = tar::Builder::new(File::create(&archive).unwrap());
+        for name in ["a.txt", "sub/b.txt"] {
+            let data = [b'x'; 100];
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, &data[..]).unwrap();
+        }
+        builder.finish().unwrap();
+
+        let extractor = |max_bytes| Extractor {
+            root: dir.clone(),
+            target: dir.join("out"),
+            limits: ExtractSettings { max_bytes, max_files: 10 },
+            overwrite: true,
+            jobs: Jobs::default(),
+            id: String::new(),
+            files: 0,
+            bytes: 0,
+        };
+        extractor(200).run(&archive, Format::Tar).unwrap();
+        assert_eq!(fs::read(dir.join("out/sub/b.txt")).unwrap().len(), 100);
+
+        let err = extractor(150).run(&archive, Format::Tar).unwrap_err();
+        assert!(err.to_string().contains("more than 150 bytes"));
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_extract_does_not_follow_symbolic_links() {
+        let dir = std::env::temp_dir()
+            .join(format!("sftp-manager-extract-links-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let root = dir.join("root");
+        let outside = dir.join("outside");
+        fs::create_dir_all(root.join("out")).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(outside.join("victim"), b"keep").unwrap();
+        std::os::unix::fs::symlink(outside.join("victim"), root.join("out/a"))
+            .unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("escape")).unwrap();
+
+        let archive = dir.join("bundle.tar");
+        let mut builder = tar::Builder::new(File::create(&archive).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(3);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, "a", &b"new"[..]).unwrap();
+        builder.finish().unwrap();
+
+        let extractor = |target: &str| Extractor {
+            root: root.clone(),
+            target: root.join(target),
+            limits: ExtractSettings { max_bytes: 100, max_files: 10 },
+            overwrite: true,
+            jobs: Jobs::default(),
+            id: String::new(),
+            files: 0,
+            bytes: 0,
+        };
+
+        // The link is replaced, not written through
+        extractor("out").run(&archive, Format::Tar).unwrap();
+        assert_eq!(fs::read(root.join("out/a")).unwrap(), b"new");
+        assert!(
+            !fs::symlink_metadata(root.join("out/a")).unwrap().is_symlink()
+        );
+        assert_eq!(fs::read(outside.join("victim")).unwrap(), b"keep");
+
+        // A target behind a link out of the root is refused before anything
+        // is created there
+        assert!(extractor("escape/sub").run(&archive, Format::Tar).is_err());
+        assert!(!outside.join("sub").exists());
+        assert!(extractor("escape").run(&archive, Format::Tar).is_err());
+        assert!(!outside.join("a").exists());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,300 @@
+use crate::config::settings::Settings;
+use crate::events::{Event, EventBus};
+use crate::models::files::{FileSearchQuery, FileSearchResponse, SearchResult};
+use crate::responses::sftp::SftpApiResponse;
+use crate::sftp::glob::glob_match;
+use crate::sftp::scratch::ScratchArea;
+use crate::sftp::trash::Trash;
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{Notify, RwLock, watch};
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+// Results returned when the query sets no limit
+const DEFAULT_LIMIT: usize = 100;
+
+#[derive(Debug, Clone)]
+struct IndexedFile {
+    bytes: u64,
+    modified: DateTime<Utc>,
+}
+
+// In-memory index of the files below the SFTP root, keyed by their path
+// as seen over SFTP. It is built by walking the tree once and then kept up
+// to date from the file watcher's events, with a full rescan at a fixed
+// interval and whenever events were missed.
+pub struct FileIndex {
+    root_dir: PathBuf,
+    settings: watch::Receiver<Settings>,
+    // `None` until the first scan has finished
+    files: RwLock<Option<BTreeMap<String, IndexedFile>>>,
+    rescan: Notify,
+}
+
+impl FileIndex {
+    pub fn new(root_dir: String, settings: watch::Receiver<Settings>) -> Self {
+        Self {
+            root_dir: PathBuf::from(root_dir),
+            settings,
+            files: RwLock::new(None),
+            rescan: Notify::new(),
+        }
+    }
+
+    pub fn start(self: &Arc<Self>, events: &EventBus) -> Vec<JoinHandle<()>> {
+        let index = self.clone();
+        let scanner = tokio::spawn(async move {
+            loop {
+                index.scan().await;
+                let interval = Duration::from_secs(
+                    index.settings.borrow().search.rescan_interval_secs,
+                );
+                tokio::select! {
+                    _ = tokio::time::sleep(interval) => {}
+                    _ = index.rescan.notified() => {}
+                }
+            }
+        });
+
+        let index = self.clone();
+        let mut receiver = events.subscribe();
+        let updater = tokio::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(envelope) => index.apply(&envelope.event).await,
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!(
+                            "File index missed {} events, rescanning",
+                            skipped
+                        );
+                        index.rescan.notify_one();
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+
+        vec![scanner, updater]
+    }
+
+    async fn scan(&self) {
+        let root = self.root_dir.clone();
+        let started = std::time::Instant::now();
+        match tokio::task::spawn_blocking(move || walk(&root)).await {
+            Ok(Ok(files)) => {
+                info!(
+                    "Indexed {} files below {} in {:?}",
+                    files.len(),
+                    self.root_dir.display(),
+                    started.elapsed()
+                );
+                *self.files.write().await = Some(files);
+            }
+            Ok(Err(e)) => {
+                error!("Failed to index {}: {}", self.root_dir.display(), e)
+            }
+            Err(e) => error!("File index task failed: {}", e),
+        }
+    }
+
+    // Update the entries of a path reported by the file watcher
+    async fn apply(&self, event: &Event) {
+        let path = match event {
+            Event::FileCreated { path }
+            | Event::FileModified { path }
+            | Event::FileDeleted { path } => path,
+            _ => return,
+        };
+        let local_path = self.root_dir.join(path.trim_start_matches('/'));
+        let metadata = tokio::fs::symlink_metadata(&local_path).await.ok();
+
+        let mut files = self.files.write().await;
+        let Some(files) = files.as_mut() else { return };
+        match metadata {
+            Some(metadata) if metadata.is_file() => {
+                files.insert(path.clone(), indexed(&metadata));
+            }
+            // A deleted directory takes everything below it along
+            _ => {
+                files.remove(path);
+                let prefix = format!("{}/", path.trim_end_matches('/'));
+                let below: Vec<String> = files
+                    .range::<String, _>((
+                        Bound::Included(&prefix),
+                        Bound::Unbounded,
+                    ))
+                    .take_while(|(p, _)| p.starts_with(&prefix))
+                    .map(|(p, _)| p.clone())
+                    .collect();
+                for p in below {
+                    files.remove(&p);
+                }
+            }
+        }
+        debug!("Updated file index for {}", path);
+    }
+
+    pub async fn search(
+        &self,
+        query: FileSearchQuery,
+    ) -> SftpApiResponse<FileSearchResponse> {
+        let search = self.settings.borrow().search.clone();
+        if !search.enabled {
+            return SftpApiResponse::error(
+                StatusCode::NOT_FOUND,
+                "File search is disabled",
+            );
+        }
+        if query.q.is_empty() {
+            return SftpApiResponse::error(
+                StatusCode::BAD_REQUEST,
+                "Query must not be empty",
+            );
+        }
+        let files = self.files.read().await;
+        let Some(files) = files.as_ref() else {
+            return SftpApiResponse::error(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "File index is not ready",
+            );
+        };
+
+        let base = format!("/{}", query.path.trim_matches('/'));
+        let prefix =
+            if base == "/" { base.clone() } else { format!("{}/", base) };
+        let limit =
+            query.limit.unwrap_or(DEFAULT_LIMIT).min(search.max_results);
+        let matcher = Matcher::new(&query.q);
+
+        let mut results = Vec::new();
+        let mut truncated = false;
+        let candidates = files
+            .range::<String, _>((Bound::Included(&prefix), Bound::Unbounded))
+            .take_while(|(path, _)| path.starts_with(&prefix));
+        for (path, file) in candidates {
+            let relative = &path[prefix.len()..];
+            if let Some(depth) = query.depth
+                && relative.matches('/').count() >= depth
+            {
+                continue;
+            }
+            if !matcher.matches(path) {
+                continue;
+            }
+            if results.len() == limit {
+                truncated = true;
+                break;
+            }
+            results.push(SearchResult {
+                path: path.clone(),
+                bytes: file.bytes,
+                modified: file.modified,
+            });
+        }
+
+        SftpApiResponse::success(FileSearchResponse { results, truncated })
+    }
+}
+
+// How a query is compared with indexed paths
+enum Matcher {
+    // Glob against the whole path when it names directories, otherwise
+    // against the file name
+    Glob { pattern: String, full_path: bool },
+    // Case-insensitive substring of the file name
+    Substring(String),
+}
+
+impl Matcher {
+    fn new(query: &str) -> Self {
+        if query.contains(['*', '?']) {
+            Matcher::Glob {
+                pattern: query.to_string(),
+                full_path: query.contains('/'),
+            }
+        } else {
+            Matcher::Substring(query.to_lowercase())
+        }
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        match self {
+            Matcher::Glob { pattern, full_path: true } => {
+                glob_match(pattern, path)
+            }
+            Matcher::Glob { pattern, full_path: false } => {
+                glob_match(pattern, name)
+            }
+            Matcher::Substring(needle) => {
+                name.to_lowercase().contains(needle.as_str())
+            }
+        }
+    }
+}
+
+fn indexed(metadata: &std::fs::Metadata) -> IndexedFile {
+    let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+    IndexedFile { bytes: metadata.len(), modified: modified.into() }
+}
+
+// Every regular file below the root, skipping the trash
+fn walk(root: &Path) -> io::Result<BTreeMap<String, IndexedFile>> {
+    let mut files = BTreeMap::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            // Removed while walking
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            if Trash::contains(relative) || ScratchArea::contains(relative) {
+                continue;
+            }
+            let Ok(metadata) = entry.path().symlink_metadata() else {
+                continue;
+            };
+            if metadata.is_dir() {
+                pending.push(path);
+            } else if metadata.is_file() {
+                let key = format!("/{}", relative.to_string_lossy());
+                files.insert(key, indexed(&metadata));
+            }
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matcher() {
+        let csv = Matcher::new("*.csv");
+        assert!(csv.matches("/in/sub/report.csv"));
+        assert!(!csv.matches("/in/report.csv.bak"));
+
+        let nested = Matcher::new("/in/**/2026-*.csv");
+        assert!(nested.matches("/in/a/b/2026-01.csv"));
+        assert!(!nested.matches("/out/2026-01.csv"));
+
+        let substring = Matcher::new("Report");
+        assert!(substring.matches("/in/monthly-report.pdf"));
+        assert!(!substring.matches("/reports/summary.pdf"));
+    }
+}
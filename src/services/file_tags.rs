@@ -0,0 +1,156 @@
+use crate::models::files::FileTagSearchRequest;
+use crate::responses::sftp::SftpApiResponse;
+use crate::services::accounts::no_database;
+use crate::services::files::FileService;
+use crate::store::{FileTags, Repository};
+use axum::http::StatusCode;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tracing::{error, info};
+
+// Tags a file may carry
+const MAX_TAGS: usize = 32;
+const MAX_KEY_LEN: usize = 64;
+const MAX_VALUE_LEN: usize = 256;
+// Results returned when the search sets no limit, and at most
+const DEFAULT_LIMIT: u32 = 100;
+const MAX_LIMIT: u32 = 1000;
+
+// Key/value tags on files below the default SFTP root, kept in the
+// database, e.g. the customer and batch an upload belongs to
+pub struct FileTagService {
+    files: Arc<FileService>,
+    repository: Option<Arc<dyn Repository>>,
+}
+
+impl FileTagService {
+    pub fn new(
+        files: Arc<FileService>,
+        repository: Option<Arc<dyn Repository>>,
+    ) -> Self {
+        Self { files, repository }
+    }
+
+    pub async fn get(&self, path: &str) -> SftpApiResponse<FileTags> {
+        let Some(repository) = &self.repository else {
+            return no_database();
+        };
+        let path = normalize(path);
+        match repository.get_file_tags(&path).await {
+            Ok(tags) => SftpApiResponse::success(FileTags { path, tags }),
+            Err(e) => internal_error("read file tags", e),
+        }
+    }
+
+    // Replace the tags of an existing file; an empty map removes them
+    pub async fn set(
+        &self,
+        path: &str,
+        tags: BTreeMap<String, String>,
+    ) -> SftpApiResponse<FileTags> {
+        let Some(repository) = &self.repository else {
+            return no_database();
+        };
+        if let Err(message) = validate(&tags) {
+            return SftpApiResponse::error(StatusCode::BAD_REQUEST, message);
+        }
+        if path.trim_matches('/').is_empty()
+            || self.files.resolve(path).await.is_none()
+        {
+            return SftpApiResponse::error(
+                StatusCode::NOT_FOUND,
+                "No such file or directory",
+            );
+        }
+
+        let path = normalize(path);
+        info!("Setting {} tag(s) on {}", tags.len(), path);
+        match repository.set_file_tags(&path, &tags).await {
+            Ok(()) => SftpApiResponse::success(FileTags { path, tags }),
+            Err(e) => internal_error("store file tags", e),
+        }
+    }
+
+    pub async fn search(
+        &self,
+        request: FileTagSearchRequest,
+    ) -> SftpApiResponse<Vec<FileTags>> {
+        let Some(repository) = &self.repository else {
+            return no_database();
+        };
+        if request.tags.is_empty() {
+            return SftpApiResponse::error(
+                StatusCode::BAD_REQUEST,
+                "At least one tag is required",
+            );
+        }
+        let limit = request.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+        match repository.find_tagged_files(&request.tags, limit).await {
+            Ok(files) => SftpApiResponse::success(files),
+            Err(e) => internal_error("search file tags", e),
+        }
+    }
+}
+
+// Path as seen over SFTP, e.g. "in/a.csv" becomes "/in/a.csv"
+fn normalize(path: &str) -> String {
+    format!("/{}", path.trim_matches('/'))
+}
+
+fn validate(tags: &BTreeMap<String, String>) -> Result<(), String> {
+    if tags.len() > MAX_TAGS {
+        return Err(format!("A file may carry at most {} tags", MAX_TAGS));
+    }
+    for (key, value) in tags {
+        let valid_key = !key.is_empty()
+            && key.len() <= MAX_KEY_LEN
+            && key.chars().all(|c| {
+                c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | ':')
+            });
+        if !valid_key {
+            return Err(format!(
+                "Invalid tag key '{}': use up to {} letters, digits, '.', \
+                 '_', '-' and ':'",
+                key, MAX_KEY_LEN
+            ));
+        }
+        if value.len() > MAX_VALUE_LEN {
+            return Err(format!(
+                "Value of tag '{}' is longer than {} bytes",
+                key, MAX_VALUE_LEN
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn internal_error<T: serde::Serialize>(
+    action: &str,
+    e: anyhow::Error,
+) -> SftpApiResponse<T> {
+    error!("Failed to {}: {}", action, e);
+    SftpApiResponse::error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Failed to {}", action),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_tags() {
+        let tag = |k: &str, v: &str| -> BTreeMap<String, String> {
+            [(k.to_string(), v.to_string())].into()
+        };
+        assert!(validate(&tag("customer", "Acme Corp")).is_ok());
+        assert!(validate(&tag("batch-id", "")).is_ok());
+        assert!(validate(&tag("", "x")).is_err());
+        assert!(validate(&tag("with space", "x")).is_err());
+        assert!(validate(&tag("k", &"x".repeat(MAX_VALUE_LEN + 1))).is_err());
+
+        let many = (0..=MAX_TAGS).map(|i| (i.to_string(), String::new()));
+        assert!(validate(&many.collect()).is_err());
+    }
+}
@@ -0,0 +1,624 @@
+use crate::config::settings::Settings;
+use crate::events::Event;
+use crate::models::files::{
+    DirectoryEntry, DirectoryListing, FileDeleteResponse, FileUploadResponse,
+    RestoreRequest, TrashListResponse,
+};
+use crate::responses::sftp::SftpApiResponse;
+use crate::services::upload_limits::UploadAborted;
+use crate::sftp::ServerContext;
+use crate::sftp::checksum::file_checksums;
+use crate::sftp::hooks::CompletedUpload;
+use crate::sftp::scratch::{SCRATCH_DIR, ScratchArea};
+use crate::sftp::sparse;
+use crate::sftp::trash::{TRASH_DIR, Trash, TrashEntry};
+use axum::body::Body;
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use rand::RngExt;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio_util::io::ReaderStream;
+use tracing::{error, info, warn};
+
+// How often items past their retention are purged from the trash
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// User recorded for files changed through the API
+const API_USER: &str = "api";
+
+// File operations on an SFTP root for the REST API, either the default
+// one or a tenant's, and the trash purging for every root. Transfers are reported like SFTP ones.
+pub struct FileService {
+    root_dir: PathBuf,
+    settings: watch::Receiver<Settings>,
+    context: ServerContext,
+}
+
+impl FileService {
+    pub fn new(
+        root_dir: String,
+        settings: watch::Receiver<Settings>,
+        context: ServerContext,
+    ) -> Self {
+        Self { root_dir: PathBuf::from(root_dir), settings, context }
+    }
+
+    // Purge the trash of the default root, of every instance and tenant. Roots and
+    // the retention period are re-read before every run.
+    pub fn start_purging(&self) -> JoinHandle<()> {
+        let settings = self.settings.clone();
+        tokio::spawn(async move {
+            loop {
+                let (roots, retention_days) = {
+                    let settings = settings.borrow();
+                    let mut roots = vec![settings.sftp.root_dir.clone()];
+                    roots.extend(
+                        settings.instances.iter().map(|i| i.root_dir.clone()),
+                    );
+                    roots.extend(
+                        settings.tenants.iter().map(|t| t.root_dir.clone()),
+                    );
+                    (roots, settings.trash.retention_days)
+                };
+                let cutoff =
+                    Utc::now() - chrono::Duration::days(retention_days as i64);
+                for root in roots {
+                    match Trash::new(&root).purge(cutoff).await {
+                        Ok(purged) if !purged.is_empty() => info!(
+                            "Purged {} item(s) from the trash of {}",
+                            purged.len(),
+                            root
+                        ),
+                        Ok(_) => {}
+                        Err(e) => {
+                            error!("Failed to purge trash of {}: {}", root, e)
+                        }
+                    }
+                }
+                tokio::time::sleep(PURGE_INTERVAL).await;
+            }
+        })
+    }
+
+    // Delete a file or empty directory, into the trash when it is enabled
+    pub async fn delete(
+        &self,
+        path: &str,
+    ) -> SftpApiResponse<FileDeleteResponse> {
+        let Some(local_path) = self.local_path(path) else {
+            return SftpApiResponse::error(
+                StatusCode::BAD_REQUEST,
+                "Invalid path",
+            );
+        };
+        let path = format!("/{}", path.trim_start_matches('/'));
+        let metadata = match fs::symlink_metadata(&local_path).await {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return SftpApiResponse::error(
+                    StatusCode::NOT_FOUND,
+                    "No such file or directory",
+                );
+            }
+            Err(e) => return internal_error("Failed to read file", e),
+        };
+
+        if metadata.is_dir() {
+            match is_empty_dir(&local_path).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    return SftpApiResponse::error(
+                        StatusCode::CONFLICT,
+                        "Directory not empty",
+                    );
+                }
+                Err(e) => return internal_error("Failed to read directory", e),
+            }
+        }
+
+        let result = if self.settings.borrow().trash.enabled {
+            Trash::new(&self.root_dir)
+                .put(&local_path, &path, API_USER)
+                .await
+                .map(|entry| Some(entry.id))
+        } else if metadata.is_dir() {
+            fs::remove_dir(&local_path).await.map(|_| None)
+        } else {
+            fs::remove_file(&local_path).await.map(|_| None)
+        };
+        match result {
+            Ok(trash_id) => {
+                info!("Deleted {} through the API", path);
+                SftpApiResponse::success(FileDeleteResponse { path, trash_id })
+            }
+            Err(e) => internal_error("Failed to delete file", e),
+        }
+    }
+
+    pub async fn list(&self, path: &str) -> SftpApiResponse<DirectoryListing> {
+        let Some(local_path) = self.resolve(path).await else {
+            return SftpApiResponse::error(
+                StatusCode::BAD_REQUEST,
+                "Invalid path",
+            );
+        };
+        let path = format!("/{}", path.trim_matches('/'));
+        let mut dir = match fs::read_dir(&local_path).await {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return SftpApiResponse::error(
+                    StatusCode::NOT_FOUND,
+                    "No such directory",
+                );
+            }
+            Err(e) => return internal_error("Failed to read directory", e),
+        };
+
+        let mut entries = Vec::new();
+        loop {
+            let entry = match dir.next_entry().await {
+                Ok(Some(entry)) => entry,
+                Ok(None) => break,
+                Err(e) => return internal_error("Failed to read directory", e),
+            };
+            let name = entry.file_name().to_string_lossy().to_string();
+            if path == "/" && (name == TRASH_DIR || name == SCRATCH_DIR)
+                || self.context.filenames.hides(&name)
+            {
+                continue;
+            }
+            // Skip entries removed while listing and dangling links
+            let Ok(metadata) = fs::metadata(entry.path()).await else {
+                continue;
+            };
+            let modified =
+                metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            entries.push(DirectoryEntry {
+                name,
+                is_dir: metadata.is_dir(),
+                bytes: if metadata.is_dir() { 0 } else { metadata.len() },
+                modified: DateTime::<Utc>::from(modified),
+            });
+        }
+        entries.sort_by(|a, b| {
+            b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name))
+        });
+
+        SftpApiResponse::success(DirectoryListing { path, entries })
+    }
+
+    // Stream a file to the client. The download is reported when it
+    // starts, as the end of the response body is not observed.
+    pub async fn download(&self, path: &str) -> Response {
+        let Some(local_path) = self.resolve(path).await else {
+            return SftpApiResponse::<()>::error(
+                StatusCode::BAD_REQUEST,
+                "Invalid path",
+            )
+            .into_response();
+        };
+        let file = match fs::File::open(&local_path).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return SftpApiResponse::<()>::error(
+                    StatusCode::NOT_FOUND,
+                    "No such file",
+                )
+                .into_response();
+            }
+            Err(e) => {
+                return internal_error::<()>("Failed to open file", e)
+                    .into_response();
+            }
+        };
+        let bytes = match file.metadata().await {
+            Ok(metadata) if metadata.is_file() => metadata.len(),
+            Ok(_) => {
+                return SftpApiResponse::<()>::error(
+                    StatusCode::BAD_REQUEST,
+                    "Not a regular file",
+                )
+                .into_response();
+            }
+            Err(e) => {
+                return internal_error::<()>("Failed to read file", e)
+                    .into_response();
+            }
+        };
+
+        let path = format!("/{}", path.trim_start_matches('/'));
+        let name = path.rsplit('/').next().unwrap_or_default();
+        self.report_download(&path, bytes);
+
+        (
+            [
+                (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+                (header::CONTENT_LENGTH, bytes.to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!(
+                        "attachment; filename=\"{}\"",
+                        name.replace(['"', '\\'], "_")
+                    ),
+                ),
+            ],
+            Body::from_stream(ReaderStream::new(file)),
+        )
+            .into_response()
+    }
+
+    // Report a download through the API like an SFTP one: counted and
+    // published
+    pub fn report_download(&self, path: &str, bytes: u64) {
+        info!("Downloading {} ({} bytes) through the API", path, bytes);
+        self.context.stats.record_download();
+        self.context.stats.record_bytes_out(bytes);
+        self.context.events.publish(Event::FileDownloaded {
+            username: API_USER.to_string(),
+            peer: None,
+            path: path.to_string(),
+            bytes,
+            duration_ms: 0,
+        });
+    }
+
+    // Store the request body as a file. It is written next to its target
+    // under a temporary name and only renamed into place once complete, so
+    // clients and hooks never see a partial upload.
+    pub async fn upload(
+        &self,
+        path: &str,
+        overwrite: bool,
+        body: Body,
+    ) -> SftpApiResponse<FileUploadResponse> {
+        let (path, local_path) = match self.upload_target(path, overwrite).await
+        {
+            Ok(target) => target,
+            Err((status, message)) => {
+                return SftpApiResponse::error(status, message);
+            }
+        };
+
+        let started = Instant::now();
+        let temp_path = local_path.with_file_name(format!(
+            ".{}.upload-{:08x}",
+            local_path.file_name().unwrap_or_default().to_string_lossy(),
+            rand::rng().random::<u32>()
+        ));
+        let sparse = self.context.sparse_files;
+        let bytes = match write_body(&temp_path, body, sparse).await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                let _ = fs::remove_file(&temp_path).await;
+                warn!("Upload of {} through the API failed: {}", path, e);
+                if let Some(aborted) = UploadAborted::find(&e) {
+                    return SftpApiResponse::error(
+                        aborted.status,
+                        aborted.message.clone(),
+                    );
+                }
+                return SftpApiResponse::error(
+                    StatusCode::BAD_REQUEST,
+                    format!("Upload failed: {}", e),
+                );
+            }
+        };
+        if let Err(e) = fs::rename(&temp_path, &local_path).await {
+            let _ = fs::remove_file(&temp_path).await;
+            return internal_error("Failed to store file", e);
+        }
+
+        SftpApiResponse::success(
+            self.complete_upload(path, local_path, bytes, started).await,
+        )
+    }
+
+    // Client path and location on disk of a file to be uploaded, named
+    // under the filename policy. The parent directory must exist below the
+    // root and, unless overwriting, the file must not.
+    pub async fn upload_target(
+        &self,
+        path: &str,
+        overwrite: bool,
+    ) -> Result<(String, PathBuf), (StatusCode, String)> {
+        let invalid = || (StatusCode::BAD_REQUEST, "Invalid path".to_string());
+        let path = self.new_name(path)?;
+        if !self.context.has_space(&self.root_dir, 0).await {
+            warn!("Refusing upload of {}: no space left", path);
+            return Err((
+                StatusCode::INSUFFICIENT_STORAGE,
+                "Not enough storage space".to_string(),
+            ));
+        }
+        let local_path = self
+            .local_path(&path)
+            .filter(|p| p.file_name().is_some())
+            .ok_or_else(invalid)?;
+        let parent = local_path.parent().ok_or_else(invalid)?;
+        if self.resolve_local(parent).await.is_none() {
+            return Err((
+                StatusCode::NOT_FOUND,
+                "No such directory".to_string(),
+            ));
+        }
+        let path = format!("/{}", path.trim_start_matches('/'));
+        if !overwrite && fs::symlink_metadata(&local_path).await.is_ok() {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("{} already exists", path),
+            ));
+        }
+        Ok((path, local_path))
+    }
+
+    // Whether blocks of zeros in uploads are left as holes
+    pub fn sparse_files(&self) -> bool {
+        self.context.sparse_files
+    }
+
+    // Name to store for a file or directory created by a client
+    fn new_name(&self, path: &str) -> Result<String, (StatusCode, String)> {
+        self.context.filenames.apply(path).map_err(|e| {
+            (StatusCode::BAD_REQUEST, format!("Invalid name: {}", e))
+        })
+    }
+
+    // Create a directory and any missing parents. The nearest existing
+    // ancestor must be inside the root, so links cannot lead out of it.
+    pub async fn create_dir_all(
+        &self,
+        path: &str,
+    ) -> Result<PathBuf, (StatusCode, String)> {
+        let path = self.new_name(path)?;
+        let local_path = self.local_path(&path).ok_or_else(|| {
+            (StatusCode::BAD_REQUEST, "Invalid path".to_string())
+        })?;
+        let mut existing = local_path.as_path();
+        while existing != self.root_dir
+            && fs::symlink_metadata(existing).await.is_err()
+        {
+            existing = existing.parent().unwrap_or(&self.root_dir);
+        }
+        if self.resolve_local(existing).await.is_none() {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "Path leads out of the root".to_string(),
+            ));
+        }
+        match fs::create_dir_all(&local_path).await {
+            Ok(()) => Ok(local_path),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::AlreadyExists | io::ErrorKind::NotADirectory
+                ) =>
+            {
+                Err((
+                    StatusCode::CONFLICT,
+                    format!("{} is not a directory", path),
+                ))
+            }
+            Err(e) => {
+                error!("Failed to create directory {}: {}", path, e);
+                Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to create directory: {}", e),
+                ))
+            }
+        }
+    }
+
+    // Report a file stored through the API like an SFTP upload: counted,
+    // hashed, passed through the upload hooks and published
+    pub async fn complete_upload(
+        &self,
+        path: String,
+        local_path: PathBuf,
+        bytes: u64,
+        started: Instant,
+    ) -> FileUploadResponse {
+        let checksums = if self.context.checksum_algorithms.is_empty() {
+            Default::default()
+        } else {
+            file_checksums(&self.context.checksum_algorithms, &local_path)
+                .await
+                .unwrap_or_else(|e| {
+                    error!("Failed to compute checksums of {}: {}", path, e);
+                    Default::default()
+                })
+        };
+        info!("Uploaded {} ({} bytes) through the API", path, bytes);
+        self.context.stats.record_upload();
+        self.context.stats.record_bytes_in(bytes);
+        // Written in one go, so complete right away
+        self.context.completion.complete(CompletedUpload {
+            username: API_USER.to_string(),
+            peer: None,
+            path: path.clone(),
+            local_path,
+            root_dir: self.root_dir.clone(),
+            bytes,
+            duration_ms: started.elapsed().as_millis() as u64,
+            checksums: checksums.clone(),
+        });
+
+        FileUploadResponse { path, bytes, checksums }
+    }
+
+    pub async fn list_trash(&self) -> SftpApiResponse<TrashListResponse> {
+        match Trash::new(&self.root_dir).list().await {
+            Ok(entries) => {
+                SftpApiResponse::success(TrashListResponse { entries })
+            }
+            Err(e) => internal_error("Failed to read the trash", e),
+        }
+    }
+
+    pub async fn restore(
+        &self,
+        request: RestoreRequest,
+    ) -> SftpApiResponse<TrashEntry> {
+        let trash = Trash::new(&self.root_dir);
+        let id = match (request.id, request.path) {
+            (Some(id), _) => id,
+            (None, Some(path)) => match trash.list().await {
+                Ok(entries) => {
+                    match entries.into_iter().find(|e| e.path == path) {
+                        Some(entry) => entry.id,
+                        None => {
+                            return SftpApiResponse::error(
+                                StatusCode::NOT_FOUND,
+                                "No deleted item has this path",
+                            );
+                        }
+                    }
+                }
+                Err(e) => return internal_error("Failed to read the trash", e),
+            },
+            (None, None) => {
+                return SftpApiResponse::error(
+                    StatusCode::BAD_REQUEST,
+                    "Either id or path is required",
+                );
+            }
+        };
+
+        match trash.restore(&id).await {
+            Ok(entry) => {
+                info!("Restored {} from the trash", entry.path);
+                self.context.events.publish(Event::FileRestored {
+                    path: entry.path.clone(),
+                    id: entry.id.clone(),
+                });
+                SftpApiResponse::success(entry)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                SftpApiResponse::error(
+                    StatusCode::NOT_FOUND,
+                    "No such item in the trash",
+                )
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                SftpApiResponse::error(StatusCode::CONFLICT, e.to_string())
+            }
+            Err(e) if e.kind() == io::ErrorKind::InvalidInput => {
+                SftpApiResponse::error(StatusCode::BAD_REQUEST, e.to_string())
+            }
+            Err(e) => internal_error("Failed to restore item", e),
+        }
+    }
+
+    // Permanently remove files, bypassing the trash, along with trashed
+    // items from those paths or deleted by `username`. Returns how many
+    // files and trashed items were removed.
+    pub async fn erase(
+        &self,
+        paths: &[String],
+        username: &str,
+    ) -> io::Result<usize> {
+        let mut erased = 0;
+        for path in paths {
+            let Some(local_path) = self.local_path(path) else {
+                continue;
+            };
+            // The file itself may be a link; only its directory is resolved
+            let inside = match local_path.parent() {
+                Some(parent) => self.resolve_local(parent).await.is_some(),
+                None => false,
+            };
+            if !inside {
+                continue;
+            }
+            match fs::symlink_metadata(&local_path).await {
+                Ok(metadata) if !metadata.is_dir() => {
+                    fs::remove_file(&local_path).await?;
+                    info!("Erased {}", path);
+                    erased += 1;
+                }
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let purged = Trash::new(&self.root_dir)
+            .purge_where(|entry| {
+                entry.deleted_by == username || paths.contains(&entry.path)
+            })
+            .await?;
+        Ok(erased + purged.len())
+    }
+
+    // Location of an existing client path on disk, including the root,
+    // unless a symbolic link leads out of the root
+    pub async fn resolve(&self, path: &str) -> Option<PathBuf> {
+        let local_path = if path.trim_matches('/').is_empty() {
+            self.root_dir.clone()
+        } else {
+            self.local_path(path)?
+        };
+        self.resolve_local(&local_path).await
+    }
+
+    async fn resolve_local(&self, local_path: &Path) -> Option<PathBuf> {
+        let root = fs::canonicalize(&self.root_dir).await.ok()?;
+        let canonical = fs::canonicalize(local_path).await.ok()?;
+        canonical.starts_with(&root).then_some(canonical)
+    }
+
+    // Location of a client path on disk, or `None` for the root itself,
+    // paths leaving it and paths inside the trash or the scratch areas
+    fn local_path(&self, path: &str) -> Option<PathBuf> {
+        let relative = Path::new(path.trim_start_matches('/'));
+        let normal = relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+        if !normal
+            || relative.as_os_str().is_empty()
+            || Trash::contains(relative)
+            || ScratchArea::contains(relative)
+        {
+            return None;
+        }
+        Some(self.root_dir.join(relative))
+    }
+}
+
+async fn write_body(
+    path: &Path,
+    body: Body,
+    sparse: bool,
+) -> anyhow::Result<u64> {
+    let mut file = fs::File::create_new(path).await?;
+    let mut stream = body.into_data_stream();
+    let mut bytes = 0;
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        sparse::write_at(&mut file, bytes, &chunk, sparse).await?;
+        bytes += chunk.len() as u64;
+    }
+    file.flush().await?;
+    Ok(bytes)
+}
+
+async fn is_empty_dir(path: &Path) -> io::Result<bool> {
+    Ok(fs::read_dir(path).await?.next_entry().await?.is_none())
+}
+
+fn internal_error<T: serde::Serialize>(
+    message: &str,
+    e: io::Error,
+) -> SftpApiResponse<T> {
+    error!("{}: {}", message, e);
+    SftpApiResponse::error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("{}: {}", message, e),
+    )
+}
@@ -0,0 +1,185 @@
+use crate::events::{Event, EventBus};
+use crate::sftp::ListingCache;
+use crate::sftp::scratch::ScratchArea;
+use crate::sftp::trash::Trash;
+use notify::event::{ModifyKind, RenameMode};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+// What happened to a path, after merging the raw notifications
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Change {
+    Created,
+    Modified,
+    Removed,
+}
+
+// Watches the SFTP root, including changes made by other processes, and
+// publishes one event per file once it has been quiet for the debounce
+// period. Uploads in progress therefore report a single change.
+pub struct FsWatcher {
+    root_dir: PathBuf,
+    debounce: Duration,
+    events: EventBus,
+    listing_cache: Option<ListingCache>,
+}
+
+impl FsWatcher {
+    pub fn new(root_dir: String, debounce: Duration, events: EventBus) -> Self {
+        Self {
+            root_dir: PathBuf::from(root_dir),
+            debounce,
+            events,
+            listing_cache: None,
+        }
+    }
+
+    // Clear the cached SFTP listings of changed paths as soon as they are
+    // seen, without waiting for the debounce period
+    pub fn with_listing_cache(mut self, cache: Option<ListingCache>) -> Self {
+        self.listing_cache = cache;
+        self
+    }
+
+    pub fn start(mut self) -> notify::Result<JoinHandle<()>> {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        // Notifications carry absolute paths
+        self.root_dir = std::fs::canonicalize(&self.root_dir)?;
+
+        let listing_cache = self.listing_cache.clone();
+        let mut watcher = notify::recommended_watcher(
+            move |result: notify::Result<notify::Event>| match result {
+                Ok(event) => {
+                    for change in changes(&event) {
+                        if let Some(cache) = &listing_cache {
+                            cache.invalidate_tree(&change.0);
+                        }
+                        let _ = tx.send(change);
+                    }
+                }
+                Err(e) => warn!("File watcher error: {}", e),
+            },
+        )?;
+        watcher.watch(&self.root_dir, RecursiveMode::Recursive)?;
+        info!("Watching {} for file changes", self.root_dir.display());
+
+        Ok(tokio::spawn(async move {
+            // The watcher stops when dropped, keep it alive with the task
+            let _watcher = watcher;
+            let mut pending: HashMap<PathBuf, (Change, Instant)> =
+                HashMap::new();
+            let mut tick = tokio::time::interval(self.debounce / 2);
+
+            loop {
+                tokio::select! {
+                    change = rx.recv() => {
+                        let Some((path, change)) = change else { break };
+                        let previous = pending.remove(&path).map(|(c, _)| c);
+                        if let Some(change) = merge(previous, change) {
+                            pending.insert(path, (change, Instant::now()));
+                        }
+                    }
+                    _ = tick.tick() => {
+                        let settled: Vec<PathBuf> = pending
+                            .iter()
+                            .filter(|(_, (_, seen))| {
+                                seen.elapsed() >= self.debounce
+                            })
+                            .map(|(path, _)| path.clone())
+                            .collect();
+                        for path in settled {
+                            if let Some((change, _)) = pending.remove(&path) {
+                                self.publish(&path, change);
+                            }
+                        }
+                    }
+                }
+            }
+        }))
+    }
+
+    fn publish(&self, path: &Path, change: Change) {
+        // Directories are reported through the files inside them
+        if change != Change::Removed && !path.is_file() {
+            return;
+        }
+        let relative = path.strip_prefix(&self.root_dir).unwrap_or(path);
+        // Moves into the trash are reported as the original path's deletion
+        if Trash::contains(relative) || ScratchArea::contains(relative) {
+            return;
+        }
+        let path = format!("/{}", relative.to_string_lossy());
+        self.events.publish(match change {
+            Change::Created => Event::FileCreated { path },
+            Change::Modified => Event::FileModified { path },
+            Change::Removed => Event::FileDeleted { path },
+        });
+    }
+}
+
+// Paths affected by a raw notification. Renames count as the old path
+// being removed and the new one created.
+fn changes(event: &notify::Event) -> Vec<(PathBuf, Change)> {
+    let change = match event.kind {
+        EventKind::Create(_) => Change::Created,
+        EventKind::Remove(_) => Change::Removed,
+        EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+            Change::Removed
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::To)) => Change::Created,
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both))
+            if event.paths.len() == 2 =>
+        {
+            return vec![
+                (event.paths[0].clone(), Change::Removed),
+                (event.paths[1].clone(), Change::Created),
+            ];
+        }
+        // Permission and timestamp changes are not reported
+        EventKind::Modify(ModifyKind::Metadata(_)) => return Vec::new(),
+        EventKind::Modify(_) => Change::Modified,
+        _ => return Vec::new(),
+    };
+    event.paths.iter().map(|p| (p.clone(), change)).collect()
+}
+
+// Combine a change with the one still pending for the same path; `None`
+// when they cancel out
+fn merge(previous: Option<Change>, next: Change) -> Option<Change> {
+    match (previous, next) {
+        (None, next) => Some(next),
+        (Some(Change::Created), Change::Removed) => None,
+        (Some(Change::Created), _) => Some(Change::Created),
+        (Some(Change::Modified), Change::Removed) => Some(Change::Removed),
+        (Some(Change::Modified), _) => Some(Change::Modified),
+        (Some(Change::Removed), Change::Removed) => Some(Change::Removed),
+        // Replaced by a new file
+        (Some(Change::Removed), _) => Some(Change::Modified),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_changes() {
+        // An upload is created and then written many times
+        let mut change = merge(None, Change::Created);
+        change = merge(change, Change::Modified);
+        assert_eq!(merge(change, Change::Modified), Some(Change::Created));
+
+        // A temporary file that came and went is not reported
+        assert_eq!(merge(Some(Change::Created), Change::Removed), None);
+        assert_eq!(
+            merge(Some(Change::Removed), Change::Created),
+            Some(Change::Modified)
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use crate::config::settings::Settings;
+use crate::events::{Event, EventBus};
+use crate::models::files::{IntegrityMismatch, IntegrityReport};
+use crate::responses::sftp::SftpApiResponse;
+use crate::schedule::CronExpr;
+use crate::sftp::checksum::{ChecksumAlgorithm, file_checksums};
+use crate::store::{Repository, TransferRecord};
+use axum::http::StatusCode;
+use chrono::{DateTime, Timelike, Utc};
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::fs;
+use tokio::sync::{RwLock, watch};
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+// Algorithms tried in order when a file has several recorded digests
+const PREFERRED: [ChecksumAlgorithm; 4] = [
+    ChecksumAlgorithm::Sha256,
+    ChecksumAlgorithm::Sha512,
+    ChecksumAlgorithm::Sha1,
+    ChecksumAlgorithm::Md5,
+];
+
+// What re-hashing one file found
+enum Outcome {
+    Match,
+    Mismatch(IntegrityMismatch),
+    Missing,
+    // Changed since its last upload, e.g. by another process
+    Modified,
+}
+
+// Periodically re-hashes uploaded files and compares them with the
+// checksums in the transfer log, to detect bit rot on the storage
+pub struct IntegrityService {
+    root_dir: PathBuf,
+    repository: Option<Arc<dyn Repository>>,
+    settings: watch::Receiver<Settings>,
+    events: EventBus,
+    report: RwLock<Option<IntegrityReport>>,
+}
+
+impl IntegrityService {
+    pub fn new(
+        root_dir: String,
+        repository: Option<Arc<dyn Repository>>,
+        settings: watch::Receiver<Settings>,
+        events: EventBus,
+    ) -> Self {
+        Self {
+            root_dir: PathBuf::from(root_dir),
+            repository,
+            settings,
+            events,
+            report: RwLock::new(None),
+        }
+    }
+
+    // Check the schedule at the start of every minute. The schedule is
+    // re-read each time so changes apply on reload.
+    pub fn start(self: &Arc<Self>) -> JoinHandle<()> {
+        let service = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let now = Utc::now();
+                let wait = 60 - now.second() as u64;
+                tokio::time::sleep(Duration::from_secs(wait)).await;
+
+                let integrity = service.settings.borrow().integrity.clone();
+                if !integrity.enabled {
+                    continue;
+                }
+                match CronExpr::parse(&integrity.schedule) {
+                    Ok(schedule) if schedule.matches(Utc::now()) => {
+                        service.run().await
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!("Invalid integrity schedule: {}", e),
+                }
+            }
+        })
+    }
+
+    async fn run(&self) {
+        let Some(repository) = &self.repository else {
+            warn!("Integrity check skipped, the database is disabled");
+            return;
+        };
+        let uploads = match repository.latest_uploads_with_checksums().await {
+            Ok(uploads) => uploads,
+            Err(e) => {
+                error!("Failed to read transfer log: {}", e);
+                return;
+            }
+        };
+
+        info!("Verifying the integrity of {} file(s)", uploads.len());
+        let mut report = IntegrityReport {
+            started_at: Utc::now(),
+            finished_at: None,
+            checked: 0,
+            missing: 0,
+            modified: 0,
+            errors: 0,
+            mismatches: Vec::new(),
+        };
+        *self.report.write().await = Some(report.clone());
+
+        for upload in uploads {
+            match self.verify(&upload).await {
+                Ok(Outcome::Match) => report.checked += 1,
+                Ok(Outcome::Mismatch(mismatch)) => {
+                    report.checked += 1;
+                    warn!(
+                        "{} does not match its {} checksum from {}",
+                        mismatch.path, mismatch.algorithm, mismatch.uploaded_at
+                    );
+                    self.events.publish(Event::IntegrityMismatch {
+                        path: mismatch.path.clone(),
+                        algorithm: mismatch.algorithm.clone(),
+                        expected: mismatch.expected.clone(),
+                        actual: mismatch.actual.clone(),
+                    });
+                    report.mismatches.push(mismatch);
+                }
+                Ok(Outcome::Missing) => report.missing += 1,
+                Ok(Outcome::Modified) => report.modified += 1,
+                Err(e) => {
+                    warn!("Failed to verify {}: {}", upload.path, e);
+                    report.errors += 1;
+                }
+            }
+        }
+
+        report.finished_at = Some(Utc::now());
+        info!(
+            "Integrity check finished: {} checked, {} mismatched, {} missing",
+            report.checked,
+            report.mismatches.len(),
+            report.missing
+        );
+        self.events.publish(Event::IntegrityCheckCompleted {
+            checked: report.checked,
+            mismatches: report.mismatches.len() as u64,
+            missing: report.missing,
+        });
+        *self.report.write().await = Some(report);
+    }
+
+    async fn verify(&self, upload: &TransferRecord) -> io::Result<Outcome> {
+        let Some(algorithm) = PREFERRED
+            .into_iter()
+            .find(|a| upload.checksums.contains_key(a.name()))
+        else {
+            return Ok(Outcome::Match);
+        };
+
+        let local_path =
+            self.root_dir.join(upload.path.trim_start_matches('/'));
+        let metadata = match fs::metadata(&local_path).await {
+            Ok(metadata) if metadata.is_file() => metadata,
+            Ok(_) => return Ok(Outcome::Missing),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(Outcome::Missing);
+            }
+            Err(e) => return Err(e),
+        };
+        // Bit rot leaves the modification time alone
+        let modified: DateTime<Utc> = metadata.modified()?.into();
+        if modified > upload.timestamp {
+            return Ok(Outcome::Modified);
+        }
+
+        let expected = &upload.checksums[algorithm.name()];
+        let actual = file_checksums(&[algorithm], &local_path)
+            .await?
+            .remove(algorithm.name())
+            .unwrap_or_default();
+        if actual.eq_ignore_ascii_case(expected) {
+            return Ok(Outcome::Match);
+        }
+        Ok(Outcome::Mismatch(IntegrityMismatch {
+            path: upload.path.clone(),
+            algorithm: algorithm.name().to_string(),
+            expected: expected.clone(),
+            actual,
+            uploaded_at: upload.timestamp,
+            uploaded_by: upload.username.clone(),
+        }))
+    }
+
+    pub async fn get_report(&self) -> SftpApiResponse<IntegrityReport> {
+        match self.report.read().await.as_ref() {
+            Some(report) => SftpApiResponse::success(report.clone()),
+            None => SftpApiResponse::error(
+                StatusCode::NOT_FOUND,
+                "No integrity check has run yet",
+            ),
+        }
+    }
+}
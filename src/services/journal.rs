@@ -0,0 +1,386 @@
+use crate::events::{EventBus, EventEnvelope};
+use crate::responses::sftp::SftpApiResponse;
+use crate::store::ERASED_USER;
+use axum::http::StatusCode;
+use serde::Serialize;
+use serde_json::Value;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+const JOURNAL_FILE: &str = "journal.log";
+
+// Most entries returned by a single read
+const MAX_READ: usize = 1000;
+
+// A journal line: the event plus its position in the journal
+#[derive(Debug, Serialize)]
+struct JournalEntry<'a> {
+    seq: u64,
+    #[serde(flatten)]
+    envelope: &'a EventEnvelope,
+}
+
+// Appends every published event to a JSON lines file, rotating it once it
+// grows past `max_file_bytes`. Sequence numbers continue across restarts so
+// consumers can resume reading where they stopped.
+pub struct EventJournal {
+    dir: PathBuf,
+    max_file_bytes: u64,
+    max_files: usize,
+    file: File,
+    size: u64,
+    next_seq: u64,
+}
+
+impl EventJournal {
+    pub fn open(
+        dir: impl Into<PathBuf>,
+        max_file_bytes: u64,
+        max_files: usize,
+    ) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+
+        let next_seq = last_seq(&dir).map_or(1, |seq| seq + 1);
+        let path = dir.join(JOURNAL_FILE);
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+
+        info!(
+            "Event journal at {}, next sequence {}",
+            path.display(),
+            next_seq
+        );
+        Ok(Self { dir, max_file_bytes, max_files, file, size, next_seq })
+    }
+
+    // Subscribe to the bus and append events until the bus is closed. The
+    // journal stays shared so erasures can redact it in between.
+    pub fn start(
+        journal: Arc<Mutex<Self>>,
+        events: &EventBus,
+    ) -> JoinHandle<()> {
+        let mut receiver = events.subscribe();
+
+        tokio::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(envelope) => {
+                        let appended = match journal.lock() {
+                            Ok(mut journal) => journal.append(&envelope),
+                            Err(_) => break,
+                        };
+                        if let Err(e) = appended {
+                            error!("Failed to write event journal: {}", e);
+                        }
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!(
+                            "Event journal lagged, {} events not written",
+                            skipped
+                        );
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+
+            info!("Event journal stopped");
+        })
+    }
+
+    fn append(&mut self, envelope: &EventEnvelope) -> io::Result<()> {
+        let entry = JournalEntry { seq: self.next_seq, envelope };
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+
+        if self.size > 0 && self.size + line.len() as u64 > self.max_file_bytes
+        {
+            self.rotate()?;
+        }
+
+        // One write per line so a crash never leaves half an entry behind
+        // another one
+        self.file.write_all(&line)?;
+        self.file.flush()?;
+        self.size += line.len() as u64;
+        self.next_seq += 1;
+        Ok(())
+    }
+
+    // journal.log becomes journal.log.1, journal.log.1 becomes .2 and so on
+    fn rotate(&mut self) -> io::Result<()> {
+        let _ = fs::remove_file(rotated_path(&self.dir, self.max_files));
+        for n in (1..self.max_files).rev() {
+            let from = rotated_path(&self.dir, n);
+            if from.exists() {
+                fs::rename(&from, rotated_path(&self.dir, n + 1))?;
+            }
+        }
+
+        let current = self.dir.join(JOURNAL_FILE);
+        if self.max_files > 0 {
+            fs::rename(&current, rotated_path(&self.dir, 1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&current)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    // Replace `username` wherever it appears in an entry by the erased
+    // placeholder, rewriting the files that mention it. Returns the number
+    // of entries changed.
+    pub fn redact_user(&mut self, username: &str) -> io::Result<u64> {
+        let mut redacted = 0;
+        for path in journal_files(&self.dir) {
+            let Ok(file) = File::open(&path) else {
+                continue;
+            };
+            let mut lines = Vec::new();
+            let mut changed = 0;
+            for line in BufReader::new(file).lines() {
+                let line = line?;
+                let Ok(mut entry) = serde_json::from_str::<Value>(&line) else {
+                    lines.push(line);
+                    continue;
+                };
+                if redact(&mut entry, username) {
+                    changed += 1;
+                    lines.push(serde_json::to_string(&entry)?);
+                } else {
+                    lines.push(line);
+                }
+            }
+            if changed == 0 {
+                continue;
+            }
+
+            // Written aside and renamed over, so the file is never half
+            // redacted
+            let temporary = path.with_extension("redacting");
+            let mut contents = lines.join("\n");
+            contents.push('\n');
+            fs::write(&temporary, &contents)?;
+            fs::rename(&temporary, &path)?;
+            if path == self.dir.join(JOURNAL_FILE) {
+                self.file = OpenOptions::new().append(true).open(&path)?;
+                self.size = contents.len() as u64;
+            }
+            redacted += changed;
+        }
+        Ok(redacted)
+    }
+}
+
+// Replace every string equal to `username` in `value`; whether any was
+// replaced
+fn redact(value: &mut Value, username: &str) -> bool {
+    let children: Vec<&mut Value> = match value {
+        Value::String(s) if s == username => {
+            *s = ERASED_USER.to_string();
+            return true;
+        }
+        Value::Array(values) => values.iter_mut().collect(),
+        Value::Object(fields) => fields.values_mut().collect(),
+        _ => return false,
+    };
+    // Every child is visited, not just up to the first match
+    let mut found = false;
+    for child in children {
+        found |= redact(child, username);
+    }
+    found
+}
+
+fn rotated_path(dir: &Path, n: usize) -> PathBuf {
+    dir.join(format!("{}.{}", JOURNAL_FILE, n))
+}
+
+// Journal files from oldest to newest
+fn journal_files(dir: &Path) -> Vec<PathBuf> {
+    let mut rotated: Vec<(usize, PathBuf)> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let n = name.strip_prefix(JOURNAL_FILE)?.strip_prefix('.')?;
+            Some((n.parse().ok()?, entry.path()))
+        })
+        .collect();
+    rotated.sort_by_key(|(n, _)| std::cmp::Reverse(*n));
+
+    let mut files: Vec<PathBuf> = rotated.into_iter().map(|(_, p)| p).collect();
+    files.push(dir.join(JOURNAL_FILE));
+    files
+}
+
+fn entries(path: &Path) -> impl Iterator<Item = Value> {
+    File::open(path)
+        .ok()
+        .map(|f| BufReader::new(f).lines())
+        .into_iter()
+        .flatten()
+        .map_while(Result::ok)
+        // A torn last line after a crash is skipped
+        .filter_map(|line| serde_json::from_str(&line).ok())
+}
+
+fn seq_of(entry: &Value) -> Option<u64> {
+    entry.get("seq").and_then(Value::as_u64)
+}
+
+fn last_seq(dir: &Path) -> Option<u64> {
+    journal_files(dir)
+        .iter()
+        .rev()
+        .find_map(|path| entries(path).filter_map(|e| seq_of(&e)).last())
+}
+
+// Entries with a sequence number greater than `since`, oldest first
+pub fn read_since(dir: &Path, since: u64, limit: usize) -> Vec<Value> {
+    journal_files(dir)
+        .iter()
+        .flat_map(|path| entries(path))
+        .filter(|entry| seq_of(entry).is_some_and(|seq| seq > since))
+        .take(limit)
+        .collect()
+}
+
+// Read access to the journal for replaying events, and redaction of
+// erased users
+pub struct JournalService {
+    journal: Option<Arc<Mutex<EventJournal>>>,
+}
+
+impl JournalService {
+    pub fn new(journal: Option<Arc<Mutex<EventJournal>>>) -> Self {
+        Self { journal }
+    }
+
+    // Redact `username` from every journal file; 0 without a journal
+    pub async fn redact_user(&self, username: &str) -> io::Result<u64> {
+        let Some(journal) = self.journal.clone() else {
+            return Ok(0);
+        };
+        let username = username.to_string();
+        tokio::task::spawn_blocking(move || {
+            journal
+                .lock()
+                .map_err(|_| io::Error::other("event journal poisoned"))?
+                .redact_user(&username)
+        })
+        .await
+        .map_err(io::Error::other)?
+    }
+
+    pub async fn read(
+        &self,
+        since: u64,
+        limit: usize,
+    ) -> SftpApiResponse<Vec<Value>> {
+        let dir = self.journal.as_ref().and_then(|journal| {
+            journal.lock().ok().map(|journal| journal.dir.clone())
+        });
+        let Some(dir) = dir else {
+            return SftpApiResponse::error(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "The event journal is disabled",
+            );
+        };
+
+        let limit = limit.min(MAX_READ);
+        match tokio::task::spawn_blocking(move || {
+            read_since(&dir, since, limit)
+        })
+        .await
+        {
+            Ok(entries) => SftpApiResponse::success(entries),
+            Err(e) => {
+                error!("Journal read task failed: {}", e);
+                SftpApiResponse::error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to read the event journal",
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::Event;
+
+    fn envelope(enabled: bool) -> EventEnvelope {
+        EventEnvelope {
+            timestamp: "2024-01-01T00:00:00+00:00".to_string(),
+            event: Event::ServerToggled { enabled },
+        }
+    }
+
+    #[test]
+    fn test_rotation_keeps_sequence_across_reopen() {
+        let dir = std::env::temp_dir()
+            .join(format!("sftp-manager-journal-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut journal = EventJournal::open(&dir, 200, 2).unwrap();
+        for i in 0..10 {
+            journal.append(&envelope(i % 2 == 0)).unwrap();
+        }
+        drop(journal);
+
+        // Old files beyond max_files are gone, so reading starts later
+        let all = read_since(&dir, 0, 100);
+        assert!(all.len() < 10);
+        assert_eq!(all.last().and_then(seq_of), Some(10));
+        assert!(!rotated_path(&dir, 3).exists());
+
+        let mut journal = EventJournal::open(&dir, 200, 2).unwrap();
+        journal.append(&envelope(true)).unwrap();
+        let tail = read_since(&dir, 10, 100);
+        assert_eq!(tail.len(), 1);
+        assert_eq!(seq_of(&tail[0]), Some(11));
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_redaction_leaves_no_trace_of_the_user() {
+        let dir = std::env::temp_dir().join(format!(
+            "sftp-manager-journal-redact-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        let login = |username: &str| EventEnvelope {
+            timestamp: "2024-01-01T00:00:00+00:00".to_string(),
+            event: Event::CredentialsRotated { username: username.to_string() },
+        };
+
+        let mut journal = EventJournal::open(&dir, 250, 3).unwrap();
+        for username in ["alice", "bob", "alice", "bob"] {
+            journal.append(&login(username)).unwrap();
+        }
+        assert!(rotated_path(&dir, 1).exists());
+        assert_eq!(journal.redact_user("alice").unwrap(), 2);
+        journal.append(&login("carol")).unwrap();
+
+        let entries = read_since(&dir, 0, 100);
+        let text = serde_json::to_string(&entries).unwrap();
+        assert!(!text.contains("alice"));
+        assert_eq!(text.matches(ERASED_USER).count(), 2);
+        assert_eq!(text.matches("bob").count(), 2);
+        assert_eq!(entries.last().and_then(seq_of), Some(5));
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,164 @@
+use crate::config::settings::MdnsSettings;
+use crate::models::sftp::SftpState;
+use crate::utils::syslog::hostname;
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+const SERVICE_TYPE: &str = "_sftp-ssh._tcp.local.";
+// How often the listener is checked for starting, stopping or moving
+const POLL: Duration = Duration::from_secs(5);
+
+// What is currently advertised
+#[derive(PartialEq)]
+struct Advertised {
+    port: u16,
+    username: Option<String>,
+}
+
+// Advertises the default SFTP listener on the local network over
+// mDNS/DNS-SD while it runs, and withdraws it when it stops
+pub struct MdnsAdvertiser {
+    daemon: ServiceDaemon,
+    instance_name: String,
+    host_name: String,
+    state: SftpState,
+}
+
+impl MdnsAdvertiser {
+    pub fn new(
+        settings: &MdnsSettings,
+        state: SftpState,
+    ) -> anyhow::Result<Self> {
+        let host = match hostname().as_str() {
+            "-" => "sftp-manager".to_string(),
+            host => host.to_string(),
+        };
+        let instance_name = if settings.instance_name.is_empty() {
+            format!("SFTP on {}", host)
+        } else {
+            settings.instance_name.clone()
+        };
+        Ok(Self {
+            daemon: ServiceDaemon::new()?,
+            instance_name,
+            host_name: local_host_name(&host),
+            state,
+        })
+    }
+
+    pub fn start(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut advertised: Option<(Advertised, String)> = None;
+            loop {
+                let wanted = if self.state.is_running().await {
+                    Some(Advertised {
+                        port: self.state.listen_address().await.port,
+                        username: self
+                            .state
+                            .get_credentials()
+                            .await
+                            .map(|c| c.username),
+                    })
+                } else {
+                    None
+                };
+                if wanted.as_ref() != advertised.as_ref().map(|(a, _)| a) {
+                    if let Some((_, fullname)) = advertised.take() {
+                        self.withdraw(&fullname);
+                    }
+                    if let Some(wanted) = wanted {
+                        advertised = self
+                            .advertise(&wanted)
+                            .map(|fullname| (wanted, fullname));
+                    }
+                }
+                tokio::time::sleep(POLL).await;
+            }
+        })
+    }
+
+    // Register the service, returning its full name
+    fn advertise(&self, advertised: &Advertised) -> Option<String> {
+        let service = service_info(
+            &self.instance_name,
+            &self.host_name,
+            advertised.port,
+            advertised.username.as_deref(),
+        );
+        let result = service.and_then(|service| {
+            let fullname = service.get_fullname().to_string();
+            self.daemon.register(service).map(|_| fullname)
+        });
+        match result {
+            Ok(fullname) => {
+                info!(
+                    "Advertising SFTP on port {} over mDNS as {}",
+                    advertised.port, fullname
+                );
+                Some(fullname)
+            }
+            Err(e) => {
+                warn!("Failed to advertise SFTP over mDNS: {}", e);
+                None
+            }
+        }
+    }
+
+    fn withdraw(&self, fullname: &str) {
+        match self.daemon.unregister(fullname) {
+            Ok(_) => info!("Stopped advertising {} over mDNS", fullname),
+            Err(e) => warn!("Failed to withdraw {} from mDNS: {}", fullname, e),
+        }
+    }
+}
+
+// Service record on the addresses of all interfaces. The TXT keys are
+// those of the DNS-SD registry for sftp-ssh: the login and the path.
+fn service_info(
+    instance_name: &str,
+    host_name: &str,
+    port: u16,
+    username: Option<&str>,
+) -> Result<ServiceInfo, mdns_sd::Error> {
+    let mut properties = vec![("path", "/")];
+    if let Some(username) = username {
+        properties.push(("u", username));
+    }
+    Ok(ServiceInfo::new(
+        SERVICE_TYPE,
+        instance_name,
+        host_name,
+        (),
+        port,
+        &properties[..],
+    )?
+    .enable_addr_auto())
+}
+
+// The first label of a host name in the .local domain
+fn local_host_name(host: &str) -> String {
+    let label = host.split('.').next().unwrap_or(host);
+    format!("{}.local.", label)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_record() {
+        assert_eq!(local_host_name("nas.example.com"), "nas.local.");
+        assert_eq!(local_host_name("nas"), "nas.local.");
+
+        let service =
+            service_info("SFTP on nas", "nas.local.", 2222, Some("sftp_ab12"))
+                .unwrap();
+        assert_eq!(service.get_fullname(), "SFTP on nas._sftp-ssh._tcp.local.");
+        assert_eq!(service.get_port(), 2222);
+        assert_eq!(service.get_property_val_str("u"), Some("sftp_ab12"));
+        assert_eq!(service.get_property_val_str("path"), Some("/"));
+        assert!(service.is_addr_auto());
+    }
+}
@@ -0,0 +1,165 @@
+use anyhow::{Context, anyhow};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rustls_pki_types::CertificateDer;
+use rustls_pki_types::pem::PemObject;
+use std::fmt::Write;
+
+// A gauge with one sample per set of labels, rendered in the Prometheus
+// text format
+pub struct Gauge {
+    name: &'static str,
+    help: &'static str,
+    samples: Vec<(Vec<(&'static str, String)>, f64)>,
+}
+
+impl Gauge {
+    pub fn new(name: &'static str, help: &'static str) -> Self {
+        Self { name, help, samples: Vec::new() }
+    }
+
+    pub fn set(&mut self, labels: &[(&'static str, &str)], value: f64) {
+        let labels = labels.iter().map(|&(k, v)| (k, v.to_string())).collect();
+        self.samples.push((labels, value));
+    }
+}
+
+// The exposition of `gauges`, served as text/plain; version=0.0.4
+pub fn render(gauges: &[Gauge]) -> String {
+    let mut text = String::new();
+    for gauge in gauges {
+        let _ = writeln!(text, "# HELP {} {}", gauge.name, gauge.help);
+        let _ = writeln!(text, "# TYPE {} gauge", gauge.name);
+        for (labels, value) in &gauge.samples {
+            text.push_str(gauge.name);
+            if !labels.is_empty() {
+                let labels: Vec<String> = labels
+                    .iter()
+                    .map(|(k, v)| format!("{}=\"{}\"", k, escape(v)))
+                    .collect();
+                let _ = write!(text, "{{{}}}", labels.join(","));
+            }
+            let _ = writeln!(text, " {}", value);
+        }
+    }
+    text
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+// When the first certificate of a PEM file, the one presented to clients,
+// stops being valid
+pub fn certificate_expiry(file: &str) -> anyhow::Result<DateTime<Utc>> {
+    let certificate = CertificateDer::pem_file_iter(file)
+        .with_context(|| format!("cannot read {}", file))?
+        .next()
+        .ok_or_else(|| anyhow!("no certificate in {}", file))?
+        .with_context(|| format!("invalid certificate {}", file))?;
+    not_after(&certificate)
+        .ok_or_else(|| anyhow!("cannot read the validity of {}", file))
+}
+
+// notAfter of a DER certificate (RFC 5280, 4.1): the second time of the
+// validity, which follows the version, serial number, signature algorithm
+// and issuer in the TBSCertificate
+fn not_after(der: &[u8]) -> Option<DateTime<Utc>> {
+    let (0x30, certificate, _) = element(der)? else {
+        return None;
+    };
+    let (0x30, mut tbs, _) = element(certificate)? else {
+        return None;
+    };
+    // The version is optional, explicitly tagged [0]
+    if tbs.first() == Some(&0xa0) {
+        tbs = element(tbs)?.2;
+    }
+    for _ in 0..3 {
+        tbs = element(tbs)?.2;
+    }
+    let (0x30, validity, _) = element(tbs)? else {
+        return None;
+    };
+    let (_, _, validity) = element(validity)?;
+    let (tag, time, _) = element(validity)?;
+    let format = match tag {
+        // UTCTime, years 1950 to 2049
+        0x17 => "%y%m%d%H%M%SZ",
+        // GeneralizedTime
+        0x18 => "%Y%m%d%H%M%SZ",
+        _ => return None,
+    };
+    let time = std::str::from_utf8(time).ok()?;
+    NaiveDateTime::parse_from_str(time, format).ok().map(|t| t.and_utc())
+}
+
+// The DER element at the start of `data`: its tag, its contents and the
+// data after it
+fn element(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = data.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (length, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 4 {
+            return None;
+        }
+        let (bytes, rest) = rest.split_at_checked(count)?;
+        (bytes.iter().fold(0, |l, &b| l << 8 | b as usize), rest)
+    };
+    let (contents, rest) = rest.split_at_checked(length)?;
+    Some((tag, contents, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn der(tag: u8, contents: &[u8]) -> Vec<u8> {
+        let mut element = vec![tag];
+        if contents.len() < 0x80 {
+            element.push(contents.len() as u8);
+        } else {
+            element.push(0x82);
+            element.extend_from_slice(&(contents.len() as u16).to_be_bytes());
+        }
+        element.extend_from_slice(contents);
+        element
+    }
+
+    #[test]
+    fn test_certificate_expiry_and_exposition() {
+        let validity =
+            [der(0x17, b"240101000000Z"), der(0x18, b"20350615120000Z")]
+                .concat();
+        let tbs = [
+            der(0xa0, &der(0x02, &[2])),
+            der(0x02, &[0x01; 20]),
+            der(0x30, &der(0x06, &[0x2a; 8])),
+            der(0x30, &[0x31; 200]),
+            der(0x30, &validity),
+            der(0x30, &[]),
+        ]
+        .concat();
+        let certificate =
+            der(0x30, &[der(0x30, &tbs), der(0x30, &[])].concat());
+        assert_eq!(
+            not_after(&certificate),
+            Some(Utc.with_ymd_and_hms(2035, 6, 15, 12, 0, 0).unwrap())
+        );
+        assert_eq!(not_after(&certificate[..40]), None);
+
+        let mut gauge = Gauge::new("disk_free_bytes", "Free bytes");
+        gauge.set(&[("instance", "a\"b")], 1024.0);
+        gauge.set(&[], -1.5);
+        assert_eq!(
+            render(&[gauge]),
+            "# HELP disk_free_bytes Free bytes\n\
+             # TYPE disk_free_bytes gauge\n\
+             disk_free_bytes{instance=\"a\\\"b\"} 1024\n\
+             disk_free_bytes -1.5\n"
+        );
+    }
+}
@@ -0,0 +1,434 @@
+mod s3;
+mod sftp;
+
+use crate::config::settings::{MirrorKind, MirrorTarget, Settings};
+use crate::events::{Event, EventBus};
+use crate::models::files::{MirrorJob, MirrorQueueResponse};
+use crate::responses::sftp::SftpApiResponse;
+use crate::sftp::glob::glob_match;
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use chrono::{DateTime, TimeDelta, Utc};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::fs;
+use tokio::sync::Notify;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+// Longest wait between two looks at the queue
+const IDLE_POLL: Duration = Duration::from_secs(60);
+// Longest delay between two attempts of a job
+const MAX_BACKOFF_SECS: u64 = 6 * 60 * 60;
+
+// Connection to a mirror target, reused for every due job of the target
+#[async_trait]
+pub(crate) trait MirrorClient: Send {
+    // Copy a local file to the target, replacing any previous copy
+    async fn upload(
+        &mut self,
+        local_path: &Path,
+        path: &str,
+    ) -> anyhow::Result<u64>;
+    // Delete the copy of a file from the target
+    async fn remove(&mut self, path: &str) -> anyhow::Result<()>;
+}
+
+pub(crate) async fn connect(
+    target: &MirrorTarget,
+) -> anyhow::Result<Box<dyn MirrorClient>> {
+    Ok(match target.kind {
+        MirrorKind::Sftp => Box::new(sftp::SftpMirror::connect(target).await?),
+        MirrorKind::S3 => Box::new(s3::S3Mirror::new(target)?),
+    })
+}
+
+// Copies new and changed files below the default root to remote targets.
+// Jobs are files in the queue directory, so copies that have not
+// happened yet survive restarts; failures are retried with backoff.
+pub struct MirrorService {
+    root_dir: PathBuf,
+    pending_dir: PathBuf,
+    failed_dir: PathBuf,
+    settings: watch::Receiver<Settings>,
+    events: EventBus,
+    // Woken when a job is queued
+    wake: Notify,
+}
+
+impl MirrorService {
+    pub fn new(
+        root_dir: String,
+        settings: watch::Receiver<Settings>,
+        events: EventBus,
+    ) -> Self {
+        let queue_dir = PathBuf::from(&settings.borrow().mirror.queue_dir);
+        Self {
+            root_dir: PathBuf::from(root_dir),
+            pending_dir: queue_dir.join("pending"),
+            failed_dir: queue_dir.join("failed"),
+            settings,
+            events,
+            wake: Notify::new(),
+        }
+    }
+
+    // Queue files as they are uploaded or changed, and copy them in the
+    // background
+    pub fn start(self: &Arc<Self>) -> Vec<JoinHandle<()>> {
+        let mut receiver = self.events.subscribe();
+        let service = self.clone();
+        let listener = tokio::spawn(async move {
+            loop {
+                let path = match receiver.recv().await {
+                    Ok(envelope) => match envelope.event {
+                        Event::FileUploaded { path, .. }
+                        | Event::FileCreated { path }
+                        | Event::FileModified { path }
+                        | Event::PipelineCompleted { path, .. } => path,
+                        _ => continue,
+                    },
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Mirror lagged, {} events dropped", skipped);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                service.enqueue(&path).await;
+            }
+        });
+
+        let service = self.clone();
+        let worker = tokio::spawn(async move {
+            for dir in [&service.pending_dir, &service.failed_dir] {
+                if let Err(e) = fs::create_dir_all(dir).await {
+                    error!(
+                        "Cannot create mirror queue {}: {}",
+                        dir.display(),
+                        e
+                    );
+                    return;
+                }
+            }
+            info!("Mirroring started");
+            loop {
+                let next = service.run_due().await;
+                let wait = next
+                    .and_then(|at| (at - Utc::now()).to_std().ok())
+                    .unwrap_or(if next.is_some() {
+                        Duration::ZERO
+                    } else {
+                        IDLE_POLL
+                    })
+                    .min(IDLE_POLL);
+                tokio::select! {
+                    _ = service.wake.notified() => {}
+                    _ = tokio::time::sleep(wait) => {}
+                }
+            }
+        });
+
+        vec![listener, worker]
+    }
+
+    // Queue a file for every target whose rule matches its path. A file
+    // queued again before it was copied keeps a single job.
+    async fn enqueue(&self, path: &str) {
+        let targets: Vec<String> = {
+            let settings = self.settings.borrow();
+            let mut targets: Vec<String> = settings
+                .mirror
+                .rules
+                .iter()
+                .filter(|rule| glob_match(&rule.glob, path))
+                .map(|rule| rule.target.clone())
+                .collect();
+            targets.sort();
+            targets.dedup();
+            targets
+        };
+
+        let now = Utc::now();
+        for target in targets {
+            let job = MirrorJob {
+                id: job_id(&target, path),
+                target,
+                path: path.to_string(),
+                attempts: 0,
+                queued_at: now,
+                next_attempt_at: now,
+                last_error: None,
+            };
+            debug!("Queueing {} for mirror target {}", path, job.target);
+            if let Err(e) = write_job(&self.pending_dir, &job).await {
+                error!("Failed to queue {} for mirroring: {}", path, e);
+            }
+        }
+        self.wake.notify_one();
+    }
+
+    // Attempt every due job, one connection per target. Returns when the
+    // next job not yet due should run.
+    async fn run_due(&self) -> Option<DateTime<Utc>> {
+        let jobs = match read_jobs(&self.pending_dir).await {
+            Ok(jobs) => jobs,
+            Err(e) => {
+                error!("Failed to read the mirror queue: {}", e);
+                return None;
+            }
+        };
+        let now = Utc::now();
+        let mut next: Option<DateTime<Utc>> = None;
+        let mut due: BTreeMap<String, Vec<MirrorJob>> = BTreeMap::new();
+        for job in jobs {
+            if job.next_attempt_at <= now {
+                due.entry(job.target.clone()).or_default().push(job);
+            } else {
+                next = Some(next.map_or(job.next_attempt_at, |n| {
+                    n.min(job.next_attempt_at)
+                }));
+            }
+        }
+
+        for (name, jobs) in due {
+            let target = self
+                .settings
+                .borrow()
+                .mirror
+                .targets
+                .iter()
+                .find(|t| t.name == name)
+                .cloned();
+            let mut client = match target {
+                Some(target) => connect(&target).await,
+                None => Err(anyhow::anyhow!("no target is named '{}'", name)),
+            };
+            for job in jobs {
+                let result = match &mut client {
+                    Ok(client) => self.copy(client.as_mut(), &job).await,
+                    Err(e) => Err(anyhow::anyhow!("{:#}", e)),
+                };
+                if let Some(retry_at) = self.finish(job, result).await {
+                    next = Some(next.map_or(retry_at, |n| n.min(retry_at)));
+                }
+            }
+        }
+        next
+    }
+
+    async fn copy(
+        &self,
+        client: &mut dyn MirrorClient,
+        job: &MirrorJob,
+    ) -> anyhow::Result<Option<u64>> {
+        let local_path = self.root_dir.join(job.path.trim_start_matches('/'));
+        match fs::metadata(&local_path).await {
+            Ok(metadata) if metadata.is_file() => {}
+            // Moved or removed since it was queued; the new location, if
+            // any, is queued on its own
+            Ok(_) => return Ok(None),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        client.upload(&local_path, &job.path).await.map(Some)
+    }
+
+    // Remove a finished job, or schedule its retry. Returns when a retried
+    // job is due again.
+    async fn finish(
+        &self,
+        mut job: MirrorJob,
+        result: anyhow::Result<Option<u64>>,
+    ) -> Option<DateTime<Utc>> {
+        let error = match result {
+            Ok(copied) => {
+                match copied {
+                    Some(bytes) => {
+                        info!(
+                            "Mirrored {} ({} bytes) to {}",
+                            job.path, bytes, job.target
+                        );
+                        self.events.publish(Event::FileMirrored {
+                            path: job.path.clone(),
+                            target: job.target.clone(),
+                            bytes,
+                        });
+                    }
+                    None => info!(
+                        "Skipped mirroring {} to {}, it no longer exists",
+                        job.path, job.target
+                    ),
+                }
+                self.remove_unless_requeued(&job).await;
+                return None;
+            }
+            Err(e) => format!("{:#}", e),
+        };
+
+        job.attempts += 1;
+        job.last_error = Some(error.clone());
+        let max_attempts = self.settings.borrow().mirror.max_attempts;
+        if job.attempts >= max_attempts {
+            error!(
+                "Giving up mirroring {} to {} after {} attempts: {}",
+                job.path, job.target, job.attempts, error
+            );
+            self.events.publish(Event::MirrorFailed {
+                path: job.path.clone(),
+                target: job.target.clone(),
+                attempts: job.attempts,
+                error,
+            });
+            if let Err(e) = write_job(&self.failed_dir, &job).await {
+                error!("Failed to record failed mirror job: {}", e);
+            }
+            self.remove_unless_requeued(&job).await;
+            return None;
+        }
+
+        let base = self.settings.borrow().mirror.retry_base_secs;
+        let delay = base
+            .saturating_mul(1 << (job.attempts - 1).min(20))
+            .min(MAX_BACKOFF_SECS);
+        job.next_attempt_at = Utc::now() + TimeDelta::seconds(delay as i64);
+        warn!(
+            "Mirroring {} to {} failed (attempt {}), retrying in {}s: {}",
+            job.path, job.target, job.attempts, delay, error
+        );
+        // Queued again while copying: the new job runs right away instead
+        if self.is_requeued(&job).await {
+            return Some(Utc::now());
+        }
+        if let Err(e) = write_job(&self.pending_dir, &job).await {
+            error!("Failed to reschedule mirror job: {}", e);
+        }
+        Some(job.next_attempt_at)
+    }
+
+    // Whether the job file was replaced by a newer queueing of the file
+    async fn is_requeued(&self, job: &MirrorJob) -> bool {
+        read_job(&job_path(&self.pending_dir, &job.id))
+            .await
+            .is_ok_and(|current| current.queued_at != job.queued_at)
+    }
+
+    async fn remove_unless_requeued(&self, job: &MirrorJob) {
+        if !self.is_requeued(job).await {
+            let _ = fs::remove_file(job_path(&self.pending_dir, &job.id)).await;
+        }
+    }
+
+    pub async fn get_queue(&self) -> SftpApiResponse<MirrorQueueResponse> {
+        let pending = read_jobs(&self.pending_dir).await;
+        let failed = read_jobs(&self.failed_dir).await;
+        match (pending, failed) {
+            (Ok(pending), Ok(failed)) => {
+                SftpApiResponse::success(MirrorQueueResponse {
+                    pending,
+                    failed,
+                })
+            }
+            (Err(e), _) | (_, Err(e)) => SftpApiResponse::error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to read the mirror queue: {}", e),
+            ),
+        }
+    }
+
+    // Queue every failed job again with a fresh set of attempts
+    pub async fn retry_failed(&self) -> SftpApiResponse<MirrorQueueResponse> {
+        let failed = match read_jobs(&self.failed_dir).await {
+            Ok(failed) => failed,
+            Err(e) => {
+                return SftpApiResponse::error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to read the mirror queue: {}", e),
+                );
+            }
+        };
+        let now = Utc::now();
+        for mut job in failed {
+            job.attempts = 0;
+            job.queued_at = now;
+            job.next_attempt_at = now;
+            if let Err(e) = write_job(&self.pending_dir, &job).await {
+                error!("Failed to queue mirror job {}: {}", job.id, e);
+                continue;
+            }
+            let _ = fs::remove_file(job_path(&self.failed_dir, &job.id)).await;
+        }
+        info!("Queued failed mirror jobs again");
+        self.wake.notify_one();
+        self.get_queue().await
+    }
+}
+
+// Same target and path, same job
+fn job_id(target: &str, path: &str) -> String {
+    let digest = Sha256::digest(format!("{}\0{}", target, path).as_bytes());
+    hex::encode(&digest[..16])
+}
+
+fn job_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{}.json", id))
+}
+
+async fn read_job(path: &Path) -> io::Result<MirrorJob> {
+    let contents = fs::read(path).await?;
+    serde_json::from_slice(&contents)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+// Jobs in a queue directory, oldest first
+async fn read_jobs(dir: &Path) -> io::Result<Vec<MirrorJob>> {
+    let mut entries = match fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut jobs = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.extension().is_none_or(|e| e != "json") {
+            continue;
+        }
+        match read_job(&path).await {
+            Ok(job) => jobs.push(job),
+            // Removed by the worker in the meantime
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => warn!("Ignoring mirror job {}: {}", path.display(), e),
+        }
+    }
+    jobs.sort_by_key(|job| job.queued_at);
+    Ok(jobs)
+}
+
+// Write a job atomically, so readers never see a partial file
+async fn write_job(dir: &Path, job: &MirrorJob) -> io::Result<()> {
+    fs::create_dir_all(dir).await?;
+    let path = job_path(dir, &job.id);
+    let temp_path = path.with_extension("json.tmp");
+    let contents = serde_json::to_vec_pretty(job)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    fs::write(&temp_path, contents).await?;
+    fs::rename(&temp_path, &path).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_ids_depend_on_target_and_path() {
+        assert_eq!(job_id("lake", "/a.csv"), job_id("lake", "/a.csv"));
+        assert_ne!(job_id("lake", "/a.csv"), job_id("backup", "/a.csv"));
+        assert_ne!(job_id("lake", "/a.csv"), job_id("lake", "/b.csv"));
+        assert_eq!(job_id("lake", "/a.csv").len(), 32);
+    }
+}
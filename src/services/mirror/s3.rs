@@ -0,0 +1,158 @@
+use super::MirrorClient;
+use crate::config::settings::MirrorTarget;
+use crate::s3::sigv4;
+use crate::sftp::SecretString;
+use anyhow::{Context, bail};
+use async_trait::async_trait;
+use axum::http::{HeaderMap, HeaderValue, Method, Uri};
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::time::Duration;
+use tokio::fs::File;
+use tokio::io::AsyncReadExt;
+use tokio_util::io::ReaderStream;
+
+pub struct S3Mirror {
+    client: reqwest::Client,
+    endpoint: String,
+    host: String,
+    bucket: String,
+    prefix: String,
+    region: String,
+    access_key_id: String,
+    secret_access_key: SecretString,
+}
+
+impl S3Mirror {
+    pub fn new(target: &MirrorTarget) -> anyhow::Result<Self> {
+        let endpoint = target.endpoint.trim_end_matches('/').to_string();
+        let uri: Uri = endpoint.parse().context("invalid endpoint")?;
+        let host = uri
+            .authority()
+            .context("endpoint has no host")?
+            .as_str()
+            .to_string();
+        let client = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(30))
+            .build()
+            .context("cannot build HTTP client")?;
+
+        Ok(Self {
+            client,
+            endpoint,
+            host,
+            bucket: target.bucket.clone(),
+            prefix: target.prefix.clone(),
+            region: target.region.clone(),
+            access_key_id: target.access_key_id.clone(),
+            secret_access_key: target
+                .secret_access_key
+                .clone()
+                .unwrap_or_default(),
+        })
+    }
+
+    // Object key, URL and signed headers of a request for `path`
+    fn signed(
+        &self,
+        method: Method,
+        path: &str,
+        payload_hash: &str,
+        size: u64,
+    ) -> anyhow::Result<(String, String, HeaderMap)> {
+        let key = format!("{}{}", self.prefix, path.trim_start_matches('/'));
+        let url = format!(
+            "{}/{}/{}",
+            self.endpoint,
+            self.bucket,
+            sigv4::uri_encode(&key, false)
+        );
+        let uri: Uri = url.parse().context("invalid object URL")?;
+
+        let mut headers = HeaderMap::new();
+        headers.insert("host", HeaderValue::from_str(&self.host)?);
+        headers.insert(
+            "x-amz-date",
+            HeaderValue::from_str(
+                &Utc::now().format("%Y%m%dT%H%M%SZ").to_string(),
+            )?,
+        );
+        headers.insert(
+            "x-amz-content-sha256",
+            HeaderValue::from_str(payload_hash)?,
+        );
+        headers.insert("content-length", HeaderValue::from(size));
+        let authorization = sigv4::authorization(
+            &method,
+            &uri,
+            &headers,
+            &self.access_key_id,
+            self.secret_access_key.expose(),
+            &self.region,
+            payload_hash,
+        )
+        .map_err(|e| anyhow::anyhow!("cannot sign request: {}", e))?;
+        headers.insert("authorization", HeaderValue::from_str(&authorization)?);
+        Ok((key, url, headers))
+    }
+}
+
+// Hex SHA-256 of a file, read without holding it in memory
+async fn file_sha256(path: &Path) -> std::io::Result<String> {
+    let mut file = File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buffer).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+#[async_trait]
+impl MirrorClient for S3Mirror {
+    async fn upload(
+        &mut self,
+        local_path: &Path,
+        path: &str,
+    ) -> anyhow::Result<u64> {
+        // Signed over the content, so a file changing while it is sent is
+        // rejected rather than stored half-written
+        let payload_hash = file_sha256(local_path).await?;
+        let file = File::open(local_path).await?;
+        let size = file.metadata().await?.len();
+
+        let (key, url, headers) =
+            self.signed(Method::PUT, path, &payload_hash, size)?;
+
+        let response = self
+            .client
+            .put(url)
+            .headers(headers)
+            .body(reqwest::Body::wrap_stream(ReaderStream::new(file)))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            bail!("PUT {} returned {}: {}", key, status, body.trim());
+        }
+        Ok(size)
+    }
+
+    async fn remove(&mut self, path: &str) -> anyhow::Result<()> {
+        let (key, url, headers) =
+            self.signed(Method::DELETE, path, sigv4::EMPTY_SHA256, 0)?;
+        let response = self.client.delete(url).headers(headers).send().await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            bail!("DELETE {} returned {}: {}", key, status, body.trim());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,189 @@
+use super::MirrorClient;
+use crate::config::settings::MirrorTarget;
+use anyhow::{Context, bail};
+use async_trait::async_trait;
+use russh::client::{self, AuthResult, Handle};
+use russh::keys::{HashAlg, PrivateKeyWithHashAlg, PublicKey};
+use russh_sftp::client::SftpSession;
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::fs::File;
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+struct HostKeyCheck {
+    host: String,
+    expected: Option<String>,
+}
+
+impl client::Handler for HostKeyCheck {
+    type Error = russh::Error;
+
+    async fn check_server_key(
+        &mut self,
+        key: &PublicKey,
+    ) -> Result<bool, Self::Error> {
+        let fingerprint = key.fingerprint(HashAlg::Sha256).to_string();
+        match &self.expected {
+            Some(expected) => {
+                let matches = *expected == fingerprint;
+                if !matches {
+                    warn!(
+                        "Mirror host {} presented key {}, expected {}",
+                        self.host, fingerprint, expected
+                    );
+                }
+                Ok(matches)
+            }
+            None => {
+                warn!(
+                    "Accepting host key {} of mirror host {} without \
+                     verification, set host_key_fingerprint to pin it",
+                    fingerprint, self.host
+                );
+                Ok(true)
+            }
+        }
+    }
+}
+
+pub struct SftpMirror {
+    // Keeps the SSH connection open for the session
+    _handle: Handle<HostKeyCheck>,
+    sftp: SftpSession,
+    remote_dir: String,
+    // Remote directories known to exist
+    created_dirs: HashSet<String>,
+}
+
+impl SftpMirror {
+    pub async fn connect(target: &MirrorTarget) -> anyhow::Result<Self> {
+        let config = Arc::new(client::Config {
+            inactivity_timeout: Some(Duration::from_secs(300)),
+            ..Default::default()
+        });
+        let handler = HostKeyCheck {
+            host: target.host.clone(),
+            expected: target.host_key_fingerprint.clone(),
+        };
+        let address = (target.host.as_str(), target.port);
+        let mut handle = tokio::time::timeout(
+            CONNECT_TIMEOUT,
+            client::connect(config, address, handler),
+        )
+        .await
+        .context("timed out connecting")?
+        .with_context(|| {
+            format!("cannot connect to {}:{}", target.host, target.port)
+        })?;
+
+        let result = match &target.private_key_file {
+            Some(key_file) => {
+                let key = russh::keys::load_secret_key(key_file, None)
+                    .with_context(|| format!("cannot load {}", key_file))?;
+                let hash = handle.best_supported_rsa_hash().await?.flatten();
+                handle
+                    .authenticate_publickey(
+                        &target.username,
+                        PrivateKeyWithHashAlg::new(Arc::new(key), hash),
+                    )
+                    .await?
+            }
+            None => {
+                let password = target
+                    .password
+                    .as_ref()
+                    .map(|p| p.expose().to_string())
+                    .unwrap_or_default();
+                handle.authenticate_password(&target.username, password).await?
+            }
+        };
+        if !matches!(result, AuthResult::Success) {
+            bail!("authentication as '{}' was rejected", target.username);
+        }
+
+        let channel = handle.channel_open_session().await?;
+        channel.request_subsystem(true, "sftp").await?;
+        let sftp = SftpSession::new(channel.into_stream())
+            .await
+            .context("cannot start SFTP")?;
+        info!("Connected to mirror target {}", target.name);
+
+        Ok(Self {
+            _handle: handle,
+            sftp,
+            remote_dir: target.remote_dir.trim_end_matches('/').to_string(),
+            created_dirs: HashSet::new(),
+        })
+    }
+
+    // Create every missing directory above a remote path. Errors are left
+    // for the upload to report, as most mean the directory exists already.
+    async fn create_parents(&mut self, remote_path: &str) {
+        let Some((parent, _)) = remote_path.rsplit_once('/') else {
+            return;
+        };
+        let mut dir = String::new();
+        for component in parent.split('/').filter(|c| !c.is_empty()) {
+            dir.push('/');
+            dir.push_str(component);
+            if self.created_dirs.contains(&dir) {
+                continue;
+            }
+            if self.sftp.metadata(dir.as_str()).await.is_err() {
+                let _ = self.sftp.create_dir(dir.as_str()).await;
+            }
+            self.created_dirs.insert(dir.clone());
+        }
+    }
+}
+
+#[async_trait]
+impl MirrorClient for SftpMirror {
+    async fn upload(
+        &mut self,
+        local_path: &Path,
+        path: &str,
+    ) -> anyhow::Result<u64> {
+        let remote_path = format!("{}{}", self.remote_dir, path);
+        let temp_path = format!("{}.mirror-tmp", remote_path);
+        self.create_parents(&remote_path).await;
+
+        let mut local = File::open(local_path).await?;
+        let mut remote = self
+            .sftp
+            .create(temp_path.as_str())
+            .await
+            .with_context(|| format!("cannot create {}", temp_path))?;
+        let bytes = tokio::io::copy(&mut local, &mut remote).await?;
+        remote.shutdown().await?;
+        drop(remote);
+
+        // SFTP v3 rename fails when the target exists
+        if self
+            .sftp
+            .rename(temp_path.as_str(), remote_path.as_str())
+            .await
+            .is_err()
+        {
+            let _ = self.sftp.remove_file(remote_path.as_str()).await;
+            self.sftp
+                .rename(temp_path.as_str(), remote_path.as_str())
+                .await
+                .with_context(|| format!("cannot rename to {}", remote_path))?;
+        }
+        Ok(bytes)
+    }
+
+    async fn remove(&mut self, path: &str) -> anyhow::Result<()> {
+        let remote_path = format!("{}{}", self.remote_dir, path);
+        self.sftp
+            .remove_file(remote_path.as_str())
+            .await
+            .with_context(|| format!("cannot remove {}", remote_path))
+    }
+}
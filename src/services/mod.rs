@@ -1,2 +1,39 @@
+pub mod access_hours;
+pub mod account_logins;
+pub mod accounts;
+pub mod audit;
+pub mod backup;
+pub mod cluster;
+pub mod config_reload;
+pub mod connection_hooks;
+pub mod credential_delivery;
+pub mod disk_usage;
+pub mod event_stream;
+pub mod extract;
+pub mod file_index;
+pub mod file_tags;
+pub mod files;
+pub mod fs_watcher;
+pub mod integrity;
+pub mod journal;
+pub mod mdns;
+pub mod metrics;
+pub mod mirror;
+pub mod pipeline;
+pub mod port_mapping;
+pub mod privacy;
+pub mod redis_state;
+pub mod retention;
+pub mod secrets;
 pub mod sftp_lifecycle;
+pub mod sftp_probe;
 pub mod sftp_service;
+pub mod smtp;
+pub mod state_store;
+pub mod supervisor;
+pub mod tenants;
+pub mod tus;
+pub mod upload_limits;
+pub mod vault;
+pub mod virus_scan;
+pub mod webhook;
@@ -0,0 +1,318 @@
+use crate::config::settings::{
+    PipelineAction, PipelineFailure, PipelineRule, Settings,
+};
+use crate::events::{Event, EventBus};
+use crate::services::webhook::{EVENT_HEADER, SIGNATURE_HEADER, sign};
+use crate::sftp::checksum::{ChecksumAlgorithm, file_checksums};
+use crate::sftp::glob::glob_match;
+use crate::sftp::hooks::{CompletedUpload, HookOutcome, UploadHook};
+use anyhow::{Context, anyhow, bail};
+use async_trait::async_trait;
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::fs;
+use tokio::process::Command;
+use tokio::sync::watch;
+use tracing::{debug, error, info, warn};
+
+// Bytes of a failed command's stderr kept in the error message
+const STDERR_TAIL: usize = 512;
+
+// Where the uploaded file currently is; move actions change it
+struct Location {
+    local_path: PathBuf,
+    virtual_path: String,
+}
+
+// Runs the actions of the first pipeline rule matching a completed upload.
+// Rules are read from the live settings so they apply on reload.
+pub struct UploadPipeline {
+    settings: watch::Receiver<Settings>,
+    client: reqwest::Client,
+    events: EventBus,
+}
+
+impl UploadPipeline {
+    pub fn new(settings: watch::Receiver<Settings>, events: EventBus) -> Self {
+        let client = reqwest::Client::builder()
+            .build()
+            .expect("Failed to build pipeline HTTP client");
+
+        Self { settings, client, events }
+    }
+
+    async fn run(&self, rule: &PipelineRule, upload: &CompletedUpload) {
+        let mut location = Location {
+            local_path: upload.local_path.clone(),
+            virtual_path: upload.path.clone(),
+        };
+        let mut failed = false;
+
+        for action in &rule.actions {
+            let result = self.apply(action, rule, upload, &mut location).await;
+            let Err(e) = result else { continue };
+
+            failed = true;
+            error!(
+                "Pipeline rule {} failed on {} ({} action): {:#}",
+                rule.name,
+                location.virtual_path,
+                action_name(action),
+                e
+            );
+            self.events.publish(Event::PipelineFailed {
+                rule: rule.name.clone(),
+                path: location.virtual_path.clone(),
+                username: upload.username.clone(),
+                action: action_name(action).to_string(),
+                error: format!("{:#}", e),
+            });
+
+            match rule.on_failure {
+                PipelineFailure::Continue => continue,
+                PipelineFailure::Stop => {}
+                PipelineFailure::Move => {
+                    let target = rule.failed_dir.as_str();
+                    if let Err(e) =
+                        move_into(&upload.root_dir, target, &mut location).await
+                    {
+                        error!(
+                            "Failed to move {} to {}: {}",
+                            location.virtual_path, target, e
+                        );
+                    }
+                }
+            }
+            break;
+        }
+
+        if !failed {
+            info!(
+                "Pipeline rule {} completed for {}",
+                rule.name, location.virtual_path
+            );
+            self.events.publish(Event::PipelineCompleted {
+                rule: rule.name.clone(),
+                path: location.virtual_path,
+                username: upload.username.clone(),
+            });
+        }
+    }
+
+    async fn apply(
+        &self,
+        action: &PipelineAction,
+        rule: &PipelineRule,
+        upload: &CompletedUpload,
+        location: &mut Location,
+    ) -> anyhow::Result<()> {
+        match action {
+            PipelineAction::Move { target } => {
+                move_into(&upload.root_dir, target, location).await
+            }
+            PipelineAction::Webhook { url, secret } => {
+                self.notify(url, secret.as_deref(), rule, upload, location)
+                    .await
+            }
+            PipelineAction::Checksum { algorithm } => {
+                write_checksum(*algorithm, upload, location).await
+            }
+            PipelineAction::Command { command, timeout_secs } => {
+                let timeout = Duration::from_secs(*timeout_secs);
+                run_command(command, timeout, rule, upload, location).await
+            }
+        }
+    }
+
+    async fn notify(
+        &self,
+        url: &str,
+        secret: Option<&str>,
+        rule: &PipelineRule,
+        upload: &CompletedUpload,
+        location: &Location,
+    ) -> anyhow::Result<()> {
+        let body = serde_json::to_vec(&json!({
+            "rule": rule.name,
+            "path": location.virtual_path,
+            "uploaded_path": upload.path,
+            "username": upload.username,
+            "bytes": upload.bytes,
+            "checksums": upload.checksums,
+        }))?;
+        let timeout =
+            Duration::from_secs(self.settings.borrow().webhooks.timeout_secs);
+
+        let mut request = self
+            .client
+            .post(url)
+            .header("Content-Type", "application/json")
+            .header(EVENT_HEADER, "pipeline")
+            .timeout(timeout);
+        if let Some(secret) = secret {
+            request = request.header(SIGNATURE_HEADER, sign(secret, &body));
+        }
+
+        let response = request.body(body).send().await?;
+        if !response.status().is_success() {
+            bail!("{} returned {}", url, response.status());
+        }
+        debug!("Pipeline webhook {} accepted {}", url, location.virtual_path);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UploadHook for UploadPipeline {
+    async fn on_upload(&self, upload: &CompletedUpload) -> HookOutcome {
+        let rule = self
+            .settings
+            .borrow()
+            .pipeline
+            .rules
+            .iter()
+            .find(|rule| glob_match(&rule.glob, &upload.path))
+            .cloned();
+        match rule {
+            Some(rule) => self.run(&rule, upload).await,
+            None => debug!("No pipeline rule matches {}", upload.path),
+        }
+        HookOutcome::Continue
+    }
+}
+
+fn action_name(action: &PipelineAction) -> &'static str {
+    match action {
+        PipelineAction::Move { .. } => "move",
+        PipelineAction::Webhook { .. } => "webhook",
+        PipelineAction::Checksum { .. } => "checksum",
+        PipelineAction::Command { .. } => "command",
+    }
+}
+
+// Move the file into a directory below the SFTP root, keeping its name
+async fn move_into(
+    root_dir: &Path,
+    target: &str,
+    location: &mut Location,
+) -> anyhow::Result<()> {
+    let file_name = location
+        .local_path
+        .file_name()
+        .ok_or_else(|| anyhow!("path has no file name"))?
+        .to_os_string();
+    let dir = root_dir.join(target.trim_start_matches('/'));
+    fs::create_dir_all(&dir)
+        .await
+        .with_context(|| format!("cannot create {}", target))?;
+
+    let local_path = dir.join(&file_name);
+    fs::rename(&location.local_path, &local_path)
+        .await
+        .with_context(|| format!("cannot move to {}", target))?;
+
+    location.local_path = local_path;
+    location.virtual_path = format!(
+        "{}/{}",
+        target.trim_end_matches('/'),
+        file_name.to_string_lossy()
+    );
+    Ok(())
+}
+
+// Write "<digest>  <file name>" next to the file, as sha256sum and friends
+// do, so it can be checked with `sha256sum -c`
+async fn write_checksum(
+    algorithm: ChecksumAlgorithm,
+    upload: &CompletedUpload,
+    location: &Location,
+) -> anyhow::Result<()> {
+    let digest = match upload.checksums.get(algorithm.name()) {
+        Some(digest) => digest.clone(),
+        None => file_checksums(&[algorithm], &location.local_path)
+            .await?
+            .remove(algorithm.name())
+            .unwrap_or_default(),
+    };
+    let file_name = location
+        .local_path
+        .file_name()
+        .ok_or_else(|| anyhow!("path has no file name"))?
+        .to_string_lossy()
+        .to_string();
+    let sidecar = location.local_path.with_file_name(format!(
+        "{}.{}",
+        file_name,
+        algorithm.name()
+    ));
+    fs::write(&sidecar, format!("{}  {}\n", digest, file_name)).await?;
+    Ok(())
+}
+
+async fn run_command(
+    command: &[String],
+    timeout: Duration,
+    rule: &PipelineRule,
+    upload: &CompletedUpload,
+    location: &Location,
+) -> anyhow::Result<()> {
+    let local_path = location.local_path.to_string_lossy();
+    let args: Vec<String> = command
+        .iter()
+        .map(|arg| {
+            arg.replace("{path}", &local_path)
+                .replace("{virtual_path}", &location.virtual_path)
+                .replace("{username}", &upload.username)
+                .replace("{rule}", &rule.name)
+        })
+        .collect();
+    run_program(&args, timeout).await
+}
+
+// Run a program with its arguments, failing when it cannot be started,
+// runs past `timeout` or exits with a non-zero status; the end of its
+// stderr is kept in the error
+pub async fn run_program(
+    args: &[String],
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    let (program, args) =
+        args.split_first().ok_or_else(|| anyhow!("empty command"))?;
+
+    let child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("cannot run {}", program))?;
+
+    let output =
+        match tokio::time::timeout(timeout, child.wait_with_output()).await {
+            Ok(output) => output?,
+            Err(_) => bail!("{} timed out after {:?}", program, timeout),
+        };
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stderr = stderr.trim();
+        let start =
+            stderr.char_indices().rev().nth(STDERR_TAIL).map_or(0, |(i, _)| i);
+        bail!(
+            "{} exited with {}: {}",
+            program,
+            output.status,
+            &stderr[start..]
+        );
+    }
+    if !output.stderr.is_empty() {
+        warn!(
+            "{} wrote to stderr: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,251 @@
+use crate::config::settings::{PortMappingProtocol, PortMappingSettings};
+use crate::models::sftp::{PortMapping, SftpState};
+use anyhow::{Context, bail};
+use chrono::{TimeDelta, Utc};
+use igd_next::SearchOptions;
+use igd_next::aio::tokio::search_gateway;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+const NAT_PMP_PORT: u16 = 5351;
+// Wait before trying again after a failed request
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+const DESCRIPTION: &str = "sftp-manager";
+
+// Keeps a TCP port mapping for the default SFTP listener on the local
+// router, over UPnP IGD or NAT-PMP, so home deployments behind NAT are
+// reachable without router setup. Mappings left behind when the listen
+// port changes or the server stops expire with their lease.
+pub struct PortMapper {
+    settings: PortMappingSettings,
+    state: SftpState,
+    mapping: RwLock<Option<PortMapping>>,
+}
+
+impl PortMapper {
+    pub fn new(settings: PortMappingSettings, state: SftpState) -> Self {
+        Self { settings, state, mapping: RwLock::new(None) }
+    }
+
+    // Request the mapping, then renew it halfway through each lease
+    pub fn start(self: &Arc<Self>) -> JoinHandle<()> {
+        let mapper = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let port = mapper.state.listen_address().await.port;
+                let wait = match mapper.map(port).await {
+                    Ok(mapping) => {
+                        let previous = mapper.mapping.read().await.clone();
+                        if previous.is_none_or(|p| {
+                            p.external_ip != mapping.external_ip
+                                || p.external_port != mapping.external_port
+                        }) {
+                            info!(
+                                "Port {} mapped to {}:{} over {}",
+                                port,
+                                mapping.external_ip,
+                                mapping.external_port,
+                                mapping.protocol
+                            );
+                        }
+                        // The router may grant a shorter lease
+                        let lease = (mapping.expires_at - Utc::now())
+                            .num_seconds()
+                            .max(RETRY_DELAY.as_secs() as i64);
+                        *mapper.mapping.write().await = Some(mapping);
+                        Duration::from_secs(lease as u64 / 2)
+                    }
+                    Err(e) => {
+                        warn!("Failed to map port {}: {:#}", port, e);
+                        *mapper.mapping.write().await = None;
+                        RETRY_DELAY
+                    }
+                };
+                tokio::time::sleep(wait).await;
+            }
+        })
+    }
+
+    // The mapping currently held, if any
+    pub async fn current(&self) -> Option<PortMapping> {
+        self.mapping.read().await.clone()
+    }
+
+    async fn map(&self, port: u16) -> anyhow::Result<PortMapping> {
+        let requested = self.settings.external_port.unwrap_or(port);
+        let lease = self.settings.lease_secs;
+        let (protocol, external_ip, external_port, lease) =
+            match self.settings.protocol {
+                PortMappingProtocol::Upnp => {
+                    let (ip, port) = map_upnp(port, requested, lease).await?;
+                    ("upnp", ip, port, lease)
+                }
+                PortMappingProtocol::NatPmp => {
+                    let gateway = match &self.settings.gateway {
+                        Some(gateway) => gateway.parse()?,
+                        None => default_gateway()
+                            .context("no default gateway, set the gateway")?,
+                    };
+                    let (ip, port, lease) =
+                        map_nat_pmp(gateway, port, requested, lease).await?;
+                    ("natpmp", ip, port, lease)
+                }
+            };
+        Ok(PortMapping {
+            protocol,
+            external_ip: external_ip.to_string(),
+            external_port,
+            internal_port: port,
+            expires_at: Utc::now() + TimeDelta::seconds(lease as i64),
+        })
+    }
+}
+
+async fn map_upnp(
+    port: u16,
+    external_port: u16,
+    lease: u32,
+) -> anyhow::Result<(IpAddr, u16)> {
+    let mut options = SearchOptions::default();
+    options.timeout = Some(Duration::from_secs(5));
+    let gateway =
+        search_gateway(options).await.context("no UPnP gateway found")?;
+    let local_ip = local_ip_towards(gateway.addr).await?;
+    gateway
+        .add_port(
+            igd_next::PortMappingProtocol::TCP,
+            external_port,
+            SocketAddr::new(local_ip, port),
+            lease,
+            DESCRIPTION,
+        )
+        .await
+        .context("gateway refused the mapping")?;
+    let external_ip = gateway
+        .get_external_ip()
+        .await
+        .context("cannot read the external address")?;
+    Ok((external_ip, external_port))
+}
+
+// Address of this host on the route to `peer`
+async fn local_ip_towards(peer: SocketAddr) -> anyhow::Result<IpAddr> {
+    let bind: SocketAddr = match peer {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => "[::]:0".parse()?,
+    };
+    let socket = UdpSocket::bind(bind).await?;
+    socket.connect(peer).await?;
+    Ok(socket.local_addr()?.ip())
+}
+
+// Map a TCP port over NAT-PMP (RFC 6886). Returns the external address,
+// the port and the lease granted by the router.
+async fn map_nat_pmp(
+    gateway: Ipv4Addr,
+    port: u16,
+    external_port: u16,
+    lease: u32,
+) -> anyhow::Result<(IpAddr, u16, u32)> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.connect((gateway, NAT_PMP_PORT)).await?;
+
+    let response = nat_pmp_request(&socket, &[0, 0]).await?;
+    let address = parse_nat_pmp(&response, 0, 12)?;
+    let external_ip =
+        Ipv4Addr::new(address[8], address[9], address[10], address[11]);
+
+    let mut request = vec![0, 2, 0, 0];
+    request.extend_from_slice(&port.to_be_bytes());
+    request.extend_from_slice(&external_port.to_be_bytes());
+    request.extend_from_slice(&lease.to_be_bytes());
+    let response = nat_pmp_request(&socket, &request).await?;
+    let mapping = parse_nat_pmp(&response, 2, 16)?;
+    let mapped_port = u16::from_be_bytes([mapping[10], mapping[11]]);
+    let granted = u32::from_be_bytes([
+        mapping[12],
+        mapping[13],
+        mapping[14],
+        mapping[15],
+    ]);
+    Ok((external_ip.into(), mapped_port, granted))
+}
+
+// Send a request, retrying with a doubling timeout as the RFC suggests
+async fn nat_pmp_request(
+    socket: &UdpSocket,
+    request: &[u8],
+) -> anyhow::Result<Vec<u8>> {
+    let mut wait = Duration::from_millis(250);
+    let mut buffer = [0u8; 16];
+    for _ in 0..5 {
+        socket.send(request).await?;
+        match tokio::time::timeout(wait, socket.recv(&mut buffer)).await {
+            Ok(received) => return Ok(buffer[..received?].to_vec()),
+            Err(_) => wait *= 2,
+        }
+    }
+    bail!("no answer from the NAT-PMP gateway")
+}
+
+// Check a response to the request with `opcode`, returning it when it
+// reports success
+fn parse_nat_pmp(
+    response: &[u8],
+    opcode: u8,
+    len: usize,
+) -> anyhow::Result<&[u8]> {
+    if response.len() < len || response[0] != 0 || response[1] != 128 + opcode {
+        bail!("malformed NAT-PMP response");
+    }
+    match u16::from_be_bytes([response[2], response[3]]) {
+        0 => Ok(response),
+        code => bail!("NAT-PMP gateway refused with result code {}", code),
+    }
+}
+
+fn default_gateway() -> Option<Ipv4Addr> {
+    parse_default_gateway(&std::fs::read_to_string("/proc/net/route").ok()?)
+}
+
+// Gateway of the default route in a Linux routing table, whose addresses
+// are hex in host byte order
+fn parse_default_gateway(table: &str) -> Option<Ipv4Addr> {
+    table.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.get(1) != Some(&"00000000") {
+            return None;
+        }
+        let gateway = u32::from_str_radix(fields.get(2)?, 16).ok()?;
+        (gateway != 0).then(|| Ipv4Addr::from(gateway.to_le_bytes()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nat_pmp_responses_and_default_gateway() {
+        let mapped = [0, 130, 0, 0, 0, 0, 0, 1, 8, 174, 8, 174, 0, 0, 14, 16];
+        assert!(parse_nat_pmp(&mapped, 2, 16).is_ok());
+        assert!(parse_nat_pmp(&mapped, 0, 12).is_err());
+        let refused = [0, 130, 0, 2, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0];
+        assert!(parse_nat_pmp(&refused, 2, 16).is_err());
+        assert!(parse_nat_pmp(&mapped[..8], 2, 16).is_err());
+
+        let table = "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\n\
+                     eth0\t0000A8C0\t00000000\t0001\t0\t0\t0\n\
+                     eth0\t00000000\t0101A8C0\t0003\t0\t0\t0\n";
+        assert_eq!(
+            parse_default_gateway(table),
+            Some(Ipv4Addr::new(192, 168, 1, 1))
+        );
+        assert_eq!(parse_default_gateway(""), None);
+    }
+}
@@ -0,0 +1,303 @@
+use crate::models::accounts::{
+    EraseUserRequest, ErasureReport, UserDataExport,
+};
+use crate::responses::sftp::SftpApiResponse;
+use crate::services::accounts::no_database;
+use crate::services::files::FileService;
+use crate::services::journal::JournalService;
+use crate::services::tenants::TenantService;
+use crate::sftp::recording::remove_recordings;
+use crate::store::{Repository, TransferDirection, UserData};
+use axum::http::StatusCode;
+use chrono::Utc;
+use serde_json::json;
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+
+// Audit action of the tombstone left by an erasure
+const ERASED_ACTION: &str = "user_erased";
+
+// Data-subject requests: export everything kept about a user, or erase it
+// for good. Files are those the user last uploaded, below the root of the
+// user's tenant or the default root.
+pub struct PrivacyService {
+    repository: Option<Arc<dyn Repository>>,
+    files: Arc<FileService>,
+    tenants: Arc<TenantService>,
+    // Where session recordings are kept, whether recording is on or not
+    recordings: PathBuf,
+    journal: Arc<JournalService>,
+}
+
+impl PrivacyService {
+    pub fn new(
+        repository: Option<Arc<dyn Repository>>,
+        files: Arc<FileService>,
+        tenants: Arc<TenantService>,
+        recordings: PathBuf,
+        journal: Arc<JournalService>,
+    ) -> Self {
+        Self { repository, files, tenants, recordings, journal }
+    }
+
+    pub async fn export(
+        &self,
+        username: &str,
+    ) -> SftpApiResponse<UserDataExport> {
+        let Some(repository) = &self.repository else {
+            return no_database();
+        };
+        let data = match repository.user_data(username).await {
+            Ok(data) => data,
+            Err(e) => return internal_error("read user data", e),
+        };
+        let paths = match self.owned_files(repository, username, &data).await {
+            Ok(paths) => paths,
+            Err(e) => return internal_error("read user data", e),
+        };
+        let stored = self.files_of(&data);
+        let mut files = Vec::new();
+        for path in paths {
+            if stored.resolve(&path).await.is_some() {
+                files.push(path);
+            }
+        }
+
+        info!("Exported data of user {}", username);
+        SftpApiResponse::success(UserDataExport {
+            username: username.to_string(),
+            exported_at: Utc::now(),
+            data,
+            files,
+        })
+    }
+
+    // Delete the user's files, session recordings, account, keys and
+    // shares, and strip their audit entries, transfers and event journal
+    // entries down to tombstones. Cannot be undone.
+    pub async fn erase(
+        &self,
+        username: &str,
+        request: EraseUserRequest,
+    ) -> SftpApiResponse<ErasureReport> {
+        let Some(repository) = &self.repository else {
+            return no_database();
+        };
+        if request.confirm != username {
+            return SftpApiResponse::error(
+                StatusCode::BAD_REQUEST,
+                "confirm must repeat the username",
+            );
+        }
+        let data = match repository.user_data(username).await {
+            Ok(data) => data,
+            Err(e) => return internal_error("read user data", e),
+        };
+        let paths = match self.owned_files(repository, username, &data).await {
+            Ok(paths) => paths,
+            Err(e) => return internal_error("read user data", e),
+        };
+
+        // Files first, so a failure leaves the records to retry from
+        let files = match self.files_of(&data).erase(&paths, username).await {
+            Ok(files) => files,
+            Err(e) => return internal_error("erase files", e.into()),
+        };
+        for path in &paths {
+            if let Err(e) =
+                repository.set_file_tags(path, &Default::default()).await
+            {
+                warn!("Failed to remove tags of {}: {}", path, e);
+            }
+        }
+        let recordings =
+            match remove_recordings(&self.recordings, username).await {
+                Ok(recordings) => recordings,
+                Err(e) => return internal_error("erase recordings", e.into()),
+            };
+        let journal_entries = match self.journal.redact_user(username).await {
+            Ok(entries) => entries,
+            Err(e) => return internal_error("redact event journal", e.into()),
+        };
+        let records = match repository.erase_user(username).await {
+            Ok(records) => records,
+            Err(e) => return internal_error("erase user records", e),
+        };
+
+        let erased_at = Utc::now();
+        let tombstone = json!({
+            "reference": request.reference,
+            "erased_at": erased_at,
+            "files": files,
+            "recordings": recordings,
+            "journal_entries": journal_entries,
+            "records": &records,
+        });
+        if let Err(e) =
+            repository.record_audit(ERASED_ACTION, None, tombstone).await
+        {
+            error!("Failed to record erasure tombstone: {}", e);
+        }
+        info!(
+            "Erased data of a user: {} file(s), {} recording(s), {} audit \
+             entries, {} transfers, {} journal entries",
+            files,
+            recordings,
+            records.audit_entries,
+            records.transfers,
+            journal_entries
+        );
+        SftpApiResponse::success(ErasureReport {
+            username: username.to_string(),
+            erased_at,
+            files,
+            recordings,
+            journal_entries,
+            records,
+        })
+    }
+
+    // Paths the user uploaded and nobody has uploaded to since
+    async fn owned_files(
+        &self,
+        repository: &Arc<dyn Repository>,
+        username: &str,
+        data: &UserData,
+    ) -> anyhow::Result<Vec<String>> {
+        let uploaded: BTreeSet<&str> = data
+            .transfers
+            .iter()
+            .filter(|t| t.direction == TransferDirection::Upload)
+            .map(|t| t.path.as_str())
+            .collect();
+        let mut owned = Vec::new();
+        for path in uploaded {
+            let latest = repository.latest_upload(path).await?;
+            if latest.is_some_and(|t| t.username == username) {
+                owned.push(path.to_string());
+            }
+        }
+        Ok(owned)
+    }
+
+    fn files_of(&self, data: &UserData) -> Arc<FileService> {
+        data.user
+            .as_ref()
+            .and_then(|user| user.tenant.as_deref())
+            .and_then(|tenant| self.tenants.files(tenant))
+            .unwrap_or_else(|| self.files.clone())
+    }
+}
+
+fn internal_error<T: serde::Serialize>(
+    action: &str,
+    e: anyhow::Error,
+) -> SftpApiResponse<T> {
+    error!("Failed to {}: {}", action, e);
+    SftpApiResponse::error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Failed to {}", action),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::settings::Settings;
+    use crate::services::journal::EventJournal;
+    use crate::sftp::ServerContext;
+    use crate::store::NewTransfer;
+    use crate::store::sqlite::SqliteRepository;
+    use std::sync::Mutex;
+    use tokio::sync::watch;
+
+    #[tokio::test]
+    async fn test_erasure_leaves_nothing_under_the_username() {
+        let dir = std::env::temp_dir()
+            .join(format!("sftp-manager-privacy-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let (root, recordings, journal_dir) =
+            (dir.join("root"), dir.join("recordings"), dir.join("journal"));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::create_dir_all(&recordings).unwrap();
+        std::fs::create_dir_all(&journal_dir).unwrap();
+
+        let repository = Arc::new(SqliteRepository::in_memory().unwrap());
+        repository.create_user("alice", &[], None).await.unwrap();
+        repository.add_key("alice", "ssh-ed25519 AAAA", None).await.unwrap();
+        std::fs::write(root.join("report.csv"), "a,b").unwrap();
+        repository
+            .record_transfer(NewTransfer {
+                username: "alice".to_string(),
+                peer: Some("192.0.2.1:4000".to_string()),
+                path: "/report.csv".to_string(),
+                direction: TransferDirection::Upload,
+                bytes: 3,
+                duration_ms: 1,
+                checksums: Default::default(),
+            })
+            .await
+            .unwrap();
+        repository
+            .record_audit("login", Some("alice"), json!({"username": "alice"}))
+            .await
+            .unwrap();
+        for name in
+            ["20261017T080000Z-1-alice.jsonl", "20261017T080000Z-2-bob.jsonl"]
+        {
+            std::fs::write(recordings.join(name), "{}\n").unwrap();
+        }
+        std::fs::write(
+            journal_dir.join("journal.log"),
+            "{\"seq\":1,\"type\":\"credentials_rotated\",\
+             \"data\":{\"username\":\"alice\"}}\n",
+        )
+        .unwrap();
+
+        let settings = Settings::default();
+        let (_, settings_rx) = watch::channel(settings.clone());
+        let context = ServerContext::for_tests();
+        let files = Arc::new(FileService::new(
+            root.to_string_lossy().into_owned(),
+            settings_rx.clone(),
+            context.clone(),
+        ));
+        let tenants =
+            Arc::new(TenantService::new(&settings, &context, settings_rx));
+        let journal = EventJournal::open(&journal_dir, 1 << 20, 2).unwrap();
+        let journal =
+            Arc::new(JournalService::new(Some(Arc::new(Mutex::new(journal)))));
+        let privacy = PrivacyService::new(
+            Some(repository.clone()),
+            files,
+            tenants,
+            recordings.clone(),
+            journal,
+        );
+
+        let request =
+            EraseUserRequest { confirm: "alice".to_string(), reference: None };
+        let report = privacy.erase("alice", request).await.sftp.unwrap();
+        assert_eq!((report.files, report.recordings), (1, 1));
+        assert_eq!(report.journal_entries, 1);
+
+        assert!(!root.join("report.csv").exists());
+        let left: Vec<_> = std::fs::read_dir(&recordings)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(left, ["20261017T080000Z-2-bob.jsonl"]);
+        let journal =
+            std::fs::read_to_string(journal_dir.join("journal.log")).unwrap();
+        assert!(!journal.contains("alice"));
+        let data = repository.user_data("alice").await.unwrap();
+        assert!(data.user.is_none() && data.keys.is_empty());
+        assert!(data.audit.is_empty() && data.transfers.is_empty());
+        let audit = repository.list_audit(100, None).await.unwrap();
+        assert!(!serde_json::to_string(&audit).unwrap().contains("alice"));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
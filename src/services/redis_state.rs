@@ -0,0 +1,281 @@
+use crate::models::sftp::SftpState;
+use crate::services::state_store::{
+    BannedIp, PersistedState, StaleRevision, StateBackend,
+};
+use crate::sftp::SessionRegistry;
+use async_trait::async_trait;
+use chrono::Utc;
+use futures_util::StreamExt;
+use redis::AsyncCommands;
+use redis::aio::MultiplexedConnection;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+// How often each replica refreshes its session summary
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+// Summaries of replicas that stopped refreshing disappear after this
+const HEARTBEAT_TTL_SECS: u64 = 15;
+
+// Replaces the state document only when the stored revision is the one
+// before the new document's, so a replica that missed a change cannot
+// write its stale state back. Returns the stored revision on refusal.
+const SAVE_STATE_SCRIPT: &str = r#"
+local stored = redis.call('GET', KEYS[1])
+local revision = 0
+if stored then
+    revision = cjson.decode(stored)['revision'] or 0
+end
+if revision + 1 ~= tonumber(ARGV[1]) then
+    return revision
+end
+redis.call('SET', KEYS[1], ARGV[2])
+return -1
+"#;
+
+// Sessions currently served by one replica
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplicaSummary {
+    pub replica_id: String,
+    pub sessions: usize,
+    pub open_files: usize,
+    pub updated_at: String,
+}
+
+// Keeps the SFTP state in Redis so every replica behind a load balancer
+// serves the same credentials. Changes are announced on a channel so other
+// replicas pick them up immediately. Each banned IP has a key of its own
+// that expires with the ban, so bans never touch the credentials.
+pub struct RedisStateStore {
+    client: redis::Client,
+    conn: MultiplexedConnection,
+    prefix: String,
+    replica_id: String,
+}
+
+impl RedisStateStore {
+    pub async fn connect(
+        url: &str,
+        prefix: &str,
+        replica_id: &str,
+    ) -> anyhow::Result<Self> {
+        let client = redis::Client::open(url)?;
+        let conn = client.get_multiplexed_async_connection().await?;
+        info!("Connected to Redis as replica {}", replica_id);
+
+        Ok(Self {
+            client,
+            conn,
+            prefix: prefix.to_string(),
+            replica_id: replica_id.to_string(),
+        })
+    }
+
+    fn state_key(&self) -> String {
+        format!("{}:state", self.prefix)
+    }
+
+    fn channel(&self) -> String {
+        format!("{}:state:changed", self.prefix)
+    }
+
+    fn ban_key(&self, ip: &str) -> String {
+        format!("{}:ban:{}", self.prefix, ip)
+    }
+
+    fn replica_key(&self, replica_id: &str) -> String {
+        format!("{}:replica:{}", self.prefix, replica_id)
+    }
+
+    // Apply state changes made by other replicas until the task is aborted.
+    // State is read through `backend`, which may wrap this store.
+    pub fn watch(
+        self: Arc<Self>,
+        state: SftpState,
+        backend: Arc<dyn StateBackend>,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = self.follow_changes(&state, &backend).await {
+                    warn!("Lost Redis state subscription: {}", e);
+                }
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        })
+    }
+
+    async fn follow_changes(
+        &self,
+        state: &SftpState,
+        backend: &Arc<dyn StateBackend>,
+    ) -> anyhow::Result<()> {
+        let mut pubsub = self.client.get_async_pubsub().await?;
+        pubsub.subscribe(self.channel()).await?;
+
+        // Changes may have been missed while not subscribed
+        refresh(state, backend).await;
+
+        let mut messages = pubsub.on_message();
+        while let Some(message) = messages.next().await {
+            let origin: String = message.get_payload().unwrap_or_default();
+            if origin == self.replica_id {
+                continue;
+            }
+            debug!("SFTP state changed by replica {}", origin);
+            refresh(state, backend).await;
+        }
+        Ok(())
+    }
+
+    // Periodically publish this replica's session counts
+    pub fn heartbeat(
+        self: Arc<Self>,
+        sessions: SessionRegistry,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+            loop {
+                interval.tick().await;
+
+                let summary = ReplicaSummary {
+                    replica_id: self.replica_id.clone(),
+                    sessions: sessions.count(),
+                    open_files: sessions.open_files(),
+                    updated_at: Utc::now().to_rfc3339(),
+                };
+                let Ok(body) = serde_json::to_string(&summary) else {
+                    continue;
+                };
+
+                let mut conn = self.conn.clone();
+                let result: redis::RedisResult<()> = conn
+                    .set_ex(
+                        self.replica_key(&self.replica_id),
+                        body,
+                        HEARTBEAT_TTL_SECS,
+                    )
+                    .await;
+                if let Err(e) = result {
+                    warn!("Failed to publish replica heartbeat: {}", e);
+                }
+            }
+        })
+    }
+
+    // Replicas that sent a heartbeat recently
+    pub async fn replicas(&self) -> anyhow::Result<Vec<ReplicaSummary>> {
+        let mut conn = self.conn.clone();
+        let keys: Vec<String> = {
+            let iter: redis::AsyncIter<String> =
+                conn.scan_match(self.replica_key("*")).await?;
+            iter.filter_map(|key| async move { key.ok() }).collect().await
+        };
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let values: Vec<Option<String>> = conn.mget(&keys).await?;
+        let mut replicas: Vec<ReplicaSummary> = values
+            .into_iter()
+            .flatten()
+            .filter_map(|v| serde_json::from_str(&v).ok())
+            .collect();
+        replicas.sort_by(|a, b| a.replica_id.cmp(&b.replica_id));
+        Ok(replicas)
+    }
+
+    // Bans of every replica that have not expired yet
+    async fn load_bans(&self) -> anyhow::Result<Vec<BannedIp>> {
+        let mut conn = self.conn.clone();
+        let keys: Vec<String> = {
+            let iter: redis::AsyncIter<String> =
+                conn.scan_match(self.ban_key("*")).await?;
+            iter.filter_map(|key| async move { key.ok() }).collect().await
+        };
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // Keys that expired since the scan come back empty
+        let values: Vec<Option<String>> = conn.mget(&keys).await?;
+        Ok(values
+            .into_iter()
+            .flatten()
+            .filter_map(|v| serde_json::from_str(&v).ok())
+            .collect())
+    }
+}
+
+async fn refresh(state: &SftpState, backend: &Arc<dyn StateBackend>) {
+    match backend.load().await {
+        Ok(Some(persisted)) => state.apply_persisted(&persisted).await,
+        Ok(None) => {}
+        Err(e) => error!("Failed to read SFTP state from Redis: {}", e),
+    }
+}
+
+#[async_trait]
+impl StateBackend for RedisStateStore {
+    fn describe(&self) -> String {
+        format!("Redis key {}", self.state_key())
+    }
+
+    async fn load(&self) -> anyhow::Result<Option<PersistedState>> {
+        let mut conn = self.conn.clone();
+        let body: Option<String> = conn.get(self.state_key()).await?;
+        let state = body
+            .map(|b| PersistedState::from_json(b.as_bytes()))
+            .transpose()?;
+
+        let bans = self.load_bans().await?;
+        if state.is_none() && bans.is_empty() {
+            return Ok(None);
+        }
+        let mut state = state.unwrap_or_default();
+        state.bans.extend(bans);
+        Ok(Some(state))
+    }
+
+    async fn save(&self, state: &PersistedState) -> anyhow::Result<()> {
+        // Bans live in keys of their own, see save_bans
+        let body = serde_json::to_string(&PersistedState {
+            bans: Vec::new(),
+            ..state.clone()
+        })?;
+        let mut conn = self.conn.clone();
+        let stored: i64 = redis::Script::new(SAVE_STATE_SCRIPT)
+            .key(self.state_key())
+            .arg(state.revision)
+            .arg(body)
+            .invoke_async(&mut conn)
+            .await?;
+        if stored >= 0 {
+            return Err(StaleRevision { revision: state.revision }.into());
+        }
+        let _: () = conn.publish(self.channel(), &self.replica_id).await?;
+        Ok(())
+    }
+
+    async fn save_bans(&self, bans: &[BannedIp]) -> anyhow::Result<()> {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut pipe = redis::pipe();
+        for ban in bans.iter().filter(|ban| ban.until > now) {
+            pipe.set_ex(
+                self.ban_key(&ban.ip.to_string()),
+                serde_json::to_string(ban)?,
+                ban.until - now,
+            )
+            .ignore();
+        }
+
+        let mut conn = self.conn.clone();
+        let _: () = pipe.query_async(&mut conn).await?;
+        let _: () = conn.publish(self.channel(), &self.replica_id).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,257 @@
+use crate::config::settings::{RetentionAction, RetentionRule, Settings};
+use crate::events::{Event, EventBus};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+// A file found below a rule's directory
+struct Candidate {
+    path: PathBuf,
+    bytes: u64,
+    modified: SystemTime,
+}
+
+// Why a file is removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Reason {
+    Age,
+    FileCount,
+    TotalBytes,
+}
+
+impl Reason {
+    fn as_str(self) -> &'static str {
+        match self {
+            Reason::Age => "max_age",
+            Reason::FileCount => "max_files",
+            Reason::TotalBytes => "max_total_bytes",
+        }
+    }
+}
+
+// Enforces the retention rules on the SFTP root at a fixed interval.
+// Rules are re-read from the settings before every run.
+pub struct RetentionService {
+    root_dir: PathBuf,
+    settings: watch::Receiver<Settings>,
+    events: EventBus,
+}
+
+impl RetentionService {
+    pub fn new(
+        root_dir: String,
+        settings: watch::Receiver<Settings>,
+        events: EventBus,
+    ) -> Self {
+        Self { root_dir: PathBuf::from(root_dir), settings, events }
+    }
+
+    pub fn start(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                let retention = self.settings.borrow().retention.clone();
+                for rule in &retention.rules {
+                    self.apply(rule, retention.dry_run).await;
+                }
+                let interval = Duration::from_secs(retention.interval_secs);
+                tokio::time::sleep(interval).await;
+            }
+        })
+    }
+
+    async fn apply(&self, rule: &RetentionRule, dry_run: bool) {
+        let dir = self.root_dir.join(rule.path.trim_start_matches('/'));
+        let root = self.root_dir.clone();
+        let task_rule = rule.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let files = collect_files(&dir)?;
+            let selected = select(files, &task_rule, SystemTime::now());
+            let mut removed = Vec::new();
+            for (file, reason) in selected {
+                if !dry_run
+                    && let Err(e) = remove(&root, &file.path, &task_rule)
+                {
+                    error!("Failed to remove {}: {}", file.path.display(), e);
+                    continue;
+                }
+                removed.push((file, reason));
+            }
+            Ok::<_, io::Error>(removed)
+        })
+        .await;
+
+        let removed = match result {
+            Ok(Ok(removed)) => removed,
+            Ok(Err(e)) => {
+                warn!("Retention rule for {} failed: {}", rule.path, e);
+                return;
+            }
+            Err(e) => {
+                error!("Retention task failed: {}", e);
+                return;
+            }
+        };
+        if removed.is_empty() {
+            debug!("Retention rule for {}: nothing to remove", rule.path);
+            return;
+        }
+
+        let action = match rule.action {
+            RetentionAction::Delete => "deleted",
+            RetentionAction::Archive => "archived",
+        };
+        info!(
+            "Retention rule for {} {} {} file(s){}",
+            rule.path,
+            action,
+            removed.len(),
+            if dry_run { " (dry run)" } else { "" }
+        );
+        for (file, reason) in removed {
+            let path = virtual_path(&self.root_dir, &file.path);
+            self.events.publish(Event::FileExpired {
+                path,
+                bytes: file.bytes,
+                action: action.to_string(),
+                reason: reason.as_str().to_string(),
+                dry_run,
+            });
+        }
+    }
+}
+
+// Regular files below a directory, at any depth
+fn collect_files(dir: &Path) -> io::Result<Vec<Candidate>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let entry = entry?;
+            // Symlinks are not followed
+            let metadata = entry.path().symlink_metadata()?;
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else if metadata.is_file() {
+                files.push(Candidate {
+                    path: entry.path(),
+                    bytes: metadata.len(),
+                    modified: metadata.modified()?,
+                });
+            }
+        }
+    }
+    Ok(files)
+}
+
+// Files to remove: everything older than the maximum age, then the oldest
+// of the rest until the count and size limits are met
+fn select(
+    mut files: Vec<Candidate>,
+    rule: &RetentionRule,
+    now: SystemTime,
+) -> Vec<(Candidate, Reason)> {
+    files.sort_by_key(|f| f.modified);
+    let max_age = Duration::from_secs(rule.max_age_days * 24 * 60 * 60);
+
+    let mut count = files.len();
+    let mut total: u64 = files.iter().map(|f| f.bytes).sum();
+    let mut selected = Vec::new();
+    for file in files {
+        let age = now.duration_since(file.modified).unwrap_or_default();
+        let reason = if rule.max_age_days > 0 && age > max_age {
+            Reason::Age
+        } else if rule.max_files > 0 && count > rule.max_files {
+            Reason::FileCount
+        } else if rule.max_total_bytes > 0 && total > rule.max_total_bytes {
+            Reason::TotalBytes
+        } else {
+            continue;
+        };
+        count -= 1;
+        total -= file.bytes;
+        selected.push((file, reason));
+    }
+    selected
+}
+
+fn remove(root: &Path, path: &Path, rule: &RetentionRule) -> io::Result<()> {
+    match rule.action {
+        RetentionAction::Delete => fs::remove_file(path),
+        RetentionAction::Archive => {
+            // Keep the path below the root inside the archive
+            let relative = path.strip_prefix(root).unwrap_or(path);
+            let target = Path::new(&rule.archive_dir).join(relative);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            if fs::rename(path, &target).is_err() {
+                fs::copy(path, &target)?;
+                fs::remove_file(path)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn virtual_path(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    format!("/{}", relative.to_string_lossy())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(
+        name: &str,
+        bytes: u64,
+        age_days: u64,
+        now: SystemTime,
+    ) -> Candidate {
+        Candidate {
+            path: PathBuf::from(name),
+            bytes,
+            modified: now - Duration::from_secs(age_days * 24 * 60 * 60),
+        }
+    }
+
+    #[test]
+    fn test_select_oldest_first() {
+        let now = SystemTime::now();
+        let rule = RetentionRule {
+            path: "/".to_string(),
+            max_age_days: 30,
+            max_total_bytes: 250,
+            max_files: 0,
+            action: RetentionAction::Delete,
+            archive_dir: String::new(),
+        };
+        let files = vec![
+            file("new", 100, 1, now),
+            file("ancient", 10, 90, now),
+            file("old", 100, 10, now),
+            file("mid", 100, 5, now),
+        ];
+
+        let selected: Vec<(String, Reason)> = select(files, &rule, now)
+            .into_iter()
+            .map(|(f, r)| (f.path.to_string_lossy().to_string(), r))
+            .collect();
+        assert_eq!(
+            selected,
+            vec![
+                ("ancient".to_string(), Reason::Age),
+                ("old".to_string(), Reason::TotalBytes),
+            ]
+        );
+    }
+}
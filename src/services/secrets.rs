@@ -0,0 +1,202 @@
+use crate::config::settings::SecretsSettings;
+use crate::models::sftp::SftpCredentials;
+use crate::services::state_store::{BannedIp, PersistedState, StateBackend};
+use async_trait::async_trait;
+use chacha20poly1305::XChaCha20Poly1305;
+use chacha20poly1305::XNonce;
+use chacha20poly1305::aead::{Aead, Generate, KeyInit};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use zeroize::Zeroizing;
+
+// Prefix of sealed values; bump when the format changes
+const SEALED_PREFIX: &str = "v1";
+const NONCE_LEN: usize = 24;
+
+// Encrypts secrets with a 256-bit key before they are persisted
+#[derive(Clone)]
+pub struct SecretCipher {
+    cipher: XChaCha20Poly1305,
+    key_id: String,
+}
+
+impl SecretCipher {
+    // Key given as 64 hex characters
+    pub fn from_hex(key: &str) -> anyhow::Result<Self> {
+        let bytes =
+            Zeroizing::new(hex::decode(key.trim()).map_err(|e| {
+                anyhow::anyhow!("secret key is not hex: {}", e)
+            })?);
+        if bytes.len() != 32 {
+            anyhow::bail!(
+                "secret key must be 32 bytes (64 hex characters), got {}",
+                bytes.len()
+            );
+        }
+
+        let cipher = XChaCha20Poly1305::new_from_slice(&bytes)
+            .map_err(|e| anyhow::anyhow!("invalid secret key: {}", e))?;
+        // Identifies which key sealed a value without revealing it
+        let key_id = hex::encode(&Sha256::digest(&bytes)[..4]);
+        Ok(Self { cipher, key_id })
+    }
+
+    // Load the key named by the settings: a key file wins over the
+    // environment variable. `None` means secrets stay unencrypted.
+    pub fn from_settings(
+        settings: &SecretsSettings,
+    ) -> anyhow::Result<Option<Self>> {
+        if !settings.key_file.is_empty() {
+            let key = Zeroizing::new(
+                std::fs::read_to_string(&settings.key_file).map_err(|e| {
+                    anyhow::anyhow!("cannot read {}: {}", settings.key_file, e)
+                })?,
+            );
+            return Self::from_hex(&key).map(Some);
+        }
+        match std::env::var(&settings.key_env).map(Zeroizing::new) {
+            Ok(key) if !key.trim().is_empty() => Self::from_hex(&key).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    // Encrypt into "v1:<key id>:<hex nonce and ciphertext>"
+    pub fn seal(&self, plaintext: &[u8]) -> anyhow::Result<String> {
+        let nonce = XNonce::generate();
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| anyhow::anyhow!("encryption failed"))?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+        Ok(format!("{}:{}:{}", SEALED_PREFIX, self.key_id, hex::encode(sealed)))
+    }
+
+    pub fn open(&self, sealed: &str) -> anyhow::Result<Vec<u8>> {
+        let mut parts = sealed.splitn(3, ':');
+        let (Some(SEALED_PREFIX), Some(key_id), Some(body)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            anyhow::bail!("unrecognised sealed value");
+        };
+        if key_id != self.key_id {
+            anyhow::bail!(
+                "value was sealed with key {}, current key is {}",
+                key_id,
+                self.key_id
+            );
+        }
+
+        let bytes = hex::decode(body)?;
+        if bytes.len() < NONCE_LEN {
+            anyhow::bail!("sealed value is truncated");
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let nonce = XNonce::try_from(nonce)
+            .map_err(|_| anyhow::anyhow!("invalid nonce"))?;
+        self.cipher
+            .decrypt(&nonce, ciphertext)
+            .map_err(|_| anyhow::anyhow!("decryption failed"))
+    }
+}
+
+// Wraps a state backend so credentials are only ever stored encrypted.
+// Plaintext credentials written before encryption was enabled are still
+// read and get encrypted on the next save.
+pub struct EncryptedStateBackend {
+    inner: Arc<dyn StateBackend>,
+    cipher: SecretCipher,
+}
+
+impl EncryptedStateBackend {
+    pub fn new(inner: Arc<dyn StateBackend>, cipher: SecretCipher) -> Self {
+        Self { inner, cipher }
+    }
+}
+
+#[async_trait]
+impl StateBackend for EncryptedStateBackend {
+    fn describe(&self) -> String {
+        format!(
+            "{} (encrypted, key {})",
+            self.inner.describe(),
+            self.cipher.key_id()
+        )
+    }
+
+    async fn load(&self) -> anyhow::Result<Option<PersistedState>> {
+        let Some(mut state) = self.inner.load().await? else {
+            return Ok(None);
+        };
+        if let Some(sealed) = state.sealed_credentials.take() {
+            let plaintext = Zeroizing::new(self.cipher.open(&sealed)?);
+            let credentials: SftpCredentials =
+                serde_json::from_slice(&plaintext)?;
+            state.credentials = Some(credentials);
+        }
+        Ok(Some(state))
+    }
+
+    async fn save(&self, state: &PersistedState) -> anyhow::Result<()> {
+        let mut sealed = state.clone();
+        if let Some(credentials) = sealed.credentials.take() {
+            let plaintext = Zeroizing::new(serde_json::to_vec(&credentials)?);
+            sealed.sealed_credentials = Some(self.cipher.seal(&plaintext)?);
+        }
+        self.inner.save(&sealed).await
+    }
+
+    // Bans hold no secrets; passing them through keeps the inner store
+    // from rewriting the sealed credentials along with them
+    async fn save_bans(&self, bans: &[BannedIp]) -> anyhow::Result<()> {
+        self.inner.save_bans(bans).await
+    }
+}
+
+// Re-encrypt the persisted state with a new key
+pub async fn rekey(
+    inner: Arc<dyn StateBackend>,
+    old: Option<SecretCipher>,
+    new: SecretCipher,
+) -> anyhow::Result<()> {
+    let state = match old {
+        Some(old) => {
+            EncryptedStateBackend::new(inner.clone(), old).load().await?
+        }
+        None => inner.load().await?,
+    };
+    let Some(mut state) = state else {
+        anyhow::bail!("no persisted state found at {}", inner.describe());
+    };
+    if state.sealed_credentials.is_some() {
+        anyhow::bail!("persisted state is encrypted but no current key is set");
+    }
+
+    state.revision += 1;
+    EncryptedStateBackend::new(inner, new).save(&state).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str =
+        "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+    #[test]
+    fn test_seal_roundtrip_and_wrong_key() {
+        let cipher = SecretCipher::from_hex(KEY).unwrap();
+        let sealed = cipher.seal(b"hunter2").unwrap();
+        assert!(!sealed.contains("hunter2"));
+        assert_eq!(cipher.open(&sealed).unwrap(), b"hunter2");
+
+        let other = SecretCipher::from_hex(&KEY.replace("00", "ff")).unwrap();
+        assert!(other.open(&sealed).is_err());
+        assert!(SecretCipher::from_hex("abcd").is_err());
+    }
+}
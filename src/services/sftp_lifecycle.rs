@@ -1,123 +1,514 @@
-use crate::models::sftp::SftpState;
-use std::time::Duration;
+use crate::config::settings::Settings;
+use crate::error::{Error, Result};
+use crate::events::Event;
+use crate::ftps::{FtpsConfig, run_ftps_server, tls_acceptor};
+use crate::models::sftp::{
+    DrainState, ListenAddress, ServerFailure, SftpState,
+};
+use crate::sftp::ServerContext;
+use chrono::{DateTime, Utc};
+use std::time::{Duration, SystemTime};
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, watch};
 use tokio::task::JoinHandle;
-use tokio::time::interval;
+use tokio::time::{Instant, interval, interval_at};
 use tracing::{error, info, warn};
 
+// A server that stops sooner than this after starting still counts as
+// failing, so its restarts keep backing off
+const STABLE_UPTIME: Duration = Duration::from_secs(60);
+
+// Expiry warnings already emitted for the current expiration time
+#[derive(Default)]
+struct ExpiryWarnings {
+    expiration: Option<SystemTime>,
+    sent: Vec<u64>,
+}
+
+// A running SFTP server and the switches controlling its listener
+struct ServerTask {
+    task: JoinHandle<Result<()>>,
+    accepting: watch::Sender<bool>,
+    // Hands the accept loop a listener bound to a new address
+    rebind: mpsc::Sender<TcpListener>,
+    // Address the listener is bound to
+    address: ListenAddress,
+    // FTPS server following the same accepting switch, when enabled
+    ftps: Option<JoinHandle<()>>,
+    started: Instant,
+    // Failed attempts before this start, carried over if it dies early
+    earlier_failures: u32,
+}
+
+impl ServerTask {
+    // Failed attempts to count before the server's exit
+    fn failures_before_exit(&self) -> u32 {
+        if self.started.elapsed() < STABLE_UPTIME {
+            self.earlier_failures
+        } else {
+            0
+        }
+    }
+}
+
+// Controls a running lifecycle manager
+pub struct SftpLifecycleHandle {
+    shutdown: watch::Sender<bool>,
+    task: JoinHandle<()>,
+}
+
+impl SftpLifecycleHandle {
+    // Stop the SFTP server, disconnect clients and end the manager
+    pub async fn shutdown(self) {
+        let _ = self.shutdown.send(true);
+        if let Err(e) = self.task.await {
+            error!("SFTP lifecycle manager failed: {}", e);
+        }
+    }
+}
+
 // SFTP lifecycle manager
+// Reacts to state changes as soon as they are signalled; the periodic
+// check only covers time-based transitions.
 // Handles:
 // - Starting the SFTP server when enabled
 // - Stopping the server when disabled
 // - Checking for credential expiration
+// - Warning ahead of credential expiration
 // - Auto-disabling on expiration
+// - Retrying failed starts with exponential backoff
+// - Draining connections ahead of maintenance
+// - Moving the listener when the listen address changes
+// - Disconnecting users whose access hours have ended
 pub struct SftpLifecycleManager {
     state: SftpState,
-    bind_address: String,
-    port: u16,
     root_directory: String,
-    check_interval_secs: u64,
+    context: ServerContext,
+    // Live settings, updated on configuration reload
+    settings: watch::Receiver<Settings>,
+    // Socket passed by systemd, used instead of binding the listen address
+    activated: Option<std::net::TcpListener>,
 }
 
 impl SftpLifecycleManager {
     // Create a new lifecycle manager
     pub fn new(
         state: SftpState,
-        bind_address: String,
-        port: u16,
         root_directory: String,
+        context: ServerContext,
+        settings: watch::Receiver<Settings>,
     ) -> Self {
-        Self {
-            state,
-            bind_address,
-            port,
-            root_directory,
-            check_interval_secs: 10, // Check every 10 seconds
-        }
+        Self { state, root_directory, context, settings, activated: None }
+    }
+
+    // Serve on a socket passed by systemd. It stays open while the server
+    // is stopped; the state's listen address should be fixed to it.
+    pub fn with_listener(mut self, listener: std::net::TcpListener) -> Self {
+        self.activated = Some(listener);
+        self
     }
 
     // Start the lifecycle management task
-    // Returns a JoinHandle that can be used to stop the manager
-    pub fn start(self) -> JoinHandle<()> {
-        tokio::spawn(async move {
-            self.run().await;
-        })
+    // Returns a handle that can be used to stop the manager
+    pub fn start(self) -> SftpLifecycleHandle {
+        let (shutdown, shutdown_rx) = watch::channel(false);
+        let task = tokio::spawn(async move {
+            self.run(shutdown_rx).await;
+        });
+        SftpLifecycleHandle { shutdown, task }
     }
 
     // Main lifecycle loop
-    async fn run(self) {
+    async fn run(self, mut shutdown: watch::Receiver<bool>) {
         info!("SFTP lifecycle manager started");
 
-        let mut check_interval =
-            interval(Duration::from_secs(self.check_interval_secs));
-        let mut server_task: Option<JoinHandle<()>> = None;
+        let mut check_interval = interval(self.check_interval());
+        let mut server_task: Option<ServerTask> = None;
+        let mut expiry_warnings = ExpiryWarnings::default();
 
         loop {
-            // Wait for the next check
-            check_interval.tick().await;
+            let retry_at =
+                self.state.get_failure().await.and_then(|f| f.retry_at);
+
+            // Follow a reloaded check interval
+            let period = self.check_interval();
+            if check_interval.period() != period {
+                check_interval = interval_at(Instant::now() + period, period);
+            }
+
+            // Wait for a state change, a retry or the next periodic check
+            tokio::select! {
+                _ = check_interval.tick() => {}
+                _ = self.state.changed() => {}
+                _ = sleep_until(retry_at) => {}
+                reason = server_exit(&mut server_task) => {
+                    let earlier = server_task
+                        .take()
+                        .map_or(0, |server| server.failures_before_exit());
+                    self.state.set_running(false).await;
+                    error!("❌ SFTP server stopped unexpectedly: {}", reason);
+                    self.record_failure(&reason, earlier).await;
+                }
+                _ = shutdown.changed() => {
+                    info!("Shutting down SFTP lifecycle manager");
+                    self.stop_server(
+                        server_task.take(),
+                        "Server is shutting down",
+                        self.stop_timeout(),
+                    )
+                    .await;
+                    return;
+                }
+            }
 
             // Check for expiration first
             if self.state.is_expired().await {
                 warn!("SFTP credentials expired, disabling");
+                let username =
+                    self.state.get_credentials().await.map(|c| c.username);
                 self.state.disable().await;
+                self.context
+                    .events
+                    .publish(Event::CredentialsExpired { username });
+            }
+            self.warn_expiring(&mut expiry_warnings).await;
+            if server_task.is_some() {
+                self.context.enforce_access_hours().await;
             }
 
-            let is_enabled = self.state.is_enabled().await;
+            // An unfinished drain takes precedence over normal reconciliation
+            if let Some(drain) = self.state.get_drain().await
+                && drain.completed_at.is_none()
+            {
+                self.drain_step(&mut server_task, drain).await;
+                continue;
+            }
+
+            let should_run = self.state.should_run().await;
             let is_running = server_task.is_some();
 
-            match (is_enabled, is_running) {
+            // Failures only matter while the server is meant to run
+            if !should_run && self.state.get_failure().await.is_some() {
+                self.state.set_failure(None).await;
+            }
+
+            match (should_run, is_running) {
                 (true, false) => {
-                    // Should be running but isn't - start it
-                    info!("Starting SFTP server on port {}", self.port);
+                    // Should be running but isn't - start it, unless a
+                    // previous failure asks to wait
+                    if let Some(failure) = self.state.get_failure().await
+                        && failure
+                            .retry_at
+                            .is_none_or(|at| SystemTime::now() < at)
+                    {
+                        continue;
+                    }
 
+                    let earlier = self
+                        .state
+                        .get_failure()
+                        .await
+                        .map_or(0, |f| f.attempts);
                     match self.start_server().await {
-                        Ok(task) => {
+                        Ok(mut task) => {
+                            task.earlier_failures = earlier;
                             server_task = Some(task);
+                            self.state.set_running(true).await;
+                            self.state.set_failure(None).await;
                             info!("✅ SFTP server started successfully");
                         }
                         Err(e) => {
                             error!("❌ Failed to start SFTP server: {}", e);
-                            // Disable on failure to prevent continuous restart attempts
-                            self.state.disable().await;
+                            self.record_failure(&e, earlier).await;
                         }
                     }
                 }
                 (false, true) => {
                     // Should not be running but is - stop it
                     info!("Stopping SFTP server");
-
-                    if let Some(task) = server_task.take() {
-                        task.abort();
-                        info!("✅ SFTP server stopped");
+                    self.stop_server(
+                        server_task.take(),
+                        "SFTP server stopped",
+                        self.stop_timeout(),
+                    )
+                    .await;
+                    info!("✅ SFTP server stopped");
+                }
+                (true, true) => {
+                    let Some(server) = server_task.as_mut() else {
+                        continue;
+                    };
+                    // Resume accepting if a drain was cancelled
+                    if server.accepting.send_if_modified(|accepting| {
+                        !std::mem::replace(accepting, true)
+                    }) {
+                        info!("SFTP listener accepting connections again");
                     }
+                    self.follow_listen_address(server).await;
                 }
-                _ => {
+                (false, false) => {
                     // State is consistent, do nothing
                 }
             }
         }
     }
 
-    // Start the actual SFTP server
-    async fn start_server(
+    // Move the listener if the listen address changed: bind the new
+    // address first, then swap it in. Sessions accepted on the old address
+    // keep running until they disconnect.
+    async fn follow_listen_address(&self, server: &mut ServerTask) {
+        let listen = self.state.listen_address().await;
+        if listen == server.address {
+            return;
+        }
+
+        let listener =
+            match TcpListener::bind((listen.bind_addrs.as_str(), listen.port))
+                .await
+            {
+                Ok(listener) => listener,
+                Err(e) => {
+                    // Keep serving on the old address and retry on the next check
+                    error!("Failed to bind SFTP listener on {}: {}", listen, e);
+                    return;
+                }
+            };
+
+        if server.rebind.send(listener).await.is_ok() {
+            info!("SFTP listener moved from {} to {}", server.address, listen);
+            server.address = listen;
+        }
+    }
+
+    // Advance a drain: close the listener, then stop the server once open
+    // transfers have finished or the deadline has passed
+    async fn drain_step(
         &self,
-    ) -> Result<JoinHandle<()>, Box<dyn std::error::Error + Send + Sync>> {
-        // Get credentials
-        let credentials = self
-            .state
-            .get_credentials()
+        server_task: &mut Option<ServerTask>,
+        drain: DrainState,
+    ) {
+        if let Some(server) = server_task.as_ref()
+            && server.accepting.send_if_modified(|accepting| {
+                std::mem::replace(accepting, false)
+            })
+        {
+            info!("Draining SFTP server, no longer accepting connections");
+        }
+
+        let sessions = &self.context.sessions;
+        let open_files = sessions.open_files();
+        let now = SystemTime::now();
+
+        if open_files > 0 && now < drain.deadline {
+            info!(
+                "Draining: {} session(s), {} open file(s)",
+                sessions.count(),
+                open_files
+            );
+            return;
+        }
+
+        if open_files > 0 {
+            warn!("Drain deadline reached with {} open file(s)", open_files);
+        }
+        // The drain deadline already bounded the wait for transfers
+        self.stop_server(
+            server_task.take(),
+            "Server is going down for maintenance",
+            Duration::ZERO,
+        )
+        .await;
+        self.state
+            .set_drain(Some(DrainState { completed_at: Some(now), ..drain }))
+            .await;
+        info!("✅ SFTP drain complete");
+    }
+
+    // Announce credentials crossing an expiry warning threshold. Several
+    // thresholds crossed at once, e.g. after a restart, give one warning.
+    async fn warn_expiring(&self, warnings: &mut ExpiryWarnings) {
+        let expiration = self.state.current().expiration;
+        if warnings.expiration != expiration {
+            *warnings = ExpiryWarnings { expiration, sent: Vec::new() };
+        }
+        let Some(expiration) = expiration else { return };
+
+        let remaining = expiration
+            .duration_since(SystemTime::now())
+            .unwrap_or_default()
+            .as_secs();
+        let crossed: Vec<u64> = self
+            .settings
+            .borrow()
+            .sftp
+            .expiry_warning_secs
+            .iter()
+            .copied()
+            .filter(|t| remaining <= *t && !warnings.sent.contains(t))
+            .collect();
+        if crossed.is_empty() {
+            return;
+        }
+        warnings.sent.extend(crossed);
+
+        let username = self.state.get_credentials().await.map(|c| c.username);
+        let expires_at = DateTime::<Utc>::from(expiration).to_rfc3339();
+        warn!(
+            "SFTP credentials for {} expire in {}s, at {}",
+            username.as_deref().unwrap_or("unknown user"),
+            remaining,
+            expires_at
+        );
+        self.context.events.publish(Event::CredentialsExpiring {
+            username,
+            expires_at,
+            remaining_secs: remaining,
+        });
+    }
+
+    // Count a failed start after `earlier_failures` others and schedule the
+    // next attempt with exponential backoff, or give up once the attempts
+    // are exhausted
+    async fn record_failure(&self, error: &Error, earlier_failures: u32) {
+        let reason = error.to_string();
+        let attempts = earlier_failures + 1;
+        let (max_attempts, backoff, backoff_max) = {
+            let settings = self.settings.borrow();
+            (
+                settings.sftp.restart_max_attempts,
+                settings.sftp.restart_backoff_secs,
+                settings.sftp.restart_backoff_max_secs,
+            )
+        };
+
+        let now = SystemTime::now();
+        let gave_up = max_attempts > 0 && attempts >= max_attempts;
+        let retry_at = if gave_up {
+            error!(
+                "Giving up on the SFTP server after {} failed attempt(s)",
+                attempts
+            );
+            None
+        } else {
+            let delay = backoff
+                .saturating_mul(1 << (attempts - 1).min(16))
+                .min(backoff_max);
+            warn!("Retrying the SFTP server in {}s", delay);
+            Some(now + Duration::from_secs(delay))
+        };
+
+        self.state
+            .set_failure(Some(ServerFailure {
+                reason: reason.clone(),
+                kind: error.kind(),
+                attempts,
+                failed_at: now,
+                retry_at,
+            }))
+            .await;
+        self.context.events.publish(Event::ServerFailed {
+            reason,
+            attempts,
+            gave_up,
+        });
+    }
+
+    // Expiry, schedule and drain checks run at least this often
+    fn check_interval(&self) -> Duration {
+        Duration::from_secs(self.settings.borrow().sftp.check_interval_secs)
+    }
+
+    fn stop_timeout(&self) -> Duration {
+        Duration::from_secs(self.settings.borrow().sftp.stop_timeout_secs)
+    }
+
+    // Stop accepting, give open transfers up to `timeout` to finish, then
+    // disconnect remaining clients and wait for the server task
+    async fn stop_server(
+        &self,
+        server: Option<ServerTask>,
+        reason: &str,
+        timeout: Duration,
+    ) {
+        let sessions = &self.context.sessions;
+        self.state.set_running(false).await;
+        let Some(server) = server else {
+            sessions.disconnect_all(reason).await;
+            return;
+        };
+
+        let _ = server.accepting.send(false);
+        sessions.set_closing(true);
+
+        let open_files = sessions.open_files();
+        if open_files > 0 && !timeout.is_zero() {
+            info!(
+                "Waiting up to {}s for {} open file(s) to close",
+                timeout.as_secs(),
+                open_files
+            );
+            if !sessions.wait_for_transfers(timeout).await {
+                warn!(
+                    "Stop timeout reached with {} open file(s)",
+                    sessions.open_files()
+                );
+            }
+        }
+
+        sessions.disconnect_all(reason).await;
+
+        // Dropping the switch ends the accept loops
+        drop(server.accepting);
+        let mut task = server.task;
+        if tokio::time::timeout(Duration::from_secs(5), &mut task)
             .await
-            .ok_or("No credentials available")?;
+            .is_err()
+        {
+            warn!("SFTP server task did not stop in time, aborting it");
+            task.abort();
+        }
+        if let Some(mut ftps) = server.ftps
+            && tokio::time::timeout(Duration::from_secs(5), &mut ftps)
+                .await
+                .is_err()
+        {
+            warn!("FTPS server task did not stop in time, aborting it");
+            ftps.abort();
+        }
+        sessions.set_closing(false);
+    }
+
+    // Start the actual SFTP server
+    async fn start_server(&self) -> Result<ServerTask> {
+        // Get credentials
+        let credentials =
+            self.state.get_credentials().await.ok_or(Error::NoCredentials)?;
+
+        // Bind before spawning so a busy port is reported right away
+        let address = self.state.listen_address().await;
+        let listener = match &self.activated {
+            Some(activated) => activated
+                .try_clone()
+                .and_then(TcpListener::from_std)
+                .map_err(Error::ActivatedSocket)?,
+            None => {
+                TcpListener::bind((address.bind_addrs.as_str(), address.port))
+                    .await
+                    .map_err(|e| Error::bind(&address, e))?
+            }
+        };
 
-        // Clone values for the task
-        let bind_address = self.bind_address.clone();
-        let port = self.port;
+        // Clone values for the task; credentials stay shared with the state
         let root_dir = self.root_directory.clone();
-        let username = credentials.username.clone();
-        let password = credentials.password.clone();
+        let shared_credentials = self.state.credentials();
+        let context = self.context.clone();
+        let (accepting, accepting_rx) = watch::channel(true);
+        let (rebind, rebind_rx) = mpsc::channel(1);
+        let ftps = self.start_ftps(&address, accepting_rx.clone()).await?;
 
         info!(
-            "Starting SFTP server: address={}, port={}, root={}, user={}",
-            bind_address, port, root_dir, username
+            "Starting SFTP server: address={}, root={}, user={}",
+            address, root_dir, credentials.username
         );
 
         // Spawn the server task
@@ -128,34 +519,195 @@ impl SftpLifecycleManager {
             info!("SFTP server task started");
 
             // Start the actual SFTP server
-            if let Err(e) = run_sftp_server(
+            let result = run_sftp_server(
+                listener,
                 root_dir,
-                bind_address,
-                port,
-                username,
-                password,
+                shared_credentials,
+                context,
+                accepting_rx,
+                rebind_rx,
+            )
+            .await;
+
+            info!("SFTP server task ended");
+            result
+        });
+
+        Ok(ServerTask {
+            task,
+            accepting,
+            rebind,
+            address,
+            ftps,
+            started: Instant::now(),
+            earlier_failures: 0,
+        })
+    }
+
+    // Start the FTPS server next to the SFTP server when enabled. It
+    // serves the same root with the same credentials and shares the
+    // accepting switch, so draining and stopping apply to both.
+    async fn start_ftps(
+        &self,
+        address: &ListenAddress,
+        accepting: watch::Receiver<bool>,
+    ) -> Result<Option<JoinHandle<()>>> {
+        let settings = self.settings.borrow().ftps.clone();
+        if !settings.enabled {
+            return Ok(None);
+        }
+
+        let tls = tls_acceptor(
+            &settings.certificate_file,
+            &settings.private_key_file,
+        )?;
+        let passive_address = match &settings.passive_address {
+            Some(address) => Some(address.parse().map_err(|e| {
+                Error::Config(format!("ftps.passive_address: {}", e))
+            })?),
+            None => None,
+        };
+        let config = FtpsConfig {
+            passive_ports: settings.passive_port_min
+                ..=settings.passive_port_max,
+            passive_address,
+        };
+        let listener =
+            TcpListener::bind((address.bind_addrs.as_str(), settings.port))
+                .await
+                .map_err(|e| {
+                    Error::bind(format!("FTPS port {}", settings.port), e)
+                })?;
+
+        let root_dir = self.root_directory.clone();
+        let credentials = self.state.credentials();
+        let context = self.context.clone();
+        Ok(Some(tokio::spawn(async move {
+            if let Err(e) = run_ftps_server(
+                listener,
+                root_dir,
+                credentials,
+                context,
+                config,
+                tls,
+                accepting,
             )
             .await
             {
-                error!("SFTP server error: {}", e);
+                error!("❌ FTPS server stopped: {}", e);
             }
+        })))
+    }
+}
 
-            info!("SFTP server task ended");
-        });
+// Wait until a running server task ends and return why; never completes
+// while no server runs
+async fn server_exit(server: &mut Option<ServerTask>) -> Error {
+    let Some(server) = server else {
+        return std::future::pending().await;
+    };
+    match (&mut server.task).await {
+        Ok(Ok(())) => Error::Task("accept loop returned".to_string()),
+        Ok(Err(e)) => e,
+        Err(e) => Error::Task(e.to_string()),
+    }
+}
 
-        Ok(task)
+// Sleep until `deadline`; never completes without one
+async fn sleep_until(deadline: Option<SystemTime>) {
+    match deadline {
+        Some(deadline) => {
+            let delay =
+                deadline.duration_since(SystemTime::now()).unwrap_or_default();
+            tokio::time::sleep(delay).await;
+        }
+        None => std::future::pending().await,
     }
 }
 
 // Convenience function to start the lifecycle manager
 pub fn start_sftp_lifecycle(
     state: SftpState,
-    bind_address: String,
-    port: u16,
     root_directory: String,
-) -> JoinHandle<()> {
-    let manager =
-        SftpLifecycleManager::new(state, bind_address, port, root_directory);
+    context: ServerContext,
+    settings: watch::Receiver<Settings>,
+    listener: Option<std::net::TcpListener>,
+) -> SftpLifecycleHandle {
+    let mut manager =
+        SftpLifecycleManager::new(state, root_directory, context, settings);
+    if let Some(listener) = listener {
+        manager = manager.with_listener(listener);
+    }
 
     manager.start()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::sftp::SftpCredentials;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    fn free_port() -> u16 {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port()
+    }
+
+    // Whether `port` on localhost reaches a listener within two seconds
+    async fn listening(port: u16, expected: bool) -> bool {
+        for _ in 0..20 {
+            if TcpStream::connect(("127.0.0.1", port)).await.is_ok() == expected
+            {
+                return true;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        false
+    }
+
+    #[tokio::test]
+    async fn test_listener_follows_a_changed_address() {
+        let (old_port, new_port) = (free_port(), free_port());
+        let state = SftpState::new(ListenAddress {
+            bind_addrs: "127.0.0.1".to_string(),
+            port: old_port,
+        });
+        let (_tx, settings) = watch::channel(Settings::default());
+        let lifecycle = start_sftp_lifecycle(
+            state.clone(),
+            std::env::temp_dir().to_string_lossy().to_string(),
+            ServerContext::for_tests(),
+            settings,
+            None,
+        );
+
+        state
+            .enable(
+                SftpCredentials::new("user".to_string(), "secret".to_string()),
+                None,
+            )
+            .await;
+        assert!(listening(old_port, true).await);
+        let mut session =
+            TcpStream::connect(("127.0.0.1", old_port)).await.unwrap();
+        let mut banner = [0u8; 8];
+        session.read_exact(&mut banner).await.unwrap();
+
+        state
+            .set_listen_address(ListenAddress {
+                bind_addrs: "127.0.0.1".to_string(),
+                port: new_port,
+            })
+            .await;
+        assert!(listening(new_port, true).await);
+        assert!(listening(old_port, false).await);
+        // Moved in place: the session from before the move is still served
+        session.write_all(b"SSH-2.0-test\r\n").await.unwrap();
+        let mut rest = [0u8; 64];
+        assert!(session.read(&mut rest).await.unwrap() > 0);
+
+        lifecycle.shutdown().await;
+        assert!(listening(new_port, false).await);
+    }
+}
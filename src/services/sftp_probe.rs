@@ -0,0 +1,126 @@
+use crate::models::sftp::ListenerCheck;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tracing::debug;
+
+// Self-check that connects to the SFTP listener the way a client would
+#[derive(Debug, Clone)]
+pub struct SftpProbe {
+    host: String,
+    port: u16,
+    handshake: bool,
+    timeout: Duration,
+}
+
+impl SftpProbe {
+    pub fn new(
+        bind_addrs: &str,
+        port: u16,
+        handshake: bool,
+        timeout: Duration,
+    ) -> Self {
+        Self { host: probe_host(bind_addrs), port, handshake, timeout }
+    }
+
+    // Connect to the listener and, if configured, wait for the SSH banner
+    pub async fn check(&self) -> ListenerCheck {
+        let address = format!("{}:{}", self.host, self.port);
+        let started = Instant::now();
+
+        let outcome = timeout(self.timeout, self.connect(&address)).await;
+        let latency_ms = started.elapsed().as_millis() as u64;
+
+        let (reachable, server_banner, error) = match outcome {
+            Ok(Ok(banner)) => (true, banner, None),
+            Ok(Err(e)) => (false, None, Some(e)),
+            Err(_) => (false, None, Some("timed out".to_string())),
+        };
+
+        debug!(
+            "SFTP probe {}: reachable={}, latency={}ms",
+            address, reachable, latency_ms
+        );
+
+        ListenerCheck { reachable, address, server_banner, latency_ms, error }
+    }
+
+    async fn connect(&self, address: &str) -> Result<Option<String>, String> {
+        let mut stream =
+            TcpStream::connect(address).await.map_err(|e| e.to_string())?;
+
+        if !self.handshake {
+            return Ok(None);
+        }
+
+        // The server sends its identification string first (RFC 4253 4.2)
+        let mut buffer = [0u8; 255];
+        let n = stream.read(&mut buffer).await.map_err(|e| e.to_string())?;
+        let banner = String::from_utf8_lossy(&buffer[..n]);
+        let banner = banner.lines().next().unwrap_or_default().to_string();
+
+        if banner.starts_with("SSH-2.0-") {
+            Ok(Some(banner))
+        } else {
+            Err(format!("unexpected identification string: {:?}", banner))
+        }
+    }
+}
+
+// Wildcard bind addresses are not connectable, use loopback instead
+fn probe_host(bind_addrs: &str) -> String {
+    match bind_addrs {
+        "0.0.0.0" | "" => "127.0.0.1".to_string(),
+        "::" | "[::]" => "[::1]".to_string(),
+        host if host.contains(':') && !host.starts_with('[') => {
+            format!("[{}]", host)
+        }
+        host => host.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_probe_host_connects_to_wildcards_over_loopback() {
+        assert_eq!(probe_host("0.0.0.0"), "127.0.0.1");
+        assert_eq!(probe_host(""), "127.0.0.1");
+        assert_eq!(probe_host("::"), "[::1]");
+        assert_eq!(probe_host("[::]"), "[::1]");
+        assert_eq!(probe_host("fd00::7"), "[fd00::7]");
+        assert_eq!(probe_host("[fd00::7]"), "[fd00::7]");
+        assert_eq!(probe_host("192.0.2.10"), "192.0.2.10");
+    }
+
+    #[tokio::test]
+    async fn test_probe_tells_a_listening_port_from_a_closed_one() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let _ = stream.write_all(b"SSH-2.0-probe_test\r\n").await;
+            }
+        });
+        let timeout = Duration::from_secs(5);
+
+        let check =
+            SftpProbe::new("0.0.0.0", port, true, timeout).check().await;
+        assert!(check.reachable, "{:?}", check.error);
+        assert_eq!(check.address, format!("127.0.0.1:{}", port));
+        assert_eq!(check.server_banner.as_deref(), Some("SSH-2.0-probe_test"));
+
+        // A port nothing listens on any more
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = closed.local_addr().unwrap().port();
+        drop(closed);
+        let check =
+            SftpProbe::new("0.0.0.0", port, false, timeout).check().await;
+        assert!(!check.reachable);
+        assert!(check.error.is_some());
+    }
+}
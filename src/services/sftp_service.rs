@@ -1,115 +1,404 @@
+use crate::config::settings::Settings;
+use crate::events::Event;
 use crate::models::sftp::{
-    CredentialsResponse, SftpCredentials, SftpState, SftpStatusResponse,
+    CredentialHistoryResponse, CredentialsAccessor, CredentialsResponse,
+    DrainRequest, DrainState, DrainStatus, EnableRequest, FailureStatus,
+    ImportResponse, InstanceStatus, ListenAddress, PathRulesBody, PortMapping,
+    RecordingInfo, ScheduleRequest, ScheduleResponse, SftpCredentials,
+    SftpSnapshot, SftpState, SftpStatusResponse, StateSnapshot,
     ToggleSftpResponse,
 };
 use crate::responses::sftp::SftpApiResponse;
-use axum::http::StatusCode;
-use rand::Rng;
+use crate::schedule::Schedule;
+use crate::services::accounts::no_database;
+use crate::services::audit::record_audit_event;
+use crate::services::config_reload::changed_fields;
+use crate::services::credential_delivery::{
+    CredentialDelivery, IssuedCredentials,
+};
+use crate::services::port_mapping::PortMapper;
+use crate::services::sftp_probe::SftpProbe;
+use crate::sftp::recording::parse_recording_name;
+use crate::sftp::registry::SessionInfo;
+use crate::sftp::resume::ResumePoint;
+use crate::sftp::{DiskStatus, ServerContext};
+use crate::stats::{BucketSize, StatsSnapshot};
+use crate::store::Repository;
+use axum::body::Body;
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use chrono::Utc;
+use rand::RngExt;
 use rand::distr::Alphanumeric;
+use std::io;
+use std::path::{Component, Path};
+use std::sync::Arc;
 use std::time::{Duration, SystemTime};
-use tracing::{info, warn};
+use tokio::fs;
+use tokio::sync::watch;
+use tokio_util::io::ReaderStream;
+use tracing::{error, info, warn};
+
+// Version of the export format produced by `export_state`
+const SNAPSHOT_FORMAT_VERSION: u32 = 1;
 
 // SFTP service for managing server lifecycle
 pub struct SftpService {
-    pub bind_addrs: String,
-    pub port: u16,
     pub root_dir: String,
     pub state: SftpState,
+    pub context: ServerContext,
+    // Live settings, updated on configuration reload
+    pub settings: watch::Receiver<Settings>,
+    // Router mapping advertised when no external address is configured
+    port_mapper: Option<Arc<PortMapper>>,
+    // Sends new credentials by email or to Slack
+    delivery: Option<CredentialDelivery>,
+    // Database of accounts, also holding the audit log written whenever
+    // credentials are handed out
+    repository: Option<Arc<dyn Repository>>,
 }
 
 impl SftpService {
     // Create a new SFTP service
     pub fn new(
-        bind_addrs: String,
-        port: u16,
         root_dir: String,
         sftp_state: SftpState,
+        context: ServerContext,
+        settings: watch::Receiver<Settings>,
     ) -> Self {
-        Self { bind_addrs, port, root_dir, state: sftp_state }
+        Self {
+            root_dir,
+            state: sftp_state,
+            context,
+            settings,
+            port_mapper: None,
+            delivery: None,
+            repository: None,
+        }
     }
 
-    // Toggle SFTP server on/off
-    pub async fn toggle(&self) -> SftpApiResponse<ToggleSftpResponse> {
-        let is_enabled = self.state.is_enabled().await;
+    pub fn with_port_mapper(mut self, port_mapper: Arc<PortMapper>) -> Self {
+        self.port_mapper = Some(port_mapper);
+        self
+    }
 
-        if is_enabled {
-            // Disable SFTP
-            info!("Disabling SFTP server");
-            self.state.disable().await;
+    pub fn with_repository(mut self, repository: Arc<dyn Repository>) -> Self {
+        self.repository = Some(repository);
+        self
+    }
 
-            SftpApiResponse::success(ToggleSftpResponse {
-                status: "disabled".to_string(),
-                enabled: false,
-                credentials: None,
-                expires_at: None,
-            })
-        } else {
-            info!("Enabling SFTP server");
+    pub fn with_credential_delivery(
+        mut self,
+        delivery: CredentialDelivery,
+    ) -> Self {
+        self.delivery = Some(delivery);
+        self
+    }
 
-            // Generate new credentials
-            let credentials = self.generate_credentials();
+    // Listener self-check using the current address and probe settings
+    pub async fn probe(&self) -> SftpProbe {
+        let listen = self.state.listen_address().await;
+        let settings = self.settings.borrow();
+        SftpProbe::new(
+            &listen.bind_addrs,
+            listen.port,
+            settings.sftp.self_check_handshake,
+            Duration::from_millis(settings.sftp.self_check_timeout_ms),
+        )
+    }
 
-            // Calculate expiration time
-            let expiration = Some(
-                SystemTime::now() + Duration::from_secs(30 * 24 * 60 * 60),
+    // Toggle SFTP server on/off
+    pub async fn toggle(
+        &self,
+        instance: &str,
+        accessor: CredentialsAccessor,
+    ) -> SftpApiResponse<ToggleSftpResponse> {
+        if self.state.is_enabled().await {
+            self.disable().await
+        } else {
+            self.enable(EnableRequest::default(), instance, accessor).await
+        }
+    }
+
+    // Enable the server with new credentials, optionally overriding how
+    // long they last. The credentials go back to `accessor`.
+    pub async fn enable(
+        &self,
+        request: EnableRequest,
+        instance: &str,
+        accessor: CredentialsAccessor,
+    ) -> SftpApiResponse<ToggleSftpResponse> {
+        if self.state.is_enabled().await {
+            return SftpApiResponse::error(
+                StatusCode::CONFLICT,
+                "SFTP is already enabled".to_string(),
             );
+        }
+        info!("Enabling SFTP server");
 
-            // Enable the server
-            self.state.enable(credentials.clone(), expiration).await;
+        // Generate new credentials, never reusing a recent password
+        let credentials = self.generate_unused_credentials().await;
+        let keep = self.settings.borrow().sftp.password_history;
+        self.state.record_rotation(credentials.password.expose(), keep).await;
 
-            // Log formatted expiration date
-            let formatted_expiration = expiration
-                .map(format_system_time)
-                .unwrap_or_else(|| "N/A".to_string());
+        // Calculate expiration time; 0 days means never
+        let days = request
+            .expiration_days
+            .unwrap_or_else(|| self.settings.borrow().sftp.expiration_days);
+        let expiration = (days > 0).then(|| {
+            SystemTime::now() + Duration::from_secs(days * 24 * 60 * 60)
+        });
 
-            info!(
-                "SFTP enabled with username: {}, expires at {}",
-                credentials.username, formatted_expiration
-            );
+        // Enable the server
+        self.state.enable(credentials.clone(), expiration).await;
+        self.context.events.publish(Event::ServerToggled { enabled: true });
 
-            SftpApiResponse::success(ToggleSftpResponse {
-                status: "enabled".to_string(),
-                enabled: true,
-                credentials: Some(credentials),
-                expires_at: expiration.map(format_system_time),
-            })
+        // Log formatted expiration date
+        let formatted_expiration = expiration
+            .map(format_system_time)
+            .unwrap_or_else(|| "N/A".to_string());
+
+        info!(
+            "SFTP enabled with username: {}, expires at {}",
+            credentials.username, formatted_expiration
+        );
+        self.deliver("enabled", &credentials, expiration).await;
+        self.credentials_accessed(instance, &credentials.username, accessor)
+            .await;
+
+        SftpApiResponse::success(ToggleSftpResponse {
+            status: "enabled".to_string(),
+            enabled: true,
+            credentials: Some(credentials),
+            expires_at: expiration.map(format_system_time),
+        })
+    }
+
+    // Disable the server; disabling a disabled server is not an error
+    pub async fn disable(&self) -> SftpApiResponse<ToggleSftpResponse> {
+        if self.state.is_enabled().await {
+            info!("Disabling SFTP server");
+            self.state.disable().await;
+            self.context
+                .events
+                .publish(Event::ServerToggled { enabled: false });
         }
+
+        SftpApiResponse::success(ToggleSftpResponse {
+            status: "disabled".to_string(),
+            enabled: false,
+            credentials: None,
+            expires_at: None,
+        })
     }
 
     // Get current SFTP status
     pub async fn get_status(&self) -> SftpApiResponse<SftpStatusResponse> {
         let enabled = self.state.is_enabled().await;
+        let disk = self.disk_status().await;
+        let runtime = self.state.current();
+        let running = runtime.running;
+        let listen_addr = running.then(|| runtime.listen.to_string());
+        let active_sessions = self.context.sessions.count();
+        let last_error = runtime.last_error.clone();
 
         if !enabled {
             return SftpApiResponse::success(SftpStatusResponse {
                 enabled: false,
                 expires_at: None,
+                expiring_soon: false,
+                schedule_open: None,
+                listener: None,
+                drain: None,
+                failure: None,
+                disk,
+                running,
+                listen_addr,
+                active_sessions,
+                last_error,
             });
         }
 
         // Check for expiration
         if self.state.is_expired().await {
             warn!("SFTP credentials have expired, disabling");
-            self.state.disable().await;
+            self.expire().await;
 
             return SftpApiResponse::success(SftpStatusResponse {
                 enabled: false,
                 expires_at: None,
+                expiring_soon: false,
+                schedule_open: None,
+                listener: None,
+                drain: None,
+                failure: None,
+                disk,
+                running,
+                listen_addr,
+                active_sessions,
+                last_error,
             });
         }
 
-        // Get expiration info
-        let expiration = *self.state.expiration.read().await;
-        let expires_at = expiration.map(format_system_time);
+        // One consistent view of the state for the rest of the report
+        let current = self.state.current();
+        let expires_at = current.expiration.map(format_system_time);
+
+        let has_schedule = !current.schedule.is_empty();
+        let schedule_open = current.schedule.is_open(Utc::now());
+
+        // Outside a scheduled window, while draining or after a failed
+        // start the listener is expected to be down
+        let listener = if current.should_run() && current.failure.is_none() {
+            let listener = self.probe().await.check().await;
+            if !listener.reachable {
+                warn!(
+                    "SFTP is enabled but the listener at {} is unreachable",
+                    listener.address
+                );
+            }
+            Some(listener)
+        } else {
+            None
+        };
+
+        let warn_within = self
+            .settings
+            .borrow()
+            .sftp
+            .expiry_warning_secs
+            .iter()
+            .copied()
+            .max();
+        let expiring_soon = match (self.state.remaining().await, warn_within) {
+            (Some(remaining), Some(within)) => remaining.as_secs() <= within,
+            _ => false,
+        };
+
         SftpApiResponse::success(SftpStatusResponse {
             enabled: true,
             expires_at,
+            expiring_soon,
+            schedule_open: has_schedule.then_some(schedule_open),
+            listener,
+            drain: self.drain_status().await,
+            failure: self.failure_status().await,
+            disk,
+            running,
+            listen_addr,
+            active_sessions,
+            last_error,
         })
     }
 
-    // Get SFTP credentials
+    // Desired against actual state, as reported by the supervisor
+    pub async fn instance_status(&self, name: &str) -> InstanceStatus {
+        let current = self.state.current();
+        let desired_running = current.should_run();
+        let running = current.running;
+        let failure = self.failure_status().await;
+
+        InstanceStatus {
+            name: name.to_string(),
+            bind_addrs: current.listen.bind_addrs.clone(),
+            port: current.listen.port,
+            root_dir: self.root_dir.clone(),
+            enabled: current.enabled,
+            desired_running,
+            running,
+            healthy: desired_running == running && failure.is_none(),
+            sessions: self.context.sessions.count(),
+            failure,
+        }
+    }
+
+    async fn failure_status(&self) -> Option<FailureStatus> {
+        self.state.get_failure().await.map(|f| FailureStatus {
+            reason: f.reason,
+            kind: f.kind.to_string(),
+            attempts: f.attempts,
+            failed_at: format_system_time(f.failed_at),
+            retry_at: f.retry_at.map(format_system_time),
+            gave_up: f.retry_at.is_none(),
+        })
+    }
+
+    // Stop accepting new connections and let open transfers finish
+    pub async fn start_drain(
+        &self,
+        request: DrainRequest,
+    ) -> SftpApiResponse<DrainStatus> {
+        if !self.state.is_enabled().await {
+            return SftpApiResponse::error(
+                StatusCode::BAD_REQUEST,
+                "SFTP is not enabled",
+            );
+        }
+
+        if self.state.get_drain().await.is_none() {
+            let timeout = request
+                .timeout_secs
+                .map(Duration::from_secs)
+                .unwrap_or_else(|| {
+                    Duration::from_secs(
+                        self.settings.borrow().sftp.drain_timeout_secs,
+                    )
+                });
+            let now = SystemTime::now();
+
+            info!("Draining SFTP server, deadline in {:?}", timeout);
+            self.state
+                .set_drain(Some(DrainState {
+                    started_at: now,
+                    deadline: now + timeout,
+                    completed_at: None,
+                }))
+                .await;
+        }
+
+        self.get_drain().await
+    }
+
+    // Get progress of the current drain
+    pub async fn get_drain(&self) -> SftpApiResponse<DrainStatus> {
+        match self.drain_status().await {
+            Some(status) => SftpApiResponse::success(status),
+            None => SftpApiResponse::error(
+                StatusCode::NOT_FOUND,
+                "SFTP is not draining",
+            ),
+        }
+    }
+
+    // Cancel a drain and resume accepting connections
+    pub async fn cancel_drain(&self) -> SftpApiResponse<DrainStatus> {
+        let response = self.get_drain().await;
+        if self.state.get_drain().await.is_some() {
+            info!("Drain cancelled, SFTP listener will resume");
+            self.state.set_drain(None).await;
+        }
+        response
+    }
+
+    async fn drain_status(&self) -> Option<DrainStatus> {
+        let drain = self.state.get_drain().await?;
+        let sessions = &self.context.sessions;
+
+        Some(DrainStatus {
+            started_at: format_system_time(drain.started_at),
+            deadline: format_system_time(drain.deadline),
+            completed: drain.completed_at.is_some(),
+            active_sessions: sessions.count(),
+            open_files: sessions.open_files(),
+        })
+    }
+
+    // Get SFTP credentials of `instance`; every retrieval is published for
+    // the audit log
     pub async fn get_credentials(
         &self,
+        instance: &str,
+        accessor: CredentialsAccessor,
     ) -> Result<SftpApiResponse<CredentialsResponse>, SftpApiResponse<()>> {
         // Check if enabled
         if !self.state.is_enabled().await {
@@ -122,7 +411,7 @@ impl SftpService {
         // Check if expired
         if self.state.is_expired().await {
             warn!("Attempted to get expired credentials");
-            self.state.disable().await;
+            self.expire().await;
             return Err(SftpApiResponse::error(
                 StatusCode::BAD_REQUEST,
                 "SFTP credentials have expired".to_string(),
@@ -138,15 +427,409 @@ impl SftpService {
                 )
             })?;
 
+        let listen = self.state.listen_address().await;
+        let (host, port, port_mapping) = self.client_address(&listen).await;
+        self.credentials_accessed(instance, &credentials.username, accessor)
+            .await;
         Ok(SftpApiResponse::success(CredentialsResponse {
             username: credentials.username,
             password: credentials.password,
             root_dir: self.root_dir.clone(),
-            bind_addrs: self.bind_addrs.clone(),
-            port: self.port,
+            host,
+            bind_addrs: listen.bind_addrs,
+            port,
+            port_mapping,
         }))
     }
 
+    // Record that the credentials of `instance` were handed out. The audit
+    // entry is written before the response leaves, not left to the event
+    // bus, which drops events for subscribers that fall behind.
+    async fn credentials_accessed(
+        &self,
+        instance: &str,
+        username: &str,
+        accessor: CredentialsAccessor,
+    ) {
+        info!(
+            "SFTP credentials of {} retrieved over {} by {} ({} key)",
+            instance,
+            accessor.api,
+            accessor.client.as_deref().unwrap_or("unknown client"),
+            accessor.api_key.as_deref().unwrap_or("no")
+        );
+        let event = Event::CredentialsAccessed {
+            instance: instance.to_string(),
+            username: username.to_string(),
+            api: accessor.api.to_string(),
+            client: accessor.client,
+            forwarded_for: accessor.forwarded_for,
+            certificate: accessor.certificate,
+            api_key: accessor.api_key,
+        };
+        if let Some(repository) = &self.repository {
+            record_audit_event(repository.as_ref(), &event).await;
+        }
+        self.context.events.publish(event);
+    }
+
+    // Where clients connect: the external address, the router's mapping
+    // or the listen address, in that order
+    async fn client_address(
+        &self,
+        listen: &ListenAddress,
+    ) -> (String, u16, Option<PortMapping>) {
+        let port_mapping = match &self.port_mapper {
+            Some(mapper) => mapper
+                .current()
+                .await
+                .filter(|mapping| mapping.internal_port == listen.port),
+            None => None,
+        };
+        let (host, port) = self
+            .settings
+            .borrow()
+            .external_address(listen.port)
+            .or_else(|| {
+                port_mapping.as_ref().map(|mapping| {
+                    (mapping.external_ip.clone(), mapping.external_port)
+                })
+            })
+            .unwrap_or_else(|| (listen.bind_addrs.clone(), listen.port));
+        (host, port, port_mapping)
+    }
+
+    // Send newly issued credentials on the configured delivery channels
+    async fn deliver(
+        &self,
+        reason: &'static str,
+        credentials: &SftpCredentials,
+        expiration: Option<SystemTime>,
+    ) {
+        let Some(delivery) = &self.delivery else { return };
+        let listen = self.state.listen_address().await;
+        let (host, port, _) = self.client_address(&listen).await;
+        delivery.deliver(IssuedCredentials {
+            reason,
+            username: credentials.username.clone(),
+            password: credentials.password.clone(),
+            host,
+            port,
+            expires_at: expiration.map(format_system_time),
+        });
+    }
+
+    // Get the configured schedule windows
+    pub async fn get_schedule(&self) -> SftpApiResponse<ScheduleResponse> {
+        let windows = self.state.current().schedule.windows();
+        let open = self.state.is_schedule_open().await;
+        SftpApiResponse::success(ScheduleResponse { windows, open })
+    }
+
+    // Replace the schedule windows; an empty list removes the restriction
+    pub async fn set_schedule(
+        &self,
+        request: ScheduleRequest,
+    ) -> SftpApiResponse<ScheduleResponse> {
+        let schedule = match Schedule::parse(&request.windows) {
+            Ok(schedule) => schedule,
+            Err(e) => {
+                warn!("Rejected schedule: {}", e);
+                return SftpApiResponse::error(StatusCode::BAD_REQUEST, e);
+            }
+        };
+
+        info!("Updating SFTP schedule: {:?}", request.windows);
+        self.state.set_schedule(schedule).await;
+        self.get_schedule().await
+    }
+
+    pub async fn get_path_rules(&self) -> SftpApiResponse<PathRulesBody> {
+        let rules = self.context.path_rules.rules();
+        SftpApiResponse::success(PathRulesBody { rules })
+    }
+
+    // Replace the path rules until the next configuration reload; an empty
+    // list makes every path read-write
+    pub async fn set_path_rules(
+        &self,
+        request: PathRulesBody,
+    ) -> SftpApiResponse<PathRulesBody> {
+        if let Some(e) = request.rules.iter().find_map(|r| r.validate().err()) {
+            warn!("Rejected path rules: {}", e);
+            return SftpApiResponse::error(StatusCode::BAD_REQUEST, e);
+        }
+
+        info!("Updating SFTP path rules: {:?}", request.rules);
+        self.context.path_rules.set(request.rules);
+        self.get_path_rules().await
+    }
+
+    // Get cumulative usage statistics
+    pub async fn get_stats(
+        &self,
+        bucket: Option<BucketSize>,
+    ) -> SftpApiResponse<StatsSnapshot> {
+        let mut snapshot = self.context.stats.snapshot(bucket);
+        snapshot.disk = self.disk_status().await;
+        SftpApiResponse::success(snapshot)
+    }
+
+    // Free space on the filesystem of the root, logged when it runs low
+    async fn disk_status(&self) -> Option<DiskStatus> {
+        let root = Path::new(&self.root_dir);
+        match self.context.disk_space.status(root).await {
+            Ok(status) => {
+                if status.low {
+                    warn!(
+                        "Low disk space on {}: {} bytes available",
+                        self.root_dir, status.available_bytes
+                    );
+                }
+                Some(status)
+            }
+            Err(e) => {
+                warn!("Cannot read free space of {}: {}", self.root_dir, e);
+                None
+            }
+        }
+    }
+
+    // Recorded sessions, newest first
+    pub async fn list_recordings(&self) -> SftpApiResponse<Vec<RecordingInfo>> {
+        let Some(policy) = &self.context.recordings else {
+            return SftpApiResponse::error(
+                StatusCode::NOT_FOUND,
+                "Session recording is disabled",
+            );
+        };
+        let mut entries = match fs::read_dir(&policy.dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return SftpApiResponse::success(Vec::new());
+            }
+            Err(e) => {
+                error!("Failed to list recordings: {}", e);
+                return SftpApiResponse::error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to list recordings: {}", e),
+                );
+            }
+        };
+        let mut recordings = Vec::new();
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let Some((started_at, session_id, username)) =
+                parse_recording_name(&name)
+            else {
+                continue;
+            };
+            let size_bytes =
+                entry.metadata().await.map(|m| m.len()).unwrap_or_default();
+            recordings.push(RecordingInfo {
+                session_id,
+                username: username.to_string(),
+                started_at: started_at.to_rfc3339(),
+                size_bytes,
+                name,
+            });
+        }
+        recordings.sort_by(|a, b| b.name.cmp(&a.name));
+        SftpApiResponse::success(recordings)
+    }
+
+    // One recording as JSON Lines: a header with the session, then one
+    // line per request
+    pub async fn get_recording(&self, name: &str) -> Response {
+        let Some(policy) = &self.context.recordings else {
+            return SftpApiResponse::<()>::error(
+                StatusCode::NOT_FOUND,
+                "Session recording is disabled",
+            )
+            .into_response();
+        };
+        // Only names of recordings, so nothing outside the directory
+        if name.contains(['/', '\\']) || parse_recording_name(name).is_none() {
+            return SftpApiResponse::<()>::error(
+                StatusCode::NOT_FOUND,
+                "No such recording",
+            )
+            .into_response();
+        }
+        match fs::File::open(policy.dir.join(name)).await {
+            Ok(file) => (
+                [(header::CONTENT_TYPE, "application/x-ndjson")],
+                Body::from_stream(ReaderStream::new(file)),
+            )
+                .into_response(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                SftpApiResponse::<()>::error(
+                    StatusCode::NOT_FOUND,
+                    "No such recording",
+                )
+                .into_response()
+            }
+            Err(e) => {
+                error!("Failed to open recording {}: {}", name, e);
+                SftpApiResponse::<()>::error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to open recording: {}", e),
+                )
+                .into_response()
+            }
+        }
+    }
+
+    // Sessions connected to the default server
+    pub fn get_sessions(&self) -> SftpApiResponse<Vec<SessionInfo>> {
+        SftpApiResponse::success(self.context.sessions.list())
+    }
+
+    // Where a client resumes uploading `path` to the default server, e.g.
+    // after its connection dropped. Files filed into dated landing
+    // directories are found under their undated path as well.
+    pub async fn get_resume_point(
+        &self,
+        path: &str,
+    ) -> SftpApiResponse<ResumePoint> {
+        let relative = path.trim_start_matches('/');
+        if relative.is_empty()
+            || !Path::new(relative)
+                .components()
+                .all(|c| matches!(c, Component::Normal(_)))
+        {
+            return SftpApiResponse::error(
+                StatusCode::BAD_REQUEST,
+                "Invalid path",
+            );
+        }
+        let Ok(root) = fs::canonicalize(&self.root_dir).await else {
+            return SftpApiResponse::error(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Root directory is not accessible",
+            );
+        };
+
+        // Uploads are tracked by canonical path, as the SFTP handler
+        // resolves them
+        let path = format!("/{}", relative);
+        let landed = self.context.landing.landed_paths(&path);
+        for candidate in std::iter::once(path).chain(landed) {
+            let local = root.join(candidate.trim_start_matches('/'));
+            let Ok(local) = fs::canonicalize(&local).await else {
+                continue;
+            };
+            if !local.starts_with(&root) {
+                break;
+            }
+            match fs::metadata(&local).await {
+                Ok(metadata) if metadata.is_file() => {
+                    return SftpApiResponse::success(
+                        self.context.uploads.resume_point(
+                            &local,
+                            candidate,
+                            metadata.len(),
+                        ),
+                    );
+                }
+                _ => break,
+            }
+        }
+        SftpApiResponse::error(StatusCode::NOT_FOUND, "No such file")
+    }
+
+    // Disconnect one session; the client may log in again
+    pub async fn kick_session(&self, id: u64) -> SftpApiResponse<()> {
+        if self
+            .context
+            .sessions
+            .disconnect(id, "Disconnected by administrator")
+            .await
+        {
+            SftpApiResponse::success(())
+        } else {
+            SftpApiResponse::error(
+                StatusCode::NOT_FOUND,
+                format!("Session {} not found", id),
+            )
+        }
+    }
+
+    // Replace the credentials of the running server, keeping its expiry.
+    // Established sessions stay connected; new logins need the new ones,
+    // which go back to `accessor`.
+    pub async fn rotate_credentials(
+        &self,
+        instance: &str,
+        accessor: CredentialsAccessor,
+    ) -> SftpApiResponse<ToggleSftpResponse> {
+        if !self.state.is_enabled().await {
+            return SftpApiResponse::error(
+                StatusCode::BAD_REQUEST,
+                "SFTP is not enabled",
+            );
+        }
+
+        let credentials = self.generate_unused_credentials().await;
+        let keep = self.settings.borrow().sftp.password_history;
+        self.state.record_rotation(credentials.password.expose(), keep).await;
+        self.state.rotate_credentials(credentials.clone()).await;
+
+        info!("Rotated SFTP credentials, new user {}", credentials.username);
+        self.context.events.publish(Event::CredentialsRotated {
+            username: credentials.username.clone(),
+        });
+
+        let expiration = self.state.current().expiration;
+        self.deliver("rotated", &credentials, expiration).await;
+        self.credentials_accessed(instance, &credentials.username, accessor)
+            .await;
+        SftpApiResponse::success(ToggleSftpResponse {
+            status: "rotated".to_string(),
+            enabled: true,
+            credentials: Some(credentials),
+            expires_at: expiration.map(format_system_time),
+        })
+    }
+
+    async fn generate_unused_credentials(&self) -> SftpCredentials {
+        loop {
+            let credentials = self.generate_credentials();
+            if !self
+                .state
+                .is_recent_password(credentials.password.expose())
+                .await
+            {
+                return credentials;
+            }
+            warn!("Generated a recently used password, generating another");
+        }
+    }
+
+    // When recent passwords were generated, without the hashes
+    pub async fn get_credential_history(
+        &self,
+    ) -> SftpApiResponse<CredentialHistoryResponse> {
+        let rotated_at = self
+            .state
+            .history
+            .read()
+            .await
+            .iter()
+            .rev()
+            .map(|r| {
+                format_system_time(
+                    SystemTime::UNIX_EPOCH + Duration::from_secs(r.rotated_at),
+                )
+            })
+            .collect();
+
+        SftpApiResponse::success(CredentialHistoryResponse {
+            limit: self.settings.borrow().sftp.password_history,
+            rotated_at,
+        })
+    }
+
     /// Generate random credentials
     fn generate_credentials(&self) -> SftpCredentials {
         let username: String = rand::rng()
@@ -164,17 +847,182 @@ impl SftpService {
         SftpCredentials::new(username, password)
     }
 
+    // Snapshot of the state needed to recreate this deployment elsewhere,
+    // including the accounts, keys and shares of the database. The
+    // configuration is redacted, and the credentials and password hashes
+    // are only included for an `accessor` asking for them, which is
+    // recorded like any other retrieval of credentials.
+    pub async fn export_state(
+        &self,
+        instance: &str,
+        accessor: Option<CredentialsAccessor>,
+    ) -> anyhow::Result<StateSnapshot> {
+        let expiration = self.state.current().expiration;
+        let with_secrets = accessor.is_some();
+        let credentials = match accessor {
+            Some(accessor) => {
+                let credentials = self.state.get_credentials().await;
+                if let Some(credentials) = &credentials {
+                    self.credentials_accessed(
+                        instance,
+                        &credentials.username,
+                        accessor,
+                    )
+                    .await;
+                }
+                credentials
+            }
+            None => None,
+        };
+        let accounts = match &self.repository {
+            Some(repository) => {
+                let mut accounts = repository.export_accounts().await?;
+                if !with_secrets {
+                    for user in &mut accounts.users {
+                        user.password_hash = None;
+                    }
+                }
+                Some(accounts)
+            }
+            None => None,
+        };
+
+        Ok(StateSnapshot {
+            format_version: SNAPSHOT_FORMAT_VERSION,
+            exported_at: format_system_time(SystemTime::now()),
+            sftp: SftpSnapshot {
+                enabled: self.state.is_enabled().await,
+                credentials,
+                expires_at: expiration.map(format_system_time),
+            },
+            schedule: self.state.current().schedule.windows(),
+            accounts,
+            config: self.settings.borrow().redacted(),
+        })
+    }
+
+    // Restore a snapshot produced by `export_state`
+    pub async fn import_state(
+        &self,
+        snapshot: StateSnapshot,
+    ) -> SftpApiResponse<ImportResponse> {
+        if snapshot.format_version != SNAPSHOT_FORMAT_VERSION {
+            return SftpApiResponse::error(
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "Unsupported snapshot format version {}",
+                    snapshot.format_version
+                ),
+            );
+        }
+
+        let schedule = match Schedule::parse(&snapshot.schedule) {
+            Ok(schedule) => schedule,
+            Err(e) => {
+                return SftpApiResponse::error(
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid schedule window: {}", e),
+                );
+            }
+        };
+
+        let expiration = match snapshot.sftp.expires_at.as_deref() {
+            Some(value) => match chrono::DateTime::parse_from_rfc3339(value) {
+                Ok(dt) => Some(SystemTime::from(dt)),
+                Err(e) => {
+                    return SftpApiResponse::error(
+                        StatusCode::BAD_REQUEST,
+                        format!("Invalid expires_at '{}': {}", value, e),
+                    );
+                }
+            },
+            None => None,
+        };
+
+        let credentials =
+            match (snapshot.sftp.enabled, snapshot.sftp.credentials) {
+                (true, Some(credentials)) => Some(credentials),
+                (true, None) => {
+                    return SftpApiResponse::error(
+                        StatusCode::BAD_REQUEST,
+                        "Snapshot is enabled but has no credentials; export \
+                         it with credentials=true",
+                    );
+                }
+                (false, _) => None,
+            };
+
+        let config_differences = changed_fields(
+            &self.settings.borrow().redacted(),
+            &snapshot.config,
+        )
+        .unwrap_or_default();
+
+        // Accounts go first and all at once, so a failure leaves both them
+        // and the SFTP state as they were
+        let accounts = snapshot.accounts.as_ref().map(|a| a.users.len());
+        if let Some(accounts) = &snapshot.accounts {
+            let Some(repository) = &self.repository else {
+                return no_database();
+            };
+            if let Err(e) = repository.import_accounts(accounts).await {
+                error!("Failed to import accounts: {}", e);
+                return SftpApiResponse::error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to import accounts: {}", e),
+                );
+            }
+            info!("Imported {} accounts", accounts.users.len());
+        }
+
+        let was_enabled = self.state.is_enabled().await;
+        self.state.set_schedule(schedule).await;
+        match credentials {
+            Some(credentials) => {
+                info!("Importing state for user {}", credentials.username);
+                self.state.enable(credentials, expiration).await;
+            }
+            None => {
+                info!("Importing disabled state");
+                self.state.disable().await;
+            }
+        }
+
+        let enabled = self.state.is_enabled().await;
+        if enabled != was_enabled {
+            self.context.events.publish(Event::ServerToggled { enabled });
+        }
+        for field in &config_differences {
+            warn!("Imported configuration differs in {}", field);
+        }
+
+        SftpApiResponse::success(ImportResponse {
+            enabled,
+            expires_at: expiration.filter(|_| enabled).map(format_system_time),
+            schedule: self.state.current().schedule.windows(),
+            accounts,
+            config_differences,
+        })
+    }
+
     // Check and handle expiration
     #[allow(dead_code)]
     pub async fn check_expiration(&self) -> bool {
         if self.state.is_expired().await {
             info!("SFTP credentials expired, disabling server");
-            self.state.disable().await;
+            self.expire().await;
             true
         } else {
             false
         }
     }
+
+    // Disable SFTP after expiration and announce it
+    async fn expire(&self) {
+        let username = self.state.get_credentials().await.map(|c| c.username);
+        self.state.disable().await;
+        self.context.events.publish(Event::CredentialsExpired { username });
+    }
 }
 
 // Format SystemTime
@@ -186,3 +1034,161 @@ fn format_system_time(time: SystemTime) -> String {
         .map(|dt| dt.to_rfc3339())
         .unwrap_or_else(|| "unknown".to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service() -> SftpService {
+        let (_tx, settings) = watch::channel(Settings::default());
+        SftpService::new(
+            std::env::temp_dir().to_string_lossy().to_string(),
+            SftpState::new(ListenAddress {
+                bind_addrs: "127.0.0.1".to_string(),
+                port: 2222,
+            }),
+            ServerContext::for_tests(),
+            settings,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_exported_state_is_imported_on_another_host() {
+        use crate::store::ShareLink;
+        use crate::store::sqlite::SqliteRepository;
+
+        let database = || -> Arc<dyn Repository> {
+            Arc::new(SqliteRepository::in_memory().unwrap())
+        };
+        let accounts = database();
+        let source = service().with_repository(accounts.clone());
+        let expiration = SystemTime::now() + Duration::from_secs(3600);
+        source
+            .state
+            .enable(
+                SftpCredentials::new("mover".to_string(), "s3cret".to_string()),
+                Some(expiration),
+            )
+            .await;
+        accounts.create_user("alice", &[], None).await.unwrap();
+        accounts
+            .set_password_hash("alice", Some("$argon2id$hash"))
+            .await
+            .unwrap();
+        accounts.add_key("alice", "ssh-ed25519 AAAA", None).await.unwrap();
+        accounts
+            .create_share(&ShareLink {
+                token: "t0ken".to_string(),
+                path: "/out/report.csv".to_string(),
+                created_by: Some("alice".to_string()),
+                created_at: Utc::now(),
+                expires_at: None,
+                url: None,
+            })
+            .await
+            .unwrap();
+
+        // Credentials and password hashes stay out of the snapshot unless
+        // asked for
+        let snapshot = source.export_state("default", None).await.unwrap();
+        assert!(snapshot.sftp.enabled);
+        assert!(snapshot.sftp.credentials.is_none());
+        let users = &snapshot.accounts.as_ref().unwrap().users;
+        assert_eq!(users[0].account.username, "alice");
+        assert!(users[0].password_hash.is_none());
+        let refused = service().import_state(snapshot).await;
+        assert_eq!(refused.status, StatusCode::BAD_REQUEST);
+
+        // Asking for them is recorded as a retrieval
+        let mut events = source.context.events.subscribe();
+        let accessor = CredentialsAccessor {
+            api: "rest",
+            client: Some("10.0.0.5".to_string()),
+            forwarded_for: None,
+            certificate: None,
+            api_key: None,
+        };
+        let snapshot =
+            source.export_state("default", Some(accessor)).await.unwrap();
+        assert!(matches!(
+            events.try_recv().unwrap().event,
+            Event::CredentialsAccessed { username, .. } if username == "mover"
+        ));
+
+        // Through JSON, as the endpoints move it. Accounts of the target
+        // are replaced by those of the snapshot.
+        let json = serde_json::to_string(&snapshot).unwrap();
+        let replaced = database();
+        replaced.create_user("stale", &[], None).await.unwrap();
+        let target = service().with_repository(replaced.clone());
+        let imported =
+            target.import_state(serde_json::from_str(&json).unwrap()).await;
+        assert_eq!(imported.status, StatusCode::OK);
+        let imported = imported.sftp.unwrap();
+        assert!(imported.enabled);
+        assert!(imported.expires_at.is_some());
+        assert_eq!(imported.accounts, Some(1));
+        assert!(imported.config_differences.is_empty());
+
+        let credentials = target.state.get_credentials().await.unwrap();
+        assert_eq!(credentials.username, "mover");
+        assert_eq!(credentials.password.expose(), "s3cret");
+        let users = replaced.list_users().await.unwrap();
+        assert_eq!(users.len(), 1);
+        assert_eq!(users[0].username, "alice");
+        let hash = replaced.password_hash("alice").await.unwrap();
+        assert_eq!(hash.as_deref(), Some("$argon2id$hash"));
+        let keys = replaced.list_keys("alice").await.unwrap();
+        assert_eq!(keys[0].public_key, "ssh-ed25519 AAAA");
+        let share = replaced.get_share("t0ken").await.unwrap().unwrap();
+        assert_eq!(share.path, "/out/report.csv");
+
+        // A snapshot with accounts needs a database to take them
+        let refused = service().import_state(snapshot).await;
+        assert_eq!(refused.status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_every_response_with_credentials_is_audited() {
+        use crate::services::audit::AuditService;
+        use crate::store::sqlite::SqliteRepository;
+
+        let repository: Arc<dyn Repository> =
+            Arc::new(SqliteRepository::in_memory().unwrap());
+        let service = service().with_repository(repository.clone());
+        let accessor = |client: &str| CredentialsAccessor {
+            api: "grpc",
+            client: Some(client.to_string()),
+            forwarded_for: None,
+            certificate: None,
+            api_key: Some("admin".to_string()),
+        };
+
+        // Refused requests hand out nothing and are not recorded
+        assert!(
+            service.get_credentials("default", accessor("a")).await.is_err()
+        );
+
+        let enabled = service.toggle("default", accessor("b")).await;
+        assert!(enabled.sftp.unwrap().credentials.is_some());
+        assert!(
+            service.get_credentials("default", accessor("c")).await.is_ok()
+        );
+        let rotated =
+            service.rotate_credentials("default", accessor("d")).await;
+        let username = rotated.sftp.unwrap().credentials.unwrap().username;
+
+        let audit = AuditService::new(Some(repository));
+        let accesses = audit.list_credentials_access(10).await.sftp.unwrap();
+        let clients: Vec<&str> =
+            accesses.iter().filter_map(|a| a.client.as_deref()).collect();
+        assert_eq!(clients, ["d", "c", "b"]);
+        assert_eq!(accesses[0].username, username);
+        assert!(
+            accesses
+                .iter()
+                .all(|a| a.api == "grpc"
+                    && a.api_key.as_deref() == Some("admin"))
+        );
+    }
+}
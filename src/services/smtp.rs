@@ -0,0 +1,253 @@
+use crate::config::settings::{EmailDeliverySettings, SmtpTls};
+use anyhow::{Context, anyhow, bail};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use chrono::Utc;
+use rustls_pki_types::ServerName;
+use std::sync::Arc;
+use tokio::io::{
+    AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufStream,
+};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore, crypto};
+use zeroize::Zeroizing;
+
+trait SmtpStream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> SmtpStream for T {}
+
+// A plain text message
+pub struct Mail<'a> {
+    pub to: &'a [String],
+    pub subject: &'a str,
+    pub body: &'a str,
+}
+
+// Hand a message to the configured SMTP server. Just enough SMTP for a
+// relay or a submission server: EHLO, STARTTLS, AUTH PLAIN and one
+// message per connection.
+pub async fn send(
+    settings: &EmailDeliverySettings,
+    mail: &Mail<'_>,
+) -> anyhow::Result<()> {
+    let host = settings.smtp_host.as_str();
+    let stream =
+        TcpStream::connect((host, settings.smtp_port)).await.with_context(
+            || format!("cannot connect to {}:{}", host, settings.smtp_port),
+        )?;
+    let stream: Box<dyn SmtpStream> = match settings.smtp_tls {
+        SmtpTls::Tls => Box::new(tls(stream, host).await?),
+        SmtpTls::StartTls | SmtpTls::None => Box::new(stream),
+    };
+    let mut connection = Connection::new(stream);
+    connection.expect(220).await.context("greeting")?;
+
+    // Introduce ourselves by the domain we send from
+    let domain = settings.from.rsplit('@').next().unwrap_or("localhost");
+    let ehlo = format!("EHLO {}", domain);
+    connection.command(&ehlo, 250).await?;
+    if settings.smtp_tls == SmtpTls::StartTls {
+        connection.command("STARTTLS", 220).await?;
+        let stream = connection.stream.into_inner();
+        connection = Connection::new(Box::new(tls(stream, host).await?));
+        connection.command(&ehlo, 250).await?;
+    }
+
+    if !settings.username.is_empty() {
+        let password = settings.password.as_ref().map_or("", |p| p.expose());
+        let token = Zeroizing::new(STANDARD.encode(Zeroizing::new(format!(
+            "\0{}\0{}",
+            settings.username, password
+        ))));
+        connection
+            .command(&Zeroizing::new(format!("AUTH PLAIN {}", *token)), 235)
+            .await?;
+    }
+
+    connection.command(&format!("MAIL FROM:<{}>", settings.from), 250).await?;
+    for to in mail.to {
+        connection.command(&format!("RCPT TO:<{}>", to), 250).await?;
+    }
+    connection.command("DATA", 354).await?;
+    let message = message(&settings.from, mail);
+    connection.stream.write_all(message.as_bytes()).await?;
+    connection.stream.flush().await?;
+    connection.expect(250).await.context("message refused")?;
+
+    // The message is accepted; a failing QUIT changes nothing
+    let _ = connection.command("QUIT", 221).await;
+    Ok(())
+}
+
+struct Connection {
+    stream: BufStream<Box<dyn SmtpStream>>,
+}
+
+impl Connection {
+    fn new(stream: Box<dyn SmtpStream>) -> Self {
+        Self { stream: BufStream::new(stream) }
+    }
+
+    // Send a command and check the class of the reply, e.g. 2xx for 250
+    async fn command(
+        &mut self,
+        command: &str,
+        expected: u16,
+    ) -> anyhow::Result<()> {
+        self.stream.write_all(command.as_bytes()).await?;
+        self.stream.write_all(b"\r\n").await?;
+        self.stream.flush().await?;
+        // Only the verb, AUTH carries the password
+        let verb = command.split(' ').next().unwrap_or(command);
+        self.expect(expected).await.with_context(|| format!("{} refused", verb))
+    }
+
+    async fn expect(&mut self, expected: u16) -> anyhow::Result<()> {
+        let (code, text) = self.reply().await?;
+        if code / 100 != expected / 100 {
+            bail!("server replied {} {}", code, text);
+        }
+        Ok(())
+    }
+
+    // Read a reply, joining the lines of multi-line replies
+    async fn reply(&mut self) -> anyhow::Result<(u16, String)> {
+        let mut text = String::new();
+        loop {
+            let mut line = String::new();
+            if self.stream.read_line(&mut line).await? == 0 {
+                bail!("connection closed");
+            }
+            let line = line.trim_end();
+            let code = line
+                .get(..3)
+                .and_then(|code| code.parse::<u16>().ok())
+                .ok_or_else(|| anyhow!("malformed reply {:?}", line))?;
+            text.push_str(line.get(4..).unwrap_or_default());
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return Ok((code, text));
+            }
+            text.push(' ');
+        }
+    }
+}
+
+async fn tls<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: S,
+    host: &str,
+) -> anyhow::Result<tokio_rustls::client::TlsStream<S>> {
+    let mut roots = RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    let config = ClientConfig::builder_with_provider(Arc::new(
+        crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()?
+    .with_root_certificates(roots)
+    .with_no_client_auth();
+    let name = ServerName::try_from(host.to_string())?;
+    TlsConnector::from(Arc::new(config))
+        .connect(name, stream)
+        .await
+        .context("TLS handshake failed")
+}
+
+// Headers and body as sent after DATA, lines starting with a dot escaped
+// and the terminating dot appended
+fn message(from: &str, mail: &Mail<'_>) -> Zeroizing<String> {
+    let mut message = Zeroizing::new(format!(
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\n\
+         MIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\n\
+         Content-Transfer-Encoding: 8bit\r\n\r\n",
+        from,
+        mail.to.join(", "),
+        mail.subject,
+        Utc::now().to_rfc2822()
+    ));
+    for line in mail.body.lines() {
+        if line.starts_with('.') {
+            message.push('.');
+        }
+        message.push_str(line);
+        message.push_str("\r\n");
+    }
+    message.push_str(".\r\n");
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_message_is_handed_to_the_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // Plays the server, answering every command and keeping the
+        // conversation
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufStream::new(stream);
+            let mut transcript = String::new();
+            stream.write_all(b"220 test ESMTP\r\n").await.unwrap();
+            stream.flush().await.unwrap();
+            let mut data = false;
+            loop {
+                let mut line = String::new();
+                if stream.read_line(&mut line).await.unwrap() == 0 {
+                    break;
+                }
+                transcript.push_str(&line);
+                let reply: &[u8] = match line.as_str() {
+                    ".\r\n" if data => {
+                        data = false;
+                        b"250 queued\r\n"
+                    }
+                    _ if data => continue,
+                    l if l.starts_with("EHLO") => {
+                        b"250-test\r\n250 AUTH PLAIN\r\n"
+                    }
+                    l if l.starts_with("AUTH") => b"235 ok\r\n",
+                    "DATA\r\n" => {
+                        data = true;
+                        b"354 go ahead\r\n"
+                    }
+                    "QUIT\r\n" => b"221 bye\r\n",
+                    _ => b"250 ok\r\n",
+                };
+                stream.write_all(reply).await.unwrap();
+                stream.flush().await.unwrap();
+            }
+            transcript
+        });
+
+        let settings = EmailDeliverySettings {
+            to: vec!["ops@example.com".to_string()],
+            from: "sftp@example.com".to_string(),
+            smtp_host: "127.0.0.1".to_string(),
+            smtp_port: port,
+            smtp_tls: SmtpTls::None,
+            username: "mailer".to_string(),
+            password: Some("secret".to_string().into()),
+            password_file: None,
+        };
+        let mail = Mail {
+            to: &settings.to,
+            subject: "Credentials",
+            body: "Username: alice\n.hidden\n",
+        };
+        send(&settings, &mail).await.unwrap();
+
+        let transcript = server.await.unwrap();
+        assert!(transcript.starts_with("EHLO example.com\r\n"));
+        let token = STANDARD.encode("\0mailer\0secret");
+        assert!(transcript.contains(&format!("AUTH PLAIN {}\r\n", token)));
+        assert!(transcript.contains("MAIL FROM:<sftp@example.com>\r\n"));
+        assert!(transcript.contains("RCPT TO:<ops@example.com>\r\n"));
+        assert!(transcript.contains("Subject: Credentials\r\n"));
+        assert!(
+            transcript.contains("\r\nUsername: alice\r\n..hidden\r\n.\r\n")
+        );
+        assert!(transcript.ends_with("QUIT\r\n"));
+    }
+}
@@ -0,0 +1,451 @@
+use crate::models::sftp::{CredentialRotation, SftpCredentials};
+use anyhow::bail;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use tokio::io::AsyncWriteExt;
+use tracing::debug;
+
+// Format version written by this release
+pub const STATE_VERSION: u64 = 3;
+
+// Upgrade steps for stored state, indexed by the version they upgrade
+// from. Never edit a released step; bump STATE_VERSION and append one.
+const STATE_UPGRADES: &[fn(&mut Value)] = &[
+    // 0 -> 1: state saved before versioning has the same fields
+    |_| {},
+    // 1 -> 2: history of generated passwords
+    |value| {
+        if let Some(map) = value.as_object_mut() {
+            map.entry("password_history")
+                .or_insert_with(|| Value::Array(vec![]));
+        }
+    },
+    // 2 -> 3: IPs banned after failed logins
+    |value| {
+        if let Some(map) = value.as_object_mut() {
+            map.entry("bans").or_insert_with(|| Value::Array(vec![]));
+        }
+    },
+];
+
+// SFTP state that survives a process restart
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PersistedState {
+    // Format of the stored document, see STATE_UPGRADES
+    #[serde(default)]
+    pub version: u64,
+    // Counts the saves; a store shared by replicas only accepts a save
+    // made on top of the previous revision
+    #[serde(default)]
+    pub revision: u64,
+    pub enabled: bool,
+    pub credentials: Option<SftpCredentials>,
+    // Credentials encrypted with the configured secret key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sealed_credentials: Option<String>,
+    // Seconds since the Unix epoch
+    pub expires_at: Option<u64>,
+    #[serde(default)]
+    pub password_history: Vec<CredentialRotation>,
+    // Shared so a client banned by one replica is refused by all of them
+    #[serde(default)]
+    pub bans: Vec<BannedIp>,
+}
+
+// An IP refused after failed logins, until the given seconds since the
+// Unix epoch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BannedIp {
+    pub ip: IpAddr,
+    pub until: u64,
+}
+
+impl From<(IpAddr, SystemTime)> for BannedIp {
+    fn from((ip, until): (IpAddr, SystemTime)) -> Self {
+        let until = until
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        Self { ip, until }
+    }
+}
+
+impl Default for PersistedState {
+    fn default() -> Self {
+        Self {
+            version: STATE_VERSION,
+            revision: 0,
+            enabled: false,
+            credentials: None,
+            sealed_credentials: None,
+            expires_at: None,
+            password_history: Vec::new(),
+            bans: Vec::new(),
+        }
+    }
+}
+
+impl PersistedState {
+    // Parse a stored document, upgrading it from older formats first
+    pub fn from_json(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut value: Value = serde_json::from_slice(bytes)?;
+        upgrade(&mut value)?;
+        Ok(serde_json::from_value(value)?)
+    }
+
+    pub fn expiration(&self) -> Option<SystemTime> {
+        self.expires_at
+            .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    pub fn ban_list(&self) -> Vec<(IpAddr, SystemTime)> {
+        self.bans
+            .iter()
+            .map(|ban| {
+                (
+                    ban.ip,
+                    SystemTime::UNIX_EPOCH + Duration::from_secs(ban.until),
+                )
+            })
+            .collect()
+    }
+}
+
+// A save refused because another replica saved the state first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaleRevision {
+    pub revision: u64,
+}
+
+impl StaleRevision {
+    pub fn find(error: &anyhow::Error) -> Option<&StaleRevision> {
+        error.chain().find_map(|e| e.downcast_ref::<StaleRevision>())
+    }
+}
+
+impl fmt::Display for StaleRevision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "stored SFTP state changed before revision {} was saved",
+            self.revision
+        )
+    }
+}
+
+impl std::error::Error for StaleRevision {}
+
+// Run every upgrade step between the document's version and the current one
+fn upgrade(value: &mut Value) -> anyhow::Result<()> {
+    let Some(map) = value.as_object_mut() else {
+        bail!("stored SFTP state is not a JSON object");
+    };
+    let version = map.get("version").and_then(Value::as_u64).unwrap_or(0);
+    if version > STATE_VERSION {
+        bail!(
+            "stored SFTP state has version {}, this release supports up to {}",
+            version,
+            STATE_VERSION
+        );
+    }
+
+    for (from, step) in STATE_UPGRADES.iter().enumerate().skip(version as usize)
+    {
+        debug!("Upgrading stored SFTP state from version {}", from);
+        step(value);
+    }
+    if let Some(map) = value.as_object_mut() {
+        map.insert("version".to_string(), STATE_VERSION.into());
+    }
+    Ok(())
+}
+
+// Somewhere the SFTP state is kept between restarts
+#[async_trait]
+pub trait StateBackend: Send + Sync {
+    // Human readable location, for log messages
+    fn describe(&self) -> String;
+
+    // Read the stored state; `None` means nothing was saved yet
+    async fn load(&self) -> anyhow::Result<Option<PersistedState>>;
+
+    // Store `state`. A backend shared by replicas refuses it with
+    // StaleRevision unless the stored revision is the one before it.
+    async fn save(&self, state: &PersistedState) -> anyhow::Result<()>;
+
+    // Store the bans alone, leaving enabled state and credentials as
+    // they are
+    async fn save_bans(&self, bans: &[BannedIp]) -> anyhow::Result<()> {
+        let mut state = self.load().await?.unwrap_or_default();
+        state.revision += 1;
+        state.bans = bans.to_vec();
+        self.save(&state).await
+    }
+}
+
+// Stores the SFTP state as a JSON file, replaced atomically on every save
+pub struct FileStateStore {
+    path: PathBuf,
+}
+
+impl FileStateStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    fn read(&self) -> anyhow::Result<Option<PersistedState>> {
+        match std::fs::read(&self.path) {
+            Ok(bytes) => PersistedState::from_json(&bytes).map(Some),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // Write to a temporary file and rename it over the old one so a crash
+    // never leaves a truncated state file behind
+    async fn write(&self, state: &PersistedState) -> io::Result<()> {
+        if let Some(parent) = self.path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let body = serde_json::to_vec_pretty(state)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let tmp = self.path.with_extension("tmp");
+
+        let mut options = tokio::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        // The file holds the SFTP password
+        #[cfg(unix)]
+        options.mode(0o600);
+
+        let mut file = options.open(&tmp).await?;
+        file.write_all(&body).await?;
+        file.sync_all().await?;
+        drop(file);
+
+        tokio::fs::rename(&tmp, &self.path).await?;
+        debug!("Saved SFTP state to {}", self.path.display());
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl StateBackend for FileStateStore {
+    fn describe(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    async fn load(&self) -> anyhow::Result<Option<PersistedState>> {
+        self.read()
+    }
+
+    async fn save(&self, state: &PersistedState) -> anyhow::Result<()> {
+        Ok(self.write(state).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::sftp::{ListenAddress, SftpState};
+    use crate::sftp::{AuthFailureLimits, AuthFailureTracker};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_save_and_load_roundtrip() {
+        let dir = std::env::temp_dir()
+            .join(format!("sftp-manager-state-{}", std::process::id()));
+        let store = FileStateStore::new(dir.join("state.json"));
+        assert!(store.load().await.unwrap().is_none());
+
+        let state = PersistedState {
+            enabled: true,
+            credentials: Some(SftpCredentials::new(
+                "user".to_string(),
+                "secret".to_string(),
+            )),
+            expires_at: Some(1_700_000_000),
+            ..Default::default()
+        };
+        store.save(&state).await.unwrap();
+
+        let loaded = store.load().await.unwrap().unwrap();
+        assert!(loaded.enabled);
+        assert_eq!(loaded.expiration(), state.expiration());
+        assert_eq!(loaded.credentials.unwrap().username, "user");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    // State shared by every replica the way the Redis store keeps it: one
+    // JSON document saved on top of its previous revision, bans apart
+    #[derive(Default)]
+    struct SharedBackend {
+        document: std::sync::Mutex<Option<String>>,
+        bans: std::sync::Mutex<Vec<BannedIp>>,
+    }
+
+    #[async_trait]
+    impl StateBackend for SharedBackend {
+        fn describe(&self) -> String {
+            "shared test backend".to_string()
+        }
+
+        async fn load(&self) -> anyhow::Result<Option<PersistedState>> {
+            let body = self.document.lock().unwrap().clone();
+            let mut state = body
+                .map(|b| PersistedState::from_json(b.as_bytes()))
+                .transpose()?
+                .unwrap_or_default();
+            state.bans = self.bans.lock().unwrap().clone();
+            Ok(Some(state))
+        }
+
+        async fn save(&self, state: &PersistedState) -> anyhow::Result<()> {
+            let mut document = self.document.lock().unwrap();
+            let stored = document
+                .as_ref()
+                .map(|b| PersistedState::from_json(b.as_bytes()))
+                .transpose()?
+                .map_or(0, |s| s.revision);
+            if stored + 1 != state.revision {
+                return Err(StaleRevision { revision: state.revision }.into());
+            }
+            *document = Some(serde_json::to_string(&PersistedState {
+                bans: Vec::new(),
+                ..state.clone()
+            })?);
+            Ok(())
+        }
+
+        async fn save_bans(&self, bans: &[BannedIp]) -> anyhow::Result<()> {
+            *self.bans.lock().unwrap() = bans.to_vec();
+            Ok(())
+        }
+    }
+
+    fn replica(
+        backend: &Arc<dyn StateBackend>,
+    ) -> (SftpState, AuthFailureTracker) {
+        let tracker = AuthFailureTracker::new(AuthFailureLimits {
+            threshold: 0,
+            per_ip_threshold: 1,
+            per_user_threshold: 0,
+            window: Duration::from_secs(60),
+            ban: Duration::from_secs(600),
+        });
+        let state = SftpState::new(ListenAddress {
+            bind_addrs: "0.0.0.0".to_string(),
+            port: 2222,
+        })
+        .with_store(backend.clone())
+        .with_bans(tracker.clone());
+        (state, tracker)
+    }
+
+    // Ban `ip` on a replica and wait until the ban reached the store
+    async fn ban(
+        tracker: &AuthFailureTracker,
+        backend: &Arc<dyn StateBackend>,
+        ip: IpAddr,
+    ) {
+        tracker.record_failure("root", Some(ip));
+        for _ in 0..100 {
+            let stored = backend.load().await.unwrap().unwrap();
+            if stored.bans.iter().any(|b| b.ip == ip) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("ban of {} was not saved", ip);
+    }
+
+    #[tokio::test]
+    async fn test_replicas_share_credentials_and_bans() {
+        let backend: Arc<dyn StateBackend> = Arc::new(SharedBackend::default());
+        let (first, first_bans) = replica(&backend);
+        let (second, second_bans) = replica(&backend);
+        let _saver = first.persist_bans().unwrap();
+
+        first
+            .enable(
+                SftpCredentials::new(
+                    "shared".to_string(),
+                    "secret".to_string(),
+                ),
+                None,
+            )
+            .await;
+        let ip: IpAddr = "203.0.113.9".parse().unwrap();
+        ban(&first_bans, &backend, ip).await;
+
+        // What the Redis subscription does when the first replica publishes
+        second.restore().await;
+        assert!(second.is_enabled().await);
+        assert_eq!(second.get_credentials().await.unwrap().username, "shared");
+        assert!(second_bans.is_banned(ip));
+
+        second.disable().await;
+        first.restore().await;
+        assert!(!first.is_enabled().await);
+    }
+
+    #[tokio::test]
+    async fn test_stale_replica_cannot_bring_back_revoked_credentials() {
+        let backend: Arc<dyn StateBackend> = Arc::new(SharedBackend::default());
+        let (first, first_bans) = replica(&backend);
+        let (second, second_bans) = replica(&backend);
+        let _saver = second.persist_bans().unwrap();
+
+        first
+            .enable(
+                SftpCredentials::new("old".to_string(), "secret".to_string()),
+                None,
+            )
+            .await;
+        second.restore().await;
+        // The second replica misses this disable
+        first.disable().await;
+
+        // Its ban is shared without writing its stale credentials back
+        let ip: IpAddr = "203.0.113.10".parse().unwrap();
+        ban(&second_bans, &backend, ip).await;
+        first.restore().await;
+        assert!(!first.is_enabled().await);
+        assert!(first_bans.is_banned(ip));
+
+        // A rotation on top of the stale state is refused and the second
+        // replica takes over the stored state instead
+        second
+            .rotate_credentials(SftpCredentials::new(
+                "new".to_string(),
+                "secret".to_string(),
+            ))
+            .await;
+        assert!(!second.is_enabled().await);
+        assert!(second.get_credentials().await.is_none());
+        first.restore().await;
+        assert!(!first.is_enabled().await);
+    }
+
+    #[test]
+    fn test_unversioned_state_is_upgraded() {
+        let loaded = PersistedState::from_json(
+            br#"{"enabled":false,"credentials":null,"expires_at":null}"#,
+        )
+        .unwrap();
+        assert_eq!(loaded.version, STATE_VERSION);
+
+        let newer =
+            format!(r#"{{"version":{},"enabled":false}}"#, STATE_VERSION + 1);
+        assert!(PersistedState::from_json(newer.as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,202 @@
+use crate::models::sftp::{
+    CredentialsAccessor, CredentialsResponse, InstanceStatus,
+    ToggleSftpResponse,
+};
+use crate::responses::sftp::SftpApiResponse;
+use crate::services::files::FileService;
+use crate::services::sftp_lifecycle::{
+    SftpLifecycleHandle, start_sftp_lifecycle,
+};
+use crate::services::sftp_service::SftpService;
+use axum::http::StatusCode;
+use futures_util::future::join_all;
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use tracing::info;
+
+// Name of the instance configured under [sftp]
+pub const DEFAULT_INSTANCE: &str = "default";
+
+// Runs a lifecycle manager per SFTP instance. Each instance reconciles its
+// own desired and actual state and has its own restart policy.
+pub struct SftpSupervisor {
+    instances: Vec<(String, Arc<SftpService>)>,
+    handles: Mutex<Vec<SftpLifecycleHandle>>,
+}
+
+impl SftpSupervisor {
+    pub fn new() -> Self {
+        Self { instances: Vec::new(), handles: Mutex::new(Vec::new()) }
+    }
+
+    // Start managing the server described by `service`
+    pub fn add(&mut self, name: &str, service: Arc<SftpService>) {
+        self.add_with_listener(name, service, None);
+    }
+
+    // Start managing a server, serving on `listener` when systemd passed
+    // one instead of binding the configured address
+    pub fn add_with_listener(
+        &mut self,
+        name: &str,
+        service: Arc<SftpService>,
+        listener: Option<std::net::TcpListener>,
+    ) {
+        info!("Supervising SFTP instance {}", name);
+        let handle = start_sftp_lifecycle(
+            service.state.clone(),
+            service.root_dir.clone(),
+            service.context.clone(),
+            service.settings.clone(),
+            listener,
+        );
+        self.handles.get_mut().unwrap().push(handle);
+        self.instances.push((name.to_string(), service));
+    }
+
+    // Supervised instances by name, the default one first
+    pub fn instances(&self) -> &[(String, Arc<SftpService>)] {
+        &self.instances
+    }
+
+    fn get(&self, name: &str) -> Option<&Arc<SftpService>> {
+        self.instances.iter().find(|(n, _)| n == name).map(|(_, s)| s)
+    }
+
+    // File operations confined to the root directory of an instance
+    pub fn files(&self, name: &str) -> Option<FileService> {
+        self.get(name).map(|service| {
+            FileService::new(
+                service.root_dir.clone(),
+                service.settings.clone(),
+                service.context.clone(),
+            )
+        })
+    }
+
+    pub async fn list(&self) -> SftpApiResponse<Vec<InstanceStatus>> {
+        let mut statuses = Vec::with_capacity(self.instances.len());
+        for (name, service) in &self.instances {
+            statuses.push(service.instance_status(name).await);
+        }
+        SftpApiResponse::success(statuses)
+    }
+
+    pub async fn status(&self, name: &str) -> SftpApiResponse<InstanceStatus> {
+        match self.get(name) {
+            Some(service) => {
+                SftpApiResponse::success(service.instance_status(name).await)
+            }
+            None => unknown_instance(name),
+        }
+    }
+
+    pub async fn toggle(
+        &self,
+        name: &str,
+        accessor: CredentialsAccessor,
+    ) -> SftpApiResponse<ToggleSftpResponse> {
+        match self.get(name) {
+            Some(service) => service.toggle(name, accessor).await,
+            None => unknown_instance(name),
+        }
+    }
+
+    pub async fn credentials(
+        &self,
+        name: &str,
+        accessor: CredentialsAccessor,
+    ) -> Result<SftpApiResponse<CredentialsResponse>, SftpApiResponse<()>> {
+        match self.get(name) {
+            Some(service) => service.get_credentials(name, accessor).await,
+            None => Err(unknown_instance(name)),
+        }
+    }
+
+    // Stop every instance, letting their transfers finish
+    pub async fn shutdown(&self) {
+        let handles = std::mem::take(&mut *self.handles.lock().unwrap());
+        join_all(handles.into_iter().map(|h| h.shutdown())).await;
+    }
+}
+
+pub fn unknown_instance<T: Serialize>(name: &str) -> SftpApiResponse<T> {
+    SftpApiResponse::error(
+        StatusCode::NOT_FOUND,
+        format!("Unknown SFTP instance '{}'", name),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::settings::Settings;
+    use crate::models::sftp::{ListenAddress, SftpState};
+    use crate::sftp::ServerContext;
+    use std::time::Duration;
+    use tokio::net::TcpStream;
+    use tokio::sync::watch;
+
+    fn instance(port: u16) -> Arc<SftpService> {
+        let (_tx, settings) = watch::channel(Settings::default());
+        Arc::new(SftpService::new(
+            std::env::temp_dir().to_string_lossy().to_string(),
+            SftpState::new(ListenAddress {
+                bind_addrs: "127.0.0.1".to_string(),
+                port,
+            }),
+            ServerContext::for_tests(),
+            settings,
+        ))
+    }
+
+    fn free_port() -> u16 {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port()
+    }
+
+    fn accessor() -> CredentialsAccessor {
+        CredentialsAccessor {
+            api: "rest",
+            client: None,
+            forwarded_for: None,
+            certificate: None,
+            api_key: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_instances_are_reconciled_separately() {
+        let (port_a, port_b) = (free_port(), free_port());
+        let mut supervisor = SftpSupervisor::new();
+        supervisor.add("a", instance(port_a));
+        supervisor.add("b", instance(port_b));
+
+        let toggled = supervisor.toggle("a", accessor()).await;
+        assert_eq!(toggled.status, StatusCode::OK);
+
+        let mut running = false;
+        for _ in 0..20 {
+            if supervisor.status("a").await.sftp.unwrap().running {
+                running = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert!(running);
+        assert!(TcpStream::connect(("127.0.0.1", port_a)).await.is_ok());
+
+        // The other instance stays down and keeps its own credentials
+        let other = supervisor.status("b").await.sftp.unwrap();
+        assert!(!other.enabled && !other.running && other.healthy);
+        assert!(TcpStream::connect(("127.0.0.1", port_b)).await.is_err());
+        assert!(supervisor.credentials("a", accessor()).await.is_ok());
+        assert!(supervisor.credentials("b", accessor()).await.is_err());
+
+        let missing = supervisor.status("missing").await;
+        assert_eq!(missing.status, StatusCode::NOT_FOUND);
+
+        supervisor.shutdown().await;
+        assert!(TcpStream::connect(("127.0.0.1", port_a)).await.is_err());
+    }
+}
@@ -0,0 +1,126 @@
+use crate::config::settings::{Settings, TenantSettings};
+use crate::models::accounts::TenantInfo;
+use crate::responses::sftp::SftpApiResponse;
+use crate::services::files::FileService;
+use crate::sftp::{Quota, ServerContext};
+use axum::http::StatusCode;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::watch;
+use tracing::warn;
+
+struct Tenant {
+    settings: TenantSettings,
+    // Measures usage; only enforced when the tenant has a quota
+    quota: Quota,
+    files: Arc<FileService>,
+}
+
+impl Tenant {
+    fn enforced_quota(&self) -> Option<Quota> {
+        (self.settings.quota_bytes > 0).then(|| self.quota.clone())
+    }
+}
+
+// Customers hosted side by side, each confined to its own directory.
+// Tenants are read once at startup; changing them requires a restart.
+pub struct TenantService {
+    tenants: Vec<Tenant>,
+}
+
+impl TenantService {
+    pub fn new(
+        settings: &Settings,
+        context: &ServerContext,
+        settings_rx: watch::Receiver<Settings>,
+    ) -> Self {
+        let tenants = settings
+            .tenants
+            .iter()
+            .map(|tenant| {
+                let limit = match tenant.quota_bytes {
+                    0 => u64::MAX,
+                    bytes => bytes,
+                };
+                let quota = Quota::new(limit);
+                let context = ServerContext {
+                    quota: (tenant.quota_bytes > 0).then(|| quota.clone()),
+                    ..context.clone()
+                };
+                Tenant {
+                    settings: tenant.clone(),
+                    quota,
+                    files: Arc::new(FileService::new(
+                        tenant.root_dir.clone(),
+                        settings_rx.clone(),
+                        context,
+                    )),
+                }
+            })
+            .collect();
+        Self { tenants }
+    }
+
+    // Quota of the tenant served by an SFTP instance, shared with the
+    // tenant's API so both count against the same limit
+    pub fn instance_quota(&self, instance: &str) -> Option<Quota> {
+        self.tenants
+            .iter()
+            .find(|t| t.settings.instance.as_deref() == Some(instance))
+            .and_then(Tenant::enforced_quota)
+    }
+
+    pub fn exists(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    // File operations confined to the tenant's directory
+    pub fn files(&self, name: &str) -> Option<Arc<FileService>> {
+        self.get(name).map(|t| t.files.clone())
+    }
+
+    pub async fn list(&self) -> SftpApiResponse<Vec<TenantInfo>> {
+        let mut tenants = Vec::with_capacity(self.tenants.len());
+        for tenant in &self.tenants {
+            tenants.push(info(tenant).await);
+        }
+        SftpApiResponse::success(tenants)
+    }
+
+    pub async fn status(&self, name: &str) -> SftpApiResponse<TenantInfo> {
+        match self.get(name) {
+            Some(tenant) => SftpApiResponse::success(info(tenant).await),
+            None => unknown_tenant(name),
+        }
+    }
+
+    fn get(&self, name: &str) -> Option<&Tenant> {
+        self.tenants.iter().find(|t| t.settings.name == name)
+    }
+}
+
+async fn info(tenant: &Tenant) -> TenantInfo {
+    let settings = &tenant.settings;
+    let root = Path::new(&settings.root_dir);
+    let used_bytes = match tenant.quota.used(root).await {
+        Ok(bytes) => Some(bytes),
+        Err(e) => {
+            warn!("Cannot measure usage of tenant {}: {}", settings.name, e);
+            None
+        }
+    };
+    TenantInfo {
+        name: settings.name.clone(),
+        root_dir: settings.root_dir.clone(),
+        quota_bytes: (settings.quota_bytes > 0).then_some(settings.quota_bytes),
+        used_bytes,
+        instance: settings.instance.clone(),
+    }
+}
+
+pub fn unknown_tenant<T: serde::Serialize>(name: &str) -> SftpApiResponse<T> {
+    SftpApiResponse::error(
+        StatusCode::NOT_FOUND,
+        format!("Unknown tenant '{}'", name),
+    )
+}
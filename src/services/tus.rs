@@ -0,0 +1,568 @@
+use crate::config::settings::{Settings, TusSettings};
+use crate::responses::sftp::SftpApiResponse;
+use crate::services::files::FileService;
+use crate::services::upload_limits::{UploadAborted, UploadLimiter};
+use crate::sftp::sparse;
+use axum::body::Body;
+use axum::http::{HeaderMap, HeaderName, HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use rand::RngExt;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+// Protocol version implemented, the only one accepted
+const TUS_VERSION: &str = "1.0.0";
+const TUS_EXTENSIONS: &str = "creation,expiration,termination";
+// Content type of PATCH requests
+const OFFSET_STREAM: &str = "application/offset+octet-stream";
+
+// How often expired uploads are deleted
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+const TUS_RESUMABLE: HeaderName = HeaderName::from_static("tus-resumable");
+const TUS_VERSION_HEADER: HeaderName = HeaderName::from_static("tus-version");
+const TUS_EXTENSION: HeaderName = HeaderName::from_static("tus-extension");
+const TUS_MAX_SIZE: HeaderName = HeaderName::from_static("tus-max-size");
+const UPLOAD_LENGTH: HeaderName = HeaderName::from_static("upload-length");
+const UPLOAD_OFFSET: HeaderName = HeaderName::from_static("upload-offset");
+const UPLOAD_METADATA: HeaderName = HeaderName::from_static("upload-metadata");
+const UPLOAD_EXPIRES: HeaderName = HeaderName::from_static("upload-expires");
+
+// An unfinished upload, stored as `<id>.json` next to its data in
+// `<id>.bin`. The offset is the size of the data file, so it survives
+// restarts and interrupted requests.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TusUpload {
+    id: String,
+    // Client path of the finished file
+    path: String,
+    length: u64,
+    overwrite: bool,
+    // Upload-Metadata as sent at creation, base64 encoded
+    #[serde(default)]
+    metadata: String,
+    created_at: DateTime<Utc>,
+}
+
+type Reply = Result<Response, (StatusCode, String)>;
+
+// Resumable uploads into the default SFTP root following the TUS 1.0
+// protocol. Finished uploads are handed to the file service and so show
+// up in the transfer log and the upload pipeline like any other upload.
+pub struct TusService {
+    files: Arc<FileService>,
+    uploads: Arc<UploadLimiter>,
+    settings: watch::Receiver<Settings>,
+    // Uploads with a request in progress
+    busy: Arc<Mutex<HashSet<String>>>,
+}
+
+// Marks an upload as busy until dropped
+struct BusyGuard {
+    busy: Arc<Mutex<HashSet<String>>>,
+    id: String,
+}
+
+impl Drop for BusyGuard {
+    fn drop(&mut self) {
+        self.busy.lock().unwrap().remove(&self.id);
+    }
+}
+
+impl TusService {
+    pub fn new(
+        files: Arc<FileService>,
+        uploads: Arc<UploadLimiter>,
+        settings: watch::Receiver<Settings>,
+    ) -> Self {
+        Self { files, uploads, settings, busy: Arc::default() }
+    }
+
+    // Delete uploads that were not continued within the expiry period. The
+    // directory and period are re-read before every run.
+    pub fn start_purging(&self) -> JoinHandle<()> {
+        let settings = self.settings.clone();
+        tokio::spawn(async move {
+            loop {
+                let tus = settings.borrow().tus.clone();
+                if tus.enabled {
+                    match purge_expired(&tus).await {
+                        Ok(0) => {}
+                        Ok(purged) => {
+                            info!("Deleted {} expired TUS upload(s)", purged)
+                        }
+                        Err(e) => error!("Failed to purge TUS uploads: {}", e),
+                    }
+                }
+                tokio::time::sleep(PURGE_INTERVAL).await;
+            }
+        })
+    }
+
+    pub fn options(&self) -> Response {
+        self.respond(Ok(self.capabilities(StatusCode::NO_CONTENT)))
+    }
+
+    pub async fn create(&self, headers: &HeaderMap) -> Response {
+        self.respond(self.try_create(headers).await)
+    }
+
+    pub async fn head(&self, id: &str) -> Response {
+        self.respond(self.try_head(id).await)
+    }
+
+    pub async fn patch(
+        &self,
+        id: &str,
+        headers: &HeaderMap,
+        body: Body,
+    ) -> Response {
+        self.respond(self.try_patch(id, headers, body).await)
+    }
+
+    pub async fn delete(&self, id: &str, headers: &HeaderMap) -> Response {
+        self.respond(self.try_delete(id, headers).await)
+    }
+
+    // Turn errors into JSON responses; every response names the protocol
+    // version, and a version mismatch lists the supported one
+    fn respond(&self, reply: Reply) -> Response {
+        let reply = if self.settings.borrow().tus.enabled {
+            reply
+        } else {
+            Err((StatusCode::NOT_FOUND, "TUS uploads are disabled".to_string()))
+        };
+        let mut response = match reply {
+            Ok(response) => response,
+            Err((status, message)) => {
+                let mut response =
+                    SftpApiResponse::<()>::error(status, message)
+                        .into_response();
+                if status == StatusCode::PRECONDITION_FAILED {
+                    response.headers_mut().insert(
+                        TUS_VERSION_HEADER,
+                        HeaderValue::from_static(TUS_VERSION),
+                    );
+                }
+                response
+            }
+        };
+        response
+            .headers_mut()
+            .insert(TUS_RESUMABLE, HeaderValue::from_static(TUS_VERSION));
+        response
+    }
+
+    fn capabilities(&self, status: StatusCode) -> Response {
+        let max_bytes = self.settings.borrow().tus.max_bytes;
+        let mut response = status.into_response();
+        let headers = response.headers_mut();
+        headers
+            .insert(TUS_VERSION_HEADER, HeaderValue::from_static(TUS_VERSION));
+        headers.insert(TUS_EXTENSION, HeaderValue::from_static(TUS_EXTENSIONS));
+        if max_bytes > 0 {
+            headers.insert(TUS_MAX_SIZE, HeaderValue::from(max_bytes));
+        }
+        response
+    }
+
+    async fn try_create(&self, headers: &HeaderMap) -> Reply {
+        check_version(headers)?;
+        let tus = self.settings.borrow().tus.clone();
+        let length: u64 = header_value(headers, &UPLOAD_LENGTH)
+            .and_then(|v| v.parse().ok())
+            .ok_or_else(|| bad_request("Upload-Length is required"))?;
+        if tus.max_bytes > 0 && length > tus.max_bytes {
+            return Err((
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("Uploads are limited to {} bytes", tus.max_bytes),
+            ));
+        }
+
+        let encoded = header_value(headers, &UPLOAD_METADATA).unwrap_or("");
+        let metadata = parse_metadata(encoded)
+            .ok_or_else(|| bad_request("Invalid Upload-Metadata"))?;
+        let path = match (metadata.get("path"), metadata.get("filename")) {
+            (Some(path), _) => path.clone(),
+            (None, Some(filename)) if !filename.contains('/') => {
+                format!("/{}", filename)
+            }
+            _ => {
+                return Err(bad_request(
+                    "Upload-Metadata must contain path or filename",
+                ));
+            }
+        };
+        let overwrite = metadata.get("overwrite").is_some_and(|v| v == "true");
+        let (path, _) = self.files.upload_target(&path, overwrite).await?;
+
+        let upload = TusUpload {
+            id: format!("{:032x}", rand::rng().random::<u128>()),
+            path,
+            length,
+            overwrite,
+            metadata: encoded.to_string(),
+            created_at: Utc::now(),
+        };
+        let dir = PathBuf::from(&tus.dir);
+        let stored = async {
+            fs::create_dir_all(&dir).await?;
+            fs::File::create_new(dir.join(format!("{}.bin", upload.id)))
+                .await?;
+            fs::write(
+                dir.join(format!("{}.json", upload.id)),
+                serde_json::to_vec(&upload)?,
+            )
+            .await
+        }
+        .await;
+        if let Err(e) = stored {
+            return Err(internal("Failed to create upload", e));
+        }
+        info!(
+            "TUS upload {} created for {} ({} bytes)",
+            upload.id, upload.path, upload.length
+        );
+
+        let mut response = StatusCode::CREATED.into_response();
+        let headers = response.headers_mut();
+        let location = format!("/files/tus/{}", upload.id);
+        headers.insert(header::LOCATION, header_text(&location));
+        headers.insert(UPLOAD_OFFSET, HeaderValue::from(0u64));
+        headers.insert(UPLOAD_EXPIRES, expires(&tus));
+        if length == 0 {
+            let _guard = self.lock(&upload.id)?;
+            self.finish(&tus, &upload).await?;
+        }
+        Ok(response)
+    }
+
+    async fn try_head(&self, id: &str) -> Reply {
+        let tus = self.settings.borrow().tus.clone();
+        let upload = load(&tus, id).await?;
+        let offset = offset(&tus, id).await?;
+
+        let mut response = StatusCode::OK.into_response();
+        let headers = response.headers_mut();
+        headers.insert(UPLOAD_OFFSET, HeaderValue::from(offset));
+        headers.insert(UPLOAD_LENGTH, HeaderValue::from(upload.length));
+        if !upload.metadata.is_empty() {
+            headers.insert(UPLOAD_METADATA, header_text(&upload.metadata));
+        }
+        headers.insert(
+            header::CACHE_CONTROL,
+            HeaderValue::from_static("no-store"),
+        );
+        Ok(response)
+    }
+
+    async fn try_patch(
+        &self,
+        id: &str,
+        headers: &HeaderMap,
+        body: Body,
+    ) -> Reply {
+        check_version(headers)?;
+        if header_value(headers, &header::CONTENT_TYPE) != Some(OFFSET_STREAM) {
+            return Err((
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                format!("Content-Type must be {}", OFFSET_STREAM),
+            ));
+        }
+        let requested: u64 = header_value(headers, &UPLOAD_OFFSET)
+            .and_then(|v| v.parse().ok())
+            .ok_or_else(|| bad_request("Upload-Offset is required"))?;
+
+        let tus = self.settings.borrow().tus.clone();
+        let upload = load(&tus, id).await?;
+        let _guard = self.lock(id)?;
+        let current = offset(&tus, id).await?;
+        let body = self
+            .uploads
+            .admit(headers, body)
+            .map_err(|aborted| (aborted.status, aborted.message))?;
+        if requested != current {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("Upload-Offset is {}, not {}", current, requested),
+            ));
+        }
+
+        let data_path = data_file(&tus, id);
+        let mut file = OpenOptions::new()
+            .write(true)
+            .open(&data_path)
+            .await
+            .map_err(|e| internal("Failed to open upload", e))?;
+        let mut offset = current;
+        let sparse = self.files.sparse_files();
+        let mut stream = body.into_data_stream();
+        let mut failure = None;
+        while let Some(chunk) = stream.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                // Keep what arrived so the client can resume from there
+                Err(e) => {
+                    let e = anyhow::Error::new(e);
+                    failure = Some(match UploadAborted::find(&e) {
+                        Some(aborted) => {
+                            (aborted.status, aborted.message.clone())
+                        }
+                        None => {
+                            bad_request(&format!("Upload interrupted: {}", e))
+                        }
+                    });
+                    break;
+                }
+            };
+            if offset + chunk.len() as u64 > upload.length {
+                failure = Some(bad_request("Data exceeds Upload-Length"));
+                break;
+            }
+            if let Err(e) =
+                sparse::write_at(&mut file, offset, &chunk, sparse).await
+            {
+                failure = Some(internal("Failed to write upload", e));
+                break;
+            }
+            offset += chunk.len() as u64;
+        }
+        if let Err(e) = file.flush().await {
+            return Err(internal("Failed to write upload", e));
+        }
+        drop(file);
+        if let Some(failure) = failure {
+            warn!("TUS upload {} stopped at {}: {}", id, offset, failure.1);
+            return Err(failure);
+        }
+
+        if offset == upload.length {
+            self.finish(&tus, &upload).await?;
+        }
+        let mut response = StatusCode::NO_CONTENT.into_response();
+        let headers = response.headers_mut();
+        headers.insert(UPLOAD_OFFSET, HeaderValue::from(offset));
+        headers.insert(UPLOAD_EXPIRES, expires(&tus));
+        Ok(response)
+    }
+
+    async fn try_delete(&self, id: &str, headers: &HeaderMap) -> Reply {
+        check_version(headers)?;
+        let tus = self.settings.borrow().tus.clone();
+        let upload = load(&tus, id).await?;
+        let _guard = self.lock(id)?;
+        remove(&tus, id).await;
+        info!("TUS upload {} for {} terminated", id, upload.path);
+        Ok(StatusCode::NO_CONTENT.into_response())
+    }
+
+    // Move a complete upload into the SFTP root and report it
+    async fn finish(
+        &self,
+        tus: &TusSettings,
+        upload: &TusUpload,
+    ) -> Result<(), (StatusCode, String)> {
+        let target =
+            self.files.upload_target(&upload.path, upload.overwrite).await;
+        let (path, local_path) = match target {
+            Ok(target) => target,
+            // The target was taken meanwhile; the data cannot go anywhere
+            Err(e) => {
+                remove(tus, &upload.id).await;
+                return Err(e);
+            }
+        };
+        let data_path = data_file(tus, &upload.id);
+        if let Err(e) = move_file(&data_path, &local_path).await {
+            return Err(internal("Failed to store upload", e));
+        }
+        remove(tus, &upload.id).await;
+
+        let started = Instant::now()
+            - Utc::now()
+                .signed_duration_since(upload.created_at)
+                .to_std()
+                .unwrap_or_default();
+        self.files
+            .complete_upload(path, local_path, upload.length, started)
+            .await;
+        Ok(())
+    }
+
+    fn lock(&self, id: &str) -> Result<BusyGuard, (StatusCode, String)> {
+        if !self.busy.lock().unwrap().insert(id.to_string()) {
+            return Err((
+                StatusCode::LOCKED,
+                "Another request for this upload is in progress".to_string(),
+            ));
+        }
+        Ok(BusyGuard { busy: self.busy.clone(), id: id.to_string() })
+    }
+}
+
+// Clients must speak the implemented protocol version
+fn check_version(headers: &HeaderMap) -> Result<(), (StatusCode, String)> {
+    if header_value(headers, &TUS_RESUMABLE) != Some(TUS_VERSION) {
+        return Err((
+            StatusCode::PRECONDITION_FAILED,
+            format!("Tus-Resumable must be {}", TUS_VERSION),
+        ));
+    }
+    Ok(())
+}
+
+fn header_value<'a>(
+    headers: &'a HeaderMap,
+    name: &HeaderName,
+) -> Option<&'a str> {
+    headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim)
+}
+
+// Upload-Metadata values have already been checked to be ASCII
+fn header_text(value: &str) -> HeaderValue {
+    HeaderValue::from_str(value).unwrap_or(HeaderValue::from_static(""))
+}
+
+// Upload-Metadata: comma separated keys, each followed by a space and its
+// base64 encoded value unless empty. `None` when malformed.
+fn parse_metadata(header: &str) -> Option<BTreeMap<String, String>> {
+    let mut metadata = BTreeMap::new();
+    for pair in header.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (key, value) = match pair.split_once(' ') {
+            Some((key, encoded)) => {
+                let decoded = STANDARD.decode(encoded.trim()).ok()?;
+                (key, String::from_utf8(decoded).ok()?)
+            }
+            None => (pair, String::new()),
+        };
+        if key.is_empty() || metadata.insert(key.to_string(), value).is_some() {
+            return None;
+        }
+    }
+    Some(metadata)
+}
+
+// When an upload untouched from now on expires, as an HTTP date
+fn expires(tus: &TusSettings) -> HeaderValue {
+    let at = Utc::now() + chrono::Duration::hours(tus.expire_hours as i64);
+    header_text(&at.format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+}
+
+fn bad_request(message: &str) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, message.to_string())
+}
+
+fn internal(message: &str, e: io::Error) -> (StatusCode, String) {
+    error!("{}: {}", message, e);
+    (StatusCode::INTERNAL_SERVER_ERROR, format!("{}: {}", message, e))
+}
+
+fn data_file(tus: &TusSettings, id: &str) -> PathBuf {
+    Path::new(&tus.dir).join(format!("{}.bin", id))
+}
+
+// Upload IDs are generated hex strings; anything else is not ours
+async fn load(
+    tus: &TusSettings,
+    id: &str,
+) -> Result<TusUpload, (StatusCode, String)> {
+    let not_found = || (StatusCode::NOT_FOUND, "No such upload".to_string());
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(not_found());
+    }
+    let info = Path::new(&tus.dir).join(format!("{}.json", id));
+    let data = match fs::read(&info).await {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(not_found());
+        }
+        Err(e) => return Err(internal("Failed to read upload", e)),
+    };
+    serde_json::from_slice(&data)
+        .map_err(|e| internal("Failed to read upload", e.into()))
+}
+
+async fn offset(
+    tus: &TusSettings,
+    id: &str,
+) -> Result<u64, (StatusCode, String)> {
+    fs::metadata(data_file(tus, id))
+        .await
+        .map(|m| m.len())
+        .map_err(|e| internal("Failed to read upload", e))
+}
+
+async fn remove(tus: &TusSettings, id: &str) {
+    let dir = Path::new(&tus.dir);
+    let _ = fs::remove_file(dir.join(format!("{}.json", id))).await;
+    let _ = fs::remove_file(dir.join(format!("{}.bin", id))).await;
+}
+
+// Rename, or copy when the upload directory is on another filesystem
+async fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to).await {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            fs::copy(from, to).await?;
+            fs::remove_file(from).await
+        }
+        Err(e) => Err(e),
+    }
+}
+
+// Delete uploads whose data was last written before the expiry period
+async fn purge_expired(tus: &TusSettings) -> io::Result<usize> {
+    let cutoff = SystemTime::now()
+        - Duration::from_secs(tus.expire_hours.saturating_mul(60 * 60));
+    let mut dir = match fs::read_dir(&tus.dir).await {
+        Ok(dir) => dir,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    let mut purged = 0;
+    while let Some(entry) = dir.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let Some(id) = name.strip_suffix(".json") else { continue };
+        let written = fs::metadata(data_file(tus, id))
+            .await
+            .and_then(|m| m.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        if written < cutoff {
+            remove(tus, id).await;
+            purged += 1;
+        }
+    }
+    Ok(purged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_metadata() {
+        let metadata =
+            parse_metadata("filename cmVwb3J0LmNzdg==, path L2luL3JlcG9ydC5jc3Y=,is_confidential")
+                .unwrap();
+        assert_eq!(metadata["filename"], "report.csv");
+        assert_eq!(metadata["path"], "/in/report.csv");
+        assert_eq!(metadata["is_confidential"], "");
+        assert!(parse_metadata("").unwrap().is_empty());
+
+        assert!(parse_metadata("filename not-base64!").is_none());
+        assert!(parse_metadata("a YQ==,a Yg==").is_none());
+    }
+}
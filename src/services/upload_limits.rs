@@ -0,0 +1,230 @@
+use crate::config::settings::Settings;
+use crate::responses::sftp::SftpApiResponse;
+use axum::body::Body;
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use futures_util::StreamExt;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, watch};
+
+// Why an upload through the API was refused or cut short
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UploadAborted {
+    pub status: StatusCode,
+    pub message: String,
+}
+
+impl UploadAborted {
+    fn new(status: StatusCode, message: String) -> Self {
+        Self { status, message }
+    }
+
+    // The abort behind a failed upload, when the body was cut short by
+    // the limits rather than by the client or the disk
+    pub fn find(error: &anyhow::Error) -> Option<&UploadAborted> {
+        error.chain().find_map(|e| e.downcast_ref::<UploadAborted>())
+    }
+}
+
+impl fmt::Display for UploadAborted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for UploadAborted {}
+
+impl IntoResponse for UploadAborted {
+    fn into_response(self) -> Response {
+        SftpApiResponse::<()>::error(self.status, self.message).into_response()
+    }
+}
+
+// Limits on the request bodies of the upload endpoints, which are
+// streamed to disk rather than buffered: their size, how long the client
+// may leave the connection idle, and how many may be in progress. The size
+// and idle timeout are re-read for every upload.
+#[derive(Clone)]
+pub struct UploadLimiter {
+    settings: watch::Receiver<Settings>,
+    // None when the number of uploads is not limited
+    slots: Option<Arc<Semaphore>>,
+}
+
+impl UploadLimiter {
+    pub fn new(settings: watch::Receiver<Settings>) -> Self {
+        let max = settings.borrow().server.max_concurrent_uploads;
+        let slots = (max > 0).then(|| Arc::new(Semaphore::new(max)));
+        Self { settings, slots }
+    }
+
+    // Admits an upload, refusing it when all slots are taken or its
+    // Content-Length is over the limit. The body returned fails once it
+    // goes past the limit or no data arrives within the idle timeout, and
+    // holds the slot until dropped.
+    pub fn admit(
+        &self,
+        headers: &HeaderMap,
+        body: Body,
+    ) -> Result<Body, UploadAborted> {
+        let (max_bytes, idle_timeout) = {
+            let server = &self.settings.borrow().server;
+            (server.max_upload_bytes, server.upload_idle_timeout_secs)
+        };
+        let declared = headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+        if max_bytes > 0 && declared.is_some_and(|length| length > max_bytes) {
+            return Err(too_large(max_bytes));
+        }
+        let permit = match &self.slots {
+            Some(slots) => {
+                Some(slots.clone().try_acquire_owned().map_err(|_| {
+                    UploadAborted::new(
+                        StatusCode::SERVICE_UNAVAILABLE,
+                        "Too many uploads in progress, retry later".to_string(),
+                    )
+                })?)
+            }
+            None => None,
+        };
+        let idle_timeout = Duration::from_secs(idle_timeout);
+        Ok(limit(body, max_bytes, idle_timeout, permit))
+    }
+}
+
+fn too_large(max_bytes: u64) -> UploadAborted {
+    UploadAborted::new(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        format!("Uploads are limited to {} bytes", max_bytes),
+    )
+}
+
+// State of a limited body; the stream ends after the first error
+struct Limited {
+    stream: axum::body::BodyDataStream,
+    received: u64,
+    failed: bool,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+// `body`, failing past `max_bytes` (0 for no limit) or after
+// `idle_timeout` (zero for none) without data
+fn limit(
+    body: Body,
+    max_bytes: u64,
+    idle_timeout: Duration,
+    permit: Option<OwnedSemaphorePermit>,
+) -> Body {
+    let state = Limited {
+        stream: body.into_data_stream(),
+        received: 0,
+        failed: false,
+        _permit: permit,
+    };
+    let stream =
+        futures_util::stream::unfold(state, move |mut state| async move {
+            if state.failed {
+                return None;
+            }
+            let next = if idle_timeout.is_zero() {
+                state.stream.next().await
+            } else {
+                match tokio::time::timeout(idle_timeout, state.stream.next())
+                    .await
+                {
+                    Ok(next) => next,
+                    Err(_) => {
+                        state.failed = true;
+                        let aborted = UploadAborted::new(
+                            StatusCode::REQUEST_TIMEOUT,
+                            format!(
+                                "No data received for {} seconds",
+                                idle_timeout.as_secs()
+                            ),
+                        );
+                        return Some((Err(axum::Error::new(aborted)), state));
+                    }
+                }
+            };
+            let item = match next? {
+                Ok(chunk) => {
+                    state.received += chunk.len() as u64;
+                    if max_bytes > 0 && state.received > max_bytes {
+                        Err(axum::Error::new(too_large(max_bytes)))
+                    } else {
+                        Ok(chunk)
+                    }
+                }
+                Err(e) => Err(e),
+            };
+            state.failed = item.is_err();
+            Some((item, state))
+        });
+    Body::from_stream(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(
+        max_bytes: u64,
+        idle_secs: u64,
+        concurrent: usize,
+    ) -> UploadLimiter {
+        let mut settings = Settings::default();
+        settings.server.max_upload_bytes = max_bytes;
+        settings.server.upload_idle_timeout_secs = idle_secs;
+        settings.server.max_concurrent_uploads = concurrent;
+        UploadLimiter::new(watch::channel(settings).1)
+    }
+
+    async fn drain(body: Body) -> anyhow::Result<u64> {
+        let mut stream = body.into_data_stream();
+        let mut bytes = 0;
+        while let Some(chunk) = stream.next().await {
+            bytes += chunk?.len() as u64;
+        }
+        Ok(bytes)
+    }
+
+    #[tokio::test]
+    async fn test_uploads_are_cut_at_the_limits() {
+        let uploads = limiter(10, 1, 1);
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_LENGTH, "11".parse().unwrap());
+        let refused = uploads.admit(&headers, Body::empty()).unwrap_err();
+        assert_eq!(refused.status, StatusCode::PAYLOAD_TOO_LARGE);
+
+        // Chunked bodies without a length fail once past the limit
+        let chunks: Vec<Result<&'static [u8], std::io::Error>> =
+            vec![Ok(b"123456"), Ok(b"789012")];
+        let body = Body::from_stream(futures_util::stream::iter(chunks));
+        let body = uploads.admit(&HeaderMap::new(), body).unwrap();
+        // The only slot is taken until the body is dropped
+        let busy = uploads.admit(&HeaderMap::new(), Body::empty());
+        assert_eq!(busy.unwrap_err().status, StatusCode::SERVICE_UNAVAILABLE);
+        let error = drain(body).await.unwrap_err();
+        let aborted = UploadAborted::find(&error).unwrap();
+        assert_eq!(aborted.status, StatusCode::PAYLOAD_TOO_LARGE);
+
+        // Stalled clients time out
+        tokio::time::pause();
+        let stalled = Body::from_stream(futures_util::stream::pending::<
+            Result<&'static [u8], std::io::Error>,
+        >());
+        let body = uploads.admit(&HeaderMap::new(), stalled).unwrap();
+        let error = drain(body).await.unwrap_err();
+        let aborted = UploadAborted::find(&error).unwrap();
+        assert_eq!(aborted.status, StatusCode::REQUEST_TIMEOUT);
+
+        let body = Body::from("0123456789");
+        let body = limiter(0, 0, 0).admit(&HeaderMap::new(), body).unwrap();
+        assert_eq!(drain(body).await.unwrap(), 10);
+    }
+}
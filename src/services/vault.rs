@@ -0,0 +1,342 @@
+use crate::config::settings::VaultSettings;
+use crate::services::state_store::{PersistedState, StateBackend};
+use crate::sftp::SecretString;
+use anyhow::{Context, anyhow};
+use async_trait::async_trait;
+use reqwest::header::HeaderValue;
+use reqwest::{Method, StatusCode};
+use russh::keys::PrivateKey;
+use russh::keys::ssh_key::{self, LineEnding, rand_core::OsRng};
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+use zeroize::Zeroizing;
+
+// Secret holding the SFTP host key, below the configured path
+const HOST_KEY_SECRET: &str = "host_key";
+// Renewal is attempted again after this long when it fails
+const RENEW_RETRY: Duration = Duration::from_secs(60);
+
+// Client for Vault's KV version 2 secrets engine. Secrets live below
+// `<mount>/data/<path>/`; the token is renewed before its TTL runs out.
+pub struct VaultClient {
+    http: reqwest::Client,
+    address: String,
+    namespace: String,
+    mount: String,
+    path: String,
+    token: SecretString,
+}
+
+impl VaultClient {
+    // Client for the configured Vault, or `None` when no address is set
+    pub async fn from_settings(
+        settings: &VaultSettings,
+    ) -> anyhow::Result<Option<Arc<Self>>> {
+        if settings.address.is_empty() {
+            return Ok(None);
+        }
+
+        let token = if !settings.token_file.is_empty() {
+            let contents = Zeroizing::new(
+                std::fs::read_to_string(&settings.token_file).with_context(
+                    || format!("cannot read {}", settings.token_file),
+                )?,
+            );
+            SecretString::new(contents.trim().to_string())
+        } else {
+            std::env::var(&settings.token_env)
+                .map_err(|_| anyhow!("{} is not set", settings.token_env))?
+                .into()
+        };
+
+        let client = Self {
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()?,
+            address: settings.address.trim_end_matches('/').to_string(),
+            namespace: settings.namespace.clone(),
+            mount: settings.mount.trim_matches('/').to_string(),
+            path: settings.path.trim_matches('/').to_string(),
+            token,
+        };
+
+        // Fail at startup rather than on the first save
+        let ttl = client.token_ttl().await?;
+        info!(
+            "Connected to Vault at {} (token TTL {})",
+            client.address,
+            ttl.map_or("unlimited".to_string(), |t| format!(
+                "{}s",
+                t.as_secs()
+            ))
+        );
+        Ok(Some(Arc::new(client)))
+    }
+
+    pub fn describe(&self, name: &str) -> String {
+        format!("{}/v1/{}", self.address, self.secret_path("data", name))
+    }
+
+    fn secret_path(&self, kind: &str, name: &str) -> String {
+        format!("{}/{}/{}/{}", self.mount, kind, self.path, name)
+    }
+
+    async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> anyhow::Result<Option<Value>> {
+        // Sensitive headers are left out of Debug output
+        let mut token = HeaderValue::from_str(self.token.expose())?;
+        token.set_sensitive(true);
+        let mut request = self
+            .http
+            .request(method, format!("{}/v1/{}", self.address, path))
+            .header("X-Vault-Token", token);
+        if !self.namespace.is_empty() {
+            request = request.header("X-Vault-Namespace", &self.namespace);
+        }
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+
+        let response = request.send().await?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            StatusCode::NO_CONTENT => Ok(Some(Value::Null)),
+            status if status.is_success() => Ok(Some(response.json().await?)),
+            status => {
+                let body = response.text().await.unwrap_or_default();
+                Err(anyhow!("Vault returned {} for {}: {}", status, path, body))
+            }
+        }
+    }
+
+    // Fields of the latest version of a secret
+    pub async fn read(&self, name: &str) -> anyhow::Result<Option<Value>> {
+        let path = self.secret_path("data", name);
+        let response = self.request(Method::GET, &path, None).await?;
+        Ok(response.and_then(|r| r.pointer("/data/data").cloned()))
+    }
+
+    // Store a new version of a secret
+    pub async fn write(&self, name: &str, data: Value) -> anyhow::Result<()> {
+        let path = self.secret_path("data", name);
+        self.request(Method::POST, &path, Some(json!({ "data": data })))
+            .await?;
+        Ok(())
+    }
+
+    // The host key kept in Vault, generated and stored on first use
+    pub async fn host_key(&self) -> anyhow::Result<PrivateKey> {
+        let mut stored = self.read(HOST_KEY_SECRET).await?;
+        if let Some(Value::String(pem)) = stored
+            .as_mut()
+            .and_then(|s| s.get_mut("private_key"))
+            .map(Value::take)
+        {
+            let pem = Zeroizing::new(pem);
+            return Ok(PrivateKey::from_openssh(pem.as_str())?);
+        }
+
+        let key = PrivateKey::random(&mut OsRng, ssh_key::Algorithm::Ed25519)?;
+        let pem = key.to_openssh(LineEnding::LF)?;
+        self.write(HOST_KEY_SECRET, json!({ "private_key": pem.as_str() }))
+            .await?;
+        info!("Generated host key and stored it in Vault");
+        Ok(key)
+    }
+
+    // Remaining lifetime of the token; `None` when it never expires
+    async fn token_ttl(&self) -> anyhow::Result<Option<Duration>> {
+        let response = self
+            .request(Method::GET, "auth/token/lookup-self", None)
+            .await?
+            .ok_or_else(|| anyhow!("token lookup is not available"))?;
+        let ttl = response.pointer("/data/ttl").and_then(Value::as_u64);
+        let renewable = response
+            .pointer("/data/renewable")
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+
+        match ttl {
+            Some(0) | None => Ok(None),
+            Some(_) if !renewable => {
+                warn!("Vault token is not renewable and will expire");
+                Ok(None)
+            }
+            Some(ttl) => Ok(Some(Duration::from_secs(ttl))),
+        }
+    }
+
+    // Renew the token whenever half of its TTL has passed
+    pub fn renew(self: Arc<Self>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                let wait = match self.token_ttl().await {
+                    Ok(Some(ttl)) => ttl / 2,
+                    Ok(None) => return,
+                    Err(e) => {
+                        error!("Failed to look up Vault token: {}", e);
+                        tokio::time::sleep(RENEW_RETRY).await;
+                        continue;
+                    }
+                };
+                tokio::time::sleep(wait).await;
+
+                match self
+                    .request(
+                        Method::POST,
+                        "auth/token/renew-self",
+                        Some(json!({})),
+                    )
+                    .await
+                {
+                    Ok(_) => info!("Renewed Vault token"),
+                    Err(e) => {
+                        error!("Failed to renew Vault token: {}", e);
+                        tokio::time::sleep(RENEW_RETRY).await;
+                    }
+                }
+            }
+        })
+    }
+}
+
+// Stores the SFTP state as a Vault secret
+pub struct VaultStateStore {
+    client: Arc<VaultClient>,
+    name: String,
+}
+
+impl VaultStateStore {
+    pub fn new(client: Arc<VaultClient>, name: impl Into<String>) -> Self {
+        Self { client, name: name.into() }
+    }
+}
+
+#[async_trait]
+impl StateBackend for VaultStateStore {
+    fn describe(&self) -> String {
+        self.client.describe(&self.name)
+    }
+
+    async fn load(&self) -> anyhow::Result<Option<PersistedState>> {
+        match self.client.read(&self.name).await? {
+            Some(data) => {
+                PersistedState::from_json(&serde_json::to_vec(&data)?).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn save(&self, state: &PersistedState) -> anyhow::Result<()> {
+        self.client.write(&self.name, serde_json::to_value(state)?).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::sftp::SftpCredentials;
+    use axum::Router;
+    use axum::body::Bytes;
+    use axum::extract::State;
+    use axum::http::{HeaderMap, Uri};
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    type Secrets = Arc<Mutex<HashMap<String, Value>>>;
+
+    // Answers like Vault's KV version 2 engine, keeping secrets in memory
+    async fn fake_vault(
+        State(secrets): State<Secrets>,
+        method: Method,
+        uri: Uri,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> (StatusCode, String) {
+        let token = headers.get("X-Vault-Token").and_then(|t| t.to_str().ok());
+        if token != Some("test-token") {
+            return (StatusCode::FORBIDDEN, String::new());
+        }
+        let path = uri.path().trim_start_matches("/v1/").to_string();
+        if path == "auth/token/lookup-self" {
+            let lookup = json!({ "data": { "ttl": 0, "renewable": false } });
+            return (StatusCode::OK, lookup.to_string());
+        }
+
+        let mut secrets = secrets.lock().unwrap();
+        match method {
+            Method::GET => match secrets.get(&path) {
+                Some(data) => {
+                    let body = json!({ "data": { "data": data } });
+                    (StatusCode::OK, body.to_string())
+                }
+                None => (StatusCode::NOT_FOUND, String::new()),
+            },
+            Method::POST => {
+                let body: Value = serde_json::from_slice(&body).unwrap();
+                secrets.insert(path, body["data"].clone());
+                (StatusCode::OK, json!({ "data": {} }).to_string())
+            }
+            _ => (StatusCode::METHOD_NOT_ALLOWED, String::new()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_host_key_and_state_are_kept_in_vault() {
+        let secrets = Secrets::default();
+        let app =
+            Router::new().fallback(fake_vault).with_state(secrets.clone());
+        let listener =
+            tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let token_file = std::env::temp_dir()
+            .join(format!("sftp-manager-vault-token-{}", std::process::id()));
+        std::fs::write(&token_file, "test-token\n").unwrap();
+        let settings = VaultSettings {
+            address,
+            token_file: token_file.display().to_string(),
+            token_env: "SFTP_MANAGER_TEST_UNSET".to_string(),
+            namespace: String::new(),
+            mount: "/secret/".to_string(),
+            path: "sftp-manager".to_string(),
+        };
+        let client =
+            VaultClient::from_settings(&settings).await.unwrap().unwrap();
+        let _ = std::fs::remove_file(&token_file);
+
+        // Generated once, then read back on every start
+        let key = client.host_key().await.unwrap();
+        assert!(
+            secrets
+                .lock()
+                .unwrap()
+                .contains_key("secret/data/sftp-manager/host_key")
+        );
+        let again = client.host_key().await.unwrap();
+        assert_eq!(key.public_key(), again.public_key());
+
+        let store = VaultStateStore::new(client, "state");
+        assert!(store.load().await.unwrap().is_none());
+        let state = PersistedState {
+            enabled: true,
+            credentials: Some(SftpCredentials::new(
+                "vaulted".to_string(),
+                "secret".to_string(),
+            )),
+            ..Default::default()
+        };
+        store.save(&state).await.unwrap();
+        let loaded = store.load().await.unwrap().unwrap();
+        assert!(loaded.enabled);
+        assert_eq!(loaded.credentials.unwrap().username, "vaulted");
+    }
+}
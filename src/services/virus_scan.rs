@@ -0,0 +1,240 @@
+use crate::config::settings::ScanSettings;
+use crate::events::{Event, EventBus};
+use crate::sftp::hooks::{CompletedUpload, HookOutcome, UploadHook};
+use anyhow::{Context, anyhow, bail};
+use async_trait::async_trait;
+use chrono::Utc;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::fs;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpStream, UnixStream};
+use tracing::{error, info, warn};
+
+// Size of the chunks streamed to clamd
+const CHUNK_SIZE: usize = 64 * 1024;
+
+trait ClamdStream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> ClamdStream for T {}
+
+// Result of scanning one file
+#[derive(Debug, PartialEq, Eq)]
+enum Verdict {
+    Clean,
+    // Name of the matched signature
+    Infected(String),
+}
+
+// Scans uploads with clamd and moves infected files into quarantine
+pub struct VirusScanner {
+    address: String,
+    quarantine_dir: PathBuf,
+    timeout: Duration,
+    quarantine_on_error: bool,
+    events: EventBus,
+}
+
+impl VirusScanner {
+    // Scanner for the configured clamd, or `None` when scanning is disabled
+    pub async fn from_settings(
+        settings: &ScanSettings,
+        events: EventBus,
+    ) -> anyhow::Result<Option<Self>> {
+        if settings.clamd_address.is_empty() {
+            return Ok(None);
+        }
+
+        let quarantine_dir = PathBuf::from(&settings.quarantine_dir);
+        fs::create_dir_all(&quarantine_dir).await.with_context(|| {
+            format!("cannot create {}", quarantine_dir.display())
+        })?;
+
+        let scanner = Self {
+            address: settings.clamd_address.clone(),
+            quarantine_dir,
+            timeout: Duration::from_secs(settings.timeout_secs),
+            quarantine_on_error: settings.quarantine_on_error,
+            events,
+        };
+
+        // clamd may start after us, so an unreachable daemon is not fatal
+        match scanner.ping().await {
+            Ok(()) => {
+                info!("Scanning uploads with clamd at {}", scanner.address)
+            }
+            Err(e) => {
+                warn!("clamd at {} is not reachable: {}", scanner.address, e)
+            }
+        }
+        Ok(Some(scanner))
+    }
+
+    async fn connect(&self) -> anyhow::Result<Box<dyn ClamdStream>> {
+        // Paths are unix sockets, anything else is host:port
+        if self.address.starts_with('/') {
+            Ok(Box::new(UnixStream::connect(&self.address).await?))
+        } else {
+            Ok(Box::new(TcpStream::connect(&self.address).await?))
+        }
+    }
+
+    // Read the reply to a command; clamd closes the connection after it
+    async fn read_reply(
+        &self,
+        stream: &mut Box<dyn ClamdStream>,
+    ) -> anyhow::Result<String> {
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply).await?;
+        let reply = String::from_utf8_lossy(&reply);
+        Ok(reply.trim_end_matches(['\0', '\n']).to_string())
+    }
+
+    async fn ping(&self) -> anyhow::Result<()> {
+        let run = async {
+            let mut stream = self.connect().await?;
+            stream.write_all(b"zPING\0").await?;
+            match self.read_reply(&mut stream).await?.as_str() {
+                "PONG" => Ok(()),
+                reply => Err(anyhow!("unexpected reply {:?}", reply)),
+            }
+        };
+        tokio::time::timeout(self.timeout, run)
+            .await
+            .map_err(|_| anyhow!("timed out"))?
+    }
+
+    // Stream the file to clamd with INSTREAM
+    async fn scan(&self, path: &Path) -> anyhow::Result<Verdict> {
+        let run = async {
+            let mut file = fs::File::open(path).await?;
+            let mut stream = self.connect().await?;
+            stream.write_all(b"zINSTREAM\0").await?;
+
+            let mut buffer = vec![0u8; CHUNK_SIZE];
+            loop {
+                let n = file.read(&mut buffer).await?;
+                if n == 0 {
+                    break;
+                }
+                stream.write_all(&(n as u32).to_be_bytes()).await?;
+                stream.write_all(&buffer[..n]).await?;
+            }
+            stream.write_all(&0u32.to_be_bytes()).await?;
+
+            parse_reply(&self.read_reply(&mut stream).await?)
+        };
+        tokio::time::timeout(self.timeout, run)
+            .await
+            .map_err(|_| anyhow!("timed out after {:?}", self.timeout))?
+    }
+
+    // Move a file into the quarantine directory under a unique name
+    async fn quarantine(&self, path: &Path) -> anyhow::Result<PathBuf> {
+        let file_name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let target = self.quarantine_dir.join(format!(
+            "{}_{}",
+            Utc::now().format("%Y%m%dT%H%M%S%.6f"),
+            file_name
+        ));
+
+        // The quarantine may live on another filesystem
+        if fs::rename(path, &target).await.is_err() {
+            fs::copy(path, &target).await?;
+            fs::remove_file(path).await?;
+        }
+        Ok(target)
+    }
+
+    async fn quarantine_upload(
+        &self,
+        upload: &CompletedUpload,
+        reason: String,
+    ) {
+        match self.quarantine(&upload.local_path).await {
+            Ok(target) => {
+                warn!(
+                    "Quarantined {} uploaded by {} to {}: {}",
+                    upload.path,
+                    upload.username,
+                    target.display(),
+                    reason
+                );
+                self.events.publish(Event::FileQuarantined {
+                    username: upload.username.clone(),
+                    peer: upload.peer.clone(),
+                    path: upload.path.clone(),
+                    reason,
+                });
+            }
+            Err(e) => {
+                error!("Failed to quarantine {}: {}", upload.path, e)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl UploadHook for VirusScanner {
+    async fn on_upload(&self, upload: &CompletedUpload) -> HookOutcome {
+        match self.scan(&upload.local_path).await {
+            Ok(Verdict::Clean) => {
+                info!(
+                    "Scanned {} ({} bytes): clean",
+                    upload.path, upload.bytes
+                );
+                HookOutcome::Continue
+            }
+            Ok(Verdict::Infected(signature)) => {
+                self.quarantine_upload(
+                    upload,
+                    format!("infected: {}", signature),
+                )
+                .await;
+                HookOutcome::Stop
+            }
+            Err(e) => {
+                error!("Failed to scan {}: {}", upload.path, e);
+                if self.quarantine_on_error {
+                    self.quarantine_upload(
+                        upload,
+                        format!("scan failed: {}", e),
+                    )
+                    .await;
+                    HookOutcome::Stop
+                } else {
+                    HookOutcome::Continue
+                }
+            }
+        }
+    }
+}
+
+// Replies look like "stream: OK" or "stream: Eicar-Signature FOUND"
+fn parse_reply(reply: &str) -> anyhow::Result<Verdict> {
+    let result = reply.strip_prefix("stream: ").unwrap_or(reply);
+    if result == "OK" {
+        Ok(Verdict::Clean)
+    } else if let Some(signature) = result.strip_suffix(" FOUND") {
+        Ok(Verdict::Infected(signature.to_string()))
+    } else {
+        bail!("clamd replied {:?}", reply)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_reply() {
+        assert_eq!(parse_reply("stream: OK").unwrap(), Verdict::Clean);
+        assert_eq!(
+            parse_reply("stream: Win.Test.EICAR_HDB-1 FOUND").unwrap(),
+            Verdict::Infected("Win.Test.EICAR_HDB-1".to_string())
+        );
+        assert!(parse_reply("INSTREAM size limit exceeded. ERROR").is_err());
+    }
+}
@@ -0,0 +1,232 @@
+use crate::config::settings::{Settings, WebhookEndpoint};
+use crate::events::{EventBus, EventEnvelope};
+use crate::store::Repository;
+use hmac::{Hmac, KeyInit, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+// Header carrying the hex encoded HMAC-SHA256 of the request body
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+// Header carrying the event name
+pub const EVENT_HEADER: &str = "X-Webhook-Event";
+
+// Body of a webhook request: the event, plus the external SFTP address so
+// receivers can tell clients where to connect and the tags of the file the
+// event is about
+#[derive(Serialize)]
+struct Payload<'a> {
+    #[serde(flatten)]
+    envelope: &'a EventEnvelope,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    server: Option<Server>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    tags: BTreeMap<String, String>,
+}
+
+#[derive(Serialize)]
+struct Server {
+    host: String,
+    port: u16,
+}
+
+// Delivers published events to the configured webhook endpoints
+pub struct WebhookDispatcher {
+    client: reqwest::Client,
+    // Live settings so endpoint changes apply on configuration reload
+    settings: watch::Receiver<Settings>,
+    // Source of file tags; payloads carry none without a database
+    repository: Option<Arc<dyn Repository>>,
+}
+
+impl WebhookDispatcher {
+    pub fn new(
+        settings: watch::Receiver<Settings>,
+        repository: Option<Arc<dyn Repository>>,
+    ) -> Self {
+        let client = reqwest::Client::builder()
+            .build()
+            .expect("Failed to build webhook HTTP client");
+
+        Self { client, settings, repository }
+    }
+
+    // Subscribe to the bus and deliver events until the bus is closed
+    pub fn start(self, events: &EventBus) -> JoinHandle<()> {
+        let mut receiver = events.subscribe();
+
+        tokio::spawn(async move {
+            info!(
+                "Webhook dispatcher started with {} endpoint(s)",
+                self.settings.borrow().webhooks.endpoints.len()
+            );
+
+            loop {
+                match receiver.recv().await {
+                    Ok(envelope) => self.dispatch(envelope).await,
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!(
+                            "Webhook dispatcher lagged, {} events dropped",
+                            skipped
+                        );
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+
+            info!("Webhook dispatcher stopped");
+        })
+    }
+
+    // Fan an event out to every endpoint subscribed to it
+    async fn dispatch(&self, envelope: EventEnvelope) {
+        let event_name = envelope.event.name();
+        let settings = self.settings.borrow().webhooks.clone();
+        if !settings.endpoints.iter().any(|e| subscribed(e, event_name)) {
+            return;
+        }
+
+        let server = {
+            let settings = self.settings.borrow();
+            settings
+                .external_address(settings.sftp.port)
+                .map(|(host, port)| Server { host, port })
+        };
+        let tags = self.tags(&envelope).await;
+        let payload = Payload { envelope: &envelope, server, tags };
+        let body = match serde_json::to_vec(&payload) {
+            Ok(body) => Arc::new(body),
+            Err(e) => {
+                error!("Failed to serialize webhook payload: {}", e);
+                return;
+            }
+        };
+        let timeout = Duration::from_secs(settings.timeout_secs);
+
+        for endpoint in settings.endpoints {
+            if !subscribed(&endpoint, event_name) {
+                continue;
+            }
+
+            let client = self.client.clone();
+            let body = body.clone();
+            let max_retries = settings.max_retries;
+
+            tokio::spawn(async move {
+                deliver(
+                    client,
+                    endpoint,
+                    event_name,
+                    body,
+                    max_retries,
+                    timeout,
+                )
+                .await;
+            });
+        }
+    }
+
+    // Tags of the file an event is about; none when the lookup fails
+    async fn tags(&self, envelope: &EventEnvelope) -> BTreeMap<String, String> {
+        let (Some(repository), Some(path)) =
+            (&self.repository, envelope.event.path())
+        else {
+            return BTreeMap::new();
+        };
+        repository.get_file_tags(path).await.unwrap_or_else(|e| {
+            warn!("Failed to read tags of {} for webhooks: {}", path, e);
+            BTreeMap::new()
+        })
+    }
+}
+
+// Whether an endpoint wants an event; an empty list subscribes to all
+fn subscribed(endpoint: &WebhookEndpoint, event_name: &str) -> bool {
+    endpoint.events.is_empty()
+        || endpoint.events.iter().any(|e| e == event_name)
+}
+
+// POST a payload, retrying with exponential backoff on failure
+async fn deliver(
+    client: reqwest::Client,
+    endpoint: WebhookEndpoint,
+    event_name: &'static str,
+    body: Arc<Vec<u8>>,
+    max_retries: u32,
+    timeout: Duration,
+) {
+    let signature = endpoint.secret.as_ref().map(|s| sign(s.expose(), &body));
+    let mut backoff = Duration::from_secs(1);
+
+    for attempt in 0..=max_retries {
+        let mut request = client
+            .post(&endpoint.url)
+            .header("Content-Type", "application/json")
+            .header(EVENT_HEADER, event_name)
+            .timeout(timeout)
+            .body(body.as_ref().clone());
+        if let Some(signature) = &signature {
+            request = request.header(SIGNATURE_HEADER, signature);
+        }
+
+        match request.send().await {
+            Ok(response) if response.status().is_success() => {
+                debug!("Delivered {} webhook to {}", event_name, endpoint.url);
+                return;
+            }
+            Ok(response) => warn!(
+                "Webhook {} returned {} (attempt {})",
+                endpoint.url,
+                response.status(),
+                attempt + 1
+            ),
+            Err(e) => warn!(
+                "Webhook {} failed: {} (attempt {})",
+                endpoint.url,
+                e,
+                attempt + 1
+            ),
+        }
+
+        if attempt < max_retries {
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+    }
+
+    error!(
+        "Giving up on {} webhook to {} after {} attempts",
+        event_name,
+        endpoint.url,
+        max_retries + 1
+    );
+}
+
+// Compute the signature header value for a payload
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_matches_known_vector() {
+        // RFC 4231 test case 2
+        let signature = sign("Jefe", b"what do ya want for nothing?");
+        assert_eq!(
+            signature,
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
@@ -0,0 +1,15 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+/// Tells when users may be connected, e.g. from the access windows of
+/// their accounts
+#[async_trait]
+pub trait AccessHours: Send + Sync {
+    /// Whether `username` may be connected at `time`; users without
+    /// restrictions always may
+    async fn allows(
+        &self,
+        username: &str,
+        time: DateTime<Utc>,
+    ) -> anyhow::Result<bool>;
+}
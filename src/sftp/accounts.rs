@@ -0,0 +1,23 @@
+use async_trait::async_trait;
+use russh::keys::PublicKey;
+
+/// User accounts that may log in besides the generated credentials, e.g.
+/// those kept in the database
+#[async_trait]
+pub trait AccountLogins: Send + Sync {
+    /// Whether `password` is the password of the account `username`;
+    /// accounts without a password never match
+    async fn password_matches(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> anyhow::Result<bool>;
+
+    /// Whether `key` is one of the authorized keys of the account
+    /// `username`
+    async fn key_authorized(
+        &self,
+        username: &str,
+        key: &PublicKey,
+    ) -> anyhow::Result<bool>;
+}
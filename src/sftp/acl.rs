@@ -0,0 +1,147 @@
+use crate::sftp::glob::glob_match;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+/// What clients may do with the files below a path
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PathAccess {
+    ReadWrite,
+    /// Downloads and listings only
+    ReadOnly,
+    /// Uploads, new directories and renames, but no downloads, listings
+    /// or removals, e.g. for a drop box
+    WriteOnly,
+    #[serde(rename = "none")]
+    NoAccess,
+}
+
+/// Kind of request checked against the path rules
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Read,
+    List,
+    Stat,
+    /// Creating, writing to, renaming and making directories
+    Write,
+    Delete,
+}
+
+impl PathAccess {
+    pub fn allows(self, operation: Operation) -> bool {
+        use Operation::*;
+        match self {
+            PathAccess::ReadWrite => true,
+            PathAccess::ReadOnly => matches!(operation, Read | List | Stat),
+            PathAccess::WriteOnly => matches!(operation, Write | Stat),
+            PathAccess::NoAccess => false,
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Operation::Read => "read",
+            Operation::List => "list",
+            Operation::Stat => "stat",
+            Operation::Write => "write",
+            Operation::Delete => "delete",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Access granted to the paths matching a glob, e.g. "/incoming/**"
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PathRule {
+    pub pattern: String,
+    pub access: PathAccess,
+}
+
+impl PathRule {
+    /// Patterns are absolute client paths
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.pattern.starts_with('/') {
+            return Err(format!(
+                "pattern '{}' must start with '/'",
+                self.pattern
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether the rule covers `path`; a rule for "/dir/**" also covers
+    /// the directory itself
+    fn matches(&self, path: &str) -> bool {
+        glob_match(&self.pattern, path)
+            || glob_match(&self.pattern, &format!("{}/", path))
+    }
+}
+
+/// Path rules checked on every file operation, replaced through the API
+/// or on reload. The first rule matching a path decides; paths no rule
+/// matches are read-write.
+#[derive(Clone, Default)]
+pub struct PathRules {
+    rules: Arc<RwLock<Arc<[PathRule]>>>,
+}
+
+impl PathRules {
+    pub fn new(rules: Vec<PathRule>) -> Self {
+        Self { rules: Arc::new(RwLock::new(rules.into())) }
+    }
+
+    pub fn set(&self, rules: Vec<PathRule>) {
+        *self.rules.write().unwrap() = rules.into();
+    }
+
+    pub fn rules(&self) -> Vec<PathRule> {
+        self.rules.read().unwrap().to_vec()
+    }
+
+    /// Access to `path`, an absolute client path
+    pub fn access(&self, path: &str) -> PathAccess {
+        let rules = self.rules.read().unwrap().clone();
+        rules
+            .iter()
+            .find(|rule| rule.matches(path))
+            .map_or(PathAccess::ReadWrite, |rule| rule.access)
+    }
+
+    pub fn allows(&self, path: &str, operation: Operation) -> bool {
+        self.access(path).allows(operation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: &str, access: PathAccess) -> PathRule {
+        PathRule { pattern: pattern.to_string(), access }
+    }
+
+    #[test]
+    fn test_first_matching_rule_decides() {
+        let rules = PathRules::new(vec![
+            rule("/incoming/**", PathAccess::WriteOnly),
+            rule("/outgoing/private/**", PathAccess::NoAccess),
+            rule("/outgoing/**", PathAccess::ReadOnly),
+        ]);
+        assert!(rules.allows("/incoming/a.csv", Operation::Write));
+        assert!(rules.allows("/incoming/a.csv", Operation::Stat));
+        assert!(!rules.allows("/incoming/a.csv", Operation::Read));
+        assert!(!rules.allows("/incoming", Operation::List));
+        assert!(!rules.allows("/incoming/a.csv", Operation::Delete));
+        assert!(rules.allows("/outgoing/b.csv", Operation::Read));
+        assert!(!rules.allows("/outgoing/b.csv", Operation::Write));
+        assert!(!rules.allows("/outgoing/private/c", Operation::Stat));
+        assert!(rules.allows("/other", Operation::Delete));
+
+        rules.set(Vec::new());
+        assert!(rules.allows("/incoming/a.csv", Operation::Read));
+        assert!(rule("incoming/**", PathAccess::ReadOnly).validate().is_err());
+    }
+}
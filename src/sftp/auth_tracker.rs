@@ -0,0 +1,287 @@
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::Notify;
+
+/// Failures within the window that trigger an alert (0 disables each)
+#[derive(Debug, Clone, Copy)]
+pub struct AuthFailureLimits {
+    /// Failures across all clients
+    pub threshold: u32,
+    /// Failures from a single IP address
+    pub per_ip_threshold: u32,
+    /// Failures against a single username
+    pub per_user_threshold: u32,
+    /// Length of the sliding window
+    pub window: Duration,
+    /// How long an IP reaching the per-IP threshold is banned (zero
+    /// disables banning)
+    pub ban: Duration,
+}
+
+/// Alert raised when failed logins cross one of the limits
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthAlert {
+    /// Failures across all clients
+    Spike { failures: u32 },
+    /// Failures from one IP address, with the usernames tried
+    Ip { ip: String, failures: u32, usernames: Vec<String> },
+    /// Failures against one username, with the IPs they came from
+    User { username: String, failures: u32, ips: Vec<String> },
+}
+
+/// Counts failed authentication attempts in a sliding time window, overall
+/// and per source IP and username
+#[derive(Clone)]
+pub struct AuthFailureTracker {
+    inner: Arc<Mutex<TrackerState>>,
+    /// Signalled whenever an IP is banned here, so the ban can be shared
+    banned: Arc<Notify>,
+}
+
+struct TrackerState {
+    limits: AuthFailureLimits,
+    /// Timestamps of recent failures, oldest first
+    failures: VecDeque<Instant>,
+    /// Recent failures per IP with the username tried
+    by_ip: HashMap<String, VecDeque<(Instant, String)>>,
+    /// Recent failures per username with the source IP, when known
+    by_user: HashMap<String, VecDeque<(Instant, Option<String>)>>,
+    /// Banned IPs and when their ban ends
+    banned: HashMap<IpAddr, Instant>,
+}
+
+impl AuthFailureTracker {
+    /// Creates a tracker with the given limits
+    pub fn new(limits: AuthFailureLimits) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(TrackerState {
+                limits,
+                failures: VecDeque::new(),
+                by_ip: HashMap::new(),
+                by_user: HashMap::new(),
+                banned: HashMap::new(),
+            })),
+            banned: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Length of the sliding window
+    pub fn window(&self) -> Duration {
+        self.inner.lock().unwrap().limits.window
+    }
+
+    /// How long IPs are banned; zero when banning is disabled
+    pub fn ban_duration(&self) -> Duration {
+        self.inner.lock().unwrap().limits.ban
+    }
+
+    /// Whether connections from `ip` are refused after too many failures
+    pub fn is_banned(&self, ip: IpAddr) -> bool {
+        let now = Instant::now();
+        let mut state = self.inner.lock().unwrap();
+        state.banned.retain(|_, until| *until > now);
+        state.banned.contains_key(&ip)
+    }
+
+    /// IPs banned right now, with the time their ban ends
+    pub fn bans(&self) -> Vec<(IpAddr, SystemTime)> {
+        let now = Instant::now();
+        let wall = SystemTime::now();
+        let mut state = self.inner.lock().unwrap();
+        state.banned.retain(|_, until| *until > now);
+        state
+            .banned
+            .iter()
+            .map(|(ip, until)| (*ip, wall + until.duration_since(now)))
+            .collect()
+    }
+
+    /// Takes over bans made elsewhere, e.g. by another replica. A ban
+    /// already known here keeps the later of the two ends.
+    pub fn adopt_bans(&self, bans: &[(IpAddr, SystemTime)]) {
+        let now = Instant::now();
+        let wall = SystemTime::now();
+        let mut state = self.inner.lock().unwrap();
+        for (ip, until) in bans {
+            let Ok(left) = until.duration_since(wall) else {
+                continue;
+            };
+            let until = now + left;
+            let entry = state.banned.entry(*ip).or_insert(until);
+            *entry = (*entry).max(until);
+        }
+    }
+
+    /// Waits until an IP is banned here after failed logins
+    pub async fn ban_added(&self) {
+        self.banned.notified().await
+    }
+
+    /// Changes the limits, keeping recorded failures
+    pub fn reconfigure(&self, limits: AuthFailureLimits) {
+        self.inner.lock().unwrap().limits = limits;
+    }
+
+    /// Records a failure and returns the alerts whose threshold it reached.
+    /// The counts behind an alert are reset so each spike is reported once.
+    pub fn record_failure(
+        &self,
+        username: &str,
+        ip: Option<IpAddr>,
+    ) -> Vec<AuthAlert> {
+        let now = Instant::now();
+        let mut state = self.inner.lock().unwrap();
+        let limits = state.limits;
+        let source = ip.map(|ip| ip.to_string());
+
+        prune(&mut state.failures, now, limits.window, |t| *t);
+        state.by_ip.retain(|_, entries| {
+            prune(entries, now, limits.window, |(t, _)| *t);
+            !entries.is_empty()
+        });
+        state.by_user.retain(|_, entries| {
+            prune(entries, now, limits.window, |(t, _)| *t);
+            !entries.is_empty()
+        });
+
+        let mut alerts = Vec::new();
+
+        state.failures.push_back(now);
+        let count = state.failures.len() as u32;
+        if limits.threshold > 0 && count >= limits.threshold {
+            state.failures.clear();
+            alerts.push(AuthAlert::Spike { failures: count });
+        }
+
+        if let Some(addr) = ip {
+            let ip = addr.to_string();
+            let entries = state.by_ip.entry(ip.clone()).or_default();
+            entries.push_back((now, username.to_string()));
+            let count = entries.len() as u32;
+            if limits.per_ip_threshold > 0 && count >= limits.per_ip_threshold {
+                let usernames: BTreeSet<String> =
+                    entries.drain(..).map(|(_, u)| u).collect();
+                state.by_ip.remove(&ip);
+                if !limits.ban.is_zero() {
+                    state.banned.insert(addr, now + limits.ban);
+                    self.banned.notify_one();
+                }
+                alerts.push(AuthAlert::Ip {
+                    ip,
+                    failures: count,
+                    usernames: usernames.into_iter().collect(),
+                });
+            }
+        }
+
+        let entries = state.by_user.entry(username.to_string()).or_default();
+        entries.push_back((now, source));
+        let count = entries.len() as u32;
+        if limits.per_user_threshold > 0 && count >= limits.per_user_threshold {
+            let ips: BTreeSet<String> =
+                entries.drain(..).filter_map(|(_, ip)| ip).collect();
+            state.by_user.remove(username);
+            alerts.push(AuthAlert::User {
+                username: username.to_string(),
+                failures: count,
+                ips: ips.into_iter().collect(),
+            });
+        }
+
+        alerts
+    }
+}
+
+/// Drops entries older than the window from the front of the queue
+fn prune<T>(
+    entries: &mut VecDeque<T>,
+    now: Instant,
+    window: Duration,
+    time: impl Fn(&T) -> Instant,
+) {
+    while let Some(oldest) = entries.front() {
+        if now.duration_since(time(oldest)) > window {
+            entries.pop_front();
+        } else {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker(
+        threshold: u32,
+        per_ip: u32,
+        per_user: u32,
+    ) -> AuthFailureTracker {
+        AuthFailureTracker::new(AuthFailureLimits {
+            threshold,
+            per_ip_threshold: per_ip,
+            per_user_threshold: per_user,
+            window: Duration::from_secs(60),
+            ban: Duration::from_secs(60),
+        })
+    }
+
+    #[test]
+    fn test_alerts_per_ip_and_username() {
+        let tracker = tracker(0, 3, 2);
+        let a: IpAddr = "10.0.0.1".parse().unwrap();
+        let b: IpAddr = "10.0.0.2".parse().unwrap();
+
+        assert!(tracker.record_failure("admin", Some(a)).is_empty());
+        assert_eq!(
+            tracker.record_failure("admin", Some(b)),
+            vec![AuthAlert::User {
+                username: "admin".to_string(),
+                failures: 2,
+                ips: vec!["10.0.0.1".to_string(), "10.0.0.2".to_string()],
+            }]
+        );
+        assert_eq!(tracker.record_failure("root", Some(a)), vec![]);
+        assert_eq!(
+            tracker.record_failure("guest", Some(a)),
+            vec![AuthAlert::Ip {
+                ip: "10.0.0.1".to_string(),
+                failures: 3,
+                usernames: vec![
+                    "admin".to_string(),
+                    "guest".to_string(),
+                    "root".to_string(),
+                ],
+            }]
+        );
+        // The IP's count starts over after its alert
+        assert!(tracker.record_failure("nobody", Some(a)).is_empty());
+        // and it stays banned for a while
+        assert!(tracker.is_banned(a));
+        assert!(!tracker.is_banned(b));
+    }
+
+    #[test]
+    fn test_bans_are_shared_with_another_tracker() {
+        let banning = tracker(0, 1, 0);
+        let other = tracker(0, 0, 0);
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+
+        banning.record_failure("admin", Some(ip));
+        let bans = banning.bans();
+        assert_eq!(bans.len(), 1);
+        assert_eq!(bans[0].0, ip);
+        assert!(!other.is_banned(ip));
+
+        other.adopt_bans(&bans);
+        assert!(other.is_banned(ip));
+
+        // Bans that already ended are not taken over
+        let ended = "10.0.0.2".parse().unwrap();
+        let past = SystemTime::now() - Duration::from_secs(1);
+        other.adopt_bans(&[(ended, past)]);
+        assert!(!other.is_banned(ended));
+    }
+}
@@ -0,0 +1,192 @@
+use md5::Md5;
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+use std::collections::BTreeMap;
+use std::path::Path;
+use tokio::fs;
+use tokio::io::{self, AsyncReadExt};
+
+/// Size of the chunks read when a file has to be hashed from disk
+const READ_CHUNK: usize = 256 * 1024;
+
+/// Digest algorithms available for uploaded files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumAlgorithm {
+    Md5,
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl ChecksumAlgorithm {
+    /// Name used as the key of the digest in events and the transfer log
+    pub fn name(self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Md5 => "md5",
+            ChecksumAlgorithm::Sha1 => "sha1",
+            ChecksumAlgorithm::Sha256 => "sha256",
+            ChecksumAlgorithm::Sha512 => "sha512",
+        }
+    }
+}
+
+enum Hasher {
+    Md5(Md5),
+    Sha1(Sha1),
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+impl Hasher {
+    fn new(algorithm: ChecksumAlgorithm) -> Self {
+        match algorithm {
+            ChecksumAlgorithm::Md5 => Hasher::Md5(Md5::new()),
+            ChecksumAlgorithm::Sha1 => Hasher::Sha1(Sha1::new()),
+            ChecksumAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            ChecksumAlgorithm::Sha512 => Hasher::Sha512(Sha512::new()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Md5(h) => h.update(data),
+            Hasher::Sha1(h) => h.update(data),
+            Hasher::Sha256(h) => h.update(data),
+            Hasher::Sha512(h) => h.update(data),
+        }
+    }
+
+    fn finish(self) -> String {
+        match self {
+            Hasher::Md5(h) => hex::encode(h.finalize()),
+            Hasher::Sha1(h) => hex::encode(h.finalize()),
+            Hasher::Sha256(h) => hex::encode(h.finalize()),
+            Hasher::Sha512(h) => hex::encode(h.finalize()),
+        }
+    }
+}
+
+/// Digests of an uploaded file, computed as its bytes are written
+pub struct UploadChecksum {
+    algorithms: Vec<ChecksumAlgorithm>,
+    hashers: Vec<Hasher>,
+    /// Bytes hashed so far, all from the start of the file
+    hashed: u64,
+    /// Whether every write continued where the previous one ended
+    in_order: bool,
+}
+
+impl UploadChecksum {
+    /// Starts digests with the given algorithms
+    pub fn new(algorithms: &[ChecksumAlgorithm]) -> Self {
+        Self {
+            algorithms: algorithms.to_vec(),
+            hashers: algorithms.iter().map(|a| Hasher::new(*a)).collect(),
+            hashed: 0,
+            in_order: true,
+        }
+    }
+
+    /// Feeds a write at the given offset. Writes out of order cannot be
+    /// hashed incrementally, so the file is read back when finishing.
+    pub fn update(&mut self, offset: u64, data: &[u8]) {
+        if !self.in_order {
+            return;
+        }
+        if offset != self.hashed {
+            self.in_order = false;
+            return;
+        }
+        for hasher in &mut self.hashers {
+            hasher.update(data);
+        }
+        self.hashed += data.len() as u64;
+    }
+
+    /// Hex digests by algorithm name. The file is hashed from disk when the
+    /// writes did not cover it in order from the start, e.g. after a resume.
+    pub async fn finish(
+        self,
+        path: &Path,
+    ) -> io::Result<BTreeMap<String, String>> {
+        let size = fs::metadata(path).await?.len();
+        if self.in_order && self.hashed == size {
+            Ok(digests(&self.algorithms, self.hashers))
+        } else {
+            file_checksums(&self.algorithms, path).await
+        }
+    }
+}
+
+/// Hex digests of a file on disk by algorithm name
+pub async fn file_checksums(
+    algorithms: &[ChecksumAlgorithm],
+    path: &Path,
+) -> io::Result<BTreeMap<String, String>> {
+    Ok(digests(algorithms, hash_file(algorithms, path).await?))
+}
+
+fn digests(
+    algorithms: &[ChecksumAlgorithm],
+    hashers: Vec<Hasher>,
+) -> BTreeMap<String, String> {
+    algorithms
+        .iter()
+        .zip(hashers)
+        .map(|(algorithm, hasher)| {
+            (algorithm.name().to_string(), hasher.finish())
+        })
+        .collect()
+}
+
+async fn hash_file(
+    algorithms: &[ChecksumAlgorithm],
+    path: &Path,
+) -> io::Result<Vec<Hasher>> {
+    let mut hashers: Vec<Hasher> =
+        algorithms.iter().map(|a| Hasher::new(*a)).collect();
+    let mut file = fs::File::open(path).await?;
+    let mut buffer = vec![0u8; READ_CHUNK];
+    loop {
+        let n = file.read(&mut buffer).await?;
+        if n == 0 {
+            break;
+        }
+        for hasher in &mut hashers {
+            hasher.update(&buffer[..n]);
+        }
+    }
+    Ok(hashers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELLO_SHA256: &str =
+        "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+
+    #[tokio::test]
+    async fn test_in_order_and_out_of_order_writes() {
+        let path = std::env::temp_dir()
+            .join(format!("sftp-manager-checksum-{}", std::process::id()));
+        std::fs::write(&path, b"hello world").unwrap();
+
+        let mut checksum = UploadChecksum::new(&[ChecksumAlgorithm::Sha256]);
+        checksum.update(0, b"hello ");
+        checksum.update(6, b"world");
+        let digests = checksum.finish(&path).await.unwrap();
+        assert_eq!(digests["sha256"], HELLO_SHA256);
+
+        // Read back from disk
+        let mut checksum = UploadChecksum::new(&[ChecksumAlgorithm::Sha256]);
+        checksum.update(6, b"world");
+        checksum.update(0, b"hello ");
+        let digests = checksum.finish(&path).await.unwrap();
+        assert_eq!(digests["sha256"], HELLO_SHA256);
+
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,79 @@
+use std::sync::{Arc, Mutex};
+
+/// Which client software may use the server, matched against the
+/// identification string clients send first, e.g. "SSH-2.0-OpenSSH_9.6".
+/// `*` in a pattern matches any run of characters.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientVersionRules {
+    /// Only matching clients are let in; all are when empty
+    pub allow: Vec<String>,
+    /// Matching clients are refused, even when allowed
+    pub deny: Vec<String>,
+}
+
+impl ClientVersionRules {
+    pub fn allows(&self, client: &str) -> bool {
+        let matches = |pattern: &String| wildcard_match(pattern, client);
+        if self.deny.iter().any(matches) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(matches)
+    }
+}
+
+/// Client version rules shared by the sessions, replaced on reload
+#[derive(Clone, Default)]
+pub struct ClientVersions {
+    rules: Arc<Mutex<ClientVersionRules>>,
+}
+
+impl ClientVersions {
+    pub fn new(rules: ClientVersionRules) -> Self {
+        Self { rules: Arc::new(Mutex::new(rules)) }
+    }
+
+    /// Changes the rules; sessions already logged in are kept
+    pub fn reconfigure(&self, rules: ClientVersionRules) {
+        *self.rules.lock().unwrap() = rules;
+    }
+
+    /// Whether a client identifying itself as `client` may log in
+    pub fn allows(&self, client: &str) -> bool {
+        self.rules.lock().unwrap().allows(client)
+    }
+}
+
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let Some((head, rest)) = pattern.split_once('*') else {
+        return pattern == text;
+    };
+    let Some(text) = text.strip_prefix(head) else {
+        return false;
+    };
+    (0..=text.len())
+        .filter(|&i| text.is_char_boundary(i))
+        .any(|i| wildcard_match(rest, &text[i..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deny_takes_precedence_over_allow() {
+        let rules = ClientVersionRules {
+            allow: vec!["SSH-2.0-OpenSSH_*".into(), "SSH-2.0-libssh*".into()],
+            deny: vec!["SSH-2.0-libssh-0.6*".into()],
+        };
+        assert!(rules.allows("SSH-2.0-OpenSSH_9.6p1 Ubuntu-3ubuntu13"));
+        assert!(rules.allows("SSH-2.0-libssh_0.10.6"));
+        assert!(!rules.allows("SSH-2.0-libssh-0.6.3"));
+        assert!(!rules.allows("SSH-2.0-PuTTY_Release_0.81"));
+
+        let rules =
+            ClientVersionRules { allow: Vec::new(), deny: vec!["*Go".into()] };
+        assert!(!rules.allows("SSH-2.0-Go"));
+        assert!(rules.allows("SSH-2.0-Go-1"));
+        assert!(ClientVersionRules::default().allows("SSH-2.0-anything"));
+    }
+}
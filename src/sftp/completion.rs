@@ -0,0 +1,249 @@
+use crate::events::{Event, EventBus};
+use crate::sftp::hooks::{CompletedUpload, UploadHook, run_upload_hooks};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{debug, info};
+
+/// Partial uploads never renamed are forgotten after this long
+const PARTIAL_TTL: Duration = Duration::from_secs(24 * 3600);
+
+/// When a closed upload counts as complete
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompletionRules {
+    /// Uploads to names ending in one of these, e.g. ".part" or WinSCP's
+    /// ".filepart", are partial until renamed to a name without
+    pub partial_suffixes: Vec<String>,
+    /// How long the size and modification time of a closed upload must
+    /// stay the same; zero completes uploads when they are closed
+    pub stable_for: Duration,
+}
+
+impl CompletionRules {
+    /// Whether uploads to `path` wait for a rename
+    pub fn is_partial(&self, path: &Path) -> bool {
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            return false;
+        };
+        self.partial_suffixes
+            .iter()
+            .any(|suffix| name.len() > suffix.len() && name.ends_with(suffix))
+    }
+}
+
+/// A closed upload not yet deemed complete
+struct Pending {
+    upload: CompletedUpload,
+    /// Tells a stability timer whether the upload changed hands since
+    generation: u64,
+    since: Instant,
+    partial: bool,
+}
+
+/// Decides when uploads are complete: a handle closed after writing is
+/// held back while its name marks it as partial and, optionally, until
+/// the file stops changing. Only then do the upload hooks run and
+/// FileUploaded get published, so webhooks and pipelines never see a
+/// file still being written.
+#[derive(Clone)]
+pub struct UploadCompletion {
+    rules: Arc<Mutex<CompletionRules>>,
+    pending: Arc<Mutex<HashMap<PathBuf, Pending>>>,
+    generation: Arc<AtomicU64>,
+    hooks: Arc<[Arc<dyn UploadHook>]>,
+    events: EventBus,
+}
+
+impl UploadCompletion {
+    pub fn new(
+        rules: CompletionRules,
+        hooks: Arc<[Arc<dyn UploadHook>]>,
+        events: EventBus,
+    ) -> Self {
+        Self {
+            rules: Arc::new(Mutex::new(rules)),
+            pending: Arc::new(Mutex::new(HashMap::new())),
+            generation: Arc::new(AtomicU64::new(0)),
+            hooks,
+            events,
+        }
+    }
+
+    /// Changes the rules; uploads already held keep waiting under the old
+    /// ones
+    pub fn reconfigure(&self, rules: CompletionRules) {
+        *self.rules.lock().unwrap() = rules;
+    }
+
+    /// Records an upload whose handle was closed
+    pub fn closed(&self, upload: CompletedUpload) {
+        let rules = self.rules.lock().unwrap().clone();
+        if rules.is_partial(&upload.local_path) {
+            debug!("Upload of {} is partial until renamed", upload.path);
+            self.hold(upload, true);
+            return;
+        }
+        if rules.stable_for.is_zero() {
+            self.pending.lock().unwrap().remove(&upload.local_path);
+            self.complete(upload);
+            return;
+        }
+        let path = upload.local_path.clone();
+        let generation = self.hold(upload, false);
+        let completion = self.clone();
+        tokio::spawn(async move {
+            completion.settle(path, generation, rules.stable_for).await;
+        });
+    }
+
+    /// Holds an upload staged in a session's scratch area, which is
+    /// partial whatever its name until moved out
+    pub fn staged(&self, upload: CompletedUpload) {
+        debug!("Upload of {} is staged until moved out", upload.path);
+        self.hold(upload, true);
+    }
+
+    /// Follows a held upload to its new name; leaving the partial name
+    /// behind may complete it, unless it is still `staged`
+    pub fn renamed(&self, from: &Path, to: &Path, path: String, staged: bool) {
+        let Some(pending) = self.pending.lock().unwrap().remove(from) else {
+            return;
+        };
+        let mut upload = pending.upload;
+        upload.local_path = to.to_path_buf();
+        upload.path = path;
+        if staged {
+            self.staged(upload);
+        } else {
+            self.closed(upload);
+        }
+    }
+
+    /// Drops a held upload whose file was removed
+    pub fn removed(&self, path: &Path) {
+        self.pending.lock().unwrap().remove(path);
+    }
+
+    /// Runs the upload hooks and publishes the upload
+    pub fn complete(&self, upload: CompletedUpload) {
+        info!("Upload of {} is complete", upload.path);
+        self.events.publish(Event::FileUploaded {
+            username: upload.username.clone(),
+            peer: upload.peer.clone(),
+            path: upload.path.clone(),
+            bytes: upload.bytes,
+            duration_ms: upload.duration_ms,
+            checksums: upload.checksums.clone(),
+        });
+        run_upload_hooks(self.hooks.clone(), upload);
+    }
+
+    fn hold(&self, upload: CompletedUpload, partial: bool) -> u64 {
+        let generation = self.generation.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|_, p| !p.partial || p.since.elapsed() < PARTIAL_TTL);
+        pending.insert(
+            upload.local_path.clone(),
+            Pending { upload, generation, since: Instant::now(), partial },
+        );
+        generation
+    }
+
+    /// Completes the upload at `path` once its file has not changed for
+    /// `stable_for`. Gives up when the file is gone or the upload was
+    /// closed again, renamed or removed in the meantime.
+    async fn settle(
+        self,
+        path: PathBuf,
+        generation: u64,
+        stable_for: Duration,
+    ) {
+        let mut last = file_state(&path).await;
+        loop {
+            tokio::time::sleep(stable_for).await;
+            let current = file_state(&path).await;
+            let mut pending = self.pending.lock().unwrap();
+            if pending.get(&path).map(|p| p.generation) != Some(generation) {
+                return;
+            }
+            if current.is_none() {
+                pending.remove(&path);
+                return;
+            }
+            if current != last {
+                last = current;
+                continue;
+            }
+            let Some(held) = pending.remove(&path) else { return };
+            drop(pending);
+            self.complete(held.upload);
+            return;
+        }
+    }
+}
+
+/// Size and modification time of a file, if it still exists
+async fn file_state(path: &Path) -> Option<(u64, Option<SystemTime>)> {
+    let metadata = tokio::fs::metadata(path).await.ok()?;
+    Some((metadata.len(), metadata.modified().ok()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upload(local_path: &Path, path: &str) -> CompletedUpload {
+        CompletedUpload {
+            username: "alice".to_string(),
+            peer: None,
+            path: path.to_string(),
+            local_path: local_path.to_path_buf(),
+            root_dir: std::env::temp_dir(),
+            bytes: 5,
+            duration_ms: 1,
+            checksums: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_uploads_complete_after_rename_and_once_stable() {
+        let dir = std::env::temp_dir()
+            .join(format!("sftp-manager-completion-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let part = dir.join("data.csv.part");
+        let done = dir.join("data.csv");
+        std::fs::write(&part, b"hello").unwrap();
+
+        let events = EventBus::new(16);
+        let mut received = events.subscribe();
+        let completion = UploadCompletion::new(
+            CompletionRules {
+                partial_suffixes: vec![".part".to_string()],
+                stable_for: Duration::from_millis(50),
+            },
+            Arc::from(Vec::new()),
+            events,
+        );
+
+        completion.closed(upload(&part, "/data.csv.part"));
+        std::fs::rename(&part, &done).unwrap();
+        completion.renamed(&part, &done, "/data.csv".to_string(), false);
+        // Held until the file has been stable for a while
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(received.try_recv().is_err());
+
+        let envelope = received.recv().await.unwrap();
+        assert_eq!(envelope.event.path(), Some("/data.csv"));
+        assert!(completion.pending.lock().unwrap().is_empty());
+
+        // Removed before it settled
+        completion.closed(upload(&done, "/data.csv"));
+        completion.removed(&done);
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert!(received.try_recv().is_err());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+use zeroize::Zeroizing;
+
+/// A password or key, wiped from memory when dropped and left out of
+/// `Debug` output
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SecretString(Zeroizing<String>);
+
+impl SecretString {
+    pub fn new(secret: String) -> Self {
+        Self(Zeroizing::new(secret))
+    }
+
+    /// The secret itself; avoid copying it into longer-lived strings
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(secret: String) -> Self {
+        Self::new(secret)
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[redacted]")
+    }
+}
+
+/// Username and password accepted by the SFTP server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SftpCredentials {
+    pub username: String,
+    pub password: SecretString,
+}
+
+impl SftpCredentials {
+    pub fn new(username: String, password: String) -> Self {
+        Self { username, password: password.into() }
+    }
+
+    /// Whether a login attempt matches these credentials
+    pub fn matches(&self, username: &str, password: &str) -> bool {
+        self.username == username && self.password.expose() == password
+    }
+}
+
+/// Where the listeners find the credentials accepted right now
+pub trait CredentialsSource: Send + Sync {
+    /// The current credentials; `None` while the server is disabled
+    fn load(&self) -> Option<Arc<SftpCredentials>>;
+}
+
+/// Credentials shared with the owner of the server state; read on every
+/// login so a rotation applies without restarting the listener
+pub type SharedCredentials = Arc<dyn CredentialsSource>;
+
+/// Source without credentials, as while the server is disabled; for tests
+#[cfg(test)]
+pub struct NoCredentials;
+
+#[cfg(test)]
+impl CredentialsSource for NoCredentials {
+    fn load(&self) -> Option<Arc<SftpCredentials>> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secrets_are_redacted() {
+        let credentials =
+            SftpCredentials::new("alice".to_string(), "hunter2".to_string());
+        let debug = format!("{:?}", credentials);
+        assert!(debug.contains("alice"));
+        assert!(!debug.contains("hunter2"));
+        // Serialized as the plain string
+        assert_eq!(
+            serde_json::to_string(&credentials).unwrap(),
+            r#"{"username":"alice","password":"hunter2"}"#
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// How long a reading of the free space is reused, so busy uploads do not
+/// query the filesystem for every packet
+const READING_TTL: Duration = Duration::from_secs(1);
+
+/// Thresholds for the space left on the filesystem of a root directory
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DiskLimits {
+    /// Writes are refused once they would leave less than this; 0 never
+    /// refuses them
+    pub reserve_bytes: u64,
+    /// Space is reported as low below this
+    pub warning_bytes: u64,
+}
+
+/// Space on the filesystem of a root directory
+#[derive(Debug, Clone, Serialize)]
+pub struct DiskStatus {
+    pub available_bytes: u64,
+    pub total_bytes: u64,
+    pub reserve_bytes: u64,
+    pub low: bool,
+}
+
+type Readings = HashMap<PathBuf, (Instant, (u64, u64))>;
+
+/// Checks writes against the free space on the filesystem they go to
+#[derive(Clone)]
+pub struct DiskGuard {
+    limits: Arc<Mutex<DiskLimits>>,
+    readings: Arc<Mutex<Readings>>,
+}
+
+impl DiskGuard {
+    pub fn new(limits: DiskLimits) -> Self {
+        Self { limits: Arc::new(Mutex::new(limits)), readings: Arc::default() }
+    }
+
+    pub fn reconfigure(&self, limits: DiskLimits) {
+        *self.limits.lock().unwrap() = limits;
+    }
+
+    /// Whether `incoming` more bytes fit below `root` while keeping the
+    /// reserve. Writes are let through when the space cannot be read.
+    pub async fn allows(&self, root: &Path, incoming: u64) -> bool {
+        let reserve = self.limits.lock().unwrap().reserve_bytes;
+        if reserve == 0 {
+            return true;
+        }
+        match self.space(root).await {
+            Ok((available, _)) => available.saturating_sub(incoming) >= reserve,
+            Err(e) => {
+                warn!("Cannot read free space of {}: {}", root.display(), e);
+                true
+            }
+        }
+    }
+
+    /// Free and total space of the filesystem holding `root`
+    pub async fn status(&self, root: &Path) -> io::Result<DiskStatus> {
+        let limits = *self.limits.lock().unwrap();
+        let (available_bytes, total_bytes) = self.space(root).await?;
+        Ok(DiskStatus {
+            available_bytes,
+            total_bytes,
+            reserve_bytes: limits.reserve_bytes,
+            low: available_bytes
+                < limits.warning_bytes.max(limits.reserve_bytes),
+        })
+    }
+
+    async fn space(&self, root: &Path) -> io::Result<(u64, u64)> {
+        if let Some((read_at, space)) = self.readings.lock().unwrap().get(root)
+            && read_at.elapsed() < READING_TTL
+        {
+            return Ok(*space);
+        }
+        let path = root.to_path_buf();
+        let space = tokio::task::spawn_blocking(move || {
+            let stat = nix::sys::statvfs::statvfs(&path)?;
+            let unit = stat.fragment_size() as u64;
+            Ok::<_, io::Error>((
+                stat.blocks_available() as u64 * unit,
+                stat.blocks() as u64 * unit,
+            ))
+        })
+        .await
+        .map_err(io::Error::other)??;
+        self.readings
+            .lock()
+            .unwrap()
+            .insert(root.to_path_buf(), (Instant::now(), space));
+        Ok(space)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_reserve_is_kept() {
+        let root = std::env::temp_dir();
+        let guard = DiskGuard::new(DiskLimits::default());
+        let status = guard.status(&root).await.unwrap();
+        assert!(status.total_bytes >= status.available_bytes);
+        assert!(!status.low);
+        assert!(guard.allows(&root, u64::MAX).await);
+
+        guard.reconfigure(DiskLimits {
+            reserve_bytes: 1,
+            warning_bytes: u64::MAX,
+        });
+        assert!(guard.allows(&root, 0).await);
+        assert!(!guard.allows(&root, status.available_bytes).await);
+        assert!(guard.status(&root).await.unwrap().low);
+    }
+}
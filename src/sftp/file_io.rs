@@ -0,0 +1,298 @@
+use nix::fcntl::{FcntlArg, OFlag, fcntl};
+use std::io;
+use std::ops::Range;
+use std::os::fd::{AsFd, AsRawFd, OwnedFd};
+use std::sync::Arc;
+use tokio::fs::File;
+
+/// Operations queued on the ring at most
+const RING_ENTRIES: u32 = 256;
+
+/// How the SFTP server reads and writes file contents: through tokio::fs,
+/// which runs each call on the blocking thread pool, or through io_uring,
+/// which batches positional reads and writes of all sessions on one ring.
+/// io_uring is only available on Linux builds with the io-uring feature.
+#[derive(Clone, Default)]
+pub struct FileIo {
+    ring: Option<Arc<ring::Ring>>,
+}
+
+/// Descriptor of an open file for io_uring operations. It is a duplicate
+/// that stays open until they complete, even if the handle is closed.
+#[derive(Clone)]
+pub struct DirectFile {
+    fd: Arc<OwnedFd>,
+    readable: bool,
+}
+
+impl DirectFile {
+    /// Whether the file was opened for reading
+    pub fn is_readable(&self) -> bool {
+        self.readable
+    }
+}
+
+impl FileIo {
+    /// Sets up io_uring, which fails without kernel support, e.g. on
+    /// kernels before 5.6 or where seccomp filters it out
+    pub fn io_uring() -> io::Result<Self> {
+        Ok(Self { ring: Some(Arc::new(ring::Ring::new(RING_ENTRIES)?)) })
+    }
+
+    pub fn is_io_uring(&self) -> bool {
+        self.ring.is_some()
+    }
+
+    /// Descriptor for io_uring operations on `file`; none with tokio::fs,
+    /// in which case the file is used directly
+    pub fn direct(&self, file: &File) -> Option<DirectFile> {
+        self.ring.as_ref()?;
+        let fd = file.as_fd().try_clone_to_owned().ok()?;
+        let flags = fcntl(fd.as_raw_fd(), FcntlArg::F_GETFL).ok()?;
+        let mode = OFlag::from_bits_truncate(flags) & OFlag::O_ACCMODE;
+        Some(DirectFile { fd: fd.into(), readable: mode != OFlag::O_WRONLY })
+    }
+
+    /// Reads up to `len` bytes at `offset`; fewer at the end of the file
+    pub async fn read_at(
+        &self,
+        file: &DirectFile,
+        offset: u64,
+        len: usize,
+    ) -> io::Result<Vec<u8>> {
+        let (read, mut buffer) =
+            self.submit(file, offset, vec![0; len], 0..len, false).await?;
+        buffer.truncate(read);
+        Ok(buffer)
+    }
+
+    /// Writes `data[range]` at `offset + range.start`, returning `data`
+    pub async fn write_at(
+        &self,
+        file: &DirectFile,
+        offset: u64,
+        mut data: Vec<u8>,
+        range: Range<usize>,
+    ) -> io::Result<Vec<u8>> {
+        let mut start = range.start;
+        while start < range.end {
+            let written;
+            (written, data) =
+                self.submit(file, offset, data, start..range.end, true).await?;
+            if written == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            start += written;
+        }
+        Ok(data)
+    }
+
+    async fn submit(
+        &self,
+        file: &DirectFile,
+        offset: u64,
+        buffer: Vec<u8>,
+        range: Range<usize>,
+        write: bool,
+    ) -> io::Result<(usize, Vec<u8>)> {
+        let ring = self.ring.as_ref().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::Unsupported, "io_uring is not in use")
+        })?;
+        ring.submit(Job {
+            fd: file.fd.clone(),
+            offset: offset + range.start as u64,
+            buffer,
+            range,
+            write,
+        })
+        .await
+    }
+}
+
+/// A read into or a write from `buffer[range]` at `offset`
+#[cfg_attr(
+    not(all(target_os = "linux", feature = "io-uring")),
+    allow(dead_code)
+)]
+struct Job {
+    fd: Arc<OwnedFd>,
+    offset: u64,
+    buffer: Vec<u8>,
+    range: Range<usize>,
+    write: bool,
+}
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+mod ring {
+    use super::Job;
+    use io_uring::{IoUring, opcode, types};
+    use std::collections::HashMap;
+    use std::io;
+    use std::os::fd::AsRawFd;
+    use std::sync::mpsc;
+    use tokio::sync::oneshot;
+    use tracing::error;
+
+    type Reply = oneshot::Sender<io::Result<(usize, Vec<u8>)>>;
+
+    /// Ring driven by a dedicated thread, which submits the queued jobs
+    /// together and answers them as they complete
+    pub(super) struct Ring {
+        jobs: mpsc::Sender<(Job, Reply)>,
+    }
+
+    impl Ring {
+        pub(super) fn new(entries: u32) -> io::Result<Self> {
+            let ring = IoUring::new(entries)?;
+            let (tx, rx) = mpsc::channel();
+            std::thread::Builder::new()
+                .name("sftp-io-uring".to_string())
+                .spawn(move || {
+                    if let Err(e) = run(ring, rx) {
+                        error!("io_uring thread failed: {}", e);
+                    }
+                })?;
+            Ok(Self { jobs: tx })
+        }
+
+        pub(super) async fn submit(
+            &self,
+            job: Job,
+        ) -> io::Result<(usize, Vec<u8>)> {
+            let (tx, rx) = oneshot::channel();
+            self.jobs.send((job, tx)).map_err(|_| stopped())?;
+            rx.await.map_err(|_| stopped())?
+        }
+    }
+
+    /// Leaks the jobs in flight when the thread gives up, as the kernel may
+    /// still be using their buffers
+    fn abandon(in_flight: HashMap<u64, (Job, Reply)>) {
+        std::mem::forget(in_flight);
+    }
+
+    fn stopped() -> io::Error {
+        io::Error::other("io_uring thread stopped")
+    }
+
+    /// Runs until the ring is dropped and the jobs in flight are done.
+    /// Jobs own their buffer and descriptor, so both outlive the kernel's
+    /// use of them even when the waiting request is abandoned.
+    fn run(
+        mut ring: IoUring,
+        jobs: mpsc::Receiver<(Job, Reply)>,
+    ) -> io::Result<()> {
+        let capacity = ring.params().sq_entries() as usize;
+        let mut in_flight: HashMap<u64, (Job, Reply)> = HashMap::new();
+        let mut next_id = 0u64;
+        loop {
+            let mut queued = Vec::new();
+            if in_flight.is_empty() {
+                match jobs.recv() {
+                    Ok(job) => queued.push(job),
+                    Err(_) => return Ok(()),
+                }
+            }
+            while in_flight.len() + queued.len() < capacity {
+                match jobs.try_recv() {
+                    Ok(job) => queued.push(job),
+                    Err(_) => break,
+                }
+            }
+
+            for (mut job, reply) in queued {
+                let fd = types::Fd(job.fd.as_raw_fd());
+                let len = job.range.len() as u32;
+                let entry = if job.write {
+                    let data = job.buffer[job.range.clone()].as_ptr();
+                    opcode::Write::new(fd, data, len).offset(job.offset).build()
+                } else {
+                    let data = job.buffer[job.range.clone()].as_mut_ptr();
+                    opcode::Read::new(fd, data, len).offset(job.offset).build()
+                };
+                // The buffer's heap allocation does not move with the job
+                let entry = entry.user_data(next_id);
+                // SAFETY: the descriptor and buffer are kept in `in_flight`
+                // until the operation completes
+                if let Err(e) = unsafe { ring.submission().push(&entry) } {
+                    abandon(in_flight);
+                    return Err(io::Error::other(e));
+                }
+                in_flight.insert(next_id, (job, reply));
+                next_id = next_id.wrapping_add(1);
+            }
+
+            if let Err(e) = ring.submit_and_wait(1)
+                && e.kind() != io::ErrorKind::Interrupted
+            {
+                abandon(in_flight);
+                return Err(e);
+            }
+            for completion in ring.completion() {
+                let Some((job, reply)) =
+                    in_flight.remove(&completion.user_data())
+                else {
+                    continue;
+                };
+                let result = match completion.result() {
+                    n if n < 0 => Err(io::Error::from_raw_os_error(-n)),
+                    n => Ok((n as usize, job.buffer)),
+                };
+                let _ = reply.send(result);
+            }
+        }
+    }
+}
+
+#[cfg(not(all(target_os = "linux", feature = "io-uring")))]
+mod ring {
+    use super::Job;
+    use std::io;
+
+    /// Stand-in for builds without io_uring support, never constructed
+    pub(super) struct Ring;
+
+    impl Ring {
+        pub(super) fn new(_entries: u32) -> io::Result<Self> {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "built without the io-uring feature",
+            ))
+        }
+
+        pub(super) async fn submit(
+            &self,
+            _job: Job,
+        ) -> io::Result<(usize, Vec<u8>)> {
+            Err(io::ErrorKind::Unsupported.into())
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux", feature = "io-uring"))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_io_uring_reads_and_writes_at_offsets() {
+        // Kernels without io_uring fall back to tokio::fs
+        let Ok(io) = FileIo::io_uring() else { return };
+        let path = std::env::temp_dir()
+            .join(format!("sftp-manager-io-uring-{}", std::process::id()));
+        let file = File::create(&path).await.unwrap();
+        let direct = io.direct(&file).unwrap();
+
+        let data =
+            io.write_at(&direct, 2, b"--hello".to_vec(), 2..7).await.unwrap();
+        assert_eq!(data, b"--hello");
+        io.write_at(&direct, 0, b"head".to_vec(), 0..4).await.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"headhello");
+
+        let read = File::open(&path).await.unwrap();
+        let direct = io.direct(&read).unwrap();
+        assert_eq!(io.read_at(&direct, 4, 100).await.unwrap(), b"hello");
+        assert!(io.read_at(&direct, 9, 100).await.unwrap().is_empty());
+
+        let _ = std::fs::remove_file(path);
+    }
+}
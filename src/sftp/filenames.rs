@@ -0,0 +1,313 @@
+use crate::sftp::glob::glob_match;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+
+/// Unicode normalization form applied to names created by clients
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Normalization {
+    /// Names are stored as sent
+    #[default]
+    None,
+    /// Composed, as created on Linux and Windows
+    Nfc,
+    /// Decomposed, as sent by macOS clients
+    Nfd,
+}
+
+/// Treatment of names that are awkward or invalid on other platforms:
+/// control characters, trailing spaces or dots and names Windows reserves
+/// for devices, such as "CON" or "nul.txt"
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Sanitization {
+    /// Names are accepted as they are
+    #[default]
+    Off,
+    /// Offending characters are replaced or dropped, reserved names get a
+    /// leading '_'
+    Lenient,
+    /// Such names are refused
+    Strict,
+}
+
+/// Treatment of names starting with a dot, such as the ".DS_Store" and
+/// "._report.pdf" files macOS clients leave behind
+#[derive(Debug, Clone, Default)]
+pub struct Dotfiles {
+    /// Leave them out of directory listings
+    pub hide: bool,
+    /// Refuse to create them
+    pub block: bool,
+    /// Glob patterns of names that are neither hidden nor refused, e.g.
+    /// ".keep"
+    pub allow: Vec<String>,
+}
+
+impl Dotfiles {
+    fn applies_to(&self, name: &str) -> bool {
+        name.starts_with('.')
+            && name != "."
+            && name != ".."
+            && !self.allow.iter().any(|pattern| glob_match(pattern, name))
+    }
+}
+
+/// How names of new files and directories are rewritten or refused
+#[derive(Debug, Clone, Default)]
+pub struct FilenamePolicy {
+    pub normalization: Normalization,
+    /// Refuse names that were not valid UTF-8
+    pub reject_invalid_utf8: bool,
+    /// Replace non-ASCII characters by ASCII lookalikes, e.g. "é" by "e"
+    pub transliterate: bool,
+    pub sanitization: Sanitization,
+    pub dotfiles: Dotfiles,
+}
+
+/// Why a name was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilenameError {
+    InvalidUtf8,
+    ControlCharacter,
+    TrailingSpaceOrDot,
+    Reserved(String),
+    Dotfile(String),
+}
+
+impl fmt::Display for FilenameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilenameError::InvalidUtf8 => write!(f, "name is not valid UTF-8"),
+            FilenameError::ControlCharacter => {
+                write!(f, "name contains a control character")
+            }
+            FilenameError::TrailingSpaceOrDot => {
+                write!(f, "name ends with a space or a dot")
+            }
+            FilenameError::Reserved(name) => {
+                write!(f, "'{}' is a reserved name", name)
+            }
+            FilenameError::Dotfile(name) => {
+                write!(f, "'{}' is a dotfile", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FilenameError {}
+
+/// Device names Windows reserves, with or without an extension
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6",
+    "COM7", "COM8", "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6",
+    "LPT7", "LPT8", "LPT9",
+];
+
+impl FilenamePolicy {
+    /// `path` in the form names are stored in, without refusing or
+    /// sanitizing anything; used to look up files that already exist
+    pub fn normalize(&self, path: &str) -> String {
+        if self.transliterate {
+            transliterate(path)
+        } else {
+            match self.normalization {
+                Normalization::None => path.to_string(),
+                Normalization::Nfc => path.nfc().collect(),
+                Normalization::Nfd => path.nfd().collect(),
+            }
+        }
+    }
+
+    /// The name to store for `path` as sent by a client. Invalid UTF-8 has
+    /// already been replaced by U+FFFD when the packet was decoded.
+    pub fn apply(&self, path: &str) -> Result<String, FilenameError> {
+        if self.reject_invalid_utf8
+            && path.contains(char::REPLACEMENT_CHARACTER)
+        {
+            return Err(FilenameError::InvalidUtf8);
+        }
+        let path = self.normalize(path);
+        // Only the name being created; it may be inside a hidden directory
+        let name = path.rsplit('/').next().unwrap_or_default();
+        if self.dotfiles.block && self.dotfiles.applies_to(name) {
+            return Err(FilenameError::Dotfile(name.to_string()));
+        }
+        if self.sanitization == Sanitization::Off {
+            return Ok(path);
+        }
+        let components = path
+            .split('/')
+            .map(|component| match component {
+                "" | "." | ".." => Ok(component.to_string()),
+                _ => self.sanitize(component),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(components.join("/"))
+    }
+
+    /// Whether `name`, an entry of a directory, is left out of listings
+    pub fn hides(&self, name: &str) -> bool {
+        self.dotfiles.hide && self.dotfiles.applies_to(name)
+    }
+
+    /// One path component, checked or cleaned up
+    fn sanitize(&self, name: &str) -> Result<String, FilenameError> {
+        let strict = self.sanitization == Sanitization::Strict;
+        let mut name = if name.chars().any(char::is_control) {
+            if strict {
+                return Err(FilenameError::ControlCharacter);
+            }
+            name.replace(char::is_control, "_")
+        } else {
+            name.to_string()
+        };
+        if name.ends_with([' ', '.']) {
+            if strict {
+                return Err(FilenameError::TrailingSpaceOrDot);
+            }
+            name.truncate(name.trim_end_matches([' ', '.']).len());
+            if name.is_empty() {
+                name.push('_');
+            }
+        }
+        let stem = name.split('.').next().unwrap_or_default().trim_end();
+        if RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(stem)) {
+            if strict {
+                return Err(FilenameError::Reserved(name));
+            }
+            name.insert(0, '_');
+        }
+        Ok(name)
+    }
+}
+
+/// ASCII version of a name: accents are dropped and a few letters spelled
+/// out; anything else outside ASCII becomes '_'
+fn transliterate(name: &str) -> String {
+    let mut ascii = String::with_capacity(name.len());
+    for c in name.nfd().filter(|c| !is_combining_mark(*c)) {
+        match c {
+            c if c.is_ascii() => ascii.push(c),
+            'ß' => ascii.push_str("ss"),
+            'æ' => ascii.push_str("ae"),
+            'Æ' => ascii.push_str("AE"),
+            'œ' => ascii.push_str("oe"),
+            'Œ' => ascii.push_str("OE"),
+            'ø' => ascii.push('o'),
+            'Ø' => ascii.push('O'),
+            'đ' | 'ð' => ascii.push('d'),
+            'Đ' | 'Ð' => ascii.push('D'),
+            'ł' => ascii.push('l'),
+            'Ł' => ascii.push('L'),
+            'þ' => ascii.push_str("th"),
+            'Þ' => ascii.push_str("Th"),
+            _ => ascii.push('_'),
+        }
+    }
+    ascii
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names_are_normalized() {
+        let decomposed = "/in/Cafe\u{301}.csv";
+        let composed = "/in/Caf\u{e9}.csv";
+        let policy = |normalization| FilenamePolicy {
+            normalization,
+            ..Default::default()
+        };
+        assert_eq!(
+            policy(Normalization::Nfc).apply(decomposed).unwrap(),
+            composed
+        );
+        assert_eq!(
+            policy(Normalization::Nfd).apply(composed).unwrap(),
+            decomposed
+        );
+        assert_eq!(
+            policy(Normalization::None).apply(decomposed).unwrap(),
+            decomposed
+        );
+
+        let policy = FilenamePolicy {
+            reject_invalid_utf8: true,
+            transliterate: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            policy.apply("/Straße/Ærø 日.txt").unwrap(),
+            "/Strasse/AEro _.txt"
+        );
+        assert_eq!(
+            policy.apply("/bad\u{fffd}.txt"),
+            Err(FilenameError::InvalidUtf8)
+        );
+    }
+
+    #[test]
+    fn test_awkward_names_are_sanitized_or_refused() {
+        let mut policy = FilenamePolicy {
+            sanitization: Sanitization::Lenient,
+            ..Default::default()
+        };
+        assert_eq!(
+            policy.apply("/in/a\tb/report. .").unwrap(),
+            "/in/a_b/report"
+        );
+        assert_eq!(policy.apply("/nul.txt").unwrap(), "/_nul.txt");
+        assert_eq!(policy.apply("/../Com1").unwrap(), "/../_Com1");
+        assert_eq!(policy.apply("/console.log").unwrap(), "/console.log");
+
+        policy.sanitization = Sanitization::Strict;
+        assert_eq!(
+            policy.apply("/a\u{7}b"),
+            Err(FilenameError::ControlCharacter)
+        );
+        assert_eq!(
+            policy.apply("/dir /x"),
+            Err(FilenameError::TrailingSpaceOrDot)
+        );
+        assert_eq!(
+            policy.apply("/AUX.tar.gz"),
+            Err(FilenameError::Reserved("AUX.tar.gz".to_string()))
+        );
+        assert_eq!(policy.apply("/ok.txt").unwrap(), "/ok.txt");
+    }
+
+    #[test]
+    fn test_dotfiles_are_hidden_or_refused() {
+        let mut policy = FilenamePolicy {
+            dotfiles: Dotfiles {
+                hide: true,
+                block: false,
+                allow: vec![".keep".to_string(), ".~lock.*#".to_string()],
+            },
+            ..Default::default()
+        };
+        assert!(policy.hides(".DS_Store"));
+        assert!(policy.hides("._report.pdf"));
+        assert!(!policy.hides(".keep"));
+        assert!(!policy.hides(".~lock.orders.csv#"));
+        assert!(!policy.hides("report.pdf"));
+        assert!(!policy.hides(".."));
+        assert_eq!(policy.apply("/in/.DS_Store").unwrap(), "/in/.DS_Store");
+
+        policy.dotfiles.block = true;
+        assert_eq!(
+            policy.apply("/in/._a.pdf"),
+            Err(FilenameError::Dotfile("._a.pdf".to_string()))
+        );
+        assert_eq!(policy.apply("/in/.keep").unwrap(), "/in/.keep");
+        assert_eq!(policy.apply("/.git/config").unwrap(), "/.git/config");
+    }
+}
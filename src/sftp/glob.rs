@@ -0,0 +1,50 @@
+// Match a path against a glob pattern. `*` matches within one path
+// segment, `**` matches across segments and `?` matches one character.
+pub fn glob_match(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let path: Vec<char> = path.chars().collect();
+    match_from(&pattern, &path)
+}
+
+fn match_from(pattern: &[char], path: &[char]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        ['*', '*', rest @ ..] => {
+            // "**/" also matches no directory at all
+            let rest_after_slash = rest.strip_prefix(&['/']).unwrap_or(rest);
+            (0..=path.len()).any(|i| {
+                match_from(rest, &path[i..])
+                    || match_from(rest_after_slash, &path[i..])
+            })
+        }
+        ['*', rest @ ..] => {
+            let segment = path.iter().position(|c| *c == '/');
+            let end = segment.unwrap_or(path.len());
+            (0..=end).any(|i| match_from(rest, &path[i..]))
+        }
+        ['?', rest @ ..] => match path {
+            [c, tail @ ..] if *c != '/' => match_from(rest, tail),
+            _ => false,
+        },
+        [p, rest @ ..] => match path {
+            [c, tail @ ..] if c == p => match_from(rest, tail),
+            _ => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("/incoming/*.csv", "/incoming/a.csv"));
+        assert!(!glob_match("/incoming/*.csv", "/incoming/sub/a.csv"));
+        assert!(glob_match("/incoming/**/*.csv", "/incoming/sub/a.csv"));
+        assert!(glob_match("/incoming/**/*.csv", "/incoming/a.csv"));
+        assert!(glob_match("**", "/any/thing"));
+        assert!(glob_match("/data-??.txt", "/data-01.txt"));
+        assert!(!glob_match("/data-??.txt", "/data-1.txt"));
+    }
+}
@@ -1,27 +1,77 @@
+use crate::events::Event;
+use crate::sftp::acl::Operation;
+use crate::sftp::checksum::UploadChecksum;
+use crate::sftp::file_io::DirectFile;
+use crate::sftp::handles::HandleTable;
+use crate::sftp::hooks::CompletedUpload;
+use crate::sftp::locks::{
+    BLOCK_DELETE, BLOCK_READ, BLOCK_WRITE, LockError, LockOwner, RangeRequest,
+};
+use crate::sftp::memory::{HANDLE_BYTES, MemoryBudget, name_bytes};
+use crate::sftp::read_ahead::ReadAhead;
+use crate::sftp::scratch::{SCRATCH_DIR, ScratchArea};
+use crate::sftp::server::ServerContext;
+use crate::sftp::sparse;
+use crate::sftp::transfer_slots::{TransferSlot, TransferSlots};
+use crate::sftp::trash::{TRASH_DIR, Trash};
 use russh_sftp::protocol::{
-    Data, File, FileAttributes, Handle, Name, OpenFlags, Status, StatusCode,
-    Version,
+    Data, ExtendedReply, File, FileAttributes, Handle, Name, OpenFlags, Packet,
+    Status, StatusCode, Version,
 };
 use std::collections::HashMap;
+use std::fs::Metadata;
+use std::net::SocketAddr;
 use std::os::unix::prelude::{MetadataExt, PermissionsExt};
 use std::path::{Path, PathBuf};
-use std::time::UNIX_EPOCH;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Instant, UNIX_EPOCH};
 use tokio::{
     io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
     {fs, io},
 };
 use tracing::{debug, error, info, warn};
 
+/// Extension through which clients learn the largest reads and writes
+const LIMITS_EXTENSION: &str = "limits@openssh.com";
+
+/// Extensions carrying the byte-range lock requests of SFTP v6, whose own
+/// packet types a version 3 server cannot receive
+const BLOCK_EXTENSION: &str = "block@sftp-manager";
+const UNBLOCK_EXTENSION: &str = "unblock@sftp-manager";
+
+/// Extension through which clients ask where to resume an upload: the
+/// request carries the path, the reply the offset as a uint64
+const RESUME_EXTENSION: &str = "resume-offset@sftp-manager";
+
 /// Maintains the session state for an SFTP connection
 pub struct SftpSession {
     /// Protocol version negotiated with a client
     version: Option<u32>,
     /// Root directory for this SFTP session
     root_dir: String,
-    /// Map of open file/directory handles
-    open_handles: HashMap<String, OpenHandle>,
-    /// Counter for generating unique handle IDs
-    next_handle_id: u64,
+    /// Open file/directory handles by the token given to the client
+    open_handles: HandleTable<OpenHandle>,
+    /// Authenticated user owning this session
+    username: String,
+    /// Shared events and statistics
+    context: ServerContext,
+    /// ID of the owning SSH session in the session registry
+    session_id: u64,
+    /// Remote address of the client
+    peer_addr: Option<SocketAddr>,
+    /// Memory held in handles, listings and read-ahead
+    memory: MemoryBudget,
+    /// Number of this session among the holders of byte-range locks
+    lock_session: u64,
+    /// Private scratch area, when sessions get one
+    scratch: Option<ScratchArea>,
+    /// Whether the scratch area was created, so it is removed at the end
+    scratch_created: AtomicBool,
+    /// Files streamed in all channels of the SSH session
+    transfers: TransferSlots,
+    /// Number of this channel among those of the SSH session
+    channel: u64,
 }
 
 /// Holds file/directory information for open handles
@@ -29,41 +79,337 @@ pub struct OpenHandle {
     /// Whether this handle refers to a directory
     pub is_dir: bool,
     /// List of directory contents if this is a directory handle
-    pub dir_contents: Option<Vec<String>>,
+    pub dir_contents: Option<Arc<[String]>>,
     /// Current index when reading directory contents
     pub dir_index: usize,
     /// Full path of the opened file/directory
     pub path: PathBuf,
+    /// Flags the file was opened with; reads need READ
+    pub pflags: OpenFlags,
     /// File handle (if this is a file)
     pub file: Option<fs::File>,
+    /// Number of bytes written through this handle
+    pub bytes_written: u64,
+    /// Number of bytes read through this handle
+    pub bytes_read: u64,
+    /// When the handle was opened
+    pub opened_at: Instant,
+    /// Digests of the data written, for handles opened for writing
+    pub checksum: Option<UploadChecksum>,
+    /// Descriptor for io_uring reads and writes, when it is in use
+    pub direct: Option<DirectFile>,
+    /// Chunks prefetched for sequential reads
+    pub read_ahead: ReadAhead,
+    /// Taken from the session's memory budget for this handle
+    pub memory_bytes: usize,
+    /// Held from the first read or write until the end of the file is
+    /// read or the handle is closed
+    pub transfer_slot: Option<TransferSlot>,
+    /// Last read or write, to hand the slot of the least recently used
+    /// handle over when the channel holds all it may
+    pub transferred_at: Instant,
 }
 
 impl SftpSession {
     /// Creates a new SFTP session with the specified root directory
-    pub fn new(root_dir: String) -> Self {
+    pub fn new(
+        root_dir: String,
+        username: String,
+        context: ServerContext,
+        session_id: u64,
+        peer_addr: Option<SocketAddr>,
+        transfers: TransferSlots,
+    ) -> Self {
         debug!("Creating new SFTP session with root: {}", root_dir);
+        let memory = MemoryBudget::new(context.session_memory_bytes);
+        let lock_session = context.locks.session();
+        let scratch = context
+            .scratch
+            .as_deref()
+            .map(|dir| ScratchArea::new(dir, lock_session));
         Self {
             version: None,
             root_dir,
-            open_handles: HashMap::new(),
-            next_handle_id: 1,
+            open_handles: HandleTable::default(),
+            username,
+            context,
+            session_id,
+            peer_addr,
+            memory,
+            lock_session,
+            scratch,
+            scratch_created: AtomicBool::new(false),
+            channel: transfers.channel(),
+            transfers,
+        }
+    }
+
+    /// Publishes the number of open file handles and the memory they hold
+    /// to the session registry
+    fn report_open_files(&self) {
+        let open_files =
+            self.open_handles.values().filter(|h| !h.is_dir).count();
+        self.context.sessions.set_open_files(self.session_id, open_files);
+        self.context
+            .sessions
+            .set_memory_bytes(self.session_id, self.memory.used());
+    }
+
+    /// Gives the file behind `handle` one of the session's transfer slots
+    /// unless it has one, waiting while other channels hold all of them.
+    /// When none is free and this channel holds some, the slot of its
+    /// least recently used handle is handed over.
+    async fn claim_transfer_slot(&mut self, handle: &str) {
+        let Some(open_handle) = self.open_handles.get_mut(handle) else {
+            return;
+        };
+        open_handle.transferred_at = Instant::now();
+        if open_handle.is_dir || open_handle.transfer_slot.is_some() {
+            return;
+        }
+        let slot = match self.transfers.acquire(self.channel).await {
+            Some(slot) => Some(slot),
+            None => {
+                debug!(
+                    "All transfer slots are held, taking over the least \
+                     recently used one of this channel"
+                );
+                self.open_handles
+                    .values_mut()
+                    .filter(|h| h.transfer_slot.is_some())
+                    .min_by_key(|h| h.transferred_at)
+                    .and_then(|h| h.transfer_slot.take())
+            }
+        };
+        if let Some(open_handle) = self.open_handles.get_mut(handle) {
+            open_handle.transfer_slot = slot;
+        }
+    }
+
+    /// Logs and counts a request refused for the session's memory budget
+    fn refuse_memory(&self, request: &str) -> StatusCode {
+        warn!(
+            user = %self.username,
+            "Refusing {}: over the session memory budget, {} bytes in use",
+            request,
+            self.memory.used()
+        );
+        self.context.stats.record_memory_refusal();
+        StatusCode::Failure
+    }
+
+    /// Path under which a file or directory named by the client is created,
+    /// following the filename policy
+    fn incoming_name(&self, path: String) -> Result<String, StatusCode> {
+        let name = self.context.filenames.apply(&path).map_err(|e| {
+            warn!("Refusing to create '{}': {}", path, e);
+            StatusCode::Failure
+        })?;
+        if name != path {
+            debug!("Creating '{}' as '{}'", path, name);
+        }
+        Ok(name)
+    }
+
+    /// `path` in the form the filename policy stores names in, so files
+    /// created under a rewritten name are found by the name the client
+    /// sent. Names stored before the policy changed are kept as sent.
+    fn existing_name(&self, path: &str) -> String {
+        let name = self.context.filenames.normalize(path);
+        if name == path {
+            return name;
         }
+        let exists = |path: &str| {
+            Path::new(&self.root_dir)
+                .join(path.trim_start_matches('/'))
+                .exists()
+        };
+        if !exists(&name) && exists(path) {
+            return path.to_string();
+        }
+        name
+    }
+
+    /// Where a file the client names `path` is stored. New files directly
+    /// in a landing directory are created in today's dated directory;
+    /// existing ones are looked up by their normalized name there and in
+    /// yesterday's unless they exist under the undated path.
+    fn landed_path(&self, path: String, creating: bool) -> String {
+        let path = if creating { path } else { self.existing_name(&path) };
+        let landed = self.context.landing.landed_paths(&path);
+        let Some(today) = landed.first() else {
+            return path;
+        };
+        if creating {
+            debug!("Filing '{}' as '{}'", path, today);
+            return today.clone();
+        }
+        let exists = |path: &str| {
+            Path::new(&self.root_dir)
+                .join(path.trim_start_matches('/'))
+                .exists()
+        };
+        if exists(&path) {
+            return path;
+        }
+        landed.into_iter().find(|p| exists(p)).unwrap_or(path)
+    }
+
+    /// Refuses `operation` on `path` when the path rules do not allow it
+    fn check_access(
+        &self,
+        path: &Path,
+        operation: Operation,
+    ) -> Result<(), StatusCode> {
+        let path = self.virtual_path(path);
+        if self.context.path_rules.allows(&path, operation) {
+            return Ok(());
+        }
+        warn!(
+            user = %self.username,
+            "Refusing to {} {}: not allowed by the path rules",
+            operation,
+            path
+        );
+        Err(StatusCode::PermissionDenied)
     }
 
-    /// Generates a unique handle ID string
-    fn generate_handle(&mut self) -> String {
-        let handle_id = self.next_handle_id;
-        self.next_handle_id += 1;
-        format!("handle_{}", handle_id)
+    /// Holder of the byte-range locks taken through `handle`; path
+    /// operations go through none, so all locks apply to them
+    fn lock_owner(&self, handle: &str) -> LockOwner {
+        LockOwner { session: self.lock_session, handle: handle.to_string() }
+    }
+
+    /// Refuses `access`, one of the lock BLOCK_ bits, to a range of `path`
+    /// locked by another handle
+    fn check_lock(
+        &self,
+        path: &Path,
+        handle: &str,
+        access: u32,
+        offset: u64,
+        length: u64,
+    ) -> Result<(), StatusCode> {
+        let owner = self.lock_owner(handle);
+        if !self.context.locks.is_blocked(path, &owner, access, offset, length)
+        {
+            return Ok(());
+        }
+        warn!(
+            user = %self.username,
+            "Refusing access to {}: byte range locked by another handle",
+            self.virtual_path(path)
+        );
+        Err(StatusCode::Failure)
+    }
+
+    /// Takes or releases a byte-range lock, answering with its outcome
+    fn lock_range(&self, id: u32, request: &str, data: &[u8]) -> Packet {
+        let block = request == BLOCK_EXTENSION;
+        let status = |status_code, message: &str| {
+            Packet::Status(Status {
+                id,
+                status_code,
+                error_message: message.to_string(),
+                language_tag: "en-US".to_string(),
+            })
+        };
+        let Some(range) = RangeRequest::parse(data, block) else {
+            warn!("Malformed {} request", request);
+            return status(StatusCode::BadMessage, "Malformed request");
+        };
+        let Some(open_handle) =
+            self.open_handles.get(&range.handle).filter(|h| !h.is_dir)
+        else {
+            warn!("Invalid handle for {}: {:?}", request, range.handle);
+            return status(StatusCode::Failure, "Invalid handle");
+        };
+
+        let locks = &self.context.locks;
+        let owner = self.lock_owner(&range.handle);
+        let path = &open_handle.path;
+        let result = if block {
+            locks.block(path, owner, range.offset, range.length, range.mask)
+        } else {
+            locks.unblock(path, &owner, range.offset, range.length)
+        };
+        // SFTP v3 has neither SSH_FX_LOCK_CONFLICT nor
+        // SSH_FX_NO_MATCHING_BYTE_RANGE_LOCK; the message tells clients
+        match result {
+            Ok(()) => {
+                debug!(
+                    "{} {} bytes from {} of {}",
+                    if block { "Locked" } else { "Unlocked" },
+                    range.length,
+                    range.offset,
+                    path.display()
+                );
+                status(StatusCode::Ok, "Ok")
+            }
+            Err(LockError::Conflict) => {
+                info!(
+                    user = %self.username,
+                    "Lock on {} conflicts with another handle's",
+                    self.virtual_path(path)
+                );
+                status(StatusCode::Failure, "Byte range lock conflict")
+            }
+            Err(LockError::NoMatchingLock) => {
+                status(StatusCode::Failure, "No matching byte range lock")
+            }
+        }
+    }
+
+    /// Answers where to resume uploading the file named in `data`
+    async fn resume_offset(
+        &self,
+        id: u32,
+        data: &[u8],
+    ) -> Result<Packet, StatusCode> {
+        let length = data.get(..4).ok_or(StatusCode::BadMessage)?;
+        let length = u32::from_be_bytes(length.try_into().unwrap()) as usize;
+        let path = data.get(4..4 + length).ok_or(StatusCode::BadMessage)?;
+        let path = String::from_utf8_lossy(path).to_string();
+        let path = self.landed_path(path, false);
+
+        let full_path = self
+            .normalize_path(&path)
+            .await
+            .map_err(|_| StatusCode::NoSuchFile)?;
+        self.check_access(&full_path, Operation::Write)?;
+        let metadata = self
+            .metadata(&full_path)
+            .await
+            .map_err(|_| StatusCode::NoSuchFile)?;
+        if !metadata.is_file() {
+            return Err(StatusCode::NoSuchFile);
+        }
+
+        let point =
+            self.context.uploads.resume_point(&full_path, path, metadata.len());
+        debug!("Upload of {} resumes at {}", point.path, point.offset);
+        Ok(Packet::ExtendedReply(ExtendedReply {
+            id,
+            data: point.offset.to_be_bytes().to_vec(),
+        }))
+    }
+
+    /// Whether `incoming` more bytes may be written under the root without
+    /// eating into the disk reserve or going over the quota
+    async fn has_space(&self, incoming: u64) -> bool {
+        self.context.has_space(Path::new(&self.root_dir), incoming).await
     }
 
     /// Creates a File object from a path with proper attributes
     async fn path_to_file(&self, path: &Path) -> io::Result<File> {
-        let metadata = fs::metadata(path).await?;
+        let metadata = self.metadata(path).await?;
         let attrs = FileAttributes {
             size: if metadata.is_file() { Some(metadata.len()) } else { None },
             uid: Some(metadata.uid()),
+            // Shown in the long listing instead of the numeric IDs
+            user: self.context.owner_names.user(metadata.uid()).await,
             gid: Some(metadata.gid()),
+            group: self.context.owner_names.group(metadata.gid()).await,
             permissions: Some(metadata.permissions().mode()),
             atime: metadata.accessed().ok().and_then(|t| {
                 t.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs() as u32)
@@ -71,7 +417,6 @@ impl SftpSession {
             mtime: metadata.modified().ok().and_then(|t| {
                 t.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs() as u32)
             }),
-            ..Default::default()
         };
 
         let file_name = path
@@ -82,12 +427,339 @@ impl SftpSession {
         Ok(File::new(file_name, attrs))
     }
 
+    /// Attributes of a path, from the listing cache when it is enabled
+    async fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        let Some(cache) = &self.context.listing_cache else {
+            return fs::metadata(path).await;
+        };
+        if let Some(metadata) = cache.metadata(path) {
+            return Ok(metadata);
+        }
+        let metadata = fs::metadata(path).await?;
+        cache.insert_metadata(path.to_path_buf(), metadata.clone());
+        Ok(metadata)
+    }
+
+    /// Forgets the cached listing and attributes a change makes stale
+    fn invalidate(&self, path: &Path) {
+        if let Some(cache) = &self.context.listing_cache {
+            cache.invalidate(path);
+        }
+    }
+
+    /// Like `invalidate`, for directories whose contents moved or went
+    fn invalidate_tree(&self, path: &Path) {
+        if let Some(cache) = &self.context.listing_cache {
+            cache.invalidate_tree(path);
+        }
+    }
+
+    /// Names in a directory, without the trash at the root
+    /// Fails once the names would take more than `limit` bytes, without
+    /// reading the rest of the directory. Returns the names and their size.
+    async fn list_names(
+        &self,
+        dir: &Path,
+        limit: usize,
+    ) -> Result<(Arc<[String]>, usize), StatusCode> {
+        if let Some(names) =
+            self.context.listing_cache.as_ref().and_then(|c| c.listing(dir))
+        {
+            let bytes = names.iter().map(|n| name_bytes(n)).sum();
+            if bytes > limit {
+                return Err(self.refuse_memory("directory listing"));
+            }
+            return Ok((names, bytes));
+        }
+
+        let mut entries = fs::read_dir(dir).await.map_err(|e| {
+            warn!(
+                "Permission denied reading directory '{}': {}",
+                dir.display(),
+                e
+            );
+            StatusCode::PermissionDenied
+        })?;
+
+        let at_root = self.virtual_path(dir) == "/";
+        let hide_trash = self.context.trash && at_root;
+        let mut names = vec![];
+        let mut bytes = 0;
+        while let Some(entry) = entries.next_entry().await.map_err(|e| {
+            warn!("Failed to read directory entry: {}", e);
+            StatusCode::Failure
+        })? {
+            if let Ok(name) = entry.file_name().into_string() {
+                if hide_trash && name == TRASH_DIR
+                    || at_root && name == SCRATCH_DIR
+                    || self.context.filenames.hides(&name)
+                {
+                    continue;
+                }
+                bytes += name_bytes(&name);
+                if bytes > limit {
+                    return Err(self.refuse_memory("directory listing"));
+                }
+                names.push(name);
+            }
+        }
+
+        let names: Arc<[String]> = names.into();
+        if let Some(cache) = &self.context.listing_cache {
+            cache.insert_listing(dir.to_path_buf(), names.clone());
+        }
+        Ok((names, bytes))
+    }
+
+    /// Opens a file for `open` once the handle fits in the memory budget
+    async fn open_file(
+        &mut self,
+        id: u32,
+        path: PathBuf,
+        pflags: OpenFlags,
+        creating_file: bool,
+    ) -> Result<Handle, StatusCode> {
+        // Ensure parent directories exist when creating files
+        if creating_file
+            && let Some(parent) = path.parent()
+            && !parent.exists()
+        {
+            info!("Creating parent directories for: {}", path.display());
+            fs::create_dir_all(parent).await.map_err(|e| {
+                error!("Failed to create parent directories: {}", e);
+                StatusCode::PermissionDenied
+            })?;
+        }
+
+        // Configure file opening options
+        let mut open_options = fs::OpenOptions::new();
+        if pflags.contains(OpenFlags::READ) {
+            open_options.read(true);
+        }
+        if pflags.contains(OpenFlags::WRITE) {
+            open_options.write(true);
+        }
+        if pflags.contains(OpenFlags::CREATE) {
+            open_options.create(true);
+        }
+        if pflags.contains(OpenFlags::TRUNCATE) {
+            open_options.truncate(true);
+        }
+        if pflags.contains(OpenFlags::APPEND) {
+            open_options.append(true);
+        }
+
+        // Open the file
+        let file = open_options.open(&path).await.map_err(|e| {
+            error!("Failed to open file {}: {}", path.display(), e);
+            StatusCode::Failure
+        })?;
+        if creating_file || pflags.contains(OpenFlags::WRITE) {
+            self.invalidate(&path);
+        }
+        if pflags.intersects(OpenFlags::WRITE | OpenFlags::APPEND) {
+            let existing = file.metadata().await.map_or(0, |m| m.len());
+            self.context.uploads.opened(&path, existing);
+        }
+
+        let checksum = (pflags.contains(OpenFlags::WRITE)
+            && !self.context.checksum_algorithms.is_empty())
+        .then(|| UploadChecksum::new(&self.context.checksum_algorithms));
+
+        // Read-ahead is left out rather than the file refused when its
+        // buffers do not fit in the budget
+        let limits = self.context.transfer_limits;
+        let read_ahead_bytes =
+            limits.read_ahead_chunks * limits.max_read_bytes as usize;
+        let (read_ahead_chunks, memory_bytes) =
+            if self.memory.try_reserve(read_ahead_bytes) {
+                (limits.read_ahead_chunks, HANDLE_BYTES + read_ahead_bytes)
+            } else {
+                (0, HANDLE_BYTES)
+            };
+
+        let direct = self.context.file_io.direct(&file);
+        let handle = self.open_handles.insert(OpenHandle {
+            is_dir: false,
+            dir_contents: None,
+            dir_index: 0,
+            file: Some(file),
+            direct,
+            read_ahead: ReadAhead::new(read_ahead_chunks),
+            path,
+            pflags,
+            bytes_written: 0,
+            bytes_read: 0,
+            opened_at: Instant::now(),
+            checksum,
+            memory_bytes,
+            transfer_slot: None,
+            transferred_at: Instant::now(),
+        });
+        debug!("Created handle {}", handle);
+        self.report_open_files();
+
+        Ok(Handle { id, handle })
+    }
+
+    /// Logs a summary of a closed file handle and reports the transfer.
+    /// A handle that both read and wrote counts as an upload.
+    async fn finish_transfer(&self, handle: OpenHandle) {
+        let (direction, bytes) = match (handle.bytes_written, handle.bytes_read)
+        {
+            (0, 0) => return,
+            (0, read) => ("download", read),
+            (written, _) => ("upload", written),
+        };
+
+        let path = self.virtual_path(&handle.path);
+        let duration = handle.opened_at.elapsed();
+        let duration_ms = duration.as_millis() as u64;
+        let peer = self.peer_addr.map(|a| a.ip().to_string());
+        info!(
+            user = %self.username,
+            client = peer.as_deref().unwrap_or("-"),
+            path = %path,
+            bytes_read = handle.bytes_read,
+            bytes_written = handle.bytes_written,
+            duration_ms,
+            throughput_bps = (bytes as f64 / duration.as_secs_f64().max(0.001))
+                as u64,
+            "Transfer finished: {} {}",
+            direction,
+            path
+        );
+
+        if handle.bytes_written > 0 {
+            self.context.stats.record_upload();
+            let checksums = match handle.checksum {
+                Some(checksum) => match checksum.finish(&handle.path).await {
+                    Ok(checksums) => checksums,
+                    Err(e) => {
+                        error!(
+                            "Failed to compute checksums of {}: {}",
+                            path, e
+                        );
+                        Default::default()
+                    }
+                },
+                None => Default::default(),
+            };
+            let staged = self.in_scratch(&handle.path);
+            let upload = CompletedUpload {
+                username: self.username.clone(),
+                peer,
+                path,
+                local_path: handle.path,
+                root_dir: PathBuf::from(&self.root_dir),
+                bytes,
+                duration_ms,
+                checksums,
+            };
+            // Files staged in the scratch area complete once moved out
+            if staged {
+                self.context.completion.staged(upload);
+            } else {
+                self.context.completion.closed(upload);
+            }
+            return;
+        }
+        self.context.stats.record_download();
+        self.context.events.publish(Event::FileDownloaded {
+            username: self.username.clone(),
+            peer,
+            path,
+            bytes,
+            duration_ms,
+        });
+    }
+
+    /// Converts an absolute path back into the client's view of the tree
+    fn virtual_path(&self, path: &Path) -> String {
+        let root = Path::new(&self.root_dir)
+            .canonicalize()
+            .unwrap_or_else(|_| PathBuf::from(&self.root_dir));
+
+        let Ok(relative) = path.strip_prefix(&root) else {
+            return path.display().to_string();
+        };
+        self.scratch
+            .as_ref()
+            .and_then(|scratch| scratch.to_virtual(relative))
+            .unwrap_or_else(|| format!("/{}", relative.display()))
+    }
+
+    /// Creates the scratch area on first use
+    async fn create_scratch(&self, scratch: &ScratchArea) -> io::Result<()> {
+        if self.scratch_created.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let dir = scratch.local_dir(Path::new(&self.root_dir));
+        fs::create_dir_all(&dir).await?;
+        self.scratch_created.store(true, Ordering::Relaxed);
+        debug!("Created scratch area {}", dir.display());
+        Ok(())
+    }
+
+    /// Whether a full path lies in the session's scratch area
+    fn in_scratch(&self, full_path: &Path) -> bool {
+        let (Some(scratch), Ok(root)) =
+            (&self.scratch, Path::new(&self.root_dir).canonicalize())
+        else {
+            return false;
+        };
+        full_path.strip_prefix(&root).is_ok_and(|r| scratch.owns(r))
+    }
+
+    /// The listing of `dir` with the scratch area added when it appears
+    /// there, and the bytes of the name added
+    async fn with_scratch_entry(
+        &self,
+        dir: &Path,
+        names: Arc<[String]>,
+    ) -> (Arc<[String]>, usize) {
+        let Some(scratch) = &self.scratch else {
+            return (names, 0);
+        };
+        let (parent, name) = scratch.entry();
+        if self.virtual_path(dir) != parent
+            || names.iter().any(|n| n == name)
+            || self.create_scratch(scratch).await.is_err()
+        {
+            return (names, 0);
+        }
+        let mut names = names.to_vec();
+        names.push(name.to_string());
+        (names.into(), name_bytes(name))
+    }
+
+    /// Location of the entry `name` of a listing of `dir`, which is the
+    /// scratch area for the name it appears under
+    fn entry_path(&self, dir: &Path, name: &str) -> PathBuf {
+        match &self.scratch {
+            Some(scratch)
+                if scratch.entry() == (&self.virtual_path(dir), name) =>
+            {
+                scratch.local_dir(Path::new(&self.root_dir))
+            }
+            _ => dir.join(name),
+        }
+    }
+
     /// Normalizes and secures file paths within the root
     /// Prevents directory traversal attacks
     async fn normalize_path(&self, path: &str) -> io::Result<PathBuf> {
         debug!("Normalizing path: {}", path);
         let root_path = Path::new(&self.root_dir);
 
+        // Paths in the scratch area lead to its own directory
+        let scratch_path = self.scratch.as_ref().and_then(|s| s.to_local(path));
+        if let (Some(scratch), Some(_)) = (&self.scratch, &scratch_path) {
+            self.create_scratch(scratch).await?;
+        }
+        let path = scratch_path.as_deref().unwrap_or(path);
+        let path = &self.existing_name(path);
+
         // Handle empty or root path cases
         if path.is_empty() || path == "/" {
             return match root_path.canonicalize() {
@@ -106,12 +778,43 @@ impl SftpSession {
         debug!("Target path after joining: {}", target_path.display());
 
         // Special handling for paths that don't exist yet
-        if !target_path.exists() {
-            return self.handle_nonexistent_path(target_path, root_path).await;
+        let full_path = if !target_path.exists() {
+            self.handle_nonexistent_path(target_path, root_path).await?
+        } else {
+            // For existing paths, canonicalize and check
+            self.canonicalize_and_validate(target_path, root_path).await?
+        };
+
+        // Scratch areas are private to their sessions
+        let scratch_dirs = root_path.canonicalize()?.join(SCRATCH_DIR);
+        if full_path.starts_with(&scratch_dirs) && !self.in_scratch(&full_path)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "Scratch area of another session",
+            ));
         }
 
-        // For existing paths, canonicalize and check
-        self.canonicalize_and_validate(target_path, root_path).await
+        // Deleted items are only reachable through the API
+        if self.context.trash
+            && Trash::contains(Path::new(&self.virtual_path(&full_path)))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "Trash is not accessible",
+            ));
+        }
+        Ok(full_path)
+    }
+
+    /// Moves a removed file or directory into the trash
+    async fn move_to_trash(&self, full_path: &Path) -> io::Result<()> {
+        let path = self.virtual_path(full_path);
+        let entry = Trash::new(&self.root_dir)
+            .put(full_path, &path, &self.username)
+            .await?;
+        info!("Moved {} to the trash as {}", path, entry.id);
+        Ok(())
     }
 
     /// Handle normalization for paths that don't exist yet
@@ -197,6 +900,30 @@ impl SftpSession {
     }
 }
 
+impl Drop for SftpSession {
+    /// Handles left open when the channel ends are closed with it, and so
+    /// are their byte-range locks. The scratch area goes with the session.
+    fn drop(&mut self) {
+        self.context.locks.release_session(self.lock_session);
+        if let Some(scratch) = &self.scratch
+            && self.scratch_created.load(Ordering::Relaxed)
+            && let Ok(runtime) = tokio::runtime::Handle::try_current()
+        {
+            let dir = scratch.local_dir(Path::new(&self.root_dir));
+            runtime.spawn(async move {
+                match fs::remove_dir_all(&dir).await {
+                    Ok(()) => debug!("Removed scratch area {}", dir.display()),
+                    Err(e) => warn!(
+                        "Failed to remove scratch area {}: {}",
+                        dir.display(),
+                        e
+                    ),
+                }
+            });
+        }
+    }
+}
+
 impl russh_sftp::server::Handler for SftpSession {
     type Error = StatusCode;
 
@@ -218,7 +945,35 @@ impl russh_sftp::server::Handler for SftpSession {
         self.version = Some(version);
         info!("SFTP version: {}, extensions: {:?}", version, extensions);
 
-        Ok(Version::new())
+        let mut reply = Version::new();
+        reply.extensions.insert(LIMITS_EXTENSION.to_string(), "1".to_string());
+        for extension in [BLOCK_EXTENSION, UNBLOCK_EXTENSION, RESUME_EXTENSION]
+        {
+            reply.extensions.insert(extension.to_string(), "1".to_string());
+        }
+        Ok(reply)
+    }
+
+    async fn extended(
+        &mut self,
+        id: u32,
+        request: String,
+        data: Vec<u8>,
+    ) -> Result<Packet, Self::Error> {
+        match request.as_str() {
+            LIMITS_EXTENSION => Ok(Packet::ExtendedReply(ExtendedReply {
+                id,
+                data: self.context.transfer_limits.openssh_limits(),
+            })),
+            BLOCK_EXTENSION | UNBLOCK_EXTENSION => {
+                Ok(self.lock_range(id, &request, &data))
+            }
+            RESUME_EXTENSION => self.resume_offset(id, &data).await,
+            _ => {
+                debug!("Unsupported SFTP extension requested: {}", request);
+                Err(StatusCode::OpUnsupported)
+            }
+        }
     }
 
     async fn open(
@@ -230,65 +985,52 @@ impl russh_sftp::server::Handler for SftpSession {
     ) -> Result<Handle, Self::Error> {
         info!("Opening file: {}, flags: {:?}", filename, pflags);
 
+        // Running transfers may finish while stopping, new ones may not
+        if self.context.sessions.is_closing() {
+            warn!("Refusing to open {} while the server stops", filename);
+            return Err(StatusCode::Failure);
+        }
+
+        if pflags.intersects(OpenFlags::WRITE | OpenFlags::APPEND)
+            && !self.has_space(0).await
+        {
+            warn!("Refusing to open {} for writing: no space left", filename);
+            return Err(StatusCode::Failure);
+        }
+
         let creating_file = pflags.contains(OpenFlags::CREATE);
+        let filename = if creating_file {
+            self.incoming_name(filename)?
+        } else {
+            filename
+        };
+        let filename = self.landed_path(filename, creating_file);
 
         let path = self.normalize_path(&filename).await.map_err(|e| {
             warn!("Failed to normalize path '{}': {}", filename, e);
             StatusCode::NoSuchFile
         })?;
 
-        // Ensure parent directories exist when creating files
-        if creating_file
-            && let Some(parent) = path.parent()
-            && !parent.exists()
-        {
-            info!("Creating parent directories for: {}", path.display());
-            fs::create_dir_all(parent).await.map_err(|e| {
-                error!("Failed to create parent directories: {}", e);
-                StatusCode::PermissionDenied
-            })?;
-        }
-
-        // Configure file opening options
-        let mut open_options = fs::OpenOptions::new();
         if pflags.contains(OpenFlags::READ) {
-            open_options.read(true);
+            self.check_access(&path, Operation::Read)?;
         }
-        if pflags.contains(OpenFlags::WRITE) {
-            open_options.write(true);
-        }
-        if pflags.contains(OpenFlags::CREATE) {
-            open_options.create(true);
+        if pflags.intersects(
+            OpenFlags::WRITE
+                | OpenFlags::APPEND
+                | OpenFlags::CREATE
+                | OpenFlags::TRUNCATE,
+        ) {
+            self.check_access(&path, Operation::Write)?;
         }
-        if pflags.contains(OpenFlags::TRUNCATE) {
-            open_options.truncate(true);
+
+        if !self.memory.try_reserve(HANDLE_BYTES) {
+            return Err(self.refuse_memory("file handle"));
         }
-        if pflags.contains(OpenFlags::APPEND) {
-            open_options.append(true);
+        let result = self.open_file(id, path, pflags, creating_file).await;
+        if result.is_err() {
+            self.memory.release(HANDLE_BYTES);
         }
-
-        // Open the file
-        let file = open_options.open(&path).await.map_err(|e| {
-            error!("Failed to open file {}: {}", path.display(), e);
-            StatusCode::Failure
-        })?;
-
-        // Create and store the handle
-        let handle = self.generate_handle();
-        debug!("Created handle {} for file: {}", handle, path.display());
-
-        self.open_handles.insert(
-            handle.clone(),
-            OpenHandle {
-                is_dir: false,
-                dir_contents: None,
-                dir_index: 0,
-                file: Some(file),
-                path,
-            },
-        );
-
-        Ok(Handle { id, handle })
+        result
     }
 
     async fn close(
@@ -296,11 +1038,27 @@ impl russh_sftp::server::Handler for SftpSession {
         id: u32,
         handle: String,
     ) -> Result<Status, Self::Error> {
-        info!("Closing handle: {}", handle);
-        if self.open_handles.remove(&handle).is_some() {
-            debug!("Successfully closed handle: {}", handle);
-        } else {
-            warn!("Attempted to close non-existent handle: {}", handle);
+        info!("Closing handle: {:?}", handle);
+        // Handles are closed once; unknown ones may be guesses
+        let Some(open_handle) = self.open_handles.remove(&handle) else {
+            warn!("Attempted to close non-existent handle: {:?}", handle);
+            return Err(StatusCode::Failure);
+        };
+        debug!("Successfully closed handle: {}", handle);
+        self.context.locks.release_handle(&self.lock_owner(&handle));
+        self.memory.release(open_handle.memory_bytes);
+        self.report_open_files();
+
+        if !open_handle.is_dir {
+            if open_handle.bytes_written > 0 {
+                self.invalidate(&open_handle.path);
+            }
+            if let Some(file) = &open_handle.file
+                && let Ok(metadata) = file.metadata().await
+            {
+                self.context.uploads.closed(&open_handle.path, metadata.len());
+            }
+            self.finish_transfer(open_handle).await;
         }
         Ok(Status {
             id,
@@ -322,27 +1080,86 @@ impl russh_sftp::server::Handler for SftpSession {
             handle, offset, len
         );
 
+        // Files are read by path, so the open mode is checked here; a file
+        // opened for writing only was not checked against the read rules
+        let write_only = self
+            .open_handles
+            .get(&handle)
+            .is_some_and(|h| !h.is_dir && !h.pflags.contains(OpenFlags::READ));
+        if write_only {
+            warn!(
+                "Refusing to read from handle {} opened without READ",
+                handle
+            );
+            return Err(StatusCode::PermissionDenied);
+        }
+
+        // The client picks the length, so it is capped before allocating
+        let len = len.min(self.context.transfer_limits.max_read_bytes);
+        self.claim_transfer_slot(&handle).await;
+
+        let path = self
+            .open_handles
+            .get(&handle)
+            .filter(|h| !h.is_dir)
+            .map(|h| h.path.clone());
+        if let Some(path) = path {
+            self.check_lock(&path, &handle, BLOCK_READ, offset, len.into())?;
+        }
+
         let open_handle =
-            self.open_handles.get(&handle).ok_or(StatusCode::Failure)?;
+            self.open_handles.get_mut(&handle).ok_or(StatusCode::Failure)?;
 
         if open_handle.is_dir {
             warn!("Attempt to read from directory handle: {}", handle);
             return Err(StatusCode::Failure);
         }
 
-        let mut file = fs::File::open(&open_handle.path)
-            .await
-            .map_err(|_| StatusCode::Failure)?;
-
-        file.seek(io::SeekFrom::Start(offset))
-            .await
-            .map_err(|_| StatusCode::Failure)?;
-
-        let mut buffer = vec![0u8; len as usize];
-        let n =
-            file.read(&mut buffer).await.map_err(|_| StatusCode::Failure)?;
+        let prefetched = open_handle
+            .read_ahead
+            .read(&open_handle.path, offset, len as usize)
+            .await;
+        let direct = open_handle.direct.as_ref().filter(|d| d.is_readable());
+        let buffer = match (prefetched, direct) {
+            (Some(prefetched), _) => prefetched.map_err(|e| {
+                error!("Failed to read ahead at offset {}: {}", offset, e);
+                StatusCode::Failure
+            })?,
+            (None, Some(direct)) => self
+                .context
+                .file_io
+                .read_at(direct, offset, len as usize)
+                .await
+                .map_err(|e| {
+                    error!("Failed to read at offset {}: {}", offset, e);
+                    StatusCode::Failure
+                })?,
+            (None, None) => {
+                let mut file = fs::File::open(&open_handle.path)
+                    .await
+                    .map_err(|_| StatusCode::Failure)?;
+
+                file.seek(io::SeekFrom::Start(offset))
+                    .await
+                    .map_err(|_| StatusCode::Failure)?;
+
+                let mut buffer = vec![0u8; len as usize];
+                let n = file
+                    .read(&mut buffer)
+                    .await
+                    .map_err(|_| StatusCode::Failure)?;
+                buffer.truncate(n);
+                buffer
+            }
+        };
 
-        buffer.truncate(n);
+        let n = buffer.len();
+        if n == 0 {
+            // At the end of the file, so another file may stream meanwhile
+            open_handle.transfer_slot = None;
+        }
+        open_handle.bytes_read += n as u64;
+        self.context.stats.record_bytes_out(n as u64);
         Ok(Data { id, data: buffer })
     }
 
@@ -360,9 +1177,62 @@ impl russh_sftp::server::Handler for SftpSession {
             data.len()
         );
 
+        let max_write = self.context.transfer_limits.max_write_bytes;
+        if data.len() > max_write as usize {
+            warn!(
+                "Refusing write of {} bytes to handle {}: over {} bytes",
+                data.len(),
+                handle,
+                max_write
+            );
+            return Ok(Status {
+                id,
+                status_code: StatusCode::Failure,
+                error_message: format!(
+                    "Writes are limited to {} bytes",
+                    max_write
+                ),
+                language_tag: "en-US".to_string(),
+            });
+        }
+
+        // SFTP v3 has no code for a full disk; the message tells clients
+        if !self.has_space(data.len() as u64).await {
+            warn!("Refusing write to handle {}: no space left", handle);
+            return Ok(Status {
+                id,
+                status_code: StatusCode::Failure,
+                error_message: "No space left on device".to_string(),
+                language_tag: "en-US".to_string(),
+            });
+        }
+
+        let path = self
+            .open_handles
+            .get(&handle)
+            .filter(|h| !h.is_dir)
+            .map(|h| h.path.clone());
+        if let Some(path) = path
+            && !data.is_empty()
+        {
+            let length = data.len() as u64;
+            if self
+                .check_lock(&path, &handle, BLOCK_WRITE, offset, length)
+                .is_err()
+            {
+                return Ok(Status {
+                    id,
+                    status_code: StatusCode::Failure,
+                    error_message: "Byte range lock conflict".to_string(),
+                    language_tag: "en-US".to_string(),
+                });
+            }
+        }
+
+        self.claim_transfer_slot(&handle).await;
         let open_handle =
             self.open_handles.get_mut(&handle).ok_or_else(|| {
-                warn!("Invalid handle: {}", handle);
+                warn!("Invalid handle: {:?}", handle);
                 StatusCode::Failure
             })?;
 
@@ -376,20 +1246,54 @@ impl russh_sftp::server::Handler for SftpSession {
             StatusCode::Failure
         })?;
 
-        file.seek(io::SeekFrom::Start(offset)).await.map_err(|e| {
-            error!("Failed to seek to offset {}: {}", offset, e);
-            StatusCode::Failure
-        })?;
+        let data = match &open_handle.direct {
+            Some(direct) => sparse::write_direct(
+                &self.context.file_io,
+                direct,
+                file,
+                offset,
+                data,
+                self.context.sparse_files,
+            )
+            .await
+            .map_err(|e| {
+                error!("Failed to write data at offset {}: {}", offset, e);
+                StatusCode::Failure
+            })?,
+            None => {
+                sparse::write_at(
+                    file,
+                    offset,
+                    &data,
+                    self.context.sparse_files,
+                )
+                .await
+                .map_err(|e| {
+                    error!("Failed to write data at offset {}: {}", offset, e);
+                    StatusCode::Failure
+                })?;
 
-        file.write_all(&data).await.map_err(|e| {
-            error!("Failed to write data: {}", e);
-            StatusCode::Failure
-        })?;
+                file.flush().await.map_err(|e| {
+                    error!("Failed to flush data: {}", e);
+                    StatusCode::Failure
+                })?;
+                data
+            }
+        };
 
-        file.flush().await.map_err(|e| {
-            error!("Failed to flush data: {}", e);
-            StatusCode::Failure
-        })?;
+        if let Some(checksum) = &mut open_handle.checksum {
+            checksum.update(offset, &data);
+        }
+        self.context.uploads.written(
+            &open_handle.path,
+            offset,
+            data.len() as u64,
+        );
+        open_handle.bytes_written += data.len() as u64;
+        open_handle.read_ahead.reset();
+        let path = open_handle.path.clone();
+        self.invalidate(&path);
+        self.context.stats.record_bytes_in(data.len() as u64);
 
         Ok(Status {
             id,
@@ -410,8 +1314,9 @@ impl russh_sftp::server::Handler for SftpSession {
             warn!("Failed to normalize path '{}': {}", path, e);
             StatusCode::NoSuchFile
         })?;
+        self.check_access(&full_path, Operation::List)?;
 
-        let metadata = fs::metadata(&full_path).await.map_err(|e| {
+        let metadata = self.metadata(&full_path).await.map_err(|e| {
             warn!(
                 "Failed to read metadata for '{}': {}",
                 full_path.display(),
@@ -425,42 +1330,38 @@ impl russh_sftp::server::Handler for SftpSession {
             return Err(StatusCode::NoSuchFile);
         }
 
-        let mut entries = fs::read_dir(&full_path).await.map_err(|e| {
-            warn!(
-                "Permission denied reading directory '{}': {}",
-                full_path.display(),
-                e
-            );
-            StatusCode::PermissionDenied
-        })?;
-
-        let mut names = vec![];
-        while let Some(entry) = entries.next_entry().await.map_err(|e| {
-            warn!("Failed to read directory entry: {}", e);
-            StatusCode::Failure
-        })? {
-            if let Ok(name) = entry.file_name().into_string() {
-                names.push(name);
-            }
+        let limit = self.memory.remaining().saturating_sub(HANDLE_BYTES);
+        let (names, bytes) = self.list_names(&full_path, limit).await?;
+        let (names, scratch_bytes) =
+            self.with_scratch_entry(&full_path, names).await;
+        let memory_bytes = HANDLE_BYTES + bytes + scratch_bytes;
+        if !self.memory.try_reserve(memory_bytes) {
+            return Err(self.refuse_memory("directory listing"));
         }
 
-        let handle = self.generate_handle();
+        let entries = names.len();
+        let handle = self.open_handles.insert(OpenHandle {
+            is_dir: true,
+            dir_contents: Some(names),
+            dir_index: 0,
+            path: full_path,
+            pflags: OpenFlags::empty(),
+            file: None,
+            bytes_written: 0,
+            bytes_read: 0,
+            opened_at: Instant::now(),
+            checksum: None,
+            direct: None,
+            read_ahead: ReadAhead::new(0),
+            memory_bytes,
+            transfer_slot: None,
+            transferred_at: Instant::now(),
+        });
         debug!(
             "Created directory handle '{}' with {} entries",
-            handle,
-            names.len()
-        );
-
-        self.open_handles.insert(
-            handle.clone(),
-            OpenHandle {
-                is_dir: true,
-                dir_contents: Some(names),
-                dir_index: 0,
-                path: full_path,
-                file: None,
-            },
+            handle, entries
         );
+        self.report_open_files();
 
         Ok(Handle { id, handle })
     }
@@ -481,7 +1382,7 @@ impl russh_sftp::server::Handler for SftpSession {
         ) = {
             let open_handle =
                 self.open_handles.get_mut(&handle).ok_or_else(|| {
-                    warn!("Invalid directory handle: {}", handle);
+                    warn!("Invalid directory handle: {:?}", handle);
                     StatusCode::Failure
                 })?;
 
@@ -534,10 +1435,11 @@ impl russh_sftp::server::Handler for SftpSession {
 
         // Process each file in the batch
         for filename in dir_contents {
-            let path_buf = current_dir_path.join(&filename);
+            let path_buf = self.entry_path(&current_dir_path, &filename);
             match self.path_to_file(&path_buf).await {
+                // Named as listed, which the scratch area is not on disk
                 Ok(file) => {
-                    files.push(file);
+                    files.push(File::new(filename, file.attrs));
                 }
                 Err(e) => {
                     warn!("Failed to get file info for {}: {}", filename, e);
@@ -562,11 +1464,14 @@ impl russh_sftp::server::Handler for SftpSession {
         path: String,
     ) -> Result<Status, Self::Error> {
         info!("Remove file: {}", path);
+        let path = self.landed_path(path, false);
 
         let full_path = self
             .normalize_path(&path)
             .await
             .map_err(|_| StatusCode::NoSuchFile)?;
+        self.check_access(&full_path, Operation::Delete)?;
+        self.check_lock(&full_path, "", BLOCK_DELETE, 0, 0)?;
 
         if !full_path.exists() {
             warn!("Path does not exist: {}", full_path.display());
@@ -583,10 +1488,19 @@ impl russh_sftp::server::Handler for SftpSession {
             return Err(StatusCode::Failure);
         }
 
-        fs::remove_file(&full_path).await.map_err(|e| {
+        let removed = if self.context.trash {
+            self.move_to_trash(&full_path).await
+        } else {
+            fs::remove_file(&full_path).await
+        };
+        removed.map_err(|e| {
             error!("Failed to remove file {}: {}", full_path.display(), e);
             StatusCode::Failure
         })?;
+        self.invalidate(&full_path);
+        self.context.uploads.removed(&full_path);
+        self.context.completion.removed(&full_path);
+        self.context.stats.record_delete();
 
         Ok(Status {
             id,
@@ -603,11 +1517,13 @@ impl russh_sftp::server::Handler for SftpSession {
         _attrs: FileAttributes,
     ) -> Result<Status, Self::Error> {
         info!("Create directory: {}", path);
+        let path = self.incoming_name(path)?;
 
         let full_path = self.normalize_path(&path).await.map_err(|e| {
             warn!("Failed to normalize path '{}': {}", path, e);
             StatusCode::NoSuchFile
         })?;
+        self.check_access(&full_path, Operation::Write)?;
 
         if full_path.exists() {
             if full_path.is_dir() {
@@ -631,6 +1547,7 @@ impl russh_sftp::server::Handler for SftpSession {
             error!("Failed to create directory {}: {}", full_path.display(), e);
             StatusCode::Failure
         })?;
+        self.invalidate(&full_path);
 
         info!("Successfully created directory: {}", full_path.display());
         Ok(Status {
@@ -652,6 +1569,8 @@ impl russh_sftp::server::Handler for SftpSession {
             .normalize_path(&path)
             .await
             .map_err(|_| StatusCode::NoSuchFile)?;
+        self.check_access(&full_path, Operation::Delete)?;
+        self.check_lock(&full_path, "", BLOCK_DELETE, 0, 0)?;
 
         if !full_path.exists() {
             warn!("Path does not exist: {}", full_path.display());
@@ -668,10 +1587,28 @@ impl russh_sftp::server::Handler for SftpSession {
             return Err(StatusCode::Failure);
         }
 
-        fs::remove_dir(&full_path).await.map_err(|e| {
+        let removed = if self.context.trash {
+            // Only empty directories can be removed, as without the trash
+            let mut entries = fs::read_dir(&full_path).await.map_err(|e| {
+                error!("Failed to read {}: {}", full_path.display(), e);
+                StatusCode::Failure
+            })?;
+            match entries.next_entry().await {
+                Ok(None) => self.move_to_trash(&full_path).await,
+                Ok(Some(_)) => Err(io::Error::new(
+                    io::ErrorKind::DirectoryNotEmpty,
+                    "Directory not empty",
+                )),
+                Err(e) => Err(e),
+            }
+        } else {
+            fs::remove_dir(&full_path).await
+        };
+        removed.map_err(|e| {
             error!("Failed to remove directory {}: {}", full_path.display(), e);
             StatusCode::Failure
         })?;
+        self.invalidate_tree(&full_path);
 
         Ok(Status {
             id,
@@ -705,13 +1642,15 @@ impl russh_sftp::server::Handler for SftpSession {
         path: String,
     ) -> Result<russh_sftp::protocol::Attrs, Self::Error> {
         debug!("Stat request for: {}", path);
+        let path = self.landed_path(path, false);
 
         let full_path = self.normalize_path(&path).await.map_err(|e| {
             warn!("Failed to normalize path '{}': {}", path, e);
             StatusCode::NoSuchFile
         })?;
+        self.check_access(&full_path, Operation::Stat)?;
 
-        let metadata = fs::metadata(&full_path).await.map_err(|e| {
+        let metadata = self.metadata(&full_path).await.map_err(|e| {
             warn!("Failed to stat file '{}': {}", full_path.display(), e);
             StatusCode::NoSuchFile
         })?;
@@ -744,6 +1683,22 @@ impl russh_sftp::server::Handler for SftpSession {
         newpath: String,
     ) -> Result<Status, Self::Error> {
         info!("Rename: {} to {}", oldpath, newpath);
+        let newpath = self.incoming_name(newpath)?;
+        // A file filed by date is renamed within its dated directory, so a
+        // partial upload finished after midnight stays with its day
+        let oldpath = self.existing_name(&oldpath);
+        let landed = self.landed_path(oldpath.clone(), false);
+        let into_landing =
+            !self.context.landing.landed_paths(&newpath).is_empty();
+        let newpath = match landed.rsplit_once('/') {
+            Some((dir, _)) if landed != oldpath && into_landing => {
+                let name = newpath.rsplit('/').next().unwrap_or_default();
+                format!("{}/{}", dir, name)
+            }
+            _ if landed != oldpath => newpath,
+            _ => self.landed_path(newpath, true),
+        };
+        let oldpath = landed;
 
         let old_full_path = self
             .normalize_path(&oldpath)
@@ -754,11 +1709,24 @@ impl russh_sftp::server::Handler for SftpSession {
             .normalize_path(&newpath)
             .await
             .map_err(|_| StatusCode::NoSuchFile)?;
+        self.check_access(&old_full_path, Operation::Write)?;
+        self.check_access(&new_full_path, Operation::Write)?;
+        // Replacing a file removes it as much as renaming it away does
+        self.check_lock(&old_full_path, "", BLOCK_DELETE, 0, 0)?;
+        self.check_lock(&new_full_path, "", BLOCK_DELETE, 0, 0)?;
 
         if !old_full_path.exists() {
             warn!("Old path does not exist: {}", old_full_path.display());
             return Err(StatusCode::NoSuchFile);
         }
+        if let Some(parent) = new_full_path.parent()
+            && !parent.exists()
+        {
+            fs::create_dir_all(parent).await.map_err(|e| {
+                error!("Failed to create parent directories: {}", e);
+                StatusCode::Failure
+            })?;
+        }
 
         fs::rename(&old_full_path, &new_full_path).await.map_err(|e| {
             error!(
@@ -769,6 +1737,15 @@ impl russh_sftp::server::Handler for SftpSession {
             );
             StatusCode::Failure
         })?;
+        self.invalidate_tree(&old_full_path);
+        self.invalidate_tree(&new_full_path);
+        self.context.uploads.renamed(&old_full_path, &new_full_path);
+        self.context.completion.renamed(
+            &old_full_path,
+            &new_full_path,
+            self.virtual_path(&new_full_path),
+            self.in_scratch(&new_full_path),
+        );
 
         Ok(Status {
             id,
@@ -778,3 +1755,145 @@ impl russh_sftp::server::Handler for SftpSession {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sftp::acl::{PathAccess, PathRule, PathRules};
+    use crate::sftp::filenames::{FilenamePolicy, Normalization};
+    use russh_sftp::protocol::FileAttributes;
+    use russh_sftp::server::Handler;
+    use std::time::Duration;
+
+    fn session(
+        root: &Path,
+        context: ServerContext,
+        transfers: &TransferSlots,
+    ) -> SftpSession {
+        SftpSession::new(
+            root.to_string_lossy().to_string(),
+            "bob".to_string(),
+            context,
+            1,
+            None,
+            transfers.clone(),
+        )
+    }
+
+    async fn open_for_reading(session: &mut SftpSession, path: &str) -> String {
+        session
+            .open(
+                0,
+                path.to_string(),
+                OpenFlags::READ,
+                FileAttributes::default(),
+            )
+            .await
+            .unwrap()
+            .handle
+    }
+
+    #[tokio::test]
+    async fn test_existing_files_are_found_by_unnormalized_names() {
+        let root = std::env::temp_dir()
+            .join(format!("sftp-manager-handler-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let context = ServerContext {
+            filenames: FilenamePolicy {
+                normalization: Normalization::Nfc,
+                ..Default::default()
+            },
+            ..ServerContext::for_tests()
+        };
+        let mut session = session(&root, context, &TransferSlots::default());
+
+        let composed = "/Caf\u{e9}.csv";
+        let decomposed = "/Cafe\u{301}.csv";
+        let flags = OpenFlags::CREATE | OpenFlags::WRITE;
+        let handle = session
+            .open(1, decomposed.to_string(), flags, FileAttributes::default())
+            .await
+            .unwrap();
+        session.close(2, handle.handle).await.unwrap();
+        assert!(root.join(composed.trim_start_matches('/')).exists());
+
+        session.stat(3, decomposed.to_string()).await.unwrap();
+        session
+            .rename(4, decomposed.to_string(), "/done.csv".to_string())
+            .await
+            .unwrap();
+        assert!(root.join("done.csv").exists());
+        assert!(!root.join(composed.trim_start_matches('/')).exists());
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[tokio::test]
+    async fn test_files_past_the_transfer_limit_share_or_wait_for_slots() {
+        let root = std::env::temp_dir()
+            .join(format!("sftp-manager-transfers-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        for name in ["a.csv", "b.csv", "c.csv", "d.csv"] {
+            std::fs::write(root.join(name), b"data").unwrap();
+        }
+        // Two slots for the SSH session, shared by its two channels
+        let transfers = TransferSlots::new(2);
+        let mut first = session(&root, ServerContext::for_tests(), &transfers);
+        let mut second = session(&root, ServerContext::for_tests(), &transfers);
+
+        // More open files than slots on one channel, read in turns
+        let mut handles = Vec::new();
+        for path in ["/a.csv", "/b.csv", "/c.csv"] {
+            handles.push(open_for_reading(&mut first, path).await);
+        }
+        for (id, handle) in (1..).zip(handles.iter().chain(&handles)) {
+            let data = first.read(id, handle.clone(), 0, 4).await.unwrap();
+            assert_eq!(data.data, b"data");
+        }
+
+        // The other channel holds none, so it waits
+        let d = open_for_reading(&mut second, "/d.csv").await;
+        let queued =
+            tokio::spawn(async move { second.read(10, d, 0, 4).await.is_ok() });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!queued.is_finished());
+
+        // Reaching the end of a file gives its slot back
+        let c = handles[2].clone();
+        assert!(first.read(11, c, 4, 4).await.unwrap().data.is_empty());
+        assert!(queued.await.unwrap());
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[tokio::test]
+    async fn test_files_opened_for_writing_cannot_be_read() {
+        let root = std::env::temp_dir()
+            .join(format!("sftp-manager-dropbox-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("dropbox")).unwrap();
+        std::fs::write(root.join("dropbox/report.csv"), b"secret").unwrap();
+        let context = ServerContext {
+            path_rules: PathRules::new(vec![PathRule {
+                pattern: "/dropbox/**".to_string(),
+                access: PathAccess::WriteOnly,
+            }]),
+            ..ServerContext::for_tests()
+        };
+        let mut session = session(&root, context, &TransferSlots::default());
+
+        let handle = session
+            .open(
+                1,
+                "/dropbox/report.csv".to_string(),
+                OpenFlags::WRITE,
+                FileAttributes::default(),
+            )
+            .await
+            .unwrap()
+            .handle;
+        let read = session.read(2, handle, 0, 6).await;
+        assert!(matches!(read, Err(StatusCode::PermissionDenied)));
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
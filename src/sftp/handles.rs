@@ -0,0 +1,130 @@
+use rand::RngExt;
+use std::collections::HashMap;
+use tracing::debug;
+
+/// Random bytes in a handle, shown to clients as twice as many hex digits
+const TOKEN_BYTES: usize = 16;
+
+/// Open handles of one SFTP session by the token the client refers to
+/// them with. Tokens are 128 random bits, so a client can neither guess
+/// the handles of another session nor predict its own next one, and
+/// strings not shaped like a token are refused before they are looked up.
+pub struct HandleTable<T> {
+    entries: HashMap<String, T>,
+}
+
+impl<T> Default for HandleTable<T> {
+    fn default() -> Self {
+        Self { entries: HashMap::new() }
+    }
+}
+
+impl<T> HandleTable<T> {
+    /// Stores `value` under a new token and returns the token
+    pub fn insert(&mut self, value: T) -> String {
+        let token = loop {
+            let bytes: [u8; TOKEN_BYTES] = rand::rng().random();
+            let token = hex::encode(bytes);
+            if !self.entries.contains_key(&token) {
+                break token;
+            }
+        };
+        self.entries.insert(token.clone(), value);
+        token
+    }
+
+    pub fn get(&self, token: &str) -> Option<&T> {
+        self.entries.get(checked(token)?)
+    }
+
+    pub fn get_mut(&mut self, token: &str) -> Option<&mut T> {
+        self.entries.get_mut(checked(token)?)
+    }
+
+    pub fn remove(&mut self, token: &str) -> Option<T> {
+        self.entries.remove(checked(token)?)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.entries.values()
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.entries.values_mut()
+    }
+}
+
+/// `token` when it looks like one this table hands out
+fn checked(token: &str) -> Option<&str> {
+    let well_formed = token.len() == TOKEN_BYTES * 2
+        && token.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'));
+    if !well_formed {
+        debug!("Refusing malformed handle of {} bytes", token.len());
+        return None;
+    }
+    Some(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    /// A string of up to 80 random characters, mostly from the alphabet of
+    /// tokens so it gets past the cheaper checks now and then
+    fn random_string(rng: &mut impl Rng) -> String {
+        const CHARS: &[u8] = b"0123456789abcdefABCDEF_-/ \0handle";
+        let len = rng.random_range(0..80);
+        (0..len)
+            .map(|_| CHARS[rng.random_range(0..CHARS.len())] as char)
+            .collect()
+    }
+
+    #[test]
+    fn test_handles_are_opaque_and_checked() {
+        let mut rng = rand::rng();
+        let mut table = HandleTable::default();
+        let tokens: Vec<String> = (0..64).map(|i| table.insert(i)).collect();
+        let unique: std::collections::HashSet<_> = tokens.iter().collect();
+        assert_eq!(unique.len(), tokens.len());
+        for (i, token) in tokens.iter().enumerate() {
+            assert_eq!(table.get(token), Some(&i));
+        }
+
+        // Sequential IDs, tokens of another session and random strings
+        let mut other = HandleTable::default();
+        let foreign = other.insert(0);
+        for guess in ["", "1", "handle_1", "handle_2", &foreign] {
+            assert!(table.get(guess).is_none(), "{:?}", guess);
+        }
+        for _ in 0..10_000 {
+            let guess = random_string(&mut rng);
+            assert!(table.get(&guess).is_none(), "{:?}", guess);
+        }
+
+        // Tokens changed in one place, cut short, extended or in capitals
+        for token in &tokens {
+            let mut changed = token.clone().into_bytes();
+            let at = rng.random_range(0..changed.len());
+            changed[at] = if changed[at] == b'0' { b'1' } else { b'0' };
+            let changed = String::from_utf8(changed).unwrap();
+            let misused = [
+                changed,
+                token[1..].to_string(),
+                format!("{}0", token),
+                format!(" {}", token),
+                token.to_uppercase(),
+            ];
+            for guess in misused.into_iter().filter(|g| g != token) {
+                assert!(table.get(&guess).is_none(), "{:?}", guess);
+                assert!(table.remove(&guess).is_none(), "{:?}", guess);
+            }
+        }
+
+        // Closed handles stay closed
+        assert_eq!(table.remove(&tokens[0]), Some(0));
+        assert!(table.remove(&tokens[0]).is_none());
+        assert!(table.get_mut(&tokens[0]).is_none());
+        assert_eq!(table.values().count(), tokens.len() - 1);
+    }
+}
@@ -0,0 +1,212 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tracing::warn;
+
+/// Connection events waiting for the connection hooks; more are dropped
+const CONNECTION_QUEUE: usize = 1024;
+
+/// An upload whose file handle was closed, once it is complete
+#[derive(Debug, Clone)]
+pub struct CompletedUpload {
+    /// User who uploaded the file
+    pub username: String,
+    /// IP address of the client
+    pub peer: Option<String>,
+    /// Path as seen by the client
+    pub path: String,
+    /// Location of the file on disk
+    pub local_path: PathBuf,
+    /// Root directory of the SFTP server that received the file
+    pub root_dir: PathBuf,
+    /// Number of bytes written
+    pub bytes: u64,
+    /// Time the file handle was open
+    pub duration_ms: u64,
+    /// Hex digests by algorithm name
+    pub checksums: BTreeMap<String, String>,
+}
+
+/// What happens after a hook has run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookOutcome {
+    /// Run the next hook
+    Continue,
+    /// Skip the remaining hooks, e.g. because the file was moved away
+    Stop,
+}
+
+/// Work done on every completed upload, outside of the SFTP session
+#[async_trait]
+pub trait UploadHook: Send + Sync {
+    async fn on_upload(&self, upload: &CompletedUpload) -> HookOutcome;
+}
+
+/// Runs the hooks one after another in a background task
+pub fn run_upload_hooks(
+    hooks: Arc<[Arc<dyn UploadHook>]>,
+    upload: CompletedUpload,
+) {
+    if hooks.is_empty() {
+        return;
+    }
+    tokio::spawn(async move {
+        for hook in hooks.iter() {
+            if hook.on_upload(&upload).await == HookOutcome::Stop {
+                break;
+            }
+        }
+    });
+}
+
+/// What happened to an SSH connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionEvent {
+    /// A client connected and passed the peer checks
+    Connected,
+    /// The client logged in
+    AuthAccepted,
+    /// A login attempt was refused
+    AuthRejected,
+    /// The connection ended
+    Disconnected,
+}
+
+impl ConnectionEvent {
+    /// Name used in hook payloads and command arguments
+    pub fn name(self) -> &'static str {
+        match self {
+            ConnectionEvent::Connected => "connected",
+            ConnectionEvent::AuthAccepted => "auth_accepted",
+            ConnectionEvent::AuthRejected => "auth_rejected",
+            ConnectionEvent::Disconnected => "disconnected",
+        }
+    }
+}
+
+/// An event of an SSH connection and what is known about the connection
+/// at that point
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionInfo {
+    pub event: ConnectionEvent,
+    /// ID of the session in the session registry
+    pub session_id: u64,
+    /// Address of the client
+    pub peer: Option<SocketAddr>,
+    /// Country the client connects from, when looked up
+    pub country: Option<String>,
+    /// User logging in or logged in
+    pub username: Option<String>,
+    /// How the user authenticated or tried to
+    pub auth_method: Option<&'static str>,
+    /// SSH identification string of the client, once known
+    pub client: Option<String>,
+    /// Why a login was refused, e.g. "invalid_credentials"
+    pub reason: Option<&'static str>,
+}
+
+/// Custom logic run on connection events, e.g. updating firewall rules.
+/// Hooks only observe; they cannot refuse a connection.
+#[async_trait]
+pub trait ConnectionHook: Send + Sync {
+    async fn on_connection(&self, info: &ConnectionInfo);
+}
+
+/// Hands connection events to the connection hooks, one at a time in the
+/// order they happened, in a background task. Without hooks nothing is
+/// queued.
+#[derive(Clone, Default)]
+pub struct ConnectionHooks {
+    queue: Option<mpsc::Sender<ConnectionInfo>>,
+}
+
+impl ConnectionHooks {
+    /// Starts the task running `hooks`
+    pub fn new(hooks: Vec<Arc<dyn ConnectionHook>>) -> Self {
+        if hooks.is_empty() {
+            return Self::default();
+        }
+        let (queue, mut events) = mpsc::channel(CONNECTION_QUEUE);
+        tokio::spawn(async move {
+            while let Some(info) = events.recv().await {
+                for hook in &hooks {
+                    hook.on_connection(&info).await;
+                }
+            }
+        });
+        Self { queue: Some(queue) }
+    }
+
+    /// Queues an event for the hooks; never waits for them
+    pub fn notify(&self, info: ConnectionInfo) {
+        let Some(queue) = &self.queue else {
+            return;
+        };
+        if let Err(e) = queue.try_send(info) {
+            warn!("Connection hooks are behind, dropping an event: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    struct Recorder(Arc<Mutex<Vec<(ConnectionEvent, u64)>>>);
+
+    #[async_trait]
+    impl ConnectionHook for Recorder {
+        async fn on_connection(&self, info: &ConnectionInfo) {
+            self.0.lock().unwrap().push((info.event, info.session_id));
+        }
+    }
+
+    fn info(event: ConnectionEvent, session_id: u64) -> ConnectionInfo {
+        ConnectionInfo {
+            event,
+            session_id,
+            peer: None,
+            country: None,
+            username: None,
+            auth_method: None,
+            client: None,
+            reason: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_connection_hooks_see_events_in_order() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let hooks = ConnectionHooks::new(vec![
+            Arc::new(Recorder(seen.clone())),
+            Arc::new(Recorder(seen.clone())),
+        ]);
+        hooks.notify(info(ConnectionEvent::Connected, 1));
+        hooks.notify(info(ConnectionEvent::Disconnected, 1));
+        for _ in 0..100 {
+            if seen.lock().unwrap().len() == 4 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+        use ConnectionEvent::*;
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [
+                (Connected, 1),
+                (Connected, 1),
+                (Disconnected, 1),
+                (Disconnected, 1)
+            ]
+        );
+
+        // Without hooks events go nowhere
+        ConnectionHooks::default().notify(info(Connected, 2));
+    }
+}
@@ -0,0 +1,98 @@
+use chrono::{Days, NaiveDate, Utc};
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+/// Directories whose uploads are filed by date: with the default layout a
+/// file uploaded as "/incoming/a.csv" on 15 June 2024 is stored as
+/// "/incoming/2024/06/15/a.csv". Clients keep using the undated path.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LandingRules {
+    /// Client paths of the landing directories, e.g. "/incoming"
+    pub dirs: Vec<String>,
+    /// strftime format of the dated subdirectories, evaluated in UTC
+    pub layout: String,
+}
+
+impl LandingRules {
+    /// Where a file named `path` by the client lands when uploaded on
+    /// `date`; None unless `path` is directly inside a landing directory
+    pub fn dated(&self, path: &str, date: NaiveDate) -> Option<String> {
+        let path = format!("/{}", path.trim_start_matches('/'));
+        let (dir, name) = path.rsplit_once('/')?;
+        if name.is_empty() || name == "." || name == ".." {
+            return None;
+        }
+        if !self.dirs.iter().any(|d| d.trim_end_matches('/') == dir) {
+            return None;
+        }
+        // Invalid layouts fail to format instead of panicking
+        let mut dated = String::new();
+        write!(dated, "{}", date.format(&self.layout)).ok()?;
+        Some(format!("{}/{}/{}", dir, dated.trim_matches('/'), name))
+    }
+}
+
+/// Landing rules shared by the sessions, replaced on reload
+#[derive(Clone, Default)]
+pub struct DatedLanding {
+    rules: Arc<Mutex<LandingRules>>,
+}
+
+impl DatedLanding {
+    pub fn new(rules: LandingRules) -> Self {
+        Self { rules: Arc::new(Mutex::new(rules)) }
+    }
+
+    /// Changes the rules; files already filed stay where they are
+    pub fn reconfigure(&self, rules: LandingRules) {
+        *self.rules.lock().unwrap() = rules;
+    }
+
+    /// Where a file named `path` lands: today's dated path first, then
+    /// yesterday's, where uploads from just before midnight are. Empty
+    /// when `path` is not directly inside a landing directory.
+    pub fn landed_paths(&self, path: &str) -> Vec<String> {
+        let today = Utc::now().date_naive();
+        let rules = self.rules.lock().unwrap();
+        [Some(today), today.checked_sub_days(Days::new(1))]
+            .into_iter()
+            .flatten()
+            .filter_map(|date| rules.dated(path, date))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uploads_into_landing_dirs_are_dated() {
+        let rules = LandingRules {
+            dirs: vec!["/incoming/".to_string(), "/".to_string()],
+            layout: "%Y/%m/%d".to_string(),
+        };
+        let date = NaiveDate::from_ymd_opt(2024, 6, 15).unwrap();
+        assert_eq!(
+            rules.dated("/incoming/a.csv", date).as_deref(),
+            Some("/incoming/2024/06/15/a.csv")
+        );
+        assert_eq!(
+            rules.dated("incoming/a.csv", date).as_deref(),
+            Some("/incoming/2024/06/15/a.csv")
+        );
+        assert_eq!(
+            rules.dated("/a.csv", date).as_deref(),
+            Some("/2024/06/15/a.csv")
+        );
+        assert_eq!(rules.dated("/incoming/x/a.csv", date), None);
+        assert_eq!(rules.dated("/outgoing/a.csv", date), None);
+
+        let rules = LandingRules { layout: "%Q".to_string(), ..rules };
+        assert_eq!(rules.dated("/incoming/a.csv", date), None);
+        assert_eq!(
+            DatedLanding::default().landed_paths("/incoming/a"),
+            Vec::<String>::new()
+        );
+    }
+}
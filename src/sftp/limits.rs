@@ -0,0 +1,93 @@
+use std::time::Duration;
+
+/// Room for the header fields around the data of a read or write packet
+const PACKET_OVERHEAD: u32 = 1024;
+
+/// Buffer and packet sizes of transfers, to trade memory for throughput:
+/// large on fast links, small on constrained devices
+#[derive(Debug, Clone, Copy)]
+pub struct TransferLimits {
+    /// Bytes a client may send on an SSH channel before waiting for the
+    /// server to catch up
+    pub window_bytes: u32,
+    /// Largest SSH packet payload
+    pub max_packet_bytes: u32,
+    /// Largest SFTP read served; longer reads return less
+    pub max_read_bytes: u32,
+    /// Largest SFTP write accepted; longer writes are refused
+    pub max_write_bytes: u32,
+    /// Buffer of FTPS data connections
+    pub transfer_buffer_bytes: usize,
+    /// Chunks read from disk ahead of sequential SFTP reads; 0 disables
+    /// read-ahead
+    pub read_ahead_chunks: usize,
+}
+
+impl Default for TransferLimits {
+    fn default() -> Self {
+        Self {
+            window_bytes: 2 * 1024 * 1024,
+            max_packet_bytes: 32 * 1024,
+            max_read_bytes: 255 * 1024,
+            max_write_bytes: 255 * 1024,
+            transfer_buffer_bytes: 64 * 1024,
+            read_ahead_chunks: 4,
+        }
+    }
+}
+
+impl TransferLimits {
+    /// Body of the reply to the limits@openssh.com extension, which lets
+    /// clients size their requests: the longest SFTP packet, read and
+    /// write, and the number of open handles, 0 for no limit
+    pub fn openssh_limits(&self) -> Vec<u8> {
+        let max_packet =
+            self.max_read_bytes.max(self.max_write_bytes) + PACKET_OVERHEAD;
+        [
+            max_packet as u64,
+            self.max_read_bytes as u64,
+            self.max_write_bytes as u64,
+            0,
+        ]
+        .iter()
+        .flat_map(|value| value.to_be_bytes())
+        .collect()
+    }
+}
+
+/// Keepalive requests sent to quiet SSH clients, so connections whose
+/// client vanished, e.g. behind a NAT that forgot them, are dropped along
+/// with their session and open handles
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeepAlive {
+    /// Silence after which a keepalive is sent; None sends none
+    pub interval: Option<Duration>,
+    /// Keepalives left unanswered before the connection is dropped
+    pub max_missed: usize,
+}
+
+impl Default for KeepAlive {
+    fn default() -> Self {
+        Self { interval: Some(Duration::from_secs(30)), max_missed: 3 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_openssh_limits_reply() {
+        let limits = TransferLimits {
+            max_read_bytes: 65536,
+            max_write_bytes: 32768,
+            ..Default::default()
+        };
+        let reply = limits.openssh_limits();
+        assert_eq!(reply.len(), 32);
+        assert_eq!(reply[..8], (65536u64 + 1024).to_be_bytes());
+        assert_eq!(reply[8..16], 65536u64.to_be_bytes());
+        assert_eq!(reply[16..24], 32768u64.to_be_bytes());
+        assert_eq!(reply[24..], 0u64.to_be_bytes());
+    }
+}
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+struct Cached<T> {
+    at: Instant,
+    value: T,
+}
+
+#[derive(Default)]
+struct Entries {
+    listings: HashMap<PathBuf, Cached<Arc<[String]>>>,
+    metadata: HashMap<PathBuf, Cached<Metadata>>,
+}
+
+impl Entries {
+    fn len(&self) -> usize {
+        self.listings.len() + self.metadata.len()
+    }
+}
+
+/// Short-lived cache of directory listings and file attributes, keyed by
+/// canonical path, for clients that list the same large directory every
+/// few seconds. Writes through the server forget the paths they touch;
+/// other changes show once the watcher reports them or the entry expires.
+#[derive(Clone)]
+pub struct ListingCache {
+    ttl: Duration,
+    max_entries: usize,
+    entries: Arc<Mutex<Entries>>,
+}
+
+impl ListingCache {
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        Self { ttl, max_entries, entries: Arc::default() }
+    }
+
+    /// Names in a directory, if listed within the TTL
+    pub fn listing(&self, dir: &Path) -> Option<Arc<[String]>> {
+        let entries = self.entries.lock().unwrap();
+        let cached = entries.listings.get(dir)?;
+        (cached.at.elapsed() < self.ttl).then(|| cached.value.clone())
+    }
+
+    pub fn insert_listing(&self, dir: PathBuf, names: Arc<[String]>) {
+        let mut entries = self.entries.lock().unwrap();
+        self.make_room(&mut entries);
+        entries
+            .listings
+            .insert(dir, Cached { at: Instant::now(), value: names });
+    }
+
+    /// Attributes of a path, if read within the TTL
+    pub fn metadata(&self, path: &Path) -> Option<Metadata> {
+        let entries = self.entries.lock().unwrap();
+        let cached = entries.metadata.get(path)?;
+        (cached.at.elapsed() < self.ttl).then(|| cached.value.clone())
+    }
+
+    pub fn insert_metadata(&self, path: PathBuf, metadata: Metadata) {
+        let mut entries = self.entries.lock().unwrap();
+        self.make_room(&mut entries);
+        entries
+            .metadata
+            .insert(path, Cached { at: Instant::now(), value: metadata });
+    }
+
+    /// Forget a path that changed, along with the listing and attributes
+    /// of the directory holding it
+    pub fn invalidate(&self, path: &Path) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.listings.remove(path);
+        entries.metadata.remove(path);
+        if let Some(parent) = path.parent() {
+            entries.listings.remove(parent);
+            entries.metadata.remove(parent);
+        }
+    }
+
+    /// Forget a path and everything below it, for directories that were
+    /// removed or renamed
+    pub fn invalidate_tree(&self, path: &Path) {
+        self.invalidate(path);
+        let mut entries = self.entries.lock().unwrap();
+        entries.listings.retain(|key, _| !key.starts_with(path));
+        entries.metadata.retain(|key, _| !key.starts_with(path));
+    }
+
+    /// Drop expired entries when full, and everything if that is not enough
+    fn make_room(&self, entries: &mut Entries) {
+        if entries.len() < self.max_entries {
+            return;
+        }
+        entries.listings.retain(|_, cached| cached.at.elapsed() < self.ttl);
+        entries.metadata.retain(|_, cached| cached.at.elapsed() < self.ttl);
+        if entries.len() >= self.max_entries {
+            *entries = Entries::default();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changes_forget_the_path_and_its_directory() {
+        let cache = ListingCache::new(Duration::from_secs(60), 100);
+        let metadata = std::fs::metadata(std::env::temp_dir()).unwrap();
+        let names: Arc<[String]> = vec!["a".to_string()].into();
+        cache.insert_listing(PathBuf::from("/r"), names.clone());
+        cache.insert_listing(PathBuf::from("/r/d"), names.clone());
+        cache.insert_metadata(PathBuf::from("/r/d"), metadata.clone());
+        cache.insert_metadata(PathBuf::from("/r/d/f"), metadata.clone());
+        cache.insert_metadata(PathBuf::from("/r/e"), metadata);
+
+        cache.invalidate(Path::new("/r/d/f"));
+        assert!(cache.metadata(Path::new("/r/d/f")).is_none());
+        assert!(cache.listing(Path::new("/r/d")).is_none());
+        assert!(cache.metadata(Path::new("/r/d")).is_none());
+        assert!(cache.listing(Path::new("/r")).is_some());
+
+        cache.insert_metadata(
+            PathBuf::from("/r/d/f"),
+            std::fs::metadata(std::env::temp_dir()).unwrap(),
+        );
+        cache.invalidate_tree(Path::new("/r/d"));
+        assert!(cache.metadata(Path::new("/r/d/f")).is_none());
+        assert!(cache.listing(Path::new("/r")).is_none());
+        assert!(cache.metadata(Path::new("/r/e")).is_some());
+
+        let expired = ListingCache::new(Duration::ZERO, 100);
+        expired.insert_listing(PathBuf::from("/r"), names);
+        assert!(expired.listing(Path::new("/r")).is_none());
+    }
+}
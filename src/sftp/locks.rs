@@ -0,0 +1,267 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Others may not read the range (SSH_FXF_BLOCK_READ)
+pub const BLOCK_READ: u32 = 0x0000_0040;
+/// Others may not write the range (SSH_FXF_BLOCK_WRITE)
+pub const BLOCK_WRITE: u32 = 0x0000_0080;
+/// Others may not remove or rename the file (SSH_FXF_BLOCK_DELETE)
+pub const BLOCK_DELETE: u32 = 0x0000_0100;
+/// Only conflicts with other locks, reads and writes are not checked
+/// (SSH_FXF_BLOCK_ADVISORY)
+pub const BLOCK_ADVISORY: u32 = 0x0000_2000;
+
+/// Handle holding a lock: the SFTP session, numbered by `ByteRangeLocks`,
+/// and the handle within it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockOwner {
+    pub session: u64,
+    pub handle: String,
+}
+
+/// Why a request for a lock was turned down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockError {
+    /// Another handle holds a lock over part of the range
+    Conflict,
+    /// Unlocking a range the handle holds no lock for
+    NoMatchingLock,
+}
+
+#[derive(Debug, Clone)]
+struct RangeLock {
+    owner: LockOwner,
+    start: u64,
+    /// Exclusive; u64::MAX for locks up to the end of the file
+    end: u64,
+    mask: u32,
+}
+
+impl RangeLock {
+    fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.start < end && start < self.end
+    }
+
+    /// Locks that block reads are exclusive; those blocking only writes
+    /// or deletes are shared, like the read locks of fcntl
+    fn conflicts_with(&self, mask: u32) -> bool {
+        (self.mask | mask) & BLOCK_READ != 0
+    }
+
+    /// Whether the lock keeps other handles from `access`, one of the
+    /// BLOCK_ bits
+    fn blocks(&self, access: u32) -> bool {
+        self.mask & BLOCK_ADVISORY == 0 && self.mask & access != 0
+    }
+}
+
+/// Byte-range locks of the files of one server instance, taken through the
+/// SFTP v6 block and unblock requests. Locks of other handles are enforced
+/// on reads, writes, removes and renames unless they are advisory.
+#[derive(Clone, Default)]
+pub struct ByteRangeLocks {
+    locks: Arc<Mutex<HashMap<PathBuf, Vec<RangeLock>>>>,
+    next_session: Arc<AtomicU64>,
+}
+
+impl ByteRangeLocks {
+    /// Number for a new SFTP session, unique across connections
+    pub fn session(&self) -> u64 {
+        self.next_session.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Locks `length` bytes from `offset` of `path`, or up to the end of the
+    /// file when `length` is 0
+    pub fn block(
+        &self,
+        path: &Path,
+        owner: LockOwner,
+        offset: u64,
+        length: u64,
+        mask: u32,
+    ) -> Result<(), LockError> {
+        let (start, end) = range(offset, length);
+        let mut locks = self.locks.lock().unwrap();
+        let held = locks.entry(path.to_path_buf()).or_default();
+        if held.iter().any(|lock| {
+            lock.owner != owner
+                && lock.overlaps(start, end)
+                && lock.conflicts_with(mask)
+        }) {
+            return Err(LockError::Conflict);
+        }
+        held.push(RangeLock { owner, start, end, mask });
+        Ok(())
+    }
+
+    /// Releases the lock `owner` took over exactly this range
+    pub fn unblock(
+        &self,
+        path: &Path,
+        owner: &LockOwner,
+        offset: u64,
+        length: u64,
+    ) -> Result<(), LockError> {
+        let (start, end) = range(offset, length);
+        let mut locks = self.locks.lock().unwrap();
+        let held = locks.get_mut(path).ok_or(LockError::NoMatchingLock)?;
+        let index = held
+            .iter()
+            .position(|l| &l.owner == owner && l.start == start && l.end == end)
+            .ok_or(LockError::NoMatchingLock)?;
+        held.remove(index);
+        if held.is_empty() {
+            locks.remove(path);
+        }
+        Ok(())
+    }
+
+    /// Whether a handle other than `owner` keeps it from `access`, one of
+    /// the BLOCK_ bits, to `length` bytes from `offset` of `path`
+    pub fn is_blocked(
+        &self,
+        path: &Path,
+        owner: &LockOwner,
+        access: u32,
+        offset: u64,
+        length: u64,
+    ) -> bool {
+        let (start, end) = range(offset, length);
+        let locks = self.locks.lock().unwrap();
+        locks.get(path).is_some_and(|held| {
+            held.iter().any(|lock| {
+                &lock.owner != owner
+                    && lock.overlaps(start, end)
+                    && lock.blocks(access)
+            })
+        })
+    }
+
+    /// Releases the locks of a handle, once it is closed
+    pub fn release_handle(&self, owner: &LockOwner) {
+        self.release(|lock| &lock.owner == owner);
+    }
+
+    /// Releases the locks of all handles of a session, once it ends
+    pub fn release_session(&self, session: u64) {
+        self.release(|lock| lock.owner.session == session);
+    }
+
+    fn release(&self, released: impl Fn(&RangeLock) -> bool) {
+        let mut locks = self.locks.lock().unwrap();
+        locks.retain(|_, held| {
+            held.retain(|lock| !released(lock));
+            !held.is_empty()
+        });
+    }
+}
+
+/// Handle and range of a block or unblock request, laid out like the
+/// SSH_FXP_BLOCK and SSH_FXP_UNBLOCK packets of SFTP v6
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeRequest {
+    pub handle: String,
+    pub offset: u64,
+    pub length: u64,
+    /// BLOCK_ bits; 0 for unblock requests, which carry none
+    pub mask: u32,
+}
+
+impl RangeRequest {
+    /// Reads a request from the data of an extended request; block
+    /// requests end with the lock mask
+    pub fn parse(data: &[u8], with_mask: bool) -> Option<Self> {
+        let length = u32::from_be_bytes(data.get(..4)?.try_into().ok()?);
+        let (handle, rest) = data[4..].split_at_checked(length as usize)?;
+        let handle = String::from_utf8(handle.to_vec()).ok()?;
+        let number = |at: usize| rest.get(at..at + 8)?.try_into().ok();
+        let offset = u64::from_be_bytes(number(0)?);
+        let length = u64::from_be_bytes(number(8)?);
+        let mask = match with_mask {
+            true => u32::from_be_bytes(rest.get(16..20)?.try_into().ok()?),
+            false => 0,
+        };
+        Some(Self { handle, offset, length, mask })
+    }
+}
+
+/// Start and exclusive end of a range, 0 bytes meaning up to the end
+fn range(offset: u64, length: u64) -> (u64, u64) {
+    match length {
+        0 => (offset, u64::MAX),
+        length => (offset, offset.saturating_add(length)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn owner(session: u64, handle: &str) -> LockOwner {
+        LockOwner { session, handle: handle.to_string() }
+    }
+
+    #[test]
+    fn test_locks_conflict_over_overlapping_ranges() {
+        let locks = ByteRangeLocks::default();
+        let path = Path::new("/data/orders.edi");
+        let (a, b) = (owner(1, "1"), owner(2, "1"));
+
+        let exclusive = BLOCK_READ | BLOCK_WRITE;
+        assert_eq!(locks.block(path, a.clone(), 0, 100, exclusive), Ok(()));
+        assert_eq!(
+            locks.block(path, b.clone(), 50, 10, BLOCK_WRITE),
+            Err(LockError::Conflict)
+        );
+        assert_eq!(locks.block(path, b.clone(), 100, 0, exclusive), Ok(()));
+        assert!(locks.is_blocked(path, &b, BLOCK_WRITE, 99, 1));
+        assert!(!locks.is_blocked(path, &a, BLOCK_WRITE, 99, 1));
+        assert!(locks.is_blocked(path, &a, BLOCK_READ, 1000, 1));
+
+        // Shared locks coexist but still keep others from writing
+        let other = Path::new("/data/invoices.edi");
+        assert_eq!(locks.block(other, a.clone(), 0, 0, BLOCK_WRITE), Ok(()));
+        assert_eq!(locks.block(other, b.clone(), 0, 0, BLOCK_WRITE), Ok(()));
+        assert!(locks.is_blocked(other, &a, BLOCK_WRITE, 0, 1));
+        assert!(!locks.is_blocked(other, &a, BLOCK_READ, 0, 1));
+
+        assert_eq!(
+            locks.unblock(path, &a, 0, 50),
+            Err(LockError::NoMatchingLock)
+        );
+        assert_eq!(locks.unblock(path, &a, 0, 100), Ok(()));
+        assert_eq!(locks.block(path, b.clone(), 50, 10, BLOCK_WRITE), Ok(()));
+
+        locks.release_session(2);
+        assert!(!locks.is_blocked(path, &a, BLOCK_WRITE, 0, 0));
+        assert!(!locks.is_blocked(other, &a, BLOCK_WRITE, 0, 0));
+
+        // Advisory locks only conflict with other locks
+        let advisory = BLOCK_READ | BLOCK_WRITE | BLOCK_ADVISORY;
+        assert_eq!(locks.block(path, a.clone(), 0, 0, advisory), Ok(()));
+        assert!(!locks.is_blocked(path, &b, BLOCK_WRITE, 0, 1));
+        assert_eq!(
+            locks.block(path, b.clone(), 0, 1, BLOCK_WRITE),
+            Err(LockError::Conflict)
+        );
+        locks.release_handle(&a);
+        assert_eq!(locks.block(path, b, 0, 1, BLOCK_WRITE), Ok(()));
+
+        let mut data = vec![0, 0, 0, 1, b'7'];
+        data.extend_from_slice(&10u64.to_be_bytes());
+        data.extend_from_slice(&20u64.to_be_bytes());
+        assert_eq!(RangeRequest::parse(&data, true), None);
+        data.extend_from_slice(&BLOCK_WRITE.to_be_bytes());
+        assert_eq!(
+            RangeRequest::parse(&data, true),
+            Some(RangeRequest {
+                handle: "7".to_string(),
+                offset: 10,
+                length: 20,
+                mask: BLOCK_WRITE,
+            })
+        );
+    }
+}
@@ -0,0 +1,71 @@
+/// Rough cost of the bookkeeping of an open handle
+pub const HANDLE_BYTES: usize = 512;
+
+/// Memory a session holds in open handles, directory listings and
+/// read-ahead buffers, checked against a limit so that one client listing
+/// a directory of millions of entries cannot exhaust the server
+#[derive(Debug)]
+pub struct MemoryBudget {
+    /// 0 is unlimited
+    limit: usize,
+    used: usize,
+}
+
+impl MemoryBudget {
+    pub fn new(limit: usize) -> Self {
+        Self { limit, used: 0 }
+    }
+
+    /// Takes `bytes` from the budget if they fit in what is left
+    pub fn try_reserve(&mut self, bytes: usize) -> bool {
+        if bytes > self.remaining() {
+            return false;
+        }
+        self.used += bytes;
+        true
+    }
+
+    /// Returns bytes taken with `try_reserve`
+    pub fn release(&mut self, bytes: usize) {
+        self.used = self.used.saturating_sub(bytes);
+    }
+
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    /// Bytes that may still be reserved
+    pub fn remaining(&self) -> usize {
+        match self.limit {
+            0 => usize::MAX,
+            limit => limit.saturating_sub(self.used),
+        }
+    }
+}
+
+/// Memory taken by a name held in a directory listing
+pub fn name_bytes(name: &str) -> usize {
+    name.len() + size_of::<String>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reservations_stay_within_the_limit() {
+        let mut budget = MemoryBudget::new(1000);
+        assert!(budget.try_reserve(600));
+        assert!(!budget.try_reserve(600));
+        assert_eq!(budget.remaining(), 400);
+        assert!(budget.try_reserve(400));
+        budget.release(600);
+        assert_eq!(budget.used(), 400);
+        budget.release(10_000);
+        assert_eq!(budget.used(), 0);
+
+        let mut unlimited = MemoryBudget::new(0);
+        assert!(unlimited.try_reserve(usize::MAX / 2));
+        assert_eq!(unlimited.remaining(), usize::MAX);
+    }
+}
@@ -1,9 +1,63 @@
+pub mod access_hours;
+pub mod accounts;
+pub mod acl;
+pub mod auth_tracker;
+pub mod checksum;
+pub mod client_versions;
+pub mod completion;
+pub mod credentials;
+pub mod disk_space;
+pub mod file_io;
+pub mod filenames;
+pub mod glob;
 pub mod handler;
+pub mod handles;
+pub mod hooks;
+pub mod landing;
+pub mod limits;
+pub mod listing_cache;
+pub mod locks;
+pub mod memory;
+pub mod negotiation;
+pub mod owners;
+pub mod peer_filter;
+pub mod quota;
+pub mod read_ahead;
+pub mod recording;
+pub mod registry;
+pub mod resume;
+pub mod scratch;
 pub mod server;
 pub mod session;
+pub mod sparse;
+pub mod tarpit;
+pub mod transfer_slots;
+pub mod trash;
 
+pub use access_hours::AccessHours;
+pub use accounts::AccountLogins;
+pub use acl::{PathRule, PathRules};
+pub use auth_tracker::{AuthFailureLimits, AuthFailureTracker};
+pub use client_versions::{ClientVersionRules, ClientVersions};
+pub use completion::{CompletionRules, UploadCompletion};
+pub use credentials::{
+    CredentialsSource, SecretString, SftpCredentials, SharedCredentials,
+};
+pub use disk_space::{DiskGuard, DiskLimits, DiskStatus};
+pub use file_io::FileIo;
 #[allow(unused_imports)]
 pub use handler::{OpenHandle, SftpSession};
-pub use server::run_sftp_server;
+pub use landing::{DatedLanding, LandingRules};
+pub use limits::{KeepAlive, TransferLimits};
+pub use listing_cache::ListingCache;
+pub use locks::ByteRangeLocks;
+pub use owners::OwnerNames;
+pub use peer_filter::PeerFilter;
+pub use quota::Quota;
+pub use recording::RecordingPolicy;
+pub use registry::SessionRegistry;
+pub use resume::UploadProgress;
+pub use server::{ServerContext, run_sftp_server};
 #[allow(unused_imports)]
 pub use session::{SshServerImpl, SshSession};
+pub use tarpit::{Tarpit, TarpitLimits};
@@ -0,0 +1,311 @@
+use serde::Serialize;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Bytes looked at for the identification string and the first KEXINIT
+/// of each side before giving up
+const CAPTURE_LIMIT: usize = 64 * 1024;
+
+/// SSH_MSG_KEXINIT
+const MSG_KEXINIT: u8 = 20;
+
+/// Algorithms agreed on in the initial key exchange
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Negotiated {
+    pub kex: String,
+    pub host_key: String,
+    /// Client to server cipher; clients ask for the same both ways
+    pub cipher: String,
+    /// Client to server MAC; None for AEAD ciphers, which need none
+    pub mac: Option<String>,
+}
+
+/// Algorithm lists a side offered in its KEXINIT
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct KexInit {
+    kex: Vec<String>,
+    host_key: Vec<String>,
+    cipher: Vec<String>,
+    mac: Vec<String>,
+}
+
+impl KexInit {
+    /// Picks, like RFC 4253 says, the first algorithm of the client's list
+    /// that the server supports
+    fn negotiate(client: &KexInit, server: &KexInit) -> Option<Negotiated> {
+        let pick = |client: &[String], server: &[String]| {
+            client.iter().find(|name| server.contains(name)).cloned()
+        };
+        let cipher = pick(&client.cipher, &server.cipher)?;
+        let aead =
+            cipher.contains("-gcm@") || cipher.contains("chacha20-poly1305");
+        Some(Negotiated {
+            kex: pick(&client.kex, &server.kex)?,
+            host_key: pick(&client.host_key, &server.host_key)?,
+            mac: if aead { None } else { pick(&client.mac, &server.mac) },
+            cipher,
+        })
+    }
+}
+
+/// Negotiated algorithms of a connection, once both KEXINITs were seen
+#[derive(Clone, Default)]
+pub struct Negotiation {
+    negotiated: Arc<OnceLock<Negotiated>>,
+}
+
+impl Negotiation {
+    pub fn get(&self) -> Option<Negotiated> {
+        self.negotiated.get().cloned()
+    }
+}
+
+/// Reads the cleartext start of one direction of a connection until its
+/// KEXINIT is complete
+#[derive(Default)]
+struct Capture {
+    buffer: Vec<u8>,
+    kexinit: Option<KexInit>,
+    done: bool,
+}
+
+impl Capture {
+    fn feed(&mut self, data: &[u8]) {
+        if self.done || data.is_empty() {
+            return;
+        }
+        self.buffer.extend_from_slice(data);
+        match parse_kexinit(&self.buffer) {
+            Ok(Some(kexinit)) => self.kexinit = Some(kexinit),
+            Ok(None) if self.buffer.len() < CAPTURE_LIMIT => return,
+            _ => {}
+        }
+        self.done = true;
+        self.buffer = Vec::new();
+    }
+}
+
+/// Passes a connection through to russh, which keeps the negotiated
+/// algorithms to itself, and works them out from the KEXINIT messages
+/// both sides send in the clear before any encryption starts
+pub struct KexTap<S> {
+    inner: S,
+    /// Sent by the client
+    received: Capture,
+    /// Sent by the server
+    sent: Capture,
+    negotiation: Negotiation,
+}
+
+impl<S> KexTap<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            received: Capture::default(),
+            sent: Capture::default(),
+            negotiation: Negotiation::default(),
+        }
+    }
+
+    pub fn negotiation(&self) -> Negotiation {
+        self.negotiation.clone()
+    }
+
+    fn settle(&mut self) {
+        if let (Some(client), Some(server)) =
+            (&self.received.kexinit, &self.sent.kexinit)
+        {
+            if let Some(negotiated) = KexInit::negotiate(client, server) {
+                let _ = self.negotiation.negotiated.set(negotiated);
+            }
+            self.received.kexinit = None;
+            self.sent.kexinit = None;
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for KexTap<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if !this.received.done {
+            this.received.feed(&buf.filled()[before..]);
+            this.settle();
+        }
+        result
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for KexTap<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut this.inner).poll_write(cx, data);
+        if let Poll::Ready(Ok(written)) = result
+            && !this.sent.done
+        {
+            this.sent.feed(&data[..written]);
+            this.settle();
+        }
+        result
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+/// The KEXINIT following the identification string at the start of
+/// `data`: Ok(None) while incomplete, Err when it is not there
+fn parse_kexinit(data: &[u8]) -> Result<Option<KexInit>, ()> {
+    // Servers may send other lines before their identification string
+    let mut start = 0;
+    loop {
+        let Some(end) = data[start..].iter().position(|&b| b == b'\n') else {
+            return Ok(None);
+        };
+        let line = &data[start..start + end];
+        start += end + 1;
+        if line.starts_with(b"SSH-") {
+            break;
+        }
+    }
+
+    let packet = &data[start..];
+    let Some(length) = packet.get(..4) else {
+        return Ok(None);
+    };
+    let length = u32::from_be_bytes(length.try_into().unwrap()) as usize;
+    if length > CAPTURE_LIMIT {
+        return Err(());
+    }
+    let Some(packet) = packet.get(4..4 + length) else {
+        return Ok(None);
+    };
+    let padding = *packet.first().ok_or(())? as usize;
+    let payload = packet.get(1..length.checked_sub(padding).ok_or(())?);
+    let payload = payload.ok_or(())?;
+    if payload.first() != Some(&MSG_KEXINIT) {
+        return Err(());
+    }
+
+    // Message number and cookie, then the name-lists in order
+    let mut rest = payload.get(17..).ok_or(())?;
+    let mut next_list = || -> Result<Vec<String>, ()> {
+        let length = rest.get(..4).ok_or(())?;
+        let length = u32::from_be_bytes(length.try_into().unwrap()) as usize;
+        let names = rest.get(4..4 + length).ok_or(())?;
+        rest = &rest[4 + length..];
+        Ok(String::from_utf8_lossy(names)
+            .split(',')
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect())
+    };
+    let kex = next_list()?;
+    let host_key = next_list()?;
+    let cipher = next_list()?;
+    let _cipher_server_to_client = next_list()?;
+    let mac = next_list()?;
+    Ok(Some(KexInit { kex, host_key, cipher, mac }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hello(id: &str, lists: [&str; 10]) -> Vec<u8> {
+        let mut payload = vec![MSG_KEXINIT];
+        payload.extend_from_slice(&[0; 16]);
+        for list in lists {
+            payload.extend_from_slice(&(list.len() as u32).to_be_bytes());
+            payload.extend_from_slice(list.as_bytes());
+        }
+        payload.extend_from_slice(&[0, 0, 0, 0, 0]);
+        let padding = 4;
+        let mut data = format!("{}\r\n", id).into_bytes();
+        let length = (1 + payload.len() + padding) as u32;
+        data.extend_from_slice(&length.to_be_bytes());
+        data.push(padding as u8);
+        data.extend_from_slice(&payload);
+        data.extend_from_slice(&[0; 4]);
+        data
+    }
+
+    #[test]
+    fn test_algorithms_are_negotiated_from_the_kexinits() {
+        let client = hello(
+            "SSH-2.0-OpenSSH_9.6",
+            [
+                "sntrup761x25519-sha512,curve25519-sha256,ext-info-c",
+                "ssh-ed25519,rsa-sha2-512",
+                "aes128-ctr,aes256-gcm@openssh.com",
+                "aes128-ctr,aes256-gcm@openssh.com",
+                "hmac-sha2-256-etm@openssh.com,hmac-sha2-256",
+                "hmac-sha2-256-etm@openssh.com,hmac-sha2-256",
+                "none",
+                "none",
+                "",
+                "",
+            ],
+        );
+        let server = hello(
+            "SSH-2.0-russh_0.54",
+            [
+                "curve25519-sha256,kex-strict-s-v00@openssh.com",
+                "ssh-ed25519",
+                "aes256-gcm@openssh.com,aes128-ctr",
+                "aes256-gcm@openssh.com,aes128-ctr",
+                "hmac-sha2-256",
+                "hmac-sha2-256",
+                "none",
+                "none",
+                "",
+                "",
+            ],
+        );
+
+        // Delivered in pieces, as reads and writes come
+        let mut tap = KexTap::new(());
+        for chunk in client.chunks(7) {
+            tap.received.feed(chunk);
+        }
+        tap.settle();
+        assert!(tap.negotiation().get().is_none());
+        tap.sent.feed(&server);
+        tap.settle();
+
+        let negotiated = tap.negotiation().get().unwrap();
+        assert_eq!(negotiated.kex, "curve25519-sha256");
+        assert_eq!(negotiated.host_key, "ssh-ed25519");
+        assert_eq!(negotiated.cipher, "aes128-ctr");
+        assert_eq!(negotiated.mac.as_deref(), Some("hmac-sha2-256"));
+
+        // Not a KEXINIT
+        assert!(
+            parse_kexinit(b"SSH-2.0-x\r\n\0\0\0\x06\x04\x15\0\0\0\0").is_err()
+        );
+        assert_eq!(parse_kexinit(b"SSH-2.0-"), Ok(None));
+    }
+}
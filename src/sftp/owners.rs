@@ -0,0 +1,70 @@
+use nix::unistd::{Gid, Group, Uid, User};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long a resolved name is used before it is looked up again
+const CACHE_TTL: Duration = Duration::from_secs(300);
+
+type NameCache = Arc<Mutex<HashMap<u32, (Instant, Option<String>)>>>;
+
+/// Resolves user and group IDs to names for directory listings, caching
+/// the answers. IDs without a name resolve to None and are shown as is.
+#[derive(Clone, Default)]
+pub struct OwnerNames {
+    users: NameCache,
+    groups: NameCache,
+}
+
+impl OwnerNames {
+    /// Name of the user with ID `uid`
+    pub async fn user(&self, uid: u32) -> Option<String> {
+        lookup(&self.users, uid, |id| {
+            User::from_uid(Uid::from_raw(id)).ok().flatten().map(|u| u.name)
+        })
+        .await
+    }
+
+    /// Name of the group with ID `gid`
+    pub async fn group(&self, gid: u32) -> Option<String> {
+        lookup(&self.groups, gid, |id| {
+            Group::from_gid(Gid::from_raw(id)).ok().flatten().map(|g| g.name)
+        })
+        .await
+    }
+}
+
+async fn lookup(
+    cache: &NameCache,
+    id: u32,
+    resolve: fn(u32) -> Option<String>,
+) -> Option<String> {
+    if let Some((resolved_at, name)) = cache.lock().unwrap().get(&id)
+        && resolved_at.elapsed() < CACHE_TTL
+    {
+        return name.clone();
+    }
+    // The lookup may ask a directory service such as LDAP
+    let name =
+        tokio::task::spawn_blocking(move || resolve(id)).await.ok().flatten();
+    cache.lock().unwrap().insert(id, (Instant::now(), name.clone()));
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_ids_resolve_to_names() {
+        let names = OwnerNames::default();
+        let uid = nix::unistd::getuid().as_raw();
+        let expected =
+            User::from_uid(Uid::from_raw(uid)).unwrap().map(|u| u.name);
+        assert_eq!(names.user(uid).await, expected);
+        // Answered from the cache the second time
+        assert_eq!(names.user(uid).await, expected);
+        assert!(names.users.lock().unwrap().contains_key(&uid));
+        assert_eq!(names.group(u32::MAX - 1).await, None);
+    }
+}
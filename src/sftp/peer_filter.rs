@@ -0,0 +1,17 @@
+use std::net::IpAddr;
+
+/// Decides from their address which clients may connect, e.g. by country
+pub trait PeerFilter: Send + Sync {
+    /// Country code of the address, when known
+    fn country(&self, ip: IpAddr) -> Option<String>;
+
+    /// Whether a client from `country` may connect
+    fn allows(&self, country: Option<&str>) -> bool;
+}
+
+/// Outcome of screening a new connection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerCheck {
+    pub allowed: bool,
+    pub country: Option<String>,
+}
@@ -0,0 +1,104 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// How long a measured usage is reused. Writes within that time are
+/// counted on top of it, so the limit holds without walking the tree for
+/// every packet.
+const USAGE_TTL: Duration = Duration::from_secs(10);
+
+#[derive(Default)]
+struct Usage {
+    measured_at: Option<Instant>,
+    bytes: u64,
+}
+
+/// Limits the bytes the files below a root may take up, e.g. those of a
+/// tenant
+#[derive(Clone)]
+pub struct Quota {
+    limit_bytes: u64,
+    usage: Arc<Mutex<Usage>>,
+}
+
+impl Quota {
+    pub fn new(limit_bytes: u64) -> Self {
+        Self { limit_bytes, usage: Arc::default() }
+    }
+
+    /// Whether `incoming` more bytes fit below `root` within the limit,
+    /// counting them as used if so. Writes are let through when the usage
+    /// cannot be read.
+    pub async fn allows(&self, root: &Path, incoming: u64) -> bool {
+        let used = match self.used(root).await {
+            Ok(used) => used,
+            Err(e) => {
+                warn!("Cannot measure usage of {}: {}", root.display(), e);
+                return true;
+            }
+        };
+        if used.saturating_add(incoming) > self.limit_bytes {
+            return false;
+        }
+        let mut usage = self.usage.lock().unwrap();
+        usage.bytes = usage.bytes.saturating_add(incoming);
+        true
+    }
+
+    /// Bytes taken up by the files below `root`
+    pub async fn used(&self, root: &Path) -> io::Result<u64> {
+        {
+            let usage = self.usage.lock().unwrap();
+            if usage.measured_at.is_some_and(|at| at.elapsed() < USAGE_TTL) {
+                return Ok(usage.bytes);
+            }
+        }
+        let path = root.to_path_buf();
+        let bytes = tokio::task::spawn_blocking(move || tree_size(path))
+            .await
+            .map_err(io::Error::other)??;
+        *self.usage.lock().unwrap() =
+            Usage { measured_at: Some(Instant::now()), bytes };
+        Ok(bytes)
+    }
+}
+
+/// Total size of the files below `dir`; links are not followed
+fn tree_size(dir: PathBuf) -> io::Result<u64> {
+    let mut total = 0;
+    let mut pending = vec![dir];
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else if metadata.is_file() {
+                total += metadata.len();
+            }
+        }
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_writes_count_against_the_limit() {
+        let root = std::env::temp_dir().join("sftp-manager-quota-test");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        std::fs::write(root.join("a"), [0; 60]).unwrap();
+        std::fs::write(root.join("sub/b"), [0; 30]).unwrap();
+
+        let quota = Quota::new(100);
+        assert_eq!(quota.used(&root).await.unwrap(), 90);
+        assert!(!quota.allows(&root, 11).await);
+        assert!(quota.allows(&root, 10).await);
+        assert!(!quota.allows(&root, 1).await);
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+
+/// Reads in a row that continue where the previous one ended before
+/// chunks are prefetched
+const SEQUENTIAL_READS: u32 = 2;
+
+/// A chunk being read from disk ahead of the client's request
+struct Prefetch {
+    offset: u64,
+    len: usize,
+    task: JoinHandle<io::Result<Vec<u8>>>,
+}
+
+/// Read-ahead of one handle. Once a client reads a file sequentially, the
+/// chunks after its latest read are read from disk in the background while
+/// earlier ones are on the wire, so downloads over high-latency links do
+/// not wait on the disk between requests. Chunks are the size of the
+/// client's reads; a read anywhere else drops them.
+pub struct ReadAhead {
+    /// Chunks read ahead at most; 0 disables read-ahead
+    depth: usize,
+    /// Opened on the first prefetch, with the size it had then
+    file: Option<(Arc<File>, u64)>,
+    next_offset: u64,
+    sequential: u32,
+    pending: VecDeque<Prefetch>,
+}
+
+impl ReadAhead {
+    pub fn new(depth: usize) -> Self {
+        Self {
+            depth,
+            file: None,
+            next_offset: 0,
+            sequential: 0,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Records a read of `len` bytes at `offset` of the file at `path` and
+    /// returns the data if it was prefetched. Sequential reads queue the
+    /// chunks that follow.
+    pub async fn read(
+        &mut self,
+        path: &Path,
+        offset: u64,
+        len: usize,
+    ) -> Option<io::Result<Vec<u8>>> {
+        if self.depth == 0 || len == 0 {
+            return None;
+        }
+        if offset != self.next_offset {
+            self.reset();
+            self.sequential = 0;
+        }
+        self.next_offset = offset + len as u64;
+        self.sequential = self.sequential.saturating_add(1);
+
+        let hit = match self.pending.front() {
+            Some(next) if next.offset == offset && next.len == len => {
+                self.pending.pop_front()
+            }
+            // The client changed its read size
+            Some(_) => {
+                self.reset();
+                None
+            }
+            None => None,
+        };
+        if self.sequential >= SEQUENTIAL_READS {
+            self.fill(path, offset + len as u64, len);
+        }
+
+        let hit = hit?;
+        Some(hit.task.await.unwrap_or_else(|e| Err(io::Error::other(e))))
+    }
+
+    /// Drops the prefetched chunks, e.g. after the file was written to
+    pub fn reset(&mut self) {
+        for prefetch in self.pending.drain(..) {
+            prefetch.task.abort();
+        }
+    }
+
+    /// Queues chunks of `len` bytes from `start` up to the depth, stopping
+    /// at the end of the file
+    fn fill(&mut self, path: &Path, start: u64, len: usize) {
+        if self.file.is_none() {
+            let Ok(file) = File::open(path) else { return };
+            let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+            self.file = Some((Arc::new(file), size));
+        }
+        let Some((file, size)) = &self.file else { return };
+
+        let mut offset =
+            self.pending.back().map_or(start, |p| p.offset + p.len as u64);
+        while self.pending.len() < self.depth && offset < *size {
+            let file = file.clone();
+            let task = tokio::task::spawn_blocking(move || {
+                let mut buffer = vec![0; len];
+                let read = file.read_at(&mut buffer, offset)?;
+                buffer.truncate(read);
+                Ok(buffer)
+            });
+            self.pending.push_back(Prefetch { offset, len, task });
+            offset += len as u64;
+        }
+    }
+}
+
+impl Drop for ReadAhead {
+    fn drop(&mut self) {
+        self.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sequential_reads_are_served_ahead() {
+        let path = std::env::temp_dir()
+            .join(format!("sftp-manager-read-ahead-{}", std::process::id()));
+        let data: Vec<u8> = (0..10_000u32).map(|i| i as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+
+        let mut read_ahead = ReadAhead::new(3);
+        // Nothing is prefetched until the reads are sequential
+        assert!(read_ahead.read(&path, 0, 1000).await.is_none());
+        assert!(read_ahead.read(&path, 1000, 1000).await.is_none());
+        for offset in (2000..10_000).step_by(1000) {
+            let chunk = read_ahead.read(&path, offset, 1000).await.unwrap();
+            let offset = offset as usize;
+            assert_eq!(chunk.unwrap(), data[offset..offset + 1000]);
+        }
+        // Nothing is read past the end
+        assert!(read_ahead.pending.is_empty());
+
+        // A seek starts over
+        assert!(read_ahead.read(&path, 500, 1000).await.is_none());
+        assert!(read_ahead.pending.is_empty());
+        assert!(ReadAhead::new(0).read(&path, 0, 1000).await.is_none());
+
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,577 @@
+use crate::sftp::handler::SftpSession;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use russh_sftp::protocol::{
+    Attrs, Data, FileAttributes, Handle, Name, OpenFlags, Packet, Status,
+    StatusCode, Version,
+};
+use russh_sftp::server::Handler;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::fs::{self, File};
+use tokio::io::AsyncWriteExt;
+use tracing::warn;
+
+/// Extension of recording files, one JSON object per line
+pub const RECORDING_EXTENSION: &str = "jsonl";
+
+/// Which sessions are recorded and how
+#[derive(Debug, Clone)]
+pub struct RecordingPolicy {
+    pub dir: PathBuf,
+    /// Also record the data read and written, base64 encoded
+    pub include_data: bool,
+    /// Users whose sessions are recorded; empty records everyone's
+    pub users: Arc<[String]>,
+}
+
+impl RecordingPolicy {
+    pub fn records(&self, username: &str) -> bool {
+        self.users.is_empty() || self.users.iter().any(|u| u == username)
+    }
+
+    /// Creates the recording of a new session. The file is named after the
+    /// start time, the session ID and the user, e.g.
+    /// "20261017T080000Z-12-alice.jsonl".
+    pub async fn start(
+        &self,
+        session_id: u64,
+        username: &str,
+        peer: Option<SocketAddr>,
+    ) -> io::Result<Recorder> {
+        fs::create_dir_all(&self.dir).await?;
+        let started_at = Utc::now();
+        let name = format!(
+            "{}-{}-{}.{}",
+            started_at.format("%Y%m%dT%H%M%SZ"),
+            session_id,
+            file_username(username),
+            RECORDING_EXTENSION
+        );
+        let path = self.dir.join(name);
+        let file = File::create_new(&path).await?;
+        let mut recorder =
+            Recorder { file, path, include_data: self.include_data };
+        let header = serde_json::json!({
+            "session_id": session_id,
+            "username": username,
+            "peer": peer.map(|p| p.to_string()),
+            "started_at": started_at.to_rfc3339(),
+        });
+        recorder.append(&header).await?;
+        Ok(recorder)
+    }
+}
+
+/// Username as it appears in recording file names
+fn file_username(username: &str) -> String {
+    username.replace(['/', '\\'], "_")
+}
+
+/// Deletes the recordings of `username`'s sessions from `dir`, returning
+/// how many there were. A missing directory holds none.
+pub async fn remove_recordings(
+    dir: &Path,
+    username: &str,
+) -> io::Result<usize> {
+    let username = file_username(username);
+    let mut entries = match fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    let mut removed = 0;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name();
+        let theirs = name
+            .to_str()
+            .and_then(parse_recording_name)
+            .is_some_and(|(_, _, user)| user == username);
+        if theirs {
+            fs::remove_file(entry.path()).await?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// Start time, session ID and user of the recording file `name`
+pub fn parse_recording_name(name: &str) -> Option<(DateTime<Utc>, u64, &str)> {
+    let stem = name.strip_suffix(RECORDING_EXTENSION)?.strip_suffix('.')?;
+    let mut parts = stem.splitn(3, '-');
+    let started_at =
+        NaiveDateTime::parse_from_str(parts.next()?, "%Y%m%dT%H%M%SZ").ok()?;
+    let session_id = parts.next()?.parse().ok()?;
+    Some((started_at.and_utc(), session_id, parts.next()?))
+}
+
+/// One request and the outcome of it
+#[derive(Debug, Default, Serialize)]
+struct Entry {
+    time: String,
+    id: u32,
+    op: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+    /// New path of a rename, target of a link or resolved path
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    handle: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flags: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    offset: Option<u64>,
+    /// Bytes asked for by a read or sent by a write
+    #[serde(skip_serializing_if = "Option::is_none")]
+    length: Option<u64>,
+    /// Bytes returned by a read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bytes: Option<u64>,
+    /// Names returned by a directory read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entries: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<u32>,
+    /// Name of an extended request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    extension: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<String>,
+    status: String,
+    duration_us: u64,
+}
+
+impl Entry {
+    fn new(id: u32, op: &'static str) -> Self {
+        Self { time: Utc::now().to_rfc3339(), id, op, ..Default::default() }
+    }
+
+    fn path(mut self, path: &str) -> Self {
+        self.path = Some(path.to_string());
+        self
+    }
+
+    fn handle(mut self, handle: &str) -> Self {
+        self.handle = Some(handle.to_string());
+        self
+    }
+}
+
+/// Status code a response stands for
+trait Reply {
+    fn code(&self) -> StatusCode {
+        StatusCode::Ok
+    }
+}
+
+impl Reply for Status {
+    fn code(&self) -> StatusCode {
+        self.status_code
+    }
+}
+
+impl Reply for Attrs {}
+impl Reply for Data {}
+impl Reply for Handle {}
+impl Reply for Name {}
+impl Reply for Packet {}
+impl Reply for Version {}
+
+/// Appends the entries of one session to its recording file
+pub struct Recorder {
+    file: File,
+    path: PathBuf,
+    include_data: bool,
+}
+
+impl Recorder {
+    async fn append<T: Serialize>(&mut self, entry: &T) -> io::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        self.file.write_all(&line).await
+    }
+
+    async fn record<T: Reply>(
+        &mut self,
+        mut entry: Entry,
+        started: Instant,
+        result: &Result<T, StatusCode>,
+    ) {
+        let code = match result {
+            Ok(reply) => reply.code(),
+            Err(code) => *code,
+        };
+        entry.status = code.to_string();
+        entry.duration_us = started.elapsed().as_micros() as u64;
+        if let Err(e) = self.append(&entry).await {
+            warn!("Failed to write to {}: {}", self.path.display(), e);
+        }
+    }
+
+    fn data(&self, data: &[u8]) -> Option<String> {
+        self.include_data.then(|| STANDARD.encode(data))
+    }
+}
+
+/// SFTP handler recording every request of the session it serves, for
+/// reviewing what a client did after the fact
+pub struct RecordedSession {
+    inner: SftpSession,
+    recorder: Recorder,
+}
+
+impl RecordedSession {
+    pub fn new(inner: SftpSession, recorder: Recorder) -> Self {
+        Self { inner, recorder }
+    }
+}
+
+impl Handler for RecordedSession {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        self.inner.unimplemented()
+    }
+
+    async fn init(
+        &mut self,
+        version: u32,
+        extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        let mut entry = Entry::new(0, "init");
+        entry.version = Some(version);
+        let started = Instant::now();
+        let result = self.inner.init(version, extensions).await;
+        self.recorder.record(entry, started, &result).await;
+        result
+    }
+
+    async fn open(
+        &mut self,
+        id: u32,
+        filename: String,
+        pflags: OpenFlags,
+        attrs: FileAttributes,
+    ) -> Result<Handle, Self::Error> {
+        let mut entry = Entry::new(id, "open").path(&filename);
+        entry.flags = Some(pflags.bits());
+        let started = Instant::now();
+        let result = self.inner.open(id, filename, pflags, attrs).await;
+        if let Ok(handle) = &result {
+            entry.handle = Some(handle.handle.clone());
+        }
+        self.recorder.record(entry, started, &result).await;
+        result
+    }
+
+    async fn close(
+        &mut self,
+        id: u32,
+        handle: String,
+    ) -> Result<Status, Self::Error> {
+        let entry = Entry::new(id, "close").handle(&handle);
+        let started = Instant::now();
+        let result = self.inner.close(id, handle).await;
+        self.recorder.record(entry, started, &result).await;
+        result
+    }
+
+    async fn read(
+        &mut self,
+        id: u32,
+        handle: String,
+        offset: u64,
+        len: u32,
+    ) -> Result<Data, Self::Error> {
+        let mut entry = Entry::new(id, "read").handle(&handle);
+        entry.offset = Some(offset);
+        entry.length = Some(len.into());
+        let started = Instant::now();
+        let result = self.inner.read(id, handle, offset, len).await;
+        if let Ok(data) = &result {
+            entry.bytes = Some(data.data.len() as u64);
+            entry.data = self.recorder.data(&data.data);
+        }
+        self.recorder.record(entry, started, &result).await;
+        result
+    }
+
+    async fn write(
+        &mut self,
+        id: u32,
+        handle: String,
+        offset: u64,
+        data: Vec<u8>,
+    ) -> Result<Status, Self::Error> {
+        let mut entry = Entry::new(id, "write").handle(&handle);
+        entry.offset = Some(offset);
+        entry.length = Some(data.len() as u64);
+        entry.data = self.recorder.data(&data);
+        let started = Instant::now();
+        let result = self.inner.write(id, handle, offset, data).await;
+        self.recorder.record(entry, started, &result).await;
+        result
+    }
+
+    async fn lstat(
+        &mut self,
+        id: u32,
+        path: String,
+    ) -> Result<Attrs, Self::Error> {
+        let entry = Entry::new(id, "lstat").path(&path);
+        let started = Instant::now();
+        let result = self.inner.lstat(id, path).await;
+        self.recorder.record(entry, started, &result).await;
+        result
+    }
+
+    async fn fstat(
+        &mut self,
+        id: u32,
+        handle: String,
+    ) -> Result<Attrs, Self::Error> {
+        let entry = Entry::new(id, "fstat").handle(&handle);
+        let started = Instant::now();
+        let result = self.inner.fstat(id, handle).await;
+        self.recorder.record(entry, started, &result).await;
+        result
+    }
+
+    async fn setstat(
+        &mut self,
+        id: u32,
+        path: String,
+        attrs: FileAttributes,
+    ) -> Result<Status, Self::Error> {
+        let entry = Entry::new(id, "setstat").path(&path);
+        let started = Instant::now();
+        let result = self.inner.setstat(id, path, attrs).await;
+        self.recorder.record(entry, started, &result).await;
+        result
+    }
+
+    async fn fsetstat(
+        &mut self,
+        id: u32,
+        handle: String,
+        attrs: FileAttributes,
+    ) -> Result<Status, Self::Error> {
+        let entry = Entry::new(id, "fsetstat").handle(&handle);
+        let started = Instant::now();
+        let result = self.inner.fsetstat(id, handle, attrs).await;
+        self.recorder.record(entry, started, &result).await;
+        result
+    }
+
+    async fn opendir(
+        &mut self,
+        id: u32,
+        path: String,
+    ) -> Result<Handle, Self::Error> {
+        let mut entry = Entry::new(id, "opendir").path(&path);
+        let started = Instant::now();
+        let result = self.inner.opendir(id, path).await;
+        if let Ok(handle) = &result {
+            entry.handle = Some(handle.handle.clone());
+        }
+        self.recorder.record(entry, started, &result).await;
+        result
+    }
+
+    async fn readdir(
+        &mut self,
+        id: u32,
+        handle: String,
+    ) -> Result<Name, Self::Error> {
+        let mut entry = Entry::new(id, "readdir").handle(&handle);
+        let started = Instant::now();
+        let result = self.inner.readdir(id, handle).await;
+        if let Ok(name) = &result {
+            entry.entries = Some(name.files.len());
+        }
+        self.recorder.record(entry, started, &result).await;
+        result
+    }
+
+    async fn remove(
+        &mut self,
+        id: u32,
+        filename: String,
+    ) -> Result<Status, Self::Error> {
+        let entry = Entry::new(id, "remove").path(&filename);
+        let started = Instant::now();
+        let result = self.inner.remove(id, filename).await;
+        self.recorder.record(entry, started, &result).await;
+        result
+    }
+
+    async fn mkdir(
+        &mut self,
+        id: u32,
+        path: String,
+        attrs: FileAttributes,
+    ) -> Result<Status, Self::Error> {
+        let entry = Entry::new(id, "mkdir").path(&path);
+        let started = Instant::now();
+        let result = self.inner.mkdir(id, path, attrs).await;
+        self.recorder.record(entry, started, &result).await;
+        result
+    }
+
+    async fn rmdir(
+        &mut self,
+        id: u32,
+        path: String,
+    ) -> Result<Status, Self::Error> {
+        let entry = Entry::new(id, "rmdir").path(&path);
+        let started = Instant::now();
+        let result = self.inner.rmdir(id, path).await;
+        self.recorder.record(entry, started, &result).await;
+        result
+    }
+
+    async fn realpath(
+        &mut self,
+        id: u32,
+        path: String,
+    ) -> Result<Name, Self::Error> {
+        let mut entry = Entry::new(id, "realpath").path(&path);
+        let started = Instant::now();
+        let result = self.inner.realpath(id, path).await;
+        if let Ok(name) = &result {
+            entry.target = name.files.first().map(|f| f.filename.clone());
+        }
+        self.recorder.record(entry, started, &result).await;
+        result
+    }
+
+    async fn stat(
+        &mut self,
+        id: u32,
+        path: String,
+    ) -> Result<Attrs, Self::Error> {
+        let entry = Entry::new(id, "stat").path(&path);
+        let started = Instant::now();
+        let result = self.inner.stat(id, path).await;
+        self.recorder.record(entry, started, &result).await;
+        result
+    }
+
+    async fn rename(
+        &mut self,
+        id: u32,
+        oldpath: String,
+        newpath: String,
+    ) -> Result<Status, Self::Error> {
+        let mut entry = Entry::new(id, "rename").path(&oldpath);
+        entry.target = Some(newpath.clone());
+        let started = Instant::now();
+        let result = self.inner.rename(id, oldpath, newpath).await;
+        self.recorder.record(entry, started, &result).await;
+        result
+    }
+
+    async fn readlink(
+        &mut self,
+        id: u32,
+        path: String,
+    ) -> Result<Name, Self::Error> {
+        let mut entry = Entry::new(id, "readlink").path(&path);
+        let started = Instant::now();
+        let result = self.inner.readlink(id, path).await;
+        if let Ok(name) = &result {
+            entry.target = name.files.first().map(|f| f.filename.clone());
+        }
+        self.recorder.record(entry, started, &result).await;
+        result
+    }
+
+    async fn symlink(
+        &mut self,
+        id: u32,
+        linkpath: String,
+        targetpath: String,
+    ) -> Result<Status, Self::Error> {
+        let mut entry = Entry::new(id, "symlink").path(&linkpath);
+        entry.target = Some(targetpath.clone());
+        let started = Instant::now();
+        let result = self.inner.symlink(id, linkpath, targetpath).await;
+        self.recorder.record(entry, started, &result).await;
+        result
+    }
+
+    async fn extended(
+        &mut self,
+        id: u32,
+        request: String,
+        data: Vec<u8>,
+    ) -> Result<Packet, Self::Error> {
+        let mut entry = Entry::new(id, "extended");
+        entry.extension = Some(request.clone());
+        entry.length = Some(data.len() as u64);
+        let started = Instant::now();
+        let result = self.inner.extended(id, request, data).await;
+        self.recorder.record(entry, started, &result).await;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_requests_are_recorded() {
+        let dir = std::env::temp_dir()
+            .join(format!("sftp-manager-recording-{}", std::process::id()));
+        let policy = RecordingPolicy {
+            dir: dir.clone(),
+            include_data: true,
+            users: vec!["alice".to_string()].into(),
+        };
+        assert!(policy.records("alice"));
+        assert!(!policy.records("bob"));
+
+        let mut recorder = policy.start(7, "alice", None).await.unwrap();
+        let mut entry = Entry::new(3, "write").handle("1");
+        entry.offset = Some(0);
+        entry.data = recorder.data(b"hi");
+        let written = Ok(Status {
+            id: 3,
+            status_code: StatusCode::Ok,
+            error_message: String::new(),
+            language_tag: String::new(),
+        });
+        recorder.record(entry, Instant::now(), &written).await;
+        let missing: Result<Attrs, _> = Err(StatusCode::NoSuchFile);
+        let entry = Entry::new(4, "stat").path("/gone");
+        recorder.record(entry, Instant::now(), &missing).await;
+        recorder.file.flush().await.unwrap();
+
+        let name = recorder.path.file_name().unwrap().to_str().unwrap();
+        let (_, session_id, username) = parse_recording_name(name).unwrap();
+        assert_eq!((session_id, username), (7, "alice"));
+        assert_eq!(parse_recording_name("notes.jsonl"), None);
+
+        let content = std::fs::read_to_string(&recorder.path).unwrap();
+        let lines: Vec<serde_json::Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines[0]["session_id"], 7);
+        assert_eq!(lines[1]["op"], "write");
+        assert_eq!(lines[1]["data"], "aGk=");
+        assert_eq!(lines[1]["status"], "Ok");
+        assert_eq!(lines[2]["path"], "/gone");
+        assert_eq!(lines[2]["status"], "No such file");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,284 @@
+use crate::sftp::negotiation::Negotiated;
+use chrono::{DateTime, Utc};
+use russh::Disconnect;
+use russh::server::Handle;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{debug, info};
+
+/// Bookkeeping for one connected SSH client
+pub struct SessionEntry {
+    /// Remote address of the client
+    pub peer_addr: Option<SocketAddr>,
+    /// Authenticated username, once known
+    pub username: Option<String>,
+    /// Country of the client address, when looked up
+    pub country: Option<String>,
+    /// SSH identification string of the client software, once received
+    pub client: Option<String>,
+    /// How the user authenticated, e.g. "password"
+    pub auth_method: Option<String>,
+    /// Algorithms agreed on in the key exchange
+    pub negotiated: Option<Negotiated>,
+    /// When the TCP connection was accepted
+    pub connected_at: DateTime<Utc>,
+    /// Number of file handles currently open in the SFTP subsystem
+    pub open_files: usize,
+    /// Memory held by the SFTP subsystem's handles and listings
+    pub memory_bytes: usize,
+    /// Handle used to disconnect the client from the server side
+    handle: Option<Handle>,
+}
+
+/// Point-in-time view of a session, for reporting
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionInfo {
+    pub id: u64,
+    pub peer_addr: Option<SocketAddr>,
+    pub username: Option<String>,
+    pub country: Option<String>,
+    pub client: Option<String>,
+    pub auth_method: Option<String>,
+    pub negotiated: Option<Negotiated>,
+    pub connected_at: DateTime<Utc>,
+    pub open_files: usize,
+    pub memory_bytes: usize,
+}
+
+/// Tracks live SSH sessions across the listener's lifetime
+#[derive(Clone, Default)]
+pub struct SessionRegistry {
+    next_id: Arc<AtomicU64>,
+    sessions: Arc<Mutex<HashMap<u64, SessionEntry>>>,
+    /// Set while the server is stopping; no new files may be opened
+    closing: Arc<AtomicBool>,
+}
+
+impl SessionRegistry {
+    /// Registers a newly accepted connection and returns its ID
+    pub fn register(&self, peer_addr: Option<SocketAddr>) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        self.sessions.lock().unwrap().insert(
+            id,
+            SessionEntry {
+                peer_addr,
+                username: None,
+                country: None,
+                client: None,
+                auth_method: None,
+                negotiated: None,
+                connected_at: Utc::now(),
+                open_files: 0,
+                memory_bytes: 0,
+                handle: None,
+            },
+        );
+        debug!("Registered session {} from {:?}", id, peer_addr);
+        id
+    }
+
+    /// Stores the handle once the SSH transport is running
+    pub fn set_handle(&self, id: u64, handle: Handle) {
+        if let Some(entry) = self.sessions.lock().unwrap().get_mut(&id) {
+            entry.handle = Some(handle);
+        }
+    }
+
+    /// Records the authenticated user of a session
+    pub fn set_username(&self, id: u64, username: &str) {
+        if let Some(entry) = self.sessions.lock().unwrap().get_mut(&id) {
+            entry.username = Some(username.to_string());
+        }
+    }
+
+    /// Records the country the client connects from
+    pub fn set_country(&self, id: u64, country: &str) {
+        if let Some(entry) = self.sessions.lock().unwrap().get_mut(&id) {
+            entry.country = Some(country.to_string());
+        }
+    }
+
+    /// Records the client software a session identified itself as
+    pub fn set_client(&self, id: u64, client: &str) {
+        if let Some(entry) = self.sessions.lock().unwrap().get_mut(&id) {
+            entry.client = Some(client.to_string());
+        }
+    }
+
+    /// Records how a session authenticated and the algorithms it uses
+    pub fn set_login(
+        &self,
+        id: u64,
+        auth_method: &str,
+        negotiated: Option<Negotiated>,
+    ) {
+        if let Some(entry) = self.sessions.lock().unwrap().get_mut(&id) {
+            entry.auth_method = Some(auth_method.to_string());
+            entry.negotiated = negotiated;
+        }
+    }
+
+    /// Updates the number of open file handles of a session
+    pub fn set_open_files(&self, id: u64, open_files: usize) {
+        if let Some(entry) = self.sessions.lock().unwrap().get_mut(&id) {
+            entry.open_files = open_files;
+        }
+    }
+
+    /// Updates the memory held by a session's handles and listings
+    pub fn set_memory_bytes(&self, id: u64, memory_bytes: usize) {
+        if let Some(entry) = self.sessions.lock().unwrap().get_mut(&id) {
+            entry.memory_bytes = memory_bytes;
+        }
+    }
+
+    /// Forgets a session after its connection closed
+    pub fn remove(&self, id: u64) {
+        self.sessions.lock().unwrap().remove(&id);
+        debug!("Removed session {}", id);
+    }
+
+    /// Number of connected sessions
+    pub fn count(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+
+    /// Connected sessions, oldest first
+    pub fn list(&self) -> Vec<SessionInfo> {
+        let mut sessions: Vec<SessionInfo> = self
+            .sessions
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, s)| SessionInfo {
+                id: *id,
+                peer_addr: s.peer_addr,
+                username: s.username.clone(),
+                country: s.country.clone(),
+                client: s.client.clone(),
+                auth_method: s.auth_method.clone(),
+                negotiated: s.negotiated.clone(),
+                connected_at: s.connected_at,
+                open_files: s.open_files,
+                memory_bytes: s.memory_bytes,
+            })
+            .collect();
+        sessions.sort_by_key(|s| s.id);
+        sessions
+    }
+
+    /// Number of file handles open across all sessions
+    pub fn open_files(&self) -> usize {
+        self.sessions.lock().unwrap().values().map(|s| s.open_files).sum()
+    }
+
+    /// Marks the server as stopping, or running again
+    pub fn set_closing(&self, closing: bool) {
+        self.closing.store(closing, Ordering::Relaxed);
+    }
+
+    /// Whether the server is stopping and refuses new transfers
+    pub fn is_closing(&self) -> bool {
+        self.closing.load(Ordering::Relaxed)
+    }
+
+    /// Waits until no session has a file open or the timeout passes.
+    /// Returns whether every transfer finished.
+    pub async fn wait_for_transfers(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while self.open_files() > 0 {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        true
+    }
+
+    /// Disconnects one session; false when no such session is connected
+    pub async fn disconnect(&self, id: u64, reason: &str) -> bool {
+        let handle = {
+            let sessions = self.sessions.lock().unwrap();
+            let Some(session) = sessions.get(&id) else {
+                return false;
+            };
+            info!(
+                "Disconnecting session {} from {:?} (user {:?}): {}",
+                id, session.peer_addr, session.username, reason
+            );
+            session.handle.clone()
+        };
+
+        // The SSH transport is not running yet
+        let Some(handle) = handle else {
+            return false;
+        };
+        let _ = handle
+            .disconnect(
+                Disconnect::ByApplication,
+                reason.to_string(),
+                "en-US".to_string(),
+            )
+            .await;
+        true
+    }
+
+    /// Disconnects every session, e.g. at the end of a drain
+    pub async fn disconnect_all(&self, reason: &str) {
+        let handles: Vec<(u64, Handle)> = {
+            let sessions = self.sessions.lock().unwrap();
+            sessions
+                .iter()
+                .filter_map(|(id, s)| {
+                    info!(
+                        "Disconnecting session {} from {:?} (user {:?}, \
+                         connected since {}): {}",
+                        id,
+                        s.peer_addr,
+                        s.username,
+                        s.connected_at.to_rfc3339(),
+                        reason
+                    );
+                    s.handle.clone().map(|h| (*id, h))
+                })
+                .collect()
+        };
+
+        for (id, handle) in handles {
+            debug!("Sending disconnect to session {}", id);
+            let _ = handle
+                .disconnect(
+                    Disconnect::ByApplication,
+                    reason.to_string(),
+                    "en-US".to_string(),
+                )
+                .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_drain_waits_for_open_files() {
+        let sessions = SessionRegistry::default();
+        let id = sessions.register(None);
+        sessions.set_open_files(id, 2);
+
+        assert!(!sessions.wait_for_transfers(Duration::from_millis(150)).await);
+
+        let closer = sessions.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(150)).await;
+            closer.set_open_files(id, 0);
+        });
+        assert!(sessions.wait_for_transfers(Duration::from_secs(2)).await);
+    }
+}
@@ -0,0 +1,184 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// How far an upload got: the bytes written from the start of the file
+/// without gaps, and what was written past the first gap
+#[derive(Debug, Clone)]
+struct Progress {
+    contiguous: u64,
+    /// Start to exclusive end of ranges written past a gap, not touching
+    /// each other or the contiguous part
+    ahead: BTreeMap<u64, u64>,
+    updated_at: DateTime<Utc>,
+}
+
+impl Progress {
+    fn new(contiguous: u64) -> Self {
+        Self { contiguous, ahead: BTreeMap::new(), updated_at: Utc::now() }
+    }
+
+    fn record(&mut self, offset: u64, len: u64) {
+        self.updated_at = Utc::now();
+        let (mut start, mut end) = (offset, offset.saturating_add(len));
+        if len == 0 || end <= self.contiguous {
+            return;
+        }
+        // Merge with the ranges it overlaps or touches
+        let touching: Vec<u64> = self
+            .ahead
+            .range(..=end)
+            .filter(|&(_, &e)| e >= start)
+            .map(|(&s, _)| s)
+            .collect();
+        for s in touching {
+            let e = self.ahead.remove(&s).unwrap_or(s);
+            start = start.min(s);
+            end = end.max(e);
+        }
+        if start <= self.contiguous {
+            self.contiguous = end;
+        } else {
+            self.ahead.insert(start, end);
+        }
+    }
+}
+
+/// Where a client resumes the upload of a file
+#[derive(Debug, Clone, Serialize)]
+pub struct ResumePoint {
+    pub path: String,
+    /// Bytes from the start of the file known to be written; uploads
+    /// resume from here
+    pub offset: u64,
+    /// Size of the file, which counts any bytes written past a gap
+    pub size: u64,
+    /// Whether the offset comes from the writes to the file; otherwise it
+    /// is its size, e.g. for uploads that finished without gaps
+    pub tracked: bool,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Progress of the uploads through SFTP, so clients whose connection
+/// dropped learn where to resume. Pipelined writes may land out of order,
+/// which leaves the size of a broken upload past its last contiguous byte.
+#[derive(Clone, Default)]
+pub struct UploadProgress {
+    uploads: Arc<Mutex<HashMap<PathBuf, Progress>>>,
+}
+
+impl UploadProgress {
+    /// Starts tracking a file opened for writing, `existing` bytes long
+    /// once opened. Empty files start over; the progress of others is
+    /// kept, or taken to be their size when unknown.
+    pub fn opened(&self, path: &Path, existing: u64) {
+        let mut uploads = self.uploads.lock().unwrap();
+        if existing == 0 {
+            uploads.insert(path.to_path_buf(), Progress::new(0));
+        } else {
+            uploads
+                .entry(path.to_path_buf())
+                .or_insert_with(|| Progress::new(existing));
+        }
+    }
+
+    /// Records `len` bytes written at `offset`
+    pub fn written(&self, path: &Path, offset: u64, len: u64) {
+        let mut uploads = self.uploads.lock().unwrap();
+        if let Some(progress) = uploads.get_mut(path) {
+            progress.record(offset, len);
+        }
+    }
+
+    /// Stops tracking a closed file whose size already tells where to
+    /// resume, which is when it was written without gaps
+    pub fn closed(&self, path: &Path, size: u64) {
+        let mut uploads = self.uploads.lock().unwrap();
+        if uploads
+            .get(path)
+            .is_some_and(|p| p.ahead.is_empty() && p.contiguous >= size)
+        {
+            uploads.remove(path);
+        }
+    }
+
+    pub fn removed(&self, path: &Path) {
+        self.uploads.lock().unwrap().remove(path);
+    }
+
+    pub fn renamed(&self, from: &Path, to: &Path) {
+        let mut uploads = self.uploads.lock().unwrap();
+        uploads.remove(to);
+        if let Some(progress) = uploads.remove(from) {
+            uploads.insert(to.to_path_buf(), progress);
+        }
+    }
+
+    /// Where to resume uploading the file at `local`, `size` bytes long,
+    /// named `path` by the client
+    pub fn resume_point(
+        &self,
+        local: &Path,
+        path: String,
+        size: u64,
+    ) -> ResumePoint {
+        let uploads = self.uploads.lock().unwrap();
+        match uploads.get(local) {
+            Some(progress) => ResumePoint {
+                path,
+                offset: progress.contiguous.min(size),
+                size,
+                tracked: true,
+                updated_at: Some(progress.updated_at),
+            },
+            None => ResumePoint {
+                path,
+                offset: size,
+                size,
+                tracked: false,
+                updated_at: None,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resume_offset_stops_at_the_first_gap() {
+        let uploads = UploadProgress::default();
+        let path = Path::new("/data/in/orders.edi");
+        uploads.opened(path, 0);
+        uploads.written(path, 0, 100);
+        uploads.written(path, 200, 100);
+        uploads.written(path, 400, 100);
+        let point = uploads.resume_point(path, "/in/orders.edi".into(), 500);
+        assert_eq!((point.offset, point.tracked), (100, true));
+
+        // Dropped with gaps: still known once the handle is closed
+        uploads.closed(path, 500);
+        uploads.written(path, 100, 100);
+        uploads.written(path, 300, 100);
+        assert_eq!(uploads.resume_point(path, String::new(), 500).offset, 500);
+
+        // Without gaps the size tells
+        uploads.closed(path, 500);
+        let point = uploads.resume_point(path, String::new(), 500);
+        assert_eq!((point.offset, point.tracked), (500, false));
+
+        // Resumed uploads of untracked files continue from their size
+        uploads.opened(path, 500);
+        uploads.written(path, 600, 10);
+        assert_eq!(uploads.resume_point(path, String::new(), 610).offset, 500);
+        let moved = Path::new("/data/in/orders.done");
+        uploads.renamed(path, moved);
+        assert!(!uploads.resume_point(path, String::new(), 0).tracked);
+        assert_eq!(uploads.resume_point(moved, String::new(), 610).offset, 500);
+        uploads.removed(moved);
+        assert!(!uploads.resume_point(moved, String::new(), 0).tracked);
+    }
+}
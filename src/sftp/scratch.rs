@@ -0,0 +1,144 @@
+use rand::RngExt;
+use std::path::{Component, Path, PathBuf};
+use tokio::{fs, io};
+
+/// Hidden directory below the SFTP root holding the scratch areas of the
+/// sessions, one subdirectory each
+pub const SCRATCH_DIR: &str = ".scratch";
+
+/// Private scratch area of one SFTP session. Clients see it at a path of
+/// their tree, e.g. "/tmp", while it is stored in its own directory below
+/// the hidden scratch directory, on the same filesystem as the root so
+/// files staged there can be renamed into place. It is deleted when the
+/// session ends.
+#[derive(Debug, Clone)]
+pub struct ScratchArea {
+    /// Client path of the area, e.g. "/tmp"
+    virtual_dir: String,
+    /// Location of the area relative to the root
+    relative: PathBuf,
+}
+
+impl ScratchArea {
+    /// Area shown at `virtual_dir` for the session numbered `session`. A
+    /// random suffix keeps areas left by an earlier run apart from it.
+    pub fn new(virtual_dir: &str, session: u64) -> Self {
+        let suffix: u32 = rand::rng().random();
+        Self {
+            virtual_dir: clean(virtual_dir),
+            relative: Path::new(SCRATCH_DIR)
+                .join(format!("{}-{:08x}", session, suffix)),
+        }
+    }
+
+    /// Whether a path relative to the root lies in the scratch areas of
+    /// any session
+    pub fn contains(relative: &Path) -> bool {
+        relative
+            .components()
+            .find(|c| !matches!(c, Component::RootDir))
+            .is_some_and(|c| c.as_os_str() == SCRATCH_DIR)
+    }
+
+    /// Where the area is stored below `root`
+    pub fn local_dir(&self, root: &Path) -> PathBuf {
+        root.join(&self.relative)
+    }
+
+    /// The client path `path` moved into the area's directory, as a path
+    /// from the root, when it names the area or something in it
+    pub fn to_local(&self, path: &str) -> Option<String> {
+        let path = clean(path);
+        let rest = match path.strip_prefix(&self.virtual_dir)? {
+            "" => "",
+            rest => rest.strip_prefix('/')?,
+        };
+        let mut local = format!("/{}", self.relative.display());
+        if !rest.is_empty() {
+            local.push('/');
+            local.push_str(rest);
+        }
+        Some(local)
+    }
+
+    /// The client path of a path relative to the root, when it lies in
+    /// this area
+    pub fn to_virtual(&self, relative: &Path) -> Option<String> {
+        let rest = relative.strip_prefix(&self.relative).ok()?;
+        Some(match rest.as_os_str().is_empty() {
+            true => self.virtual_dir.clone(),
+            false => format!("{}/{}", self.virtual_dir, rest.display()),
+        })
+    }
+
+    /// Whether a path relative to the root lies in this area
+    pub fn owns(&self, relative: &Path) -> bool {
+        relative.starts_with(&self.relative)
+    }
+
+    /// Client path of the directory the area appears in, and its name
+    /// there
+    pub fn entry(&self) -> (&str, &str) {
+        match self.virtual_dir.rsplit_once('/') {
+            Some(("", name)) => ("/", name),
+            Some((parent, name)) => (parent, name),
+            None => ("/", &self.virtual_dir),
+        }
+    }
+
+    /// Removes the scratch areas below `root`, which only sessions of an
+    /// earlier run can have left while no server is serving it
+    pub async fn purge_all(root: &Path) -> io::Result<()> {
+        match fs::remove_dir_all(root.join(SCRATCH_DIR)).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// `path` as an absolute client path without "." and ".." components or
+/// repeated and trailing slashes; ".." never leaves the root
+fn clean(path: &str) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    format!("/{}", parts.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scratch_area_maps_its_client_path() {
+        let area = ScratchArea::new("/tmp/", 7);
+        let local = area.to_local("/tmp").unwrap();
+        assert!(local.starts_with("/.scratch/7-"));
+        let relative = PathBuf::from(local.trim_start_matches('/'));
+        assert!(ScratchArea::contains(&relative));
+        assert!(area.owns(&relative));
+        assert_eq!(area.to_virtual(&relative).as_deref(), Some("/tmp"));
+
+        let part = area.to_local("/incoming/../tmp/./a.zip.001").unwrap();
+        assert_eq!(part, format!("{}/a.zip.001", local));
+        let relative = Path::new(part.trim_start_matches('/'));
+        assert_eq!(
+            area.to_virtual(relative).as_deref(),
+            Some("/tmp/a.zip.001")
+        );
+
+        assert_eq!(area.to_local("/tmpfile"), None);
+        assert_eq!(area.to_local("/incoming/tmp"), None);
+        assert!(!area.owns(Path::new(".scratch/8-00000000/a")));
+        assert!(!ScratchArea::contains(Path::new("incoming/.scratch")));
+        assert_eq!(area.entry(), ("/", "tmp"));
+        assert_eq!(ScratchArea::new("/work/tmp", 1).entry(), ("/work", "tmp"));
+    }
+}
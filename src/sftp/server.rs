@@ -1,93 +1,664 @@
+use crate::error::{Error, Result};
+use crate::events::{Event, EventBus};
+use crate::sftp::access_hours::AccessHours;
+use crate::sftp::accounts::AccountLogins;
+use crate::sftp::acl::PathRules;
+use crate::sftp::auth_tracker::{AuthAlert, AuthFailureTracker};
+use crate::sftp::checksum::ChecksumAlgorithm;
+use crate::sftp::client_versions::ClientVersions;
+use crate::sftp::completion::UploadCompletion;
+use crate::sftp::credentials::SharedCredentials;
+use crate::sftp::disk_space::DiskGuard;
+use crate::sftp::file_io::FileIo;
+use crate::sftp::filenames::FilenamePolicy;
+use crate::sftp::hooks::ConnectionHooks;
+use crate::sftp::landing::DatedLanding;
+use crate::sftp::limits::{KeepAlive, TransferLimits};
+use crate::sftp::listing_cache::ListingCache;
+use crate::sftp::locks::ByteRangeLocks;
+use crate::sftp::negotiation::KexTap;
+use crate::sftp::owners::OwnerNames;
+use crate::sftp::peer_filter::{PeerCheck, PeerFilter};
+use crate::sftp::quota::Quota;
+use crate::sftp::recording::RecordingPolicy;
+use crate::sftp::registry::SessionRegistry;
+use crate::sftp::resume::UploadProgress;
+use crate::sftp::scratch::ScratchArea;
 use crate::sftp::session::SshServerImpl;
+use crate::sftp::tarpit::Tarpit;
+use crate::stats::SftpStats;
+use chrono::Utc;
 use russh::keys::ssh_key::{self, rand_core::OsRng};
+use russh::keys::{PrivateKey, PublicKey};
 use russh::server::Server as _;
+use std::net::SocketAddr;
+use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::RwLock;
-use tracing::{debug, info};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{RwLock, mpsc, watch};
+use tracing::{debug, error, info, warn};
+
+// Shared services the SFTP server reports into; outlives server restarts
+#[derive(Clone)]
+pub struct ServerContext {
+    // Bus used to publish uploads and authentication failure spikes
+    pub events: EventBus,
+    // Shared tracker of failed authentication attempts
+    pub auth_failures: AuthFailureTracker,
+    // Usage counters exposed through the stats endpoint
+    pub stats: SftpStats,
+    // Live sessions, used for draining and reporting
+    pub sessions: SessionRegistry,
+    // Configured host key; a random one is generated per start otherwise
+    pub host_key: Option<PrivateKey>,
+    // Digests computed for every upload; empty computes none
+    pub checksum_algorithms: Arc<[ChecksumAlgorithm]>,
+    // Leave blocks of zeros written past the end of a file as holes
+    pub sparse_files: bool,
+    // Decides when uploads are complete, then runs the upload hooks, e.g.
+    // virus scanning, and publishes them
+    pub completion: UploadCompletion,
+    // Told about connections opening and closing and about logins
+    pub connection_hooks: ConnectionHooks,
+    // Move removed files and directories to the hidden trash directory
+    pub trash: bool,
+    // Client path of the private scratch directory of each SFTP session;
+    // sessions get none when unset
+    pub scratch: Option<String>,
+    // Applied to the names of created files and directories
+    pub filenames: FilenamePolicy,
+    // What clients may do below which paths
+    pub path_rules: PathRules,
+    // Files uploaded into landing directories end up in dated ones
+    pub landing: DatedLanding,
+    // Byte-range locks clients take on the files of this server
+    pub locks: ByteRangeLocks,
+    // How far uploads got, for clients resuming them
+    pub uploads: UploadProgress,
+    // Screens new connections, e.g. by country; all are let in when unset
+    pub peer_filter: Option<Arc<dyn PeerFilter>>,
+    // Client software allowed to log in, by SSH identification string
+    pub client_versions: ClientVersions,
+    // Holds connections from clients banned for failed logins
+    pub tarpit: Tarpit,
+    // User and group names shown in directory listings
+    pub owner_names: OwnerNames,
+    // Refuses writes that would eat into the disk reserve
+    pub disk_space: DiskGuard,
+    // Sessions whose requests are recorded; none are when unset
+    pub recordings: Option<RecordingPolicy>,
+    // When users may be connected; unrestricted when unset
+    pub access_hours: Option<Arc<dyn AccessHours>>,
+    // Accounts that may log in besides the generated credentials; only
+    // those are accepted when unset
+    pub accounts: Option<Arc<dyn AccountLogins>>,
+    // Bytes the files below the root may take up; unlimited when unset
+    pub quota: Option<Quota>,
+    // Buffer and packet sizes of transfers
+    pub transfer_limits: TransferLimits,
+    // How unresponsive clients are detected and dropped
+    pub keepalive: KeepAlive,
+    // Recent listings and attributes, for clients that re-list often
+    pub listing_cache: Option<ListingCache>,
+    // Reads and writes file contents over tokio::fs or io_uring
+    pub file_io: FileIo,
+    // Bytes of handles, listings and read-ahead one session may hold;
+    // 0 is unlimited
+    pub session_memory_bytes: usize,
+    // Files one SSH session may stream at once over its SFTP channels; 0
+    // is unlimited
+    pub session_transfers: usize,
+}
+
+impl ServerContext {
+    // The connection back unless its client is banned; connections from
+    // banned clients are held in the tarpit or closed. Lines sent by the
+    // tarpit start with `prefix`.
+    pub fn unless_banned(
+        &self,
+        stream: TcpStream,
+        peer: SocketAddr,
+        prefix: &'static str,
+    ) -> Option<TcpStream> {
+        if !self.auth_failures.is_banned(peer.ip()) {
+            return Some(stream);
+        }
+        self.tarpit.hold(stream, peer, prefix, &self.stats);
+        None
+    }
+
+    // Whether `incoming` more bytes may be written under `root` without
+    // eating into the disk reserve or going over the quota
+    pub async fn has_space(&self, root: &Path, incoming: u64) -> bool {
+        if !self.disk_space.allows(root, incoming).await {
+            return false;
+        }
+        match &self.quota {
+            Some(quota) => quota.allows(root, incoming).await,
+            None => true,
+        }
+    }
+
+    // Look up where a new connection comes from and whether it may
+    // proceed; rejections are published for the audit log
+    pub fn check_peer(&self, peer: SocketAddr, protocol: &str) -> PeerCheck {
+        let Some(filter) = &self.peer_filter else {
+            return PeerCheck { allowed: true, country: None };
+        };
+        let country = filter.country(peer.ip());
+        let allowed = filter.allows(country.as_deref());
+        if !allowed {
+            warn!(
+                "Rejected {} connection from {} (country {})",
+                protocol,
+                peer,
+                country.as_deref().unwrap_or("unknown")
+            );
+            self.events.publish(Event::ConnectionRejected {
+                protocol: protocol.to_string(),
+                peer: peer.to_string(),
+                country: country.clone(),
+            });
+        }
+        PeerCheck { allowed, country }
+    }
+
+    // Whether `password` is the password of the account `user`. Lookup
+    // failures refuse the login.
+    pub async fn account_password_matches(
+        &self,
+        user: &str,
+        password: &str,
+    ) -> bool {
+        let Some(accounts) = &self.accounts else {
+            return false;
+        };
+        accounts.password_matches(user, password).await.unwrap_or_else(|e| {
+            error!("Failed to check the password of {}: {}", user, e);
+            false
+        })
+    }
+
+    // Whether `key` is an authorized key of the account `user`. Lookup
+    // failures refuse the login.
+    pub async fn account_key_authorized(
+        &self,
+        user: &str,
+        key: &PublicKey,
+    ) -> bool {
+        let Some(accounts) = &self.accounts else {
+            return false;
+        };
+        accounts.key_authorized(user, key).await.unwrap_or_else(|e| {
+            error!("Failed to check the keys of {}: {}", user, e);
+            false
+        })
+    }
+
+    // Whether a user with valid credentials may log in now. Logins are
+    // refused when the access windows cannot be looked up; refusals are
+    // published for the audit log.
+    pub async fn within_access_hours(
+        &self,
+        user: &str,
+        peer: Option<SocketAddr>,
+        protocol: &str,
+    ) -> bool {
+        let Some(hours) = &self.access_hours else {
+            return true;
+        };
+        match hours.allows(user, Utc::now()).await {
+            Ok(true) => return true,
+            Ok(false) => {
+                warn!(
+                    "Refused {} login of {} outside access hours",
+                    protocol, user
+                )
+            }
+            Err(e) => {
+                error!("Failed to look up access hours of {}: {}", user, e)
+            }
+        }
+        self.events.publish(Event::LoginOutsideAccessHours {
+            username: user.to_string(),
+            protocol: protocol.to_string(),
+            peer: peer.map(|a| a.to_string()),
+        });
+        false
+    }
+
+    // Disconnect sessions whose user is past the end of their access
+    // windows. Sessions are kept when the lookup fails.
+    pub async fn enforce_access_hours(&self) {
+        let Some(hours) = &self.access_hours else {
+            return;
+        };
+        let now = Utc::now();
+        for session in self.sessions.list() {
+            let Some(user) = &session.username else {
+                continue;
+            };
+            match hours.allows(user, now).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    self.sessions
+                        .disconnect(session.id, "Access hours have ended")
+                        .await;
+                }
+                Err(e) => {
+                    error!("Failed to look up access hours of {}: {}", user, e)
+                }
+            }
+        }
+    }
+
+    // Count a rejected login, publish it and raise alerts when failures
+    // pile up; shared by every protocol checking the credentials
+    pub fn login_failed(
+        &self,
+        user: &str,
+        peer: Option<SocketAddr>,
+        country: Option<&str>,
+    ) {
+        self.stats.record_failed_login();
+        self.events.publish(Event::LoginFailed {
+            username: user.to_string(),
+            peer: peer.map(|a| a.to_string()),
+            country: country.map(String::from),
+        });
+        let window_secs = self.auth_failures.window().as_secs();
+        let alerts =
+            self.auth_failures.record_failure(user, peer.map(|a| a.ip()));
+        for alert in alerts {
+            let event = match alert {
+                AuthAlert::Spike { failures } => {
+                    warn!(
+                        "{} failed logins within {} seconds",
+                        failures, window_secs
+                    );
+                    Event::AuthFailureSpike { failures, window_secs }
+                }
+                AuthAlert::Ip { ip, failures, usernames } => {
+                    warn!(
+                        "{} failed logins from {} within {} seconds (users: {})",
+                        failures,
+                        ip,
+                        window_secs,
+                        usernames.join(", ")
+                    );
+                    let ban = self.auth_failures.ban_duration();
+                    if !ban.is_zero() {
+                        warn!("Banning {} for {} seconds", ip, ban.as_secs());
+                    }
+                    Event::AuthFailuresFromIp {
+                        ip,
+                        failures,
+                        window_secs,
+                        usernames,
+                    }
+                }
+                AuthAlert::User { username, failures, ips } => {
+                    warn!(
+                        "{} failed logins for {} within {} seconds (from: {})",
+                        failures,
+                        username,
+                        window_secs,
+                        ips.join(", ")
+                    );
+                    Event::AuthFailuresForUser {
+                        username,
+                        failures,
+                        window_secs,
+                        ips,
+                    }
+                }
+            };
+            self.events.publish(event);
+        }
+    }
+}
+
+#[cfg(test)]
+impl ServerContext {
+    // Context with every optional service unset, for tests
+    pub fn for_tests() -> Self {
+        let events = EventBus::new(16);
+        Self {
+            events: events.clone(),
+            auth_failures: AuthFailureTracker::new(
+                crate::sftp::AuthFailureLimits {
+                    threshold: 100,
+                    per_ip_threshold: 10,
+                    per_user_threshold: 10,
+                    window: Duration::from_secs(60),
+                    ban: Duration::ZERO,
+                },
+            ),
+            stats: SftpStats::new(),
+            sessions: SessionRegistry::default(),
+            host_key: None,
+            checksum_algorithms: Vec::new().into(),
+            sparse_files: false,
+            completion: UploadCompletion::new(
+                Default::default(),
+                Vec::new().into(),
+                events,
+            ),
+            connection_hooks: ConnectionHooks::default(),
+            trash: false,
+            scratch: None,
+            filenames: FilenamePolicy::default(),
+            path_rules: PathRules::default(),
+            landing: DatedLanding::default(),
+            locks: ByteRangeLocks::default(),
+            uploads: UploadProgress::default(),
+            peer_filter: None,
+            client_versions: ClientVersions::default(),
+            tarpit: Tarpit::new(crate::sftp::tarpit::TarpitLimits {
+                enabled: false,
+                delay: Duration::from_secs(10),
+                max_connections: 0,
+            }),
+            owner_names: OwnerNames::default(),
+            disk_space: DiskGuard::new(Default::default()),
+            recordings: None,
+            access_hours: None,
+            accounts: None,
+            quota: None,
+            transfer_limits: Default::default(),
+            keepalive: Default::default(),
+            listing_cache: None,
+            file_io: FileIo::default(),
+            session_memory_bytes: 0,
+            session_transfers: 0,
+        }
+    }
+}
 
 // Main SFTP server structure
 #[derive(Clone)]
 pub struct SftpServer {
     // Root directory path for the SFTP server
     pub root_dir: Arc<RwLock<String>>,
-    // Optional credentials for authentication (username, password)
-    pub credentials: Arc<RwLock<Option<(String, String)>>>,
+    // Credentials accepted at login, shared with the SFTP state
+    pub credentials: SharedCredentials,
+    // Shared events, auth tracking and statistics
+    pub context: ServerContext,
 }
 
 impl SftpServer {
     // Creates a new SFTP server instance with the given root directory
-    pub fn new(root_dir: String) -> Self {
-        Self {
-            root_dir: Arc::new(RwLock::new(root_dir)),
-            credentials: Arc::new(RwLock::new(None)),
-        }
-    }
-
-    // Sets the username/password credentials to be used for authentication
-    pub async fn set_credentials(&self, username: String, password: String) {
-        info!("Setting SFTP credentials for user: {}", username);
-        let mut creds = self.credentials.write().await;
-        *creds = Some((username, password));
-    }
-
-    // Clears the stored credentials
-    #[allow(dead_code)]
-    pub async fn clear_credentials(&self) {
-        info!("Clearing SFTP credentials");
-        let mut creds = self.credentials.write().await;
-        *creds = None;
+    pub fn new(
+        root_dir: String,
+        credentials: SharedCredentials,
+        context: ServerContext,
+    ) -> Self {
+        Self { root_dir: Arc::new(RwLock::new(root_dir)), credentials, context }
     }
 
-    // Starts the SFTP server on the given address and port
+    // Serves the SFTP server on an already bound listener.
+    // While `accepting` is false the listener is closed but established
+    // sessions keep running; the server returns once the sender is dropped.
+    // A listener received on `rebind` replaces the current one, so the
+    // server moves address without dropping sessions.
     pub async fn start_server(
         self,
-        addrs: String,
-        port: u16,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let config = create_ssh_config();
+        listener: TcpListener,
+        mut accepting: watch::Receiver<bool>,
+        mut rebind: mpsc::Receiver<TcpListener>,
+    ) -> Result<()> {
+        let config = Arc::new(create_ssh_config(
+            self.context.host_key.clone(),
+            self.context.transfer_limits,
+            self.context.keepalive,
+        )?);
+        let sessions = self.context.sessions.clone();
+        let context = self.context.clone();
         let mut ssh_server = SshServerImpl::new(self);
 
-        debug!("Starting SFTP server on Addrs:{}, Port: {}", addrs, port);
+        // Bound again on the same address when accepting resumes
+        let mut local_addr =
+            listener.local_addr().map_err(|e| Error::listener("SFTP", e))?;
+        info!("Starting SFTP server on {}", local_addr);
+        let mut listener = Some(listener);
+
+        loop {
+            let Some(socket) = &listener else {
+                // Wait until accepting is switched back on
+                tokio::select! {
+                    changed = accepting.changed() => {
+                        if changed.is_err() {
+                            break;
+                        }
+                        if *accepting.borrow() {
+                            info!("Resuming SFTP listener on {}", local_addr);
+                            let resumed = TcpListener::bind(local_addr)
+                                .await
+                                .map_err(|e| Error::bind(local_addr, e))?;
+                            listener = Some(resumed);
+                        }
+                    }
+                    // Remember a new address for when accepting resumes
+                    Some(moved) = rebind.recv() => {
+                        local_addr = moved
+                            .local_addr()
+                            .map_err(|e| Error::listener("SFTP", e))?;
+                    }
+                }
+                continue;
+            };
+
+            tokio::select! {
+                accepted = socket.accept() => {
+                    let (stream, peer_addr) =
+                        accepted.map_err(|e| Error::listener("SFTP", e))?;
+                    let Some(stream) =
+                        context.unless_banned(stream, peer_addr, "")
+                    else {
+                        continue;
+                    };
+                    let check = context.check_peer(peer_addr, "sftp");
+                    if !check.allowed {
+                        continue;
+                    }
+                    let mut handler = ssh_server.new_client(Some(peer_addr));
+                    let session_id = handler.session_id();
+                    handler.set_country(check.country);
+                    handler.connected();
+                    let stream = KexTap::new(stream);
+                    handler.set_negotiation(stream.negotiation());
+                    let config = config.clone();
+                    let sessions = sessions.clone();
+
+                    tokio::spawn(async move {
+                        match russh::server::run_stream(config, stream, handler)
+                            .await
+                        {
+                            Ok(session) => {
+                                sessions.set_handle(session_id, session.handle());
+                                if let Err(e) = session.await {
+                                    if matches!(
+                                        e.downcast_ref::<russh::Error>(),
+                                        Some(russh::Error::KeepaliveTimeout)
+                                    ) {
+                                        info!("Session {} from {} dropped, client stopped answering keepalives", session_id, peer_addr);
+                                    } else {
+                                        debug!("Session {} ended with error: {}", session_id, e);
+                                    }
+                                }
+                            }
+                            Err(e) => {
+                                debug!("Connection setup from {} failed: {}", peer_addr, e);
+                            }
+                        }
+                        sessions.remove(session_id);
+                    });
+                }
+                Some(moved) = rebind.recv() => {
+                    // Dropping the old listener only stops new connections
+                    local_addr = moved
+                        .local_addr()
+                        .map_err(|e| Error::listener("SFTP", e))?;
+                    info!("SFTP listener now accepting on {}", local_addr);
+                    listener = Some(moved);
+                }
+                changed = accepting.changed() => {
+                    if changed.is_err() {
+                        break;
+                    }
+                    if !*accepting.borrow() {
+                        warn!("SFTP listener closed, no new connections accepted");
+                        listener = None;
+                    }
+                }
+            }
+        }
 
-        ssh_server.run_on_address(Arc::new(config), (addrs, port)).await?;
         info!("SFTP server has shut down");
         Ok(())
     }
 }
 
-// Create SSH server configuration
-fn create_ssh_config() -> russh::server::Config {
-    russh::server::Config {
+// Create SSH server configuration, with a random host key when none is
+// configured
+fn create_ssh_config(
+    host_key: Option<PrivateKey>,
+    limits: TransferLimits,
+    keepalive: KeepAlive,
+) -> Result<russh::server::Config> {
+    let host_key = match host_key {
+        Some(host_key) => host_key,
+        None => PrivateKey::random(&mut OsRng, ssh_key::Algorithm::Ed25519)
+            .map_err(|e| Error::HostKey(e.to_string()))?,
+    };
+
+    Ok(russh::server::Config {
         auth_rejection_time: Duration::from_secs(3),
         auth_rejection_time_initial: Some(Duration::from_secs(0)),
-        keys: vec![
-            russh::keys::PrivateKey::random(
-                &mut OsRng,
-                ssh_key::Algorithm::Ed25519,
-            )
-            .expect("Failed to generate SSH key"),
-        ],
+        keys: vec![host_key],
+        window_size: limits.window_bytes,
+        maximum_packet_size: limits.max_packet_bytes,
+        keepalive_interval: keepalive.interval,
+        keepalive_max: keepalive.max_missed,
         ..Default::default()
-    }
+    })
 }
 
 // Entry point to run the SFTP server
 // This is the main function called from the lifecycle manager
 pub async fn run_sftp_server(
+    listener: TcpListener,
     root_dir: String,
-    bind_address: String,
-    port: u16,
-    username: String,
-    password: String,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    credentials: SharedCredentials,
+    context: ServerContext,
+    accepting: watch::Receiver<bool>,
+    rebind: mpsc::Receiver<TcpListener>,
+) -> Result<()> {
     info!("Initializing SFTP server with root directory: {}", root_dir);
+    // No session of this server has a scratch area yet
+    if context.scratch.is_some()
+        && let Err(e) = ScratchArea::purge_all(Path::new(&root_dir)).await
+    {
+        warn!("Failed to remove old scratch areas of {}: {}", root_dir, e);
+    }
+
+    let sftp_server = SftpServer::new(root_dir, credentials, context);
+    sftp_server.start_server(listener, accepting, rebind).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sftp::credentials::NoCredentials;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // Serves a server with test settings on an ephemeral port of localhost
+    async fn serve() -> (
+        SocketAddr,
+        watch::Sender<bool>,
+        mpsc::Sender<TcpListener>,
+        tokio::task::JoinHandle<Result<()>>,
+    ) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (accepting, accepting_rx) = watch::channel(true);
+        let (rebind, rebind_rx) = mpsc::channel(1);
+        let server = SftpServer::new(
+            std::env::temp_dir().to_string_lossy().to_string(),
+            Arc::new(NoCredentials),
+            ServerContext::for_tests(),
+        );
+        let task = tokio::spawn(server.start_server(
+            listener,
+            accepting_rx,
+            rebind_rx,
+        ));
+        (addr, accepting, rebind, task)
+    }
 
-    let sftp_server = SftpServer::new(root_dir);
-    sftp_server.set_credentials(username, password).await;
+    // Whether connections to the address are refused within two seconds
+    async fn refused(addr: SocketAddr) -> bool {
+        for _ in 0..20 {
+            if TcpStream::connect(addr).await.is_err() {
+                return true;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        false
+    }
 
-    info!("Starting SFTP server on {}:{}", bind_address, port);
-    sftp_server.start_server(bind_address, port).await?;
+    #[tokio::test]
+    async fn test_draining_closes_the_listener_until_resumed() {
+        let (addr, accepting, _rebind, task) = serve().await;
+        assert!(TcpStream::connect(addr).await.is_ok());
 
-    Ok(())
+        accepting.send(false).unwrap();
+        assert!(refused(addr).await);
+
+        accepting.send(true).unwrap();
+        let mut resumed = false;
+        for _ in 0..20 {
+            if TcpStream::connect(addr).await.is_ok() {
+                resumed = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert!(resumed);
+
+        // Dropping the switch ends the server
+        drop(accepting);
+        let ended = tokio::time::timeout(Duration::from_secs(2), task).await;
+        assert!(matches!(ended, Ok(Ok(Ok(())))));
+    }
+
+    #[tokio::test]
+    async fn test_rebinding_moves_the_listener_and_keeps_sessions() {
+        let (old_addr, _accepting, rebind, _task) = serve().await;
+        let mut established = TcpStream::connect(old_addr).await.unwrap();
+        // The server greets once it accepted the connection
+        let mut banner = [0u8; 8];
+        established.read_exact(&mut banner).await.unwrap();
+        assert_eq!(&banner, b"SSH-2.0-");
+
+        let moved = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let new_addr = moved.local_addr().unwrap();
+        rebind.send(moved).await.unwrap();
+
+        assert!(refused(old_addr).await);
+        assert!(TcpStream::connect(new_addr).await.is_ok());
+
+        // The session accepted on the old address is still served
+        established.write_all(b"SSH-2.0-test\r\n").await.unwrap();
+        let mut rest = [0u8; 64];
+        let read = tokio::time::timeout(
+            Duration::from_secs(2),
+            established.read(&mut rest),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert!(read > 0);
+    }
 }
@@ -1,13 +1,18 @@
+use crate::events::Event;
 use crate::sftp::handler::SftpSession;
+use crate::sftp::hooks::{ConnectionEvent, ConnectionInfo};
+use crate::sftp::negotiation::Negotiation;
+use crate::sftp::recording::{RecordedSession, Recorder};
 use crate::sftp::server::SftpServer;
+use crate::sftp::transfer_slots::TransferSlots;
 use russh::keys::ssh_key;
 use russh::server::{Auth, Msg, Session};
-use russh::{Channel, ChannelId};
+use russh::{Channel, ChannelId, Disconnect};
 use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use tracing::{debug, info, warn};
+use tracing::{debug, error, info, warn};
 
 /// Implements SSH server using russh
 #[derive(Clone)]
@@ -25,8 +30,9 @@ impl SshServerImpl {
 impl russh::server::Server for SshServerImpl {
     type Handler = SshSession;
 
-    fn new_client(&mut self, _addr: Option<SocketAddr>) -> Self::Handler {
-        SshSession::new(self.sftp_server.clone())
+    fn new_client(&mut self, addr: Option<SocketAddr>) -> Self::Handler {
+        let session_id = self.sftp_server.context.sessions.register(addr);
+        SshSession::new(self.sftp_server.clone(), session_id, addr)
     }
 }
 
@@ -36,12 +42,147 @@ pub struct SshSession {
     clients: Arc<Mutex<HashMap<ChannelId, Channel<Msg>>>>,
     /// Reference to the parent SFTP server
     sftp_server: SftpServer,
+    /// Username of the authenticated user, once authentication succeeded
+    username: Option<String>,
+    /// ID of this session in the session registry
+    session_id: u64,
+    /// Remote address of the client
+    peer_addr: Option<SocketAddr>,
+    /// Country the client connects from, when looked up
+    country: Option<String>,
+    /// How the user authenticated, once accepted
+    auth_method: Option<&'static str>,
+    /// Algorithms agreed on with the client
+    negotiation: Negotiation,
+    /// SSH identification string of the client, once authenticated
+    client: Option<String>,
+    /// Files being streamed, shared by the SFTP channels
+    transfers: TransferSlots,
 }
 
 impl SshSession {
     /// Create a new SSH session
-    pub fn new(sftp_server: SftpServer) -> Self {
-        Self { clients: Arc::new(Mutex::new(HashMap::new())), sftp_server }
+    pub fn new(
+        sftp_server: SftpServer,
+        session_id: u64,
+        peer_addr: Option<SocketAddr>,
+    ) -> Self {
+        let transfers =
+            TransferSlots::new(sftp_server.context.session_transfers);
+        Self {
+            clients: Arc::new(Mutex::new(HashMap::new())),
+            sftp_server,
+            username: None,
+            session_id,
+            peer_addr,
+            country: None,
+            auth_method: None,
+            negotiation: Negotiation::default(),
+            client: None,
+            transfers,
+        }
+    }
+
+    /// ID of this session in the session registry
+    pub fn session_id(&self) -> u64 {
+        self.session_id
+    }
+
+    /// Opens the recording of this session if its user's sessions are
+    /// recorded. A session is not refused when recording fails.
+    async fn start_recording(&self, username: &str) -> Option<Recorder> {
+        let policy = self.sftp_server.context.recordings.as_ref()?;
+        if !policy.records(username) {
+            return None;
+        }
+        match policy.start(self.session_id, username, self.peer_addr).await {
+            Ok(recorder) => {
+                info!("Recording SFTP session {}", self.session_id);
+                Some(recorder)
+            }
+            Err(e) => {
+                error!(
+                    "Failed to start recording session {}: {}",
+                    self.session_id, e
+                );
+                None
+            }
+        }
+    }
+
+    /// Records the country of the client, for the registry and login events
+    pub fn set_country(&mut self, country: Option<String>) {
+        if let Some(country) = &country {
+            self.sftp_server
+                .context
+                .sessions
+                .set_country(self.session_id, country);
+        }
+        self.country = country;
+    }
+
+    /// Where the algorithms negotiated on the connection turn up
+    pub fn set_negotiation(&mut self, negotiation: Negotiation) {
+        self.negotiation = negotiation;
+    }
+
+    /// Tells the connection hooks the client has connected
+    pub fn connected(&self) {
+        self.notify_hooks(self.hook_info(ConnectionEvent::Connected));
+    }
+
+    /// What the connection hooks are told about `event` of this session
+    fn hook_info(&self, event: ConnectionEvent) -> ConnectionInfo {
+        ConnectionInfo {
+            event,
+            session_id: self.session_id,
+            peer: self.peer_addr,
+            country: self.country.clone(),
+            username: self.username.clone(),
+            auth_method: self.auth_method,
+            client: self.client.clone(),
+            reason: None,
+        }
+    }
+
+    /// Lets `user` in with valid credentials when within their access
+    /// windows
+    async fn accept_login(&mut self, user: &str, method: &'static str) -> Auth {
+        if !self
+            .sftp_server
+            .context
+            .within_access_hours(user, self.peer_addr, "sftp")
+            .await
+        {
+            self.auth_rejected(user, method, "access_hours");
+            return Auth::Reject {
+                proceed_with_methods: None,
+                partial_success: false,
+            };
+        }
+        info!("Authentication successful for user: {}", user);
+        self.username = Some(user.to_string());
+        self.auth_method = Some(method);
+        Auth::Accept
+    }
+
+    /// Tells the connection hooks a login attempt of `user` was refused
+    fn auth_rejected(
+        &self,
+        user: &str,
+        method: &'static str,
+        reason: &'static str,
+    ) {
+        self.notify_hooks(ConnectionInfo {
+            username: Some(user.to_string()),
+            auth_method: Some(method),
+            reason: Some(reason),
+            ..self.hook_info(ConnectionEvent::AuthRejected)
+        });
+    }
+
+    fn notify_hooks(&self, info: ConnectionInfo) {
+        self.sftp_server.context.connection_hooks.notify(info);
     }
 
     /// Retrieves and removes a channel by ID from active clients
@@ -51,6 +192,13 @@ impl SshSession {
     }
 }
 
+impl Drop for SshSession {
+    /// The handler goes with the connection, however it ended
+    fn drop(&mut self) {
+        self.notify_hooks(self.hook_info(ConnectionEvent::Disconnected));
+    }
+}
+
 impl russh::server::Handler for SshSession {
     type Error = anyhow::Error;
 
@@ -62,26 +210,121 @@ impl russh::server::Handler for SshSession {
     ) -> Result<Auth, Self::Error> {
         info!("Auth attempt with password: user={}", user);
 
-        let credentials = self.sftp_server.credentials.read().await;
-        if let Some((username, pass)) = &*credentials
-            && username == user
-            && pass == password
+        // Read at every attempt so rotated credentials apply immediately
+        let generated = self
+            .sftp_server
+            .credentials
+            .load()
+            .as_deref()
+            .is_some_and(|c| c.matches(user, password));
+        if generated
+            || self
+                .sftp_server
+                .context
+                .account_password_matches(user, password)
+                .await
         {
-            info!("Authentication successful for user: {}", user);
-            return Ok(Auth::Accept);
+            return Ok(self.accept_login(user, "password").await);
         }
 
         warn!("Authentication failed for user: {}", user);
+        self.sftp_server.context.login_failed(
+            user,
+            self.peer_addr,
+            self.country.as_deref(),
+        );
+        self.auth_rejected(user, "password", "invalid_credentials");
+
         Ok(Auth::Reject { proceed_with_methods: None, partial_success: false })
     }
 
+    /// Checks the client software once the credentials are accepted, the
+    /// first point at which its identification string is available, and
+    /// records the login
+    async fn auth_succeeded(
+        &mut self,
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        let context = &self.sftp_server.context;
+        let username = self.username.clone().unwrap_or_default();
+        let client =
+            String::from_utf8_lossy(session.remote_sshid()).into_owned();
+        info!("Session {} client software: {}", self.session_id, client);
+        context.sessions.set_client(self.session_id, &client);
+        self.client = Some(client.clone());
+
+        if !context.client_versions.allows(&client) {
+            warn!(
+                "Refusing client {} of user {} from {:?}",
+                client, username, self.peer_addr
+            );
+            context.events.publish(Event::ClientRejected {
+                username,
+                peer: self.peer_addr.map(|a| a.to_string()),
+                client,
+            });
+            self.notify_hooks(ConnectionInfo {
+                reason: Some("client_software"),
+                ..self.hook_info(ConnectionEvent::AuthRejected)
+            });
+            self.username = None;
+            session.disconnect(
+                Disconnect::ByApplication,
+                "Client software not allowed",
+                "",
+            )?;
+            return Ok(());
+        }
+
+        let auth_method = self.auth_method.unwrap_or("unknown");
+        let negotiated = self.negotiation.get();
+        if let Some(n) = &negotiated {
+            info!(
+                "Session {} uses kex {}, host key {}, cipher {}, mac {}",
+                self.session_id,
+                n.kex,
+                n.host_key,
+                n.cipher,
+                n.mac.as_deref().unwrap_or("implicit")
+            );
+        }
+        context.stats.record_session(&username);
+        context.sessions.set_username(self.session_id, &username);
+        context.sessions.set_login(
+            self.session_id,
+            auth_method,
+            negotiated.clone(),
+        );
+        context.events.publish(Event::LoginSucceeded {
+            username,
+            peer: self.peer_addr.map(|a| a.to_string()),
+            country: self.country.clone(),
+            client: Some(client),
+            auth_method: auth_method.to_string(),
+            negotiated,
+        });
+        self.notify_hooks(self.hook_info(ConnectionEvent::AuthAccepted));
+        Ok(())
+    }
+
     /// Disables public key authentication
     async fn auth_publickey(
         &mut self,
         user: &str,
-        _public_key: &ssh_key::PublicKey,
+        public_key: &ssh_key::PublicKey,
     ) -> Result<Auth, Self::Error> {
-        info!("Public key authentication attempt by {}, rejecting", user);
+        info!("Auth attempt with public key: user={}", user);
+        if self
+            .sftp_server
+            .context
+            .account_key_authorized(user, public_key)
+            .await
+        {
+            return Ok(self.accept_login(user, "publickey").await);
+        }
+        // Clients try every key they have, so unknown keys are not counted
+        // as failed logins
+        debug!("Public key not authorized for user: {}", user);
         Ok(Auth::Reject { proceed_with_methods: None, partial_success: false })
     }
 
@@ -102,6 +345,10 @@ impl russh::server::Handler for SshSession {
         channel: Channel<Msg>,
         _session: &mut Session,
     ) -> Result<bool, Self::Error> {
+        // Refused clients are on their way out
+        if self.username.is_none() {
+            return Ok(false);
+        }
         debug!("Channel session opened: {:?}", channel.id());
         let mut clients = self.clients.lock().await;
         clients.insert(channel.id(), channel);
@@ -124,8 +371,24 @@ impl russh::server::Handler for SshSession {
             session.channel_success(channel_id)?;
             info!("Starting SFTP subsystem with root directory: {}", root_dir);
 
-            let sftp = SftpSession::new(root_dir);
-            russh_sftp::server::run(channel.into_stream(), sftp).await;
+            let username = self.username.clone().unwrap_or_default();
+            let recorder = self.start_recording(&username).await;
+            let sftp = SftpSession::new(
+                root_dir,
+                username,
+                self.sftp_server.context.clone(),
+                self.session_id,
+                self.peer_addr,
+                self.transfers.clone(),
+            );
+            let stream = channel.into_stream();
+            match recorder {
+                Some(recorder) => {
+                    let sftp = RecordedSession::new(sftp, recorder);
+                    russh_sftp::server::run(stream, sftp).await;
+                }
+                None => russh_sftp::server::run(stream, sftp).await,
+            }
         } else {
             warn!("Unsupported subsystem requested: {}", name);
             session.channel_failure(channel_id)?;
@@ -134,3 +397,80 @@ impl russh::server::Handler for SshSession {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sftp::access_hours::AccessHours;
+    use crate::sftp::accounts::AccountLogins;
+    use crate::sftp::credentials::NoCredentials;
+    use crate::sftp::server::ServerContext;
+    use async_trait::async_trait;
+    use chrono::{DateTime, Utc};
+    use russh::server::Handler;
+
+    /// alice logs in with "secret", but only while `open`
+    struct Alice {
+        open: bool,
+    }
+
+    #[async_trait]
+    impl AccountLogins for Alice {
+        async fn password_matches(
+            &self,
+            username: &str,
+            password: &str,
+        ) -> anyhow::Result<bool> {
+            Ok(username == "alice" && password == "secret")
+        }
+
+        async fn key_authorized(
+            &self,
+            _username: &str,
+            _key: &ssh_key::PublicKey,
+        ) -> anyhow::Result<bool> {
+            Ok(false)
+        }
+    }
+
+    #[async_trait]
+    impl AccessHours for Alice {
+        async fn allows(
+            &self,
+            _username: &str,
+            _time: DateTime<Utc>,
+        ) -> anyhow::Result<bool> {
+            Ok(self.open)
+        }
+    }
+
+    fn ssh_session(open: bool) -> SshSession {
+        let alice = Arc::new(Alice { open });
+        let context = ServerContext {
+            accounts: Some(alice.clone()),
+            access_hours: Some(alice),
+            ..ServerContext::for_tests()
+        };
+        let server = SftpServer::new(
+            "/tmp".to_string(),
+            Arc::new(NoCredentials),
+            context,
+        );
+        SshSession::new(server, 1, None)
+    }
+
+    #[tokio::test]
+    async fn test_account_logins_keep_to_the_access_windows() {
+        let mut within = ssh_session(true);
+        let auth = within.auth_password("alice", "secret").await.unwrap();
+        assert!(matches!(auth, Auth::Accept));
+        assert_eq!(within.username.as_deref(), Some("alice"));
+        let wrong = ssh_session(true).auth_password("alice", "guess").await;
+        assert!(matches!(wrong.unwrap(), Auth::Reject { .. }));
+
+        let mut outside = ssh_session(false);
+        let auth = outside.auth_password("alice", "secret").await.unwrap();
+        assert!(matches!(auth, Auth::Reject { .. }));
+        assert_eq!(outside.username, None);
+    }
+}
@@ -0,0 +1,143 @@
+use crate::sftp::file_io::{DirectFile, FileIo};
+use std::io::{self, SeekFrom};
+use std::ops::Range;
+use tokio::fs::File;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+/// Granularity of holes; zero runs shorter than this are written out
+pub const BLOCK_SIZE: u64 = 4096;
+
+/// Writes `data` at `offset`. With `sparse`, aligned blocks of zeros past
+/// the current end of the file are skipped and the file extended instead,
+/// so the filesystem leaves holes rather than allocating them. Zeros over
+/// existing data are always written.
+pub async fn write_at(
+    file: &mut File,
+    offset: u64,
+    data: &[u8],
+    sparse: bool,
+) -> io::Result<()> {
+    if !sparse || (data.len() as u64) < BLOCK_SIZE {
+        return write_run(file, offset, data).await;
+    }
+
+    let len = file.metadata().await?.len();
+    let runs = data_runs(offset, data, len);
+    for run in &runs {
+        write_run(file, offset + run.start as u64, &data[run.clone()]).await?;
+    }
+    if ends_in_hole(&runs, data) {
+        file.set_len(offset + data.len() as u64).await?;
+    }
+    Ok(())
+}
+
+/// Like `write_at`, through io_uring. Returns `data` for the checksums.
+pub async fn write_direct(
+    io: &FileIo,
+    direct: &DirectFile,
+    file: &File,
+    offset: u64,
+    mut data: Vec<u8>,
+    sparse: bool,
+) -> io::Result<Vec<u8>> {
+    if !sparse || (data.len() as u64) < BLOCK_SIZE {
+        let all = 0..data.len();
+        return io.write_at(direct, offset, data, all).await;
+    }
+
+    let len = file.metadata().await?.len();
+    let runs = data_runs(offset, &data, len);
+    for run in &runs {
+        data = io.write_at(direct, offset, data, run.clone()).await?;
+    }
+    if ends_in_hole(&runs, &data) {
+        file.set_len(offset + data.len() as u64).await?;
+    }
+    Ok(data)
+}
+
+/// Parts of `data`, written at `offset` to a file of `len` bytes, that are
+/// not aligned blocks of zeros past its end
+fn data_runs(offset: u64, data: &[u8], len: u64) -> Vec<Range<usize>> {
+    let mut runs = Vec::new();
+    let mut run_start = 0;
+    let mut index = 0;
+    while index < data.len() {
+        let position = offset + index as u64;
+        let block_end = ((position / BLOCK_SIZE + 1) * BLOCK_SIZE - offset)
+            .min(data.len() as u64) as usize;
+        let block = &data[index..block_end];
+        if position >= len
+            && block.len() as u64 == BLOCK_SIZE
+            && block.iter().all(|b| *b == 0)
+        {
+            if run_start < index {
+                runs.push(run_start..index);
+            }
+            run_start = block_end;
+        }
+        index = block_end;
+    }
+    if run_start < data.len() {
+        runs.push(run_start..data.len());
+    }
+    runs
+}
+
+/// Whether the data ended in a hole, which leaves the file to be extended
+fn ends_in_hole(runs: &[Range<usize>], data: &[u8]) -> bool {
+    runs.last().is_none_or(|run| run.end < data.len())
+}
+
+async fn write_run(
+    file: &mut File,
+    offset: u64,
+    data: &[u8],
+) -> io::Result<()> {
+    file.seek(SeekFrom::Start(offset)).await?;
+    file.write_all(data).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::MetadataExt;
+
+    #[tokio::test]
+    async fn test_zero_blocks_become_holes() {
+        let dir = std::env::temp_dir()
+            .join(format!("sftp-manager-sparse-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("disk.img");
+        let mut file = File::create(&path).await.unwrap();
+        let zeros = vec![0; 1 << 20];
+        write_at(&mut file, 0, b"head", true).await.unwrap();
+        write_at(&mut file, 4, &zeros, true).await.unwrap();
+        write_at(&mut file, 4 + zeros.len() as u64, &zeros, true)
+            .await
+            .unwrap();
+        file.flush().await.unwrap();
+
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(metadata.len(), 4 + 2 * zeros.len() as u64);
+        assert!(metadata.blocks() * 512 < zeros.len() as u64);
+        let content = std::fs::read(&path).unwrap();
+        assert_eq!(&content[..4], b"head");
+        assert!(content[4..].iter().all(|b| *b == 0));
+
+        // Zeros over existing data are written
+        write_at(&mut file, 0, &[0; 4], true).await.unwrap();
+        file.flush().await.unwrap();
+        assert_eq!(&std::fs::read(&path).unwrap()[..4], &[0; 4]);
+
+        let plain = dir.join("plain.img");
+        let mut file = File::create(&plain).await.unwrap();
+        write_at(&mut file, 0, &zeros, false).await.unwrap();
+        file.flush().await.unwrap();
+        let metadata = std::fs::metadata(&plain).unwrap();
+        assert!(metadata.blocks() * 512 >= zeros.len() as u64);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
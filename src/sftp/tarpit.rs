@@ -0,0 +1,140 @@
+use crate::stats::SftpStats;
+use rand::RngExt;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tracing::{debug, info};
+
+/// Longest a client is held, in case it never gives up
+const MAX_HOLD: Duration = Duration::from_secs(3600);
+
+/// How banned clients are treated
+#[derive(Debug, Clone, Copy)]
+pub struct TarpitLimits {
+    /// Hold banned clients instead of closing their connections
+    pub enabled: bool,
+    /// Pause between the lines sent to a held client
+    pub delay: Duration,
+    /// Clients held at once; further ones are closed right away
+    pub max_connections: usize,
+}
+
+/// Keeps connections from banned clients open while trickling out junk,
+/// so scanners waste their time instead of moving on to the next host
+#[derive(Clone)]
+pub struct Tarpit {
+    limits: Arc<Mutex<TarpitLimits>>,
+    held: Arc<AtomicUsize>,
+}
+
+impl Tarpit {
+    pub fn new(limits: TarpitLimits) -> Self {
+        Self {
+            limits: Arc::new(Mutex::new(limits)),
+            held: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Changes the limits; clients already held keep their delay
+    pub fn reconfigure(&self, limits: TarpitLimits) {
+        *self.limits.lock().unwrap() = limits;
+    }
+
+    /// Takes over the connection of a banned client. It is closed at once
+    /// when the tarpit is disabled or full. Each line starts with `prefix`,
+    /// e.g. "220-" so FTP clients read a never-ending greeting; SSH clients
+    /// skip lines sent before the server's version.
+    pub fn hold(
+        &self,
+        mut stream: TcpStream,
+        peer: SocketAddr,
+        prefix: &'static str,
+        stats: &SftpStats,
+    ) {
+        let limits = *self.limits.lock().unwrap();
+        let held = self.held.fetch_add(1, Ordering::Relaxed);
+        if !limits.enabled || held >= limits.max_connections {
+            self.held.fetch_sub(1, Ordering::Relaxed);
+            debug!("Refused connection from banned client {}", peer);
+            return;
+        }
+
+        info!("Holding banned client {} in the tarpit", peer);
+        stats.tarpit_entered();
+        let stats = stats.clone();
+        let tarpit = self.clone();
+        tokio::spawn(async move {
+            let trickle = async {
+                loop {
+                    tokio::time::sleep(limits.delay).await;
+                    let junk = rand::rng().random::<u64>();
+                    let line = format!("{}{:x}\r\n", prefix, junk);
+                    if stream.write_all(line.as_bytes()).await.is_err() {
+                        break;
+                    }
+                }
+            };
+            let _ = tokio::time::timeout(MAX_HOLD, trickle).await;
+            debug!("Released {} from the tarpit", peer);
+            stats.tarpit_left();
+            tarpit.held.fetch_sub(1, Ordering::Relaxed);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+    use tokio::net::TcpListener;
+
+    /// A connected pair: the client side and the accepted server side
+    async fn connection(listener: &TcpListener) -> (TcpStream, TcpStream) {
+        let client = TcpStream::connect(listener.local_addr().unwrap());
+        let (client, accepted) = tokio::join!(client, listener.accept());
+        (client.unwrap(), accepted.unwrap().0)
+    }
+
+    #[tokio::test]
+    async fn test_banned_clients_are_held_until_the_tarpit_is_full() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let peer = listener.local_addr().unwrap();
+        let stats = SftpStats::new();
+        let tarpit = Tarpit::new(TarpitLimits {
+            enabled: true,
+            delay: Duration::from_millis(20),
+            max_connections: 1,
+        });
+
+        let (held, stream) = connection(&listener).await;
+        tarpit.hold(stream, peer, "220-", &stats);
+        let mut held = BufReader::new(held);
+        let mut line = String::new();
+        held.read_line(&mut line).await.unwrap();
+        assert!(line.starts_with("220-") && line.ends_with("\r\n"));
+
+        // No room for a second client, its connection is closed
+        let (mut refused, stream) = connection(&listener).await;
+        tarpit.hold(stream, peer, "220-", &stats);
+        assert_eq!(refused.read(&mut [0u8; 16]).await.unwrap(), 0);
+
+        let snapshot = stats.snapshot(None);
+        assert_eq!(snapshot.totals.tarpitted, 1);
+        assert_eq!(snapshot.tarpitted_now, 1);
+
+        // Released once the client hangs up
+        drop(held);
+        let mut released = false;
+        for _ in 0..100 {
+            if stats.snapshot(None).tarpitted_now == 0 {
+                released = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(released);
+    }
+}
@@ -0,0 +1,100 @@
+use std::pin::pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use tracing::debug;
+
+/// Files one SSH session may stream at once, across all of its SFTP
+/// channels. A file handle takes a slot at its first read or write and
+/// holds it until it reaches the end of the file or is closed, so a client
+/// with many transfers open at once cannot keep the disk from the others
+/// on the instance.
+///
+/// A channel serves its requests one after another, so a handle waiting
+/// for a slot held by another handle of its own channel would wait
+/// forever, and two channels each waiting for the other's slots would
+/// too. Beyond the limit, a channel holding slots hands one over from its
+/// least recently used handle instead; only channels holding none wait.
+#[derive(Debug, Clone, Default)]
+pub struct TransferSlots {
+    /// None when the session's transfers are not limited
+    slots: Option<Arc<Slots>>,
+    /// Numbers the session's SFTP channels
+    channels: Arc<AtomicU64>,
+}
+
+#[derive(Debug)]
+struct Slots {
+    max: usize,
+    /// Channel of every slot taken
+    taken: Mutex<Vec<u64>>,
+    freed: Notify,
+}
+
+/// A slot taken for one file handle, given back when dropped
+#[derive(Debug)]
+pub struct TransferSlot {
+    slots: Option<Arc<Slots>>,
+    channel: u64,
+}
+
+impl TransferSlots {
+    /// Slots for `max` transfers at once; 0 is unlimited
+    pub fn new(max: usize) -> Self {
+        let slots = (max > 0).then(|| {
+            Arc::new(Slots {
+                max,
+                taken: Mutex::new(Vec::new()),
+                freed: Notify::new(),
+            })
+        });
+        Self { slots, channels: Arc::default() }
+    }
+
+    /// Number for a new SFTP channel of the session
+    pub fn channel(&self) -> u64 {
+        self.channels.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// A free slot for a handle of `channel`, waiting while other channels
+    /// hold all of them; None when none is free and `channel` holds some,
+    /// to be taken over from one of its handles
+    pub async fn acquire(&self, channel: u64) -> Option<TransferSlot> {
+        let Some(slots) = &self.slots else {
+            return Some(TransferSlot { slots: None, channel });
+        };
+        loop {
+            let mut freed = pin!(slots.freed.notified());
+            {
+                let mut taken = slots.taken.lock().unwrap();
+                if taken.len() < slots.max {
+                    taken.push(channel);
+                    return Some(TransferSlot {
+                        slots: Some(slots.clone()),
+                        channel,
+                    });
+                }
+                if taken.contains(&channel) {
+                    return None;
+                }
+                // Registered before unlocking so no release is missed
+                freed.as_mut().enable();
+            }
+            debug!("All transfer slots of the session are taken, queueing");
+            freed.await;
+        }
+    }
+}
+
+impl Drop for TransferSlot {
+    fn drop(&mut self) {
+        let Some(slots) = &self.slots else {
+            return;
+        };
+        let mut taken = slots.taken.lock().unwrap();
+        if let Some(i) = taken.iter().position(|c| *c == self.channel) {
+            taken.swap_remove(i);
+        }
+        slots.freed.notify_waiters();
+    }
+}
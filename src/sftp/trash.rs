@@ -0,0 +1,193 @@
+use chrono::{DateTime, Utc};
+use rand::RngExt;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::path::{Component, Path, PathBuf};
+use tokio::fs;
+use tokio::io;
+
+/// Hidden directory below the SFTP root that holds deleted items
+pub const TRASH_DIR: &str = ".trash";
+
+/// Description of a deleted item, stored next to it in the trash
+const ENTRY_FILE: &str = "entry.json";
+/// Name of the deleted file or directory inside its trash entry
+const DATA_NAME: &str = "data";
+
+/// An item moved to the trash
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashEntry {
+    pub id: String,
+    /// Path the item had, as seen by clients
+    pub path: String,
+    pub is_dir: bool,
+    pub deleted_by: String,
+    pub deleted_at: DateTime<Utc>,
+}
+
+/// Trash area of an SFTP root. Every deleted item gets its own directory
+/// so items deleted from the same path do not overwrite each other.
+pub struct Trash {
+    root_dir: PathBuf,
+}
+
+impl Trash {
+    pub fn new(root_dir: impl Into<PathBuf>) -> Self {
+        Self { root_dir: root_dir.into() }
+    }
+
+    /// Whether a path relative to the root lies inside the trash
+    pub fn contains(relative: &Path) -> bool {
+        relative
+            .components()
+            .find(|c| !matches!(c, Component::RootDir))
+            .is_some_and(|c| c.as_os_str() == TRASH_DIR)
+    }
+
+    fn dir(&self) -> PathBuf {
+        self.root_dir.join(TRASH_DIR)
+    }
+
+    /// Moves a file or directory into the trash
+    pub async fn put(
+        &self,
+        local_path: &Path,
+        path: &str,
+        deleted_by: &str,
+    ) -> io::Result<TrashEntry> {
+        let deleted_at = Utc::now();
+        let suffix: u32 = rand::rng().random();
+        let entry = TrashEntry {
+            id: format!(
+                "{}-{:08x}",
+                deleted_at.format("%Y%m%dT%H%M%S"),
+                suffix
+            ),
+            path: path.to_string(),
+            is_dir: fs::symlink_metadata(local_path).await?.is_dir(),
+            deleted_by: deleted_by.to_string(),
+            deleted_at,
+        };
+
+        let entry_dir = self.dir().join(&entry.id);
+        fs::create_dir_all(&entry_dir).await?;
+        fs::write(entry_dir.join(ENTRY_FILE), serde_json::to_vec(&entry)?)
+            .await?;
+        if let Err(e) = fs::rename(local_path, entry_dir.join(DATA_NAME)).await
+        {
+            let _ = fs::remove_dir_all(&entry_dir).await;
+            return Err(e);
+        }
+        Ok(entry)
+    }
+
+    /// Items in the trash, most recently deleted first
+    pub async fn list(&self) -> io::Result<Vec<TrashEntry>> {
+        let mut entries = Vec::new();
+        let mut dir = match fs::read_dir(self.dir()).await {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(entries);
+            }
+            Err(e) => return Err(e),
+        };
+        while let Some(item) = dir.next_entry().await? {
+            // Skip entries that were only partly written
+            if let Ok(entry) = read_entry(&item.path()).await {
+                entries.push(entry);
+            }
+        }
+        entries.sort_by_key(|e| Reverse(e.deleted_at));
+        Ok(entries)
+    }
+
+    /// Moves an item back to its original path, which must be free
+    pub async fn restore(&self, id: &str) -> io::Result<TrashEntry> {
+        if id.is_empty() || id.contains(['/', '\\']) || id.starts_with('.') {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid trash entry id",
+            ));
+        }
+        let entry_dir = self.dir().join(id);
+        let entry = read_entry(&entry_dir).await?;
+
+        let target = self.root_dir.join(entry.path.trim_start_matches('/'));
+        if fs::symlink_metadata(&target).await.is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", entry.path),
+            ));
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::rename(entry_dir.join(DATA_NAME), &target).await?;
+        fs::remove_dir_all(&entry_dir).await?;
+        Ok(entry)
+    }
+
+    /// Permanently deletes items deleted before the given time
+    pub async fn purge(
+        &self,
+        deleted_before: DateTime<Utc>,
+    ) -> io::Result<Vec<TrashEntry>> {
+        self.purge_where(|entry| entry.deleted_at < deleted_before).await
+    }
+
+    /// Permanently deletes the items matching `filter`
+    pub async fn purge_where(
+        &self,
+        filter: impl Fn(&TrashEntry) -> bool,
+    ) -> io::Result<Vec<TrashEntry>> {
+        let mut purged = Vec::new();
+        for entry in self.list().await? {
+            if filter(&entry) {
+                fs::remove_dir_all(self.dir().join(&entry.id)).await?;
+                purged.push(entry);
+            }
+        }
+        Ok(purged)
+    }
+}
+
+async fn read_entry(entry_dir: &Path) -> io::Result<TrashEntry> {
+    let data = fs::read(entry_dir.join(ENTRY_FILE)).await?;
+    Ok(serde_json::from_slice(&data)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_put_and_restore() {
+        let root = std::env::temp_dir()
+            .join(format!("sftp-manager-trash-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("in")).unwrap();
+        std::fs::write(root.join("in/a.csv"), b"a").unwrap();
+
+        let trash = Trash::new(&root);
+        let entry = trash
+            .put(&root.join("in/a.csv"), "/in/a.csv", "bob")
+            .await
+            .unwrap();
+        assert!(!root.join("in/a.csv").exists());
+        assert_eq!(trash.list().await.unwrap().len(), 1);
+
+        // The original path is taken again
+        std::fs::write(root.join("in/a.csv"), b"b").unwrap();
+        let err = trash.restore(&entry.id).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+
+        std::fs::remove_file(root.join("in/a.csv")).unwrap();
+        trash.restore(&entry.id).await.unwrap();
+        assert_eq!(std::fs::read(root.join("in/a.csv")).unwrap(), b"a");
+        assert!(trash.list().await.unwrap().is_empty());
+
+        assert!(Trash::contains(Path::new("/.trash/x")));
+        assert!(!Trash::contains(Path::new("in/.trash")));
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
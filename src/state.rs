@@ -1,10 +1,49 @@
+use crate::config::settings::Settings;
+use crate::services::accounts::AccountService;
+use crate::services::audit::AuditService;
+use crate::services::backup::BackupService;
+use crate::services::cluster::ClusterService;
+use crate::services::config_reload::ConfigReloader;
+use crate::services::disk_usage::DiskUsageService;
+use crate::services::extract::ExtractService;
+use crate::services::file_index::FileIndex;
+use crate::services::file_tags::FileTagService;
+use crate::services::files::FileService;
+use crate::services::integrity::IntegrityService;
+use crate::services::journal::JournalService;
+use crate::services::mirror::MirrorService;
+use crate::services::privacy::PrivacyService;
 use crate::services::sftp_service::SftpService;
+use crate::services::supervisor::SftpSupervisor;
+use crate::services::tenants::TenantService;
+use crate::services::tus::TusService;
+use crate::services::upload_limits::UploadLimiter;
 use chrono::{DateTime, Utc};
 use std::sync::Arc;
+use tokio::sync::watch;
 
 // Application state containing SFTP service
 #[derive(Clone)]
 pub struct AppState {
     pub sftp_service: Arc<SftpService>,
+    pub supervisor: Arc<SftpSupervisor>,
+    pub disk_usage: Arc<DiskUsageService>,
+    pub audit: Arc<AuditService>,
+    pub files: Arc<FileService>,
+    pub file_tags: Arc<FileTagService>,
+    pub tus: Arc<TusService>,
+    pub uploads: Arc<UploadLimiter>,
+    pub file_index: Arc<FileIndex>,
+    pub integrity: Arc<IntegrityService>,
+    pub extract: Arc<ExtractService>,
+    pub accounts: Arc<AccountService>,
+    pub tenants: Arc<TenantService>,
+    pub privacy: Arc<PrivacyService>,
+    pub cluster: Arc<ClusterService>,
+    pub journal: Arc<JournalService>,
+    pub mirror: Arc<MirrorService>,
+    pub backup: Arc<BackupService>,
+    pub settings: watch::Receiver<Settings>,
+    pub config_reloader: Arc<ConfigReloader>,
     pub uptime: DateTime<Utc>,
 }
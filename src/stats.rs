@@ -0,0 +1,270 @@
+use crate::sftp::DiskStatus;
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+// Hourly buckets are kept for one week
+const BUCKET_SECS: i64 = 3600;
+const MAX_BUCKETS: usize = 24 * 7;
+
+// Cumulative usage counters
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Counters {
+    pub sessions: u64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub failed_logins: u64,
+    pub files_uploaded: u64,
+    pub files_downloaded: u64,
+    pub files_deleted: u64,
+    // Connections from banned clients held in the tarpit
+    pub tarpitted: u64,
+    // Listings and handles refused for a session's memory budget
+    pub memory_refusals: u64,
+}
+
+impl Counters {
+    fn add(&mut self, other: &Counters) {
+        self.sessions += other.sessions;
+        self.bytes_in += other.bytes_in;
+        self.bytes_out += other.bytes_out;
+        self.failed_logins += other.failed_logins;
+        self.files_uploaded += other.files_uploaded;
+        self.files_downloaded += other.files_downloaded;
+        self.files_deleted += other.files_deleted;
+        self.tarpitted += other.tarpitted;
+        self.memory_refusals += other.memory_refusals;
+    }
+}
+
+// Granularity of the optional breakdown
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BucketSize {
+    Hour,
+    Day,
+}
+
+// Counters for a single time bucket
+#[derive(Debug, Clone, Serialize)]
+pub struct Bucket {
+    pub start: String,
+    #[serde(flatten)]
+    pub counters: Counters,
+}
+
+// Point-in-time copy of the statistics
+#[derive(Debug, Clone, Serialize)]
+pub struct StatsSnapshot {
+    pub started_at: String,
+    pub unique_users: u64,
+    // Banned clients currently held in the tarpit
+    pub tarpitted_now: u64,
+    #[serde(flatten)]
+    pub totals: Counters,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub buckets: Option<Vec<Bucket>>,
+    // Space left on the filesystem of the root, filled in by the caller
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disk: Option<DiskStatus>,
+}
+
+#[derive(Default)]
+struct StatsData {
+    totals: Counters,
+    users: HashSet<String>,
+    tarpitted_now: u64,
+    // Hourly counters keyed by bucket start (unix seconds)
+    hourly: BTreeMap<i64, Counters>,
+}
+
+// Usage statistics collected since process start
+#[derive(Clone)]
+pub struct SftpStats {
+    started_at: DateTime<Utc>,
+    data: Arc<Mutex<StatsData>>,
+}
+
+impl SftpStats {
+    pub fn new() -> Self {
+        Self {
+            started_at: Utc::now(),
+            data: Arc::new(Mutex::new(StatsData::default())),
+        }
+    }
+
+    pub fn record_session(&self, username: &str) {
+        self.update(|c| c.sessions += 1, Some(username));
+    }
+
+    pub fn record_bytes_in(&self, bytes: u64) {
+        self.update(|c| c.bytes_in += bytes, None);
+    }
+
+    pub fn record_bytes_out(&self, bytes: u64) {
+        self.update(|c| c.bytes_out += bytes, None);
+    }
+
+    pub fn record_failed_login(&self) {
+        self.update(|c| c.failed_logins += 1, None);
+    }
+
+    pub fn record_upload(&self) {
+        self.update(|c| c.files_uploaded += 1, None);
+    }
+
+    pub fn record_download(&self) {
+        self.update(|c| c.files_downloaded += 1, None);
+    }
+
+    pub fn record_delete(&self) {
+        self.update(|c| c.files_deleted += 1, None);
+    }
+
+    pub fn record_memory_refusal(&self) {
+        self.update(|c| c.memory_refusals += 1, None);
+    }
+
+    // A banned client entered the tarpit
+    pub fn tarpit_entered(&self) {
+        self.update(|c| c.tarpitted += 1, None);
+        self.data.lock().unwrap().tarpitted_now += 1;
+    }
+
+    // A tarpitted client disconnected or was let go
+    pub fn tarpit_left(&self) {
+        let mut data = self.data.lock().unwrap();
+        data.tarpitted_now = data.tarpitted_now.saturating_sub(1);
+    }
+
+    // Apply a change to the totals and to the current hourly bucket
+    fn update(&self, apply: impl Fn(&mut Counters), username: Option<&str>) {
+        self.update_at(Utc::now().timestamp(), apply, username);
+    }
+
+    // Apply a change to the totals and to the hourly bucket of `now`, in
+    // unix seconds
+    fn update_at(
+        &self,
+        now: i64,
+        apply: impl Fn(&mut Counters),
+        username: Option<&str>,
+    ) {
+        let bucket = now - now.rem_euclid(BUCKET_SECS);
+
+        let mut data = self.data.lock().unwrap();
+        apply(&mut data.totals);
+        apply(data.hourly.entry(bucket).or_default());
+
+        while data.hourly.len() > MAX_BUCKETS {
+            data.hourly.pop_first();
+        }
+
+        if let Some(username) = username
+            && !data.users.contains(username)
+        {
+            data.users.insert(username.to_string());
+        }
+    }
+
+    pub fn snapshot(&self, bucket_size: Option<BucketSize>) -> StatsSnapshot {
+        let data = self.data.lock().unwrap();
+
+        let buckets = bucket_size.map(|size| {
+            let width = match size {
+                BucketSize::Hour => BUCKET_SECS,
+                BucketSize::Day => BUCKET_SECS * 24,
+            };
+
+            let mut grouped: BTreeMap<i64, Counters> = BTreeMap::new();
+            for (start, counters) in &data.hourly {
+                let key = start - start.rem_euclid(width);
+                grouped.entry(key).or_default().add(counters);
+            }
+
+            grouped
+                .into_iter()
+                .map(|(start, counters)| Bucket {
+                    start: format_timestamp(start),
+                    counters,
+                })
+                .collect()
+        });
+
+        StatsSnapshot {
+            started_at: self.started_at.to_rfc3339(),
+            unique_users: data.users.len() as u64,
+            tarpitted_now: data.tarpitted_now,
+            totals: data.totals.clone(),
+            buckets,
+            disk: None,
+        }
+    }
+}
+
+impl Default for SftpStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn format_timestamp(secs: i64) -> String {
+    Utc.timestamp_opt(secs, 0)
+        .single()
+        .map(|dt| dt.to_rfc3339())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Monday 2024-01-01 00:00:00 UTC
+    const MONDAY: i64 = 1_704_067_200;
+
+    #[test]
+    fn test_hourly_buckets_roll_over_and_are_capped() {
+        let stats = SftpStats::new();
+        let upload = |c: &mut Counters| c.files_uploaded += 1;
+        stats.update_at(MONDAY, upload, None);
+        stats.update_at(MONDAY + BUCKET_SECS - 1, upload, None);
+        stats.update_at(MONDAY + BUCKET_SECS, upload, None);
+
+        let hours = stats.snapshot(Some(BucketSize::Hour)).buckets.unwrap();
+        let counts: Vec<u64> =
+            hours.iter().map(|b| b.counters.files_uploaded).collect();
+        assert_eq!(counts, [2, 1]);
+        assert_eq!(hours[0].start, "2024-01-01T00:00:00+00:00");
+        assert_eq!(hours[1].start, "2024-01-01T01:00:00+00:00");
+
+        // One more week of hours pushes the first ones out
+        for hour in 2..=MAX_BUCKETS as i64 {
+            stats.update_at(MONDAY + hour * BUCKET_SECS, upload, None);
+        }
+        let snapshot = stats.snapshot(Some(BucketSize::Hour));
+        let hours = snapshot.buckets.unwrap();
+        assert_eq!(hours.len(), MAX_BUCKETS);
+        assert_eq!(hours[0].start, "2024-01-01T01:00:00+00:00");
+        // Totals keep what the dropped buckets counted
+        assert_eq!(snapshot.totals.files_uploaded, 2 + MAX_BUCKETS as u64);
+
+        let days = stats.snapshot(Some(BucketSize::Day)).buckets.unwrap();
+        assert_eq!(days.len(), 8);
+        assert_eq!(days[0].counters.files_uploaded, 23);
+    }
+
+    #[test]
+    fn test_unique_users_are_counted_once() {
+        let stats = SftpStats::new();
+        stats.record_session("alice");
+        stats.record_session("bob");
+        stats.record_session("alice");
+        stats.record_bytes_in(10);
+
+        let snapshot = stats.snapshot(None);
+        assert_eq!(snapshot.unique_users, 2);
+        assert_eq!(snapshot.totals.sessions, 3);
+        assert!(snapshot.buckets.is_none());
+    }
+}
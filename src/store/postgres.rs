@@ -0,0 +1,780 @@
+use crate::store::{
+    AccountRecord, AccountsSnapshot, AuditEntry, AuthorizedKey, ERASED_USER,
+    ErasedUserData, FileTags, NewTransfer, Repository, ShareLink,
+    TransferDirection, TransferRecord, UserAccount, UserData,
+};
+use async_trait::async_trait;
+use chrono::Utc;
+use deadpool_postgres::{Config, Pool, PoolConfig, Runtime};
+use std::collections::BTreeMap;
+use tokio_postgres::{NoTls, Row};
+use tracing::info;
+
+// Schema versions applied in order; never edit a released entry, append
+// a new one instead
+const MIGRATIONS: &[(i32, &str)] = &[
+    (
+        1,
+        "
+    CREATE TABLE users (
+        id BIGSERIAL PRIMARY KEY,
+        username TEXT NOT NULL UNIQUE,
+        created_at TIMESTAMPTZ NOT NULL
+    );
+    CREATE TABLE authorized_keys (
+        id BIGSERIAL PRIMARY KEY,
+        username TEXT NOT NULL
+            REFERENCES users(username) ON DELETE CASCADE,
+        public_key TEXT NOT NULL,
+        comment TEXT,
+        created_at TIMESTAMPTZ NOT NULL
+    );
+    CREATE TABLE share_links (
+        token TEXT PRIMARY KEY,
+        path TEXT NOT NULL,
+        created_by TEXT,
+        created_at TIMESTAMPTZ NOT NULL,
+        expires_at TIMESTAMPTZ
+    );
+    CREATE TABLE audit_log (
+        id BIGSERIAL PRIMARY KEY,
+        timestamp TIMESTAMPTZ NOT NULL,
+        action TEXT NOT NULL,
+        username TEXT,
+        detail JSONB NOT NULL
+    );
+    CREATE TABLE transfer_log (
+        id BIGSERIAL PRIMARY KEY,
+        timestamp TIMESTAMPTZ NOT NULL,
+        username TEXT NOT NULL,
+        path TEXT NOT NULL,
+        direction TEXT NOT NULL,
+        bytes BIGINT NOT NULL
+    );
+    ",
+    ),
+    (
+        2,
+        "
+    ALTER TABLE transfer_log ADD COLUMN peer TEXT;
+    ALTER TABLE transfer_log ADD COLUMN duration_ms BIGINT;
+    ",
+    ),
+    (
+        3,
+        "
+    ALTER TABLE transfer_log ADD COLUMN checksums JSONB;
+    CREATE INDEX transfer_log_path ON transfer_log (path, direction);
+    ",
+    ),
+    (
+        4,
+        "
+    ALTER TABLE users
+        ADD COLUMN access_windows TEXT[] NOT NULL DEFAULT '{}';
+    ",
+    ),
+    (
+        5,
+        "
+    CREATE TABLE file_tags (
+        path TEXT PRIMARY KEY,
+        tags JSONB NOT NULL
+    );
+    CREATE INDEX file_tags_tags ON file_tags USING GIN (tags);
+    ",
+    ),
+    (
+        6,
+        "
+    ALTER TABLE users ADD COLUMN tenant TEXT;
+    ",
+    ),
+    (
+        7,
+        "
+    ALTER TABLE users ADD COLUMN password_hash TEXT;
+    ",
+    ),
+];
+
+// Arbitrary key for the advisory lock serializing migrations across replicas
+const MIGRATION_LOCK_KEY: i64 = 0x5f70_6d67;
+
+// Repository backed by a shared PostgreSQL database
+pub struct PostgresRepository {
+    pool: Pool,
+}
+
+impl PostgresRepository {
+    pub async fn connect(url: &str, pool_size: usize) -> anyhow::Result<Self> {
+        let config = Config {
+            url: Some(url.to_string()),
+            pool: Some(PoolConfig::new(pool_size)),
+            ..Default::default()
+        };
+        let pool = config.create_pool(Some(Runtime::Tokio1), NoTls)?;
+
+        let repository = Self { pool };
+        repository.migrate().await?;
+        info!("Connected to PostgreSQL with a pool of {}", pool_size);
+        Ok(repository)
+    }
+
+    // Apply pending migrations inside one transaction
+    async fn migrate(&self) -> anyhow::Result<()> {
+        let mut client = self.pool.get().await?;
+        let tx = client.transaction().await?;
+
+        tx.execute("SELECT pg_advisory_xact_lock($1)", &[&MIGRATION_LOCK_KEY])
+            .await?;
+        tx.batch_execute(
+            "CREATE TABLE IF NOT EXISTS schema_migrations (
+                version INTEGER PRIMARY KEY,
+                applied_at TIMESTAMPTZ NOT NULL
+            )",
+        )
+        .await?;
+
+        let current: i32 = tx
+            .query_one(
+                "SELECT COALESCE(MAX(version), 0) FROM schema_migrations",
+                &[],
+            )
+            .await?
+            .get(0);
+
+        for (version, sql) in MIGRATIONS.iter().filter(|(v, _)| *v > current) {
+            info!("Applying database migration {}", version);
+            tx.batch_execute(sql).await?;
+            tx.execute(
+                "INSERT INTO schema_migrations (version, applied_at)
+                 VALUES ($1, $2)",
+                &[version, &Utc::now()],
+            )
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+fn user_from_row(row: &Row) -> UserAccount {
+    UserAccount {
+        id: row.get(0),
+        username: row.get(1),
+        created_at: row.get(2),
+        access_windows: row.get(3),
+        tenant: row.get(4),
+    }
+}
+
+fn key_from_row(row: &Row) -> AuthorizedKey {
+    AuthorizedKey {
+        id: row.get(0),
+        username: row.get(1),
+        public_key: row.get(2),
+        comment: row.get(3),
+        created_at: row.get(4),
+    }
+}
+
+fn audit_from_row(row: &Row) -> AuditEntry {
+    AuditEntry {
+        id: row.get(0),
+        timestamp: row.get(1),
+        action: row.get(2),
+        username: row.get(3),
+        detail: row.get(4),
+    }
+}
+
+const SELECT_TRANSFERS: &str = "SELECT id, timestamp, username, peer, path,
+    direction, bytes, duration_ms, checksums FROM transfer_log";
+
+fn transfer_from_row(row: &Row) -> TransferRecord {
+    TransferRecord {
+        id: row.get(0),
+        timestamp: row.get(1),
+        username: row.get(2),
+        peer: row.get(3),
+        path: row.get(4),
+        direction: TransferDirection::parse(row.get(5))
+            .unwrap_or(TransferDirection::Upload),
+        bytes: row.get::<_, i64>(6) as u64,
+        duration_ms: row.get::<_, Option<i64>>(7).map(|ms| ms as u64),
+        checksums: row
+            .get::<_, Option<serde_json::Value>>(8)
+            .and_then(|c| serde_json::from_value(c).ok())
+            .unwrap_or_default(),
+    }
+}
+
+fn share_from_row(row: &Row) -> ShareLink {
+    ShareLink {
+        token: row.get(0),
+        path: row.get(1),
+        created_by: row.get(2),
+        created_at: row.get(3),
+        expires_at: row.get(4),
+        url: None,
+    }
+}
+
+#[async_trait]
+impl Repository for PostgresRepository {
+    async fn create_user(
+        &self,
+        username: &str,
+        access_windows: &[String],
+        tenant: Option<&str>,
+    ) -> anyhow::Result<UserAccount> {
+        let client = self.pool.get().await?;
+        let row = client
+            .query_one(
+                "INSERT INTO users
+                     (username, created_at, access_windows, tenant)
+                 VALUES ($1, $2, $3, $4)
+                 RETURNING id, username, created_at, access_windows, tenant",
+                &[&username, &Utc::now(), &access_windows, &tenant],
+            )
+            .await?;
+        Ok(user_from_row(&row))
+    }
+
+    async fn get_user(
+        &self,
+        username: &str,
+    ) -> anyhow::Result<Option<UserAccount>> {
+        let client = self.pool.get().await?;
+        let row = client
+            .query_opt(
+                "SELECT id, username, created_at, access_windows, tenant
+                 FROM users WHERE username = $1",
+                &[&username],
+            )
+            .await?;
+        Ok(row.as_ref().map(user_from_row))
+    }
+
+    async fn list_users(&self) -> anyhow::Result<Vec<UserAccount>> {
+        let client = self.pool.get().await?;
+        let rows = client
+            .query(
+                "SELECT id, username, created_at, access_windows, tenant
+                 FROM users ORDER BY username",
+                &[],
+            )
+            .await?;
+        Ok(rows.iter().map(user_from_row).collect())
+    }
+
+    async fn delete_user(&self, username: &str) -> anyhow::Result<bool> {
+        let client = self.pool.get().await?;
+        let deleted = client
+            .execute("DELETE FROM users WHERE username = $1", &[&username])
+            .await?;
+        Ok(deleted > 0)
+    }
+
+    async fn user_data(&self, username: &str) -> anyhow::Result<UserData> {
+        let client = self.pool.get().await?;
+        let user = client
+            .query_opt(
+                "SELECT id, username, created_at, access_windows, tenant
+                 FROM users WHERE username = $1",
+                &[&username],
+            )
+            .await?;
+        let keys = client
+            .query(
+                "SELECT id, username, public_key, comment, created_at
+                 FROM authorized_keys WHERE username = $1 ORDER BY id",
+                &[&username],
+            )
+            .await?;
+        let shares = client
+            .query(
+                "SELECT token, path, created_by, created_at, expires_at
+                 FROM share_links WHERE created_by = $1 ORDER BY created_at",
+                &[&username],
+            )
+            .await?;
+        let audit = client
+            .query(
+                "SELECT id, timestamp, action, username, detail
+                 FROM audit_log WHERE username = $1 ORDER BY id",
+                &[&username],
+            )
+            .await?;
+        let transfers = client
+            .query(
+                &format!(
+                    "{} WHERE username = $1 ORDER BY id",
+                    SELECT_TRANSFERS
+                ),
+                &[&username],
+            )
+            .await?;
+        Ok(UserData {
+            user: user.as_ref().map(user_from_row),
+            keys: keys.iter().map(key_from_row).collect(),
+            shares: shares.iter().map(share_from_row).collect(),
+            audit: audit.iter().map(audit_from_row).collect(),
+            transfers: transfers.iter().map(transfer_from_row).collect(),
+        })
+    }
+
+    async fn erase_user(
+        &self,
+        username: &str,
+    ) -> anyhow::Result<ErasedUserData> {
+        let mut client = self.pool.get().await?;
+        let tx = client.transaction().await?;
+        let keys = tx
+            .execute(
+                "DELETE FROM authorized_keys WHERE username = $1",
+                &[&username],
+            )
+            .await?;
+        let account = tx
+            .execute("DELETE FROM users WHERE username = $1", &[&username])
+            .await?;
+        let shares = tx
+            .execute(
+                "DELETE FROM share_links WHERE created_by = $1",
+                &[&username],
+            )
+            .await?;
+        let audit_entries = tx
+            .execute(
+                "UPDATE audit_log SET username = $2, detail = '{}'
+                 WHERE username = $1",
+                &[&username, &ERASED_USER],
+            )
+            .await?;
+        let transfers = tx
+            .execute(
+                "UPDATE transfer_log SET username = $2, peer = NULL,
+                     path = $2, checksums = NULL
+                 WHERE username = $1",
+                &[&username, &ERASED_USER],
+            )
+            .await?;
+        tx.commit().await?;
+        Ok(ErasedUserData {
+            account: account > 0,
+            keys,
+            shares,
+            audit_entries,
+            transfers,
+        })
+    }
+
+    async fn set_access_windows(
+        &self,
+        username: &str,
+        access_windows: &[String],
+    ) -> anyhow::Result<bool> {
+        let client = self.pool.get().await?;
+        let updated = client
+            .execute(
+                "UPDATE users SET access_windows = $1 WHERE username = $2",
+                &[&access_windows, &username],
+            )
+            .await?;
+        Ok(updated > 0)
+    }
+
+    async fn set_password_hash(
+        &self,
+        username: &str,
+        password_hash: Option<&str>,
+    ) -> anyhow::Result<bool> {
+        let client = self.pool.get().await?;
+        let updated = client
+            .execute(
+                "UPDATE users SET password_hash = $1 WHERE username = $2",
+                &[&password_hash, &username],
+            )
+            .await?;
+        Ok(updated > 0)
+    }
+
+    async fn password_hash(
+        &self,
+        username: &str,
+    ) -> anyhow::Result<Option<String>> {
+        let client = self.pool.get().await?;
+        let row = client
+            .query_opt(
+                "SELECT password_hash FROM users WHERE username = $1",
+                &[&username],
+            )
+            .await?;
+        Ok(row.and_then(|row| row.get(0)))
+    }
+
+    async fn add_key(
+        &self,
+        username: &str,
+        public_key: &str,
+        comment: Option<&str>,
+    ) -> anyhow::Result<AuthorizedKey> {
+        let client = self.pool.get().await?;
+        let row = client
+            .query_one(
+                "INSERT INTO authorized_keys
+                 (username, public_key, comment, created_at)
+                 VALUES ($1, $2, $3, $4)
+                 RETURNING id, username, public_key, comment, created_at",
+                &[&username, &public_key, &comment, &Utc::now()],
+            )
+            .await?;
+        Ok(key_from_row(&row))
+    }
+
+    async fn list_keys(
+        &self,
+        username: &str,
+    ) -> anyhow::Result<Vec<AuthorizedKey>> {
+        let client = self.pool.get().await?;
+        let rows = client
+            .query(
+                "SELECT id, username, public_key, comment, created_at
+                 FROM authorized_keys WHERE username = $1 ORDER BY id",
+                &[&username],
+            )
+            .await?;
+        Ok(rows.iter().map(key_from_row).collect())
+    }
+
+    async fn remove_key(&self, id: i64) -> anyhow::Result<bool> {
+        let client = self.pool.get().await?;
+        let deleted = client
+            .execute("DELETE FROM authorized_keys WHERE id = $1", &[&id])
+            .await?;
+        Ok(deleted > 0)
+    }
+
+    async fn create_share(&self, share: &ShareLink) -> anyhow::Result<()> {
+        let client = self.pool.get().await?;
+        client
+            .execute(
+                "INSERT INTO share_links
+                 (token, path, created_by, created_at, expires_at)
+                 VALUES ($1, $2, $3, $4, $5)",
+                &[
+                    &share.token,
+                    &share.path,
+                    &share.created_by,
+                    &share.created_at,
+                    &share.expires_at,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn get_share(
+        &self,
+        token: &str,
+    ) -> anyhow::Result<Option<ShareLink>> {
+        let client = self.pool.get().await?;
+        let row = client
+            .query_opt(
+                "SELECT token, path, created_by, created_at, expires_at
+                 FROM share_links WHERE token = $1",
+                &[&token],
+            )
+            .await?;
+        Ok(row.as_ref().map(share_from_row))
+    }
+
+    async fn list_shares(&self) -> anyhow::Result<Vec<ShareLink>> {
+        let client = self.pool.get().await?;
+        let rows = client
+            .query(
+                "SELECT token, path, created_by, created_at, expires_at
+                 FROM share_links ORDER BY created_at",
+                &[],
+            )
+            .await?;
+        Ok(rows.iter().map(share_from_row).collect())
+    }
+
+    async fn delete_share(&self, token: &str) -> anyhow::Result<bool> {
+        let client = self.pool.get().await?;
+        let deleted = client
+            .execute("DELETE FROM share_links WHERE token = $1", &[&token])
+            .await?;
+        Ok(deleted > 0)
+    }
+
+    async fn export_accounts(&self) -> anyhow::Result<AccountsSnapshot> {
+        let client = self.pool.get().await?;
+        let users = client
+            .query(
+                "SELECT id, username, created_at, access_windows, tenant,
+                     password_hash
+                 FROM users ORDER BY username",
+                &[],
+            )
+            .await?;
+        let keys = client
+            .query(
+                "SELECT id, username, public_key, comment, created_at
+                 FROM authorized_keys ORDER BY id",
+                &[],
+            )
+            .await?;
+        let shares = client
+            .query(
+                "SELECT token, path, created_by, created_at, expires_at
+                 FROM share_links ORDER BY created_at",
+                &[],
+            )
+            .await?;
+        Ok(AccountsSnapshot {
+            users: users
+                .iter()
+                .map(|row| AccountRecord {
+                    account: user_from_row(row),
+                    password_hash: row.get(5),
+                })
+                .collect(),
+            keys: keys.iter().map(key_from_row).collect(),
+            shares: shares.iter().map(share_from_row).collect(),
+        })
+    }
+
+    async fn import_accounts(
+        &self,
+        snapshot: &AccountsSnapshot,
+    ) -> anyhow::Result<()> {
+        let mut client = self.pool.get().await?;
+        let tx = client.transaction().await?;
+        tx.execute("DELETE FROM authorized_keys", &[]).await?;
+        tx.execute("DELETE FROM users", &[]).await?;
+        tx.execute("DELETE FROM share_links", &[]).await?;
+        for record in &snapshot.users {
+            let user = &record.account;
+            tx.execute(
+                "INSERT INTO users (username, created_at, access_windows,
+                     tenant, password_hash)
+                 VALUES ($1, $2, $3, $4, $5)",
+                &[
+                    &user.username,
+                    &user.created_at,
+                    &user.access_windows,
+                    &user.tenant,
+                    &record.password_hash,
+                ],
+            )
+            .await?;
+        }
+        for key in &snapshot.keys {
+            tx.execute(
+                "INSERT INTO authorized_keys
+                     (username, public_key, comment, created_at)
+                 VALUES ($1, $2, $3, $4)",
+                &[
+                    &key.username,
+                    &key.public_key,
+                    &key.comment,
+                    &key.created_at,
+                ],
+            )
+            .await?;
+        }
+        for share in &snapshot.shares {
+            tx.execute(
+                "INSERT INTO share_links
+                     (token, path, created_by, created_at, expires_at)
+                 VALUES ($1, $2, $3, $4, $5)",
+                &[
+                    &share.token,
+                    &share.path,
+                    &share.created_by,
+                    &share.created_at,
+                    &share.expires_at,
+                ],
+            )
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn record_audit(
+        &self,
+        action: &str,
+        username: Option<&str>,
+        detail: serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let client = self.pool.get().await?;
+        client
+            .execute(
+                "INSERT INTO audit_log (timestamp, action, username, detail)
+                 VALUES ($1, $2, $3, $4)",
+                &[&Utc::now(), &action, &username, &detail],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn list_audit(
+        &self,
+        limit: u32,
+        action: Option<&str>,
+    ) -> anyhow::Result<Vec<AuditEntry>> {
+        let client = self.pool.get().await?;
+        let rows = client
+            .query(
+                "SELECT id, timestamp, action, username, detail
+                 FROM audit_log WHERE $2::TEXT IS NULL OR action = $2
+                 ORDER BY id DESC LIMIT $1",
+                &[&i64::from(limit), &action],
+            )
+            .await?;
+        Ok(rows.iter().map(audit_from_row).collect())
+    }
+
+    async fn record_transfer(
+        &self,
+        transfer: NewTransfer,
+    ) -> anyhow::Result<()> {
+        let checksums = serde_json::to_value(&transfer.checksums)?;
+        let client = self.pool.get().await?;
+        client
+            .execute(
+                "INSERT INTO transfer_log
+                 (timestamp, username, peer, path, direction, bytes,
+                  duration_ms, checksums)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+                &[
+                    &Utc::now(),
+                    &transfer.username,
+                    &transfer.peer,
+                    &transfer.path,
+                    &transfer.direction.as_str(),
+                    &(transfer.bytes as i64),
+                    &(transfer.duration_ms as i64),
+                    &checksums,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn list_transfers(
+        &self,
+        limit: u32,
+    ) -> anyhow::Result<Vec<TransferRecord>> {
+        let client = self.pool.get().await?;
+        let rows = client
+            .query(
+                &format!("{} ORDER BY id DESC LIMIT $1", SELECT_TRANSFERS),
+                &[&i64::from(limit)],
+            )
+            .await?;
+        Ok(rows.iter().map(transfer_from_row).collect())
+    }
+
+    async fn latest_upload(
+        &self,
+        path: &str,
+    ) -> anyhow::Result<Option<TransferRecord>> {
+        let client = self.pool.get().await?;
+        let row = client
+            .query_opt(
+                &format!(
+                    "{} WHERE path = $1 AND direction = 'upload'
+                     ORDER BY id DESC LIMIT 1",
+                    SELECT_TRANSFERS
+                ),
+                &[&path],
+            )
+            .await?;
+        Ok(row.as_ref().map(transfer_from_row))
+    }
+
+    async fn latest_uploads_with_checksums(
+        &self,
+    ) -> anyhow::Result<Vec<TransferRecord>> {
+        let client = self.pool.get().await?;
+        let rows = client
+            .query(
+                &format!(
+                    "{} WHERE id IN (SELECT MAX(id) FROM transfer_log
+                         WHERE direction = 'upload' GROUP BY path)
+                     AND checksums IS NOT NULL AND checksums != '{{}}'::jsonb
+                     ORDER BY path",
+                    SELECT_TRANSFERS
+                ),
+                &[],
+            )
+            .await?;
+        Ok(rows.iter().map(transfer_from_row).collect())
+    }
+
+    async fn set_file_tags(
+        &self,
+        path: &str,
+        tags: &BTreeMap<String, String>,
+    ) -> anyhow::Result<()> {
+        let client = self.pool.get().await?;
+        if tags.is_empty() {
+            client
+                .execute("DELETE FROM file_tags WHERE path = $1", &[&path])
+                .await?;
+            return Ok(());
+        }
+        let tags = serde_json::to_value(tags)?;
+        client
+            .execute(
+                "INSERT INTO file_tags (path, tags) VALUES ($1, $2)
+                 ON CONFLICT (path) DO UPDATE SET tags = EXCLUDED.tags",
+                &[&path, &tags],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn get_file_tags(
+        &self,
+        path: &str,
+    ) -> anyhow::Result<BTreeMap<String, String>> {
+        let client = self.pool.get().await?;
+        let row = client
+            .query_opt("SELECT tags FROM file_tags WHERE path = $1", &[&path])
+            .await?;
+        Ok(row
+            .and_then(|row| serde_json::from_value(row.get(0)).ok())
+            .unwrap_or_default())
+    }
+
+    async fn find_tagged_files(
+        &self,
+        tags: &BTreeMap<String, String>,
+        limit: u32,
+    ) -> anyhow::Result<Vec<FileTags>> {
+        let tags = serde_json::to_value(tags)?;
+        let client = self.pool.get().await?;
+        let rows = client
+            .query(
+                "SELECT path, tags FROM file_tags WHERE tags @> $1
+                 ORDER BY path LIMIT $2",
+                &[&tags, &i64::from(limit)],
+            )
+            .await?;
+        Ok(rows
+            .iter()
+            .map(|row| FileTags {
+                path: row.get(0),
+                tags: serde_json::from_value(row.get(1)).unwrap_or_default(),
+            })
+            .collect())
+    }
+}
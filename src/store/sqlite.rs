@@ -0,0 +1,940 @@
+use crate::store::{
+    AccountRecord, AccountsSnapshot, AuditEntry, AuthorizedKey, ERASED_USER,
+    ErasedUserData, FileTags, NewTransfer, Repository, ShareLink,
+    TransferDirection, TransferRecord, UserAccount, UserData,
+};
+use async_trait::async_trait;
+use chrono::Utc;
+use rusqlite::{Connection, OptionalExtension, Row, params};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tracing::info;
+
+// Schema versions applied in order; never edit a released entry, append
+// a new one instead. Version 1 uses IF NOT EXISTS to adopt databases
+// created before migrations were tracked.
+const MIGRATIONS: &[(i64, &str)] = &[
+    (
+        1,
+        "
+CREATE TABLE IF NOT EXISTS users (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    username TEXT NOT NULL UNIQUE,
+    created_at TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS authorized_keys (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    username TEXT NOT NULL REFERENCES users(username) ON DELETE CASCADE,
+    public_key TEXT NOT NULL,
+    comment TEXT,
+    created_at TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS share_links (
+    token TEXT PRIMARY KEY,
+    path TEXT NOT NULL,
+    created_by TEXT,
+    created_at TEXT NOT NULL,
+    expires_at TEXT
+);
+CREATE TABLE IF NOT EXISTS audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    timestamp TEXT NOT NULL,
+    action TEXT NOT NULL,
+    username TEXT,
+    detail TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS transfer_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    timestamp TEXT NOT NULL,
+    username TEXT NOT NULL,
+    path TEXT NOT NULL,
+    direction TEXT NOT NULL,
+    bytes INTEGER NOT NULL
+);
+",
+    ),
+    (
+        2,
+        "
+ALTER TABLE transfer_log ADD COLUMN peer TEXT;
+ALTER TABLE transfer_log ADD COLUMN duration_ms INTEGER;
+",
+    ),
+    (
+        3,
+        "
+ALTER TABLE transfer_log ADD COLUMN checksums TEXT;
+CREATE INDEX transfer_log_path ON transfer_log (path, direction);
+",
+    ),
+    (
+        4,
+        "
+ALTER TABLE users ADD COLUMN access_windows TEXT;
+",
+    ),
+    (
+        5,
+        "
+CREATE TABLE file_tags (
+    path TEXT NOT NULL,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (path, key)
+);
+CREATE INDEX file_tags_key_value ON file_tags (key, value);
+",
+    ),
+    (
+        6,
+        "
+ALTER TABLE users ADD COLUMN tenant TEXT;
+",
+    ),
+    (
+        7,
+        "
+ALTER TABLE users ADD COLUMN password_hash TEXT;
+",
+    ),
+];
+
+// Repository backed by an embedded SQLite database file
+pub struct SqliteRepository {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteRepository {
+    pub fn open(path: &str) -> anyhow::Result<Self> {
+        if let Some(parent) = Path::new(path).parent()
+            && !parent.as_os_str().is_empty()
+        {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let conn = Connection::open(path)?;
+        info!("Opened SQLite database {}", path);
+        Self::init(conn)
+    }
+
+    pub fn in_memory() -> anyhow::Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(mut conn: Connection) -> anyhow::Result<Self> {
+        conn.execute_batch(
+            "PRAGMA foreign_keys = ON; PRAGMA journal_mode = WAL;",
+        )?;
+        migrate(&mut conn)?;
+        Ok(Self { conn: Arc::new(Mutex::new(conn)) })
+    }
+
+    // Run a query on the blocking thread pool
+    async fn call<T, F>(&self, f: F) -> anyhow::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let conn = conn.lock().unwrap();
+            f(&conn)
+        })
+        .await?;
+        Ok(result?)
+    }
+}
+
+// Apply pending migrations inside one transaction
+fn migrate(conn: &mut Connection) -> anyhow::Result<()> {
+    let tx = conn.transaction()?;
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+            version INTEGER PRIMARY KEY,
+            applied_at TEXT NOT NULL
+        )",
+    )?;
+
+    let current: i64 = tx.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM schema_migrations",
+        [],
+        |row| row.get(0),
+    )?;
+
+    for (version, sql) in MIGRATIONS.iter().filter(|(v, _)| *v > current) {
+        info!("Applying database migration {}", version);
+        tx.execute_batch(sql)?;
+        tx.execute(
+            "INSERT INTO schema_migrations (version, applied_at)
+             VALUES (?1, ?2)",
+            params![version, Utc::now()],
+        )?;
+    }
+
+    tx.commit()?;
+    Ok(())
+}
+
+fn user_from_row(row: &Row) -> rusqlite::Result<UserAccount> {
+    let access_windows: Option<String> = row.get(3)?;
+    Ok(UserAccount {
+        id: row.get(0)?,
+        username: row.get(1)?,
+        created_at: row.get(2)?,
+        access_windows: access_windows
+            .and_then(|w| serde_json::from_str(&w).ok())
+            .unwrap_or_default(),
+        tenant: row.get(4)?,
+    })
+}
+
+fn key_from_row(row: &Row) -> rusqlite::Result<AuthorizedKey> {
+    Ok(AuthorizedKey {
+        id: row.get(0)?,
+        username: row.get(1)?,
+        public_key: row.get(2)?,
+        comment: row.get(3)?,
+        created_at: row.get(4)?,
+    })
+}
+
+fn audit_from_row(row: &Row) -> rusqlite::Result<AuditEntry> {
+    let detail: String = row.get(4)?;
+    Ok(AuditEntry {
+        id: row.get(0)?,
+        timestamp: row.get(1)?,
+        action: row.get(2)?,
+        username: row.get(3)?,
+        detail: serde_json::from_str(&detail)
+            .unwrap_or(serde_json::Value::String(detail)),
+    })
+}
+
+const SELECT_TRANSFERS: &str = "SELECT id, timestamp, username, peer, path,
+    direction, bytes, duration_ms, checksums FROM transfer_log";
+
+fn transfer_from_row(row: &Row) -> rusqlite::Result<TransferRecord> {
+    let direction: String = row.get(5)?;
+    let checksums: Option<String> = row.get(8)?;
+    Ok(TransferRecord {
+        id: row.get(0)?,
+        timestamp: row.get(1)?,
+        username: row.get(2)?,
+        peer: row.get(3)?,
+        path: row.get(4)?,
+        direction: TransferDirection::parse(&direction)
+            .unwrap_or(TransferDirection::Upload),
+        bytes: row.get::<_, i64>(6)? as u64,
+        duration_ms: row.get::<_, Option<i64>>(7)?.map(|ms| ms as u64),
+        checksums: checksums
+            .and_then(|c| serde_json::from_str(&c).ok())
+            .unwrap_or_default(),
+    })
+}
+
+fn share_from_row(row: &Row) -> rusqlite::Result<ShareLink> {
+    Ok(ShareLink {
+        token: row.get(0)?,
+        path: row.get(1)?,
+        created_by: row.get(2)?,
+        created_at: row.get(3)?,
+        expires_at: row.get(4)?,
+        url: None,
+    })
+}
+
+#[async_trait]
+impl Repository for SqliteRepository {
+    async fn create_user(
+        &self,
+        username: &str,
+        access_windows: &[String],
+        tenant: Option<&str>,
+    ) -> anyhow::Result<UserAccount> {
+        let username = username.to_string();
+        let access_windows = access_windows.to_vec();
+        let tenant = tenant.map(str::to_string);
+        let windows = serde_json::to_string(&access_windows)?;
+        self.call(move |conn| {
+            let created_at = Utc::now();
+            conn.execute(
+                "INSERT INTO users
+                     (username, created_at, access_windows, tenant)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![username, created_at, windows, tenant],
+            )?;
+            Ok(UserAccount {
+                id: conn.last_insert_rowid(),
+                username,
+                created_at,
+                access_windows,
+                tenant,
+            })
+        })
+        .await
+    }
+
+    async fn get_user(
+        &self,
+        username: &str,
+    ) -> anyhow::Result<Option<UserAccount>> {
+        let username = username.to_string();
+        self.call(move |conn| {
+            conn.query_row(
+                "SELECT id, username, created_at, access_windows, tenant
+                 FROM users WHERE username = ?1",
+                params![username],
+                user_from_row,
+            )
+            .optional()
+        })
+        .await
+    }
+
+    async fn list_users(&self) -> anyhow::Result<Vec<UserAccount>> {
+        self.call(|conn| {
+            conn.prepare(
+                "SELECT id, username, created_at, access_windows, tenant
+                 FROM users ORDER BY username",
+            )?
+            .query_map([], user_from_row)?
+            .collect()
+        })
+        .await
+    }
+
+    async fn delete_user(&self, username: &str) -> anyhow::Result<bool> {
+        let username = username.to_string();
+        self.call(move |conn| {
+            conn.execute("DELETE FROM users WHERE username = ?1", [username])
+                .map(|n| n > 0)
+        })
+        .await
+    }
+
+    async fn user_data(&self, username: &str) -> anyhow::Result<UserData> {
+        let username = username.to_string();
+        self.call(move |conn| {
+            let user = conn
+                .query_row(
+                    "SELECT id, username, created_at, access_windows, tenant
+                     FROM users WHERE username = ?1",
+                    [&username],
+                    user_from_row,
+                )
+                .optional()?;
+            let keys = conn
+                .prepare(
+                    "SELECT id, username, public_key, comment, created_at
+                     FROM authorized_keys WHERE username = ?1 ORDER BY id",
+                )?
+                .query_map([&username], key_from_row)?
+                .collect::<rusqlite::Result<_>>()?;
+            let shares = conn
+                .prepare(
+                    "SELECT token, path, created_by, created_at, expires_at
+                     FROM share_links WHERE created_by = ?1
+                     ORDER BY created_at",
+                )?
+                .query_map([&username], share_from_row)?
+                .collect::<rusqlite::Result<_>>()?;
+            let audit = conn
+                .prepare(
+                    "SELECT id, timestamp, action, username, detail
+                     FROM audit_log WHERE username = ?1 ORDER BY id",
+                )?
+                .query_map([&username], audit_from_row)?
+                .collect::<rusqlite::Result<_>>()?;
+            let transfers = conn
+                .prepare(&format!(
+                    "{} WHERE username = ?1 ORDER BY id",
+                    SELECT_TRANSFERS
+                ))?
+                .query_map([&username], transfer_from_row)?
+                .collect::<rusqlite::Result<_>>()?;
+            Ok(UserData { user, keys, shares, audit, transfers })
+        })
+        .await
+    }
+
+    async fn erase_user(
+        &self,
+        username: &str,
+    ) -> anyhow::Result<ErasedUserData> {
+        let username = username.to_string();
+        self.call(move |conn| {
+            let tx = conn.unchecked_transaction()?;
+            let keys = tx.execute(
+                "DELETE FROM authorized_keys WHERE username = ?1",
+                [&username],
+            )?;
+            let account = tx.execute(
+                "DELETE FROM users WHERE username = ?1",
+                [&username],
+            )?;
+            let shares = tx.execute(
+                "DELETE FROM share_links WHERE created_by = ?1",
+                [&username],
+            )?;
+            let audit_entries = tx.execute(
+                "UPDATE audit_log SET username = ?2, detail = '{}'
+                 WHERE username = ?1",
+                [&username, ERASED_USER],
+            )?;
+            let transfers = tx.execute(
+                "UPDATE transfer_log SET username = ?2, peer = NULL,
+                     path = ?2, checksums = NULL
+                 WHERE username = ?1",
+                [&username, ERASED_USER],
+            )?;
+            tx.commit()?;
+            Ok(ErasedUserData {
+                account: account > 0,
+                keys: keys as u64,
+                shares: shares as u64,
+                audit_entries: audit_entries as u64,
+                transfers: transfers as u64,
+            })
+        })
+        .await
+    }
+
+    async fn set_access_windows(
+        &self,
+        username: &str,
+        access_windows: &[String],
+    ) -> anyhow::Result<bool> {
+        let username = username.to_string();
+        let windows = serde_json::to_string(access_windows)?;
+        self.call(move |conn| {
+            conn.execute(
+                "UPDATE users SET access_windows = ?1 WHERE username = ?2",
+                params![windows, username],
+            )
+            .map(|n| n > 0)
+        })
+        .await
+    }
+
+    async fn set_password_hash(
+        &self,
+        username: &str,
+        password_hash: Option<&str>,
+    ) -> anyhow::Result<bool> {
+        let username = username.to_string();
+        let password_hash = password_hash.map(str::to_string);
+        self.call(move |conn| {
+            conn.execute(
+                "UPDATE users SET password_hash = ?1 WHERE username = ?2",
+                params![password_hash, username],
+            )
+            .map(|n| n > 0)
+        })
+        .await
+    }
+
+    async fn password_hash(
+        &self,
+        username: &str,
+    ) -> anyhow::Result<Option<String>> {
+        let username = username.to_string();
+        self.call(move |conn| {
+            conn.query_row(
+                "SELECT password_hash FROM users WHERE username = ?1",
+                [username],
+                |row| row.get(0),
+            )
+            .optional()
+            .map(Option::flatten)
+        })
+        .await
+    }
+
+    async fn add_key(
+        &self,
+        username: &str,
+        public_key: &str,
+        comment: Option<&str>,
+    ) -> anyhow::Result<AuthorizedKey> {
+        let username = username.to_string();
+        let public_key = public_key.to_string();
+        let comment = comment.map(str::to_string);
+        self.call(move |conn| {
+            let created_at = Utc::now();
+            conn.execute(
+                "INSERT INTO authorized_keys
+                 (username, public_key, comment, created_at)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![username, public_key, comment, created_at],
+            )?;
+            Ok(AuthorizedKey {
+                id: conn.last_insert_rowid(),
+                username,
+                public_key,
+                comment,
+                created_at,
+            })
+        })
+        .await
+    }
+
+    async fn list_keys(
+        &self,
+        username: &str,
+    ) -> anyhow::Result<Vec<AuthorizedKey>> {
+        let username = username.to_string();
+        self.call(move |conn| {
+            conn.prepare(
+                "SELECT id, username, public_key, comment, created_at
+                 FROM authorized_keys WHERE username = ?1 ORDER BY id",
+            )?
+            .query_map([username], key_from_row)?
+            .collect()
+        })
+        .await
+    }
+
+    async fn remove_key(&self, id: i64) -> anyhow::Result<bool> {
+        self.call(move |conn| {
+            conn.execute("DELETE FROM authorized_keys WHERE id = ?1", [id])
+                .map(|n| n > 0)
+        })
+        .await
+    }
+
+    async fn create_share(&self, share: &ShareLink) -> anyhow::Result<()> {
+        let share = share.clone();
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO share_links
+                 (token, path, created_by, created_at, expires_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    share.token,
+                    share.path,
+                    share.created_by,
+                    share.created_at,
+                    share.expires_at
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_share(
+        &self,
+        token: &str,
+    ) -> anyhow::Result<Option<ShareLink>> {
+        let token = token.to_string();
+        self.call(move |conn| {
+            conn.query_row(
+                "SELECT token, path, created_by, created_at, expires_at
+                 FROM share_links WHERE token = ?1",
+                [token],
+                share_from_row,
+            )
+            .optional()
+        })
+        .await
+    }
+
+    async fn list_shares(&self) -> anyhow::Result<Vec<ShareLink>> {
+        self.call(|conn| {
+            conn.prepare(
+                "SELECT token, path, created_by, created_at, expires_at
+                 FROM share_links ORDER BY created_at",
+            )?
+            .query_map([], share_from_row)?
+            .collect()
+        })
+        .await
+    }
+
+    async fn delete_share(&self, token: &str) -> anyhow::Result<bool> {
+        let token = token.to_string();
+        self.call(move |conn| {
+            conn.execute("DELETE FROM share_links WHERE token = ?1", [token])
+                .map(|n| n > 0)
+        })
+        .await
+    }
+
+    async fn export_accounts(&self) -> anyhow::Result<AccountsSnapshot> {
+        self.call(|conn| {
+            let users = conn
+                .prepare(
+                    "SELECT id, username, created_at, access_windows, tenant,
+                         password_hash
+                     FROM users ORDER BY username",
+                )?
+                .query_map([], |row| {
+                    Ok(AccountRecord {
+                        account: user_from_row(row)?,
+                        password_hash: row.get(5)?,
+                    })
+                })?
+                .collect::<rusqlite::Result<_>>()?;
+            let keys = conn
+                .prepare(
+                    "SELECT id, username, public_key, comment, created_at
+                     FROM authorized_keys ORDER BY id",
+                )?
+                .query_map([], key_from_row)?
+                .collect::<rusqlite::Result<_>>()?;
+            let shares = conn
+                .prepare(
+                    "SELECT token, path, created_by, created_at, expires_at
+                     FROM share_links ORDER BY created_at",
+                )?
+                .query_map([], share_from_row)?
+                .collect::<rusqlite::Result<_>>()?;
+            Ok(AccountsSnapshot { users, keys, shares })
+        })
+        .await
+    }
+
+    async fn import_accounts(
+        &self,
+        snapshot: &AccountsSnapshot,
+    ) -> anyhow::Result<()> {
+        let windows = snapshot
+            .users
+            .iter()
+            .map(|record| serde_json::to_string(&record.account.access_windows))
+            .collect::<serde_json::Result<Vec<_>>>()?;
+        let snapshot = snapshot.clone();
+        self.call(move |conn| {
+            let tx = conn.unchecked_transaction()?;
+            tx.execute("DELETE FROM authorized_keys", [])?;
+            tx.execute("DELETE FROM users", [])?;
+            tx.execute("DELETE FROM share_links", [])?;
+            for (record, windows) in snapshot.users.iter().zip(&windows) {
+                let user = &record.account;
+                tx.execute(
+                    "INSERT INTO users (username, created_at, access_windows,
+                         tenant, password_hash)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        user.username,
+                        user.created_at,
+                        windows,
+                        user.tenant,
+                        record.password_hash
+                    ],
+                )?;
+            }
+            for key in &snapshot.keys {
+                tx.execute(
+                    "INSERT INTO authorized_keys
+                         (username, public_key, comment, created_at)
+                     VALUES (?1, ?2, ?3, ?4)",
+                    params![
+                        key.username,
+                        key.public_key,
+                        key.comment,
+                        key.created_at
+                    ],
+                )?;
+            }
+            for share in &snapshot.shares {
+                tx.execute(
+                    "INSERT INTO share_links
+                         (token, path, created_by, created_at, expires_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        share.token,
+                        share.path,
+                        share.created_by,
+                        share.created_at,
+                        share.expires_at
+                    ],
+                )?;
+            }
+            tx.commit()
+        })
+        .await
+    }
+
+    async fn record_audit(
+        &self,
+        action: &str,
+        username: Option<&str>,
+        detail: serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let action = action.to_string();
+        let username = username.map(str::to_string);
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO audit_log (timestamp, action, username, detail)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![Utc::now(), action, username, detail.to_string()],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn list_audit(
+        &self,
+        limit: u32,
+        action: Option<&str>,
+    ) -> anyhow::Result<Vec<AuditEntry>> {
+        let action = action.map(str::to_string);
+        self.call(move |conn| {
+            conn.prepare(
+                "SELECT id, timestamp, action, username, detail
+                 FROM audit_log WHERE ?2 IS NULL OR action = ?2
+                 ORDER BY id DESC LIMIT ?1",
+            )?
+            .query_map(params![limit, action], audit_from_row)?
+            .collect()
+        })
+        .await
+    }
+
+    async fn record_transfer(
+        &self,
+        transfer: NewTransfer,
+    ) -> anyhow::Result<()> {
+        let checksums = serde_json::to_string(&transfer.checksums)?;
+        self.call(move |conn| {
+            conn.execute(
+                "INSERT INTO transfer_log
+                 (timestamp, username, peer, path, direction, bytes,
+                  duration_ms, checksums)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    Utc::now(),
+                    transfer.username,
+                    transfer.peer,
+                    transfer.path,
+                    transfer.direction.as_str(),
+                    transfer.bytes as i64,
+                    transfer.duration_ms as i64,
+                    checksums
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn list_transfers(
+        &self,
+        limit: u32,
+    ) -> anyhow::Result<Vec<TransferRecord>> {
+        self.call(move |conn| {
+            conn.prepare(&format!(
+                "{} ORDER BY id DESC LIMIT ?1",
+                SELECT_TRANSFERS
+            ))?
+            .query_map([limit], transfer_from_row)?
+            .collect()
+        })
+        .await
+    }
+
+    async fn latest_upload(
+        &self,
+        path: &str,
+    ) -> anyhow::Result<Option<TransferRecord>> {
+        let path = path.to_string();
+        self.call(move |conn| {
+            conn.query_row(
+                &format!(
+                    "{} WHERE path = ?1 AND direction = 'upload'
+                     ORDER BY id DESC LIMIT 1",
+                    SELECT_TRANSFERS
+                ),
+                [path],
+                transfer_from_row,
+            )
+            .optional()
+        })
+        .await
+    }
+
+    async fn latest_uploads_with_checksums(
+        &self,
+    ) -> anyhow::Result<Vec<TransferRecord>> {
+        self.call(move |conn| {
+            conn.prepare(&format!(
+                "{} WHERE id IN (SELECT MAX(id) FROM transfer_log
+                     WHERE direction = 'upload' GROUP BY path)
+                 AND checksums IS NOT NULL AND checksums != '{{}}'
+                 ORDER BY path",
+                SELECT_TRANSFERS
+            ))?
+            .query_map([], transfer_from_row)?
+            .collect()
+        })
+        .await
+    }
+
+    async fn set_file_tags(
+        &self,
+        path: &str,
+        tags: &BTreeMap<String, String>,
+    ) -> anyhow::Result<()> {
+        let path = path.to_string();
+        let tags = tags.clone();
+        self.call(move |conn| {
+            let tx = conn.unchecked_transaction()?;
+            tx.execute("DELETE FROM file_tags WHERE path = ?1", [&path])?;
+            for (key, value) in &tags {
+                tx.execute(
+                    "INSERT INTO file_tags (path, key, value)
+                     VALUES (?1, ?2, ?3)",
+                    params![path, key, value],
+                )?;
+            }
+            tx.commit()
+        })
+        .await
+    }
+
+    async fn get_file_tags(
+        &self,
+        path: &str,
+    ) -> anyhow::Result<BTreeMap<String, String>> {
+        let path = path.to_string();
+        self.call(move |conn| {
+            conn.prepare("SELECT key, value FROM file_tags WHERE path = ?1")?
+                .query_map([path], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect()
+        })
+        .await
+    }
+
+    async fn find_tagged_files(
+        &self,
+        tags: &BTreeMap<String, String>,
+        limit: u32,
+    ) -> anyhow::Result<Vec<FileTags>> {
+        let tags = tags.clone();
+        self.call(move |conn| {
+            // Paths having every requested tag, then all of their tags
+            let mut sql = String::from("SELECT DISTINCT path FROM file_tags");
+            let mut args: Vec<&dyn rusqlite::ToSql> = Vec::new();
+            for (i, (key, value)) in tags.iter().enumerate() {
+                sql.push_str(if i == 0 { " WHERE " } else { " AND " });
+                sql.push_str(&format!(
+                    "path IN (SELECT path FROM file_tags
+                     WHERE key = ?{} AND value = ?{})",
+                    args.len() + 1,
+                    args.len() + 2
+                ));
+                args.push(key);
+                args.push(value);
+            }
+            sql.push_str(&format!(" ORDER BY path LIMIT {}", limit));
+            let paths: Vec<String> = conn
+                .prepare(&sql)?
+                .query_map(args.as_slice(), |row| row.get(0))?
+                .collect::<rusqlite::Result<_>>()?;
+
+            let mut select = conn
+                .prepare("SELECT key, value FROM file_tags WHERE path = ?1")?;
+            paths
+                .into_iter()
+                .map(|path| {
+                    let tags = select
+                        .query_map([&path], |row| {
+                            Ok((row.get(0)?, row.get(1)?))
+                        })?
+                        .collect::<rusqlite::Result<_>>()?;
+                    Ok(FileTags { path, tags })
+                })
+                .collect()
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_users_keys_and_logs() {
+        let repo = SqliteRepository::in_memory().unwrap();
+
+        repo.create_user("alice", &[], None).await.unwrap();
+        assert!(repo.create_user("alice", &[], None).await.is_err());
+        let windows = vec!["* 8-17 * * mon-fri".to_string()];
+        assert!(repo.set_access_windows("alice", &windows).await.unwrap());
+        let alice = repo.get_user("alice").await.unwrap().unwrap();
+        assert_eq!(alice.access_windows, windows);
+        assert!(!repo.set_access_windows("carol", &windows).await.unwrap());
+        let key =
+            repo.add_key("alice", "ssh-ed25519 AAAA", None).await.unwrap();
+        assert_eq!(repo.list_keys("alice").await.unwrap().len(), 1);
+
+        // Keys go away with their user
+        assert!(repo.delete_user("alice").await.unwrap());
+        assert!(!repo.remove_key(key.id).await.unwrap());
+
+        repo.record_transfer(NewTransfer {
+            username: "bob".to_string(),
+            peer: Some("10.0.0.7".to_string()),
+            path: "/a.csv".to_string(),
+            direction: TransferDirection::Upload,
+            bytes: 42,
+            duration_ms: 1500,
+            checksums: [("sha256".to_string(), "ab12".to_string())].into(),
+        })
+        .await
+        .unwrap();
+        let transfers = repo.list_transfers(10).await.unwrap();
+        assert_eq!(transfers[0].bytes, 42);
+        assert_eq!(transfers[0].direction, TransferDirection::Upload);
+        assert_eq!(transfers[0].duration_ms, Some(1500));
+
+        let upload = repo.latest_upload("/a.csv").await.unwrap().unwrap();
+        assert_eq!(upload.checksums["sha256"], "ab12");
+        assert!(repo.latest_upload("/b.csv").await.unwrap().is_none());
+
+        let uploads = repo.latest_uploads_with_checksums().await.unwrap();
+        assert_eq!(uploads.len(), 1);
+        assert_eq!(uploads[0].path, "/a.csv");
+
+        let batch: BTreeMap<String, String> =
+            [("batch".to_string(), "42".to_string())].into();
+        let mut tags = batch.clone();
+        tags.insert("customer".to_string(), "acme".to_string());
+        repo.set_file_tags("/a.csv", &tags).await.unwrap();
+        repo.set_file_tags("/b.csv", &batch).await.unwrap();
+        assert_eq!(repo.get_file_tags("/a.csv").await.unwrap(), tags);
+        let found = repo.find_tagged_files(&tags, 10).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].path, "/a.csv");
+        assert_eq!(found[0].tags, tags);
+        assert_eq!(repo.find_tagged_files(&batch, 10).await.unwrap().len(), 2);
+        repo.set_file_tags("/a.csv", &BTreeMap::new()).await.unwrap();
+        assert!(repo.get_file_tags("/a.csv").await.unwrap().is_empty());
+
+        for action in ["login_succeeded", "credentials_accessed"] {
+            repo.record_audit(action, Some("bob"), serde_json::json!({}))
+                .await
+                .unwrap();
+        }
+        assert_eq!(repo.list_audit(10, None).await.unwrap().len(), 2);
+        let accesses =
+            repo.list_audit(10, Some("credentials_accessed")).await.unwrap();
+        assert_eq!(accesses.len(), 1);
+        assert_eq!(accesses[0].action, "credentials_accessed");
+
+        let data = repo.user_data("bob").await.unwrap();
+        assert!(data.user.is_none());
+        assert_eq!((data.audit.len(), data.transfers.len()), (2, 1));
+        let erased = repo.erase_user("bob").await.unwrap();
+        assert_eq!((erased.audit_entries, erased.transfers), (2, 1));
+        let data = repo.user_data("bob").await.unwrap();
+        assert!(data.audit.is_empty() && data.transfers.is_empty());
+        let tombstones = repo.user_data(ERASED_USER).await.unwrap();
+        assert_eq!(tombstones.transfers[0].path, ERASED_USER);
+        assert_eq!(tombstones.transfers[0].peer, None);
+    }
+}
@@ -0,0 +1,157 @@
+// systemd integration: readiness notification (Type=notify) and sockets
+// passed by socket activation. Both are inert when not started by systemd.
+use std::collections::HashMap;
+use std::net::TcpListener;
+use tracing::{debug, warn};
+
+// Socket names matching FileDescriptorName= in the .socket units
+pub const HTTP_SOCKET: &str = "http";
+pub const SFTP_SOCKET: &str = "sftp";
+
+// The first descriptor passed by systemd
+const LISTEN_FDS_START: i32 = 3;
+
+// Send a state such as "READY=1" or "STOPPING=1" to the service manager.
+// Does nothing unless NOTIFY_SOCKET is set.
+pub fn notify(state: &str) {
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    if let Err(e) = send_notify(&path, state) {
+        warn!("Failed to notify systemd of {}: {}", state, e);
+    } else {
+        debug!("Notified systemd: {}", state);
+    }
+}
+
+#[cfg(unix)]
+fn send_notify(path: &std::ffi::OsStr, state: &str) -> std::io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::net::UnixDatagram;
+
+    let socket = UnixDatagram::unbound()?;
+    match path.as_bytes().strip_prefix(b"@") {
+        // Abstract socket names are written with a leading '@'
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            use std::os::unix::net::SocketAddr;
+            let addr = SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        _ => {
+            socket.send_to(state.as_bytes(), path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send_notify(_path: &std::ffi::OsStr, _state: &str) -> std::io::Result<()> {
+    Ok(())
+}
+
+// Listening sockets passed by systemd, by name
+#[derive(Default)]
+pub struct ActivatedSockets {
+    sockets: HashMap<String, TcpListener>,
+}
+
+impl ActivatedSockets {
+    // Take over the sockets described by LISTEN_PID, LISTEN_FDS and
+    // LISTEN_FDNAMES. Child processes ignore them as LISTEN_PID names this
+    // process.
+    pub fn from_env() -> Self {
+        let pid = std::env::var("LISTEN_PID").ok();
+        let fds = std::env::var("LISTEN_FDS").ok();
+        let names = std::env::var("LISTEN_FDNAMES").ok();
+        let passed = match (pid, fds) {
+            (Some(pid), Some(fds)) => {
+                parse_listen_fds(&pid, &fds, names.as_deref())
+            }
+            _ => Vec::new(),
+        };
+        let mut sockets = HashMap::new();
+        for (name, fd) in passed {
+            match listener_from_fd(fd) {
+                Ok(listener) => {
+                    sockets.insert(name, listener);
+                }
+                Err(e) => warn!("Ignoring activated socket {}: {}", name, e),
+            }
+        }
+        Self { sockets }
+    }
+
+    // The socket named `name`, if systemd passed one
+    pub fn take(&mut self, name: &str) -> Option<TcpListener> {
+        self.sockets.remove(name)
+    }
+}
+
+// Names and descriptors of the passed sockets. Sockets without a name are
+// assigned "http" then "sftp" by position, matching the usual unit order.
+fn parse_listen_fds(
+    pid: &str,
+    fds: &str,
+    names: Option<&str>,
+) -> Vec<(String, i32)> {
+    if pid.parse::<u32>().ok() != Some(std::process::id()) {
+        return Vec::new();
+    }
+    let count = fds.parse::<i32>().unwrap_or(0);
+    let names: Vec<&str> =
+        names.map(|n| n.split(':').collect()).unwrap_or_default();
+    (0..count.max(0))
+        .map(|i| {
+            let name = match names.get(i as usize) {
+                Some(name) if !name.is_empty() && *name != "unknown" => {
+                    name.to_string()
+                }
+                _ => [HTTP_SOCKET, SFTP_SOCKET]
+                    .get(i as usize)
+                    .map(|n| n.to_string())
+                    .unwrap_or_else(|| format!("fd{}", i)),
+            };
+            (name, LISTEN_FDS_START + i)
+        })
+        .collect()
+}
+
+#[cfg(unix)]
+fn listener_from_fd(fd: i32) -> std::io::Result<TcpListener> {
+    use std::os::fd::FromRawFd;
+    // SAFETY: systemd passes these descriptors to this process alone, and
+    // each is taken over once
+    let listener = unsafe { TcpListener::from_raw_fd(fd) };
+    // Fails for descriptors that are not sockets
+    listener.local_addr()?;
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+#[cfg(not(unix))]
+fn listener_from_fd(_fd: i32) -> std::io::Result<TcpListener> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listen_fds_are_named() {
+        let pid = std::process::id().to_string();
+        assert_eq!(
+            parse_listen_fds(&pid, "2", Some("sftp:http")),
+            vec![("sftp".to_string(), 3), ("http".to_string(), 4)]
+        );
+        // Unnamed sockets are taken by position
+        assert_eq!(
+            parse_listen_fds(&pid, "2", None),
+            vec![("http".to_string(), 3), ("sftp".to_string(), 4)]
+        );
+        // Sockets meant for another process are left alone
+        assert!(parse_listen_fds("1", "2", None).is_empty());
+    }
+}
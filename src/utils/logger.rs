@@ -1,14 +1,208 @@
-pub fn init_logging() {
-    // tracing_subscriber::fmt().with_max_level(tracing::Level::INFO).init();
-    use tracing_subscriber::{EnvFilter, fmt};
+use crate::config::settings::{LogRotation, LoggingSettings, SyslogTransport};
+use crate::utils::syslog::SyslogWriter;
+use clap::ValueEnum;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{self, Rotation};
+use tracing_subscriber::field::RecordFields;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::fmt::format::{DefaultFields, FormatFields, Writer};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer, Registry, fmt};
 
-    // Enable INFO logs and include HTTP tracing
+// How log lines are written
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum LogFormat {
+    #[default]
+    Compact,
+    Pretty,
+    Json,
+}
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+// Log to stdout and, when configured, to a rotated file and syslog. The
+// returned guard flushes the file when dropped, so keep it alive until exit.
+pub fn init_logging(
+    format: LogFormat,
+    settings: &LoggingSettings,
+) -> io::Result<Option<WorkerGuard>> {
+    // Enable INFO logs, which include one access line per HTTP request
     let filter = EnvFilter::try_from_default_env()
-        .unwrap_or_else(|_| EnvFilter::new("info,tower_http=debug"));
+        .unwrap_or_else(|_| EnvFilter::new("info"));
+
+    let mut layers = vec![format_layer(format, io::stdout, true)];
+
+    let guard = if settings.file.is_empty() {
+        None
+    } else {
+        let (writer, guard) =
+            tracing_appender::non_blocking(open_log_file(settings)?);
+        layers.push(format_layer(format, writer, false));
+        Some(guard)
+    };
+
+    if settings.syslog.transport != SyslogTransport::None {
+        // Syslog stamps its own time and severity
+        let writer = SyslogWriter::connect(&settings.syslog)?;
+        layers.push(
+            fmt::layer()
+                .with_writer(writer)
+                .with_ansi(false)
+                .with_target(false)
+                .with_level(false)
+                .without_time()
+                .compact()
+                .fmt_fields(PlainFields::default())
+                .boxed(),
+        );
+    }
+
+    tracing_subscriber::registry().with(layers).with(filter).init();
+    Ok(guard)
+}
+
+// Apply the chosen format; target module paths are hidden
+fn format_layer<W>(format: LogFormat, writer: W, ansi: bool) -> BoxedLayer
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer =
+        fmt::layer().with_writer(writer).with_ansi(ansi).with_target(false);
+    match (format, ansi) {
+        (LogFormat::Json, _) => layer.json().boxed(),
+        (LogFormat::Compact, true) => layer.compact().boxed(), // cleaner output
+        (LogFormat::Pretty, true) => layer.pretty().boxed(),
+        // Span fields are formatted once per formatter type and reused by
+        // every layer, so plain output needs its own type to avoid
+        // inheriting colour codes
+        (LogFormat::Compact, false) => {
+            layer.compact().fmt_fields(PlainFields::default()).boxed()
+        }
+        (LogFormat::Pretty, false) => {
+            layer.pretty().fmt_fields(PlainFields::default()).boxed()
+        }
+    }
+}
+
+#[derive(Default)]
+struct PlainFields(DefaultFields);
+
+impl<'w> FormatFields<'w> for PlainFields {
+    fn format_fields<R: RecordFields>(
+        &self,
+        writer: Writer<'w>,
+        fields: R,
+    ) -> std::fmt::Result {
+        self.0.format_fields(writer, fields)
+    }
+}
+
+fn open_log_file(
+    settings: &LoggingSettings,
+) -> io::Result<Box<dyn Write + Send>> {
+    let path = Path::new(&settings.file);
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(dir)?;
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::other("logging.file has no file name"))?
+        .to_string_lossy()
+        .to_string();
+
+    let rotation = match settings.rotation {
+        LogRotation::Size => {
+            return Ok(Box::new(SizeRollingFile::open(
+                path.to_path_buf(),
+                settings.max_file_bytes,
+                settings.max_files,
+            )?));
+        }
+        LogRotation::Minutely => Rotation::MINUTELY,
+        LogRotation::Hourly => Rotation::HOURLY,
+        LogRotation::Daily => Rotation::DAILY,
+        LogRotation::Never => Rotation::NEVER,
+    };
+
+    // Time based files are suffixed with their period, e.g. ".2024-06-14"
+    let appender = rolling::Builder::new()
+        .rotation(rotation)
+        .filename_prefix(file_name)
+        .max_log_files(settings.max_files.max(1))
+        .build(dir)
+        .map_err(io::Error::other)?;
+    Ok(Box::new(appender))
+}
+
+// Log file renamed to `<file>.1`, `<file>.2`, ... once it reaches a size,
+// keeping `max_files` rotated files
+struct SizeRollingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    file: File,
+    written: u64,
+}
+
+impl SizeRollingFile {
+    fn open(
+        path: PathBuf,
+        max_bytes: u64,
+        max_files: usize,
+    ) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let written = file.metadata()?.len();
+        Ok(Self { path, max_bytes, max_files, file, written })
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut name = self.path.as_os_str().to_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let _ = fs::remove_file(self.rotated(self.max_files));
+        for n in (1..self.max_files).rev() {
+            let from = self.rotated(n);
+            if from.exists() {
+                fs::rename(&from, self.rotated(n + 1))?;
+            }
+        }
+        if self.max_files > 0 {
+            fs::rename(&self.path, self.rotated(1))?;
+        }
+
+        self.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl Write for SizeRollingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Lines are written whole, so files only split between lines
+        if self.written > 0 && self.written + buf.len() as u64 > self.max_bytes
+        {
+            self.rotate()?;
+        }
+        let n = self.file.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
 
-    fmt()
-        .with_env_filter(filter)
-        .with_target(false) // hides target module paths
-        .compact() // cleaner output
-        .init();
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
 }
@@ -0,0 +1,201 @@
+use crate::config::settings::{
+    SyslogFacility, SyslogSettings, SyslogTransport,
+};
+use chrono::{SecondsFormat, Utc};
+use std::io::{self, Write};
+use std::net::{TcpStream, UdpSocket};
+use std::os::unix::net::UnixDatagram;
+use std::sync::{Arc, Mutex};
+use tracing::{Level, Metadata};
+use tracing_subscriber::fmt::MakeWriter;
+
+// Where syslog messages are sent
+enum Transport {
+    Udp(UdpSocket),
+    // Reconnected on the next message after a failed send
+    Tcp(Option<TcpStream>),
+    Unix(UnixDatagram),
+}
+
+struct Sink {
+    settings: SyslogSettings,
+    hostname: String,
+    transport: Transport,
+}
+
+impl Sink {
+    fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        match &mut self.transport {
+            Transport::Udp(socket) => {
+                socket.send_to(message, &self.settings.address)?;
+            }
+            Transport::Unix(socket) => {
+                socket.send_to(message, &self.settings.address)?;
+            }
+            Transport::Tcp(stream) => {
+                if stream.is_none() {
+                    *stream = Some(TcpStream::connect(&self.settings.address)?);
+                }
+                // Octet counting framing (RFC 6587)
+                let framed =
+                    [format!("{} ", message.len()).as_bytes(), message]
+                        .concat();
+                if let Err(e) = stream.as_mut().unwrap().write_all(&framed) {
+                    *stream = None;
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Sends every log line as an RFC 5424 message. Cloned for each event so
+// the line's severity is known when it is sent.
+#[derive(Clone)]
+pub struct SyslogWriter {
+    sink: Arc<Mutex<Sink>>,
+}
+
+impl SyslogWriter {
+    pub fn connect(settings: &SyslogSettings) -> io::Result<Self> {
+        let transport = match settings.transport {
+            SyslogTransport::Udp => {
+                Transport::Udp(UdpSocket::bind("0.0.0.0:0")?)
+            }
+            SyslogTransport::Tcp => {
+                Transport::Tcp(Some(TcpStream::connect(&settings.address)?))
+            }
+            SyslogTransport::Unix => Transport::Unix(UnixDatagram::unbound()?),
+            SyslogTransport::None => {
+                return Err(io::Error::other("syslog is disabled"));
+            }
+        };
+
+        Ok(Self {
+            sink: Arc::new(Mutex::new(Sink {
+                settings: settings.clone(),
+                hostname: hostname(),
+                transport,
+            })),
+        })
+    }
+
+    fn line(&self, severity: u8) -> SyslogLine {
+        SyslogLine { sink: self.sink.clone(), severity, buffer: Vec::new() }
+    }
+}
+
+impl<'a> MakeWriter<'a> for SyslogWriter {
+    type Writer = SyslogLine;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.line(severity(&Level::INFO))
+    }
+
+    fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+        self.line(severity(meta.level()))
+    }
+}
+
+// One log line, sent when the formatter is done with it
+pub struct SyslogLine {
+    sink: Arc<Mutex<Sink>>,
+    severity: u8,
+    buffer: Vec<u8>,
+}
+
+impl Write for SyslogLine {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for SyslogLine {
+    fn drop(&mut self) {
+        let text = String::from_utf8_lossy(&self.buffer);
+        let text = text.trim_end();
+        if text.is_empty() {
+            return;
+        }
+
+        let mut sink = self.sink.lock().unwrap();
+        let message = format_message(
+            sink.settings.facility,
+            self.severity,
+            &sink.hostname,
+            &sink.settings.app_name,
+            text,
+        );
+        // Logging a failure to log would recurse
+        if let Err(e) = sink.send(message.as_bytes()) {
+            eprintln!("Failed to send syslog message: {}", e);
+        }
+    }
+}
+
+// `<PRI>1 TIMESTAMP HOSTNAME APP-NAME PROCID MSGID SD MSG`
+fn format_message(
+    facility: SyslogFacility,
+    severity: u8,
+    hostname: &str,
+    app_name: &str,
+    text: &str,
+) -> String {
+    format!(
+        "<{}>1 {} {} {} {} - - {}",
+        facility.code() * 8 + u32::from(severity),
+        Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true),
+        hostname,
+        app_name,
+        std::process::id(),
+        text
+    )
+}
+
+fn severity(level: &Level) -> u8 {
+    match *level {
+        Level::ERROR => 3,
+        Level::WARN => 4,
+        Level::INFO => 6,
+        _ => 7,
+    }
+}
+
+pub fn hostname() -> String {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/proc/sys/kernel/hostname").ok())
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rfc5424_header() {
+        let message = format_message(
+            SyslogFacility::Local3,
+            severity(&Level::WARN),
+            "host-1",
+            "sftp-manager",
+            "disk almost full",
+        );
+
+        // local3 (19) * 8 + warning (4)
+        assert!(message.starts_with("<156>1 "));
+        let fields: Vec<&str> = message.splitn(8, ' ').collect();
+        assert_eq!(fields[2], "host-1");
+        assert_eq!(fields[3], "sftp-manager");
+        assert_eq!(fields[4], std::process::id().to_string());
+        assert_eq!(&fields[5..], ["-", "-", "disk almost full"]);
+    }
+}